@@ -0,0 +1,256 @@
+//! A local log of what is done with notes, from which usage insights are computed.
+//!
+//! The log is a plain file in the `.notes` directory. It is only ever read by this
+//! application on this machine: nothing is sent anywhere, and deleting the file or turning
+//! off `activity_log` in `.config.json` removes every trace of it.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::notes::Notes;
+use crate::stats::NoteMetrics;
+
+/// The name of the file in the `.notes` directory that stores the activity log.
+pub const ACTIVITY_FILE: &str = ".activity.jsonl";
+
+/// Notes with fewer words than this that have not been touched for `ABANDONED_AFTER_DAYS`
+/// are listed as abandoned drafts.
+pub const DRAFT_WORDS: usize = 100;
+
+/// Days without activity after which a short note counts as abandoned.
+pub const ABANDONED_AFTER_DAYS: i64 = 14;
+
+/// How many entries the lists of the insights dashboard show.
+const TOP_ENTRIES: usize = 10;
+
+/// What was done with a note.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    Created,
+    Opened,
+    Edited,
+    Deleted,
+}
+
+/// A single entry of the activity log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    /// When it happened, as a UNIX timestamp.
+    pub time: i64,
+    /// What was done.
+    pub kind: ActivityKind,
+    /// The title of the note.
+    pub title: String,
+}
+
+/// Appends an entry to the activity log.
+///
+/// # Arguments
+///
+/// * `kind` - What was done.
+/// * `title` - The title of the note.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn record(kind: ActivityKind, title: &str) -> io::Result<()> {
+    let activity = Activity {
+        time: Local::now().timestamp(),
+        kind,
+        title: title.to_string(),
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(log_path()?)?;
+    writeln!(file, "{}", serde_json::to_string(&activity)?)
+}
+
+/// Loads the activity log, oldest first.
+///
+/// Lines that cannot be read, e.g. one cut off by a crash, are skipped.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<Activity>>` containing the entries, or none if nothing was logged yet.
+pub fn load() -> io::Result<Vec<Activity>> {
+    let path = log_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Deletes the activity log.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn clear() -> io::Result<()> {
+    let path = log_path()?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn log_path() -> io::Result<PathBuf> {
+    Ok(Notes::get_notes_dir()?.join(ACTIVITY_FILE))
+}
+
+/// Usage insights, as shown in the Insights dashboard.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Insights {
+    /// The notes saved most often, most edited first.
+    pub most_edited: Vec<(String, usize)>,
+    /// The tags of the notes worked on most often, most used first.
+    pub most_used_tags: Vec<(String, usize)>,
+    /// The number of edits made in each hour of the day, in local time.
+    pub busiest_hours: [usize; 24],
+    /// Short notes that have not been touched for a while, least recently touched first.
+    pub abandoned_drafts: Vec<String>,
+}
+
+impl Insights {
+    /// Computes insights from the activity log and the current state of the notes.
+    ///
+    /// # Arguments
+    ///
+    /// * `activity` - The entries of the activity log.
+    /// * `metrics` - The metrics of every existing note.
+    /// * `now` - The current time as a UNIX timestamp.
+    pub fn compute(activity: &[Activity], metrics: &[NoteMetrics], now: i64) -> Insights {
+        let notes: HashMap<&str, &NoteMetrics> = metrics.iter().map(|note| (note.title.as_str(), note)).collect();
+        let mut edits: HashMap<&str, usize> = HashMap::new();
+        let mut tags: HashMap<&str, usize> = HashMap::new();
+        let mut last_touched: HashMap<&str, i64> = HashMap::new();
+        let mut busiest_hours = [0; 24];
+        for entry in activity {
+            let Some(note) = notes.get(entry.title.as_str()) else {
+                continue;
+            };
+            last_touched.insert(&note.title, entry.time);
+            if entry.kind != ActivityKind::Edited {
+                continue;
+            }
+            *edits.entry(&note.title).or_default() += 1;
+            for tag in &note.tags {
+                *tags.entry(tag).or_default() += 1;
+            }
+            if let Some(time) = DateTime::from_timestamp(entry.time, 0) {
+                busiest_hours[time.with_timezone(&Local).hour() as usize] += 1;
+            }
+        }
+
+        let mut abandoned: Vec<(i64, &str)> = metrics
+            .iter()
+            .filter(|note| note.word_count < DRAFT_WORDS)
+            .filter_map(|note| {
+                // Notes never seen in the log count from their last modification.
+                let touched = last_touched.get(note.title.as_str()).copied().unwrap_or(note.modified as i64);
+                let idle = now - touched >= ABANDONED_AFTER_DAYS * 24 * 60 * 60;
+                idle.then_some((touched, note.title.as_str()))
+            })
+            .collect();
+        abandoned.sort();
+
+        Insights {
+            most_edited: top(edits),
+            most_used_tags: top(tags),
+            busiest_hours,
+            abandoned_drafts: abandoned
+                .into_iter()
+                .take(TOP_ENTRIES)
+                .map(|(_, title)| title.to_string())
+                .collect(),
+        }
+    }
+}
+
+fn top(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().map(|(name, count)| (name.to_string(), count)).collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    counts.truncate(TOP_ENTRIES);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    fn metrics(title: &str, word_count: usize, modified: u64, tags: &[&str]) -> NoteMetrics {
+        NoteMetrics {
+            title: title.to_string(),
+            size_bytes: 0,
+            word_count,
+            char_count: 0,
+            reading_minutes: 0,
+            created: None,
+            modified,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            outgoing_links: 0,
+            incoming_links: 0,
+            external_links: 0,
+        }
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        record(ActivityKind::Created, "a").unwrap();
+        record(ActivityKind::Edited, "a").unwrap();
+        let mut file = OpenOptions::new().append(true).open(log_path().unwrap()).unwrap();
+        write!(file, "{{\"time\": 1, \"ki").unwrap();
+
+        let activity = load().unwrap();
+        assert_eq!(activity.len(), 2);
+        assert_eq!(activity[1].kind, ActivityKind::Edited);
+        assert_eq!(activity[1].title, "a");
+
+        clear().unwrap();
+        assert!(load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insights() {
+        let day = 24 * 60 * 60;
+        let now = 100 * day;
+        let entry = |time, kind, title: &str| Activity {
+            time,
+            kind,
+            title: title.to_string(),
+        };
+        let activity = vec![
+            entry(now - 60 * day, ActivityKind::Created, "draft"),
+            entry(now - 2 * day, ActivityKind::Edited, "journal"),
+            entry(now - day, ActivityKind::Edited, "journal"),
+            entry(now - day, ActivityKind::Edited, "recipes"),
+            entry(now - day, ActivityKind::Edited, "gone"),
+            entry(now - day, ActivityKind::Opened, "idea"),
+        ];
+        let metrics = vec![
+            metrics("draft", 10, (now - 60 * day) as u64, &[]),
+            metrics("idea", 10, (now - 30 * day) as u64, &[]),
+            metrics("journal", 500, now as u64, &["daily"]),
+            metrics("recipes", 500, now as u64, &["daily", "food"]),
+            metrics("stub", 5, (now - 20 * day) as u64, &[]),
+        ];
+
+        let insights = Insights::compute(&activity, &metrics, now);
+        assert_eq!(
+            insights.most_edited,
+            vec![("journal".to_string(), 2), ("recipes".to_string(), 1)]
+        );
+        assert_eq!(insights.most_used_tags, vec![("daily".to_string(), 3), ("food".to_string(), 1)]);
+        assert_eq!(insights.busiest_hours.iter().sum::<usize>(), 3);
+        assert_eq!(insights.abandoned_drafts, vec!["draft", "stub"]);
+    }
+}
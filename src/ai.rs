@@ -0,0 +1,325 @@
+//! Optional features backed by a language model: summarizing a note, suggesting a title for it and
+//! answering questions from the notes. Nothing is sent anywhere unless they are turned on in the
+//! preferences.
+
+use std::io;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::{AiProvider, AiSettings};
+use crate::notes::Notes;
+use crate::search::SearchIndex;
+use crate::secrets;
+
+/// The name of the secret holding the API key of an OpenAI-compatible server.
+pub const API_KEY_SECRET: &str = "ai-api-key";
+
+/// How many of the notes sharing the most words with a question are sent along with it.
+const CONTEXT_NOTES: usize = 5;
+
+/// How much of each note is sent, in characters, so that the prompt fits in small context windows.
+const MAX_NOTE_CHARS: usize = 6000;
+
+/// The longest answer asked for, in tokens.
+const MAX_TOKENS: u32 = 512;
+
+/// Something that answers prompts: a hosted API or a model running locally.
+pub trait Provider: Send {
+    /// Answers a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - What the model is asked to do with the message.
+    /// * `message` - The text to work on, e.g. a note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<String>` containing the answer.
+    fn complete(&self, instructions: &str, message: &str) -> io::Result<String>;
+}
+
+/// A server with the OpenAI chat completions API, which most hosted models and local servers
+/// like Ollama offer.
+pub struct OpenAiCompatible {
+    /// The base URL of the API, e.g. `https://api.openai.com/v1`.
+    pub url: String,
+    /// The model to use.
+    pub model: String,
+    /// The API key, if the server needs one.
+    pub api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: String,
+}
+
+impl Provider for OpenAiCompatible {
+    fn complete(&self, instructions: &str, message: &str) -> io::Result<String> {
+        let body = json!({
+            "model": self.model,
+            "messages": [
+                { "role": "system", "content": instructions },
+                { "role": "user", "content": message },
+            ],
+            "max_tokens": MAX_TOKENS,
+            "temperature": 0.2,
+        });
+        let mut request = ureq::post(&format!("{}/chat/completions", self.url.trim_end_matches('/')))
+            .set("Content-Type", "application/json");
+        if let Some(api_key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", api_key));
+        }
+        let response: ChatResponse = post(request, &body)?;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "The server sent no answer"))
+    }
+}
+
+/// The server of llama.cpp, running a model on this machine, e.g. `llama-server -m model.gguf`.
+pub struct LlamaCpp {
+    /// The URL of the server, e.g. `http://localhost:8080`.
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    content: String,
+}
+
+impl Provider for LlamaCpp {
+    fn complete(&self, instructions: &str, message: &str) -> io::Result<String> {
+        let body = json!({
+            "prompt": format!("### Instructions\n{}\n\n### Input\n{}\n\n### Response\n", instructions, message),
+            "n_predict": MAX_TOKENS,
+            "temperature": 0.2,
+            "stop": ["### "],
+        });
+        let request = ureq::post(&format!("{}/completion", self.url.trim_end_matches('/')))
+            .set("Content-Type", "application/json");
+        let response: CompletionResponse = post(request, &body)?;
+        Ok(response.content)
+    }
+}
+
+// Sends a JSON body and parses the JSON answer
+fn post<T: for<'de> Deserialize<'de>>(request: ureq::Request, body: &serde_json::Value) -> io::Result<T> {
+    let response = request.send_string(&body.to_string()).map_err(|err| match err {
+        ureq::Error::Status(code, response) => {
+            let message = response.into_string().unwrap_or_default();
+            io::Error::new(io::ErrorKind::Other, format!("The server answered {}: {}", code, message.trim()))
+        }
+        err => io::Error::new(io::ErrorKind::Other, err),
+    })?;
+    serde_json::from_str(&response.into_string()?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Sets up the provider chosen in the preferences.
+///
+/// # Returns
+///
+/// An `io::Result<Box<dyn Provider>>` containing the provider, or a `PermissionDenied` error if the
+/// features are turned off.
+pub fn provider(settings: &AiSettings) -> io::Result<Box<dyn Provider>> {
+    if !settings.enabled {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "AI features are off; turn them on in Preferences",
+        ));
+    }
+    Ok(match settings.provider {
+        AiProvider::OpenAiCompatible => Box::new(OpenAiCompatible {
+            url: settings.url.clone(),
+            model: settings.model.clone(),
+            api_key: secrets::get(API_KEY_SECRET)?.filter(|key| !key.is_empty()),
+        }),
+        AiProvider::LlamaCpp => Box::new(LlamaCpp {
+            url: settings.url.clone(),
+        }),
+    })
+}
+
+/// Something the model is asked to do with the notes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Summarize a note.
+    Summarize { title: String, content: String },
+    /// Suggest a title for a note.
+    GenerateTitle { title: String, content: String },
+    /// Answer a question from the notes that share the most words with it.
+    Ask {
+        question: String,
+        notes: Vec<(String, String)>,
+    },
+}
+
+/// What the model answered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    /// The answer, e.g. the summary.
+    pub text: String,
+    /// The titles of the notes that were sent along.
+    pub sources: Vec<String>,
+}
+
+impl Request {
+    /// Prepares a question, finding the notes to answer it from in the search index.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The search index.
+    /// * `question` - The question, in plain language.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Request>` containing the question and the notes, or a `NotFound` error if no
+    /// note shares a word with it.
+    pub fn ask(index: &SearchIndex, question: &str) -> io::Result<Request> {
+        let mut notes = Vec::new();
+        for hit in index.search_any(question, CONTEXT_NOTES) {
+            // Notes removed since they were indexed are skipped
+            if let Ok(content) = Notes::read_note_file(&hit.title) {
+                notes.push((hit.title, content));
+            }
+        }
+        if notes.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No notes mention anything in the question"));
+        }
+        Ok(Request::Ask {
+            question: question.trim().to_string(),
+            notes,
+        })
+    }
+
+    /// A heading for the answer, e.g. `Summary of ideas`.
+    pub fn label(&self) -> String {
+        match self {
+            Request::Summarize { title, .. } => format!("Summary of {}", title),
+            Request::GenerateTitle { title, .. } => format!("Title for {}", title),
+            Request::Ask { question, .. } => question.clone(),
+        }
+    }
+
+    /// Sends the request to the model, which can take a while.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Answer>` containing the answer.
+    pub fn run(&self, provider: &dyn Provider) -> io::Result<Answer> {
+        let (instructions, message) = self.prompt();
+        let text = provider.complete(instructions, &message)?;
+        let text = match self {
+            Request::GenerateTitle { .. } => clean_title(&text),
+            _ => text.trim().to_string(),
+        };
+        let sources = match self {
+            Request::Ask { notes, .. } => notes.iter().map(|(title, _)| title.clone()).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Answer { text, sources })
+    }
+
+    fn prompt(&self) -> (&'static str, String) {
+        match self {
+            Request::Summarize { title, content } => (
+                "Summarize the following note in a few sentences or bullet points. Use Markdown.",
+                format!("# {}\n\n{}", title, truncate(content)),
+            ),
+            Request::GenerateTitle { content, .. } => (
+                "Suggest a short, descriptive title for the following note. Answer with the title only.",
+                truncate(content).to_string(),
+            ),
+            Request::Ask { question, notes } => {
+                let mut message = String::new();
+                for (title, content) in notes {
+                    message.push_str(&format!("## {}\n\n{}\n\n", title, truncate(content)));
+                }
+                message.push_str(&format!("Question: {}", question));
+                (
+                    "Answer the question using only the notes given. Say so if they don't contain the answer, and name the notes you used.",
+                    message,
+                )
+            }
+        }
+    }
+}
+
+// Cuts a note down to what is sent to the model
+fn truncate(content: &str) -> &str {
+    match content.char_indices().nth(MAX_NOTE_CHARS) {
+        Some((index, _)) => &content[..index],
+        None => content,
+    }
+}
+
+/// Tidies up a suggested title: models like to quote it, make it a heading or add a label.
+pub fn clean_title(text: &str) -> String {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    let line = line.trim_start_matches('#').trim();
+    let line = line.strip_prefix("Title:").unwrap_or(line).trim();
+    line.trim_matches(|c: char| c == '"' || c == '\'' || c == '*' || c == '`').trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Echo {
+        messages: Mutex<Vec<String>>,
+        answer: &'static str,
+    }
+
+    impl Provider for Echo {
+        fn complete(&self, _: &str, message: &str) -> io::Result<String> {
+            self.messages.lock().unwrap().push(message.to_string());
+            Ok(self.answer.to_string())
+        }
+    }
+
+    #[test]
+    fn test_requests() {
+        let provider = Echo {
+            messages: Mutex::new(Vec::new()),
+            answer: "\n## \"Trip to Lisbon\"\nBecause it mentions Lisbon",
+        };
+        let request = Request::GenerateTitle {
+            title: "untitled".to_string(),
+            content: "Flights to Lisbon on Friday".to_string(),
+        };
+        assert_eq!(request.run(&provider).unwrap().text, "Trip to Lisbon");
+
+        let request = Request::Ask {
+            question: "When do I fly?".to_string(),
+            notes: vec![("trip".to_string(), "x".repeat(MAX_NOTE_CHARS + 10))],
+        };
+        let answer = request.run(&provider).unwrap();
+        assert_eq!(answer.sources, vec!["trip".to_string()]);
+        let message = provider.messages.lock().unwrap()[1].clone();
+        assert!(message.starts_with("## trip\n\n"));
+        assert!(message.ends_with("Question: When do I fly?"));
+        assert!(message.len() < MAX_NOTE_CHARS + 50);
+    }
+
+    #[test]
+    fn test_clean_title() {
+        assert_eq!(clean_title("Title: **Weekly review**"), "Weekly review");
+        assert_eq!(clean_title("  'Groceries'  "), "Groceries");
+        assert_eq!(clean_title(""), "");
+    }
+}
@@ -0,0 +1,411 @@
+use std::io::{self, Read};
+
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::clip;
+use crate::ical;
+use crate::notes::Notes;
+use crate::search::SearchIndex;
+use crate::secrets;
+use crate::store;
+use crate::todos::{self, Todos};
+
+/// The port the API listens on unless another one is given.
+pub const DEFAULT_PORT: u16 = 4780;
+
+/// The name of the secret holding the token that API requests must carry.
+pub const TOKEN_SECRET: &str = "api-token";
+
+/// The body of a request creating a note.
+#[derive(Deserialize, Debug)]
+struct NewNote {
+    title: String,
+    #[serde(default)]
+    content: String,
+    /// Append to the note if it exists, e.g. to collect clippings in an inbox note.
+    #[serde(default)]
+    append: bool,
+}
+
+/// The body of a request adding a todo.
+#[derive(Deserialize, Debug)]
+struct NewTodo {
+    description: String,
+    /// The due date as `YYYY-MM-DD`.
+    due: Option<String>,
+    /// The title of the note the todo belongs to.
+    note: Option<String>,
+}
+
+/// The body of a request clipping a web page.
+#[derive(Deserialize, Debug)]
+struct Clip {
+    /// The URL of the page, which is fetched unless its HTML is sent along.
+    url: Option<String>,
+    /// The HTML of the page, e.g. as the browser shows it to a signed-in user.
+    html: Option<String>,
+}
+
+/// A response to an API request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The JSON body, or `Value::Null` for an empty body.
+    pub body: Value,
+    /// A body in another format than JSON along with its content type, e.g. the calendar feed.
+    pub text: Option<(&'static str, String)>,
+}
+
+impl Response {
+    fn ok(body: Value) -> Response {
+        Response {
+            status: 200,
+            body,
+            text: None,
+        }
+    }
+
+    fn calendar(ics: String) -> Response {
+        Response {
+            status: 200,
+            body: Value::Null,
+            text: Some(("text/calendar; charset=utf-8", ics)),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Response {
+        Response {
+            status,
+            body: json!({ "error": message.into() }),
+            text: None,
+        }
+    }
+}
+
+/// Returns the API token, generating one on first use.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the token.
+pub fn token() -> io::Result<String> {
+    if let Some(token) = secrets::get(TOKEN_SECRET)? {
+        return Ok(token);
+    }
+    let token = secrets::generate_key();
+    secrets::set(TOKEN_SECRET, &token)?;
+    Ok(token)
+}
+
+/// Serves the notes and todos of the active vault over HTTP on `127.0.0.1` until the process
+/// is stopped. Every request except CORS preflights must carry the API token as
+/// `Authorization: Bearer <token>`.
+///
+/// # Arguments
+///
+/// * `port` - The port to listen on.
+///
+/// # Returns
+///
+/// An `io::Result<()>` with an error if the server could not be started.
+pub fn serve(port: u16) -> io::Result<()> {
+    let token = token()?;
+    let server = tiny_http::Server::http(("127.0.0.1", port)).map_err(|err| io::Error::new(io::ErrorKind::AddrInUse, err))?;
+    println!("Serving the notes API on http://127.0.0.1:{}", port);
+    println!("Send `Authorization: Bearer {}` with each request", token);
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let response = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => {
+                let authorization = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("Authorization"))
+                    .map(|header| header.value.as_str().to_string());
+                handle(
+                    request.method().as_str(),
+                    request.url(),
+                    authorization.as_deref(),
+                    &body,
+                    &token,
+                )
+            }
+            Err(err) => Response::error(400, format!("Failed to read the request: {}", err)),
+        };
+        // The query is left out, as the calendar feed may carry the token in it
+        let path = request.url().split('?').next().unwrap_or_default();
+        log::info!("{} {} {}", request.method(), path, response.status);
+        let (content_type, data) = match response.text {
+            Some((content_type, text)) => (content_type, text.into_bytes()),
+            None if response.body.is_null() => ("application/json", Vec::new()),
+            None => ("application/json", response.body.to_string().into_bytes()),
+        };
+        let mut reply = tiny_http::Response::from_data(data).with_status_code(response.status);
+        // Browser extensions call the API from other origins
+        for (field, value) in [
+            ("Content-Type", content_type),
+            ("Access-Control-Allow-Origin", "*"),
+            ("Access-Control-Allow-Headers", "Authorization, Content-Type"),
+            ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
+        ] {
+            if let Ok(header) = tiny_http::Header::from_bytes(field, value) {
+                reply.add_header(header);
+            }
+        }
+        if let Err(err) = request.respond(reply) {
+            log::warn!("Failed to answer a request: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Answers one API request.
+///
+/// Endpoints:
+/// * `GET /notes` - The titles of all notes.
+/// * `GET /notes/<title>` - A note and its content.
+/// * `POST /notes` - Creates a note from `{"title", "content", "append"}`.
+/// * `GET /search?q=<words>` - The notes matching the words, best matches first.
+/// * `GET /todos` - All todos.
+/// * `POST /todos` - Adds a todo from `{"description", "due", "note"}`.
+/// * `POST /clip` - Creates a note from a web page, from `{"url"}` or `{"html", "url"}`. The
+///   page is reduced to its main content and converted to Markdown, as in a bookmarklet.
+/// * `GET /calendar.ics?daily=1` - An iCalendar feed of the todos with a due date, and with
+///   `daily=1` of the daily notes. Calendar apps can pass the token as `?token=<token>` instead.
+///
+/// # Arguments
+///
+/// * `method` - The HTTP method.
+/// * `url` - The path and query of the request.
+/// * `authorization` - The `Authorization` header, if any.
+/// * `body` - The request body.
+/// * `token` - The API token the request must carry.
+pub fn handle(method: &str, url: &str, authorization: Option<&str>, body: &str, token: &str) -> Response {
+    if method == "OPTIONS" {
+        return Response {
+            status: 204,
+            body: Value::Null,
+            text: None,
+        };
+    }
+    let Ok(url) = Url::parse("http://localhost/").and_then(|base| base.join(url)) else {
+        return Response::error(400, "Invalid URL");
+    };
+    // Calendar apps subscribe to a URL and can't send headers, so the feed takes the token in its query
+    let presented = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(presented) => presented.to_string(),
+        None if url.path() == "/calendar.ics" => query(&url, "token").unwrap_or_default(),
+        None => String::new(),
+    };
+    if !constant_time_eq(presented.trim().as_bytes(), token.as_bytes()) {
+        return Response::error(401, "Missing or wrong API token");
+    }
+    let segments: Vec<String> = url
+        .path()
+        .trim_matches('/')
+        .split('/')
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let result = match (method, segments.as_slice()) {
+        ("GET", ["notes"]) => Notes::list_notes().map(|mut titles| {
+            titles.sort_by_key(|title| title.to_lowercase());
+            Response::ok(json!(titles))
+        }),
+        // Titles may contain folders, e.g. `/notes/meetings/standup`
+        ("GET", ["notes", title @ ..]) if !title.is_empty() => {
+            let title = title.join("/");
+            Notes::read_note_file(&title).map(|content| Response::ok(json!({ "title": title, "content": content })))
+        }
+        ("POST", ["notes"]) => match serde_json::from_str::<NewNote>(body) {
+            Ok(note) => create_note(note),
+            Err(err) => Ok(Response::error(400, err.to_string())),
+        },
+        ("GET", ["search"]) => search(&query(&url, "q").unwrap_or_default()),
+        ("GET", ["todos"]) => Todos::load_or_recover().map(|(todos, _)| Response::ok(json!(todos.items))),
+        ("POST", ["todos"]) => match serde_json::from_str::<NewTodo>(body) {
+            Ok(todo) => add_todo(todo),
+            Err(err) => Ok(Response::error(400, err.to_string())),
+        },
+        ("POST", ["clip"]) => match serde_json::from_str::<Clip>(body) {
+            Ok(clip) => clip_page(clip),
+            Err(err) => Ok(Response::error(400, err.to_string())),
+        },
+        ("GET", ["calendar.ics"]) => {
+            let daily_notes = query(&url, "daily").is_some_and(|daily| daily == "1" || daily == "true");
+            ical::feed(daily_notes).map(Response::calendar)
+        }
+        (_, ["notes", ..] | ["search"] | ["todos"] | ["clip"] | ["calendar.ics"]) => Ok(Response::error(405, "Method not allowed")),
+        _ => Ok(Response::error(404, "No such endpoint")),
+    };
+    result.unwrap_or_else(|err| {
+        let status = match err.kind() {
+            io::ErrorKind::NotFound => 404,
+            io::ErrorKind::AlreadyExists => 409,
+            io::ErrorKind::PermissionDenied => 403,
+            io::ErrorKind::InvalidInput => 400,
+            _ => 500,
+        };
+        Response::error(status, err.to_string())
+    })
+}
+
+fn create_note(note: NewNote) -> io::Result<Response> {
+    let title = note.title.trim();
+    if title.is_empty() {
+        return Ok(Response::error(400, "The title is empty"));
+    }
+    if note.append {
+        let status = if Notes::append_to_note(title, &note.content)? { 201 } else { 200 };
+        return Ok(Response {
+            status,
+            body: json!({ "title": title }),
+            text: None,
+        });
+    }
+    if Notes::note_exists(title)? {
+        return Ok(Response::error(409, format!("A note named {} already exists", title)));
+    }
+    Notes::create_note(title, &note.content)?;
+    Ok(Response {
+        status: 201,
+        body: json!({ "title": title }),
+        text: None,
+    })
+}
+
+fn clip_page(page: Clip) -> io::Result<Response> {
+    let url = page.url.as_deref().filter(|url| !url.trim().is_empty());
+    let title = match (page.html, url) {
+        (Some(html), url) => clip::note_from_html(&html, url.map(clip::parse_url).transpose()?.as_ref())?,
+        (None, Some(url)) => clip::note_from_url(url)?,
+        (None, None) => return Ok(Response::error(400, "Send the URL or the HTML of the page")),
+    };
+    Ok(Response {
+        status: 201,
+        body: json!({ "title": title }),
+        text: None,
+    })
+}
+
+fn search(query: &str) -> io::Result<Response> {
+    if let Some(hits) = store::active_store()?.search(query)? {
+        let hits: Vec<Value> = hits
+            .into_iter()
+            .map(|(title, score)| json!({ "title": title, "score": score }))
+            .collect();
+        return Ok(Response::ok(json!(hits)));
+    }
+    let mut index = SearchIndex::load()?;
+    index.refresh()?;
+    index.save()?;
+    let hits: Vec<Value> = index
+        .search(query)
+        .into_iter()
+        .map(|hit| json!({ "title": hit.title, "score": hit.score }))
+        .collect();
+    Ok(Response::ok(json!(hits)))
+}
+
+fn add_todo(todo: NewTodo) -> io::Result<Response> {
+    let description = todo.description.trim().to_string();
+    if description.is_empty() {
+        return Ok(Response::error(400, "The description is empty"));
+    }
+    let due_date = match todo.due.as_deref() {
+        Some(date) => match todos::parse_due_date(date) {
+            Some(due_date) => Some(due_date),
+            None => return Ok(Response::error(400, format!("Invalid date: {}", date))),
+        },
+        None => None,
+    };
+    let (mut todos, _) = Todos::load_or_recover()?;
+    match todo.note {
+        Some(note) => todos.add_to_note(description, note),
+        None => todos.add(description, None),
+    }
+    if let Some(added) = todos.items.last_mut() {
+        added.due_date = due_date;
+    }
+    todos.save_to_file()?;
+    Ok(Response {
+        status: 201,
+        body: json!(todos.items.last()),
+        text: None,
+    })
+}
+
+fn query(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+}
+
+// Compares without exiting early, so response times do not reveal how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    const TOKEN: &str = "secret";
+    const AUTH: Option<&str> = Some("Bearer secret");
+
+    #[test]
+    fn test_api() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        assert_eq!(handle("GET", "/notes", None, "", TOKEN).status, 401);
+        assert_eq!(handle("GET", "/notes", Some("Bearer guess"), "", TOKEN).status, 401);
+        assert_eq!(handle("OPTIONS", "/notes", None, "", TOKEN).status, 204);
+
+        let body = r#"{"title": "reading/Clippings", "content": "First"}"#;
+        assert_eq!(handle("POST", "/notes", AUTH, body, TOKEN).status, 201);
+        assert_eq!(handle("POST", "/notes", AUTH, body, TOKEN).status, 409);
+        let body = r#"{"title": "reading/Clippings", "content": "Second", "append": true}"#;
+        assert_eq!(handle("POST", "/notes", AUTH, body, TOKEN).status, 200);
+        assert_eq!(handle("POST", "/notes", AUTH, "not json", TOKEN).status, 400);
+
+        let response = handle("GET", "/notes/reading/Clippings", AUTH, "", TOKEN);
+        assert_eq!(response.body["content"], "First\n\nSecond");
+        assert_eq!(handle("GET", "/notes", AUTH, "", TOKEN).body, json!(["reading/Clippings"]));
+        assert_eq!(handle("GET", "/notes/Missing", AUTH, "", TOKEN).status, 404);
+
+        let response = handle("GET", "/search?q=second", AUTH, "", TOKEN);
+        assert_eq!(response.body[0]["title"], "reading/Clippings");
+
+        let body = r#"{"description": "Read the clippings", "due": "2030-01-31", "note": "reading/Clippings"}"#;
+        assert_eq!(handle("POST", "/todos", AUTH, body, TOKEN).status, 201);
+        let response = handle("GET", "/todos", AUTH, "", TOKEN);
+        assert_eq!(response.body[0]["description"], "Read the clippings");
+        assert_eq!(response.body[0]["note"], "reading/Clippings");
+        assert!(response.body[0]["due_date"].is_i64());
+
+        let page = "<html><head><title>Slow Bread</title></head><body><article><p>Mix the flour, water and salt, \
+            then leave the dough to rise overnight before baking it in a hot oven.</p></article></body></html>";
+        let body = json!({ "html": page, "url": "https://example.com/bread" }).to_string();
+        let response = handle("POST", "/clip", AUTH, &body, TOKEN);
+        assert_eq!(response.status, 201);
+        let content = Notes::read_note_file(response.body["title"].as_str().unwrap()).unwrap();
+        assert!(content.contains("source: https://example.com/bread"));
+        assert!(content.contains("leave the dough to rise"));
+        assert_eq!(handle("POST", "/clip", AUTH, "{}", TOKEN).status, 400);
+        let body = json!({ "html": page, "url": "not a url" }).to_string();
+        assert_eq!(handle("POST", "/clip", AUTH, &body, TOKEN).status, 400);
+
+        assert_eq!(handle("GET", "/calendar.ics", None, "", TOKEN).status, 401);
+        assert_eq!(handle("GET", "/todos?token=secret", None, "", TOKEN).status, 401);
+        let response = handle("GET", "/calendar.ics?token=secret", None, "", TOKEN);
+        let (content_type, ics) = response.text.unwrap();
+        assert!(content_type.starts_with("text/calendar"));
+        assert!(ics.contains("SUMMARY:Read the clippings"));
+
+        assert_eq!(handle("DELETE", "/todos", AUTH, "", TOKEN).status, 405);
+        assert_eq!(handle("GET", "/elsewhere", AUTH, "", TOKEN).status, 404);
+    }
+}
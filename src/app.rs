@@ -1,9 +1,85 @@
-use eframe::egui::{self, CentralPanel, SidePanel, TopBottomPanel};
+use chrono::{Datelike, NaiveDate};
+use eframe::egui::{self, CentralPanel, Id, SidePanel, TopBottomPanel};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+use std::ops::Range;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use crate::notes::Notes;
-use crate::todos::Todos;
+use crate::activity::{self, ActivityKind, Insights};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ai;
+use crate::attachments;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::audio::{self, Recorder};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::backup::{self, RestoreChange, RestoreOptions};
+use crate::batch::{self, BatchAction, DateField, DateRange, PlannedChange};
+use crate::calendar::{self, CalendarMarks};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::clip;
+use crate::command_history::CommandHistory;
+use crate::commands::{self, Command};
+use crate::complete::{self, CompletionKind};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::config::{AiProvider, BackupSchedule, S3Settings, ShareService, SyncService, WebDavSettings};
+use crate::config::{Config, NoteExtension, VaultEntry};
+#[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+use crate::dictation::Dictation;
+use crate::focus::{self, FocusSession, FocusTimer};
+use crate::reports::{self, WeeklyReport};
+use crate::editing::{self, Edit};
+use crate::editor::NoteBuffer;
+use crate::export::{self, ExportPreset, NoteFormat};
+use crate::front_matter::FrontMatter;
+use crate::fuzzy;
+use crate::highlight::{self, EditorTheme, CODE_LANGUAGES};
+use crate::history::{self, HistoryRetention};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::hotkey::{self, HotkeyListener};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ipc::{self, Incoming, Request};
+use crate::keymap::{self, Action};
+use crate::lang::{self, NoteLanguage, TextDirection, LANGUAGES};
+use crate::macros::{self, Macro, MacroStep, Macros};
+use crate::navigation::{Direction, Navigation};
+use crate::notes::{self, NoteSort, Notes};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ocr;
+use crate::outline::{self, Heading};
+use crate::preview;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::print::{self, PrintOptions};
+use crate::reader::{self, ReadingStyle};
+use crate::reading::{self, ReadingItem};
+use crate::safe_mode::{self, SafeModeReason};
+use crate::scheduler::{self, JobStatus, Schedule};
+use crate::search::{self, RelatedNote, SearchHit, SearchIndex, SearchOptions};
+use crate::selection::Selection;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::secrets;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::share;
+use crate::spellcheck::Dictionaries;
+use crate::stats::{self, VaultStats};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sync::{self, encrypted, s3, webdav, Resolution, SyncReport};
+use crate::tasks::{self, InlineTask, TaskIndex};
+use crate::templates;
+use crate::theme::{self, EditorFont, Theme};
+use crate::title;
+use crate::todos::{self, DeadlineKind, DueStatus, Recovery, Todo, TodoPatch, TodoSort, TodoStats, Todos};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tray::{Tray, TrayAction};
+use crate::vault::{self, Vault};
+use crate::verify::{Issue, VerifyReport};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::watcher::{NoteEvent, NoteWatcher};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
@@ -14,7 +90,478 @@ pub struct TemplateApp {
     todos: Arc<Mutex<Todos>>,
     selected_note: Option<String>,
     command_input: String,
+    #[serde(skip)]
+    command_status: Option<String>,
+    #[serde(skip)]
+    command_history: CommandHistory,
+    #[serde(skip)]
+    command_search: Option<CommandSearch>,
+    #[serde(skip)]
+    toasts: Vec<Toast>,
+    #[serde(skip)]
+    pending_delete: Vec<String>,
+    #[serde(skip)]
+    note_selection: Selection,
+    #[serde(skip)]
+    bulk_edit: BulkEdit,
+    #[serde(skip)]
+    merge_dialog: MergeDialog,
     mode: Mode,
+    note_sort: NoteSort,
+    manual_order: Vec<String>,
+    todo_grouping: TodoGrouping,
+    todo_project: Option<String>,
+    todo_sort: TodoSort,
+    todo_view: TodoView,
+    // Kept across restarts, as the session goes on while the app is closed
+    focus: Option<FocusTimer>,
+    #[serde(skip)]
+    todo_edit: Option<TodoEdit>,
+    // The todos picked for bulk actions, while picking them
+    #[serde(skip)]
+    todo_selection: Option<BTreeSet<usize>>,
+    // The archived todos counted in the todo statistics, loaded when they are first shown
+    #[serde(skip)]
+    todo_archive: Option<Todos>,
+    code_language: String,
+    preview: bool,
+    reading_mode: bool,
+    outline_open: bool,
+    todos_panel_open: bool,
+    reading_style: ReadingStyle,
+    recent_notes: Vec<String>,
+    // Where the cursor and scroll bar were in recently opened notes, to pick up where they were left
+    note_positions: HashMap<String, NotePosition>,
+    navigation: Navigation,
+    #[serde(skip)]
+    pending_navigation: Option<Direction>,
+    // Set while moving back or forward, so that it isn't recorded as opening another note
+    #[serde(skip)]
+    navigating: bool,
+    // The place in the recent notes that Ctrl+Tab has stepped to while Ctrl is held
+    #[serde(skip)]
+    note_cycle: Option<usize>,
+    #[serde(skip)]
+    restore_position: Option<NotePosition>,
+    // The recently opened notes of the vaults other than the active one, by vault name ("" for ~/.notes)
+    vault_recent_notes: HashMap<String, Vec<String>>,
+    search_options: SearchOptions,
+    #[serde(skip)]
+    reading_progress: f32,
+    #[serde(skip)]
+    new_note_title: String,
+    #[serde(skip)]
+    new_note_template: Option<String>,
+    #[serde(skip)]
+    new_note_todo: String,
+    #[serde(skip)]
+    buffer: Option<NoteBuffer>,
+    #[serde(skip)]
+    editor_selection: Option<(String, usize)>,
+    #[serde(skip)]
+    pending_jump: Option<usize>,
+    #[serde(skip)]
+    pending_selection: Option<Range<usize>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    watcher: Option<NoteWatcher>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    clip_dialog: ClipDialog,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    ai_dialog: AiDialog,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    passphrase_entry: PassphraseEntry,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    backup_dialog: BackupDialog,
+    #[serde(skip)]
+    schedule: Schedule,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    running_job: Option<RunningJob>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    automatic_backup: Option<Receiver<io::Result<Option<PathBuf>>>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    backup_checked_at: Option<u64>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    sync_job: Option<Receiver<io::Result<SyncReport>>>,
+    // Reads the text in images attached to the note with this title
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    ocr_job: Option<(String, Receiver<io::Result<usize>>)>,
+    // The title and content of a note to upload, which `poll_share` starts uploading on the next frame
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    share_requested: Option<(String, String)>,
+    // Uploads the note with this title, answering with its link
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    share_job: Option<(String, Receiver<io::Result<String>>)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    audio_recorder: Option<Recorder>,
+    #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+    #[serde(skip)]
+    dictation: Option<Dictation>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    last_sync: Option<(u64, Result<SyncReport, String>)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    sync_requested: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    sync_started_at: Option<u64>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    sync_password: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    sync_passphrase: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    ai_api_key: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    share_token: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    sync_conflicts_open: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    ipc_requests: Option<Receiver<Incoming>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    hotkey: Option<HotkeyListener>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    quick_capture: QuickCapture,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    tray: Option<Tray>,
+    // Set when the app should really quit rather than hide in the tray
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    quitting: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    print_options: PrintOptions,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    print_dialog_open: bool,
+    #[serde(skip)]
+    jobs_window_open: bool,
+    #[serde(skip)]
+    reading_window_open: bool,
+    // The month shown in the calendar window, while it is open
+    #[serde(skip)]
+    calendar_month: Option<NaiveDate>,
+    // Only the todos due on this day are listed, after it was picked in the calendar
+    #[serde(skip)]
+    todo_due_filter: Option<NaiveDate>,
+    #[serde(skip)]
+    reading_list: Vec<ReadingItem>,
+    #[serde(skip)]
+    vault_stats: Option<VaultStats>,
+    #[serde(skip)]
+    reports: Option<ReportsDialog>,
+    #[serde(skip)]
+    verify_report: Option<VerifyReport>,
+    #[serde(skip)]
+    insights: Option<Insights>,
+    // The spellcheck dictionaries of the languages of the notes opened so far
+    #[serde(skip)]
+    dictionaries: Dictionaries,
+    #[serde(skip)]
+    todos_recovery: Option<Recovery>,
+    #[serde(skip)]
+    date_query: DateQueryDialog,
+    #[serde(skip)]
+    quick_open: QuickOpen,
+    #[serde(skip)]
+    replace_dialog: ReplaceDialog,
+    #[serde(skip)]
+    completion: Completion,
+    #[serde(skip)]
+    export_dialog: ExportDialog,
+    #[serde(skip)]
+    macros: Macros,
+    #[serde(skip)]
+    macro_recording: Option<Macro>,
+    #[serde(skip)]
+    pending_macro: Option<String>,
+    #[serde(skip)]
+    task_index: TaskIndex,
+    #[serde(skip)]
+    search_index: SearchIndex,
+    search_query: String,
+    #[serde(skip)]
+    search_hits: Result<Vec<SearchHit>, String>,
+    #[serde(skip)]
+    search_stale: bool,
+    // The notes related to the open one, found again when it is opened or saved
+    #[serde(skip)]
+    related_notes: Option<(String, Vec<RelatedNote>)>,
+    #[serde(skip)]
+    config: Config,
+    #[serde(skip)]
+    window_title: String,
+    #[serde(skip)]
+    safe_mode: Option<SafeModeReason>,
+    #[serde(skip)]
+    vault: Option<Vault>,
+    #[serde(skip)]
+    preferences: Option<Config>,
+    // The theme last applied to the context, to apply it again only when it changes
+    #[serde(skip)]
+    applied_theme: Option<Theme>,
+    #[serde(skip)]
+    zen: Option<ZenMode>,
+    #[serde(skip)]
+    pending_switch: Option<String>,
+    #[serde(skip)]
+    window_focused: bool,
+    #[serde(skip)]
+    archived_notes: Vec<String>,
+    #[serde(skip)]
+    archived_view: Option<(String, String)>,
+    #[serde(skip)]
+    active_vault: Option<String>,
+    #[serde(skip)]
+    vaults: Vec<VaultEntry>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pending_vault_switch: Option<Option<String>>,
+}
+
+// How many notes the quick open switcher lists, and how many recently opened notes are remembered
+const QUICK_OPEN_RESULTS: usize = 12;
+const RECENT_NOTES: usize = 50;
+
+// Back and forward through the opened notes, Cmd+[ and Cmd+] on macOS
+const BACK_SHORTCUT: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::OpenBracket);
+const FORWARD_SHORTCUT: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::CloseBracket);
+
+// How many of the recent notes Ctrl+Tab steps through
+const CYCLED_NOTES: usize = 15;
+
+// How many of the recent notes are listed in the sidebar
+const LISTED_RECENT_NOTES: usize = 8;
+
+// The widest the editor gets in zen mode, in points
+const ZEN_LINE_WIDTH: f32 = 720.0;
+
+// The text fields the new note and search shortcuts focus
+const NEW_NOTE_TITLE_ID: &str = "new_note_title";
+const SEARCH_QUERY_ID: &str = "search_query";
+
+// The command bar, which is focused again after a history search
+const COMMAND_INPUT_ID: &str = "command_input";
+
+// How often to check whether a daily backup is due
+#[cfg(not(target_arch = "wasm32"))]
+const BACKUP_CHECK_SECS: u64 = 60 * 60;
+
+#[derive(Default)]
+struct QuickOpen {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+#[derive(Default)]
+struct DateQueryDialog {
+    open: bool,
+    field: DateField,
+    range: String,
+    results: Vec<String>,
+    export_dir: String,
+    tag: String,
+    rename_from: String,
+    rename_to: String,
+    review: Option<(BatchAction, Vec<PlannedChange>)>,
+}
+
+// How many seconds notifications without an Undo button are shown
+const TOAST_SECS: f64 = 4.0;
+
+// How many suggestions the completion popup lists
+const COMPLETIONS: usize = 8;
+
+// How many related notes are listed below the editor
+const RELATED_NOTES: usize = 5;
+
+#[derive(Default)]
+struct Completion {
+    query: String,
+    selected: usize,
+    // The start of a link or tag whose popup was closed with Esc
+    dismissed: Option<usize>,
+}
+
+#[derive(Default)]
+struct ExportDialog {
+    open: bool,
+    format: NoteFormat,
+    destination: String,
+    stylesheet: String,
+    include_attachments: bool,
+}
+
+#[derive(Default)]
+struct ReplaceDialog {
+    open: bool,
+    find: String,
+    replace: String,
+    options: SearchOptions,
+    review: Option<(BatchAction, Vec<PlannedChange>)>,
+}
+
+// What the find and replace dialog was asked to do
+enum ReplaceStep {
+    FindNext,
+    ReplaceOne,
+    ReplaceAllInNote,
+    PlanVault,
+    ApplyVault,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct RunningJob {
+    name: String,
+    started_at: u64,
+    receiver: Receiver<JobStatus>,
+}
+
+// Where the cursor and the scroll bar of the editor were in a note
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+struct NotePosition {
+    cursor: usize,
+    scroll: f32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct ClipDialog {
+    open: bool,
+    url: String,
+    job: Option<Receiver<io::Result<String>>>,
+    error: Option<String>,
+}
+
+// Questions for the notes and what the language model answered
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct AiDialog {
+    open: bool,
+    question: String,
+    // A request made by a command, which is sent when the window is next shown
+    pending: Option<ai::Request>,
+    job: Option<(ai::Request, Receiver<io::Result<ai::Answer>>)>,
+    answer: Option<(ai::Request, Result<ai::Answer, String>)>,
+}
+
+// The passphrase typed to unlock the open note, or to protect it in the Protect Note dialog
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct PassphraseEntry {
+    protecting: bool,
+    passphrase: String,
+    confirm: String,
+    error: Option<String>,
+}
+
+// What was done to the passphrase of the open note from the editor's toolbar
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProtectionChange {
+    Protect,
+    Relock,
+    Remove,
+}
+
+// A notification in the corner of the window that goes away by itself
+struct Toast {
+    message: String,
+    // What the Undo button of the notification reverts, if it has one
+    undo: Option<Undo>,
+    // How long the notification is shown
+    secs: f64,
+    // When the notification was first shown, as egui time
+    shown_at: Option<f64>,
+}
+
+// What the bulk actions on the notes selected in the sidebar are applied with
+#[derive(Default)]
+struct BulkEdit {
+    tag: String,
+    folder: String,
+    export_dir: String,
+}
+
+// The notes chosen to be merged, in the order they are listed, and the title of the merged note.
+// The dialog is open while there are any.
+#[derive(Default)]
+struct MergeDialog {
+    titles: Vec<String>,
+    target: String,
+}
+
+// The weekly report of the time spent in focus sessions shown in the Reports dialog
+struct ReportsDialog {
+    report: WeeklyReport,
+    export_path: String,
+    status: Option<String>,
+}
+
+// A destructive action that can be reverted from its notification
+enum Undo {
+    DeletedNotes(Vec<DeletedNote>),
+}
+
+// A note moved to the trash, along with the indices of the todos it owned and their lines
+struct DeletedNote {
+    title: String,
+    // The ids of the todos the note owned, along with their lines
+    todos: Vec<(u64, Option<usize>)>,
+}
+
+// Distraction-free writing with only the editor, which is centred and keeps the cursor in the
+// middle of the screen
+struct ZenMode {
+    // Whether the window was already full screen, so that leaving zen mode doesn't change it
+    was_fullscreen: bool,
+    // Where the cursor was last frame, to scroll only when it moves
+    cursor: Option<usize>,
+}
+
+// The small always-on-top window that jots something down without switching to the app
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct QuickCapture {
+    open: bool,
+    text: String,
+    as_todo: bool,
+    focused: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct BackupDialog {
+    open: bool,
+    destination: String,
+    file: String,
+    merge: bool,
+    // The changes a dry run of the restore found, shown before anything is restored
+    preview: Option<Vec<RestoreChange>>,
+    error: Option<String>,
 }
 
 impl Default for TemplateApp {
@@ -27,152 +574,6090 @@ impl Default for TemplateApp {
             notes.add(note);
         }
 
-        // Load todos from the file system
-        let todos = Todos::load_from_file().unwrap_or_default();
+        // Load todos from the file system, repairing the file if it is corrupted
+        let (todos, todos_recovery) = Todos::load_or_recover().unwrap_or_else(|err| {
+            log::error!("Failed to load todos: {}", err);
+            (Todos::new(), None)
+        });
 
         Self {
             notes: Arc::new(Mutex::new(notes)),
             todos: Arc::new(Mutex::new(todos)),
             selected_note: None,
             command_input: String::new(),
+            command_status: None,
+            command_history: CommandHistory::load().unwrap_or_default(),
+            command_search: None,
+            toasts: Vec::new(),
+            pending_delete: Vec::new(),
+            note_selection: Selection::default(),
+            bulk_edit: BulkEdit::default(),
+            merge_dialog: MergeDialog::default(),
             mode: Mode::Command,
+            note_sort: NoteSort::default(),
+            manual_order: Vec::new(),
+            todo_grouping: TodoGrouping::default(),
+            todo_view: TodoView::default(),
+            focus: None,
+            todo_project: None,
+            todo_sort: TodoSort::default(),
+            todo_edit: None,
+            todo_selection: None,
+            todo_archive: None,
+            code_language: String::new(),
+            preview: false,
+            reading_mode: false,
+            outline_open: false,
+            todos_panel_open: true,
+            reading_style: ReadingStyle::default(),
+            recent_notes: Vec::new(),
+            note_positions: HashMap::new(),
+            navigation: Navigation::default(),
+            pending_navigation: None,
+            navigating: false,
+            note_cycle: None,
+            restore_position: None,
+            vault_recent_notes: HashMap::new(),
+            search_options: SearchOptions::default(),
+            reading_progress: 0.0,
+            new_note_title: String::new(),
+            new_note_template: None,
+            new_note_todo: String::new(),
+            buffer: None,
+            editor_selection: None,
+            pending_jump: None,
+            pending_selection: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            watcher: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            clip_dialog: ClipDialog::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            ai_dialog: AiDialog::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            passphrase_entry: PassphraseEntry::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            backup_dialog: BackupDialog::default(),
+            schedule: Schedule::load().unwrap_or_default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            running_job: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            automatic_backup: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            backup_checked_at: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            sync_job: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            ocr_job: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            share_requested: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            share_job: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            audio_recorder: None,
+            #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+            dictation: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            last_sync: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            sync_requested: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            sync_started_at: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            sync_password: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            ai_api_key: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            share_token: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            sync_passphrase: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            sync_conflicts_open: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            ipc_requests: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            hotkey: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            quick_capture: QuickCapture::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            tray: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            quitting: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            print_options: PrintOptions::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            print_dialog_open: false,
+            jobs_window_open: false,
+            reading_window_open: false,
+            calendar_month: None,
+            todo_due_filter: None,
+            reading_list: Vec::new(),
+            vault_stats: None,
+            reports: None,
+            verify_report: None,
+            insights: None,
+            dictionaries: Dictionaries::default(),
+            todos_recovery,
+            date_query: DateQueryDialog::default(),
+            quick_open: QuickOpen::default(),
+            replace_dialog: ReplaceDialog::default(),
+            completion: Completion::default(),
+            export_dialog: ExportDialog::default(),
+            macros: Macros::load().unwrap_or_default(),
+            macro_recording: None,
+            pending_macro: None,
+            task_index: TaskIndex::default(),
+            search_index: SearchIndex::load().unwrap_or_default(),
+            search_query: String::new(),
+            search_hits: Ok(Vec::new()),
+            search_stale: false,
+            related_notes: None,
+            config: Config::load().unwrap_or_default(),
+            window_title: String::new(),
+            safe_mode: None,
+            vault: None,
+            preferences: None,
+            applied_theme: None,
+            zen: None,
+            pending_switch: None,
+            window_focused: true,
+            archived_notes: Vec::new(),
+            archived_view: None,
+            active_vault: None,
+            vaults: Vec::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            pending_vault_switch: None,
         }
     }
 }
 
 impl TemplateApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        // Reopen the vault the last session switched to, before anything is read from it
+        let vaults = vault::registered().unwrap_or_else(|err| {
+            log::error!("Failed to read the registered vaults: {}", err);
+            Vec::new()
+        });
+        let active_vault = vault::last_active()
+            .unwrap_or_default()
+            .filter(|name| match vault::resolve(Some(name)) {
+                Ok(root) => {
+                    vault::set_active_root(Some(root));
+                    true
+                }
+                Err(err) => {
+                    log::error!("Failed to open vault {}: {}", name, err);
+                    false
+                }
+            });
+        // Take the lock first, so that a second window does not write anything
+        #[cfg(not(target_arch = "wasm32"))]
+        let vault = Vault::open()
+            .map_err(|err| log::error!("Failed to open the notes directory: {}", err))
+            .ok();
+        let safe_mode = safe_mode::begin_session(safe_mode::requested()).unwrap_or_else(|err| {
+            log::error!("Failed to record the session: {}", err);
+            None
+        });
+        let mut app: Self = match cc.storage {
+            Some(storage) if safe_mode.is_none() => eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
+            _ => Default::default(),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            app.vault = vault;
+        }
+        app.active_vault = active_vault;
+        app.vaults = vaults;
+        egui_extras::install_image_loaders(&cc.egui_ctx);
+        reader::install_fonts(&cc.egui_ctx);
+        if let Some(reason) = safe_mode {
+            // Start from defaults and rebuild the search index, in case any of them is what keeps crashing
+            log::warn!("Starting in safe mode: {}", reason.describe());
+            app.safe_mode = safe_mode;
+            app.config = Config::default();
+            app.macros = Macros::default();
+            app.search_index = SearchIndex::default();
+        }
+        app.sort_notes();
+        app.refresh_tasks();
+        app.refresh_search();
+        app.refresh_archived();
+        if let Some(title) = app.selected_note.clone() {
+            app.open_note(&title);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if app.safe_mode.is_none() {
+            app.start_watcher(&cc.egui_ctx);
+            if app.config.backup.schedule != BackupSchedule::Off && !app.is_read_only() {
+                app.start_automatic_backup(&cc.egui_ctx, true);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            // Let editor plugins and hotkey daemons control this window instead of starting another
+            let ctx = cc.egui_ctx.clone();
+            match ipc::listen(move || ctx.request_repaint()) {
+                Ok(receiver) => {
+                    app.ipc_requests = Some(receiver);
+                    app.register_hotkey(&cc.egui_ctx);
+                    let ctx = cc.egui_ctx.clone();
+                    match Tray::start(move || ctx.request_repaint()) {
+                        Ok(tray) => app.tray = Some(tray),
+                        Err(err) => log::warn!("Failed to add the tray icon: {}", err),
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::AddrInUse => log::info!("Another window takes requests"),
+                Err(err) => log::error!("Failed to listen for requests from other programs: {}", err),
+            }
         }
-        Default::default()
+        app
     }
 
-    fn create_note(&mut self, title: &str, content: &str) {
-        let mut notes = self.notes.lock().unwrap();
-        notes.add(title.to_string());
-        Notes::create_note_file(title, content).unwrap();
+    // Pick up notes edited in other applications
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_watcher(&mut self, ctx: &egui::Context) {
+        let ctx = ctx.clone();
+        self.watcher = NoteWatcher::new(move || ctx.request_repaint())
+            .map_err(|err| log::warn!("Failed to watch the notes directory: {}", err))
+            .ok();
     }
 
-    fn delete_note(&mut self, title: &str) {
-        let mut notes = self.notes.lock().unwrap();
-        notes.items.retain(|note| note != title);
-        Notes::delete_note_file(title).unwrap();
+    fn leave_safe_mode(&mut self, ctx: &egui::Context) {
+        self.safe_mode = None;
+        self.config = Config::load().unwrap_or_default();
+        self.macros = Macros::load().unwrap_or_default();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.start_watcher(ctx);
+        #[cfg(target_arch = "wasm32")]
+        let _ = ctx;
     }
 
-    fn create_todo(&mut self, description: &str, due_date: Option<i64>) {
-        let mut todos = self.todos.lock().unwrap();
-        todos.add(description.to_string(), due_date);
-        todos.save_to_file().unwrap();
+    // Save everything belonging to the active vault, then reopen the app on another one
+    #[cfg(not(target_arch = "wasm32"))]
+    fn switch_vault(&mut self, ctx: &egui::Context, name: Option<String>) -> Result<String, String> {
+        let label = vault_label(name.as_deref()).to_string();
+        if name == self.active_vault {
+            return Ok(format!("Already in {}", label));
+        }
+        let root = vault::resolve(name.as_deref()).map_err(|err| err.to_string())?;
+        self.save_active_note_to_disk();
+        if !self.is_read_only() {
+            if let Err(err) = self.search_index.save() {
+                log::error!("Failed to save the search index: {}", err);
+            }
+        }
+        // Release the lock before taking the next one, in case both vaults are the same directory
+        self.watcher = None;
+        self.vault = None;
+        vault::set_active_root(name.is_some().then_some(root));
+        self.vault = Vault::open()
+            .map_err(|err| log::error!("Failed to open the notes directory: {}", err))
+            .ok();
+        if let Err(err) = vault::set_last_active(name.as_deref()) {
+            log::error!("Failed to remember the active vault: {}", err);
+        }
+
+        let previous = std::mem::replace(&mut self.active_vault, name);
+        let recent = self
+            .vault_recent_notes
+            .remove(self.active_vault.as_deref().unwrap_or_default())
+            .unwrap_or_default();
+        let previous_recent = std::mem::replace(&mut self.recent_notes, recent);
+        self.vault_recent_notes.insert(previous.unwrap_or_default(), previous_recent);
+        self.buffer = None;
+        self.selected_note = None;
+        self.editor_selection = None;
+        self.pending_switch = None;
+        self.vault_stats = None;
+        self.verify_report = None;
+        self.navigation = Navigation::default();
+        self.insights = None;
+        // The todos of the other vault are loaded with the notes
+        self.todo_edit = None;
+        self.todo_selection = None;
+        self.todo_archive = None;
+
+        if self.safe_mode.is_none() {
+            self.config = Config::load().unwrap_or_default();
+            self.macros = Macros::load().unwrap_or_default();
+            self.schedule = Schedule::load().unwrap_or_default();
+            self.search_index = SearchIndex::load().unwrap_or_default();
+            self.start_watcher(ctx);
+        } else {
+            self.search_index = SearchIndex::default();
+        }
+        self.command_history = CommandHistory::load().unwrap_or_default();
+        self.command_search = None;
+        self.reload_notes();
+        Ok(format!("Switched to {}", label))
     }
 
-    fn delete_todo(&mut self, index: usize) {
-        let mut todos = self.todos.lock().unwrap();
-        if index < todos.items.len() {
-            todos.items.remove(index);
-            todos.save_to_file().unwrap();
+    // Pick up everything a restore may have replaced
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_vault(&mut self) {
+        if self.safe_mode.is_none() {
+            self.config = Config::load().unwrap_or_default();
+            self.macros = Macros::load().unwrap_or_default();
+            self.schedule = Schedule::load().unwrap_or_default();
+            self.search_index = SearchIndex::load().unwrap_or_default();
         }
+        self.reload_notes();
     }
 
-    fn save_active_note_to_disk(&self) {
-        if let Some(selected_note) = &self.selected_note {
-            if let Some(content) = Notes::read_note_file(selected_note).ok() {
-                Notes::update_note_file(selected_note, &content).unwrap();
+    // Whether another window holds the lock of the notes directory, so nothing can be saved here
+    fn is_read_only(&self) -> bool {
+        self.vault.as_ref().is_some_and(Vault::is_read_only)
+    }
+
+    fn show_read_only_bar(&mut self, ctx: &egui::Context) {
+        let Some(vault) = self.vault.as_mut().filter(|vault| vault.is_read_only()) else {
+            return;
+        };
+        let mut take_lock = None;
+        TopBottomPanel::top("read_only_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(ui.visuals().warn_fg_color, "🔒 Read-only");
+                match vault.held_by() {
+                    Some(holder) => ui.label(format!(
+                        "The notes are open in another window (process {}) and cannot be changed here.",
+                        holder.pid
+                    )),
+                    None => ui.label("The notes are open in another window and cannot be changed here."),
+                };
+                if ui.button("Try Again").clicked() {
+                    take_lock = Some(false);
+                }
+                if ui
+                    .button("Edit Anyway")
+                    .on_hover_text("Take over the notes if the other window is gone")
+                    .clicked()
+                {
+                    take_lock = Some(true);
+                }
+            });
+        });
+        if let Some(force) = take_lock {
+            match vault.try_lock(force) {
+                Ok(true) => self.command_status = Some("The notes can be edited again".to_string()),
+                Ok(false) => self.command_status = Some("The notes are still open in another window".to_string()),
+                Err(err) => self.command_status = Some(format!("Failed to lock the notes: {}", err)),
             }
         }
     }
-}
 
-impl eframe::App for TemplateApp {
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, self);
+    fn show_safe_mode_bar(&mut self, ctx: &egui::Context) {
+        let Some(reason) = self.safe_mode else {
+            return;
+        };
+        let mut leave = false;
+        TopBottomPanel::top("safe_mode_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(ui.visuals().warn_fg_color, "⚠ Safe mode");
+                ui.label(format!(
+                    "{} Settings, macros, scheduled jobs and the file watcher are off.",
+                    reason.describe()
+                ));
+                leave = ui.button("Leave Safe Mode").clicked();
+            });
+        });
+        if leave {
+            self.leave_safe_mode(ctx);
+        }
     }
 
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Periodically save the active note to disk
-        ctx.request_repaint_after(std::time::Duration::from_secs(10));
+    fn open_note(&mut self, title: &str) {
+        let unsaved = self
+            .buffer
+            .as_ref()
+            .is_some_and(|buffer| buffer.is_dirty() && buffer.title != title);
+        if unsaved && !self.config.save_on_note_switch {
+            // Ask what to do with the changes first, see show_pending_switch
+            self.pending_switch = Some(title.to_string());
+            return;
+        }
+        self.save_active_note_to_disk();
+        if !std::mem::take(&mut self.navigating) {
+            if let Some(current) = self.selected_note.as_deref().filter(|current| *current != title) {
+                self.navigation.visit(current);
+            }
+        }
+        self.selected_note = Some(title.to_string());
+        self.editor_selection = None;
+        self.recent_notes.retain(|recent| recent != title);
+        self.recent_notes.insert(0, title.to_string());
+        self.recent_notes.truncate(RECENT_NOTES);
+        self.restore_position = self.note_positions.get(title).copied();
+        self.buffer = match NoteBuffer::open(title) {
+            Ok(buffer) => {
+                self.log_activity(ActivityKind::Opened, title);
+                Some(buffer)
+            }
+            Err(err) => {
+                log::error!("Failed to open {}: {}", title, err);
+                None
+            }
+        };
+    }
+
+    fn create_note(&mut self, title: &str, content: &str, template: Option<&str>) {
+        {
+            let mut notes = self.notes.lock().unwrap();
+            let result = match template {
+                Some(template) => Notes::create_from_template(title, template, &HashMap::new()),
+                None => Notes::create_note(title, content),
+            };
+            if let Err(err) = result {
+                self.command_status = Some(format!("Failed to create {}: {}", title, err));
+                return;
+            }
+            notes.add(title.to_string());
+        }
+        self.log_activity(ActivityKind::Created, title);
+        if !self.manual_order.iter().any(|note| note == title) {
+            self.manual_order.push(title.to_string());
+        }
+        self.sort_notes();
+        self.refresh_search();
+    }
+
+    fn duplicate_note(&mut self, title: &str) -> Result<String, String> {
         self.save_active_note_to_disk();
+        let copy = Notes::duplicate(title).map_err(|err| format!("Failed to duplicate {}: {}", title, err))?;
+        self.notes.lock().unwrap().add(copy.clone());
+        self.log_activity(ActivityKind::Created, &copy);
+        // The copy goes right after the original when notes are ordered by hand
+        let position = self.manual_order.iter().position(|note| note == title);
+        self.manual_order.insert(position.map_or(self.manual_order.len(), |index| index + 1), copy.clone());
+        self.sort_notes();
+        self.refresh_search();
+        self.open_note(&copy);
+        Ok(format!("Duplicated {} as {}", title, copy))
+    }
 
-        TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            egui::menu::bar(ui, |ui| {
-                let is_web = cfg!(target_arch = "wasm32");
-                if !is_web {
-                    ui.menu_button("File", |ui| {
-                        if ui.button("Quit").clicked() {
-                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                        }
-                    });
-                    ui.add_space(16.0);
+    // Acts on all notes selected in the sidebar at once
+    fn show_bulk_actions(&mut self, ui: &mut egui::Ui) {
+        let mut action = None;
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.strong(format!("{} selected", self.note_selection.len()));
+                if ui.small_button("Clear").clicked() {
+                    self.note_selection.clear();
                 }
-                egui::widgets::global_dark_light_mode_buttons(ui);
             });
-        });
-
-        SidePanel::left("left_panel").show(ctx, |ui| {
-            ui.heading("Notes");
-            let notes = self.notes.lock().unwrap();
-            for note in &notes.items {
-                if ui.button(note).clicked() {
-                    self.selected_note = Some(note.clone());
+            let bulk = &mut self.bulk_edit;
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut bulk.tag).hint_text("Tag").desired_width(120.0));
+                let tag = bulk.tag.trim().trim_start_matches('#');
+                if ui.add_enabled(!tag.is_empty(), egui::Button::new("Tag")).clicked() {
+                    action = Some(BatchAction::Tag(tag.to_string()));
                 }
-            }
-            if ui.button("Create Note").clicked() {
-                self.create_note("New Note", "This is a new note.");
-            }
-            if let Some(selected_note) = &self.selected_note {
-                if ui.button("Delete Note").clicked() {
-                    self.delete_note(selected_note);
-                    self.selected_note = None;
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut bulk.folder).hint_text("Top level").desired_width(120.0));
+                if ui.button("Move").on_hover_text("Move into the folder, keeping the note names").clicked() {
+                    action = Some(BatchAction::Move(bulk.folder.trim().to_string()));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut bulk.export_dir).hint_text("Directory").desired_width(120.0));
+                let dir = bulk.export_dir.trim();
+                if ui.add_enabled(!dir.is_empty(), egui::Button::new("Export")).clicked() {
+                    action = Some(BatchAction::Export(commands::expand_home(dir)));
                 }
+            });
+            if ui.button("Delete…").clicked() {
+                action = Some(BatchAction::Delete);
             }
         });
+        let Some(action) = action else {
+            return;
+        };
+        let titles = self.note_selection.titles();
+        if action == BatchAction::Delete {
+            self.request_delete(titles);
+            return;
+        }
+        self.save_active_note_to_disk();
+        self.command_status = Some(match batch::apply(&titles, &action) {
+            Ok(count) => action.describe(count),
+            Err(err) => format!("Bulk operation failed: {}", err),
+        });
+        self.reload_notes();
+        if matches!(action, BatchAction::Move(_)) {
+            self.note_selection.clear();
+        }
+    }
 
-        SidePanel::right("right_panel").show(ctx, |ui| {
-            ui.heading("Todos");
-            let todos = self.todos.lock().unwrap();
-            for (index, todo) in todos.items.iter().enumerate() {
-                ui.horizontal(|ui| {
-                    ui.label(&todo.description);
-                    if ui.button("Delete").clicked() {
-                        self.delete_todo(index);
-                    }
-                });
+    // Deletes notes right away, or asks first if the preferences say so
+    fn request_delete(&mut self, titles: Vec<String>) {
+        if self.config.confirm_delete {
+            self.pending_delete = titles;
+        } else {
+            self.delete_notes(&titles);
+        }
+    }
+
+    // Moves notes to the trash, from where the notification shown afterwards can restore them
+    fn delete_notes(&mut self, titles: &[String]) {
+        let mut deleted = Vec::new();
+        for title in titles {
+            if let Err(err) = Notes::trash(title) {
+                self.command_status = Some(format!("Failed to delete {}: {}", title, err));
+                continue;
             }
-            if ui.button("Create Todo").clicked() {
-                self.create_todo("New Todo", None);
+            self.notes.lock().unwrap().items.retain(|note| note != title);
+            self.manual_order.retain(|note| note != title);
+            self.recent_notes.retain(|note| note != title);
+            self.navigation.remove(title);
+            if self.buffer.as_ref().is_some_and(|buffer| &buffer.title == title) {
+                self.buffer = None;
             }
-        });
-
-        CentralPanel::default().show(ctx, |ui| {
-            if let Some(selected_note) = &self.selected_note {
-                if let Some(mut content) = Notes::read_note_file(selected_note).ok() {
-                    ui.text_edit_multiline(&mut content);
-                    Notes::update_note_file(selected_note, &content).unwrap();
-                }
-            } else {
-                ui.label("Select a note to edit");
+            if self.selected_note.as_ref() == Some(title) {
+                self.selected_note = None;
             }
-        });
+            self.search_index.remove(title);
+            self.search_stale = true;
+            self.log_activity(ActivityKind::Deleted, title);
 
-        TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.label("Command:");
-                ui.text_edit_singleline(&mut self.command_input);
-                if ui.button("Enter").clicked() {
-                    // Handle command input
+            let mut todos = self.todos.lock().unwrap();
+            let owned: Vec<(u64, Option<usize>)> = todos
+                .items
+                .iter()
+                .filter(|todo| todo.note.as_ref() == Some(title))
+                .map(|todo| (todo.id, todo.line))
+                .collect();
+            if todos.detach_note(title) {
+                if let Err(err) = todos.save_to_file() {
+                    self.command_status = Some(format!("Failed to save the todos: {}", err));
                 }
+            }
+            deleted.push(DeletedNote {
+                title: title.clone(),
+                todos: owned,
             });
+        }
+        self.note_selection.retain(&self.notes.lock().unwrap().items);
+        let message = match deleted.as_slice() {
+            [] => return,
+            [note] => format!("Deleted {}", note.title),
+            notes => format!("Deleted {} notes", notes.len()),
+        };
+        self.notify(message, Some(Undo::DeletedNotes(deleted)));
+    }
+
+    // Shows a notification for a while, with an Undo button if the action can be reverted
+    fn notify(&mut self, message: String, undo: Option<Undo>) {
+        let secs = if undo.is_some() { self.config.undo_secs as f64 } else { TOAST_SECS };
+        self.toasts.push(Toast {
+            message,
+            undo,
+            secs,
+            shown_at: None,
         });
     }
-}
 
-#[derive(serde::Deserialize, serde::Serialize)]
-enum Mode {
-    Command,
-    CommandInput,
+    fn undo(&mut self, undo: Undo) {
+        match undo {
+            Undo::DeletedNotes(notes) => {
+                let mut restored = Vec::new();
+                for DeletedNote { title, todos } in notes {
+                    if let Err(err) = Notes::restore(&title) {
+                        self.command_status = Some(format!("Failed to restore {}: {}", title, err));
+                        continue;
+                    }
+                    self.notes.lock().unwrap().add(title.clone());
+                    self.manual_order.push(title.clone());
+                    // Give the note back the todos it owned, unless they were deleted or attached
+                    // elsewhere meanwhile
+                    let mut items = self.todos.lock().unwrap();
+                    let mut reattached = false;
+                    for (id, line) in todos {
+                        let todo = items.items.iter_mut().find(|todo| todo.id == id);
+                        if let Some(todo) = todo.filter(|todo| todo.note.is_none()) {
+                            todo.note = Some(title.clone());
+                            todo.line = line;
+                            reattached = true;
+                        }
+                    }
+                    if reattached {
+                        if let Err(err) = items.save_to_file() {
+                            log::error!("Failed to save todos: {}", err);
+                        }
+                    }
+                    restored.push(title);
+                }
+                self.sort_notes();
+                self.refresh_search();
+                match restored.as_slice() {
+                    [] => {}
+                    [title] => {
+                        let title = title.clone();
+                        self.open_note(&title);
+                        self.command_status = Some(format!("Restored {}", title));
+                    }
+                    titles => self.command_status = Some(format!("Restored {} notes", titles.len())),
+                }
+            }
+        }
+    }
+
+    fn show_toasts(&mut self, ctx: &egui::Context) {
+        if self.toasts.is_empty() {
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        self.toasts.retain(|toast| toast.shown_at.map_or(true, |shown_at| now - shown_at < toast.secs));
+        let mut undone = None;
+        let mut dismissed = None;
+        egui::Area::new(Id::new("toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12.0, -12.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (index, toast) in self.toasts.iter_mut().enumerate() {
+                    let shown_at = *toast.shown_at.get_or_insert(now);
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(&toast.message);
+                            if toast.undo.is_some() {
+                                let left = (toast.secs - (now - shown_at)).ceil().max(0.0);
+                                if ui.button(format!("Undo ({})", left)).clicked() {
+                                    undone = Some(index);
+                                }
+                            }
+                            if ui.small_button("✕").clicked() {
+                                dismissed = Some(index);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(index) = dismissed {
+            self.toasts.remove(index);
+        } else if let Some(index) = undone {
+            if let Some(undo) = self.toasts.remove(index).undo {
+                self.undo(undo);
+            }
+        }
+        // Keep counting down while nothing else happens
+        if !self.toasts.is_empty() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
+    }
+
+    fn show_delete_confirmation(&mut self, ctx: &egui::Context) {
+        if self.pending_delete.is_empty() {
+            return;
+        }
+        let mut confirmed = false;
+        let mut cancelled = false;
+        let question = match self.pending_delete.as_slice() {
+            [title] => format!("Delete {}?", title),
+            titles => format!("Delete {} notes?", titles.len()),
+        };
+        egui::Window::new("Delete")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(question);
+                ui.weak(format!(
+                    "Deleted notes are moved to the trash and can be restored for {} seconds.",
+                    self.config.undo_secs
+                ));
+                let mut ask = self.config.confirm_delete;
+                if ui.checkbox(&mut ask, "Always ask before deleting a note").changed() {
+                    self.config.confirm_delete = ask;
+                    let result = Config::load().and_then(|mut config| {
+                        config.confirm_delete = ask;
+                        config.save()
+                    });
+                    if let Err(err) = result {
+                        self.command_status = Some(format!("Failed to save the preferences: {}", err));
+                    }
+                }
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("Delete").clicked();
+                    cancelled = ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+                });
+            });
+        if confirmed {
+            let titles = std::mem::take(&mut self.pending_delete);
+            self.delete_notes(&titles);
+        } else if cancelled {
+            self.pending_delete.clear();
+        }
+    }
+
+    fn show_merge_dialog(&mut self, ctx: &egui::Context) {
+        if self.merge_dialog.titles.is_empty() {
+            return;
+        }
+        let mut merged = false;
+        let mut cancelled = false;
+        let dialog = &mut self.merge_dialog;
+        egui::Window::new("Merge Notes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("Merge {} notes, each under a heading with its title:", dialog.titles.len()));
+                for title in &dialog.titles {
+                    ui.weak(title);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Into");
+                    ui.text_edit_singleline(&mut dialog.target);
+                });
+                let target = dialog.target.trim();
+                let error = title::validate(target).err().map(|err| err.to_string()).or_else(|| {
+                    let taken = !dialog.titles.iter().any(|title| title == target)
+                        && Notes::note_exists(target).unwrap_or(false);
+                    taken.then(|| format!("A note named {} already exists", target))
+                });
+                if let Some(error) = &error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.weak("Links to the notes are pointed at the merged note, and the others are moved to the trash.");
+                ui.horizontal(|ui| {
+                    merged = ui.add_enabled(error.is_none(), egui::Button::new("Merge")).clicked();
+                    cancelled = ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+                });
+            });
+        if merged {
+            let dialog = std::mem::take(&mut self.merge_dialog);
+            let message = match self.merge_notes(&dialog.titles, dialog.target.trim()) {
+                Ok(message) | Err(message) => message,
+            };
+            self.command_status = Some(message);
+        } else if cancelled {
+            self.merge_dialog = MergeDialog::default();
+        }
+    }
+
+    // Merges notes into one and moves the todos linked to them along, see `Notes::merge`
+    fn merge_notes(&mut self, titles: &[String], target: &str) -> Result<String, String> {
+        self.save_active_note_to_disk();
+        let merged = Notes::merge(titles, target).map_err(|err| format!("Failed to merge the notes: {}", err))?;
+        {
+            let mut todos = self.todos.lock().unwrap();
+            let mut changed = false;
+            for note in &merged {
+                changed |= todos.merge_note(&note.title, target, note.body_line, note.merged_line);
+            }
+            if changed {
+                todos.save_to_file().map_err(|err| format!("Failed to save the todos: {}", err))?;
+            }
+        }
+        for title in titles.iter().filter(|title| *title != target) {
+            self.manual_order.retain(|note| note != title);
+            self.recent_notes.retain(|note| note != title);
+            self.navigation.remove(title);
+            self.log_activity(ActivityKind::Deleted, title);
+        }
+        if !self.manual_order.iter().any(|note| note == target) {
+            self.manual_order.push(target.to_string());
+        }
+        self.note_selection.clear();
+        self.reload_notes();
+        self.open_note(target);
+        Ok(format!("Merged {} notes into {}", titles.len(), target))
+    }
+
+    fn archive_note(&mut self, title: &str) {
+        self.save_active_note_to_disk();
+        if let Err(err) = Notes::archive(title) {
+            self.command_status = Some(format!("Failed to archive {}: {}", title, err));
+            return;
+        }
+        self.notes.lock().unwrap().items.retain(|note| note != title);
+        self.manual_order.retain(|note| note != title);
+        if self.buffer.as_ref().is_some_and(|buffer| buffer.title == title) {
+            self.buffer = None;
+            self.selected_note = None;
+        }
+        self.search_index.remove(title);
+        self.search_stale = true;
+        self.refresh_archived();
+        self.command_status = Some(format!("Archived {}", title));
+    }
+
+    fn unarchive_note(&mut self, title: &str) {
+        if let Err(err) = Notes::unarchive(title) {
+            self.command_status = Some(format!("Failed to unarchive {}: {}", title, err));
+            return;
+        }
+        self.archived_view = None;
+        self.reload_notes();
+        self.command_status = Some(format!("Restored {}", title));
+    }
+
+    fn show_pending_switch(&mut self, ctx: &egui::Context) {
+        if self.pending_switch.is_none() {
+            return;
+        }
+        let Some(buffer) = &self.buffer else {
+            // Nothing is left to save, e.g. because the note was removed meanwhile
+            if let Some(title) = self.pending_switch.take() {
+                self.open_note(&title);
+            }
+            return;
+        };
+        let mut choice = None;
+        egui::Window::new("Unsaved Changes")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("{} has unsaved changes.", buffer.title));
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Discard").clicked() {
+                        choice = Some(false);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.pending_switch = None;
+                        self.navigating = false;
+                    }
+                });
+            });
+        let Some(save) = choice else {
+            return;
+        };
+        let Some(title) = self.pending_switch.take() else {
+            return;
+        };
+        if save {
+            self.save_active_note_to_disk();
+        } else {
+            self.buffer = None;
+        }
+        // Both leave the buffer clean, so the note opens right away
+        self.open_note(&title);
+    }
+
+    fn show_preferences(&mut self, ctx: &egui::Context) {
+        let Some(draft) = &mut self.preferences else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        egui::Window::new("Preferences")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong("Saving");
+                ui.horizontal(|ui| {
+                    ui.label("Save automatically");
+                    ui.add(
+                        egui::DragValue::new(&mut draft.autosave_secs)
+                            .clamp_range(0.0..=600.0)
+                            .speed(0.5)
+                            .suffix(" s"),
+                    );
+                    ui.label("after the last edit");
+                });
+                ui.checkbox(&mut draft.save_on_focus_loss, "Save when the window loses focus");
+                ui.checkbox(&mut draft.save_on_note_switch, "Save when opening another note")
+                    .on_hover_text("Otherwise you are asked whether to keep unsaved changes");
+                ui.horizontal(|ui| {
+                    ui.label("Save new notes as");
+                    egui::ComboBox::from_id_source("note_extension")
+                        .selected_text(draft.note_extension.label())
+                        .show_ui(ui, |ui| {
+                            for extension in NoteExtension::ALL {
+                                ui.selectable_value(&mut draft.note_extension, extension, extension.label());
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("Only for this vault. Run `convert-notes md` or `convert-notes txt` to rename the existing notes.");
+                ui.separator();
+                ui.strong("Deleting");
+                ui.checkbox(&mut draft.confirm_delete, "Ask before deleting a note");
+                ui.horizontal(|ui| {
+                    ui.label("Offer to undo for");
+                    ui.add(egui::DragValue::new(&mut draft.undo_secs).clamp_range(1..=600).suffix(" s"));
+                })
+                .response
+                .on_hover_text("Deleted notes are kept in the .trash folder of the notes directory");
+                if ui
+                    .button("Empty Trash")
+                    .on_hover_text("Deletes the notes in the trash for good")
+                    .clicked()
+                {
+                    self.command_status = Some(match Notes::empty_trash() {
+                        Ok(deleted) => format!("Deleted {} notes for good", deleted),
+                        Err(err) => format!("Failed to empty the trash: {}", err),
+                    });
+                }
+                ui.separator();
+                ui.strong("Todo board");
+                let mut removed = None;
+                for (index, column) in draft.board_columns.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(column).desired_width(160.0));
+                        if ui.small_button("✖").on_hover_text("Remove the column").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed {
+                    draft.board_columns.remove(index);
+                }
+                if ui.button("Add Column").clicked() {
+                    draft.board_columns.push(String::new());
+                }
+                ui.weak("Todos moved into the last column are completed");
+                ui.horizontal(|ui| {
+                    ui.label("Focus sessions last");
+                    ui.add(egui::DragValue::new(&mut draft.focus_mins).clamp_range(1..=240).suffix(" min"));
+                });
+                ui.separator();
+                ui.strong("Appearance");
+                show_theme_preferences(ui, draft);
+                ui.separator();
+                ui.strong("Keyboard shortcuts");
+                egui::Grid::new("keymap_preferences").num_columns(3).show(ui, |ui| {
+                    for action in Action::ALL {
+                        ui.label(action.label());
+                        let shortcut = draft.keymap.entry(action.id().to_string()).or_default();
+                        ui.add(
+                            egui::TextEdit::singleline(shortcut)
+                                .hint_text(action.default_shortcut())
+                                .desired_width(120.0),
+                        );
+                        if ui.add_enabled(!shortcut.is_empty(), egui::Button::new("Reset")).clicked() {
+                            shortcut.clear();
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.weak("Ctrl is Cmd on macOS. Leave a shortcut empty to keep the default.");
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    ui.separator();
+                    ui.strong("Backups");
+                    egui::Grid::new("backup_preferences").num_columns(2).show(ui, |ui| {
+                        ui.label("Back up automatically");
+                        egui::ComboBox::from_id_source("backup_schedule")
+                            .selected_text(draft.backup.schedule.label())
+                            .show_ui(ui, |ui| {
+                                for schedule in BackupSchedule::ALL {
+                                    ui.selectable_value(&mut draft.backup.schedule, schedule, schedule.label());
+                                }
+                            });
+                        ui.end_row();
+                        ui.label("Folder");
+                        ui.add(egui::TextEdit::singleline(&mut draft.backup.dir).hint_text(backup::DEFAULT_BACKUP_DIR));
+                        ui.end_row();
+                        ui.label("Keep daily backups");
+                        ui.add(egui::DragValue::new(&mut draft.backup.keep_daily).clamp_range(0..=365).suffix(" days"));
+                        ui.end_row();
+                        ui.label("Keep weekly backups");
+                        ui.add(egui::DragValue::new(&mut draft.backup.keep_weekly).clamp_range(0..=520).suffix(" weeks"));
+                        ui.end_row();
+                    });
+                    ui.weak("Older backups in the folder are removed after each automatic backup.");
+                    ui.separator();
+                    ui.strong("Sync");
+                    let mut enabled = draft.sync.is_set_up();
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut enabled, "Sync with");
+                        egui::ComboBox::from_id_source("sync_service")
+                            .selected_text(draft.sync.service.label())
+                            .show_ui(ui, |ui| {
+                                for service in SyncService::ALL {
+                                    ui.selectable_value(&mut draft.sync.service, service, service.label());
+                                }
+                            });
+                    });
+                    // Settings of the other service are kept, so switching back does not lose them
+                    match draft.sync.service {
+                        SyncService::WebDav if enabled != draft.sync.webdav.is_some() => {
+                            draft.sync.webdav = enabled.then(WebDavSettings::default);
+                        }
+                        SyncService::S3 if enabled != draft.sync.s3.is_some() => {
+                            draft.sync.s3 = enabled.then(S3Settings::default);
+                        }
+                        _ => {}
+                    }
+                    let password = &mut self.sync_password;
+                    let passphrase = &mut self.sync_passphrase;
+                    let sync = &mut draft.sync;
+                    egui::Grid::new("sync_preferences").num_columns(2).show(ui, |ui| {
+                        let secret_label = match (sync.service, &mut sync.webdav, &mut sync.s3) {
+                            (SyncService::WebDav, Some(settings), _) => {
+                                ui.label("Folder URL");
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut settings.url)
+                                        .hint_text("https://cloud.example.com/remote.php/dav/files/me/notes"),
+                                );
+                                ui.end_row();
+                                ui.label("Username");
+                                ui.text_edit_singleline(&mut settings.username);
+                                ui.end_row();
+                                "Password"
+                            }
+                            (SyncService::S3, _, Some(settings)) => {
+                                ui.label("Endpoint");
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut settings.endpoint)
+                                        .hint_text("https://s3.us-west-004.backblazeb2.com"),
+                                );
+                                ui.end_row();
+                                ui.label("Region");
+                                ui.text_edit_singleline(&mut settings.region);
+                                ui.end_row();
+                                ui.label("Bucket");
+                                ui.text_edit_singleline(&mut settings.bucket);
+                                ui.end_row();
+                                ui.label("Folder");
+                                ui.add(egui::TextEdit::singleline(&mut settings.prefix).hint_text("The whole bucket"));
+                                ui.end_row();
+                                ui.label("Access key ID");
+                                ui.text_edit_singleline(&mut settings.access_key_id);
+                                ui.end_row();
+                                "Secret access key"
+                            }
+                            _ => return,
+                        };
+                        ui.label(secret_label);
+                        ui.add(egui::TextEdit::singleline(password).password(true).hint_text("Unchanged"))
+                            .on_hover_text("Kept in the system keychain");
+                        ui.end_row();
+                        ui.label("Encryption");
+                        ui.checkbox(&mut sync.encrypt, "Encrypt notes and titles before upload")
+                            .on_hover_text("The server only sees encrypted files. Every device needs the same passphrase.");
+                        ui.end_row();
+                        if sync.encrypt {
+                            ui.label("Passphrase");
+                            ui.add(egui::TextEdit::singleline(passphrase).password(true).hint_text("Unchanged"))
+                                .on_hover_text("Kept in the system keychain. It cannot be recovered if you lose it.");
+                            ui.end_row();
+                        }
+                        ui.label("Sync every");
+                        ui.add(egui::DragValue::new(&mut sync.interval_mins).clamp_range(0..=1440).suffix(" min"))
+                            .on_hover_text("0 only syncs when you click the sync status or run `sync`");
+                        ui.end_row();
+                    });
+                    ui.separator();
+                    ui.strong("Attachments");
+                    ui.checkbox(&mut draft.ocr_attachments, "Make the text in attached images searchable")
+                        .on_hover_text("Needs the tesseract command");
+                    #[cfg(feature = "dictation")]
+                    {
+                        ui.separator();
+                        ui.strong("Dictation");
+                        ui.horizontal(|ui| {
+                            ui.label("Speech model");
+                            ui.add(egui::TextEdit::singleline(&mut draft.dictation_model).hint_text("ggml-base.en.bin"))
+                                .on_hover_text("A whisper.cpp model file; larger models are slower but more accurate");
+                        });
+                    }
+                    ui.separator();
+                    ui.strong("AI");
+                    ui.checkbox(&mut draft.ai.enabled, "Summarize notes and answer questions with a language model")
+                        .on_hover_text("Notes are only sent when you run summarize, generate-title or ask");
+                    if draft.ai.enabled {
+                        let api_key = &mut self.ai_api_key;
+                        let settings = &mut draft.ai;
+                        egui::Grid::new("ai_preferences").num_columns(2).show(ui, |ui| {
+                            ui.label("Server");
+                            egui::ComboBox::from_id_source("ai_provider")
+                                .selected_text(settings.provider.label())
+                                .show_ui(ui, |ui| {
+                                    for provider in AiProvider::ALL {
+                                        ui.selectable_value(&mut settings.provider, provider, provider.label());
+                                    }
+                                });
+                            ui.end_row();
+                            ui.label("URL");
+                            let hint = match settings.provider {
+                                AiProvider::OpenAiCompatible => "https://api.openai.com/v1",
+                                AiProvider::LlamaCpp => "http://localhost:8080",
+                            };
+                            ui.add(egui::TextEdit::singleline(&mut settings.url).hint_text(hint));
+                            ui.end_row();
+                            if settings.provider == AiProvider::OpenAiCompatible {
+                                ui.label("Model");
+                                ui.text_edit_singleline(&mut settings.model);
+                                ui.end_row();
+                                ui.label("API key");
+                                ui.add(egui::TextEdit::singleline(api_key).password(true).hint_text("Unchanged"))
+                                    .on_hover_text("Kept in the system keychain. Local servers usually need none.");
+                                ui.end_row();
+                            }
+                        });
+                    }
+                    ui.separator();
+                    ui.strong("Sharing");
+                    let token = &mut self.share_token;
+                    let settings = &mut draft.share;
+                    egui::Grid::new("share_preferences").num_columns(2).show(ui, |ui| {
+                        ui.label("Service");
+                        egui::ComboBox::from_id_source("share_service")
+                            .selected_text(settings.service.label())
+                            .show_ui(ui, |ui| {
+                                for service in ShareService::ALL {
+                                    ui.selectable_value(&mut settings.service, service, service.label());
+                                }
+                            });
+                        ui.end_row();
+                        if settings.service == ShareService::Custom {
+                            ui.label("URL");
+                            ui.add(egui::TextEdit::singleline(&mut settings.url).hint_text("https://paste.example.com"))
+                                .on_hover_text("The page is posted as the body; the service answers with its link");
+                            ui.end_row();
+                        }
+                        if settings.service != ShareService::ZeroXZero {
+                            ui.label("Token");
+                            ui.add(egui::TextEdit::singleline(token).password(true).hint_text("Unchanged"))
+                                .on_hover_text("Kept in the system keychain. Gists need a GitHub token with the gist scope.");
+                            ui.end_row();
+                        }
+                    });
+                    ui.separator();
+                    ui.strong("Window");
+                    ui.checkbox(&mut draft.close_to_tray, "Keep running in the system tray when closed")
+                        .on_hover_text("Quit from the tray menu or File → Quit");
+                    ui.separator();
+                    ui.strong("Quick capture");
+                    ui.horizontal(|ui| {
+                        ui.label("Shortcut");
+                        ui.add(egui::TextEdit::singleline(&mut draft.quick_capture_hotkey).hint_text("None"))
+                            .on_hover_text(format!("Works in any application, e.g. {}", hotkey::DEFAULT_HOTKEY));
+                    });
+                    ui.weak(format!(
+                        "Opens a small window that appends to {} or adds a todo. Where global shortcuts are not supported, bind `notes --quick-capture` in your desktop settings.",
+                        ipc::INBOX_NOTE
+                    ));
+                }
+                ui.separator();
+                apply = ui.button("Save Preferences").clicked();
+            });
+        if apply {
+            let preferences = draft.clone();
+            // Start from the file, which other windows and commands may have changed since it was loaded
+            let result = Config::load().and_then(|mut config| {
+                config.autosave_secs = preferences.autosave_secs;
+                config.save_on_focus_loss = preferences.save_on_focus_loss;
+                config.save_on_note_switch = preferences.save_on_note_switch;
+                config.note_extension = preferences.note_extension;
+                config.confirm_delete = preferences.confirm_delete;
+                config.undo_secs = preferences.undo_secs;
+                let columns: Vec<String> =
+                    preferences.board_columns.iter().map(|column| column.trim().to_string()).collect();
+                todos::validate_columns(&columns)?;
+                config.board_columns = columns;
+                config.focus_mins = preferences.focus_mins;
+                let mut keymap = preferences.keymap.clone();
+                keymap.retain(|_, shortcut| !shortcut.trim().is_empty());
+                keymap::validate(&keymap)?;
+                config.keymap = keymap;
+                theme::validate(&preferences.themes)?;
+                config.themes = preferences.themes.clone();
+                config.theme = preferences.theme.clone();
+                config.backup = preferences.backup;
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    let secret = match preferences.sync.service {
+                        SyncService::WebDav => {
+                            let webdav_settings = preferences.sync.webdav.as_ref();
+                            webdav_settings.map(|settings| webdav::password_secret(&settings.url))
+                        }
+                        SyncService::S3 => preferences.sync.s3.as_ref().map(s3::secret_key_name),
+                    };
+                    if let Some(secret) = secret.filter(|_| !self.sync_password.is_empty()) {
+                        secrets::set(&secret, &self.sync_password)?;
+                    }
+                    let passphrase_secret = encrypted::passphrase_secret(&preferences.sync);
+                    if let Some(secret) = passphrase_secret.filter(|_| !self.sync_passphrase.is_empty()) {
+                        secrets::set(&secret, &self.sync_passphrase)?;
+                    }
+                    if !preferences.quick_capture_hotkey.trim().is_empty() {
+                        hotkey::parse(&preferences.quick_capture_hotkey)?;
+                    }
+                    if !self.ai_api_key.is_empty() {
+                        secrets::set(ai::API_KEY_SECRET, &self.ai_api_key)?;
+                    }
+                    if !self.share_token.is_empty() {
+                        secrets::set(share::TOKEN_SECRET, &self.share_token)?;
+                    }
+                }
+                config.sync = preferences.sync;
+                config.quick_capture_hotkey = preferences.quick_capture_hotkey;
+                config.close_to_tray = preferences.close_to_tray;
+                config.ocr_attachments = preferences.ocr_attachments;
+                config.dictation_model = preferences.dictation_model;
+                config.ai = preferences.ai;
+                config.share = preferences.share;
+                config.save()?;
+                Ok(config)
+            });
+            match result {
+                Ok(config) => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let hotkey_changed = config.quick_capture_hotkey != self.config.quick_capture_hotkey;
+                    self.config = config;
+                    self.preferences = None;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        self.sync_password.clear();
+                        self.sync_passphrase.clear();
+                        self.ai_api_key.clear();
+                        self.share_token.clear();
+                        if hotkey_changed {
+                            self.register_hotkey(ctx);
+                        }
+                    }
+                    self.command_status = Some("Saved preferences".to_string());
+                }
+                Err(err) => self.command_status = Some(format!("Failed to save preferences: {}", err)),
+            }
+        } else if !open {
+            self.preferences = None;
+        }
+    }
+
+    // The window title, the windows shown over the panels and the background jobs, which all keep
+    // going in zen mode
+    fn show_windows(&mut self, ctx: &egui::Context) {
+        self.update_window_title(ctx);
+        self.show_reading_window(ctx);
+        self.show_calendar_window(ctx);
+        self.show_todos_recovery(ctx);
+        self.show_stats_window(ctx);
+        self.show_reports_window(ctx);
+        self.show_verify_window(ctx);
+        self.show_archived_window(ctx);
+        self.show_preferences(ctx);
+        self.show_pending_switch(ctx);
+        self.show_delete_confirmation(ctx);
+        self.show_merge_dialog(ctx);
+        self.show_toasts(ctx);
+        self.poll_focus(ctx);
+        self.show_insights_window(ctx);
+        self.show_date_query_window(ctx);
+        self.show_replace_window(ctx);
+        self.show_export_window(ctx);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.show_clip_dialog(ctx);
+            self.show_ai_window(ctx);
+            self.show_protect_dialog(ctx);
+            self.show_print_window(ctx);
+            self.show_quick_capture(ctx);
+            self.show_backup_window(ctx);
+            // Only the window holding the lock runs the jobs, so that they don't run twice
+            if self.safe_mode.is_none() && !self.is_read_only() {
+                self.poll_scheduler(ctx);
+            }
+            if self.safe_mode.is_none() {
+                self.poll_automatic_backup(ctx);
+                self.poll_sync(ctx);
+            }
+            self.poll_ocr();
+            self.poll_share(ctx);
+            #[cfg(feature = "dictation")]
+            self.poll_dictation(ctx);
+            self.show_jobs_window(ctx);
+            self.show_sync_conflicts_window(ctx);
+        }
+    }
+
+    // Hides everything but the editor and goes full screen, or brings the panels back as they were
+    fn toggle_zen(&mut self, ctx: &egui::Context) {
+        match self.zen.take() {
+            Some(zen) => {
+                if !zen.was_fullscreen {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+                }
+            }
+            None => {
+                let was_fullscreen = ctx.input(|i| i.viewport().fullscreen.unwrap_or(false));
+                ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
+                self.zen = Some(ZenMode {
+                    was_fullscreen,
+                    cursor: None,
+                });
+            }
+        }
+    }
+
+    // The theme in use, or the one being edited in the preferences so that it is previewed
+    fn active_theme(&self) -> Option<Theme> {
+        let config = self.preferences.as_ref().unwrap_or(&self.config);
+        theme::find(&config.themes, config.theme.as_deref()).cloned()
+    }
+
+    fn view_archived(&mut self, title: &str) {
+        match Notes::read_archived(title) {
+            Ok(content) => self.archived_view = Some((title.to_string(), content)),
+            Err(err) => self.command_status = Some(format!("Failed to open {}: {}", title, err)),
+        }
+    }
+
+    fn refresh_archived(&mut self) {
+        self.archived_notes = Notes::list_archived().unwrap_or_else(|err| {
+            log::error!("Failed to list archived notes: {}", err);
+            Vec::new()
+        });
+    }
+
+    fn show_archived_window(&mut self, ctx: &egui::Context) {
+        let Some((title, content)) = &self.archived_view else {
+            return;
+        };
+        let mut open = true;
+        let mut restore = false;
+        egui::Window::new(format!("{} (archived)", title))
+            .id(Id::new("archived_view"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                restore = ui.button("Unarchive").clicked();
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| ui.label(content));
+            });
+        if restore {
+            let title = title.clone();
+            self.unarchive_note(&title);
+            self.open_note(&title);
+        } else if !open {
+            self.archived_view = None;
+        }
+    }
+
+    fn sort_notes(&mut self) {
+        let mut notes = self.notes.lock().unwrap();
+        if self.note_sort == NoteSort::Manual && self.manual_order.is_empty() {
+            self.manual_order = notes.items.clone();
+        }
+        notes.sort(self.note_sort, &self.manual_order);
+    }
+
+    fn move_note(&mut self, from: usize, to: usize) {
+        let mut notes = self.notes.lock().unwrap();
+        if from < notes.items.len() && to < notes.items.len() {
+            let note = notes.items.remove(from);
+            notes.items.insert(to, note);
+            self.manual_order = notes.items.clone();
+        }
+    }
+
+    fn create_todo(&mut self, description: &str, due_date: Option<i64>) {
+        let mut todos = self.todos.lock().unwrap();
+        todos.add(description.to_string(), due_date);
+        if let Err(err) = todos.save_to_file() {
+            self.command_status = Some(format!("Failed to save the todos: {}", err));
+        }
+    }
+
+    fn create_note_todo(&mut self, note: &str, description: &str) {
+        let mut todos = self.todos.lock().unwrap();
+        todos.add_to_note(description.to_string(), note.to_string());
+        if let Err(err) = todos.save_to_file() {
+            self.command_status = Some(format!("Failed to save the todos: {}", err));
+        }
+    }
+
+    fn edit_todo(&mut self, index: usize) {
+        let todos = self.todos.lock().unwrap();
+        self.todo_edit = todos.items.get(index).map(|todo| TodoEdit {
+            index,
+            description: todo.description.clone(),
+            due_date: todo.due_date.map(todos::format_due_date).unwrap_or_default(),
+            error: None,
+        });
+    }
+
+    fn commit_todo_edit(&mut self) {
+        let Some(edit) = &mut self.todo_edit else {
+            return;
+        };
+        let description = edit.description.trim();
+        if description.is_empty() {
+            edit.error = Some("Enter a description".to_string());
+            return;
+        }
+        let due_date = match edit.due_date.trim() {
+            "" => None,
+            date => match todos::parse_due_date(date) {
+                Some(due_date) => Some(due_date),
+                None => {
+                    edit.error = Some("Enter the due date as YYYY-MM-DD".to_string());
+                    return;
+                }
+            },
+        };
+        let patch = TodoPatch {
+            description: Some(description.to_string()),
+            due_date: Some(due_date),
+            ..TodoPatch::default()
+        };
+        let mut todos = self.todos.lock().unwrap();
+        if todos.update(edit.index, patch) {
+            if let Err(err) = todos.save_to_file() {
+                edit.error = Some(format!("Failed to save: {}", err));
+                return;
+            }
+        }
+        self.todo_edit = None;
+    }
+
+    fn move_todo(&mut self, from: usize, to: usize) {
+        self.todo_edit = None;
+        self.todo_selection = None;
+        let mut todos = self.todos.lock().unwrap();
+        if todos.move_item(from, to) {
+            if let Err(err) = todos.save_to_file() {
+                log::error!("Failed to save todos: {}", err);
+            }
+        }
+    }
+
+    fn delete_todos(&mut self, indices: &[usize]) {
+        // Indices shift, so an edit in progress would apply to the wrong todo
+        self.todo_edit = None;
+        self.todo_selection = None;
+        let mut todos = self.todos.lock().unwrap();
+        if !todos.remove(indices).is_empty() {
+            if let Err(err) = todos.save_to_file() {
+                self.command_status = Some(format!("Failed to save the todos: {}", err));
+            }
+        }
+    }
+
+    fn start_focus(&mut self, index: usize) {
+        self.finish_focus();
+        let now = chrono::Utc::now().timestamp();
+        let length_secs = self.config.focus_mins.max(1) * 60;
+        self.focus = self.todos.lock().unwrap().items.get(index).map(|todo| FocusTimer::start(todo, now, length_secs));
+    }
+
+    // Ends the focus session early or on time, adding the time spent to its todo and the log
+    fn finish_focus(&mut self) {
+        let Some(timer) = self.focus.take() else {
+            return;
+        };
+        let secs = timer.elapsed_secs(chrono::Utc::now().timestamp());
+        if secs == 0 {
+            return;
+        }
+        let mut session = FocusSession {
+            start: timer.started_at,
+            secs,
+            todo: timer.description,
+            note: None,
+            project: None,
+        };
+        let mut todos = self.todos.lock().unwrap();
+        if let Some(todo) = todos.add_time_spent(timer.todo_id, secs) {
+            session.note = todo.note.clone();
+            session.project = todo.project.clone();
+            if let Err(err) = todos.save_to_file() {
+                log::error!("Failed to save todos: {}", err);
+            }
+        }
+        if let Err(err) = focus::record(&session) {
+            log::error!("Failed to log the focus session: {}", err);
+        }
+    }
+
+    // Ticks once a second while a focus session runs, and ends it when its time is up
+    fn poll_focus(&mut self, ctx: &egui::Context) {
+        let Some(timer) = &self.focus else {
+            return;
+        };
+        if !timer.is_finished(chrono::Utc::now().timestamp()) {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            return;
+        }
+        let message = format!("Focus session on {} is over", timer.description);
+        self.finish_focus();
+        self.notify(message, None);
+    }
+
+    fn move_todo_to_column(&mut self, index: usize, column: usize) {
+        let mut todos = self.todos.lock().unwrap();
+        if todos.move_to_column(index, column, &self.config.board_columns) {
+            if let Err(err) = todos.save_to_file() {
+                log::error!("Failed to save todos: {}", err);
+            }
+        }
+    }
+
+    fn complete_todos(&mut self, indices: &[usize]) {
+        let mut todos = self.todos.lock().unwrap();
+        if todos.complete(indices) > 0 {
+            if let Err(err) = todos.save_to_file() {
+                log::error!("Failed to save todos: {}", err);
+            }
+        }
+    }
+
+    fn toggle_todo(&mut self, index: usize) {
+        let mut todos = self.todos.lock().unwrap();
+        let done = todos.items.get(index).is_some_and(|todo| !todo.done);
+        let patch = TodoPatch {
+            done: Some(done),
+            ..TodoPatch::default()
+        };
+        if todos.update(index, patch) {
+            if let Err(err) = todos.save_to_file() {
+                log::error!("Failed to save todos: {}", err);
+            }
+        }
+    }
+
+    // How many todos get done, below the heading of the todos panel
+    fn show_todo_stats(&mut self, ui: &mut egui::Ui) {
+        let archive = self.todo_archive.get_or_insert_with(|| {
+            Todos::load_archive().unwrap_or_else(|err| {
+                log::error!("Failed to load the archived todos: {}", err);
+                Todos::new()
+            })
+        });
+        let stats = TodoStats::compute(&self.todos.lock().unwrap(), archive, chrono::Local::now());
+        ui.horizontal(|ui| {
+            ui.label(format!("✔ {}", stats.completed_this_week))
+                .on_hover_text("Todos completed this week");
+            ui.label(format!("🔥 {}", stats.streak))
+                .on_hover_text("Days in a row with a completed todo");
+            let overdue = egui::RichText::new(format!("⏰ {}", stats.overdue));
+            let overdue = if stats.overdue > 0 { overdue.color(egui::Color32::RED) } else { overdue };
+            ui.label(overdue).on_hover_text("Overdue todos");
+        });
+    }
+
+    // Completed todos are moved to the archive rather than deleted
+    fn clear_completed_todos(&mut self) {
+        self.todo_edit = None;
+        self.todo_selection = None;
+        self.todo_archive = None;
+        let result = self.todos.lock().unwrap().archive_completed();
+        self.command_status = Some(match result {
+            Ok(archived) => format!("Archived {} completed todos", archived),
+            Err(err) => format!("Failed to archive the completed todos: {}", err),
+        });
+    }
+
+    fn run_command(&mut self) {
+        let line = self.command_input.trim().to_string();
+        // `!!` runs the last command again, as in a shell
+        if line == "!!" {
+            self.command_input.clear();
+            self.repeat_command();
+        } else if self.run_command_line(&line) {
+            self.command_input.clear();
+        }
+    }
+
+    fn repeat_command(&mut self) {
+        match self.command_history.last().map(str::to_string) {
+            Some(line) => {
+                self.run_command_line(&line);
+            }
+            None => self.command_status = Some("No command was run yet".to_string()),
+        }
+    }
+
+    // Runs a line of the command bar, returning whether it was a command or alias. It is remembered
+    // either way, so that a typo can be recalled and fixed.
+    fn run_command_line(&mut self, line: &str) -> bool {
+        self.command_history.push(line);
+        if !self.is_read_only() {
+            if let Err(err) = self.command_history.save() {
+                log::error!("Failed to save the command history: {}", err);
+            }
+        }
+        let lines = match commands::expand_alias(&self.config.aliases, line) {
+            Ok(Some(lines)) => lines,
+            Ok(None) => vec![line.to_string()],
+            Err(err) => {
+                self.command_status = Some(err);
+                return false;
+            }
+        };
+        // Nothing of an alias runs unless all of its commands are valid
+        let mut parsed = Vec::new();
+        for line in &lines {
+            match Command::parse(line) {
+                Ok(command) => parsed.push((line, command)),
+                Err(err) => {
+                    self.command_status = Some(if lines.len() > 1 { format!("{}: {}", line, err) } else { err });
+                    return false;
+                }
+            }
+        }
+        let mut messages = Vec::new();
+        for (line, command) in parsed {
+            let recorded = !matches!(
+                command,
+                Command::RecordMacro { .. } | Command::StopRecording | Command::PlayMacro { .. }
+            );
+            if let Some(recording) = self.macro_recording.as_mut().filter(|_| recorded) {
+                recording.steps.push(MacroStep::Command(line.to_string()));
+            }
+            match self.execute_command(command) {
+                Ok(message) => messages.push(message),
+                Err(message) => {
+                    messages.push(message);
+                    break;
+                }
+            }
+        }
+        self.command_status = Some(messages.join("; "));
+        true
+    }
+
+    // The command bar. Up and Down recall the commands run before, Ctrl+R searches them.
+    fn show_command_input(&mut self, ui: &mut egui::Ui) {
+        if self.command_search.is_some() {
+            self.show_command_search(ui);
+            return;
+        }
+        let input_id = egui::Id::new(COMMAND_INPUT_ID);
+        if ui.memory(|memory| memory.has_focus(input_id)) {
+            // Take the history keys before the text field sees them
+            let (older, newer, search) = ui.input_mut(|i| {
+                (
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                    i.consume_key(egui::Modifiers::COMMAND, egui::Key::R),
+                )
+            });
+            let recalled = match (older, newer) {
+                (true, _) => self.command_history.older(&self.command_input),
+                (_, true) => self.command_history.newer(),
+                _ => None,
+            };
+            if let Some(line) = recalled.map(str::to_string) {
+                self.recall_command(ui.ctx(), line);
+            }
+            if search {
+                self.command_search = Some(CommandSearch::default());
+                self.show_command_search(ui);
+                return;
+            }
+        }
+        ui.label("Command:");
+        let response = ui.add(egui::TextEdit::singleline(&mut self.command_input).id(input_id));
+        let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if ui.button("Enter").clicked() || submitted {
+            self.run_command();
+        }
+    }
+
+    // Ctrl+R again steps to an older match, Enter puts the match in the command bar to be edited or
+    // run, and Escape leaves the search
+    fn show_command_search(&mut self, ui: &mut egui::Ui) {
+        let Some(search) = &mut self.command_search else {
+            return;
+        };
+        let (older, enter, escape) = ui.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::R),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        let matches: Vec<&str> = self.command_history.search(&search.query).collect();
+        if older {
+            search.skipped += 1;
+        }
+        search.skipped = search.skipped.min(matches.len().saturating_sub(1));
+        let found = matches.get(search.skipped).map(|line| line.to_string());
+
+        ui.label("History search:");
+        let response = ui.add(egui::TextEdit::singleline(&mut search.query).hint_text("Part of a command"));
+        response.request_focus();
+        if response.changed() {
+            search.skipped = 0;
+        }
+        match &found {
+            Some(line) => ui.monospace(line),
+            None => ui.weak("No matching command"),
+        };
+        if enter || escape {
+            self.command_search = None;
+            match found.filter(|_| enter) {
+                Some(line) => self.recall_command(ui.ctx(), line),
+                None => ui.memory_mut(|memory| memory.request_focus(egui::Id::new(COMMAND_INPUT_ID))),
+            }
+        }
+    }
+
+    // Puts a command from the history in the command bar, with the cursor after it
+    fn recall_command(&mut self, ctx: &egui::Context, line: String) {
+        let input_id = egui::Id::new(COMMAND_INPUT_ID);
+        let mut state = egui::TextEdit::load_state(ctx, input_id).unwrap_or_default();
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(line.chars().count()))));
+        state.store(ctx, input_id);
+        self.command_input = line;
+        ctx.memory_mut(|memory| memory.request_focus(input_id));
+    }
+
+    fn execute_command(&mut self, command: Command) -> Result<String, String> {
+        match command {
+            Command::Schedule { .. } | Command::Unschedule { .. } | Command::RunJob { .. } => {
+                let result = command.run();
+                self.schedule = Schedule::load().unwrap_or_default();
+                result
+            }
+            Command::SetAutosave { .. } => {
+                let result = command.run();
+                self.config = Config::load().unwrap_or_default();
+                result
+            }
+            Command::SetDue { .. } | Command::AddTodo { .. } | Command::ClearCompleted => {
+                self.todo_edit = None;
+                self.todo_selection = None;
+                self.todo_archive = None;
+                let result = command.run();
+                *self.todos.lock().unwrap() = Todos::load_from_file().unwrap_or_default();
+                result
+            }
+            Command::FindByDate { .. } => {
+                let changes_notes = matches!(
+                    command,
+                    Command::FindByDate {
+                        action: Some(_),
+                        dry_run: false,
+                        ..
+                    }
+                );
+                let result = command.run();
+                if changes_notes {
+                    self.reload_notes();
+                }
+                result
+            }
+            Command::RecordMacro { name } => {
+                self.macro_recording = Some(Macro {
+                    name: name.clone(),
+                    ..Macro::default()
+                });
+                Ok(format!("Recording {}; enter stop-recording when done", name))
+            }
+            Command::StopRecording => {
+                let recorded = self.macro_recording.take().ok_or("Not recording a macro")?;
+                let message = format!("Saved {} with {} steps", recorded.name, recorded.steps.len());
+                self.macros.add(recorded);
+                self.macros.save().map_err(|err| format!("Failed to save macros: {}", err))?;
+                Ok(message)
+            }
+            Command::PlayMacro { name, folder: None } => {
+                if self.macros.get(&name).is_none() {
+                    return Err(format!("No macro named {}", name));
+                }
+                if self.buffer.is_none() {
+                    return Err("Open a note to play a macro on".to_string());
+                }
+                let message = format!("Played {}", name);
+                self.pending_macro = Some(name);
+                Ok(message)
+            }
+            Command::PlayMacro { .. } => {
+                self.save_active_note_to_disk();
+                let result = command.run();
+                self.reload_notes();
+                result
+            }
+            Command::Duplicate { title } => {
+                let title = title
+                    .or_else(|| self.buffer.as_ref().map(|buffer| buffer.title.clone()))
+                    .ok_or("Open a note to duplicate")?;
+                self.duplicate_note(&title)
+            }
+            Command::SetLocked { title, locked } => {
+                let title = title
+                    .or_else(|| self.buffer.as_ref().map(|buffer| buffer.title.clone()))
+                    .ok_or("Open a note to lock or unlock")?;
+                // Edits made before locking are kept
+                self.save_active_note_to_disk();
+                let result = Command::SetLocked {
+                    title: Some(title.clone()),
+                    locked,
+                }
+                .run();
+                if let Some(buffer) = self.buffer.as_mut().filter(|buffer| buffer.title == title) {
+                    if let Err(err) = buffer.reload() {
+                        log::error!("Failed to reload {}: {}", title, err);
+                    }
+                }
+                result
+            }
+            Command::Split { title } => {
+                let title = title
+                    .or_else(|| self.buffer.as_ref().map(|buffer| buffer.title.clone()))
+                    .ok_or("Open a note to split")?;
+                self.save_active_note_to_disk();
+                let result = Command::Split { title: Some(title) }.run();
+                self.reload_notes();
+                result
+            }
+            Command::Today { .. } => {
+                self.save_active_note_to_disk();
+                let result = command.run();
+                self.reload_notes();
+                if result.is_ok() {
+                    self.open_note(&notes::daily_title(chrono::Local::now().date_naive()));
+                }
+                result
+            }
+            Command::ConvertNotes { .. } => {
+                self.save_active_note_to_disk();
+                let result = command.run();
+                self.config = Config::load().unwrap_or_default();
+                self.reload_notes();
+                result
+            }
+            Command::BindMacro { .. } => {
+                let result = command.run();
+                self.macros = Macros::load().unwrap_or_default();
+                result
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::AddVault { .. } => {
+                let result = command.run();
+                self.vaults = vault::registered().unwrap_or_default();
+                result
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::SwitchVault { name } => {
+                // The watcher needs the UI context, so the switch happens in the next update
+                vault::resolve(name.as_deref()).map_err(|err| err.to_string())?;
+                let message = format!("Switching to {}", vault_label(name.as_deref()));
+                self.pending_vault_switch = Some(name);
+                Ok(message)
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Restore { options, .. } => {
+                if options.dry_run {
+                    return command.run();
+                }
+                self.save_active_note_to_disk();
+                let result = command.run();
+                self.reload_vault();
+                result
+            }
+            Command::ExportStats { .. } | Command::ExportIcal { .. } | Command::Permalinks { .. } => command.run(),
+            Command::EmptyTrash => command.run(),
+            Command::ExportSite { .. } => {
+                self.save_active_note_to_disk();
+                command.run()
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Backup { .. } => command.run(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::AddFeed { .. } | Command::RemoveFeed { .. } => command.run(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Import { .. } | Command::FetchFeeds { .. } => {
+                let result = command.run();
+                self.reload_notes();
+                result
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::RotateKey | Command::ClearSecret { .. } => command.run(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Summarize | Command::GenerateTitle | Command::Ask { .. } => {
+                if !self.config.ai.enabled {
+                    return Err("AI features are off; turn them on in Preferences".to_string());
+                }
+                if self.ai_dialog.job.is_some() {
+                    return Err("Still waiting for the last answer".to_string());
+                }
+                let request = match command {
+                    Command::Ask { question } => {
+                        ai::Request::ask(&self.search_index, &question).map_err(|err| err.to_string())?
+                    }
+                    command => {
+                        let buffer = self.buffer.as_ref().ok_or("Open a note first")?;
+                        let (title, content) = (buffer.title.clone(), buffer.content.clone());
+                        if matches!(command, Command::Summarize) {
+                            ai::Request::Summarize { title, content }
+                        } else {
+                            ai::Request::GenerateTitle { title, content }
+                        }
+                    }
+                };
+                let message = format!("Asking the model: {}", request.label());
+                self.ai_dialog.pending = Some(request);
+                self.ai_dialog.open = true;
+                Ok(message)
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Share { title } => {
+                if self.share_requested.is_some() || self.share_job.is_some() {
+                    return Err("Still sharing the last note".to_string());
+                }
+                let title = title
+                    .or_else(|| self.buffer.as_ref().map(|buffer| buffer.title.clone()))
+                    .ok_or("Open a note to share")?;
+                // The open note is shared as edited, and unlocked if it is protected
+                let content = match &self.buffer {
+                    Some(buffer) if buffer.title == title => buffer.content.clone(),
+                    _ => Notes::read_note_file(&title).map_err(|err| err.to_string())?,
+                };
+                self.share_requested = Some((title.clone(), content));
+                Ok(format!("Sharing {}…", title))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Sync => {
+                // Talking to the server takes a while, so the sync runs in the background
+                if !self.config.sync.is_set_up() {
+                    return Err("Sync is not set up; add a server in Preferences".to_string());
+                }
+                self.sync_requested = true;
+                Ok("Syncing…".to_string())
+            }
+        }
+    }
+
+    // Uploads a note asked to be shared in the background, and copies the link once it is up
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_share(&mut self, ctx: &egui::Context) {
+        if let Some((title, content)) = self.share_requested.take() {
+            let settings = self.config.share.clone();
+            let shared = title.clone();
+            let repaint = ctx.clone();
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(share::share(&settings, &shared, &content));
+                repaint.request_repaint();
+            });
+            self.share_job = Some((title, receiver));
+        }
+        let Some((title, receiver)) = &self.share_job else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(io::Error::new(io::ErrorKind::Other, "The upload panicked")),
+        };
+        let title = title.clone();
+        self.share_job = None;
+        self.command_status = Some(match result {
+            Ok(url) => {
+                ctx.output_mut(|output| output.copied_text = url.clone());
+                format!("Shared {}; copied the link {}", title, url)
+            }
+            Err(err) => format!("Failed to share {}: {}", title, err),
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_scheduler(&mut self, ctx: &egui::Context) {
+        if let Some(running_job) = &self.running_job {
+            let status = match running_job.receiver.try_recv() {
+                Ok(status) => status,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => JobStatus::Failed("The job panicked".to_string()),
+            };
+            let (name, started_at) = (running_job.name.clone(), running_job.started_at);
+            self.running_job = None;
+
+            // Other instances or the CLI may have changed the schedule in the meantime.
+            self.schedule = Schedule::load().unwrap_or_else(|_| self.schedule.clone());
+            self.schedule.record(&name, started_at, status);
+            if let Err(err) = self.schedule.save() {
+                log::error!("Failed to save the schedule: {}", err);
+            }
+        }
+
+        if let Some(job) = self.schedule.next_due(scheduler::now()) {
+            let name = job.name.clone();
+            self.start_job(ctx, &name);
+        } else if let Some(secs) = self.schedule.secs_until_next(scheduler::now()) {
+            // Wake up when the next job is due
+            ctx.request_repaint_after(std::time::Duration::from_secs(secs));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_job(&mut self, ctx: &egui::Context, name: &str) {
+        let Some(job) = self.schedule.jobs.iter().find(|job| job.name == name) else {
+            return;
+        };
+        let command = job.command.clone();
+        let ctx = ctx.clone();
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(scheduler::run_job_command(&command));
+            ctx.request_repaint();
+        });
+        self.running_job = Some(RunningJob {
+            name: name.to_string(),
+            started_at: scheduler::now(),
+            receiver,
+        });
+    }
+
+    // Back up in the background, as zipping a large notes directory takes a while
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_automatic_backup(&mut self, ctx: &egui::Context, at_start: bool) {
+        let policy = self.config.backup.clone();
+        let ctx = ctx.clone();
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(backup::run_automatic(&policy, at_start));
+            ctx.request_repaint();
+        });
+        self.automatic_backup = Some(receiver);
+        self.backup_checked_at = Some(scheduler::now());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_automatic_backup(&mut self, ctx: &egui::Context) {
+        if let Some(receiver) = &self.automatic_backup {
+            let result = match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => Err(io::Error::new(io::ErrorKind::Other, "The backup panicked")),
+            };
+            self.automatic_backup = None;
+            match result {
+                Ok(Some(path)) => self.command_status = Some(format!("Backed up the notes to {}", path.display())),
+                Ok(None) => {}
+                Err(err) => self.command_status = Some(format!("Automatic backup failed: {}", err)),
+            }
+        }
+
+        if self.config.backup.schedule != BackupSchedule::Daily || self.is_read_only() {
+            return;
+        }
+        let now = scheduler::now();
+        match self.backup_checked_at {
+            Some(checked_at) if now < checked_at + BACKUP_CHECK_SECS => {
+                ctx.request_repaint_after(std::time::Duration::from_secs(checked_at + BACKUP_CHECK_SECS - now));
+            }
+            _ => self.start_automatic_backup(ctx, false),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_sync(&mut self, ctx: &egui::Context) {
+        self.save_active_note_to_disk();
+        let ctx = ctx.clone();
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(sync::run());
+            ctx.request_repaint();
+        });
+        self.sync_job = Some(receiver);
+        self.sync_started_at = Some(scheduler::now());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_sync(&mut self, ctx: &egui::Context) {
+        if let Some(receiver) = &self.sync_job {
+            let result = match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => Err(io::Error::new(io::ErrorKind::Other, "The sync panicked")),
+            };
+            self.sync_job = None;
+            if let Ok(report) = &result {
+                if report.changed_local() {
+                    // The server may have changed the configuration, macros or schedule too
+                    self.reload_vault();
+                }
+                if let Some(fingerprint) = &report.new_key {
+                    self.command_status = Some(format!(
+                        "Syncing with encryption key {}. Check that your other devices show the same key.",
+                        fingerprint
+                    ));
+                }
+            }
+            self.last_sync = Some((scheduler::now(), result.map_err(|err| err.to_string())));
+        }
+
+        if !self.config.sync.is_set_up() || self.is_read_only() {
+            self.sync_requested = false;
+            return;
+        }
+        let now = scheduler::now();
+        let interval = self.config.sync.interval_mins * 60;
+        let due_at = self.sync_started_at.map(|started_at| started_at + interval);
+        if self.sync_requested || (interval > 0 && due_at.map_or(true, |due_at| now >= due_at)) {
+            self.sync_requested = false;
+            self.start_sync(ctx);
+        } else if let Some(due_at) = due_at.filter(|_| interval > 0) {
+            ctx.request_repaint_after(std::time::Duration::from_secs(due_at - now));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_ipc(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.ipc_requests else {
+            return;
+        };
+        let incoming: Vec<Incoming> = receiver.try_iter().collect();
+        for incoming in incoming {
+            let result = self.handle_request(ctx, &incoming.request);
+            if let Err(err) = &result {
+                self.command_status = Some(err.clone());
+            }
+            incoming.reply(result);
+        }
+    }
+
+    /// Carries out a request from another program, such as opening a note.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The context of the window.
+    /// * `request` - The request.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<(), String>` with a message describing why the request failed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn handle_request(&mut self, ctx: &egui::Context, request: &Request) -> Result<(), String> {
+        match request {
+            Request::OpenNote { title } => {
+                if !self.notes.lock().unwrap().items.contains(title) {
+                    return Err(format!("No note named {}", title));
+                }
+                self.open_note(title);
+            }
+            Request::Append { title, text } => self.append_to_note(title, text)?,
+            Request::QuickCapture { text } => self.append_to_note(ipc::INBOX_NOTE, text)?,
+            Request::ShowQuickCapture => self.quick_capture.open = true,
+            Request::Focus => {}
+        }
+        if matches!(request, Request::OpenNote { .. } | Request::Focus) {
+            show_window(ctx);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn append_to_note(&mut self, title: &str, text: &str) -> Result<(), String> {
+        let open = self.buffer.as_ref().is_some_and(|buffer| buffer.title == title);
+        if open {
+            // Keep unsaved edits, the text goes after them
+            self.save_active_note_to_disk();
+        }
+        let created = Notes::append_to_note(title, text).map_err(|err| format!("Failed to append to {}: {}", title, err))?;
+        if created {
+            self.notes.lock().unwrap().add(title.to_string());
+            self.log_activity(ActivityKind::Created, title);
+            if !self.manual_order.iter().any(|note| note == title) {
+                self.manual_order.push(title.to_string());
+            }
+            self.sort_notes();
+        } else {
+            self.log_activity(ActivityKind::Edited, title);
+        }
+        if let Some(buffer) = self.buffer.as_mut().filter(|_| open) {
+            if let Err(err) = buffer.reload() {
+                log::error!("Failed to reload {}: {}", title, err);
+            }
+        }
+        self.refresh_tasks();
+        self.refresh_search();
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_tray(&mut self, ctx: &egui::Context) {
+        if self.tray.is_none() {
+            return;
+        }
+        let pending = self.todos.lock().unwrap().items.iter().filter(|todo| !todo.done).count();
+        let Some(tray) = &mut self.tray else {
+            return;
+        };
+        tray.set_pending(pending);
+        for action in tray.actions() {
+            match action {
+                TrayAction::Show => show_window(ctx),
+                TrayAction::QuickCapture => self.quick_capture.open = true,
+                TrayAction::TodaysNote => {
+                    self.open_todays_note();
+                    show_window(ctx);
+                }
+                TrayAction::Quit => {
+                    self.quitting = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            }
+        }
+        // Closing only hides the window, so the tray menu can bring it back
+        if self.config.close_to_tray && !self.quitting && ctx.input(|i| i.viewport().close_requested()) {
+            self.save_active_note_to_disk();
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+    }
+
+    // Opens the daily note of today, creating it from its folder's template the first time
+    fn open_todays_note(&mut self) {
+        self.open_daily_note(chrono::Local::now().date_naive());
+    }
+
+    fn open_daily_note(&mut self, date: NaiveDate) {
+        let title = notes::daily_title(date);
+        let exists = self.notes.lock().unwrap().items.contains(&title);
+        if !exists {
+            self.create_note(&title, "", None);
+        }
+        self.open_note(&title);
+    }
+
+    // Only the window that takes requests from other programs listens for the shortcut
+    #[cfg(not(target_arch = "wasm32"))]
+    fn register_hotkey(&mut self, ctx: &egui::Context) {
+        // Drop the old shortcut first, as only one can be listened for
+        self.hotkey = None;
+        let shortcut = self.config.quick_capture_hotkey.trim();
+        if shortcut.is_empty() || self.ipc_requests.is_none() {
+            return;
+        }
+        let ctx = ctx.clone();
+        match HotkeyListener::register(shortcut, move || ctx.request_repaint()) {
+            Ok(listener) => self.hotkey = Some(listener),
+            Err(err) => log::warn!("Failed to register the quick capture shortcut {}: {}", shortcut, err),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_quick_capture(&mut self, ctx: &egui::Context) {
+        if self.hotkey.as_ref().is_some_and(HotkeyListener::take_pressed) {
+            self.quick_capture.open = true;
+        }
+        if !self.quick_capture.open {
+            return;
+        }
+        let capture = &mut self.quick_capture;
+        let builder = egui::ViewportBuilder::default()
+            .with_title("Quick Capture")
+            .with_always_on_top()
+            .with_inner_size([360.0, 150.0])
+            .with_resizable(false);
+        let viewport = egui::ViewportId::from_hash_of("quick_capture");
+        let action = ctx.show_viewport_immediate(viewport, builder, |ctx, class| {
+            let mut action = None;
+            let mut contents = |ui: &mut egui::Ui| {
+                let text = egui::TextEdit::multiline(&mut capture.text)
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY)
+                    .hint_text("Ctrl+Enter to save, Escape to cancel");
+                let response = ui.add(text);
+                if !capture.focused {
+                    response.request_focus();
+                    capture.focused = true;
+                }
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut capture.as_todo, false, format!("Append to {}", ipc::INBOX_NOTE));
+                    ui.radio_value(&mut capture.as_todo, true, "Add a todo");
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        action = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        action = Some(false);
+                    }
+                });
+            };
+            let embedded = class == egui::ViewportClass::Embedded;
+            if embedded {
+                // The platform cannot open another window, so it shows inside the app
+                egui::Window::new("Quick Capture").collapsible(false).show(ctx, contents);
+            } else {
+                egui::CentralPanel::default().show(ctx, contents);
+            }
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter)) {
+                action = Some(true);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::Escape) || (!embedded && i.viewport().close_requested())) {
+                action = Some(false);
+            }
+            action
+        });
+        let Some(save) = action else {
+            return;
+        };
+        let capture = std::mem::take(&mut self.quick_capture);
+        let text = capture.text.trim();
+        if !save || text.is_empty() {
+            return;
+        }
+        if capture.as_todo {
+            self.create_todo(text, None);
+            self.command_status = Some("Added a todo".to_string());
+            return;
+        }
+        match self.append_to_note(ipc::INBOX_NOTE, text) {
+            Ok(()) => self.command_status = Some(format!("Added to {}", ipc::INBOX_NOTE)),
+            Err(err) => {
+                // Keep the text, so it can be saved once the problem is fixed
+                self.command_status = Some(err);
+                self.quick_capture = QuickCapture { open: true, ..capture };
+            }
+        }
+    }
+
+    // Shows how the last sync went in the top bar; clicking it syncs now
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_sync_indicator(&mut self, ui: &mut egui::Ui) {
+        if !self.config.sync.is_set_up() {
+            return;
+        }
+        let (text, hover) = match &self.last_sync {
+            _ if self.sync_job.is_some() => ("⟳ Syncing…".to_string(), "Syncing with the server".to_string()),
+            None => ("☁ Not synced".to_string(), "Click to sync now".to_string()),
+            Some((_, Ok(report))) if !report.conflicts.is_empty() => (
+                format!("⚠ {} sync conflicts", report.conflicts.len()),
+                format!("Changed here and on the server:\n{}\n\nClick to resolve", report.conflicts.join("\n")),
+            ),
+            Some((synced_at, Ok(report))) => (
+                format!("✔ Synced {}", scheduler::format_elapsed(scheduler::now().saturating_sub(*synced_at))),
+                report.summary(),
+            ),
+            Some((_, Err(err))) => ("✖ Sync failed".to_string(), err.clone()),
+        };
+        let button = egui::Button::new(text).frame(false);
+        if ui.add_enabled(self.sync_job.is_none(), button).on_hover_text(hover).clicked() {
+            if self.sync_conflicts().is_empty() {
+                self.sync_requested = true;
+            } else {
+                self.sync_conflicts_open = true;
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn sync_conflicts(&self) -> &[String] {
+        match &self.last_sync {
+            Some((_, Ok(report))) => &report.conflicts,
+            _ => &[],
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_sync_conflicts_window(&mut self, ctx: &egui::Context) {
+        if !self.sync_conflicts_open {
+            return;
+        }
+        let mut open = true;
+        let mut resolution = None;
+        let busy = self.sync_job.is_some();
+        egui::Window::new("Sync Conflicts").open(&mut open).show(ctx, |ui| {
+            let conflicts = self.sync_conflicts();
+            if conflicts.is_empty() {
+                ui.label("No conflicts.");
+            } else {
+                ui.label("These files were changed here and on the server in ways that could not be merged.");
+            }
+            egui::Grid::new("sync_conflicts_grid").striped(true).show(ui, |ui| {
+                for path in conflicts {
+                    ui.label(path);
+                    ui.add_enabled_ui(!busy, |ui| {
+                        if ui.button("Keep Mine").on_hover_text("Replace the server copy").clicked() {
+                            resolution = Some((path.clone(), Resolution::KeepMine));
+                        }
+                        if ui.button("Keep Theirs").on_hover_text("Replace the local copy").clicked() {
+                            resolution = Some((path.clone(), Resolution::KeepTheirs));
+                        }
+                        if ui.button("Keep Both").on_hover_text("Keep the local copy as \"… (conflict)\"").clicked() {
+                            resolution = Some((path.clone(), Resolution::KeepBoth));
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        });
+        self.sync_conflicts_open = open;
+        if let Some((path, resolution)) = resolution {
+            self.resolve_sync_conflict(&path, resolution);
+        }
+    }
+
+    // Settles one conflict and syncs, which transfers the kept version
+    #[cfg(not(target_arch = "wasm32"))]
+    fn resolve_sync_conflict(&mut self, path: &str, resolution: Resolution) {
+        self.save_active_note_to_disk();
+        let result = Notes::get_notes_dir().and_then(|root| sync::resolve(&root, path, resolution));
+        match result {
+            Ok(copy) => {
+                if let Some((_, Ok(report))) = &mut self.last_sync {
+                    report.conflicts.retain(|conflict| conflict != path);
+                }
+                self.command_status = Some(match copy {
+                    Some(copy) => format!("Kept your version of {} as {}", path, copy),
+                    None => format!("Resolved the conflict in {}", path),
+                });
+                if self.sync_conflicts().is_empty() {
+                    self.sync_conflicts_open = false;
+                }
+                self.reload_notes();
+                self.sync_requested = true;
+            }
+            Err(err) => self.command_status = Some(format!("Failed to resolve the conflict in {}: {}", path, err)),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_jobs_window(&mut self, ctx: &egui::Context) {
+        let mut run_now = None;
+        let mut remove = None;
+        let running = self.running_job.as_ref().map(|job| job.name.clone());
+        let now = scheduler::now();
+        egui::Window::new("Scheduled Jobs")
+            .open(&mut self.jobs_window_open)
+            .show(ctx, |ui| {
+                if self.schedule.jobs.is_empty() {
+                    ui.label("No jobs. Add one with e.g. `schedule stats nightly export-stats csv ~/stats.csv`.");
+                }
+                egui::Grid::new("jobs_grid").striped(true).show(ui, |ui| {
+                    for job in &self.schedule.jobs {
+                        ui.label(&job.name).on_hover_text(&job.command);
+                        match job.last_run {
+                            Some(last_run) => ui.label(scheduler::format_elapsed(now.saturating_sub(last_run))),
+                            None => ui.label("never run"),
+                        };
+                        if running.as_deref() == Some(job.name.as_str()) {
+                            ui.spinner();
+                        } else {
+                            match &job.last_status {
+                                Some(JobStatus::Succeeded(message)) => ui.label(message),
+                                Some(JobStatus::Failed(err)) => ui.colored_label(ui.visuals().error_fg_color, err),
+                                None => ui.label(""),
+                            };
+                        }
+                        if ui.add_enabled(running.is_none(), egui::Button::new("Run now")).clicked() {
+                            run_now = Some(job.name.clone());
+                        }
+                        if ui.button("Remove").clicked() {
+                            remove = Some(job.name.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if let Some(name) = run_now {
+            self.start_job(ctx, &name);
+        }
+        if let Some(name) = remove {
+            self.command_status = Some(match (Command::Unschedule { name }).run() {
+                Ok(message) | Err(message) => message,
+            });
+            self.schedule = Schedule::load().unwrap_or_default();
+        }
+    }
+
+    fn refresh_tasks(&mut self) {
+        if let Err(err) = self.task_index.refresh() {
+            log::error!("Failed to index tasks: {}", err);
+        }
+    }
+
+    fn refresh_search(&mut self) {
+        if let Err(err) = self.search_index.refresh() {
+            log::error!("Failed to index notes for search: {}", err);
+        }
+        self.search_stale = true;
+        self.related_notes = None;
+    }
+
+    fn show_search(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        let mut changed = ui
+            .add(
+                egui::TextEdit::singleline(&mut self.search_query)
+                    .id(egui::Id::new(SEARCH_QUERY_ID))
+                    .hint_text("Search notes"),
+            )
+            .changed();
+        ui.horizontal(|ui| {
+            let options = &mut self.search_options;
+            changed |= ui.toggle_value(&mut options.regex, ".*").on_hover_text("Regular expression").changed();
+            changed |= ui.toggle_value(&mut options.case_sensitive, "Aa").on_hover_text("Match case").changed();
+            changed |= ui.toggle_value(&mut options.whole_word, "ab").on_hover_text("Whole words").changed();
+            changed |= ui.toggle_value(&mut options.in_title_only, "Titles").on_hover_text("Search titles only").changed();
+            changed |= ui
+                .toggle_value(&mut options.include_archived, "🗄")
+                .on_hover_text("Include archived notes")
+                .changed();
+        });
+        if self.search_query.trim().is_empty() {
+            return None;
+        }
+        if changed || self.search_stale {
+            self.search_hits = self.search_index.search_with(&self.search_query, &self.search_options);
+            self.search_stale = false;
+        }
+
+        let mut opened_note = None;
+        let mut viewed_archived = None;
+        match &self.search_hits {
+            Ok(hits) if hits.is_empty() => {
+                ui.weak("No matching notes");
+            }
+            Ok(hits) => {
+                for hit in hits {
+                    let mut label = if self.search_options.in_title_only {
+                        hit.title.clone()
+                    } else {
+                        format!("{} ({})", hit.title, hit.ranges.len())
+                    };
+                    if hit.archived {
+                        label.push_str(" 🗄");
+                    }
+                    if !ui.button(label).clicked() {
+                        continue;
+                    }
+                    if hit.archived {
+                        viewed_archived = Some(hit.title.clone());
+                    } else {
+                        opened_note = Some(hit.title.clone());
+                    }
+                }
+            }
+            Err(err) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+        }
+        if let Some(title) = viewed_archived {
+            self.view_archived(&title);
+        }
+        opened_note
+    }
+
+    fn toggle_inline_task(&mut self, task: &InlineTask) {
+        match &mut self.buffer {
+            Some(buffer) if buffer.title == task.note && buffer.is_locked() => {
+                self.command_status = Some(format!("{} is locked; unlock it to tick its tasks", task.note));
+            }
+            // Edit the open note through its buffer so that unsaved changes are kept.
+            Some(buffer) if buffer.title == task.note => {
+                // The task was indexed from the file, which unsaved edits may have moved it away from
+                let updated = tasks::locate(&buffer.content, task)
+                    .and_then(|line| tasks::toggle_in_content(&buffer.content, line));
+                match updated {
+                    Some(updated) => buffer.content = updated,
+                    None => self.command_status = Some(format!("The task is no longer in {}", task.note)),
+                }
+                self.save_active_note_to_disk();
+            }
+            _ => {
+                if let Err(err) = tasks::toggle(task) {
+                    self.command_status = Some(format!("Failed to toggle the task in {}: {}", task.note, err));
+                }
+            }
+        }
+        self.refresh_tasks();
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, Id::new("drop_target")));
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop files to attach them to the note",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                egui::Color32::WHITE,
+            );
+        }
+
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        if dropped.is_empty() {
+            return;
+        }
+        let Some(buffer) = &mut self.buffer else {
+            self.command_status = Some("Open a note to attach files to it".to_string());
+            return;
+        };
+        let mut links = Vec::new();
+        let mut images = Vec::new();
+        for file in dropped {
+            let name = match &file.path {
+                Some(path) => path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                None => file.name.clone(),
+            };
+            // Web drops carry the bytes, native drops the path.
+            let stored = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => attachments::store(&name, bytes),
+                (None, Some(path)) => attachments::store_file(path),
+                (None, None) => continue,
+            };
+            match stored {
+                Ok(path) => {
+                    links.push(attachments::markdown_link(&name, &path));
+                    if attachments::is_image(&path) {
+                        images.push(path);
+                    }
+                }
+                Err(err) => self.command_status = Some(format!("Failed to attach {}: {}", name, err)),
+            }
+        }
+        if links.is_empty() {
+            return;
+        }
+
+        insert_at_cursor(ctx, buffer, &links.join("\n"));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.config.ocr_attachments && !images.is_empty() && self.ocr_job.is_none() {
+            // Reading an image takes a few seconds, so it happens off the UI thread
+            let title = buffer.title.clone();
+            let ctx = ctx.clone();
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(ocr::index(&images));
+                ctx.request_repaint();
+            });
+            self.ocr_job = Some((title, receiver));
+        }
+    }
+
+    // Starts recording an audio memo, or stops and attaches it to the open note
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_audio_recording(&mut self, ctx: &egui::Context) {
+        let Some(recorder) = self.audio_recorder.take() else {
+            match Recorder::start() {
+                Ok(recorder) => self.audio_recorder = Some(recorder),
+                Err(err) => self.command_status = Some(format!("Failed to start recording: {}", err)),
+            }
+            return;
+        };
+        let name = audio::memo_name(chrono::Local::now().naive_local());
+        let stored = recorder.finish().and_then(|wav| attachments::store(&name, &wav));
+        self.command_status = Some(match (stored, &mut self.buffer) {
+            (Ok(path), Some(buffer)) => {
+                insert_at_cursor(ctx, buffer, &attachments::markdown_link(&name, &path));
+                format!("Attached {}", name)
+            }
+            (Ok(path), None) => format!("Saved {} as {}; open a note to link it", name, path),
+            (Err(err), _) => format!("Failed to save the recording: {}", err),
+        });
+    }
+
+    // Starts dictating into the open note, or stops listening and lets what was said be transcribed
+    #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+    fn toggle_dictation(&mut self, ctx: &egui::Context) {
+        match &mut self.dictation {
+            Some(dictation) if dictation.is_listening() => dictation.stop(),
+            Some(_) => self.command_status = Some("Still transcribing the last dictation".to_string()),
+            None => {
+                let model = PathBuf::from(self.config.dictation_model.trim());
+                match Dictation::start(&model, ctx) {
+                    Ok(dictation) => self.dictation = Some(dictation),
+                    Err(err) => self.command_status = Some(format!("Failed to start dictating: {}", err)),
+                }
+            }
+        }
+    }
+
+    #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+    fn poll_dictation(&mut self, ctx: &egui::Context) {
+        let Some(dictation) = &mut self.dictation else {
+            return;
+        };
+        match dictation.poll() {
+            Ok(texts) => {
+                for text in texts {
+                    match &mut self.buffer {
+                        Some(buffer) => insert_dictation(ctx, buffer, &text),
+                        None => self.command_status = Some("Open a note to dictate into".to_string()),
+                    }
+                }
+                if dictation.is_finished() {
+                    self.dictation = None;
+                } else {
+                    // Chunks are sent off while recording even if nothing else happens
+                    ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                }
+            }
+            Err(err) => {
+                self.command_status = Some(err);
+                self.dictation = None;
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_ocr(&mut self) {
+        let Some((title, receiver)) = &self.ocr_job else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(io::Error::new(io::ErrorKind::Other, "Reading the images panicked")),
+        };
+        let title = title.clone();
+        self.ocr_job = None;
+        match result {
+            Ok(_) => {
+                // The note may have been indexed when it was saved, before the text was read. Unsaved
+                // notes are indexed again when they are saved.
+                let unsaved = self.buffer.as_ref().is_some_and(|buffer| buffer.title == title && buffer.is_dirty());
+                if !unsaved {
+                    let indexed =
+                        Notes::read_note_file(&title).and_then(|content| self.search_index.update(&title, &content));
+                    if let Err(err) = indexed {
+                        log::error!("Failed to index {}: {}", title, err);
+                    }
+                }
+            }
+            Err(err) => self.command_status = Some(format!("Failed to read the text in the images: {}", err)),
+        }
+    }
+
+    fn refresh_reading_list(&mut self) {
+        self.reading_list = reading::list().unwrap_or_else(|err| {
+            log::error!("Failed to load the reading list: {}", err);
+            Vec::new()
+        });
+    }
+
+    // Apply a front matter change to a reading list note, through the buffer if it is open
+    fn update_reading_item(&mut self, title: &str, update: impl FnOnce(&str) -> String) {
+        match &mut self.buffer {
+            Some(buffer) if buffer.title == title => {
+                buffer.content = update(&buffer.content);
+                self.save_active_note_to_disk();
+            }
+            _ => {
+                let result = Notes::read_note_file(title).and_then(|content| Notes::update_note_file(title, &update(&content)));
+                if let Err(err) = result {
+                    log::error!("Failed to update {}: {}", title, err);
+                }
+            }
+        }
+        self.refresh_reading_list();
+    }
+
+    // A month of daily notes and due todos, to open the note of a day or list the todos due on it
+    fn show_calendar_window(&mut self, ctx: &egui::Context) {
+        let Some(month) = self.calendar_month else {
+            return;
+        };
+        let today = chrono::Local::now().date_naive();
+        let marks = CalendarMarks::collect(month, &self.notes.lock().unwrap().items, &self.todos.lock().unwrap());
+        let mut open = true;
+        let mut shown_month = month;
+        let mut opened_day = None;
+        let mut filtered_day = None;
+        egui::Window::new("Calendar")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("◀").on_hover_text("Previous month").clicked() {
+                        shown_month = calendar::shift_month(month, -1);
+                    }
+                    ui.strong(month.format("%B %Y").to_string());
+                    if ui.button("▶").on_hover_text("Next month").clicked() {
+                        shown_month = calendar::shift_month(month, 1);
+                    }
+                    if ui.button("Today").clicked() {
+                        shown_month = today;
+                    }
+                });
+                let note_color = ui.visuals().selection.bg_fill;
+                let todo_color = egui::Color32::from_rgb(230, 160, 40);
+                egui::Grid::new("calendar_grid").spacing([4.0, 4.0]).show(ui, |ui| {
+                    for weekday in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"] {
+                        ui.weak(weekday);
+                    }
+                    ui.end_row();
+                    for week in calendar::month_weeks(month) {
+                        for day in week {
+                            let Some(day) = day else {
+                                ui.label("");
+                                continue;
+                            };
+                            ui.vertical(|ui| {
+                                let mut number = egui::RichText::new(day.day().to_string());
+                                if day == today {
+                                    number = number.strong().underline();
+                                }
+                                let has_note = marks.daily_notes.contains(&day);
+                                let hover = if has_note { "Open the daily note" } else { "Create the daily note" };
+                                let button = egui::Button::new(number).min_size(egui::vec2(32.0, 0.0));
+                                if ui.add(button).on_hover_text(hover).clicked() {
+                                    opened_day = Some(day);
+                                }
+                                ui.horizontal(|ui| {
+                                    if has_note {
+                                        ui.colored_label(note_color, "●").on_hover_text("Daily note");
+                                    }
+                                    if let Some(count) = marks.due_todos.get(&day) {
+                                        let dot = egui::RichText::new(format!("● {}", count)).color(todo_color);
+                                        let link = ui.add(egui::Label::new(dot).sense(egui::Sense::click()));
+                                        if link.on_hover_text("List the todos due this day").clicked() {
+                                            filtered_day = Some(day);
+                                        }
+                                    }
+                                });
+                            });
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.weak("● Daily note   ● Todos due");
+            });
+        self.calendar_month = open.then_some(shown_month);
+        if let Some(day) = opened_day {
+            self.open_daily_note(day);
+        }
+        if let Some(day) = filtered_day {
+            self.todo_due_filter = Some(day);
+            self.todos_panel_open = true;
+        }
+    }
+
+    fn show_reading_window(&mut self, ctx: &egui::Context) {
+        let mut opened_note = None;
+        let mut new_status = None;
+        let mut new_progress = None;
+        egui::Window::new("Reading List")
+            .open(&mut self.reading_window_open)
+            .show(ctx, |ui| {
+                if self.reading_list.is_empty() {
+                    ui.label("No items. Add `type: reading` or the `reading` tag to a note's front matter.");
+                }
+                egui::Grid::new("reading_grid").striped(true).show(ui, |ui| {
+                    for item in &mut self.reading_list {
+                        if ui.link(&item.title).clicked() {
+                            opened_note = Some(item.title.clone());
+                        }
+                        ui.label(item.author.as_deref().unwrap_or(""));
+                        match &item.url {
+                            Some(url) => ui.hyperlink_to("Link", url),
+                            None => ui.label(""),
+                        };
+                        if ui
+                            .button(item.status.label())
+                            .on_hover_text("Click to change the status")
+                            .clicked()
+                        {
+                            new_status = Some((item.title.clone(), item.status.next()));
+                        }
+                        // Write the progress once the slider is let go rather than on every step.
+                        let response = ui.add(egui::Slider::new(&mut item.progress, 0..=100).suffix("%"));
+                        if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                            new_progress = Some((item.title.clone(), item.progress));
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                let months = reading::finished_per_month(&self.reading_list);
+                if !months.is_empty() {
+                    ui.separator();
+                    ui.strong("Finished per month");
+                    for (month, count) in months {
+                        ui.label(format!("{}: {}", month, count));
+                    }
+                }
+            });
+
+        if let Some(title) = opened_note {
+            self.open_note(&title);
+        }
+        if let Some((title, status)) = new_status {
+            let today = chrono::Local::now().date_naive();
+            self.update_reading_item(&title, |content| reading::set_status(content, status, today));
+        }
+        if let Some((title, progress)) = new_progress {
+            self.update_reading_item(&title, |content| reading::set_progress(content, progress));
+        }
+    }
+
+    fn show_todos_recovery(&mut self, ctx: &egui::Context) {
+        let Some(recovery) = &self.todos_recovery else {
+            return;
+        };
+        let mut dismissed = false;
+        egui::Window::new("Todos Recovered").collapsible(false).show(ctx, |ui| {
+            ui.label("The todos file could not be read and was repaired.");
+            ui.weak(&recovery.error);
+            ui.label(format!("{} todos were recovered.", recovery.recovered));
+            ui.label(format!("The damaged file was kept at {}.", recovery.backup.display()));
+            if ui.button("OK").clicked() {
+                dismissed = true;
+            }
+        });
+        if dismissed {
+            self.todos_recovery = None;
+        }
+    }
+
+    fn show_stats_window(&mut self, ctx: &egui::Context) {
+        let Some(vault_stats) = &self.vault_stats else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Statistics").open(&mut open).show(ctx, |ui| {
+            ui.label(format!("Notes: {}", vault_stats.total_notes));
+            ui.label(format!(
+                "Words: {} ({} min read)",
+                vault_stats.total_words,
+                stats::reading_minutes(vault_stats.total_words)
+            ));
+
+            egui::CollapsingHeader::new("Notes per tag").show(ui, |ui| {
+                if vault_stats.notes_per_tag.is_empty() {
+                    ui.weak("No tags");
+                }
+                egui::Grid::new("tag_stats").striped(true).show(ui, |ui| {
+                    for (tag, count) in &vault_stats.notes_per_tag {
+                        ui.label(format!("#{}", tag));
+                        ui.label(count.to_string());
+                        ui.end_row();
+                    }
+                });
+            });
+            egui::CollapsingHeader::new("Notes created per week").show(ui, |ui| {
+                let most = vault_stats.created_per_week.iter().map(|(_, count)| *count).max().unwrap_or(1);
+                egui::Grid::new("week_stats").show(ui, |ui| {
+                    for (week, count) in &vault_stats.created_per_week {
+                        ui.label(week);
+                        ui.add(egui::ProgressBar::new(*count as f32 / most as f32).text(count.to_string()));
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+        if !open {
+            self.vault_stats = None;
+        }
+    }
+
+    // Opens the Reports dialog on the week of a day, keeping the export path
+    fn show_report(&mut self, day: NaiveDate) {
+        match reports::weekly_report(day) {
+            Ok(report) => {
+                let export_path = self.reports.take().map(|dialog| dialog.export_path).unwrap_or_default();
+                self.reports = Some(ReportsDialog {
+                    report,
+                    export_path,
+                    status: None,
+                });
+            }
+            Err(err) => self.command_status = Some(format!("Failed to load the focus sessions: {}", err)),
+        }
+    }
+
+    fn show_reports_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.reports else {
+            return;
+        };
+        let mut open = true;
+        let mut shown_week = None;
+        egui::Window::new("Reports").open(&mut open).show(ctx, |ui| {
+            let report = &dialog.report;
+            ui.horizontal(|ui| {
+                if ui.button("◀").on_hover_text("Previous week").clicked() {
+                    shown_week = Some(report.week - chrono::Duration::days(7));
+                }
+                ui.strong(format!("Week of {}", report.week.format("%Y-%m-%d")));
+                if ui.button("▶").on_hover_text("Next week").clicked() {
+                    shown_week = Some(report.week + chrono::Duration::days(7));
+                }
+            });
+            ui.label(format!("Time in focus sessions: {}", focus::format_duration(report.total_secs)));
+            let most = report.per_project.first().map_or(1, |(_, secs)| *secs).max(1);
+            egui::CollapsingHeader::new("Per project").default_open(true).show(ui, |ui| {
+                if report.per_project.is_empty() {
+                    ui.weak("No focus sessions");
+                }
+                egui::Grid::new("project_report").show(ui, |ui| {
+                    for (project, secs) in &report.per_project {
+                        match project {
+                            Some(project) => ui.label(format!("@{}", project)),
+                            None => ui.weak("No project"),
+                        };
+                        let fraction = *secs as f32 / most as f32;
+                        ui.add(egui::ProgressBar::new(fraction).text(focus::format_duration(*secs)));
+                        ui.end_row();
+                    }
+                });
+            });
+            let most = report.per_tag.first().map_or(1, |(_, secs)| *secs).max(1);
+            egui::CollapsingHeader::new("Per tag").default_open(true).show(ui, |ui| {
+                if report.per_tag.is_empty() {
+                    ui.weak("No sessions on todos of tagged notes");
+                }
+                egui::Grid::new("tag_report").show(ui, |ui| {
+                    for (tag, secs) in &report.per_tag {
+                        ui.label(format!("#{}", tag));
+                        let fraction = *secs as f32 / most as f32;
+                        ui.add(egui::ProgressBar::new(fraction).text(focus::format_duration(*secs)));
+                        ui.end_row();
+                    }
+                });
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut dialog.export_path).hint_text("~/report.csv"));
+                let path = dialog.export_path.trim();
+                if ui.add_enabled(!path.is_empty(), egui::Button::new("Export CSV")).clicked() {
+                    let path = commands::expand_home(path);
+                    dialog.status = Some(match reports::export(&path, &dialog.report) {
+                        Ok(()) => format!("Exported to {}", path.display()),
+                        Err(err) => format!("Export failed: {}", err),
+                    });
+                }
+            });
+            if let Some(status) = &dialog.status {
+                ui.label(status);
+            }
+        });
+        if !open {
+            self.reports = None;
+        } else if let Some(week) = shown_week {
+            self.show_report(week);
+        }
+    }
+
+    fn show_outline_panel(&mut self, ctx: &egui::Context) {
+        if !self.outline_open || self.zen.is_some() {
+            return;
+        }
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+        let headings = outline::headings(&buffer.content);
+        let mut actions = OutlineActions::default();
+        SidePanel::right("outline_panel").show(ctx, |ui| {
+            ui.heading("Outline");
+            if headings.is_empty() {
+                ui.weak("Headings such as `## Goals` show up here.");
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                show_outline(ui, &headings, 0..headings.len(), &mut actions);
+            });
+        });
+        if let Some((from, to)) = actions.moved {
+            if let Some(content) = outline::move_section(&buffer.content, from, to) {
+                buffer.content = content;
+                buffer.mark_edited(ctx.input(|i| i.time));
+            }
+        }
+        if let Some(line) = actions.jump {
+            self.pending_jump = Some(line);
+        }
+    }
+
+    // Back and forward like a browser, with Ctrl+[ and Ctrl+] or the mouse's side buttons, and
+    // Ctrl+Tab to step through the recent notes while Ctrl is held
+    fn handle_navigation(&mut self, ctx: &egui::Context) {
+        let (back, forward, cycle_back, cycle_forward, ctrl) = ctx.input_mut(|i| {
+            (
+                i.consume_shortcut(&BACK_SHORTCUT) || i.pointer.button_pressed(egui::PointerButton::Extra1),
+                i.consume_shortcut(&FORWARD_SHORTCUT) || i.pointer.button_pressed(egui::PointerButton::Extra2),
+                i.consume_key(egui::Modifiers::CTRL | egui::Modifiers::SHIFT, egui::Key::Tab),
+                i.consume_key(egui::Modifiers::CTRL, egui::Key::Tab),
+                i.modifiers.ctrl,
+            )
+        });
+        if back {
+            self.pending_navigation = Some(Direction::Back);
+        }
+        if forward {
+            self.pending_navigation = Some(Direction::Forward);
+        }
+        if let Some(direction) = self.pending_navigation.take() {
+            let current = self.selected_note.clone();
+            if let Some(title) = self.navigation.go(direction, current.as_deref()) {
+                self.navigating = true;
+                self.open_note(&title);
+            }
+        }
+
+        let count = self.recent_notes.len().min(CYCLED_NOTES);
+        if count > 1 && (cycle_back || cycle_forward) {
+            let index = self.note_cycle.unwrap_or(0);
+            self.note_cycle = Some(if cycle_forward {
+                (index + 1) % count
+            } else {
+                (index + count - 1) % count
+            });
+        }
+        let Some(index) = self.note_cycle else {
+            return;
+        };
+        if !ctrl {
+            self.note_cycle = None;
+            if let Some(title) = self.recent_notes.get(index).cloned() {
+                self.open_note(&title);
+            }
+            return;
+        }
+        egui::Area::new(Id::new("note_cycle"))
+            .order(egui::Order::Foreground)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.strong("Recent Notes");
+                    for (position, title) in self.recent_notes.iter().take(count).enumerate() {
+                        ui.selectable_label(position == index, title);
+                    }
+                });
+            });
+    }
+
+    // Check every note of the open vault and show what was found
+    fn verify_notes(&mut self) {
+        self.save_active_note_to_disk();
+        let result = match &self.vault {
+            Some(vault) => vault.verify(),
+            None => Vault::active().and_then(|vault| vault.verify()),
+        };
+        match result {
+            Ok(report) => self.verify_report = Some(report),
+            Err(err) => self.command_status = Some(format!("Failed to verify the notes: {}", err)),
+        }
+    }
+
+    fn show_verify_window(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.verify_report else {
+            return;
+        };
+        let mut open = true;
+        let mut open_title = None;
+        let mut fix = None;
+        let read_only = self.is_read_only();
+        egui::Window::new("Verify Notes").open(&mut open).show(ctx, |ui| {
+            if report.issues.is_empty() {
+                ui.label(format!("Checked {} notes, no problems found.", report.checked));
+                return;
+            }
+            ui.label(format!(
+                "Checked {} notes, found {} problems.",
+                report.checked,
+                report.issues.len()
+            ));
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                egui::Grid::new("verify_grid").striped(true).show(ui, |ui| {
+                    for issue in &report.issues {
+                        if ui.link(&issue.title).clicked() {
+                            open_title = Some(issue.title.clone());
+                        }
+                        ui.label(issue.description());
+                        match issue.fix_label() {
+                            Some(label) => {
+                                if ui
+                                    .add_enabled(!read_only, egui::Button::new(label))
+                                    .on_disabled_hover_text("Another window is using the notes directory")
+                                    .clicked()
+                                {
+                                    fix = Some(issue.clone());
+                                }
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+        if !open {
+            self.verify_report = None;
+        }
+        if let Some(title) = open_title {
+            self.open_note(&title);
+        }
+        if let Some(issue) = fix {
+            self.fix_issue(&issue);
+        }
+    }
+
+    // Fix one problem, then check again so the list stays accurate
+    fn fix_issue(&mut self, issue: &Issue) {
+        self.save_active_note_to_disk();
+        let result = match &self.vault {
+            Some(vault) => vault.fix_issue(issue),
+            None => Vault::active().and_then(|vault| vault.fix_issue(issue)),
+        };
+        match result {
+            Ok(()) => {
+                self.command_status = Some(format!("Fixed {}", issue.title));
+                self.reload_notes();
+                self.verify_notes();
+            }
+            Err(err) => self.command_status = Some(format!("Failed to fix {}: {}", issue.title, err)),
+        }
+    }
+
+    // Log what was done with a note, unless the activity log is turned off
+    fn log_activity(&self, kind: ActivityKind, title: &str) {
+        if self.config.activity_log && self.safe_mode.is_none() && !self.is_read_only() {
+            if let Err(err) = activity::record(kind, title) {
+                log::error!("Failed to log activity: {}", err);
+            }
+        }
+    }
+
+    fn show_insights_window(&mut self, ctx: &egui::Context) {
+        let Some(insights) = &self.insights else {
+            return;
+        };
+        let mut open = true;
+        let mut clear = false;
+        egui::Window::new("Insights").open(&mut open).show(ctx, |ui| {
+            ui.weak("Computed from a log kept in your notes folder. It never leaves this machine.");
+            let list = |ui: &mut egui::Ui, id: &str, entries: &[(String, usize)], prefix: &str| {
+                if entries.is_empty() {
+                    ui.weak("Nothing logged yet");
+                }
+                egui::Grid::new(id).striped(true).show(ui, |ui| {
+                    for (name, count) in entries {
+                        ui.label(format!("{}{}", prefix, name));
+                        ui.label(count.to_string());
+                        ui.end_row();
+                    }
+                });
+            };
+            egui::CollapsingHeader::new("Most edited notes")
+                .default_open(true)
+                .show(ui, |ui| list(ui, "most_edited", &insights.most_edited, ""));
+            egui::CollapsingHeader::new("Most used tags").show(ui, |ui| {
+                list(ui, "most_used_tags", &insights.most_used_tags, "#")
+            });
+            egui::CollapsingHeader::new("Busiest hours").show(ui, |ui| {
+                let most = insights.busiest_hours.iter().copied().max().unwrap_or(0).max(1);
+                egui::Grid::new("busiest_hours").show(ui, |ui| {
+                    for (hour, count) in insights.busiest_hours.iter().enumerate() {
+                        ui.label(format!("{:02}:00", hour));
+                        ui.add(egui::ProgressBar::new(*count as f32 / most as f32).text(count.to_string()));
+                        ui.end_row();
+                    }
+                });
+            });
+            egui::CollapsingHeader::new("Abandoned drafts").show(ui, |ui| {
+                if insights.abandoned_drafts.is_empty() {
+                    ui.weak("None");
+                }
+                for title in &insights.abandoned_drafts {
+                    ui.label(title);
+                }
+            });
+            ui.separator();
+            if !self.config.activity_log {
+                ui.weak("The activity log is turned off in .config.json.");
+            }
+            if ui.button("Clear Activity Log").clicked() {
+                clear = true;
+            }
+        });
+        if clear {
+            match activity::clear() {
+                Ok(()) => self.insights = Some(Insights::default()),
+                Err(err) => self.command_status = Some(format!("Failed to clear the activity log: {}", err)),
+            }
+        }
+        if !open {
+            self.insights = None;
+        }
+    }
+
+    fn show_quick_open(&mut self, ctx: &egui::Context) {
+        let shortcut = keymap::shortcut(&self.config.keymap, Action::QuickOpen);
+        if ctx.input_mut(|i| i.consume_shortcut(&shortcut)) {
+            self.quick_open = QuickOpen {
+                open: !self.quick_open.open,
+                ..QuickOpen::default()
+            };
+        }
+        if !self.quick_open.open {
+            return;
+        }
+
+        let titles = self.notes.lock().unwrap().items.clone();
+        let matches: Vec<&str> = fuzzy::rank(&self.quick_open.query, &titles, &self.recent_notes)
+            .into_iter()
+            .take(QUICK_OPEN_RESULTS)
+            .collect();
+        // Take the navigation keys before the text field sees them
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        let quick_open = &mut self.quick_open;
+        if up {
+            quick_open.selected = quick_open.selected.saturating_sub(1);
+        }
+        if down {
+            quick_open.selected += 1;
+        }
+        quick_open.selected = quick_open.selected.min(matches.len().saturating_sub(1));
+
+        let mut chosen = match enter {
+            true => matches.get(quick_open.selected).map(|title| title.to_string()),
+            false => None,
+        };
+        egui::Window::new("Quick Open")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .fixed_size([420.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut quick_open.query)
+                        .hint_text("Open note…")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    quick_open.selected = 0;
+                }
+                if matches.is_empty() {
+                    ui.weak("No matching notes");
+                }
+                for (index, title) in matches.iter().enumerate() {
+                    let label = ui.selectable_label(index == quick_open.selected, *title);
+                    if index == quick_open.selected {
+                        label.scroll_to_me(None);
+                    }
+                    if label.clicked() {
+                        chosen = Some(title.to_string());
+                    }
+                }
+            });
+
+        if escape || chosen.is_some() {
+            self.quick_open.open = false;
+        }
+        if let Some(title) = chosen {
+            self.open_note(&title);
+        }
+    }
+
+    fn show_date_query_window(&mut self, ctx: &egui::Context) {
+        let dialog = &mut self.date_query;
+        let mut open = dialog.open;
+        let mut action = None;
+        let mut apply = false;
+        egui::Window::new("Find by Date").open(&mut open).show(ctx, |ui| {
+            if let Some((action, changes)) = &mut dialog.review {
+                // Dry run: nothing is changed until the selected changes are applied.
+                ui.label(format!("{} changes would be made:", changes.len()));
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for change in changes.iter_mut() {
+                        ui.checkbox(&mut change.selected, format!("{}: {}", change.title, change.summary));
+                    }
+                });
+                let selected = changes.iter().filter(|change| change.selected).count();
+                let label = match action {
+                    BatchAction::Delete => format!("Delete {} notes", selected),
+                    _ => format!("Apply {} changes", selected),
+                };
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(selected > 0, egui::Button::new(label)).clicked() {
+                        apply = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+                if cancel {
+                    dialog.review = None;
+                }
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("date_query_field")
+                    .selected_text(dialog.field.label())
+                    .show_ui(ui, |ui| {
+                        for field in [DateField::Created, DateField::Modified] {
+                            ui.selectable_value(&mut dialog.field, field, field.label());
+                        }
+                    });
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut dialog.range).hint_text("last quarter, 2024-01-01..2024-03-31"),
+                );
+                let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Find").clicked() || submitted {
+                    match DateRange::parse(&dialog.range, chrono::Local::now().date_naive()) {
+                        Some(range) => match batch::find(dialog.field, range) {
+                            Ok(titles) => {
+                                dialog.results = titles;
+                                self.command_status = None;
+                            }
+                            Err(err) => self.command_status = Some(format!("Search failed: {}", err)),
+                        },
+                        None => self.command_status = Some(format!("Invalid date range: {}", dialog.range)),
+                    }
+                }
+            });
+
+            ui.label(format!("{} notes", dialog.results.len()));
+            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                for title in &dialog.results {
+                    ui.label(title);
+                }
+            });
+
+            ui.add_enabled_ui(!dialog.results.is_empty(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut dialog.export_dir).hint_text("Directory"));
+                    if ui
+                        .add_enabled(!dialog.export_dir.trim().is_empty(), egui::Button::new("Export…"))
+                        .clicked()
+                    {
+                        action = Some(BatchAction::Export(commands::expand_home(dialog.export_dir.trim())));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut dialog.tag).hint_text("Tag"));
+                    let tag = dialog.tag.trim().trim_start_matches('#');
+                    if ui.add_enabled(!tag.is_empty(), egui::Button::new("Tag…")).clicked() {
+                        action = Some(BatchAction::Tag(tag.to_string()));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut dialog.rename_from).hint_text("Tag").desired_width(80.0));
+                    ui.label("→");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.rename_to).hint_text("New name").desired_width(80.0));
+                    let from = dialog.rename_from.trim().trim_start_matches('#');
+                    let to = dialog.rename_to.trim().trim_start_matches('#');
+                    if ui
+                        .add_enabled(!from.is_empty() && !to.is_empty(), egui::Button::new("Rename Tag…"))
+                        .clicked()
+                    {
+                        action = Some(BatchAction::RenameTag {
+                            from: from.to_string(),
+                            to: to.to_string(),
+                        });
+                    }
+                });
+                if ui.button("Delete Notes…").clicked() {
+                    action = Some(BatchAction::Delete);
+                }
+            });
+        });
+        dialog.open = open;
+
+        if let Some(action) = action {
+            match batch::plan(&self.date_query.results, &action) {
+                Ok(changes) => self.date_query.review = Some((action, changes)),
+                Err(err) => self.command_status = Some(format!("Dry run failed: {}", err)),
+            }
+        }
+        if apply {
+            if let Some((action, changes)) = self.date_query.review.take() {
+                self.command_status = Some(match batch::apply_plan(&changes, &action) {
+                    Ok(count) => action.describe(count),
+                    Err(err) => format!("Batch operation failed: {}", err),
+                });
+                self.date_query.results.clear();
+                self.reload_notes();
+            }
+        }
+    }
+
+    fn show_export_window(&mut self, ctx: &egui::Context) {
+        let dialog = &mut self.export_dialog;
+        let mut open = dialog.open;
+        let mut export_with = None;
+        egui::Window::new("Export Note").open(&mut open).show(ctx, |ui| {
+            egui::Grid::new("export_fields").num_columns(2).show(ui, |ui| {
+                ui.label("Format");
+                egui::ComboBox::from_id_source("export_format")
+                    .selected_text(dialog.format.extension())
+                    .show_ui(ui, |ui| {
+                        for format in NoteFormat::ALL {
+                            ui.selectable_value(&mut dialog.format, format, format.extension());
+                        }
+                    });
+                ui.end_row();
+                ui.label("Destination");
+                ui.add(egui::TextEdit::singleline(&mut dialog.destination).hint_text("~/exports"));
+                ui.end_row();
+                ui.label("Stylesheet");
+                ui.add_enabled(
+                    dialog.format == NoteFormat::Html,
+                    egui::TextEdit::singleline(&mut dialog.stylesheet).hint_text("Optional CSS file"),
+                );
+                ui.end_row();
+            });
+            ui.checkbox(&mut dialog.include_attachments, "Include attachments");
+            ui.weak("The settings are saved in the note so that it can be exported again in one click.");
+            let destination = dialog.destination.trim();
+            if ui.add_enabled(!destination.is_empty(), egui::Button::new("Export")).clicked() {
+                let stylesheet = dialog.stylesheet.trim();
+                export_with = Some(ExportPreset {
+                    format: dialog.format,
+                    destination: destination.to_string(),
+                    stylesheet: (!stylesheet.is_empty()).then(|| stylesheet.to_string()),
+                    include_attachments: dialog.include_attachments,
+                });
+            }
+        });
+        dialog.open = open && export_with.is_none();
+        if let Some(preset) = export_with {
+            self.export_active_note(&preset);
+        }
+    }
+
+    // Export the open note, which also stores the preset in its front matter
+    fn export_active_note(&mut self, preset: &ExportPreset) {
+        let Some(title) = self.buffer.as_ref().map(|buffer| buffer.title.clone()) else {
+            return;
+        };
+        self.save_active_note_to_disk();
+        self.command_status = Some(match export::export(&title, preset) {
+            Ok(path) => format!("Exported {} to {}", title, path.display()),
+            Err(err) => format!("Failed to export {}: {}", title, err),
+        });
+        if let Some(buffer) = &mut self.buffer {
+            if let Err(err) = buffer.reload() {
+                log::error!("Failed to reload {}: {}", title, err);
+            }
+        }
+    }
+
+    fn show_replace_window(&mut self, ctx: &egui::Context) {
+        let dialog = &mut self.replace_dialog;
+        let mut open = dialog.open;
+        let mut step = None;
+        let has_note = self.buffer.is_some();
+        egui::Window::new("Find and Replace").open(&mut open).show(ctx, |ui| {
+            if let Some((_, changes)) = &mut dialog.review {
+                // Preview: nothing is changed until the selected notes are applied.
+                ui.label(format!("{} notes would be changed:", changes.len()));
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for change in changes.iter_mut() {
+                        ui.checkbox(&mut change.selected, format!("{}: {}", change.title, change.summary));
+                    }
+                });
+                let selected = changes.iter().filter(|change| change.selected).count();
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(selected > 0, egui::Button::new(format!("Replace in {} notes", selected)))
+                        .clicked()
+                    {
+                        step = Some(ReplaceStep::ApplyVault);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+                if cancel {
+                    dialog.review = None;
+                }
+                return;
+            }
+
+            egui::Grid::new("replace_fields").num_columns(2).show(ui, |ui| {
+                ui.label("Find");
+                let response = ui.text_edit_singleline(&mut dialog.find);
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    step = Some(ReplaceStep::FindNext);
+                }
+                ui.end_row();
+                ui.label("Replace");
+                ui.text_edit_singleline(&mut dialog.replace);
+                ui.end_row();
+            });
+            ui.horizontal(|ui| {
+                let options = &mut dialog.options;
+                ui.toggle_value(&mut options.regex, ".*").on_hover_text("Regular expression");
+                ui.toggle_value(&mut options.case_sensitive, "Aa").on_hover_text("Match case");
+                ui.toggle_value(&mut options.whole_word, "ab").on_hover_text("Whole words");
+            });
+            let valid = match dialog.options.pattern(&dialog.find) {
+                _ if dialog.find.is_empty() => false,
+                Ok(_) => true,
+                Err(err) => {
+                    ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                    false
+                }
+            };
+            ui.add_enabled_ui(valid, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add_enabled_ui(has_note, |ui| {
+                        if ui.button("Find Next").clicked() {
+                            step = Some(ReplaceStep::FindNext);
+                        }
+                        if ui.button("Replace").clicked() {
+                            step = Some(ReplaceStep::ReplaceOne);
+                        }
+                        if ui.button("Replace All in Note").clicked() {
+                            step = Some(ReplaceStep::ReplaceAllInNote);
+                        }
+                    });
+                    if ui.button("Replace in Vault…").clicked() {
+                        step = Some(ReplaceStep::PlanVault);
+                    }
+                });
+            });
+        });
+        dialog.open = open;
+
+        match step {
+            Some(ReplaceStep::FindNext) => self.replace_in_note(ctx, false),
+            Some(ReplaceStep::ReplaceOne) => self.replace_in_note(ctx, true),
+            Some(ReplaceStep::ReplaceAllInNote) => {
+                let dialog = &self.replace_dialog;
+                let (Some(buffer), Ok(pattern)) = (&mut self.buffer, dialog.options.pattern(&dialog.find)) else {
+                    return;
+                };
+                let (content, count) = dialog.options.replace_all(&buffer.content, &pattern, &dialog.replace);
+                if count > 0 {
+                    buffer.content = content;
+                    buffer.mark_edited(ctx.input(|i| i.time));
+                }
+                self.command_status = Some(format!("Replaced {} matches", count));
+            }
+            Some(ReplaceStep::PlanVault) => {
+                // Replace in the saved version of the open note, like in every other note.
+                self.save_active_note_to_disk();
+                let action = BatchAction::Replace {
+                    query: self.replace_dialog.find.clone(),
+                    replacement: self.replace_dialog.replace.clone(),
+                    options: self.replace_dialog.options.clone(),
+                };
+                let titles = self.notes.lock().unwrap().items.clone();
+                match batch::plan(&titles, &action) {
+                    Ok(changes) if changes.is_empty() => self.command_status = Some("No matches".to_string()),
+                    Ok(changes) => self.replace_dialog.review = Some((action, changes)),
+                    Err(err) => self.command_status = Some(format!("Replace failed: {}", err)),
+                }
+            }
+            Some(ReplaceStep::ApplyVault) => {
+                if let Some((action, changes)) = self.replace_dialog.review.take() {
+                    self.command_status = Some(match batch::apply_plan(&changes, &action) {
+                        Ok(count) => action.describe(count),
+                        Err(err) => format!("Replace failed: {}", err),
+                    });
+                    self.reload_notes();
+                }
+            }
+            None => {}
+        }
+    }
+
+    // Select the next match in the open note, replacing the selected match first if asked to
+    fn replace_in_note(&mut self, ctx: &egui::Context, replace: bool) {
+        let dialog = &self.replace_dialog;
+        let (Some(buffer), Ok(pattern)) = (&mut self.buffer, dialog.options.pattern(&dialog.find)) else {
+            return;
+        };
+        let selection = egui::TextEdit::load_state(ctx, Id::new("note_editor"))
+            .and_then(|state| state.cursor.char_range())
+            .map(|range| {
+                let (start, end) = (range.primary.index, range.secondary.index);
+                byte_index(&buffer.content, start.min(end))..byte_index(&buffer.content, start.max(end))
+            });
+        let mut from = selection.as_ref().map_or(0, |selection| selection.end);
+        if let Some(selection) = selection.filter(|_| replace) {
+            if let Some((content, inserted)) =
+                dialog
+                    .options
+                    .replace_at(&buffer.content, &pattern, selection, &dialog.replace)
+            {
+                buffer.content = content;
+                buffer.mark_edited(ctx.input(|i| i.time));
+                from = inserted.end;
+            }
+        }
+        match search::find_next(&buffer.content, &pattern, from) {
+            Some(found) => {
+                let start = buffer.content[..found.start].chars().count();
+                let end = start + buffer.content[found].chars().count();
+                self.pending_selection = Some(start..end);
+            }
+            None => self.command_status = Some("No matches".to_string()),
+        }
+    }
+
+    // Bring the note list, the open note and the indexes in line with changes made on disk
+    fn reload_notes(&mut self) {
+        {
+            let mut notes = self.notes.lock().unwrap();
+            notes.items.clear();
+            for title in Notes::list_notes().unwrap_or_default() {
+                notes.add(title);
+            }
+            self.note_selection.retain(&notes.items);
+        }
+        if let Some(buffer) = &mut self.buffer {
+            if Notes::note_exists(&buffer.title).unwrap_or(false) {
+                if let Err(err) = buffer.reload() {
+                    log::error!("Failed to reload {}: {}", buffer.title, err);
+                }
+            } else if !buffer.is_dirty() {
+                // Unsaved edits are kept, like for notes removed by other applications.
+                self.buffer = None;
+                self.selected_note = None;
+            }
+        }
+        *self.todos.lock().unwrap() = Todos::load_from_file().unwrap_or_default();
+        self.sort_notes();
+        self.refresh_tasks();
+        self.refresh_search();
+        self.refresh_archived();
+    }
+
+    fn create_todo_from_selection(&mut self) {
+        let (Some(buffer), Some((text, line))) = (&self.buffer, &self.editor_selection) else {
+            return;
+        };
+        let description = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if description.is_empty() {
+            return;
+        }
+        let mut todos = self.todos.lock().unwrap();
+        todos.add_linked(description, buffer.title.clone(), *line);
+        if let Err(err) = todos.save_to_file() {
+            self.command_status = Some(format!("Failed to save the todos: {}", err));
+        }
+    }
+
+    fn show_editor(&mut self, ui: &mut egui::Ui) {
+        let read_only = self.is_read_only();
+        let Some(buffer) = &mut self.buffer else {
+            ui.label("Select a note to edit");
+            return;
+        };
+        if buffer.is_sealed() {
+            ui.heading(&buffer.title);
+            ui.label("🔐 This note is protected with a passphrase.");
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let entry = &mut self.passphrase_entry;
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut entry.passphrase)
+                        .password(true)
+                        .hint_text("Passphrase"),
+                );
+                let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Unlock").clicked() || submitted {
+                    match buffer.unlock(&entry.passphrase) {
+                        Ok(()) => *entry = PassphraseEntry::default(),
+                        Err(err) => entry.error = Some(err.to_string()),
+                    }
+                }
+                if let Some(error) = &entry.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            ui.weak("Protected notes can only be unlocked in the desktop app.");
+            return;
+        }
+        let locked = buffer.is_locked();
+        let read_only = read_only || locked;
+        let jumping = self.pending_jump.is_some() || self.pending_selection.is_some() || self.pending_macro.is_some();
+        if jumping || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.reading_mode = false;
+        }
+        if self.reading_mode {
+            let style = &mut self.reading_style;
+            let words = stats::body_counts(&buffer.content).0;
+            ui.horizontal(|ui| {
+                ui.heading(&buffer.title);
+                if ui.button("Close Reading Mode").clicked() {
+                    self.reading_mode = false;
+                }
+                ui.menu_button("Aa", |ui| {
+                    ui.checkbox(&mut style.serif, "Serif font");
+                    ui.checkbox(&mut style.justify, "Justify text");
+                    ui.add(egui::Slider::new(&mut style.font_size, 12.0..=28.0).text("Font size"));
+                    ui.add(egui::Slider::new(&mut style.line_height, 1.0..=2.5).text("Line height"));
+                    ui.add(egui::Slider::new(&mut style.max_width, 400.0..=1200.0).text("Column width"));
+                    if ui.button("Reset").clicked() {
+                        *style = ReadingStyle::default();
+                    }
+                });
+                let minutes_left = stats::reading_minutes(((1.0 - self.reading_progress) * words as f32) as usize);
+                ui.add(
+                    egui::ProgressBar::new(self.reading_progress)
+                        .desired_width(160.0)
+                        .text(format!("{} min left", minutes_left)),
+                );
+            });
+            ui.separator();
+            self.reading_progress = reader::show(ui, &buffer.title, &buffer.content, style, &self.code_language);
+            return;
+        }
+
+        let mut add_todo = false;
+        let mut save = false;
+        let mut toggle_zen = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut toggle_recording = false;
+        #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+        let mut toggle_dictation = false;
+        let mut macro_commands = Vec::new();
+        let mut lock_change = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut protection_change = None;
+        let mut export_with = None;
+        let export_preset = ExportPreset::from_front_matter(&FrontMatter::parse(&buffer.content).0);
+        let language = NoteLanguage::of(&buffer.content);
+        let mut new_language = None;
+        ui.horizontal(|ui| {
+            for (direction, label, hover, shortcut) in [
+                (Direction::Back, "⬅", "Back", BACK_SHORTCUT),
+                (Direction::Forward, "➡", "Forward", FORWARD_SHORTCUT),
+            ] {
+                let hover = format!("{} ({})", hover, ui.ctx().format_shortcut(&shortcut));
+                let button = ui.add_enabled(self.navigation.can_go(direction), egui::Button::new(label));
+                if button.on_hover_text(hover).clicked() {
+                    self.pending_navigation = Some(direction);
+                }
+            }
+            ui.heading(&buffer.title);
+            if buffer.is_dirty() {
+                ui.label("●").on_hover_text("Unsaved changes");
+            }
+            if locked {
+                ui.label("🔒").on_hover_text("Read-only: changes can't be made or saved");
+                if ui.small_button("Unlock").clicked() {
+                    lock_change = Some(false);
+                }
+            } else if ui.small_button("🔓").on_hover_text("Lock: make the note read-only").clicked() {
+                lock_change = Some(true);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let label = if buffer.is_protected() { "🔐" } else { "🔑" };
+                ui.menu_button(label, |ui| {
+                    if buffer.is_protected() {
+                        ui.weak("Protected with a passphrase");
+                        if ui.button("Lock Now").clicked() {
+                            protection_change = Some(ProtectionChange::Relock);
+                            ui.close_menu();
+                        }
+                        if ui.button("Remove Passphrase").clicked() {
+                            protection_change = Some(ProtectionChange::Remove);
+                            ui.close_menu();
+                        }
+                    } else if ui.button("Protect with Passphrase…").clicked() {
+                        protection_change = Some(ProtectionChange::Protect);
+                        ui.close_menu();
+                    }
+                })
+                .response
+                .on_hover_text("Passphrase protection");
+            }
+            if let Some(recording) = &self.macro_recording {
+                ui.colored_label(ui.visuals().error_fg_color, "● REC")
+                    .on_hover_text(format!("Recording {}; enter stop-recording when done", recording.name));
+            }
+            let (words, chars) = stats::body_counts(&buffer.content);
+            ui.weak(format!("{} words · {} min read", words, stats::reading_minutes(words)))
+                .on_hover_text(format!("{} characters", chars));
+            if ui.add_enabled(buffer.is_dirty(), egui::Button::new("Save")).clicked() {
+                save = true;
+            }
+            ui.toggle_value(&mut self.preview, "Preview");
+            ui.toggle_value(&mut self.outline_open, "Outline");
+            if ui.button("Read").on_hover_text("Reading mode (Esc to close)").clicked() {
+                self.reading_mode = true;
+            }
+            let zen_shortcut = keymap::shortcut(&self.config.keymap, Action::Zen);
+            let zen_hover = format!("Zen mode ({})", ui.ctx().format_shortcut(&zen_shortcut));
+            if ui.selectable_label(self.zen.is_some(), "Zen").on_hover_text(zen_hover).clicked() {
+                toggle_zen = true;
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            match &self.audio_recorder {
+                Some(recorder) => {
+                    let label = format!("⏹ {}", audio::format_duration(recorder.elapsed()));
+                    let stop = egui::Button::new(egui::RichText::new(label).color(ui.visuals().error_fg_color));
+                    if ui.add(stop).on_hover_text("Stop recording and attach the memo").clicked() {
+                        toggle_recording = true;
+                    }
+                    ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+                }
+                None => {
+                    if ui.button("🎤").on_hover_text("Record an audio memo").clicked() {
+                        toggle_recording = true;
+                    }
+                }
+            }
+            #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+            {
+                let listening = self.dictation.as_ref().is_some_and(|dictation| dictation.is_listening());
+                let hover = if listening { "Stop dictating" } else { "Dictate into the note" };
+                if ui.selectable_label(listening, "🗣").on_hover_text(hover).clicked() {
+                    toggle_dictation = true;
+                }
+            }
+            if ui.button("Export…").clicked() {
+                let preset = export_preset.clone();
+                self.export_dialog = ExportDialog {
+                    open: true,
+                    format: preset.as_ref().map_or(NoteFormat::default(), |preset| preset.format),
+                    destination: preset.as_ref().map_or(String::new(), |preset| preset.destination.clone()),
+                    stylesheet: preset.as_ref().and_then(|preset| preset.stylesheet.clone()).unwrap_or_default(),
+                    include_attachments: preset.is_some_and(|preset| preset.include_attachments),
+                };
+            }
+            if let Some(preset) = &export_preset {
+                let hover = format!("Export as {} to {}", preset.format.extension(), preset.destination);
+                if ui.button("Export Again").on_hover_text(hover).clicked() {
+                    export_with = Some(preset.clone());
+                }
+            }
+            let has_selection = self.editor_selection.is_some();
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Add Todo from Selection"))
+                .clicked()
+            {
+                add_todo = true;
+            }
+
+            let selected_text = match &language {
+                Some(language) if language.detected => format!("{} (detected)", language.name()),
+                Some(language) => language.name().to_string(),
+                None => "Auto".to_string(),
+            };
+            egui::ComboBox::from_id_source("note_language")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    let auto = language.as_ref().map_or(true, |language| language.detected);
+                    if ui.selectable_label(auto, "Auto").clicked() {
+                        new_language = Some(None);
+                    }
+                    for (code, name) in LANGUAGES {
+                        let selected = !auto && language.as_ref().is_some_and(|language| language.code == *code);
+                        if ui.selectable_label(selected, *name).clicked() {
+                            new_language = Some(Some(*code));
+                        }
+                    }
+                });
+
+            egui::ComboBox::from_id_source("code_language")
+                .selected_text(match self.code_language.as_str() {
+                    "" => "Plain code",
+                    language => language,
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.code_language, String::new(), "Plain code");
+                    for language in CODE_LANGUAGES {
+                        ui.selectable_value(&mut self.code_language, language.to_string(), *language);
+                    }
+                })
+                .response
+                .on_hover_text("Language of code blocks that don't name one");
+        });
+        if let Some(code) = new_language {
+            buffer.content = lang::set_language(&buffer.content, code);
+            buffer.mark_edited(ui.input(|i| i.time));
+        }
+        for error in &buffer.schema_errors {
+            ui.colored_label(ui.visuals().error_fg_color, format!("⚠ {}", error))
+                .on_hover_text("Required by the schema of this folder");
+        }
+        if buffer.conflict.is_some() {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "This note was changed on disk while you were editing it.",
+                );
+                if ui.button("Keep mine").clicked() {
+                    buffer.keep_mine();
+                }
+                if ui.button("Load theirs").clicked() {
+                    buffer.take_theirs();
+                }
+            });
+        }
+
+        let mut deleted_todo = None;
+        let mut new_todo = None;
+        {
+            let todos = self.todos.lock().unwrap();
+            let owned = todos.for_note(&buffer.title);
+            egui::CollapsingHeader::new(format!("Todos ({})", owned.len()))
+                .id_source(("note_todos", &buffer.title))
+                .show(ui, |ui| {
+                    for (index, todo) in owned {
+                        ui.horizontal(|ui| {
+                            match todo.line {
+                                Some(line) => {
+                                    if ui.link(&todo.description).clicked() {
+                                        self.pending_jump = Some(line);
+                                    }
+                                }
+                                None => {
+                                    ui.label(&todo.description);
+                                }
+                            }
+                            if ui.button("Delete").clicked() {
+                                deleted_todo = Some(index);
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.new_note_todo).hint_text("New todo"));
+                        let description = self.new_note_todo.trim();
+                        if ui.add_enabled(!description.is_empty(), egui::Button::new("Add")).clicked() {
+                            new_todo = Some((buffer.title.clone(), description.to_string()));
+                        }
+                    });
+                });
+        }
+
+        let mut open_related = None;
+        if self.related_notes.as_ref().map_or(true, |(title, _)| *title != buffer.title) {
+            let related = self.search_index.related(&buffer.title, RELATED_NOTES);
+            self.related_notes = Some((buffer.title.clone(), related));
+        }
+        if let Some((_, related)) = self.related_notes.as_ref().filter(|(_, related)| !related.is_empty()) {
+            egui::TopBottomPanel::bottom("related_notes").show_inside(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ui.weak("Related:");
+                    for note in related {
+                        let hover = match note.shared_tags.is_empty() {
+                            true => "Shares words with this note".to_string(),
+                            false => format!("Also tagged #{}", note.shared_tags.join(" #")),
+                        };
+                        if ui.link(&note.title).on_hover_text(hover).clicked() {
+                            open_related = Some(note.title.clone());
+                        }
+                    }
+                });
+            });
+        }
+
+        // Jumping to a line needs the editor.
+        if jumping {
+            self.preview = false;
+        }
+        if self.preview {
+            egui::ScrollArea::vertical().show(ui, |ui| preview::show(ui, &buffer.content, &self.code_language));
+        } else {
+            let editor_id = Id::new("note_editor");
+            let jump_to = self
+                .pending_jump
+                .take()
+                .map(|line| {
+                    let index = char_index_of_line(&buffer.content, line);
+                    index..index
+                })
+                .or_else(|| self.pending_selection.take());
+            // Jumping somewhere wins over going back to where the note was left
+            let restore = self.restore_position.take().filter(|_| jump_to.is_none());
+            if let Some(position) = &restore {
+                let mut state = egui::TextEdit::load_state(ui.ctx(), editor_id).unwrap_or_default();
+                let cursor = egui::text::CCursor::new(position.cursor.min(buffer.content.chars().count()));
+                state.cursor.set_char_range(Some(egui::text::CCursorRange::one(cursor)));
+                state.store(ui.ctx(), editor_id);
+            }
+            if let Some(range) = &jump_to {
+                let mut state = egui::TextEdit::load_state(ui.ctx(), editor_id).unwrap_or_default();
+                state.cursor.set_char_range(Some(egui::text::CCursorRange::two(
+                    egui::text::CCursor::new(range.start),
+                    egui::text::CCursor::new(range.end),
+                )));
+                state.store(ui.ctx(), editor_id);
+                ui.ctx().memory_mut(|memory| memory.request_focus(editor_id));
+            }
+
+            // Play a macro from the cursor
+            if let Some(played) = self.pending_macro.take().and_then(|name| self.macros.get(&name)) {
+                let mut state = egui::TextEdit::load_state(ui.ctx(), editor_id).unwrap_or_default();
+                let cursor = state.cursor.char_range().map_or(0, |range| range.primary.index);
+                let (content, cursor) = played.replay(&buffer.content, cursor);
+                if content != buffer.content {
+                    buffer.content = content;
+                    buffer.mark_edited(ui.input(|i| i.time));
+                }
+                state
+                    .cursor
+                    .set_char_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(cursor))));
+                state.store(ui.ctx(), editor_id);
+                ui.ctx().memory_mut(|memory| memory.request_focus(editor_id));
+                macro_commands.extend(played.commands().map(str::to_string));
+            }
+            // Suggest note titles after `[[` and tags after `#`, using the cursor of the last frame
+            let cursor = egui::TextEdit::load_state(ui.ctx(), editor_id)
+                .and_then(|state| state.cursor.char_range())
+                .filter(|range| range.primary.index == range.secondary.index)
+                .map(|range| range.primary.index);
+            let trigger = cursor
+                .filter(|_| ui.memory(|memory| memory.has_focus(editor_id)))
+                .and_then(|cursor| complete::trigger(&buffer.content, byte_index(&buffer.content, cursor)))
+                .filter(|trigger| self.completion.dismissed != Some(trigger.start));
+            let suggestions: Vec<String> = match &trigger {
+                Some(trigger) => {
+                    let candidates = match trigger.kind {
+                        CompletionKind::Link => self.notes.lock().unwrap().items.clone(),
+                        CompletionKind::Tag => self.search_index.tags(),
+                    };
+                    fuzzy::rank(&trigger.query, &candidates, &self.recent_notes)
+                        .into_iter()
+                        .filter(|candidate| *candidate != trigger.query)
+                        .take(COMPLETIONS)
+                        .map(str::to_string)
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+            if let Some(trigger) = trigger.as_ref().filter(|trigger| trigger.query != self.completion.query) {
+                self.completion.query = trigger.query.clone();
+                self.completion.selected = 0;
+            }
+            let mut accepted = None;
+            if let Some(trigger) = trigger.as_ref().filter(|_| !suggestions.is_empty()) {
+                // Take the keys before the editor sees them
+                let (up, down, accept, escape) = ui.input_mut(|i| {
+                    (
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::Tab)
+                            || i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                        i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+                    )
+                });
+                let completion = &mut self.completion;
+                if up {
+                    completion.selected = completion.selected.saturating_sub(1);
+                }
+                if down {
+                    completion.selected += 1;
+                }
+                completion.selected = completion.selected.min(suggestions.len() - 1);
+                if accept {
+                    accepted = Some(suggestions[completion.selected].clone());
+                }
+                if escape {
+                    completion.dismissed = Some(trigger.start);
+                }
+            }
+
+            // Continue lists on Enter, nest them or move between table cells with Tab, and tick
+            // checkboxes with Cmd+Enter, unless the completion popup took the keys
+            let focused = ui.memory(|memory| memory.has_focus(editor_id));
+            if let Some(cursor) = cursor.filter(|_| focused && !read_only && suggestions.is_empty()) {
+                type Helper = dyn Fn(&str, usize) -> Option<Edit>;
+                let helpers: [(egui::Modifiers, egui::Key, &Helper); 4] = [
+                    (egui::Modifiers::SHIFT, egui::Key::Tab, &|content, cursor| {
+                        editing::format_table(content, cursor, -1).or_else(|| editing::indent(content, cursor, true))
+                    }),
+                    (egui::Modifiers::NONE, egui::Key::Tab, &|content, cursor| {
+                        editing::format_table(content, cursor, 1).or_else(|| editing::indent(content, cursor, false))
+                    }),
+                    (egui::Modifiers::COMMAND, egui::Key::Enter, &|content, cursor| {
+                        Some(editing::toggle_checkbox(content, cursor))
+                    }),
+                    (egui::Modifiers::NONE, egui::Key::Enter, &editing::continue_list),
+                ];
+                for (modifiers, key, helper) in helpers {
+                    if !ui.input(|i| i.key_pressed(key) && i.modifiers.matches_exact(modifiers)) {
+                        continue;
+                    }
+                    let Some(edit) = helper(&buffer.content, cursor) else {
+                        continue;
+                    };
+                    ui.input_mut(|i| i.consume_key(modifiers, key));
+                    buffer.content = edit.content;
+                    buffer.mark_edited(ui.input(|i| i.time));
+                    let mut state = egui::TextEdit::load_state(ui.ctx(), editor_id).unwrap_or_default();
+                    state
+                        .cursor
+                        .set_char_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(edit.cursor))));
+                    state.store(ui.ctx(), editor_id);
+                    break;
+                }
+            }
+
+            // Record what is typed into the editor, after the completion popup took its keys
+            if let Some(recording) = &mut self.macro_recording {
+                if ui.memory(|memory| memory.has_focus(editor_id)) {
+                    let steps = ui.input(|i| i.events.iter().filter_map(macros::step_from_event).collect::<Vec<_>>());
+                    recording.steps.extend(steps);
+                }
+            }
+
+            // Check the spelling with the dictionary of the note's language
+            if let Some(language) = &language {
+                self.dictionaries.load(language);
+            }
+            let dictionary = language.as_ref().and_then(|language| self.dictionaries.get(language));
+            let misspelled_stroke = egui::Stroke::new(1.0, ui.visuals().error_fg_color);
+            let align = match language.map(|language| language.direction()) {
+                Some(TextDirection::RightToLeft) => egui::Align::RIGHT,
+                _ => egui::Align::LEFT,
+            };
+            let mut theme = EditorTheme::from_style(ui.style());
+            if let Some(active) = &self.applied_theme {
+                theme = theme.with_font(active.editor_font_id(), active.line_spacing);
+            }
+            let code_language = &self.code_language;
+            // Mark the matches of the current search in the note
+            let search_matchers = match self.search_query.trim() {
+                "" => Vec::new(),
+                _ if self.search_options.in_title_only => Vec::new(),
+                query => self.search_options.matchers(query).unwrap_or_default(),
+            };
+            let match_color = ui.visuals().warn_fg_color.gamma_multiply(0.35);
+            let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                let mut job = highlight::cached_layout_job(ui.ctx(), &theme, text, code_language);
+                if let Some(ranges) = search::match_ranges(&search_matchers, text) {
+                    highlight::mark_ranges(&mut job, &ranges, match_color);
+                }
+                if let Some(dictionary) = dictionary {
+                    highlight::underline_ranges(&mut job, &dictionary.misspelled(text), misspelled_stroke);
+                }
+                job.wrap.max_width = wrap_width;
+                job.halign = align;
+                ui.fonts(|fonts| fonts.layout_job(job))
+            };
+            let mut scroll_area = egui::ScrollArea::vertical();
+            if let Some(position) = &restore {
+                scroll_area = scroll_area.vertical_scroll_offset(position.scroll);
+            }
+            let scrolled = scroll_area.show(ui, |ui| {
+                let output = egui::TextEdit::multiline(&mut buffer.content)
+                    .id(editor_id)
+                    .interactive(!read_only)
+                    .desired_width(f32::INFINITY)
+                    .horizontal_align(align)
+                    .layouter(&mut layouter)
+                    .show(ui);
+
+                if let Some(range) = jump_to {
+                    let cursor = output.galley.from_ccursor(egui::text::CCursor::new(range.start));
+                    let rect = output
+                        .galley
+                        .pos_from_cursor(&cursor)
+                        .translate(output.galley_pos.to_vec2());
+                    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                }
+                if output.response.changed() {
+                    self.editor_selection = None;
+                    buffer.mark_edited(ui.input(|i| i.time));
+                }
+                if let Some(cursor) = cursor.filter(|_| !suggestions.is_empty() && accepted.is_none()) {
+                    let position = output
+                        .galley
+                        .pos_from_cursor(&output.galley.from_ccursor(egui::text::CCursor::new(cursor)))
+                        .translate(output.galley_pos.to_vec2());
+                    egui::Area::new(Id::new("completion_popup"))
+                        .order(egui::Order::Foreground)
+                        .fixed_pos(position.left_bottom())
+                        .show(ui.ctx(), |ui| {
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                for (index, suggestion) in suggestions.iter().enumerate() {
+                                    let selected = index == self.completion.selected;
+                                    if ui.selectable_label(selected, suggestion).clicked() {
+                                        accepted = Some(suggestion.clone());
+                                    }
+                                }
+                            });
+                        });
+                }
+                // Typewriter scrolling keeps the line being written in the middle
+                if let (Some(zen), Some(range)) = (&mut self.zen, output.cursor_range) {
+                    if zen.cursor != Some(range.primary.ccursor.index) {
+                        zen.cursor = Some(range.primary.ccursor.index);
+                        let rect = output
+                            .galley
+                            .pos_from_cursor(&range.primary)
+                            .translate(output.galley_pos.to_vec2());
+                        ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                    }
+                    ui.add_space(ui.clip_rect().height() / 2.0);
+                }
+                if let Some(range) = output.cursor_range {
+                    let range = range.as_sorted_char_range();
+                    self.editor_selection = (!range.is_empty()).then(|| {
+                        let text: String = buffer.content.chars().skip(range.start).take(range.len()).collect();
+                        let line = buffer.content.chars().take(range.start).filter(|c| *c == '\n').count();
+                        (text, line)
+                    });
+                }
+            });
+            let caret = egui::TextEdit::load_state(ui.ctx(), editor_id)
+                .and_then(|state| state.cursor.char_range())
+                .map_or(0, |range| range.primary.index);
+            self.note_positions.insert(
+                buffer.title.clone(),
+                NotePosition {
+                    cursor: caret,
+                    scroll: scrolled.state.offset.y,
+                },
+            );
+
+            if let (Some(trigger), Some(cursor), Some(choice)) = (&trigger, cursor, accepted) {
+                let (content, end) = complete::apply(
+                    &buffer.content,
+                    trigger,
+                    byte_index(&buffer.content, cursor),
+                    &choice,
+                );
+                buffer.content = content;
+                buffer.mark_edited(ui.input(|i| i.time));
+                let index = buffer.content[..end].chars().count();
+                let mut state = egui::TextEdit::load_state(ui.ctx(), editor_id).unwrap_or_default();
+                state
+                    .cursor
+                    .set_char_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(index))));
+                state.store(ui.ctx(), editor_id);
+                ui.ctx().memory_mut(|memory| memory.request_focus(editor_id));
+            }
+        }
+
+        if add_todo {
+            self.create_todo_from_selection();
+        }
+        if toggle_zen {
+            self.toggle_zen(ui.ctx());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if toggle_recording {
+            self.toggle_audio_recording(ui.ctx());
+        }
+        #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+        if toggle_dictation {
+            self.toggle_dictation(ui.ctx());
+        }
+        if save {
+            self.save_active_note_to_disk();
+        }
+        if let Some(preset) = export_with {
+            self.export_active_note(&preset);
+        }
+        for command in macro_commands {
+            if let Err(err) = Command::parse(&command).and_then(|command| self.execute_command(command)) {
+                self.command_status = Some(err);
+            }
+        }
+        if let Some((note, description)) = new_todo {
+            self.create_note_todo(&note, &description);
+            self.new_note_todo.clear();
+        }
+        if let Some(index) = deleted_todo {
+            self.delete_todos(&[index]);
+        }
+        if let Some(title) = open_related {
+            self.open_note(&title);
+        }
+        if let Some(locked) = lock_change {
+            let result = self.execute_command(Command::SetLocked { title: None, locked });
+            self.command_status = Some(match result {
+                Ok(message) | Err(message) => message,
+            });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(change) = protection_change {
+            self.change_protection(change);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn change_protection(&mut self, change: ProtectionChange) {
+        if change == ProtectionChange::Protect {
+            self.passphrase_entry = PassphraseEntry {
+                protecting: true,
+                ..PassphraseEntry::default()
+            };
+            return;
+        }
+        self.save_active_note_to_disk();
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+        let title = buffer.title.clone();
+        let result = match change {
+            ProtectionChange::Relock => buffer.relock().map(|()| format!("Locked {}", title)),
+            _ => buffer.unprotect().map(|()| format!("Removed the passphrase of {}", title)),
+        };
+        if change == ProtectionChange::Remove && result.is_ok() {
+            if let Err(err) = self.search_index.update(&title, &buffer.content) {
+                log::error!("Failed to index {}: {}", title, err);
+            }
+            self.search_stale = true;
+        }
+        self.command_status = Some(result.unwrap_or_else(|err| format!("Failed to change {}: {}", title, err)));
+    }
+
+    // Asks for the passphrase to protect the open note with, twice to catch typos
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_protect_dialog(&mut self, ctx: &egui::Context) {
+        if !self.passphrase_entry.protecting {
+            return;
+        }
+        let mut open = true;
+        let mut protect = false;
+        let entry = &mut self.passphrase_entry;
+        egui::Window::new("Protect Note")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("The note is encrypted with the passphrase and can't be read without it.");
+                ui.add(
+                    egui::TextEdit::singleline(&mut entry.passphrase)
+                        .password(true)
+                        .hint_text("Passphrase"),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut entry.confirm)
+                        .password(true)
+                        .hint_text("Repeat the passphrase"),
+                );
+                ui.weak("Earlier versions in the history are deleted, since they are not encrypted.");
+                let ready = !entry.passphrase.is_empty() && entry.passphrase == entry.confirm;
+                if !entry.confirm.is_empty() && entry.passphrase != entry.confirm {
+                    ui.colored_label(ui.visuals().error_fg_color, "The passphrases differ");
+                }
+                if let Some(error) = &entry.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                protect = ui.add_enabled(ready, egui::Button::new("Protect")).clicked();
+            });
+        if !open {
+            self.passphrase_entry = PassphraseEntry::default();
+        }
+        if !protect {
+            return;
+        }
+        self.save_active_note_to_disk();
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+        let passphrase = std::mem::take(&mut self.passphrase_entry.passphrase);
+        let protected = buffer.protect(&passphrase).and_then(|()| {
+            let sealed = Notes::read_note_file(&buffer.title)?;
+            history::record(&buffer.title, &sealed, HistoryRetention::None)?;
+            self.search_index.update(&buffer.title, "")
+        });
+        match protected {
+            Ok(()) => {
+                self.search_stale = true;
+                self.passphrase_entry = PassphraseEntry::default();
+                self.command_status = Some(format!("Protected {} with a passphrase", buffer.title));
+            }
+            Err(err) => self.passphrase_entry.error = Some(format!("Failed to protect the note: {}", err)),
+        }
+    }
+
+    // Save the open note once no edits were made for the autosave interval of its folder
+    fn autosave(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|i| i.time);
+        let remaining = self
+            .buffer
+            .as_ref()
+            .and_then(|buffer| {
+                let delay = buffer.settings.autosave_secs.unwrap_or(self.config.autosave_secs);
+                buffer.autosave_in(now, delay)
+            });
+        match remaining {
+            Some(remaining) if remaining <= 0.0 => self.save_active_note_to_disk(),
+            Some(remaining) => ctx.request_repaint_after(std::time::Duration::from_secs_f64(remaining)),
+            None => {}
+        }
+
+        let focused = ctx.input(|i| i.viewport().focused).unwrap_or(true);
+        if self.window_focused && !focused && self.config.save_on_focus_loss {
+            self.save_active_note_to_disk();
+        }
+        self.window_focused = focused;
+    }
+
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let title = match &self.buffer {
+            Some(buffer) if buffer.is_dirty() => format!("● {} - Notes", buffer.title),
+            Some(buffer) => format!("{} - Notes", buffer.title),
+            None => "Notes".to_string(),
+        };
+        let title = match &self.active_vault {
+            Some(name) => format!("{} [{}]", title, name),
+            None => title,
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    fn save_active_note_to_disk(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        if let Some(buffer) = &mut self.buffer {
+            // Unresolved conflicts must not be overwritten, nor locked notes.
+            if buffer.is_dirty() && buffer.conflict.is_none() && !buffer.is_locked() {
+                if let Err(err) = buffer.save() {
+                    log::error!("Failed to save {}: {}", buffer.title, err);
+                    return;
+                }
+                // Only the titles of notes protected with a passphrase are indexed
+                let indexed = if buffer.is_protected() { "" } else { buffer.content.as_str() };
+                if let Err(err) = self.search_index.update(&buffer.title, indexed) {
+                    log::error!("Failed to index {}: {}", buffer.title, err);
+                }
+                self.search_stale = true;
+                self.related_notes = None;
+                if self.config.activity_log && self.safe_mode.is_none() {
+                    if let Err(err) = activity::record(ActivityKind::Edited, &buffer.title) {
+                        log::error!("Failed to log activity: {}", err);
+                    }
+                }
+                let retention = buffer.settings.history.unwrap_or(self.config.history);
+                // and their history is kept encrypted
+                let recorded = if buffer.is_protected() {
+                    Notes::read_note_file(&buffer.title)
+                } else {
+                    Ok(buffer.content.clone())
+                };
+                if let Err(err) = recorded.and_then(|content| history::record(&buffer.title, &content, retention)) {
+                    log::error!("Failed to record history of {}: {}", buffer.title, err);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_note_events(&mut self) {
+        let events = match &self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return,
+        };
+        if events.is_empty() {
+            return;
+        }
+        for event in events {
+            match event {
+                NoteEvent::Changed(title) => {
+                    {
+                        let mut notes = self.notes.lock().unwrap();
+                        if !notes.items.contains(&title) {
+                            notes.add(title.clone());
+                        }
+                    }
+                    if let Some(buffer) = self.buffer.as_mut().filter(|buffer| buffer.title == title) {
+                        if let Err(err) = buffer.reload() {
+                            log::error!("Failed to reload {}: {}", title, err);
+                        }
+                    }
+                }
+                NoteEvent::Removed(title) => {
+                    self.notes.lock().unwrap().items.retain(|note| note != &title);
+                    // Unsaved edits are kept; saving them recreates the note.
+                    if self
+                        .buffer
+                        .as_ref()
+                        .is_some_and(|buffer| buffer.title == title && !buffer.is_dirty())
+                    {
+                        self.buffer = None;
+                        self.selected_note = None;
+                    }
+                }
+            }
+        }
+        self.sort_notes();
+        self.refresh_tasks();
+        self.refresh_search();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_print_window(&mut self, ctx: &egui::Context) {
+        let Some(buffer) = &self.buffer else {
+            self.print_dialog_open = false;
+            return;
+        };
+        let options = &mut self.print_options;
+        let mut open = self.print_dialog_open;
+        let mut print_to = None;
+        egui::Window::new("Print Note").open(&mut open).show(ctx, |ui| {
+            egui::Grid::new("print_fields").num_columns(2).show(ui, |ui| {
+                ui.label("Header");
+                ui.add(egui::TextEdit::singleline(&mut options.header).hint_text("None"));
+                ui.end_row();
+                ui.label("Footer");
+                ui.add(egui::TextEdit::singleline(&mut options.footer).hint_text("None"));
+                ui.end_row();
+            });
+            ui.weak("{title}, {date}, {page} and {pages} are filled in on every page.");
+            if ui.button("Reset").clicked() {
+                *options = PrintOptions::default();
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Print…").on_hover_text("Opens the print dialog of your browser").clicked() {
+                    print_to = Some(false);
+                }
+                if ui.button("Save as PDF").clicked() {
+                    print_to = Some(true);
+                }
+            });
+        });
+        self.print_dialog_open = open && print_to.is_none();
+        let Some(as_pdf) = print_to else {
+            return;
+        };
+        let (title, content) = (&buffer.title, &buffer.content);
+        // Without a browser to print from, a PDF can still be printed with another program
+        let printed = if as_pdf {
+            Err(None)
+        } else {
+            print::print(title, content, &self.print_options).map_err(|err| {
+                log::warn!("Failed to open {} in the browser: {}", title, err);
+                Some(err)
+            })
+        };
+        self.command_status = Some(match printed {
+            Ok(()) => format!("Opened {} in the browser to print it", title),
+            Err(err) => match print::save_pdf(title, content, &self.print_options) {
+                Ok(path) if err.is_some() => format!("No browser to print from; saved {} as {}", title, path.display()),
+                Ok(path) => format!("Saved {} as {}", title, path.display()),
+                Err(err) => format!("Failed to print {}: {}", title, err),
+            },
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_clip_dialog(&mut self, ctx: &egui::Context) {
+        let finished = self.clip_dialog.job.as_ref().and_then(|job| job.try_recv().ok());
+        match finished {
+            Some(Ok(title)) => {
+                self.clip_dialog = ClipDialog::default();
+                {
+                    let mut notes = self.notes.lock().unwrap();
+                    if !notes.items.contains(&title) {
+                        notes.add(title.clone());
+                    }
+                }
+                self.sort_notes();
+                self.open_note(&title);
+            }
+            Some(Err(err)) => {
+                self.clip_dialog.job = None;
+                self.clip_dialog.error = Some(err.to_string());
+            }
+            None => {}
+        }
+
+        let dialog = &mut self.clip_dialog;
+        let mut start_clip = false;
+        egui::Window::new("New Note from URL")
+            .open(&mut dialog.open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.add(egui::TextEdit::singleline(&mut dialog.url).hint_text("https://"));
+                if dialog.job.is_some() {
+                    ui.spinner();
+                } else if ui.button("Clip").clicked() {
+                    start_clip = true;
+                }
+                if let Some(err) = &dialog.error {
+                    ui.colored_label(ui.visuals().error_fg_color, err);
+                }
+            });
+
+        if start_clip {
+            // Fetching can take a while, so it happens off the UI thread.
+            let url = dialog.url.clone();
+            let ctx = ctx.clone();
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(clip::note_from_url(&url));
+                ctx.request_repaint();
+            });
+            dialog.job = Some(receiver);
+            dialog.error = None;
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_ai_window(&mut self, ctx: &egui::Context) {
+        if let Some(request) = self.ai_dialog.pending.take() {
+            // Models take a while to answer, so they are asked off the UI thread
+            let settings = self.config.ai.clone();
+            let sent = request.clone();
+            let ctx = ctx.clone();
+            let (sender, receiver) = channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(ai::provider(&settings).and_then(|provider| sent.run(provider.as_ref())));
+                ctx.request_repaint();
+            });
+            self.ai_dialog.job = Some((request, receiver));
+        }
+        let finished = self.ai_dialog.job.as_ref().and_then(|(_, receiver)| receiver.try_recv().ok());
+        if let Some(result) = finished {
+            if let Some((request, _)) = self.ai_dialog.job.take() {
+                self.ai_dialog.answer = Some((request, result.map_err(|err| err.to_string())));
+            }
+        }
+
+        let dialog = &mut self.ai_dialog;
+        let mut ask = false;
+        let mut insert = None;
+        let mut open_source = None;
+        egui::Window::new("AI").open(&mut dialog.open).collapsible(false).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let response = ui.add(egui::TextEdit::singleline(&mut dialog.question).hint_text("Ask your notes…"));
+                let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.add_enabled(dialog.job.is_none(), egui::Button::new("Ask")).clicked() || submitted {
+                    ask = true;
+                }
+            });
+            if let Some((request, _)) = &dialog.job {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(request.label());
+                });
+                return;
+            }
+            let Some((request, answer)) = &dialog.answer else {
+                return;
+            };
+            ui.separator();
+            ui.strong(request.label());
+            match answer {
+                Ok(answer) => {
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        ui.add(egui::Label::new(&answer.text).selectable(true));
+                    });
+                    if !answer.sources.is_empty() {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.weak("From");
+                            for source in &answer.sources {
+                                if ui.link(source).clicked() {
+                                    open_source = Some(source.clone());
+                                }
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Copy").clicked() {
+                            ui.output_mut(|output| output.copied_text = answer.text.clone());
+                        }
+                        if matches!(request, ai::Request::Summarize { .. }) && ui.button("Insert into Note").clicked() {
+                            insert = Some(answer.text.clone());
+                        }
+                    });
+                }
+                Err(err) => {
+                    ui.colored_label(ui.visuals().error_fg_color, err);
+                }
+            }
+        });
+
+        if ask && !dialog.question.trim().is_empty() {
+            let question = dialog.question.trim().to_string();
+            if let Err(err) = self.execute_command(Command::Ask { question }) {
+                self.command_status = Some(err);
+            }
+        }
+        if let (Some(text), Some(buffer)) = (insert, &mut self.buffer) {
+            insert_at_cursor(ctx, buffer, &text);
+        }
+        if let Some(title) = open_source {
+            self.open_note(&title);
+        }
+    }
+
+    fn show_backup_window(&mut self, ctx: &egui::Context) {
+        let dialog = &mut self.backup_dialog;
+        let mut open = dialog.open;
+        let mut command = None;
+        let mut preview = false;
+        egui::Window::new("Back Up and Restore").open(&mut open).show(ctx, |ui| {
+            ui.heading("Back up");
+            ui.horizontal(|ui| {
+                ui.label("Folder");
+                ui.add(egui::TextEdit::singleline(&mut dialog.destination).hint_text(backup::DEFAULT_BACKUP_DIR));
+            });
+            if ui.button("Back Up Now").clicked() {
+                let destination = dialog.destination.trim();
+                let destination = if destination.is_empty() { backup::DEFAULT_BACKUP_DIR } else { destination };
+                command = Some(Command::Backup {
+                    dir: commands::expand_home(destination),
+                });
+            }
+            ui.weak("Notes, todos, attachments and settings are saved in one zip file.");
+
+            ui.separator();
+            ui.heading("Restore");
+            ui.horizontal(|ui| {
+                ui.label("Backup");
+                if ui
+                    .add(egui::TextEdit::singleline(&mut dialog.file).hint_text("notes-backup-….zip"))
+                    .changed()
+                {
+                    dialog.preview = None;
+                }
+            });
+            if ui
+                .checkbox(&mut dialog.merge, "Merge: keep notes changed or added since the backup")
+                .changed()
+            {
+                dialog.preview = None;
+            }
+            let file = dialog.file.trim();
+            match &dialog.preview {
+                None => {
+                    if ui.add_enabled(!file.is_empty(), egui::Button::new("Preview")).clicked() {
+                        preview = true;
+                    }
+                }
+                Some(changes) => {
+                    ui.label(format!("{} files differ from the backup:", changes.len()));
+                    egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                        for change in changes {
+                            ui.label(format!("{} ({})", change.path.display(), change.action.label()));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Restore").clicked() {
+                            command = Some(Command::Restore {
+                                path: commands::expand_home(file),
+                                options: RestoreOptions {
+                                    dry_run: false,
+                                    merge: dialog.merge,
+                                },
+                            });
+                        }
+                        if ui.button("Cancel").clicked() {
+                            dialog.preview = None;
+                        }
+                    });
+                }
+            }
+            if let Some(err) = &dialog.error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+        });
+        dialog.open = open;
+
+        if preview {
+            let options = RestoreOptions {
+                dry_run: true,
+                merge: dialog.merge,
+            };
+            match backup::restore(&commands::expand_home(dialog.file.trim()), options) {
+                Ok(changes) => {
+                    dialog.preview = Some(changes);
+                    dialog.error = None;
+                }
+                Err(err) => dialog.error = Some(format!("Failed to read the backup: {}", err)),
+            }
+        }
+        if let Some(command) = command {
+            let restoring = matches!(command, Command::Restore { .. });
+            match self.execute_command(command) {
+                Ok(message) => {
+                    if restoring {
+                        self.backup_dialog = BackupDialog::default();
+                    } else {
+                        self.backup_dialog.error = None;
+                    }
+                    self.command_status = Some(message);
+                }
+                Err(err) => self.backup_dialog.error = Some(err),
+            }
+        }
+    }
+}
+
+impl eframe::App for TemplateApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // Only remember positions in notes that are still among the recent ones of some vault
+        let recent = &self.recent_notes;
+        let vault_recent = &self.vault_recent_notes;
+        self.note_positions.retain(|title, _| {
+            recent.contains(title) || vault_recent.values().any(|notes| notes.contains(title))
+        });
+        // Keep the saved state of the last normal session until safe mode is left
+        if self.safe_mode.is_none() {
+            eframe::set_value(storage, eframe::APP_KEY, self);
+        }
+        if self.is_read_only() {
+            return;
+        }
+        if let Err(err) = self.search_index.save() {
+            log::error!("Failed to save the search index: {}", err);
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_active_note_to_disk();
+        if self.is_read_only() {
+            return;
+        }
+        if let Err(err) = self.search_index.save() {
+            log::error!("Failed to save the search index: {}", err);
+        }
+        if let Err(err) = safe_mode::end_session() {
+            log::error!("Failed to record the end of the session: {}", err);
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let wanted_theme = self.active_theme();
+        if wanted_theme != self.applied_theme {
+            theme::apply(ctx, wanted_theme.as_ref());
+            self.applied_theme = wanted_theme;
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&keymap::shortcut(&self.config.keymap, Action::Save))) {
+            self.save_active_note_to_disk();
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&keymap::shortcut(&self.config.keymap, Action::NewNote))) {
+            ctx.memory_mut(|memory| memory.request_focus(egui::Id::new(NEW_NOTE_TITLE_ID)));
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&keymap::shortcut(&self.config.keymap, Action::Search))) {
+            ctx.memory_mut(|memory| memory.request_focus(egui::Id::new(SEARCH_QUERY_ID)));
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&keymap::shortcut(&self.config.keymap, Action::ToggleTodos))) {
+            self.todos_panel_open = !self.todos_panel_open;
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&keymap::shortcut(&self.config.keymap, Action::Zen))) {
+            self.toggle_zen(ctx);
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&keymap::shortcut(&self.config.keymap, Action::RepeatCommand))) {
+            self.repeat_command();
+        }
+        self.show_quick_open(ctx);
+        self.handle_navigation(ctx);
+        let replace_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::H);
+        if ctx.input_mut(|i| i.consume_shortcut(&replace_shortcut)) {
+            self.replace_dialog.open = !self.replace_dialog.open;
+        }
+        // Keys bound to macros play them on the open note
+        if self.buffer.is_some() && self.macro_recording.is_none() && self.safe_mode.is_none() {
+            for bound in &self.macros.items {
+                let Some(key) = bound.key.as_deref().and_then(egui::Key::from_name) else {
+                    continue;
+                };
+                if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key)) {
+                    self.pending_macro = Some(bound.name.clone());
+                }
+            }
+        }
+        self.handle_dropped_files(ctx);
+        self.autosave(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.handle_note_events();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.poll_ipc(ctx);
+            self.handle_tray(ctx);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(name) = self.pending_vault_switch.take() {
+            let result = self.switch_vault(ctx, name);
+            self.command_status = Some(match result {
+                Ok(message) | Err(message) => message,
+            });
+        }
+        if let Some(vault) = &mut self.vault {
+            if let Err(err) = vault.heartbeat() {
+                log::error!("Failed to refresh the lock of the notes directory: {}", err);
+            }
+            ctx.request_repaint_after(std::time::Duration::from_secs(vault::HEARTBEAT_SECS));
+        }
+        self.show_read_only_bar(ctx);
+        self.show_safe_mode_bar(ctx);
+        if self.zen.is_some() {
+            CentralPanel::default().show(ctx, |ui| {
+                let margin = ((ui.available_width() - ZEN_LINE_WIDTH) / 2.0).max(0.0);
+                egui::Frame::none()
+                    .inner_margin(egui::Margin::symmetric(margin, 0.0))
+                    .show(ui, |ui| self.show_editor(ui));
+            });
+            self.show_windows(ctx);
+            return;
+        }
+        TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                let is_web = cfg!(target_arch = "wasm32");
+                let mut vault_command = None;
+                if !is_web {
+                    ui.menu_button("File", |ui| {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        ui.menu_button("Switch Vault", |ui| {
+                            if ui.selectable_label(self.active_vault.is_none(), vault_label(None)).clicked() {
+                                vault_command = Some(Command::SwitchVault { name: None });
+                                ui.close_menu();
+                            }
+                            for entry in &self.vaults {
+                                let active = self.active_vault.as_ref() == Some(&entry.name);
+                                if ui.selectable_label(active, &entry.name).on_hover_text(&entry.path).clicked() {
+                                    vault_command = Some(Command::SwitchVault {
+                                        name: Some(entry.name.clone()),
+                                    });
+                                    ui.close_menu();
+                                }
+                            }
+                            if self.vaults.is_empty() {
+                                ui.weak("Add vaults with `add-vault <name> <path>` in the command bar.");
+                            }
+                        });
+                        ui.separator();
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui.button("New Note from URL…").clicked() {
+                            self.clip_dialog.open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Today's Note").clicked() {
+                            self.open_todays_note();
+                            ui.close_menu();
+                        }
+                        if ui.button("Calendar…").clicked() {
+                            self.calendar_month = Some(chrono::Local::now().date_naive());
+                            ui.close_menu();
+                        }
+                        if ui.button("Reading List…").clicked() {
+                            self.refresh_reading_list();
+                            self.reading_window_open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Find and Replace…").clicked() {
+                            self.replace_dialog.open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Find by Date…").clicked() {
+                            self.date_query.open = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("Statistics…").clicked() {
+                            match stats::collect() {
+                                Ok(metrics) => self.vault_stats = Some(VaultStats::from_metrics(&metrics)),
+                                Err(err) => self.command_status = Some(format!("Failed to collect statistics: {}", err)),
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.button("Reports…").clicked() {
+                            self.show_report(chrono::Local::now().date_naive());
+                            ui.close_menu();
+                        }
+                        if ui.button("Verify Notes…").clicked() {
+                            self.verify_notes();
+                            ui.close_menu();
+                        }
+                        if ui.button("Insights…").clicked() {
+                            let insights = activity::load().and_then(|log| {
+                                let now = chrono::Local::now().timestamp();
+                                Ok(Insights::compute(&log, &stats::collect()?, now))
+                            });
+                            match insights {
+                                Ok(insights) => self.insights = Some(insights),
+                                Err(err) => self.command_status = Some(format!("Failed to compute insights: {}", err)),
+                            }
+                            ui.close_menu();
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            if ui.add_enabled(self.buffer.is_some(), egui::Button::new("Print…")).clicked() {
+                                self.print_dialog_open = true;
+                                ui.close_menu();
+                            }
+                            if ui.button("Back Up…").clicked() {
+                                self.backup_dialog.open = true;
+                                ui.close_menu();
+                            }
+                            if ui
+                                .add_enabled(!self.is_read_only(), egui::Button::new("Restore…"))
+                                .on_disabled_hover_text("Another window is using the notes directory")
+                                .clicked()
+                            {
+                                self.backup_dialog.open = true;
+                                ui.close_menu();
+                            }
+                        }
+                        if ui.button("Scheduled Jobs…").clicked() {
+                            self.jobs_window_open = true;
+                            ui.close_menu();
+                        }
+                        if ui
+                            .add_enabled(self.safe_mode.is_none(), egui::Button::new("Preferences…"))
+                            .on_disabled_hover_text("Settings are off in safe mode")
+                            .clicked()
+                        {
+                            self.preferences = Some(self.config.clone());
+                            ui.close_menu();
+                        }
+                        if ui.button("Quit").clicked() {
+                            #[cfg(not(target_arch = "wasm32"))]
+                            {
+                                self.quitting = true;
+                            }
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                    });
+                    ui.add_space(16.0);
+                }
+                if let Some(command) = vault_command {
+                    let result = self.execute_command(command);
+                    self.command_status = Some(match result {
+                        Ok(message) | Err(message) => message,
+                    });
+                }
+                let mut macro_command = None;
+                ui.menu_button("Macros", |ui| {
+                    match &self.macro_recording {
+                        Some(recording) => {
+                            if ui.button(format!("Stop Recording {}", recording.name)).clicked() {
+                                macro_command = Some(Command::StopRecording);
+                                ui.close_menu();
+                            }
+                        }
+                        None => {
+                            if ui.button("Record New Macro").clicked() {
+                                macro_command = Some(Command::RecordMacro {
+                                    name: format!("macro-{}", self.macros.items.len() + 1),
+                                });
+                                ui.close_menu();
+                            }
+                        }
+                    }
+                    ui.separator();
+                    if self.macros.items.is_empty() {
+                        ui.weak("No macros yet. Name one with `record <name>` in the command bar.");
+                    }
+                    for recorded in &self.macros.items {
+                        let label = match &recorded.key {
+                            Some(key) => format!("{} ({})", recorded.name, key),
+                            None => recorded.name.clone(),
+                        };
+                        ui.menu_button(label, |ui| {
+                            if ui.button("Play on Open Note").clicked() {
+                                macro_command = Some(Command::PlayMacro {
+                                    name: recorded.name.clone(),
+                                    folder: None,
+                                });
+                                ui.close_menu();
+                            }
+                            if ui.button("Play on All Notes").clicked() {
+                                macro_command = Some(Command::PlayMacro {
+                                    name: recorded.name.clone(),
+                                    folder: Some(String::new()),
+                                });
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                });
+                if let Some(command) = macro_command {
+                    let result = self.execute_command(command);
+                    self.command_status = Some(match result {
+                        Ok(message) | Err(message) => message,
+                    });
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if self.config.ai.enabled {
+                    let mut ai_command = None;
+                    ui.menu_button("AI", |ui| {
+                        let has_note = self.buffer.is_some();
+                        if ui.add_enabled(has_note, egui::Button::new("Summarize Note")).clicked() {
+                            ai_command = Some(Command::Summarize);
+                            ui.close_menu();
+                        }
+                        if ui.add_enabled(has_note, egui::Button::new("Generate Title")).clicked() {
+                            ai_command = Some(Command::GenerateTitle);
+                            ui.close_menu();
+                        }
+                        if ui.button("Ask My Notes…").clicked() {
+                            self.ai_dialog.open = true;
+                            ui.close_menu();
+                        }
+                    });
+                    if let Some(command) = ai_command {
+                        let result = self.execute_command(command);
+                        self.command_status = Some(match result {
+                            Ok(message) | Err(message) => message,
+                        });
+                    }
+                }
+                let todos_shortcut = keymap::shortcut(&self.config.keymap, Action::ToggleTodos);
+                ui.toggle_value(&mut self.todos_panel_open, "Todos")
+                    .on_hover_text(format!("Show or hide the todos ({})", ui.ctx().format_shortcut(&todos_shortcut)));
+                ui.add_space(16.0);
+                #[cfg(not(target_arch = "wasm32"))]
+                self.show_sync_indicator(ui);
+                if self.applied_theme.is_none() {
+                    egui::widgets::global_dark_light_mode_buttons(ui);
+                }
+            });
+        });
+
+        SidePanel::left("left_panel").show(ctx, |ui| {
+            ui.heading("Notes");
+            let previous_sort = self.note_sort;
+            egui::ComboBox::from_label("Sort")
+                .selected_text(self.note_sort.label())
+                .show_ui(ui, |ui| {
+                    for sort in NoteSort::ALL {
+                        ui.selectable_value(&mut self.note_sort, sort, sort.label());
+                    }
+                });
+            if self.note_sort != previous_sort {
+                self.sort_notes();
+            }
+
+            let mut moved_note = None;
+            let mut note_command = None;
+            let mut merged_notes = None;
+            let mut opened_note = self.show_search(ui);
+            if self.search_query.trim().is_empty() && self.recent_notes.len() > 1 {
+                egui::CollapsingHeader::new("Recent")
+                    .id_source("recent_notes")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        // The first one is open already
+                        for note in self.recent_notes.iter().skip(1).take(LISTED_RECENT_NOTES) {
+                            if ui.button(note).clicked() {
+                                opened_note = Some(note.clone());
+                            }
+                        }
+                    });
+            }
+            if self.search_query.trim().is_empty() {
+                let notes = self.notes.lock().unwrap();
+                let selected: Vec<String> =
+                    notes.items.iter().filter(|note| self.note_selection.contains(note)).cloned().collect();
+                for (index, note) in notes.items.iter().enumerate() {
+                    let button = egui::Button::new(note).selected(self.note_selection.contains(note));
+                    let response = if self.note_sort == NoteSort::Manual {
+                        // Notes can be dragged onto each other to rearrange them.
+                        let item = ui.dnd_drag_source(Id::new(("note", note)), index, |ui| ui.add(button));
+                        if let Some(from) = item.response.dnd_release_payload::<usize>() {
+                            moved_note = Some((*from, index));
+                        }
+                        item.inner
+                    } else {
+                        ui.add(button)
+                    };
+                    // Ctrl or Cmd and Shift select notes to act on all at once instead of opening them
+                    if response.clicked() {
+                        let modifiers = ui.input(|i| i.modifiers);
+                        if !self.note_selection.click(note, modifiers.command, modifiers.shift, &notes.items) {
+                            opened_note = Some(note.clone());
+                        }
+                    }
+                    response.context_menu(|ui| {
+                        show_note_menu(ui, note, &selected, &mut note_command, &mut merged_notes)
+                    });
+                }
+            }
+            let mut viewed_archived = None;
+            if !self.archived_notes.is_empty() {
+                egui::CollapsingHeader::new(format!("Archived ({})", self.archived_notes.len()))
+                    .id_source("archived_notes")
+                    .show(ui, |ui| {
+                        for note in &self.archived_notes {
+                            if ui.button(note).clicked() {
+                                viewed_archived = Some(note.clone());
+                            }
+                        }
+                    });
+            }
+            if let Some(title) = viewed_archived {
+                self.view_archived(&title);
+            }
+            if let Some((from, to)) = moved_note {
+                self.move_note(from, to);
+            }
+            if !self.note_selection.is_empty() {
+                self.show_bulk_actions(ui);
+            }
+            if let Some(command) = note_command {
+                let result = self.execute_command(command);
+                self.command_status = Some(match result {
+                    Ok(message) | Err(message) => message,
+                });
+            }
+            if let Some(titles) = merged_notes {
+                self.merge_dialog = MergeDialog {
+                    target: titles[0].clone(),
+                    titles,
+                };
+            }
+            if let Some(title) = opened_note {
+                self.open_note(&title);
+            }
+
+            let title = match self.new_note_title.trim() {
+                "" => "New Note".to_string(),
+                title => title.to_string(),
+            };
+            let title_error = title::validate(&title).err();
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.new_note_title)
+                        .id(egui::Id::new(NEW_NOTE_TITLE_ID))
+                        .hint_text("folder/title"),
+                );
+                if ui.add_enabled(title_error.is_none(), egui::Button::new("Create Note")).clicked() {
+                    let template = self.new_note_template.clone();
+                    self.create_note(&title, "This is a new note.", template.as_deref());
+                    self.new_note_title.clear();
+                }
+            });
+            if let Some(err) = title_error {
+                ui.colored_label(ui.visuals().error_fg_color, format!("Invalid title: {}", err));
+                let suggestion = title::sanitize(&title);
+                if ui.button(format!("Use “{}”", suggestion)).clicked() {
+                    self.new_note_title = suggestion;
+                }
+            }
+            egui::ComboBox::from_label("Template")
+                .selected_text(self.new_note_template.as_deref().unwrap_or("Folder default"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.new_note_template, None, "Folder default");
+                    for template in templates::list().unwrap_or_default() {
+                        ui.selectable_value(&mut self.new_note_template, Some(template.clone()), template);
+                    }
+                });
+            if let Some(selected_note) = self.selected_note.clone() {
+                ui.horizontal(|ui| {
+                    if ui.button("Archive Note").clicked() {
+                        self.archive_note(&selected_note);
+                    }
+                    if ui.button("Delete Note").clicked() {
+                        self.request_delete(vec![selected_note.clone()]);
+                    }
+                });
+            }
+        });
+
+        SidePanel::right("right_panel").show_animated(ctx, self.todos_panel_open, |ui| {
+            ui.heading("Todos");
+            self.show_todo_stats(ui);
+            ui.horizontal(|ui| {
+                for view in TodoView::ALL {
+                    ui.selectable_value(&mut self.todo_view, view, view.label());
+                }
+            });
+            let board = self.todo_view == TodoView::Board;
+            if !board {
+                egui::ComboBox::from_label("Group by")
+                    .selected_text(self.todo_grouping.label())
+                    .show_ui(ui, |ui| {
+                        for grouping in TodoGrouping::ALL {
+                            ui.selectable_value(&mut self.todo_grouping, grouping, grouping.label());
+                        }
+                    });
+            }
+            let projects: Vec<String> = self.todos.lock().unwrap().projects().into_iter().map(str::to_string).collect();
+            egui::ComboBox::from_label("Project")
+                .selected_text(self.todo_project.as_deref().unwrap_or("All projects"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.todo_project, None, "All projects");
+                    for project in projects {
+                        ui.selectable_value(&mut self.todo_project, Some(project.clone()), project);
+                    }
+                });
+            if !board {
+                egui::ComboBox::from_label("Sort todos")
+                    .selected_text(self.todo_sort.label())
+                    .show_ui(ui, |ui| {
+                        for sort in TodoSort::ALL {
+                            ui.selectable_value(&mut self.todo_sort, sort, sort.label());
+                        }
+                    });
+            }
+            ui.horizontal(|ui| {
+                let mut selecting = self.todo_selection.is_some();
+                if ui.toggle_value(&mut selecting, "Select").on_hover_text("Pick todos to act on at once").changed() {
+                    self.todo_selection = selecting.then(BTreeSet::new);
+                }
+                if ui.button("Clear completed").on_hover_text("Move the completed todos to the archive").clicked() {
+                    self.clear_completed_todos();
+                }
+            });
+            if let Some(selection) = &self.todo_selection {
+                let selected: Vec<usize> = selection.iter().copied().collect();
+                let mut bulk = None;
+                ui.horizontal(|ui| {
+                    ui.strong(format!("{} selected", selected.len()));
+                    ui.add_enabled_ui(!selected.is_empty(), |ui| {
+                        if ui.button("Complete").clicked() {
+                            bulk = Some(false);
+                        }
+                        if ui.button("Delete").clicked() {
+                            bulk = Some(true);
+                        }
+                    });
+                });
+                match bulk {
+                    Some(true) => self.delete_todos(&selected),
+                    Some(false) => {
+                        self.complete_todos(&selected);
+                        self.todo_selection = Some(BTreeSet::new());
+                    }
+                    None => {}
+                }
+            }
+            let mut actions = TodoActions {
+                selection: self.todo_selection.take(),
+                ..TodoActions::default()
+            };
+            if let Some(day) = self.todo_due_filter {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Due on {}", day.format("%Y-%m-%d")));
+                    if ui.small_button("✖").on_hover_text("Show all todos").clicked() {
+                        self.todo_due_filter = None;
+                    }
+                });
+            }
+            let now = chrono::Utc::now().timestamp();
+            {
+                let todos = self.todos.lock().unwrap();
+                let project = self.todo_project.as_deref();
+                let due_on = self.todo_due_filter;
+                let visible = |todo: &Todo| {
+                    project.map_or(true, |project| todo.project.as_deref() == Some(project))
+                        && due_on.map_or(true, |day| todo.due_day() == Some(day))
+                };
+                if board {
+                    show_todo_board(ui, &todos, &visible, now, &self.config, &mut self.todo_edit, &mut actions);
+                } else {
+                    let groups = match self.todo_grouping {
+                        TodoGrouping::None => None,
+                        TodoGrouping::Note => Some((todos.grouped_by_note(), "No note")),
+                        TodoGrouping::Project => Some((todos.grouped_by_project(), "No project")),
+                    };
+
+                    // Todos can be dragged onto each other to rearrange them, unless they are sorted otherwise
+                    let sort = self.todo_sort;
+                    let draggable = sort == TodoSort::Manual;
+                    match groups {
+                        Some((groups, ungrouped)) => {
+                            for (group, items) in groups {
+                                let mut items: Vec<(usize, &Todo)> =
+                                    items.into_iter().filter(|(_, todo)| visible(todo)).collect();
+                                if items.is_empty() {
+                                    continue;
+                                }
+                                todos::sort_entries(&mut items, sort);
+                                egui::CollapsingHeader::new(group.unwrap_or(ungrouped))
+                                    .id_source(("todo_group", self.todo_grouping.label(), group))
+                                    .default_open(true)
+                                    .show(ui, |ui| {
+                                        for (index, todo) in items {
+                                            let badge = badge(todo, now, &self.config);
+                                            let edit = &mut self.todo_edit;
+                                            show_todo(ui, index, todo, badge, draggable, edit, &mut actions);
+                                        }
+                                    });
+                            }
+                        }
+                        None => {
+                            let mut items: Vec<(usize, &Todo)> =
+                                todos.items.iter().enumerate().filter(|(_, todo)| visible(todo)).collect();
+                            todos::sort_entries(&mut items, sort);
+                            for (index, todo) in items {
+                                let badge = badge(todo, now, &self.config);
+                                show_todo(ui, index, todo, badge, draggable, &mut self.todo_edit, &mut actions);
+                            }
+                        }
+                    }
+
+                    egui::CollapsingHeader::new("Agenda")
+                        .id_source("agenda")
+                        .show(ui, |ui| {
+                            for kind in [DeadlineKind::Hard, DeadlineKind::Target] {
+                                ui.strong(format!("{}s", kind.label()));
+                                let agenda: Vec<(usize, &Todo)> =
+                                    todos.agenda(kind).into_iter().filter(|(_, todo)| visible(todo)).collect();
+                                if agenda.is_empty() {
+                                    ui.weak("Nothing scheduled");
+                                }
+                                for (index, todo) in agenda {
+                                    let badge = badge(todo, now, &self.config);
+                                    show_todo(ui, index, todo, badge, false, &mut self.todo_edit, &mut actions);
+                                }
+                            }
+                        });
+                }
+            }
+            self.todo_selection = actions.selection;
+            if let Some((index, column)) = actions.column {
+                self.move_todo_to_column(index, column);
+            }
+            if let Some(index) = actions.focus {
+                self.start_focus(index);
+            }
+            if let Some(index) = actions.toggled {
+                self.toggle_todo(index);
+            }
+            if let Some(index) = actions.deleted {
+                self.delete_todos(&[index]);
+            }
+            if let Some((from, to)) = actions.moved {
+                self.move_todo(from, to);
+            }
+            if let Some((note, line)) = actions.linked_note {
+                self.open_note(&note);
+                if self.selected_note.as_ref() == Some(&note) {
+                    self.pending_jump = line;
+                }
+            }
+            if let Some(index) = actions.edit {
+                self.edit_todo(index);
+            }
+            if actions.commit {
+                self.commit_todo_edit();
+            }
+            if actions.cancel {
+                self.todo_edit = None;
+            }
+            if ui.button("Create Todo").clicked() {
+                self.create_todo("New Todo", None);
+                let last = self.todos.lock().unwrap().items.len().saturating_sub(1);
+                self.edit_todo(last);
+            }
+
+            ui.separator();
+            ui.heading("Tasks in Notes");
+            let mut toggled_task = None;
+            for (note, tasks) in self.task_index.by_note() {
+                egui::CollapsingHeader::new(note)
+                    .id_source(("inline_tasks", note))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for task in tasks {
+                            let mut done = task.done;
+                            let checkbox = ui
+                                .add_enabled(!task.locked, egui::Checkbox::new(&mut done, &task.text))
+                                .on_disabled_hover_text("The note is locked");
+                            if checkbox.changed() {
+                                toggled_task = Some(task.clone());
+                            }
+                        }
+                    });
+            }
+            if let Some(task) = toggled_task {
+                self.toggle_inline_task(&task);
+            }
+        });
+
+        self.show_outline_panel(ctx);
+        CentralPanel::default().show(ctx, |ui| self.show_editor(ui));
+
+        TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                self.show_command_input(ui);
+                if let Some(timer) = &self.focus {
+                    let remaining = timer.remaining_secs(chrono::Utc::now().timestamp());
+                    ui.label(format!("⏱ {} {}", focus::format_clock(remaining), timer.description))
+                        .on_hover_text("Focus session");
+                    if ui.small_button("■").on_hover_text("Stop the focus session").clicked() {
+                        self.finish_focus();
+                    }
+                }
+                #[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+                if let Some(dictation) = &mut self.dictation {
+                    ui.label(dictation.status());
+                    if dictation.is_listening() && ui.small_button("■").on_hover_text("Stop dictating").clicked() {
+                        dictation.stop();
+                    }
+                }
+                if let Some(status) = &self.command_status {
+                    ui.label(status);
+                }
+            });
+        });
+
+        self.show_windows(ctx);
+    }
+}
+
+#[derive(Default)]
+struct CommandSearch {
+    query: String,
+    // How many newer matches Ctrl+R stepped past
+    skipped: usize,
+}
+
+// A todo being edited in the right panel
+struct TodoEdit {
+    index: usize,
+    description: String,
+    due_date: String,
+    error: Option<String>,
+}
+
+// What was done in the outline panel this frame
+#[derive(Default)]
+struct OutlineActions {
+    jump: Option<usize>,
+    moved: Option<(usize, usize)>,
+}
+
+// Shows the headings in `range` as a tree, each section collapsible under its heading
+fn show_outline(ui: &mut egui::Ui, headings: &[Heading], range: Range<usize>, actions: &mut OutlineActions) {
+    let mut index = range.start;
+    while index < range.end {
+        let end = outline::section_end(headings, index).min(range.end);
+        let mut item = |ui: &mut egui::Ui| {
+            // Headings can be dragged onto each other to move their sections
+            let heading = &headings[index];
+            let item = ui.dnd_drag_source(Id::new(("outline", index)), index, |ui| ui.link(&heading.text));
+            if item.inner.clicked() {
+                actions.jump = Some(heading.line);
+            }
+            if let Some(from) = item.response.dnd_release_payload::<usize>() {
+                actions.moved = Some((*from, index));
+            }
+        };
+        if end > index + 1 {
+            let id = ui.make_persistent_id(("outline", index, &headings[index].text));
+            egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true)
+                .show_header(ui, item)
+                .body(|ui| show_outline(ui, headings, index + 1..end, actions));
+        } else {
+            item(ui);
+        }
+        index = end;
+    }
+}
+
+// What was done to the todos in the right panel this frame, along with the todos picked for
+// bulk actions, which are ticked off instead of completed while picking them
+#[derive(Default)]
+struct TodoActions {
+    selection: Option<BTreeSet<usize>>,
+    column: Option<(usize, usize)>,
+    focus: Option<usize>,
+    toggled: Option<usize>,
+    deleted: Option<usize>,
+    linked_note: Option<(String, Option<usize>)>,
+    moved: Option<(usize, usize)>,
+    edit: Option<usize>,
+    commit: bool,
+    cancel: bool,
+}
+
+fn show_todo(
+    ui: &mut egui::Ui,
+    index: usize,
+    todo: &Todo,
+    badge: Option<egui::RichText>,
+    draggable: bool,
+    edit: &mut Option<TodoEdit>,
+    actions: &mut TodoActions,
+) {
+    if let Some(edit) = edit.as_mut().filter(|edit| edit.index == index) {
+        ui.horizontal(|ui| {
+            let description = ui.add(egui::TextEdit::singleline(&mut edit.description).desired_width(140.0));
+            let due_date = ui.add(
+                egui::TextEdit::singleline(&mut edit.due_date)
+                    .hint_text("YYYY-MM-DD")
+                    .desired_width(80.0),
+            );
+            let submitted = (description.lost_focus() || due_date.lost_focus())
+                && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if submitted || ui.button("✔").on_hover_text("Save").clicked() {
+                actions.commit = true;
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) || ui.button("✖").on_hover_text("Cancel").clicked() {
+                actions.cancel = true;
+            }
+        });
+        if let Some(err) = &edit.error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+        return;
+    }
+    let show_row = |ui: &mut egui::Ui| {
+        ui.horizontal(|ui| {
+            match &mut actions.selection {
+                Some(selection) => {
+                    let mut selected = selection.contains(&index);
+                    if ui.checkbox(&mut selected, "").on_hover_text("Select").changed() {
+                        if selected {
+                            selection.insert(index);
+                        } else {
+                            selection.remove(&index);
+                        }
+                    }
+                }
+                None => {
+                    let mut done = todo.done;
+                    if ui.checkbox(&mut done, "").on_hover_text("Completed").changed() {
+                        actions.toggled = Some(index);
+                    }
+                }
+            }
+            if let Some(badge) = badge {
+                ui.label(badge).on_hover_text(todo.deadline.label());
+            }
+            let mut description = egui::RichText::new(&todo.description);
+            if todo.done {
+                description = description.strikethrough();
+            }
+            match &todo.note {
+                Some(note) => {
+                    let link = ui.link(description).on_hover_text(format!("Open {}", note));
+                    if link.clicked() {
+                        actions.linked_note = Some((note.clone(), todo.line));
+                    }
+                }
+                None => {
+                    let label = ui.add(egui::Label::new(description).sense(egui::Sense::click()));
+                    if label.on_hover_text("Click to edit").clicked() {
+                        actions.edit = Some(index);
+                    }
+                }
+            }
+            if let Some(project) = &todo.project {
+                ui.weak(format!("@{}", project));
+            }
+            if todo.time_spent_secs > 0 {
+                ui.weak(format!("⏱ {}", focus::format_duration(todo.time_spent_secs)))
+                    .on_hover_text("Time spent in focus sessions");
+            }
+            if !todo.done && ui.small_button("▶").on_hover_text("Start a focus session").clicked() {
+                actions.focus = Some(index);
+            }
+            if ui.small_button("✏").on_hover_text("Edit").clicked() {
+                actions.edit = Some(index);
+            }
+            if ui.button("Delete").clicked() {
+                actions.deleted = Some(index);
+            }
+        });
+    };
+    if !draggable {
+        show_row(ui);
+        return;
+    }
+    let item = ui.dnd_drag_source(Id::new(("todo", index)), index, show_row);
+    if let Some(from) = item.response.dnd_release_payload::<usize>() {
+        actions.moved = Some((*from, index));
+    }
+}
+
+// Shows the todos in the columns of the board, where they can be dragged from one column into another
+fn show_todo_board(
+    ui: &mut egui::Ui,
+    todos: &Todos,
+    visible: &dyn Fn(&Todo) -> bool,
+    now: i64,
+    config: &Config,
+    edit: &mut Option<TodoEdit>,
+    actions: &mut TodoActions,
+) {
+    let columns = &config.board_columns;
+    let board = todos.board(columns);
+    ui.columns(columns.len().max(1), |uis| {
+        for (column, (ui, items)) in uis.iter_mut().zip(board).enumerate() {
+            ui.strong(&columns[column]);
+            let (_, dropped) = ui.dnd_drop_zone::<usize, ()>(egui::Frame::group(ui.style()), |ui| {
+                ui.set_min_size(egui::vec2(ui.available_width(), 40.0));
+                for (index, todo) in items.into_iter().filter(|(_, todo)| visible(todo)) {
+                    let badge = badge(todo, now, config);
+                    ui.dnd_drag_source(Id::new(("board", index)), index, |ui| {
+                        show_todo(ui, index, todo, badge, false, edit, actions);
+                    });
+                }
+            });
+            if let Some(index) = dropped {
+                actions.column = Some((*index, column));
+            }
+        }
+    });
+}
+
+// Hard deadlines are shown in bold and targets in italics, coloured once they are due soon
+fn badge(todo: &Todo, now: i64, config: &Config) -> Option<egui::RichText> {
+    let due_date = todo.due_date?;
+    let status = todo.due_status(now, config.lead_secs(todo.deadline))?;
+    let mut badge = egui::RichText::new(todos::format_due_date(due_date)).small();
+    badge = match todo.deadline {
+        DeadlineKind::Hard => badge.strong(),
+        DeadlineKind::Target => badge.italics(),
+    };
+    Some(match (status, todo.deadline) {
+        (DueStatus::Overdue, DeadlineKind::Hard) => badge.color(egui::Color32::RED),
+        (DueStatus::Overdue, DeadlineKind::Target) | (DueStatus::DueSoon, _) => {
+            badge.color(egui::Color32::from_rgb(230, 160, 40))
+        }
+        (DueStatus::Upcoming, _) => badge,
+    })
+}
+
+// The context menu of a note in the sidebar
+fn show_note_menu(
+    ui: &mut egui::Ui,
+    note: &str,
+    selected: &[String],
+    note_command: &mut Option<Command>,
+    merged_notes: &mut Option<Vec<String>>,
+) {
+    if ui.button("Duplicate").clicked() {
+        *note_command = Some(Command::Duplicate {
+            title: Some(note.to_string()),
+        });
+        ui.close_menu();
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if ui.button("Share").on_hover_text("Upload to the paste service and copy the link").clicked() {
+        *note_command = Some(Command::Share {
+            title: Some(note.to_string()),
+        });
+        ui.close_menu();
+    }
+    if ui.button("Split by Headings").on_hover_text("Make a note of each top-level section").clicked() {
+        *note_command = Some(Command::Split {
+            title: Some(note.to_string()),
+        });
+        ui.close_menu();
+    }
+    // Merging acts on the selection the note is part of
+    if selected.len() > 1
+        && selected.iter().any(|title| title == note)
+        && ui.button(format!("Merge {} Notes…", selected.len())).clicked()
+    {
+        *merged_notes = Some(selected.to_vec());
+        ui.close_menu();
+    }
+}
+
+// Picks, creates and edits the themes in the preferences, which are previewed while they are edited
+fn show_theme_preferences(ui: &mut egui::Ui, draft: &mut Config) {
+    ui.horizontal(|ui| {
+        ui.label("Theme");
+        egui::ComboBox::from_id_source("theme")
+            .selected_text(draft.theme.as_deref().unwrap_or("Built-in"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut draft.theme, None, "Built-in");
+                for theme in &draft.themes {
+                    ui.selectable_value(&mut draft.theme, Some(theme.name.clone()), &theme.name);
+                }
+            });
+        if ui.button("New Theme").clicked() {
+            let theme = Theme {
+                name: format!("Theme {}", draft.themes.len() + 1),
+                dark: ui.visuals().dark_mode,
+                ..Theme::default()
+            };
+            draft.theme = Some(theme.name.clone());
+            draft.themes.push(theme);
+        }
+    });
+    let Some(index) = draft
+        .themes
+        .iter()
+        .position(|theme| Some(&theme.name) == draft.theme.as_ref())
+    else {
+        return;
+    };
+    let panel_fill = ui.visuals().panel_fill;
+    let text_color = ui.visuals().text_color();
+    let theme = &mut draft.themes[index];
+    let mut renamed = false;
+    egui::Grid::new("theme_preferences").num_columns(2).show(ui, |ui| {
+        ui.label("Name");
+        renamed = ui.text_edit_singleline(&mut theme.name).changed();
+        ui.end_row();
+        ui.label("Based on");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut theme.dark, true, "Dark");
+            ui.radio_value(&mut theme.dark, false, "Light");
+        });
+        ui.end_row();
+        ui.label("Accent");
+        color_button(ui, &mut theme.accent);
+        ui.end_row();
+        ui.label("Background");
+        optional_color(ui, &mut theme.background, panel_fill);
+        ui.end_row();
+        ui.label("Text");
+        optional_color(ui, &mut theme.text, text_color);
+        ui.end_row();
+        ui.label("Editor font");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("editor_font")
+                .selected_text(theme.editor_font.label())
+                .show_ui(ui, |ui| {
+                    for font in EditorFont::ALL {
+                        ui.selectable_value(&mut theme.editor_font, font, font.label());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut theme.editor_font_size)
+                    .clamp_range(8.0..=40.0)
+                    .speed(0.2)
+                    .suffix(" pt"),
+            );
+        });
+        ui.end_row();
+        ui.label("Line spacing");
+        ui.add(egui::Slider::new(&mut theme.line_spacing, 1.0..=2.5));
+        ui.end_row();
+    });
+    // Keep the renamed theme selected
+    if renamed {
+        draft.theme = Some(theme.name.clone());
+    }
+    if ui.button("Delete Theme").clicked() {
+        draft.themes.remove(index);
+        draft.theme = None;
+    }
+}
+
+// Edits a colour kept as hex in a theme
+fn color_button(ui: &mut egui::Ui, color: &mut String) {
+    let mut picked = theme::parse_color(color).unwrap_or(egui::Color32::GRAY);
+    if ui.color_edit_button_srgba(&mut picked).changed() {
+        *color = theme::format_color(picked);
+    }
+}
+
+// Edits a colour of a theme that can be left to the dark or light look
+fn optional_color(ui: &mut egui::Ui, color: &mut Option<String>, default: egui::Color32) {
+    ui.horizontal(|ui| {
+        let mut custom = color.is_some();
+        if ui.checkbox(&mut custom, "Custom").changed() {
+            *color = custom.then(|| theme::format_color(default));
+        }
+        if let Some(color) = color {
+            color_button(ui, color);
+        }
+    });
+}
+
+// Brings the window back, also when it was hidden in the tray
+#[cfg(not(target_arch = "wasm32"))]
+fn show_window(ctx: &egui::Context) {
+    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+}
+
+// How a vault is named in the menu and status messages
+#[cfg(not(target_arch = "wasm32"))]
+fn vault_label(name: Option<&str>) -> &str {
+    name.unwrap_or("~/.notes")
+}
+
+// Inserts text at the cursor of the editor, or at the end if the editor has no cursor, starting
+// a new line if the cursor is in the middle of one
+fn insert_at_cursor(ctx: &egui::Context, buffer: &mut NoteBuffer, text: &str) {
+    let cursor = egui::TextEdit::load_state(ctx, Id::new("note_editor"))
+        .and_then(|state| state.cursor.char_range())
+        .map(|range| range.primary.index);
+    let index = match cursor {
+        Some(char_index) => buffer
+            .content
+            .char_indices()
+            .nth(char_index)
+            .map_or(buffer.content.len(), |(index, _)| index),
+        None => buffer.content.len(),
+    };
+    let mut text = text.to_string();
+    if index > 0 && !buffer.content[..index].ends_with('\n') {
+        text.insert(0, '\n');
+    }
+    buffer.content.insert_str(index, &text);
+    buffer.mark_edited(ctx.input(|i| i.time));
+}
+
+// Inserts dictated text at the cursor of the editor, or at the end, and moves the cursor after it so
+// that what is said next follows
+#[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+fn insert_dictation(ctx: &egui::Context, buffer: &mut NoteBuffer, text: &str) {
+    let editor_id = Id::new("note_editor");
+    let mut state = egui::TextEdit::load_state(ctx, editor_id).unwrap_or_default();
+    let index = state
+        .cursor
+        .char_range()
+        .and_then(|range| buffer.content.char_indices().nth(range.primary.index))
+        .map_or(buffer.content.len(), |(index, _)| index);
+    let mut text = text.to_string();
+    if buffer.content[..index].ends_with(|c: char| !c.is_whitespace()) {
+        text.insert(0, ' ');
+    }
+    buffer.content.insert_str(index, &text);
+    buffer.mark_edited(ctx.input(|i| i.time));
+    let cursor = buffer.content[..index + text.len()].chars().count();
+    state.cursor.set_char_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(cursor))));
+    state.store(ctx, editor_id);
+}
+
+/// Returns the char index at which the given line starts, or the end of the text if it has fewer lines.
+fn char_index_of_line(text: &str, line: usize) -> usize {
+    if line == 0 {
+        return 0;
+    }
+    text.chars()
+        .enumerate()
+        .filter(|(_, c)| *c == '\n')
+        .nth(line - 1)
+        .map(|(index, _)| index + 1)
+        .unwrap_or_else(|| text.chars().count())
+}
+
+// How the todos in the right panel are grouped
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, PartialEq, Eq)]
+enum TodoGrouping {
+    #[default]
+    None,
+    Note,
+    Project,
+}
+
+impl TodoGrouping {
+    const ALL: [TodoGrouping; 3] = [TodoGrouping::None, TodoGrouping::Note, TodoGrouping::Project];
+
+    fn label(self) -> &'static str {
+        match self {
+            TodoGrouping::None => "Nothing",
+            TodoGrouping::Note => "Note",
+            TodoGrouping::Project => "Project",
+        }
+    }
+}
+
+// Whether the right panel lists the todos or shows them on a board
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy, PartialEq, Eq)]
+enum TodoView {
+    #[default]
+    List,
+    Board,
+}
+
+impl TodoView {
+    const ALL: [TodoView; 2] = [TodoView::List, TodoView::Board];
+
+    fn label(self) -> &'static str {
+        match self {
+            TodoView::List => "List",
+            TodoView::Board => "Board",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+enum Mode {
+    Command,
+    CommandInput,
     Edit,
 }
 
+/// Returns the byte index of the given char index, or the length of the text if it is past the end.
+fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices().nth(char_index).map_or(text.len(), |(index, _)| index)
+}
+
 fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
     ui.horizontal(|ui| {
         ui.spacing_mut().item_spacing.x = 0.0;
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+
+/// The folder in the `.notes` directory that holds attachments.
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// The folder in the attachments folder that holds the text read from images, see `ocr`.
+pub const TEXT_DIR: &str = ".text";
+
+/// File extensions of attachments that are shown as images.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "svg"];
+
+/// File extensions of attachments that can be played in the preview.
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "ogg", "flac"];
+
+/// Stores a file in the attachments folder.
+///
+/// Attachments are named after the SHA-256 hash of their content, so storing the same file
+/// twice, e.g. dropping one image into several notes, reuses the existing copy.
+///
+/// # Arguments
+///
+/// * `name` - The original file name, whose extension is kept.
+/// * `data` - The content of the file.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the attachment path relative to the `.notes` directory.
+pub fn store(name: &str, data: &[u8]) -> io::Result<String> {
+    let hash = Sha256::digest(data);
+    let hash: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let extension = extension(name);
+    let file_name = |hash: &str| match &extension {
+        Some(extension) => format!("{}.{}", hash, extension),
+        None => hash.to_string(),
+    };
+
+    let dir = Notes::get_notes_dir()?.join(ATTACHMENTS_DIR);
+    fs::create_dir_all(&dir)?;
+    // Attachments used to be named after the first half of the hash
+    let legacy = file_name(&hash[..32]);
+    if dir.join(&legacy).exists() {
+        return Ok(format!("{}/{}", ATTACHMENTS_DIR, legacy));
+    }
+    let file_name = file_name(&hash);
+    let path = dir.join(&file_name);
+    if !path.exists() {
+        atomic_write(&path, data, true)?;
+    }
+    Ok(format!("{}/{}", ATTACHMENTS_DIR, file_name))
+}
+
+/// Copies a file on disk into the attachments folder.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the attachment path relative to the `.notes` directory.
+pub fn store_file(path: &Path) -> io::Result<String> {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    store(&name, &fs::read(path)?)
+}
+
+/// Returns the absolute path of an attachment given its path relative to the `.notes` directory.
+pub fn resolve(relative_path: &str) -> io::Result<PathBuf> {
+    Ok(Notes::get_notes_dir()?.join(relative_path))
+}
+
+/// Returns the path of the file holding the text read from an attachment, e.g.
+/// `attachments/.text/ab12.png.txt` for `attachments/ab12.png`.
+pub fn text_path(relative_path: &str) -> io::Result<PathBuf> {
+    let name = Path::new(relative_path)
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Not an attachment: {}", relative_path)))?;
+    let mut file_name = name.to_os_string();
+    file_name.push(".txt");
+    Ok(Notes::get_notes_dir()?.join(ATTACHMENTS_DIR).join(TEXT_DIR).join(file_name))
+}
+
+/// Returns the text read from the images a note links to, which is searched along with the note.
+///
+/// # Arguments
+///
+/// * `body` - The content of the note.
+pub fn extracted_text(body: &str) -> String {
+    let texts: Vec<String> = links(body)
+        .into_iter()
+        .filter(|link| is_image(link))
+        .filter_map(|link| fs::read_to_string(text_path(link).ok()?).ok())
+        .collect();
+    texts.join("\n")
+}
+
+/// Returns whether a file name or path refers to an image.
+pub fn is_image(name: &str) -> bool {
+    extension(name).is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Returns whether a file name or path refers to a sound, such as a recorded memo.
+pub fn is_audio(name: &str) -> bool {
+    extension(name).is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Returns the Markdown that links to an attachment: an image for pictures, a link otherwise.
+///
+/// # Arguments
+///
+/// * `name` - The original file name, used as the link text.
+/// * `relative_path` - The attachment path relative to the `.notes` directory.
+pub fn markdown_link(name: &str, relative_path: &str) -> String {
+    let text = name.replace(['[', ']'], "");
+    if is_image(relative_path) {
+        format!("![{}]({})", text, relative_path)
+    } else {
+        format!("[{}]({})", text, relative_path)
+    }
+}
+
+/// Finds the attachments a note links to, as paths relative to the `.notes` directory.
+///
+/// # Arguments
+///
+/// * `body` - The content of the note.
+pub fn links(body: &str) -> Vec<&str> {
+    let prefix = format!("]({}/", ATTACHMENTS_DIR);
+    let mut links = Vec::new();
+    for (index, _) in body.match_indices(&prefix) {
+        let rest = &body[index + 2..];
+        if let Some(end) = rest.find(')') {
+            let link = &rest[..end];
+            if !link.contains("..") && !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+/// Counts how many notes link to each attachment. Archived notes and notes in the trash are
+/// counted too, so that their attachments are kept.
+///
+/// The links in passphrase-protected notes can't be read, see `collect_garbage`.
+///
+/// # Returns
+///
+/// An `io::Result<HashMap<String, usize>>` containing the number of notes linking to each
+/// attachment path, relative to the `.notes` directory. Unreferenced attachments are left out.
+pub fn reference_counts() -> io::Result<HashMap<String, usize>> {
+    Ok(scan_references()?.0)
+}
+
+/// Deletes the attachments that no note links to anymore.
+///
+/// Nothing is deleted while some notes are protected with a passphrase, since the attachments
+/// linked from their encrypted bodies would look unreferenced.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<String>>` containing the paths of the deleted attachments, relative to
+/// the `.notes` directory.
+pub fn collect_garbage() -> io::Result<Vec<String>> {
+    let dir = Notes::get_notes_dir()?.join(ATTACHMENTS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let (counts, protected) = scan_references()?;
+    if protected > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} notes are protected with a passphrase, remove their protection before cleaning up attachments",
+                protected
+            ),
+        ));
+    }
+    let mut removed = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Skip in-progress writes
+        if name.starts_with('.') || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let relative_path = format!("{}/{}", ATTACHMENTS_DIR, name);
+        if !counts.contains_key(&relative_path) {
+            fs::remove_file(entry.path())?;
+            match fs::remove_file(text_path(&relative_path)?) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+            removed.push(relative_path);
+        }
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+// Counts the links of the notes, archived notes and notes in the trash, along with the number of notes whose body
+// is encrypted
+fn scan_references() -> io::Result<(HashMap<String, usize>, usize)> {
+    let mut counts = HashMap::new();
+    let mut protected = 0;
+    let mut count = |content: &str| {
+        if FrontMatter::parse(content).0.is_encrypted() {
+            protected += 1;
+        }
+        for link in links(content) {
+            *counts.entry(link.to_string()).or_insert(0) += 1;
+        }
+    };
+    for title in Notes::list_notes()? {
+        count(&Notes::read_note_file(&title)?);
+    }
+    for title in Notes::list_archived()? {
+        count(&Notes::read_archived(&title)?);
+    }
+    for title in Notes::list_trashed()? {
+        count(&Notes::read_trashed(&title)?);
+    }
+    Ok((counts, protected))
+}
+
+/// Returns the lowercased extension of a file name.
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_store_deduplicates() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        let first = store("photo.PNG", b"pixels").unwrap();
+        let second = store("copy.png", b"pixels").unwrap();
+        let other = store("photo.png", b"other pixels").unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert!(first.starts_with("attachments/") && first.ends_with(".png"));
+        assert_eq!(fs::read(temp_notes_dir.join(&first)).unwrap(), b"pixels");
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        let kept = store("cat.png", b"cat").unwrap();
+        let archived = store("dog.png", b"dog").unwrap();
+        let unused = store("spec.pdf", b"spec").unwrap();
+        let link = markdown_link("cat.png", &kept);
+        Notes::create_note_file("a", &format!("{}
+{}", link, link)).unwrap();
+        Notes::create_note_file("b", &link).unwrap();
+        Notes::create_note_file("c", &markdown_link("dog.png", &archived)).unwrap();
+        Notes::archive("c").unwrap();
+
+        let counts = reference_counts().unwrap();
+        assert_eq!(counts.get(&kept), Some(&2));
+        assert_eq!(counts.get(&archived), Some(&1));
+        assert_eq!(counts.get(&unused), None);
+        assert_eq!(collect_garbage().unwrap(), vec![unused.clone()]);
+        assert!(temp_notes_dir.join(&kept).exists());
+        assert!(temp_notes_dir.join(&archived).exists());
+        assert!(!temp_notes_dir.join(&unused).exists());
+
+        let trashed = store("bird.png", b"bird").unwrap();
+        Notes::create_note_file("e", &markdown_link("bird.png", &trashed)).unwrap();
+        Notes::trash("e").unwrap();
+        assert!(collect_garbage().unwrap().is_empty());
+        assert!(temp_notes_dir.join(&trashed).exists());
+
+        let unused = store("spec.pdf", b"spec").unwrap();
+        Notes::create_note_file("d", "---\nencrypted: chacha20poly1305\n---\nsealed").unwrap();
+        assert_eq!(collect_garbage().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(temp_notes_dir.join(&unused).exists());
+    }
+
+    #[test]
+    fn test_store_reuses_legacy_names() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        let hash: String = Sha256::digest(b"pixels").iter().take(16).map(|byte| format!("{:02x}", byte)).collect();
+        fs::create_dir_all(temp_notes_dir.join(ATTACHMENTS_DIR)).unwrap();
+        fs::write(temp_notes_dir.join(ATTACHMENTS_DIR).join(format!("{}.png", hash)), b"pixels").unwrap();
+        assert_eq!(store("photo.png", b"pixels").unwrap(), format!("attachments/{}.png", hash));
+    }
+
+    #[test]
+    fn test_extracted_text() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        let receipt = store("receipt.png", b"receipt").unwrap();
+        let spec = store("spec.pdf", b"spec").unwrap();
+        let path = text_path(&receipt).unwrap();
+        assert!(path.starts_with(temp_notes_dir.join(ATTACHMENTS_DIR).join(TEXT_DIR)));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "Total 12.50").unwrap();
+
+        let body = format!("{}\n{}", markdown_link("receipt.png", &receipt), markdown_link("spec.pdf", &spec));
+        assert_eq!(extracted_text(&body), "Total 12.50");
+        assert_eq!(extracted_text("No attachments"), "");
+    }
+
+    #[test]
+    fn test_markdown_link() {
+        assert_eq!(markdown_link("cat.jpg", "attachments/ab.jpg"), "![cat.jpg](attachments/ab.jpg)");
+        assert_eq!(markdown_link("[spec].pdf", "attachments/cd.pdf"), "[spec.pdf](attachments/cd.pdf)");
+        assert!(!is_image("notes"));
+        assert!(is_audio("attachments/ef.WAV"));
+        assert!(!is_audio("attachments/ab.jpg"));
+    }
+}
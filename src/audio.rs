@@ -0,0 +1,256 @@
+//! Audio memos: recording from the microphone into WAV attachments and playing them back.
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{SampleFormat, SizedSample, Stream};
+use rodio::{Decoder, OutputStream, Sink};
+
+/// A recording from the default microphone, which lasts until it is finished or dropped.
+pub struct Recorder {
+    stream: Stream,
+    samples: Arc<Mutex<Vec<i16>>>,
+    channels: u16,
+    sample_rate: u32,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Starts recording from the default microphone.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Recorder>` containing the recording, or a `NotFound` error if there is no microphone.
+    pub fn start() -> io::Result<Recorder> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No microphone found"))?;
+        let config = device.default_input_config().map_err(audio_error)?;
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let stream_config = config.config();
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => input_stream(&device, &stream_config, &samples, |sample: f32| {
+                (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+            }),
+            SampleFormat::I16 => input_stream(&device, &stream_config, &samples, |sample: i16| sample),
+            SampleFormat::U16 => {
+                input_stream(&device, &stream_config, &samples, |sample: u16| (sample as i32 - 32768) as i16)
+            }
+            format => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("The microphone records {} samples, which are not supported", format),
+            )),
+        }?;
+        stream.play().map_err(audio_error)?;
+        Ok(Recorder {
+            stream,
+            samples,
+            channels: stream_config.channels,
+            sample_rate: stream_config.sample_rate.0,
+            started: Instant::now(),
+        })
+    }
+
+    /// Returns how long the recording has been going on.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Returns the number of channels the samples are interleaved from.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the number of samples per second and channel.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Takes the samples recorded since the recording started or samples were last taken, while
+    /// recording goes on.
+    pub fn take_samples(&self) -> Vec<i16> {
+        std::mem::take(&mut *self.samples.lock().unwrap())
+    }
+
+    /// Stops recording.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vec<u8>>` containing the recording as a WAV file.
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        drop(self.stream);
+        let samples = std::mem::take(&mut *self.samples.lock().unwrap());
+        wav(&samples, self.channels, self.sample_rate)
+    }
+}
+
+// Collects the samples of the microphone as 16-bit integers, whatever format it records in
+fn input_stream<T: SizedSample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: &Arc<Mutex<Vec<i16>>>,
+    convert: fn(T) -> i16,
+) -> io::Result<Stream> {
+    let samples = samples.clone();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                samples.lock().unwrap().extend(data.iter().map(|sample| convert(*sample)));
+            },
+            |err| log::error!("Recording failed: {}", err),
+            None,
+        )
+        .map_err(audio_error)
+}
+
+/// Encodes 16-bit samples as a WAV file.
+///
+/// # Arguments
+///
+/// * `samples` - The samples, interleaved if there are several channels.
+/// * `channels` - The number of channels.
+/// * `sample_rate` - The number of samples per second and channel.
+pub fn wav(samples: &[i16], channels: u16, sample_rate: u32) -> io::Result<Vec<u8>> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut cursor = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut cursor, spec).map_err(audio_error)?;
+    for sample in samples {
+        writer.write_sample(*sample).map_err(audio_error)?;
+    }
+    writer.finalize().map_err(audio_error)?;
+    Ok(cursor.into_inner())
+}
+
+/// The name of the attachment a memo recorded at the given time is stored as, e.g. `memo 2024-05-01 09-30.wav`.
+pub fn memo_name(now: chrono::NaiveDateTime) -> String {
+    format!("memo {}.wav", now.format("%Y-%m-%d %H-%M"))
+}
+
+/// Formats a duration as minutes and seconds, e.g. `1:05`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+// The attachment being played, if any. Only one plays at a time.
+struct Playback {
+    target: String,
+    sink: Arc<Sink>,
+}
+
+static PLAYBACK: Mutex<Option<Playback>> = Mutex::new(None);
+
+/// How an audio attachment is being played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Playing(Duration),
+    Paused(Duration),
+}
+
+/// Plays an audio attachment, stopping the one that was playing.
+///
+/// # Arguments
+///
+/// * `target` - The link to the attachment in the note, which identifies it in `state`.
+/// * `path` - The audio file.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating whether playback started.
+pub fn play(target: &str, path: &Path) -> io::Result<()> {
+    stop();
+    let source = Decoder::new(BufReader::new(File::open(path)?)).map_err(audio_error)?;
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        // The output stream can't leave the thread that opened it, so the thread lives as long as the sound
+        let opened = OutputStream::try_default()
+            .map_err(audio_error)
+            .and_then(|(stream, handle)| Ok((stream, Sink::try_new(&handle).map_err(audio_error)?)));
+        match opened {
+            Ok((_stream, sink)) => {
+                let sink = Arc::new(sink);
+                sink.append(source);
+                let _ = sender.send(Ok(sink.clone()));
+                sink.sleep_until_end();
+            }
+            Err(err) => {
+                let _ = sender.send(Err(err));
+            }
+        }
+    });
+    let sink = receiver
+        .recv()
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Playback stopped unexpectedly"))??;
+    *PLAYBACK.lock().unwrap() = Some(Playback {
+        target: target.to_string(),
+        sink,
+    });
+    Ok(())
+}
+
+/// Returns how an audio attachment is being played, or `None` if it isn't.
+pub fn state(target: &str) -> Option<PlaybackState> {
+    let playback = PLAYBACK.lock().unwrap();
+    let playback = playback.as_ref().filter(|playback| playback.target == target && !playback.sink.empty())?;
+    let position = playback.sink.get_pos();
+    Some(if playback.sink.is_paused() {
+        PlaybackState::Paused(position)
+    } else {
+        PlaybackState::Playing(position)
+    })
+}
+
+/// Pauses or resumes the attachment being played.
+pub fn set_paused(paused: bool) {
+    if let Some(playback) = PLAYBACK.lock().unwrap().as_ref() {
+        if paused {
+            playback.sink.pause();
+        } else {
+            playback.sink.play();
+        }
+    }
+}
+
+/// Stops the attachment being played.
+pub fn stop() {
+    if let Some(playback) = PLAYBACK.lock().unwrap().take() {
+        playback.sink.stop();
+    }
+}
+
+fn audio_error(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav() {
+        let data = wav(&[0, i16::MAX, i16::MIN, 0], 2, 16000).unwrap();
+        let reader = hound::WavReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 16000);
+        let samples: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+        assert_eq!(samples, vec![0, i16::MAX, i16::MIN, 0]);
+    }
+
+    #[test]
+    fn test_names() {
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        assert_eq!(memo_name(now), "memo 2024-05-01 09-30.wav");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1:05");
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Local, NaiveDateTime};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::commands::expand_home;
+use crate::config::{BackupPolicy, BackupSchedule};
+use crate::ipc::SOCKET_FILE;
+use crate::notes::Notes;
+use crate::safe_mode::SESSION_FILE;
+use crate::secrets::{SECRETS_FILE, SECRETS_KEY_FILE};
+use crate::sqlite::{self, DATABASE_FILE};
+use crate::storage::atomic_write;
+use crate::vault::{self, LOCK_FILE};
+
+/// The directory backups are written to when none is given.
+pub const DEFAULT_BACKUP_DIR: &str = "~/notes-backups";
+
+/// The start of the file name of every backup, followed by the time it was made.
+pub const BACKUP_PREFIX: &str = "notes-backup-";
+
+// The format of the time in the file name of a backup
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// How a backup is restored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Only work out what would change, without changing anything.
+    pub dry_run: bool,
+    /// Keep files that changed since the backup and files that are not in it. Otherwise the
+    /// notes directory is made to match the backup exactly.
+    pub merge: bool,
+}
+
+/// What restoring a backup does to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreAction {
+    /// The file is missing and is restored from the backup.
+    Created,
+    /// The file differs from the backup and is replaced by it.
+    Overwritten,
+    /// The file differs from the backup but is kept, as the backup is merged.
+    Kept,
+    /// The file is not in the backup and is removed.
+    Removed,
+}
+
+impl RestoreAction {
+    /// A human-readable label for the action.
+    pub fn label(self) -> &'static str {
+        match self {
+            RestoreAction::Created => "restored",
+            RestoreAction::Overwritten => "overwritten",
+            RestoreAction::Kept => "kept",
+            RestoreAction::Removed => "removed",
+        }
+    }
+}
+
+/// A file changed, or left alone, by restoring a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreChange {
+    /// The path of the file relative to the notes directory.
+    pub path: PathBuf,
+    /// What happens to the file.
+    pub action: RestoreAction,
+}
+
+/// Backs up the whole notes directory, including todos, attachments and settings, into a zip file.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to write the backup to. It is created if needed.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the path of the backup, named after the current time.
+pub fn create(dir: &Path) -> io::Result<PathBuf> {
+    sqlite::require_files("Backup")?;
+    let root = Notes::get_notes_dir()?;
+    fs::create_dir_all(dir)?;
+    let name = format!("{}{}.zip", BACKUP_PREFIX, Local::now().format(TIMESTAMP_FORMAT));
+    let path = dir.join(&name);
+    let temp_path = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let result = write_archive(&root, dir, &temp_path).and_then(|_| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    Ok(path)
+}
+
+/// Restores the notes directory from a backup made by `create`.
+///
+/// # Arguments
+///
+/// * `path` - The backup file.
+/// * `options` - Whether to merge the backup and whether to only list the changes.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<RestoreChange>>` containing every file that differs from the backup, sorted by path.
+pub fn restore(path: &Path, options: RestoreOptions) -> io::Result<Vec<RestoreChange>> {
+    sqlite::require_files("Restoring a backup")?;
+    let root = Notes::get_notes_dir()?;
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(invalid_data)?;
+    // Backups kept inside the notes directory, including this one, are not removed
+    let backup_dir = path.parent().and_then(|dir| dir.strip_prefix(&root).ok());
+    let mut changes = Vec::new();
+    let mut in_backup = HashSet::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(invalid_data)?;
+        if entry.is_dir() {
+            continue;
+        }
+        // Names that would escape the notes directory are never extracted
+        let Some(relative) = entry.enclosed_name() else {
+            log::warn!("Skipping {} in {}", entry.name(), path.display());
+            continue;
+        };
+        if is_skipped(&relative) {
+            continue;
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        let target = root.join(&relative);
+        in_backup.insert(relative.clone());
+        let action = match fs::read(&target) {
+            Ok(existing) if existing == data => continue,
+            Ok(_) if options.merge => RestoreAction::Kept,
+            Ok(_) => RestoreAction::Overwritten,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => RestoreAction::Created,
+            Err(err) => return Err(err),
+        };
+        if !options.dry_run && action != RestoreAction::Kept {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            atomic_write(&target, &data, true)?;
+        }
+        changes.push(RestoreChange { path: relative, action });
+    }
+
+    if !options.merge {
+        let mut files = Vec::new();
+        collect_files(&root, &root, &mut files)?;
+        let removed = files
+            .into_iter()
+            .filter(|relative| !in_backup.contains(relative) && !in_backup_dir(relative, backup_dir));
+        for relative in removed {
+            if !options.dry_run {
+                let target = root.join(&relative);
+                vault::check_writable(&target)?;
+                fs::remove_file(target)?;
+            }
+            changes.push(RestoreChange {
+                path: relative,
+                action: RestoreAction::Removed,
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Lists the backups in a folder, newest first.
+///
+/// # Arguments
+///
+/// * `dir` - The folder the backups were written to.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<(NaiveDateTime, PathBuf)>>` containing when each backup was made and its path.
+pub fn list(dir: &Path) -> io::Result<Vec<(NaiveDateTime, PathBuf)>> {
+    let mut backups = Vec::new();
+    if !dir.exists() {
+        return Ok(backups);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let made = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(BACKUP_PREFIX)?.strip_suffix(".zip"))
+            .and_then(|stamp| NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok());
+        if let Some(made) = made {
+            backups.push((made, path));
+        }
+    }
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(backups)
+}
+
+/// Removes the backups in a folder that the policy no longer keeps.
+///
+/// # Arguments
+///
+/// * `dir` - The folder the backups were written to.
+/// * `policy` - How many daily and weekly backups to keep.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<PathBuf>>` containing the removed backups.
+pub fn prune(dir: &Path, policy: &BackupPolicy) -> io::Result<Vec<PathBuf>> {
+    let backups = list(dir)?;
+    let times: Vec<NaiveDateTime> = backups.iter().map(|(made, _)| *made).collect();
+    let mut removed = Vec::new();
+    for ((_, path), keep) in backups.into_iter().zip(retained(&times, policy)) {
+        if !keep {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Returns the folder automatic backups are written to.
+pub fn policy_dir(policy: &BackupPolicy) -> PathBuf {
+    let dir = policy.dir.trim();
+    expand_home(if dir.is_empty() { DEFAULT_BACKUP_DIR } else { dir })
+}
+
+/// Returns whether an automatic backup is due.
+///
+/// # Arguments
+///
+/// * `schedule` - When backups are made.
+/// * `last` - When the latest backup was made, if there is one.
+/// * `now` - The current local time.
+/// * `at_start` - Whether the app is starting.
+pub fn is_due(schedule: BackupSchedule, last: Option<NaiveDateTime>, now: NaiveDateTime, at_start: bool) -> bool {
+    match schedule {
+        BackupSchedule::Off => false,
+        BackupSchedule::OnStart => at_start,
+        BackupSchedule::Daily => last.map_or(true, |last| (now - last).num_hours() >= 24),
+    }
+}
+
+/// Makes a backup if the policy says one is due, then prunes the old ones.
+///
+/// # Arguments
+///
+/// * `policy` - When backups are made, where to and how many are kept.
+/// * `at_start` - Whether the app is starting.
+///
+/// # Returns
+///
+/// An `io::Result<Option<PathBuf>>` containing the path of the new backup, or `None` if none was due.
+pub fn run_automatic(policy: &BackupPolicy, at_start: bool) -> io::Result<Option<PathBuf>> {
+    let dir = policy_dir(policy);
+    let last = list(&dir)?.first().map(|(made, _)| *made);
+    if !is_due(policy.schedule, last, Local::now().naive_local(), at_start) {
+        return Ok(None);
+    }
+    let path = create(&dir)?;
+    for removed in prune(&dir, policy)? {
+        log::info!("Removed old backup {}", removed.display());
+    }
+    Ok(Some(path))
+}
+
+/// Returns which of the backups made at the given times, newest first, are kept: the latest backup
+/// of each of the last `keep_daily` days and of each of the last `keep_weekly` weeks with backups.
+/// The newest backup is always kept.
+fn retained(times: &[NaiveDateTime], policy: &BackupPolicy) -> Vec<bool> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    times
+        .iter()
+        .enumerate()
+        .map(|(index, time)| {
+            let date = time.date();
+            let daily = days.len() < policy.keep_daily && days.insert(date);
+            let weekly = weeks.len() < policy.keep_weekly && weeks.insert(date.iso_week());
+            index == 0 || daily || weekly
+        })
+        .collect()
+}
+
+fn write_archive(root: &Path, dir: &Path, temp_path: &Path) -> io::Result<()> {
+    let mut files = Vec::new();
+    collect_files(root, root, &mut files)?;
+    // Backups kept inside the notes directory are not backed up again
+    let backup_dir = dir.strip_prefix(root).ok();
+
+    let mut writer = ZipWriter::new(File::create(temp_path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for relative in files {
+        if in_backup_dir(&relative, backup_dir) {
+            continue;
+        }
+        let name: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        writer.start_file(name.join("/"), options).map_err(invalid_data)?;
+        writer.write_all(&fs::read(root.join(&relative))?)?;
+    }
+    writer.finish().map_err(invalid_data)?.sync_all()
+}
+
+/// Recursively collects the files in `dir` that belong in a backup, relative to the root.
+pub fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if path.is_file() && !is_skipped(&relative) {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+// The files of the app and of this device that `is_skipped` leaves out
+const SKIPPED_FILES: [&str; 6] = [
+    LOCK_FILE,
+    SESSION_FILE,
+    SOCKET_FILE,
+    SECRETS_FILE,
+    SECRETS_KEY_FILE,
+    DATABASE_FILE,
+];
+
+// Whether a file lies in the folder of the backups, relative to the notes directory
+fn in_backup_dir(relative: &Path, backup_dir: Option<&Path>) -> bool {
+    match backup_dir {
+        // Backups written to the notes directory itself are told apart by their names
+        Some(dir) if dir.as_os_str().is_empty() => {
+            let name = relative.to_string_lossy();
+            relative.parent() == Some(dir) && name.starts_with(BACKUP_PREFIX) && name.ends_with(".zip")
+        }
+        Some(dir) => relative.starts_with(dir),
+        None => false,
+    }
+}
+
+/// Whether a file belongs to the running app rather than to the notes, e.g. the lock or an unfinished write,
+/// or holds the secrets of this device or the database of a SQLite vault, which are neither backed up nor
+/// replaced or removed by a restore.
+fn is_skipped(relative: &Path) -> bool {
+    let name = relative.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    SKIPPED_FILES.iter().any(|file| relative == Path::new(file)) || name.ends_with(".tmp")
+}
+
+fn invalid_data(err: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let home = temp_notes_dir.parent().unwrap();
+        let root = Notes::get_notes_dir().unwrap();
+        Notes::create_note_file("groceries", "Apples").unwrap();
+        Notes::create_note_file("meetings/standup", "Notes").unwrap();
+        fs::write(root.join(LOCK_FILE), "{}").unwrap();
+        fs::write(root.join(SECRETS_KEY_FILE), "old key").unwrap();
+
+        let backup = create(&home.join("backups")).unwrap();
+        let mut archive = ZipArchive::new(File::open(&backup).unwrap()).unwrap();
+        assert!(archive.by_name(SECRETS_KEY_FILE).is_err());
+        fs::write(root.join(SECRETS_KEY_FILE), "new key").unwrap();
+        fs::write(root.join(SECRETS_FILE), "{}").unwrap();
+        assert!(backup.file_name().unwrap().to_string_lossy().starts_with(BACKUP_PREFIX));
+
+        Notes::update_note_file("groceries", "Pears").unwrap();
+        Notes::delete_note_file("meetings/standup").unwrap();
+        Notes::create_note_file("later", "Added after the backup").unwrap();
+
+        let merge = RestoreOptions {
+            dry_run: true,
+            merge: true,
+        };
+        let actions: Vec<(String, RestoreAction)> = restore(&backup, merge)
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.path.to_string_lossy().replace('\\', "/"), change.action))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("groceries.md".to_string(), RestoreAction::Kept),
+                ("meetings/standup.md".to_string(), RestoreAction::Created),
+            ]
+        );
+        assert!(Notes::read_note_file("meetings/standup").is_err());
+
+        let changes = restore(&backup, RestoreOptions::default()).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(Notes::read_note_file("groceries").unwrap(), "Apples");
+        assert_eq!(Notes::read_note_file("meetings/standup").unwrap(), "Notes");
+        assert!(Notes::read_note_file("later").is_err());
+        assert!(root.join(LOCK_FILE).exists());
+        assert_eq!(fs::read_to_string(root.join(SECRETS_KEY_FILE)).unwrap(), "new key");
+        assert!(root.join(SECRETS_FILE).exists());
+    }
+
+    #[test]
+    fn test_restore_keeps_backups_in_notes_dir() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+        let root = Notes::get_notes_dir().unwrap();
+        Notes::create_note_file("groceries", "Apples").unwrap();
+        fs::write(root.join(DATABASE_FILE), "database").unwrap();
+
+        let first = create(&root.join("backups")).unwrap();
+        let mut archive = ZipArchive::new(File::open(&first).unwrap()).unwrap();
+        assert_eq!(archive.len(), 1);
+        assert!(archive.by_name("groceries.md").is_ok());
+
+        Notes::create_note_file("later", "Added after the backup").unwrap();
+        let changes = restore(&first, RestoreOptions::default()).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(Notes::read_note_file("later").is_err());
+        assert!(first.exists());
+        assert!(root.join(DATABASE_FILE).exists());
+
+        let second = create(&root).unwrap();
+        assert!(restore(&second, RestoreOptions::default()).unwrap().is_empty());
+        assert!(second.exists());
+    }
+
+    #[test]
+    fn test_retained_backups() {
+        let time = |date: &str| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+        // Newest first: two backups on Friday, then one a day back to the previous weeks
+        let times = [
+            time("2024-03-15 18:00"),
+            time("2024-03-15 09:00"),
+            time("2024-03-14 09:00"),
+            time("2024-03-13 09:00"),
+            time("2024-03-08 09:00"),
+            time("2024-03-01 09:00"),
+            time("2024-02-23 09:00"),
+        ];
+        let policy = BackupPolicy {
+            keep_daily: 2,
+            keep_weekly: 3,
+            ..BackupPolicy::default()
+        };
+        assert_eq!(
+            retained(&times, &policy),
+            vec![true, false, true, false, true, true, false]
+        );
+
+        let none = BackupPolicy {
+            keep_daily: 0,
+            keep_weekly: 0,
+            ..BackupPolicy::default()
+        };
+        assert_eq!(retained(&times[..2], &none), vec![true, false]);
+    }
+
+    #[test]
+    fn test_backup_is_due() {
+        let time = |date: &str| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+        let now = time("2024-03-15 18:00");
+        assert!(!is_due(BackupSchedule::Off, None, now, true));
+        assert!(is_due(BackupSchedule::OnStart, Some(now), now, true));
+        assert!(!is_due(BackupSchedule::OnStart, None, now, false));
+        assert!(is_due(BackupSchedule::Daily, None, now, false));
+        assert!(!is_due(BackupSchedule::Daily, Some(time("2024-03-15 09:00")), now, false));
+        assert!(is_due(BackupSchedule::Daily, Some(time("2024-03-14 18:00")), now, false));
+    }
+}
@@ -0,0 +1,514 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate};
+
+use crate::config::Config;
+use crate::front_matter::FrontMatter;
+use crate::notes::{self, Notes};
+use crate::search::SearchOptions;
+use crate::storage::atomic_write;
+use crate::tags;
+use crate::title;
+use crate::todos::Todos;
+
+/// Which timestamp of a note a date range is matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateField {
+    /// When the note was created. Notes whose creation time is unknown use their modification time.
+    Created,
+    /// When the note was last modified.
+    #[default]
+    Modified,
+}
+
+impl DateField {
+    /// Returns the name of the field as shown to the user.
+    pub fn label(self) -> &'static str {
+        match self {
+            DateField::Created => "Created",
+            DateField::Modified => "Modified",
+        }
+    }
+}
+
+/// An inclusive range of days in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    /// The first day of the range.
+    pub start: NaiveDate,
+    /// The last day of the range.
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    /// Parses a date range relative to today.
+    ///
+    /// Accepted are `today`, `yesterday`, `this` or `last` followed by `week`, `month`,
+    /// `quarter` or `year`, `last <n> days`, a single `YYYY-MM-DD` day and `YYYY-MM-DD..YYYY-MM-DD`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - The range, e.g. `last quarter`.
+    /// * `today` - The day the range is relative to.
+    ///
+    /// # Returns
+    ///
+    /// The range, or `None` if it is not understood.
+    pub fn parse(expr: &str, today: NaiveDate) -> Option<DateRange> {
+        let expr = expr.trim().to_lowercase();
+        let words: Vec<&str> = expr.split_whitespace().collect();
+        let range = |start, end| Some(DateRange { start, end });
+        match words.as_slice() {
+            ["today"] => range(today, today),
+            ["yesterday"] => {
+                let yesterday = today.pred_opt()?;
+                range(yesterday, yesterday)
+            }
+            ["last", days, "days"] => {
+                let days: u64 = days.parse().ok().filter(|days| *days > 0)?;
+                range(today.checked_sub_days(Days::new(days - 1))?, today)
+            }
+            [which @ ("this" | "last"), unit] => {
+                let (start, end) = period_of(today, unit)?;
+                if *which == "this" {
+                    return range(start, end);
+                }
+                period_of(start.pred_opt()?, unit).and_then(|(start, end)| range(start, end))
+            }
+            [single] => match single.split_once("..") {
+                Some((start, end)) => {
+                    let start = NaiveDate::parse_from_str(start, "%Y-%m-%d").ok()?;
+                    let end = NaiveDate::parse_from_str(end, "%Y-%m-%d").ok()?;
+                    (start <= end).then_some(DateRange { start, end })
+                }
+                None => {
+                    let day = NaiveDate::parse_from_str(single, "%Y-%m-%d").ok()?;
+                    range(day, day)
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns whether a day lies within the range.
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        self.start <= day && day <= self.end
+    }
+}
+
+/// Returns the first and last day of the week, month, quarter or year containing `day`.
+fn period_of(day: NaiveDate, unit: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (start, months) = match unit {
+        "week" => {
+            let start = day.checked_sub_days(Days::new(day.weekday().num_days_from_monday() as u64))?;
+            return Some((start, start.checked_add_days(Days::new(6))?));
+        }
+        "month" => (NaiveDate::from_ymd_opt(day.year(), day.month(), 1)?, 1),
+        "quarter" => (NaiveDate::from_ymd_opt(day.year(), (day.month0() / 3) * 3 + 1, 1)?, 3),
+        "year" => (NaiveDate::from_ymd_opt(day.year(), 1, 1)?, 12),
+        _ => return None,
+    };
+    let end = start.checked_add_months(Months::new(months))?.pred_opt()?;
+    Some((start, end))
+}
+
+/// An operation applied to every note a query found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchAction {
+    /// Copies the notes into a directory, keeping their folders.
+    Export(PathBuf),
+    /// Adds a tag to the front matter of the notes.
+    Tag(String),
+    /// Renames a tag in the notes.
+    RenameTag { from: String, to: String },
+    /// Moves the notes into a folder, or to the top level if it is empty, along with their todos.
+    Move(String),
+    /// Moves the notes to the trash, detaching their todos.
+    Delete,
+    /// Replaces the matches of a search in the notes.
+    Replace {
+        query: String,
+        replacement: String,
+        options: SearchOptions,
+    },
+}
+
+impl BatchAction {
+    /// Describes the outcome of applying the action to `count` notes.
+    pub fn describe(&self, count: usize) -> String {
+        match self {
+            BatchAction::Export(dir) => format!("Exported {} notes to {}", count, dir.display()),
+            BatchAction::Tag(tag) => format!("Tagged {} notes with #{}", count, tag),
+            BatchAction::RenameTag { from, to } => format!("Renamed #{} to #{} in {} notes", from, to, count),
+            BatchAction::Move(folder) if folder.trim().is_empty() => format!("Moved {} notes to the top level", count),
+            BatchAction::Move(folder) => format!("Moved {} notes to {}", count, folder.trim()),
+            BatchAction::Delete => format!("Moved {} notes to the trash", count),
+            BatchAction::Replace { query, replacement, .. } => {
+                format!("Replaced {} with {} in {} notes", query, replacement, count)
+            }
+        }
+    }
+
+    /// Returns whether the action edits the text of the notes.
+    fn edits_text(&self) -> bool {
+        matches!(
+            self,
+            BatchAction::Tag(_) | BatchAction::RenameTag { .. } | BatchAction::Replace { .. }
+        )
+    }
+
+    /// Compiles the pattern of a replace action.
+    fn replace_pattern(&self) -> io::Result<Option<regex::Regex>> {
+        match self {
+            BatchAction::Replace { query, options, .. } => options
+                .pattern(query)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A change a batch operation would make to one note, as listed by a dry run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedChange {
+    /// The title of the note.
+    pub title: String,
+    /// What would happen to the note.
+    pub summary: String,
+    /// Whether the change is applied; users can opt out of single changes.
+    pub selected: bool,
+}
+
+/// Finds the notes whose creation or modification date lies within a range.
+///
+/// # Arguments
+///
+/// * `field` - The timestamp to match.
+/// * `range` - The days to match.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<String>>` containing the titles of the matching notes in alphabetical order.
+pub fn find(field: DateField, range: DateRange) -> io::Result<Vec<String>> {
+    let mut titles: Vec<String> = Notes::list_notes_with_meta()?
+        .into_iter()
+        .filter(|meta| {
+            let time = match field {
+                DateField::Created => meta.created.unwrap_or(meta.modified),
+                DateField::Modified => meta.modified,
+            };
+            range.contains(local_day(time))
+        })
+        .map(|meta| meta.title)
+        .collect();
+    titles.sort();
+    Ok(titles)
+}
+
+/// Lists the changes an action would make to a list of notes without making them.
+///
+/// Notes the action would leave unchanged, such as notes that already have a tag, are left out, as are
+/// passphrase-protected notes for actions that edit the text.
+///
+/// # Arguments
+///
+/// * `titles` - The titles of the notes.
+/// * `action` - The action to plan.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<PlannedChange>>` containing one selected change per affected note.
+pub fn plan(titles: &[String], action: &BatchAction) -> io::Result<Vec<PlannedChange>> {
+    let mut changes = Vec::new();
+    let pattern = action.replace_pattern()?;
+    // Exported files get the extension of the notes
+    let extension = Config::load()?.note_extension;
+    for title in titles {
+        let summary = match action {
+            BatchAction::Export(dir) => {
+                let path = dir.join(title::to_path(&Notes::get_notes_dir()?, title, extension.as_str())?);
+                if path.exists() {
+                    format!("Overwrite {}", path.display())
+                } else {
+                    format!("Write {}", path.display())
+                }
+            }
+            BatchAction::Tag(tag) => {
+                let content = Notes::read_note_file(title)?;
+                if is_protected(&content) {
+                    continue;
+                }
+                if tags::extract(&content).contains(tag) {
+                    continue;
+                }
+                format!("Add #{}", tag)
+            }
+            BatchAction::RenameTag { from, to } => {
+                let content = Notes::read_note_file(title)?;
+                if is_protected(&content) {
+                    continue;
+                }
+                if tags::rename(&content, from, to).is_none() {
+                    continue;
+                }
+                format!("Rename #{} to #{}", from, to)
+            }
+            BatchAction::Move(folder) => {
+                let moved = notes::moved_title(title, folder);
+                if &moved == title {
+                    continue;
+                }
+                format!("Move to {}", moved)
+            }
+            BatchAction::Delete => "Move to the trash".to_string(),
+            BatchAction::Replace {
+                replacement, options, ..
+            } => {
+                let content = Notes::read_note_file(title)?;
+                if is_protected(&content) {
+                    continue;
+                }
+                let Some(pattern) = &pattern else {
+                    continue;
+                };
+                match options.replace_all(&content, pattern, replacement).1 {
+                    0 => continue,
+                    1 => "Replace 1 match".to_string(),
+                    count => format!("Replace {} matches", count),
+                }
+            }
+        };
+        changes.push(PlannedChange {
+            title: title.clone(),
+            summary,
+            selected: true,
+        });
+    }
+    Ok(changes)
+}
+
+/// Applies an action to a list of notes.
+///
+/// # Arguments
+///
+/// * `titles` - The titles of the notes.
+/// * `action` - The action to apply.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of notes that were exported, changed or deleted.
+/// Passphrase-protected notes are skipped by actions that edit the text.
+pub fn apply(titles: &[String], action: &BatchAction) -> io::Result<usize> {
+    let mut count = 0;
+    let mut todos_changed = false;
+    let pattern = action.replace_pattern()?;
+    let extension = Config::load()?.note_extension;
+    let mut todos = match action {
+        BatchAction::Delete | BatchAction::Move(_) => Todos::load_or_recover()?.0,
+        _ => Todos::new(),
+    };
+    for title in titles {
+        let content = Notes::read_note_file(title)?;
+        if action.edits_text() && is_protected(&content) {
+            continue;
+        }
+        match action {
+            BatchAction::Export(dir) => {
+                let path = dir.join(title::to_path(&Notes::get_notes_dir()?, title, extension.as_str())?);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                atomic_write(&path, content.as_bytes(), false)?;
+            }
+            BatchAction::Tag(tag) => {
+                let (mut front_matter, body) = FrontMatter::parse(&content);
+                if tags::extract(&content).contains(tag) {
+                    continue;
+                }
+                front_matter.add_tags(std::slice::from_ref(tag));
+                Notes::update_note_file(title, &front_matter.render(body))?;
+            }
+            BatchAction::RenameTag { from, to } => {
+                let Some(renamed) = tags::rename(&content, from, to) else {
+                    continue;
+                };
+                Notes::update_note_file(title, &renamed)?;
+            }
+            BatchAction::Move(folder) => {
+                let moved = Notes::move_to_folder(title, folder)?;
+                if &moved == title {
+                    continue;
+                }
+                todos_changed |= todos.rename_note(title, &moved);
+            }
+            BatchAction::Delete => {
+                Notes::trash(title)?;
+                todos_changed |= todos.detach_note(title);
+            }
+            BatchAction::Replace {
+                replacement, options, ..
+            } => {
+                let Some(pattern) = &pattern else {
+                    continue;
+                };
+                let (replaced, matches) = options.replace_all(&content, pattern, replacement);
+                if matches == 0 {
+                    continue;
+                }
+                Notes::update_note_file(title, &replaced)?;
+            }
+        }
+        count += 1;
+    }
+    if todos_changed {
+        todos.save_to_file()?;
+    }
+    Ok(count)
+}
+
+/// Applies the selected changes of a dry run.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of notes that were exported, changed or deleted.
+pub fn apply_plan(changes: &[PlannedChange], action: &BatchAction) -> io::Result<usize> {
+    let titles: Vec<String> = changes
+        .iter()
+        .filter(|change| change.selected)
+        .map(|change| change.title.clone())
+        .collect();
+    apply(&titles, action)
+}
+
+// The body of a passphrase-protected note is encrypted, so its text can't be searched or edited
+fn is_protected(content: &str) -> bool {
+    FrontMatter::parse(content).0.is_encrypted()
+}
+
+fn local_day(time: SystemTime) -> NaiveDate {
+    DateTime::<Local>::from(time).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let today = day("2024-05-15");
+        let parse = |expr| DateRange::parse(expr, today).map(|range| (range.start, range.end));
+        assert_eq!(parse("today"), Some((today, today)));
+        assert_eq!(parse("last 7 days"), Some((day("2024-05-09"), today)));
+        assert_eq!(parse("this week"), Some((day("2024-05-13"), day("2024-05-19"))));
+        assert_eq!(parse("last month"), Some((day("2024-04-01"), day("2024-04-30"))));
+        assert_eq!(parse("Last Quarter"), Some((day("2024-01-01"), day("2024-03-31"))));
+        assert_eq!(parse("last year"), Some((day("2023-01-01"), day("2023-12-31"))));
+        assert_eq!(
+            parse("2024-01-10..2024-02-01"),
+            Some((day("2024-01-10"), day("2024-02-01")))
+        );
+        assert_eq!(parse("2024-02-01..2024-01-10"), None);
+        assert_eq!(parse("last fortnight"), None);
+        assert_eq!(parse("last 0 days"), None);
+    }
+
+    #[test]
+    fn test_find_and_tag() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("a", "first").unwrap();
+        Notes::create_note_file("b", "---\ntags: old\n---\nsecond").unwrap();
+
+        let today = Local::now().date_naive();
+        let titles = find(DateField::Modified, DateRange::parse("today", today).unwrap()).unwrap();
+        assert_eq!(titles, vec!["a", "b"]);
+        let titles = find(DateField::Modified, DateRange::parse("last year", today).unwrap()).unwrap();
+        assert!(titles.is_empty());
+
+        let tagged = apply(&["a".to_string(), "b".to_string()], &BatchAction::Tag("old".to_string())).unwrap();
+        assert_eq!(tagged, 1);
+        assert_eq!(
+            FrontMatter::parse(&Notes::read_note_file("a").unwrap()).0.tags(),
+            vec!["old"]
+        );
+
+        let export_dir = temp_notes_dir.parent().unwrap().join("export");
+        assert_eq!(apply(&["b".to_string()], &BatchAction::Export(export_dir.clone())).unwrap(), 1);
+        assert!(export_dir.join("b.md").exists());
+
+        let mut todos = Todos::new();
+        todos.add_to_note("Reply".to_string(), "a".to_string());
+        todos.save_to_file().unwrap();
+        let titles = vec!["a".to_string(), "work/b".to_string()];
+        Notes::create_note_file("work/b", "third").unwrap();
+        let changes = plan(&titles, &BatchAction::Move("work".to_string())).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].summary, "Move to work/a");
+        assert_eq!(apply(&titles, &BatchAction::Move("work/".to_string())).unwrap(), 1);
+        assert!(Notes::read_note_file("work/a").unwrap().ends_with("first"));
+        assert!(!Notes::note_exists("a").unwrap());
+        assert_eq!(Todos::load_from_file().unwrap().items[0].note.as_deref(), Some("work/a"));
+    }
+
+    #[test]
+    fn test_dry_run_changes_nothing() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("a", "---\ntags: draft\n---\nfirst").unwrap();
+        Notes::create_note_file("b", "second #draft").unwrap();
+        Notes::create_note_file("c", "third").unwrap();
+        let titles = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let rename = BatchAction::RenameTag {
+            from: "draft".to_string(),
+            to: "wip".to_string(),
+        };
+        let mut changes = plan(&titles, &rename).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].summary, "Rename #draft to #wip");
+        assert_eq!(Notes::read_note_file("b").unwrap(), "second #draft");
+
+        changes[0].selected = false;
+        assert_eq!(apply_plan(&changes, &rename).unwrap(), 1);
+        assert_eq!(Notes::read_note_file("a").unwrap(), "---\ntags: draft\n---\nfirst");
+        assert_eq!(Notes::read_note_file("b").unwrap(), "second #wip");
+
+        let replace = BatchAction::Replace {
+            query: "second".to_string(),
+            replacement: "2nd".to_string(),
+            options: SearchOptions::default(),
+        };
+        let changes = plan(&titles, &replace).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].summary, "Replace 1 match");
+        assert_eq!(apply_plan(&changes, &replace).unwrap(), 1);
+        assert_eq!(Notes::read_note_file("b").unwrap(), "2nd #wip");
+
+        Notes::create_note_file("d", "---\nencrypted: chacha20poly1305\n---\nsecond #wip").unwrap();
+        let titles = vec!["b".to_string(), "d".to_string()];
+        assert_eq!(plan(&titles, &BatchAction::Tag("done".to_string())).unwrap().len(), 1);
+        assert_eq!(apply(&titles, &BatchAction::Tag("done".to_string())).unwrap(), 1);
+        assert_eq!(
+            Notes::read_note_file("d").unwrap(),
+            "---\nencrypted: chacha20poly1305\n---\nsecond #wip"
+        );
+        Notes::delete_note_file("d").unwrap();
+
+        let titles = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let changes = plan(&titles, &BatchAction::Delete).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(Notes::note_path("c").unwrap().exists());
+        assert_eq!(apply_plan(&changes[2..], &BatchAction::Delete).unwrap(), 1);
+        assert!(!Notes::note_exists("c").unwrap());
+        assert_eq!(Notes::list_trashed().unwrap(), vec!["c"]);
+    }
+}
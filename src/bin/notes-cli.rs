@@ -0,0 +1,249 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! A command-line interface to the notes and todos in `~/.notes`, for use from terminals and scripts.
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal, Read};
+use std::process;
+
+use eframe_template::attachments;
+use eframe_template::export::{self, ExportPreset, NoteFormat};
+use eframe_template::notes::Notes;
+use eframe_template::scheduler::{self, JobStatus};
+use eframe_template::sqlite;
+use eframe_template::todos::{self, DeadlineKind, Todos};
+
+const USAGE: &str = "Usage: notes-cli <command> [arguments]
+
+Commands:
+  list                                  List all notes
+  new <title> [--template <name>]       Create a note; piped input becomes its content
+  show <title>                          Print a note
+  edit <title>                          Open a note in $VISUAL or $EDITOR
+  rm <title>                            Delete a note
+  export <title> [--format <md|html|txt>] [--to <dir>] [--stylesheet <css>] [--attachments]
+                                        Export a note; without options, repeat its last export
+  todo add <description> [--due <YYYY-MM-DD>] [--target]
+                                        Add a todo
+  todo list                             List todos with their numbers
+  todo done <n>                         Mark the todo with number n as done
+  gc                                    Delete attachments no note links to anymore
+  run-job <name>                        Run a scheduled job now, e.g. from cron
+  migrate sqlite                        Keep the notes in a SQLite database from now on,
+                                        importing the existing note files";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("notes-cli: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["list"] => {
+            let mut titles = Notes::list_notes().map_err(|err| err.to_string())?;
+            titles.sort_by_key(|title| title.to_lowercase());
+            for title in titles {
+                println!("{}", title);
+            }
+            Ok(())
+        }
+        ["new", title, rest @ ..] => new_note(title, rest),
+        ["show", title] => {
+            let content = Notes::read_note_file(title).map_err(|err| format!("Failed to read {}: {}", title, err))?;
+            print!("{}", content);
+            Ok(())
+        }
+        ["edit", title] => edit_note(title),
+        ["rm", title] => {
+            Notes::delete_note_file(title).map_err(|err| format!("Failed to delete {}: {}", title, err))?;
+            let mut todos = load_todos()?;
+            if todos.detach_note(title) {
+                todos.save_to_file().map_err(|err| err.to_string())?;
+            }
+            Ok(())
+        }
+        ["export", title, rest @ ..] => export_note(title, rest),
+        ["todo", "add", rest @ ..] => add_todo(rest),
+        ["todo", "list"] => {
+            for (index, todo) in load_todos()?.items.iter().enumerate() {
+                let mut line = format!("{}. [{}] {}", index + 1, if todo.done { "x" } else { " " }, todo.description);
+                if let Some(due_date) = todo.due_date {
+                    line.push_str(&format!(
+                        " ({} {})",
+                        todo.deadline.label().to_lowercase(),
+                        todos::format_due_date(due_date)
+                    ));
+                }
+                if let Some(note) = &todo.note {
+                    line.push_str(&format!(" [{}]", note));
+                }
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        ["todo", "done", number] => {
+            let mut todos = load_todos()?;
+            let todo = number
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| todos.items.get_mut(number.checked_sub(1)?))
+                .ok_or_else(|| format!("No todo number {}", number))?;
+            todo.done = true;
+            todos.save_to_file().map_err(|err| err.to_string())
+        }
+        ["gc"] => {
+            let removed = attachments::collect_garbage().map_err(|err| format!("Failed to clean up: {}", err))?;
+            for path in &removed {
+                println!("Deleted {}", path);
+            }
+            println!("{} unused attachments deleted", removed.len());
+            Ok(())
+        }
+        ["run-job", name] => {
+            let status = scheduler::run_job(name).map_err(|err| format!("Failed to run {}: {}", name, err))?;
+            match status {
+                JobStatus::Succeeded(message) => {
+                    println!("{}", message);
+                    Ok(())
+                }
+                JobStatus::Failed(err) => Err(format!("{} failed: {}", name, err)),
+            }
+        }
+        ["migrate", "sqlite"] => {
+            let imported = sqlite::migrate().map_err(|err| format!("Failed to migrate: {}", err))?;
+            println!("Imported {} notes into {}", imported, sqlite::DATABASE_FILE);
+            Ok(())
+        }
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("invalid arguments\n\n{}", USAGE)),
+    }
+}
+
+fn new_note(title: &str, options: &[&str]) -> Result<(), String> {
+    let template = match options {
+        [] => None,
+        ["--template", name] => Some(*name),
+        _ => return Err(format!("invalid arguments\n\n{}", USAGE)),
+    };
+    if Notes::note_exists(title).map_err(|err| err.to_string())? {
+        return Err(format!("A note named {} already exists", title));
+    }
+
+    let result = match template {
+        Some(template) => Notes::create_from_template(title, template, &HashMap::new()),
+        None => {
+            // Only read piped input, so that an interactive terminal doesn't wait for EOF.
+            let mut content = String::new();
+            if !io::stdin().is_terminal() {
+                io::stdin()
+                    .read_to_string(&mut content)
+                    .map_err(|err| format!("Failed to read stdin: {}", err))?;
+            }
+            Notes::create_note(title, &content)
+        }
+    };
+    result.map_err(|err| format!("Failed to create {}: {}", title, err))
+}
+
+fn edit_note(title: &str) -> Result<(), String> {
+    let path = Notes::note_path(title).map_err(|err| err.to_string())?;
+    if !path.exists() {
+        return Err(format!("No note named {}", title));
+    }
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Allow editors with arguments, e.g. `code --wait`.
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("The editor command is empty")?;
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .map_err(|err| format!("Failed to start {}: {}", program, err))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+fn export_note(title: &str, args: &[&str]) -> Result<(), String> {
+    let preset = ExportPreset::load(title).map_err(|err| format!("Failed to read {}: {}", title, err))?;
+    let mut format = preset.as_ref().map(|preset| preset.format);
+    let mut destination = preset.as_ref().map(|preset| preset.destination.clone());
+    let mut stylesheet = preset.as_ref().and_then(|preset| preset.stylesheet.clone());
+    let mut include_attachments = preset.as_ref().is_some_and(|preset| preset.include_attachments);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--format" => {
+                let name = args.next().ok_or("--format needs a format")?;
+                format = Some(NoteFormat::parse(name).ok_or_else(|| format!("Unknown format: {}", name))?);
+            }
+            "--to" => destination = Some(args.next().ok_or("--to needs a directory")?.to_string()),
+            "--stylesheet" => stylesheet = Some(args.next().ok_or("--stylesheet needs a file")?.to_string()),
+            "--attachments" => include_attachments = true,
+            _ => return Err(format!("invalid arguments\n\n{}", USAGE)),
+        }
+    }
+    let (Some(format), Some(destination)) = (format, destination) else {
+        return Err(format!("{} has not been exported before; pass --format and --to", title));
+    };
+    let preset = ExportPreset {
+        format,
+        destination,
+        stylesheet,
+        include_attachments,
+    };
+    let path = export::export(title, &preset).map_err(|err| format!("Failed to export {}: {}", title, err))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+fn add_todo(args: &[&str]) -> Result<(), String> {
+    let mut description = Vec::new();
+    let mut due_date = None;
+    let mut deadline = DeadlineKind::Hard;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--due" => {
+                let date = args.next().ok_or("--due needs a date")?;
+                due_date = Some(todos::parse_due_date(date).ok_or_else(|| format!("Invalid date: {}", date))?);
+            }
+            "--target" => deadline = DeadlineKind::Target,
+            word => description.push(word),
+        }
+    }
+    if description.is_empty() {
+        return Err(format!("invalid arguments\n\n{}", USAGE));
+    }
+
+    let mut todos = load_todos()?;
+    todos.add(description.join(" "), due_date);
+    if let Some(todo) = todos.items.last_mut() {
+        todo.deadline = deadline;
+    }
+    todos.save_to_file().map_err(|err| err.to_string())
+}
+
+fn load_todos() -> Result<Todos, String> {
+    let (todos, recovery) = Todos::load_or_recover().map_err(|err| format!("Failed to load todos: {}", err))?;
+    if let Some(recovery) = recovery {
+        eprintln!(
+            "warning: the todos file was corrupted ({}); recovered {} todos, the damaged file is kept at {}",
+            recovery.error,
+            recovery.recovered,
+            recovery.backup.display()
+        );
+    }
+    Ok(todos)
+}
@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+
+use crate::notes::{self, DAILY_FOLDER};
+use crate::todos::Todos;
+
+/// The days of a month that have something on them, for the dots of the calendar.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CalendarMarks {
+    /// The days with a daily note.
+    pub daily_notes: BTreeSet<NaiveDate>,
+    /// The number of open todos due on each day.
+    pub due_todos: BTreeMap<NaiveDate, usize>,
+}
+
+impl CalendarMarks {
+    /// Finds the daily notes and the due todos of a month.
+    ///
+    /// # Arguments
+    ///
+    /// * `month` - Any day of the month.
+    /// * `titles` - The titles of all notes.
+    /// * `todos` - The todos.
+    pub fn collect(month: NaiveDate, titles: &[String], todos: &Todos) -> CalendarMarks {
+        let in_month = |day: &NaiveDate| day.year() == month.year() && day.month() == month.month();
+        let daily_notes = titles.iter().filter_map(|title| daily_date(title)).filter(in_month).collect();
+        let mut due_todos = BTreeMap::new();
+        for day in todos.items.iter().filter(|todo| !todo.done).filter_map(|todo| todo.due_day()) {
+            if in_month(&day) {
+                *due_todos.entry(day).or_insert(0) += 1;
+            }
+        }
+        CalendarMarks { daily_notes, due_todos }
+    }
+}
+
+/// Returns the date of a daily note.
+///
+/// # Arguments
+///
+/// * `title` - The title of a note, e.g. `daily/2024-05-01`.
+///
+/// # Returns
+///
+/// The date, or `None` if the note isn't a daily note.
+pub fn daily_date(title: &str) -> Option<NaiveDate> {
+    let date = title.strip_prefix(DAILY_FOLDER)?.strip_prefix('/')?;
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    // Titles such as `daily/2024-5-1` parse too, but aren't the daily note of the day
+    (notes::daily_title(date) == title).then_some(date)
+}
+
+/// Lays out the days of a month in weeks from Monday to Sunday.
+///
+/// # Arguments
+///
+/// * `month` - Any day of the month.
+///
+/// # Returns
+///
+/// The weeks the month spans, with `None` for the days of the months before and after it.
+pub fn month_weeks(month: NaiveDate) -> Vec<[Option<NaiveDate>; 7]> {
+    let first = first_of_month(month);
+    let mut day = first - Duration::days(i64::from(first.weekday().num_days_from_monday()));
+    let mut weeks = Vec::new();
+    while weeks.is_empty() || (day.year(), day.month()) == (first.year(), first.month()) {
+        let mut week = [None; 7];
+        for slot in &mut week {
+            *slot = (day.month() == first.month()).then_some(day);
+            day += Duration::days(1);
+        }
+        weeks.push(week);
+    }
+    weeks
+}
+
+/// Returns the first day of a month.
+///
+/// # Arguments
+///
+/// * `month` - Any day of the month.
+/// * `months` - How many months to go forward, or back if negative.
+pub fn shift_month(month: NaiveDate, months: i32) -> NaiveDate {
+    let first = first_of_month(month);
+    let shifted = if months < 0 {
+        first.checked_sub_months(Months::new(months.unsigned_abs()))
+    } else {
+        first.checked_add_months(Months::new(months.unsigned_abs()))
+    };
+    shifted.unwrap_or(first)
+}
+
+fn first_of_month(day: NaiveDate) -> NaiveDate {
+    day.with_day(1).unwrap_or(day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_month_weeks() {
+        // March 2024 starts on a Friday and ends on a Sunday
+        let weeks = month_weeks(date("2024-03-14"));
+        assert_eq!(weeks.len(), 5);
+        assert_eq!(weeks[0][..4], [None; 4]);
+        assert_eq!(weeks[0][4], Some(date("2024-03-01")));
+        assert_eq!(weeks[4][6], Some(date("2024-03-31")));
+
+        // February 2021 fits exactly into four weeks
+        assert_eq!(month_weeks(date("2021-02-01")).len(), 4);
+        // April 2024 ends on a Tuesday
+        assert_eq!(month_weeks(date("2024-04-30"))[4][2], None);
+    }
+
+    #[test]
+    fn test_shift_month() {
+        assert_eq!(shift_month(date("2024-01-31"), 1), date("2024-02-01"));
+        assert_eq!(shift_month(date("2024-01-15"), -1), date("2023-12-01"));
+        assert_eq!(shift_month(date("2024-01-15"), 0), date("2024-01-01"));
+    }
+
+    #[test]
+    fn test_marks() {
+        assert_eq!(daily_date("daily/2024-03-14"), Some(date("2024-03-14")));
+        assert_eq!(daily_date("daily/2024-3-14"), None);
+        assert_eq!(daily_date("journal/2024-03-14"), None);
+
+        let titles = ["daily/2024-03-14", "daily/2024-04-01", "Ideas"].map(String::from);
+        let mut todos = Todos::new();
+        let due = |date: &str| crate::todos::parse_due_date(date);
+        todos.add("Taxes".to_string(), due("2024-03-20"));
+        todos.add("Report".to_string(), due("2024-03-20"));
+        todos.add("Done".to_string(), due("2024-03-21"));
+        todos.items[2].done = true;
+        todos.add("Later".to_string(), due("2024-04-02"));
+
+        let marks = CalendarMarks::collect(date("2024-03-01"), &titles, &todos);
+        assert_eq!(marks.daily_notes.into_iter().collect::<Vec<_>>(), vec![date("2024-03-14")]);
+        assert_eq!(marks.due_todos.into_iter().collect::<Vec<_>>(), vec![(date("2024-03-20"), 2)]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{self, Read};
+use std::ops::Range;
+
+use url::Url;
+
+use crate::attachments;
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+
+/// Creates a note from a web page.
+///
+/// The page is fetched, reduced to its main content with readability extraction and
+/// converted to Markdown. Images are downloaded into the `attachments` folder and the
+/// source URL is recorded in the note's front matter.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the page to clip.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the title of the new note or an error.
+pub fn note_from_url(url: &str) -> io::Result<String> {
+    let url = parse_url(url)?;
+    let html = fetch(&url)?.into_string()?;
+    note_from_html(&html, Some(&url))
+}
+
+/// Creates a note from the HTML of a web page, e.g. as sent by a browser extension, the
+/// same way as `note_from_url`.
+///
+/// # Arguments
+///
+/// * `html` - The HTML of the page.
+/// * `url` - The URL of the page, which is recorded as the source and which relative image
+///   links are resolved against. Without it images are linked as they are instead of downloaded.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the title of the new note or an error.
+pub fn note_from_html(html: &str, url: Option<&Url>) -> io::Result<String> {
+    // Readability resolves links against the page, so pages without a URL get a placeholder
+    let base = match url {
+        Some(url) => url.clone(),
+        None => parse_url("http://localhost/")?,
+    };
+    let product = readability::extractor::extract(&mut html.as_bytes(), &base)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+    let mut markdown = html2md::parse_html(&product.content);
+    if let Some(url) = url {
+        for (range, src) in image_links(&markdown).into_iter().rev() {
+            match download_image(url, &src) {
+                Ok(attachment) => markdown.replace_range(range, &attachment),
+                Err(err) => log::warn!("Failed to download image {}: {}", src, err),
+            }
+        }
+    }
+
+    let title = unique_title(&clip_title(&product.title))?;
+    let mut front_matter = FrontMatter::default();
+    if let Some(url) = url {
+        front_matter.set("source", url.as_str());
+    }
+    let body = format!("# {}\n\n{}\n", product.title.trim(), markdown.trim());
+    Notes::create_note(&title, &front_matter.render(&body))?;
+    Ok(title)
+}
+
+/// Parses the URL of a page to clip.
+///
+/// # Returns
+///
+/// An `io::Result<Url>` containing the URL, or an `InvalidInput` error if it isn't one.
+pub fn parse_url(url: &str) -> io::Result<Url> {
+    Url::parse(url.trim()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// Fetches a URL, turning HTTP errors into `io::Error`s.
+fn fetch(url: &Url) -> io::Result<ureq::Response> {
+    ureq::get(url.as_str())
+        .call()
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+}
+
+/// Downloads an image into the `attachments` folder, reusing an identical earlier download.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the attachment path relative to the `.notes` directory.
+fn download_image(page_url: &Url, src: &str) -> io::Result<String> {
+    let image_url = page_url
+        .join(src)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut bytes = Vec::new();
+    fetch(&image_url)?.into_reader().read_to_end(&mut bytes)?;
+
+    let name = image_url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("image");
+    attachments::store(name, &bytes)
+}
+
+/// Returns a title that is not used by an existing note, appending a counter if needed.
+fn unique_title(title: &str) -> io::Result<String> {
+    let existing = Notes::list_notes()?;
+    let mut candidate = title.to_string();
+    let mut counter = 1;
+    while existing.contains(&candidate) {
+        counter += 1;
+        candidate = format!("{} {}", title, counter);
+    }
+    Ok(candidate)
+}
+
+/// Turns a page title into a note title by replacing characters that are not allowed in file names.
+pub fn clip_title(title: &str) -> String {
+    let title: String = title
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let title = title.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if title.is_empty() {
+        "Clipping".to_string()
+    } else {
+        title.to_string()
+    }
+}
+
+/// Finds the targets of Markdown image links (`![alt](src)`).
+///
+/// # Returns
+///
+/// The byte range of each target within `markdown` along with the target itself.
+pub fn image_links(markdown: &str) -> Vec<(Range<usize>, String)> {
+    let mut links = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = markdown[offset..].find("![") {
+        let alt_start = offset + start + 2;
+        let Some(alt_len) = markdown[alt_start..].find("](") else {
+            break;
+        };
+        let src_start = alt_start + alt_len + 2;
+        let Some(src_len) = markdown[src_start..].find(')') else {
+            break;
+        };
+        let src_end = src_start + src_len;
+        // Drop an optional title, as in `![alt](src "title")`.
+        let src = markdown[src_start..src_end].split_whitespace().next().unwrap_or("");
+        if !src.is_empty() {
+            links.push((src_start..src_start + src.len(), src.to_string()));
+        }
+        offset = src_end;
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clip_title() {
+        assert_eq!(clip_title("  A/B: testing?  "), "A-B- testing-");
+        assert_eq!(clip_title("..."), "Clipping");
+    }
+
+    #[test]
+    fn test_image_links() {
+        let markdown = "Intro ![cat](img/cat.png) and ![dog](https://x.org/dog.jpg \"Dog\").";
+        let links = image_links(markdown);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].1, "img/cat.png");
+        assert_eq!(&markdown[links[0].0.clone()], "img/cat.png");
+        assert_eq!(links[1].1, "https://x.org/dog.jpg");
+        assert_eq!(&markdown[links[1].0.clone()], "https://x.org/dog.jpg");
+    }
+}
@@ -0,0 +1,165 @@
+//! The commands entered in the command bar, kept per vault so that they can be recalled with the
+//! arrow keys, searched and run again.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+
+/// The name of the file in the `.notes` directory that stores the command history.
+pub const COMMAND_HISTORY_FILE: &str = ".command-history.json";
+
+/// How many commands are kept. The oldest are forgotten first.
+const MAX_COMMANDS: usize = 500;
+
+/// The commands entered in the command bar, persisted in the `.command-history.json` file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct CommandHistory {
+    /// The commands, oldest first. A command entered again moves to the end.
+    pub commands: Vec<String>,
+    // The index of the command shown while browsing with the arrow keys
+    #[serde(skip)]
+    position: Option<usize>,
+    // What was typed before browsing started, shown again when browsing past the newest command
+    #[serde(skip)]
+    draft: String,
+}
+
+impl CommandHistory {
+    /// Loads the command history from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<CommandHistory>` containing the history, or an empty one if no command was
+    /// entered yet.
+    pub fn load() -> io::Result<CommandHistory> {
+        let path = Self::get_history_file_path()?;
+        if !path.exists() {
+            return Ok(CommandHistory::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Saves the command history to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&Self::get_history_file_path()?, data.as_bytes(), true)
+    }
+
+    /// Adds a command that was entered, and stops browsing.
+    pub fn push(&mut self, command: &str) {
+        self.position = None;
+        self.draft.clear();
+        let command = command.trim();
+        if command.is_empty() {
+            return;
+        }
+        self.commands.retain(|entered| entered != command);
+        self.commands.push(command.to_string());
+        if self.commands.len() > MAX_COMMANDS {
+            self.commands.drain(..self.commands.len() - MAX_COMMANDS);
+        }
+    }
+
+    /// Returns the command entered last.
+    pub fn last(&self) -> Option<&str> {
+        self.commands.last().map(String::as_str)
+    }
+
+    /// Steps back to the command entered before the one shown, for the Up key.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - What is in the command bar, which is kept if browsing just started.
+    ///
+    /// # Returns
+    ///
+    /// The command to show, or `None` if there is no older one.
+    pub fn older(&mut self, input: &str) -> Option<&str> {
+        let position = match self.position {
+            None if !self.commands.is_empty() => {
+                self.draft = input.to_string();
+                self.commands.len() - 1
+            }
+            Some(position) if position > 0 => position - 1,
+            _ => return None,
+        };
+        self.position = Some(position);
+        Some(&self.commands[position])
+    }
+
+    /// Steps forward to the command entered after the one shown, for the Down key.
+    ///
+    /// # Returns
+    ///
+    /// The command to show, what was typed before browsing if the newest command was shown, or
+    /// `None` if the history isn't being browsed.
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position?;
+        if position + 1 < self.commands.len() {
+            self.position = Some(position + 1);
+            Some(&self.commands[position + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Finds the commands containing a text, ignoring case.
+    ///
+    /// # Returns
+    ///
+    /// The matching commands, newest first.
+    pub fn search<'a>(&'a self, query: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let query = query.to_lowercase();
+        self.commands
+            .iter()
+            .rev()
+            .filter(move |command| command.to_lowercase().contains(&query))
+            .map(String::as_str)
+    }
+
+    fn get_history_file_path() -> io::Result<PathBuf> {
+        Ok(Notes::get_notes_dir()?.join(COMMAND_HISTORY_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browse_and_search() {
+        let mut history = CommandHistory::default();
+        for command in ["add-todo Milk", " sync ", "", "lock", "sync"] {
+            history.push(command);
+        }
+        assert_eq!(history.commands, vec!["add-todo Milk", "lock", "sync"]);
+        assert_eq!(history.last(), Some("sync"));
+
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("add-"), Some("sync"));
+        assert_eq!(history.older("sync"), Some("lock"));
+        assert_eq!(history.older("lock"), Some("add-todo Milk"));
+        assert_eq!(history.older("add-todo Milk"), None);
+        assert_eq!(history.newer(), Some("lock"));
+        assert_eq!(history.newer(), Some("sync"));
+        assert_eq!(history.newer(), Some("add-"));
+        assert_eq!(history.newer(), None);
+
+        history.push("add-todo Eggs");
+        let found: Vec<&str> = history.search("ADD").collect();
+        assert_eq!(found, vec!["add-todo Eggs", "add-todo Milk"]);
+        assert_eq!(history.search("").count(), 4);
+    }
+}
@@ -0,0 +1,1152 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use chrono::Local;
+use dirs::home_dir;
+use eframe::egui;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::backup::{self, RestoreOptions};
+use crate::batch::{self, BatchAction, DateField, DateRange};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::clip;
+use crate::config::{Config, NoteExtension};
+use crate::export;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::feeds::{self, Feeds};
+use crate::ical;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::import;
+use crate::macros::{self, Macros};
+use crate::notes::{self, Notes};
+use crate::permalinks::Permalinks;
+use crate::scheduler::{self, Job, JobStatus, Schedule};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::secrets;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::share;
+use crate::stats::{self, ExportFormat};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sync;
+use crate::todos::{self, DeadlineKind, Todos};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::vault;
+use crate::vault::Vault;
+
+/// A command entered in the command bar.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `export-stats <csv|json> <path>`: exports per-note metrics for analysis.
+    ExportStats { format: ExportFormat, path: PathBuf },
+    /// `export-ical <path> [--daily]`: exports the todos with a due date, and with `--daily` the
+    /// daily notes, as an iCalendar file.
+    ExportIcal { path: PathBuf, daily_notes: bool },
+    /// `schedule <name> <interval> <command>`: runs a command automatically, e.g. `nightly`.
+    Schedule {
+        name: String,
+        interval_secs: u64,
+        command: String,
+    },
+    /// `unschedule <name>`: removes a scheduled job.
+    Unschedule { name: String },
+    /// `run-job <name>`: runs a scheduled job immediately.
+    RunJob { name: String },
+    /// `autosave <seconds>`: sets how long to wait after the last edit before saving.
+    SetAutosave { secs: f64 },
+    /// `duplicate [title]`: copies a note, by default the open one, e.g. to `ideas (copy)`.
+    Duplicate { title: Option<String> },
+    /// `lock [title]` / `unlock [title]`: makes a note, by default the open one, read-only or
+    /// editable again.
+    SetLocked { title: Option<String>, locked: bool },
+    /// `split [title]`: splits a note, by default the open one, into a note per top-level heading
+    /// and links to them.
+    Split { title: Option<String> },
+    /// `today [template]`: opens the daily note of today, creating it the first time from a template,
+    /// by default the one of its folder.
+    Today { template: Option<String> },
+    /// `convert-notes <md|txt>`: saves new notes with an extension from now on and renames the
+    /// existing note files to it.
+    ConvertNotes { extension: NoteExtension },
+    /// `permalinks [path]`: assigns publishing slugs, optionally writing a `_redirects` file.
+    Permalinks { redirects: Option<PathBuf> },
+    /// `export-site <dir>`: exports every note as a static web site.
+    ExportSite { dir: PathBuf },
+    /// `rotate-key`: replaces the key of the file secrets are kept in when there is no keychain,
+    /// re-encrypting them.
+    #[cfg(not(target_arch = "wasm32"))]
+    RotateKey,
+    /// `clear-secret <name>`: removes a key or token from the keychain.
+    #[cfg(not(target_arch = "wasm32"))]
+    ClearSecret { name: String },
+    /// `find-dates <created|modified> <range> [export <dir>|tag <tag>|rename-tag <from> <to>|move <folder>|delete] [--dry-run]`:
+    /// finds notes by date, e.g. `find-dates modified last quarter export ~/backup`, and optionally
+    /// changes them. With `--dry-run` the changes are listed instead of made.
+    FindByDate {
+        field: DateField,
+        range: String,
+        action: Option<BatchAction>,
+        dry_run: bool,
+    },
+    /// `record <name>`: starts recording the edits and commands of a macro in the editor.
+    RecordMacro { name: String },
+    /// `stop-recording`: stops recording and saves the macro.
+    StopRecording,
+    /// `play <name> [<folder>|--all]`: plays a macro on the open note, or from the top of
+    /// every note in a folder or the whole vault.
+    PlayMacro { name: String, folder: Option<String> },
+    /// `bind <name> <key|none>`: binds a macro to a key such as `F5`, which plays it on the open note.
+    BindMacro { name: String, key: Option<String> },
+    /// `todo [@project] <description>`: adds a todo, optionally to a project, e.g. `todo @work finish report`.
+    AddTodo {
+        description: String,
+        project: Option<String>,
+    },
+    /// `due <n> <YYYY-MM-DD|none> [hard|target]`: sets the due date of the `n`th todo.
+    SetDue {
+        index: usize,
+        due_date: Option<i64>,
+        deadline: DeadlineKind,
+    },
+    /// `clear-completed`: moves the completed todos to the archive of completed todos.
+    ClearCompleted,
+    /// `empty-trash`: deletes the notes in the trash for good.
+    EmptyTrash,
+    /// `backup [dir]`: backs up the whole notes directory into a zip file, by default in `~/notes-backups`.
+    #[cfg(not(target_arch = "wasm32"))]
+    Backup { dir: PathBuf },
+    /// `restore <file> [--merge] [--dry-run]`: restores the notes directory from a backup. With
+    /// `--merge` files that changed since the backup and files not in it are kept.
+    #[cfg(not(target_arch = "wasm32"))]
+    Restore { path: PathBuf, options: RestoreOptions },
+    /// `import <file>`: imports the notes of an Evernote (`.enex`), Joplin (`.jex`) or Notion (`.zip`) export.
+    #[cfg(not(target_arch = "wasm32"))]
+    Import { path: PathBuf },
+    /// `add-feed <name> <url>`: registers an RSS or Atom feed, e.g. `add-feed rust https://blog.rust-lang.org/feed.xml`.
+    #[cfg(not(target_arch = "wasm32"))]
+    AddFeed { name: String, url: String },
+    /// `remove-feed <name>`: stops fetching a feed, keeping the notes of its items.
+    #[cfg(not(target_arch = "wasm32"))]
+    RemoveFeed { name: String },
+    /// `fetch-feeds [name]`: saves the new items of every feed, or of one feed, as notes in `feeds/<name>`.
+    #[cfg(not(target_arch = "wasm32"))]
+    FetchFeeds { name: Option<String> },
+    /// `sync`: syncs the notes directory with the server set up in the preferences.
+    #[cfg(not(target_arch = "wasm32"))]
+    Sync,
+    /// `add-vault <name> <path>`: registers another notes directory, e.g. `add-vault work ~/work-notes`.
+    #[cfg(not(target_arch = "wasm32"))]
+    AddVault { name: String, path: String },
+    /// `switch-vault [name]`: switches the app to a registered vault, or back to `~/.notes` without a name.
+    #[cfg(not(target_arch = "wasm32"))]
+    SwitchVault { name: Option<String> },
+    /// `summarize`: asks the language model set up in the preferences to summarize the open note.
+    #[cfg(not(target_arch = "wasm32"))]
+    Summarize,
+    /// `generate-title`: asks the language model to suggest a title for the open note.
+    #[cfg(not(target_arch = "wasm32"))]
+    GenerateTitle,
+    /// `ask <question>`: asks the language model to answer a question from the notes that share the
+    /// most words with it.
+    #[cfg(not(target_arch = "wasm32"))]
+    Ask { question: String },
+    /// `share [title]`: uploads a note, by default the open one, to the paste service set up in the
+    /// preferences and copies the link.
+    #[cfg(not(target_arch = "wasm32"))]
+    Share { title: Option<String> },
+}
+
+impl Command {
+    /// Parses a command, with or without a leading `:`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The text entered in the command bar.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Command, String>` containing the command or a message describing why it is invalid.
+    pub fn parse(input: &str) -> Result<Command, String> {
+        let input = input.trim();
+        let input = input.strip_prefix(':').unwrap_or(input);
+        let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let args = args.trim();
+        match name {
+            "export-stats" => {
+                let (format, path) = args
+                    .split_once(char::is_whitespace)
+                    .ok_or("Usage: export-stats <csv|json> <path>")?;
+                let format = match format {
+                    "csv" => ExportFormat::Csv,
+                    "json" => ExportFormat::Json,
+                    other => return Err(format!("Unknown export format: {}", other)),
+                };
+                Ok(Command::ExportStats {
+                    format,
+                    path: expand_home(path.trim()),
+                })
+            }
+            "export-ical" => {
+                let (path, daily_notes) = match args.strip_suffix("--daily") {
+                    Some(path) => (path.trim_end(), true),
+                    None => (args, false),
+                };
+                if path.is_empty() {
+                    return Err("Usage: export-ical <path> [--daily]".to_string());
+                }
+                Ok(Command::ExportIcal {
+                    path: expand_home(path),
+                    daily_notes,
+                })
+            }
+            "schedule" => {
+                let mut parts = args.splitn(3, char::is_whitespace);
+                let (Some(name), Some(interval), Some(command)) = (parts.next(), parts.next(), parts.next()) else {
+                    return Err("Usage: schedule <name> <interval> <command>".to_string());
+                };
+                let interval_secs =
+                    scheduler::parse_interval(interval).ok_or_else(|| format!("Invalid interval: {}", interval))?;
+                // Reject jobs that could never run, or would run jobs themselves.
+                if Command::parse(command)?.manages_jobs() {
+                    return Err("Scheduled jobs can't schedule or run jobs".to_string());
+                }
+                Ok(Command::Schedule {
+                    name: name.to_string(),
+                    interval_secs,
+                    command: command.trim().to_string(),
+                })
+            }
+            "unschedule" if !args.is_empty() => Ok(Command::Unschedule { name: args.to_string() }),
+            "run-job" if !args.is_empty() => Ok(Command::RunJob { name: args.to_string() }),
+            "unschedule" | "run-job" => Err(format!("Usage: {} <name>", name)),
+            "autosave" => match args.parse::<f64>() {
+                Ok(secs) if secs >= 0.0 && secs.is_finite() => Ok(Command::SetAutosave { secs }),
+                _ => Err("Usage: autosave <seconds>".to_string()),
+            },
+            "duplicate" => Ok(Command::Duplicate {
+                title: (!args.is_empty()).then(|| args.to_string()),
+            }),
+            "lock" | "unlock" => Ok(Command::SetLocked {
+                title: (!args.is_empty()).then(|| args.to_string()),
+                locked: name == "lock",
+            }),
+            "split" => Ok(Command::Split {
+                title: (!args.is_empty()).then(|| args.to_string()),
+            }),
+            "today" => Ok(Command::Today {
+                template: (!args.is_empty()).then(|| args.to_string()),
+            }),
+            "convert-notes" => {
+                let extension = NoteExtension::ALL
+                    .into_iter()
+                    .find(|extension| extension.as_str() == args.trim_start_matches('.'))
+                    .ok_or("Usage: convert-notes <md|txt>")?;
+                Ok(Command::ConvertNotes { extension })
+            }
+            "permalinks" => Ok(Command::Permalinks {
+                redirects: (!args.is_empty()).then(|| expand_home(args)),
+            }),
+            "export-site" if !args.is_empty() => Ok(Command::ExportSite { dir: expand_home(args) }),
+            "export-site" => Err("Usage: export-site <dir>".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            "rotate-key" if args.is_empty() => Ok(Command::RotateKey),
+            #[cfg(not(target_arch = "wasm32"))]
+            "rotate-key" => Err("Usage: rotate-key".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            "clear-secret" if !args.is_empty() => Ok(Command::ClearSecret { name: args.to_string() }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "clear-secret" => Err("Usage: clear-secret <name>".to_string()),
+            "find-dates" => {
+                const USAGE: &str = "Usage: find-dates <created|modified> <range> [export <dir>|tag <tag>|rename-tag <from> <to>|move <folder>|delete] [--dry-run]";
+                let (args, dry_run) = match args.strip_suffix("--dry-run") {
+                    Some(args) => (args.trim_end(), true),
+                    None => (args, false),
+                };
+                let (field, rest) = args.split_once(char::is_whitespace).ok_or(USAGE)?;
+                let field = match field {
+                    "created" => DateField::Created,
+                    "modified" => DateField::Modified,
+                    _ => return Err(USAGE.to_string()),
+                };
+                let (range, action) = if let Some((range, dir)) = rest.split_once(" export ") {
+                    (range, Some(BatchAction::Export(expand_home(dir.trim()))))
+                } else if let Some((range, tag)) = rest.split_once(" tag ") {
+                    (range, Some(BatchAction::Tag(tag.trim().trim_start_matches('#').to_string())))
+                } else if let Some((range, tags)) = rest.split_once(" rename-tag ") {
+                    let mut tags = tags.split_whitespace().map(|tag| tag.trim_start_matches('#').to_string());
+                    let (Some(from), Some(to), None) = (tags.next(), tags.next(), tags.next()) else {
+                        return Err(USAGE.to_string());
+                    };
+                    (range, Some(BatchAction::RenameTag { from, to }))
+                } else if let Some((range, folder)) = rest.split_once(" move ") {
+                    (range, Some(BatchAction::Move(folder.trim().to_string())))
+                } else if let Some(range) = rest.strip_suffix(" delete") {
+                    (range, Some(BatchAction::Delete))
+                } else {
+                    (rest, None)
+                };
+                let range = range.trim();
+                // Ranges are resolved when the command runs, so that scheduled jobs move with the calendar.
+                DateRange::parse(range, Local::now().date_naive()).ok_or_else(|| format!("Invalid date range: {}", range))?;
+                Ok(Command::FindByDate {
+                    field,
+                    range: range.to_string(),
+                    action,
+                    dry_run,
+                })
+            }
+            "record" if !args.is_empty() => Ok(Command::RecordMacro { name: args.to_string() }),
+            "record" => Err("Usage: record <name>".to_string()),
+            "stop-recording" => Ok(Command::StopRecording),
+            "play" => {
+                let mut parts = args.split_whitespace();
+                let (Some(name), folder, None) = (parts.next(), parts.next(), parts.next()) else {
+                    return Err("Usage: play <name> [<folder>|--all]".to_string());
+                };
+                Ok(Command::PlayMacro {
+                    name: name.to_string(),
+                    folder: folder.map(|folder| match folder {
+                        "--all" => String::new(),
+                        folder => folder.trim_end_matches('/').to_string(),
+                    }),
+                })
+            }
+            "bind" => {
+                const USAGE: &str = "Usage: bind <name> <key|none>";
+                let (name, key) = args.split_once(char::is_whitespace).ok_or(USAGE)?;
+                let key = match key.trim() {
+                    "none" => None,
+                    key if egui::Key::from_name(key).is_some() => Some(key.to_string()),
+                    key => return Err(format!("Unknown key: {}", key)),
+                };
+                Ok(Command::BindMacro {
+                    name: name.to_string(),
+                    key,
+                })
+            }
+            "todo" => {
+                let (project, description) = match args.strip_prefix('@') {
+                    Some(rest) => {
+                        let (project, description) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                        (Some(project.to_string()), description.trim())
+                    }
+                    None => (None, args),
+                };
+                if description.is_empty() || project.as_ref().is_some_and(|project| project.is_empty()) {
+                    return Err("Usage: todo [@project] <description>".to_string());
+                }
+                Ok(Command::AddTodo {
+                    description: description.to_string(),
+                    project,
+                })
+            }
+            "due" => {
+                const USAGE: &str = "Usage: due <n> <YYYY-MM-DD|none> [hard|target]";
+                let mut parts = args.split_whitespace();
+                let (Some(index), Some(date)) = (parts.next(), parts.next()) else {
+                    return Err(USAGE.to_string());
+                };
+                let index = match index.parse::<usize>() {
+                    Ok(index) if index > 0 => index - 1,
+                    _ => return Err(USAGE.to_string()),
+                };
+                let due_date = match date {
+                    "none" => None,
+                    date => Some(todos::parse_due_date(date).ok_or_else(|| format!("Invalid date: {}", date))?),
+                };
+                let deadline = match parts.next() {
+                    None | Some("hard") => DeadlineKind::Hard,
+                    Some("target") => DeadlineKind::Target,
+                    Some(_) => return Err(USAGE.to_string()),
+                };
+                Ok(Command::SetDue {
+                    index,
+                    due_date,
+                    deadline,
+                })
+            }
+            "clear-completed" => Ok(Command::ClearCompleted),
+            "empty-trash" => Ok(Command::EmptyTrash),
+            #[cfg(not(target_arch = "wasm32"))]
+            "backup" => Ok(Command::Backup {
+                dir: expand_home(if args.is_empty() { backup::DEFAULT_BACKUP_DIR } else { args }),
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "restore" => {
+                let mut options = RestoreOptions::default();
+                let mut args = args;
+                loop {
+                    if let Some(rest) = args.strip_suffix("--merge") {
+                        options.merge = true;
+                        args = rest.trim_end();
+                    } else if let Some(rest) = args.strip_suffix("--dry-run") {
+                        options.dry_run = true;
+                        args = rest.trim_end();
+                    } else {
+                        break;
+                    }
+                }
+                if args.is_empty() {
+                    return Err("Usage: restore <file> [--merge] [--dry-run]".to_string());
+                }
+                Ok(Command::Restore {
+                    path: expand_home(args),
+                    options,
+                })
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            "import" if !args.is_empty() => Ok(Command::Import { path: expand_home(args) }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "import" => Err("Usage: import <file>".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            "add-feed" => {
+                let (name, url) = args
+                    .split_once(char::is_whitespace)
+                    .ok_or("Usage: add-feed <name> <url>")?;
+                let url = clip::parse_url(url).map_err(|err| format!("Invalid URL: {}", err))?;
+                Ok(Command::AddFeed {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                })
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            "remove-feed" if !args.is_empty() => Ok(Command::RemoveFeed { name: args.to_string() }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "remove-feed" => Err("Usage: remove-feed <name>".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            "fetch-feeds" => Ok(Command::FetchFeeds {
+                name: (!args.is_empty()).then(|| args.to_string()),
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "sync" => Ok(Command::Sync),
+            #[cfg(not(target_arch = "wasm32"))]
+            "add-vault" => {
+                let (name, path) = args
+                    .split_once(char::is_whitespace)
+                    .ok_or("Usage: add-vault <name> <path>")?;
+                Ok(Command::AddVault {
+                    name: name.to_string(),
+                    path: path.trim().to_string(),
+                })
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            "switch-vault" => Ok(Command::SwitchVault {
+                name: (!args.is_empty()).then(|| args.to_string()),
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "summarize" => Ok(Command::Summarize),
+            #[cfg(not(target_arch = "wasm32"))]
+            "generate-title" => Ok(Command::GenerateTitle),
+            #[cfg(not(target_arch = "wasm32"))]
+            "ask" if !args.is_empty() => Ok(Command::Ask {
+                question: args.to_string(),
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            "ask" => Err("Usage: ask <question>".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            "share" => Ok(Command::Share {
+                title: (!args.is_empty()).then(|| args.to_string()),
+            }),
+            "" => Err("No command entered".to_string()),
+            other => Err(format!("Unknown command: {}", other)),
+        }
+    }
+
+    /// Whether the command schedules, unschedules or runs a job, which a job may not do.
+    pub fn manages_jobs(&self) -> bool {
+        matches!(
+            self,
+            Command::Schedule { .. } | Command::Unschedule { .. } | Command::RunJob { .. }
+        )
+    }
+
+    /// Runs the command.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<String, String>` containing a message describing the outcome or an error.
+    pub fn run(self) -> Result<String, String> {
+        match self {
+            Command::ExportStats { format, path } => {
+                let count = stats::export(&path, format).map_err(|err| format!("Export failed: {}", err))?;
+                Ok(format!("Exported statistics for {} notes to {}", count, path.display()))
+            }
+            Command::ExportIcal { path, daily_notes } => {
+                ical::export(&path, daily_notes).map_err(|err| format!("Export failed: {}", err))?;
+                Ok(format!("Exported the calendar to {}", path.display()))
+            }
+            Command::Schedule {
+                name,
+                interval_secs,
+                command,
+            } => {
+                let mut schedule = Schedule::load().map_err(|err| err.to_string())?;
+                schedule.add(Job {
+                    name: name.clone(),
+                    command,
+                    interval_secs,
+                    last_run: None,
+                    last_status: None,
+                });
+                schedule.save().map_err(|err| err.to_string())?;
+                Ok(format!("Scheduled {}", name))
+            }
+            Command::Unschedule { name } => {
+                let mut schedule = Schedule::load().map_err(|err| err.to_string())?;
+                if !schedule.remove(&name) {
+                    return Err(format!("No job named {}", name));
+                }
+                schedule.save().map_err(|err| err.to_string())?;
+                Ok(format!("Unscheduled {}", name))
+            }
+            Command::SetAutosave { secs } => {
+                let mut config = Config::load().map_err(|err| err.to_string())?;
+                config.autosave_secs = secs;
+                config.save().map_err(|err| err.to_string())?;
+                Ok(format!("Autosaving {} seconds after the last edit", secs))
+            }
+            Command::AddTodo { description, project } => {
+                let mut todos = Todos::load_from_file().map_err(|err| err.to_string())?;
+                let message = match &project {
+                    Some(project) => format!("Added {} to {}", description, project),
+                    None => format!("Added {}", description),
+                };
+                match project {
+                    Some(project) => todos.add_to_project(description, project),
+                    None => todos.add(description, None),
+                }
+                todos.save_to_file().map_err(|err| err.to_string())?;
+                Ok(message)
+            }
+            Command::SetDue {
+                index,
+                due_date,
+                deadline,
+            } => {
+                let mut todos = Todos::load_from_file().map_err(|err| err.to_string())?;
+                let todo = todos
+                    .items
+                    .get_mut(index)
+                    .ok_or_else(|| format!("No todo number {}", index + 1))?;
+                todo.due_date = due_date;
+                todo.deadline = deadline;
+                let message = match due_date {
+                    Some(due_date) => format!(
+                        "{} for {}: {}",
+                        deadline.label(),
+                        todo.description,
+                        todos::format_due_date(due_date)
+                    ),
+                    None => format!("Cleared the due date of {}", todo.description),
+                };
+                todos.save_to_file().map_err(|err| err.to_string())?;
+                Ok(message)
+            }
+            Command::ClearCompleted => {
+                let mut todos = Todos::load_from_file().map_err(|err| err.to_string())?;
+                let archived = todos.archive_completed().map_err(|err| err.to_string())?;
+                Ok(format!("Archived {} completed todos", archived))
+            }
+            Command::EmptyTrash => {
+                let deleted = Notes::empty_trash().map_err(|err| format!("Failed to empty the trash: {}", err))?;
+                Ok(format!("Deleted {} notes for good", deleted))
+            }
+            Command::FindByDate {
+                field,
+                range,
+                action,
+                dry_run,
+            } => {
+                let range = DateRange::parse(&range, Local::now().date_naive())
+                    .ok_or_else(|| format!("Invalid date range: {}", range))?;
+                let titles = batch::find(field, range).map_err(|err| err.to_string())?;
+                match action {
+                    None => Ok(format!("{} notes: {}", titles.len(), titles.join(", "))),
+                    Some(action) if dry_run => {
+                        let changes = batch::plan(&titles, &action).map_err(|err| err.to_string())?;
+                        let changes: Vec<String> = changes
+                            .iter()
+                            .map(|change| format!("{}: {}", change.title, change.summary))
+                            .collect();
+                        Ok(format!("Dry run, {} changes: {}", changes.len(), changes.join("; ")))
+                    }
+                    Some(action) => {
+                        let count = batch::apply(&titles, &action).map_err(|err| err.to_string())?;
+                        Ok(action.describe(count))
+                    }
+                }
+            }
+            Command::Duplicate { title: None } => Err("Open a note to duplicate".to_string()),
+            Command::Duplicate { title: Some(title) } => {
+                let copy = Notes::duplicate(&title).map_err(|err| format!("Failed to duplicate {}: {}", title, err))?;
+                Ok(format!("Duplicated {} as {}", title, copy))
+            }
+            Command::SetLocked { title: None, .. } => Err("Open a note to lock or unlock".to_string()),
+            Command::SetLocked {
+                title: Some(title),
+                locked,
+            } => {
+                let changed = Notes::set_locked(&title, locked).map_err(|err| format!("Failed to change {}: {}", title, err))?;
+                Ok(match (changed, locked) {
+                    (true, true) => format!("Locked {}", title),
+                    (true, false) => format!("Unlocked {}", title),
+                    (false, true) => format!("{} is locked already", title),
+                    (false, false) => format!("{} is not locked", title),
+                })
+            }
+            Command::Split { title: None } => Err("Open a note to split".to_string()),
+            Command::Split { title: Some(title) } => {
+                let split = Notes::split(&title).map_err(|err| format!("Failed to split {}: {}", title, err))?;
+                let mut todos = Todos::load_from_file().map_err(|err| err.to_string())?;
+                let mut moved = false;
+                for (new, lines) in &split {
+                    moved |= todos.split_note(&title, new, lines.clone());
+                }
+                if moved {
+                    todos.save_to_file().map_err(|err| err.to_string())?;
+                }
+                Ok(format!("Split {} into {} notes", title, split.len()))
+            }
+            Command::Today { template } => {
+                let title = notes::daily_title(Local::now().date_naive());
+                if Notes::note_exists(&title).map_err(|err| err.to_string())? {
+                    return Ok(format!("Opened {}", title));
+                }
+                match template {
+                    Some(template) => Notes::create_from_template(&title, &template, &HashMap::new()),
+                    None => Notes::create_note(&title, ""),
+                }
+                .map_err(|err| format!("Failed to create {}: {}", title, err))?;
+                Ok(format!("Created {}", title))
+            }
+            Command::ConvertNotes { extension } => {
+                let converted = Vault::active()
+                    .and_then(|mut vault| vault.convert_notes(extension))
+                    .map_err(|err| format!("Conversion failed: {}", err))?;
+                Ok(format!("Converted {} notes to .{}", converted.len(), extension.as_str()))
+            }
+            Command::Permalinks { redirects } => {
+                let mut permalinks = Permalinks::load().map_err(|err| err.to_string())?;
+                let assigned = permalinks
+                    .update()
+                    .map_err(|err| format!("Failed to assign slugs: {}", err))?;
+                permalinks.save().map_err(|err| err.to_string())?;
+                if let Some(path) = &redirects {
+                    permalinks
+                        .write_redirects(path)
+                        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+                }
+                Ok(format!(
+                    "Assigned {} new slugs; {} redirects",
+                    assigned,
+                    permalinks.redirects.len()
+                ))
+            }
+            Command::ExportSite { dir } => {
+                let count = export::site(&dir).map_err(|err| format!("Export failed: {}", err))?;
+                Ok(format!("Exported {} notes as a site to {}", count, dir.display()))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::RotateKey => {
+                let count = secrets::rotate_key().map_err(|err| format!("Failed to rotate the key: {}", err))?;
+                Ok(format!("Rotated the key of {} and re-encrypted {} secrets", secrets::SECRETS_FILE, count))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::ClearSecret { name } => {
+                secrets::delete(&name).map_err(|err| format!("Failed to clear {}: {}", name, err))?;
+                Ok(format!("Cleared {}", name))
+            }
+            Command::PlayMacro {
+                name,
+                folder: Some(folder),
+            } => {
+                let macros = Macros::load().map_err(|err| err.to_string())?;
+                let played = macros.get(&name).ok_or_else(|| format!("No macro named {}", name))?;
+                let prefix = format!("{}/", folder);
+                let titles: Vec<String> = Notes::list_notes()
+                    .map_err(|err| err.to_string())?
+                    .into_iter()
+                    .filter(|title| folder.is_empty() || title.starts_with(&prefix))
+                    .collect();
+                let changed = macros::play_on_notes(played, &titles).map_err(|err| err.to_string())?;
+                Ok(format!("Played {} on {} notes, {} changed", name, titles.len(), changed))
+            }
+            Command::BindMacro { name, key } => {
+                let mut macros = Macros::load().map_err(|err| err.to_string())?;
+                if !macros.bind(&name, key.as_deref()) {
+                    return Err(format!("No macro named {}", name));
+                }
+                macros.save().map_err(|err| err.to_string())?;
+                Ok(match key {
+                    Some(key) => format!("Bound {} to {}", name, key),
+                    None => format!("Unbound {}", name),
+                })
+            }
+            Command::RecordMacro { .. } | Command::StopRecording | Command::PlayMacro { folder: None, .. } => {
+                Err("Macros can only be recorded and played on the open note in the editor".to_string())
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Backup { dir } => {
+                let path = backup::create(&dir).map_err(|err| format!("Backup failed: {}", err))?;
+                Ok(format!("Backed up the notes to {}", path.display()))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Restore { path, options } => {
+                let changes = backup::restore(&path, options).map_err(|err| format!("Restore failed: {}", err))?;
+                if options.dry_run {
+                    let changes: Vec<String> = changes
+                        .iter()
+                        .map(|change| format!("{} {}", change.path.display(), change.action.label()))
+                        .collect();
+                    Ok(format!("Restoring would change {} files: {}", changes.len(), changes.join(", ")))
+                } else {
+                    Ok(format!("Restored {} ({} files changed)", path.display(), changes.len()))
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Import { path } => {
+                let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+                let (format, report) = match extension.as_deref() {
+                    Some("enex") => ("Evernote", import::enex(&path)),
+                    Some("jex") => ("Joplin", import::jex(&path)),
+                    Some("zip") => ("Notion", import::notion(&path)),
+                    _ => return Err(format!("Don't know how to import {}", path.display())),
+                };
+                let report = report.map_err(|err| format!("{} import failed: {}", format, err))?;
+                let mut message = format!(
+                    "{} import: {} notes and {} attachments imported from {}",
+                    format,
+                    report.notes.len(),
+                    report.attachments,
+                    path.display()
+                );
+                if !report.skipped.is_empty() {
+                    message.push_str(&format!("; {} skipped: {}", report.skipped.len(), report.skipped.join(", ")));
+                }
+                Ok(message)
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::AddFeed { name, url } => {
+                let mut feeds = Feeds::load().map_err(|err| err.to_string())?;
+                feeds.add(&name, &url);
+                feeds.save().map_err(|err| err.to_string())?;
+                Ok(format!("Added feed {}; enter fetch-feeds to save its items", name))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::RemoveFeed { name } => {
+                let mut feeds = Feeds::load().map_err(|err| err.to_string())?;
+                if !feeds.remove(&name) {
+                    return Err(format!("No feed named {}", name));
+                }
+                feeds.save().map_err(|err| err.to_string())?;
+                Ok(format!("Removed feed {}", name))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::FetchFeeds { name } => {
+                let created = feeds::fetch(name.as_deref()).map_err(|err| format!("Fetching feeds failed: {}", err))?;
+                Ok(format!("Saved {} new items", created.len()))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Sync => {
+                let report = sync::run().map_err(|err| format!("Sync failed: {}", err))?;
+                Ok(format!("Synced: {}", report.summary()))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::AddVault { name, path } => {
+                vault::register(&name, &path).map_err(|err| format!("Failed to add {}: {}", name, err))?;
+                Ok(format!("Added vault {} at {}", name, expand_home(&path).display()))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::SwitchVault { .. } => Err("Vaults can only be switched in the app".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Summarize | Command::GenerateTitle | Command::Ask { .. } => {
+                Err("The AI commands can only be run in the app".to_string())
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Share { title: None } => Err("Open a note to share".to_string()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Share { title: Some(title) } => {
+                let config = Config::load().map_err(|err| err.to_string())?;
+                let content = Notes::read_note_file(&title).map_err(|err| err.to_string())?;
+                let url = share::share(&config.share, &title, &content)
+                    .map_err(|err| format!("Failed to share {}: {}", title, err))?;
+                Ok(format!("Shared {} at {}", title, url))
+            }
+            Command::RunJob { name } => match scheduler::run_job(&name) {
+                Ok(JobStatus::Succeeded(message)) => Ok(message),
+                Ok(JobStatus::Failed(err)) => Err(err),
+                Err(err) => Err(err.to_string()),
+            },
+        }
+    }
+}
+
+/// Expands an alias defined in the configuration into the commands it runs.
+///
+/// In the commands of an alias, `$1` to `$9` are replaced by the words entered after its name, `$*`
+/// by all of them and `$$` by `$`. Aliases take precedence over built-in commands of the same name,
+/// but can't run other aliases.
+///
+/// # Arguments
+///
+/// * `aliases` - The commands of each alias, by name, see `Config::aliases`.
+/// * `input` - The text entered in the command bar, with or without a leading `:`.
+///
+/// # Returns
+///
+/// A `Result<Option<Vec<String>>, String>` containing the commands to run, `None` if the input is
+/// not an alias, or a message if it has fewer arguments than the alias uses.
+pub fn expand_alias(aliases: &BTreeMap<String, Vec<String>>, input: &str) -> Result<Option<Vec<String>>, String> {
+    let input = input.trim();
+    let input = input.strip_prefix(':').unwrap_or(input);
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let Some(commands) = aliases.get(name) else {
+        return Ok(None);
+    };
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let mut expanded = Vec::new();
+    for command in commands {
+        let mut line = String::new();
+        let mut chars = command.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek().copied()) {
+                ('$', Some('*')) => line.push_str(&args.join(" ")),
+                ('$', Some('$')) => line.push('$'),
+                ('$', Some(digit @ '1'..='9')) => {
+                    let index = digit as usize - '1' as usize;
+                    let arg = args
+                        .get(index)
+                        .ok_or_else(|| format!("{} needs at least {} arguments", name, index + 1))?;
+                    line.push_str(arg);
+                }
+                _ => {
+                    line.push(c);
+                    continue;
+                }
+            }
+            chars.next();
+        }
+        expanded.push(line);
+    }
+    Ok(Some(expanded))
+}
+
+/// Expands a leading `~` in a path to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => home_dir().unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export_stats() {
+        assert_eq!(
+            Command::parse(":export-stats json /tmp/stats.json"),
+            Ok(Command::ExportStats {
+                format: ExportFormat::Json,
+                path: PathBuf::from("/tmp/stats.json"),
+            })
+        );
+        assert_eq!(
+            Command::parse("export-ical ~/todos.ics --daily"),
+            Ok(Command::ExportIcal {
+                path: expand_home("~/todos.ics"),
+                daily_notes: true,
+            })
+        );
+        assert!(Command::parse("export-ical --daily").is_err());
+        assert_eq!(
+            Command::parse("export-site /tmp/site"),
+            Ok(Command::ExportSite {
+                dir: PathBuf::from("/tmp/site"),
+            })
+        );
+        assert!(Command::parse("export-site").is_err());
+        assert_eq!(
+            Command::parse("convert-notes .md"),
+            Ok(Command::ConvertNotes {
+                extension: NoteExtension::Markdown,
+            })
+        );
+        assert!(Command::parse("convert-notes rtf").is_err());
+        assert_eq!(Command::parse("duplicate"), Ok(Command::Duplicate { title: None }));
+        assert_eq!(
+            Command::parse("duplicate work/ideas"),
+            Ok(Command::Duplicate {
+                title: Some("work/ideas".to_string()),
+            })
+        );
+        assert_eq!(
+            Command::parse("unlock"),
+            Ok(Command::SetLocked {
+                title: None,
+                locked: false,
+            })
+        );
+        assert_eq!(
+            Command::parse("lock work/plan"),
+            Ok(Command::SetLocked {
+                title: Some("work/plan".to_string()),
+                locked: true,
+            })
+        );
+        assert_eq!(
+            Command::parse("split inbox"),
+            Ok(Command::Split {
+                title: Some("inbox".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("export-stats").is_err());
+        assert!(Command::parse("export-stats xml out.xml").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+        assert!(Command::parse("schedule nightly-stats soon export-stats csv out.csv").is_err());
+        assert!(Command::parse("schedule nightly-stats daily frobnicate").is_err());
+        assert!(Command::parse("run-job").is_err());
+        assert!(Command::parse("autosave -1").is_err());
+        assert!(Command::parse("due 0 2024-03-15").is_err());
+        assert!(Command::parse("due 1 tomorrow").is_err());
+        assert!(Command::parse("due 1 none soft").is_err());
+    }
+
+    #[test]
+    fn test_parse_due() {
+        assert_eq!(
+            Command::parse("due 2 none target"),
+            Ok(Command::SetDue {
+                index: 1,
+                due_date: None,
+                deadline: DeadlineKind::Target,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_find_dates() {
+        assert_eq!(
+            Command::parse("find-dates created last quarter tag #q1"),
+            Ok(Command::FindByDate {
+                field: DateField::Created,
+                range: "last quarter".to_string(),
+                action: Some(BatchAction::Tag("q1".to_string())),
+                dry_run: false,
+            })
+        );
+        assert_eq!(
+            Command::parse("find-dates modified this year rename-tag #draft wip --dry-run"),
+            Ok(Command::FindByDate {
+                field: DateField::Modified,
+                range: "this year".to_string(),
+                action: Some(BatchAction::RenameTag {
+                    from: "draft".to_string(),
+                    to: "wip".to_string(),
+                }),
+                dry_run: true,
+            })
+        );
+        assert!(Command::parse("find-dates modified today rename-tag draft").is_err());
+        assert!(Command::parse("find-dates modified someday").is_err());
+    }
+
+    #[test]
+    fn test_parse_macros() {
+        assert_eq!(
+            Command::parse("play cleanup journal/"),
+            Ok(Command::PlayMacro {
+                name: "cleanup".to_string(),
+                folder: Some("journal".to_string()),
+            })
+        );
+        assert_eq!(
+            Command::parse("play cleanup --all"),
+            Ok(Command::PlayMacro {
+                name: "cleanup".to_string(),
+                folder: Some(String::new()),
+            })
+        );
+        assert_eq!(
+            Command::parse("bind cleanup F5"),
+            Ok(Command::BindMacro {
+                name: "cleanup".to_string(),
+                key: Some("F5".to_string()),
+            })
+        );
+        assert!(Command::parse("bind cleanup Hyper").is_err());
+        assert!(Command::parse("play").is_err());
+        assert!(Command::parse("play a b c").is_err());
+    }
+
+    #[test]
+    fn test_parse_todo() {
+        assert_eq!(
+            Command::parse(":todo @work finish report"),
+            Ok(Command::AddTodo {
+                description: "finish report".to_string(),
+                project: Some("work".to_string()),
+            })
+        );
+        assert_eq!(
+            Command::parse("todo call the bank"),
+            Ok(Command::AddTodo {
+                description: "call the bank".to_string(),
+                project: None,
+            })
+        );
+        assert!(Command::parse("todo @work").is_err());
+        assert!(Command::parse("todo @ finish").is_err());
+        assert!(Command::parse("todo").is_err());
+        assert_eq!(Command::parse("clear-completed"), Ok(Command::ClearCompleted));
+        assert_eq!(Command::parse("empty-trash"), Ok(Command::EmptyTrash));
+    }
+
+    #[test]
+    fn test_parse_import() {
+        assert_eq!(
+            Command::parse("import /tmp/Personal.enex"),
+            Ok(Command::Import {
+                path: PathBuf::from("/tmp/Personal.enex"),
+            })
+        );
+        assert!(Command::parse("import").is_err());
+        assert!(Command::parse("import /tmp/notes.doc").unwrap().run().is_err());
+    }
+
+    #[test]
+    fn test_parse_restore() {
+        assert_eq!(
+            Command::parse("restore /tmp/notes-backup.zip --merge --dry-run"),
+            Ok(Command::Restore {
+                path: PathBuf::from("/tmp/notes-backup.zip"),
+                options: RestoreOptions {
+                    dry_run: true,
+                    merge: true,
+                },
+            })
+        );
+        assert_eq!(
+            Command::parse("backup /tmp/backups"),
+            Ok(Command::Backup {
+                dir: PathBuf::from("/tmp/backups"),
+            })
+        );
+        assert!(Command::parse("restore --merge").is_err());
+    }
+
+    #[test]
+    fn test_parse_vaults() {
+        assert_eq!(
+            Command::parse("add-vault work ~/Work Notes"),
+            Ok(Command::AddVault {
+                name: "work".to_string(),
+                path: "~/Work Notes".to_string(),
+            })
+        );
+        assert_eq!(
+            Command::parse("switch-vault work"),
+            Ok(Command::SwitchVault {
+                name: Some("work".to_string()),
+            })
+        );
+        assert_eq!(Command::parse("switch-vault"), Ok(Command::SwitchVault { name: None }));
+        assert!(Command::parse("add-vault work").is_err());
+    }
+
+    #[test]
+    fn test_parse_feeds() {
+        assert_eq!(
+            Command::parse("add-feed rust https://blog.rust-lang.org/feed.xml"),
+            Ok(Command::AddFeed {
+                name: "rust".to_string(),
+                url: "https://blog.rust-lang.org/feed.xml".to_string(),
+            })
+        );
+        assert!(Command::parse("add-feed rust not a url").is_err());
+        assert_eq!(Command::parse("fetch-feeds"), Ok(Command::FetchFeeds { name: None }));
+        assert!(Command::parse("remove-feed").is_err());
+    }
+
+    #[test]
+    fn test_parse_ai() {
+        assert_eq!(Command::parse("summarize"), Ok(Command::Summarize));
+        assert_eq!(Command::parse(":generate-title"), Ok(Command::GenerateTitle));
+        assert_eq!(
+            Command::parse("ask when is the dentist?"),
+            Ok(Command::Ask {
+                question: "when is the dentist?".to_string(),
+            })
+        );
+        assert!(Command::parse("ask").is_err());
+    }
+
+    #[test]
+    fn test_parse_share() {
+        assert_eq!(Command::parse("share"), Ok(Command::Share { title: None }));
+        assert_eq!(
+            Command::parse("share work/ideas"),
+            Ok(Command::Share {
+                title: Some("work/ideas".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_today() {
+        assert_eq!(Command::parse("today"), Ok(Command::Today { template: None }));
+        assert_eq!(
+            Command::parse(":today standup"),
+            Ok(Command::Today {
+                template: Some("standup".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let mut aliases = BTreeMap::new();
+        aliases.insert(
+            "standup".to_string(),
+            vec![
+                "today standup".to_string(),
+                "todo @$1 $2".to_string(),
+                "todo Costs $$5: $*".to_string(),
+            ],
+        );
+        assert_eq!(
+            expand_alias(&aliases, ":standup work review"),
+            Ok(Some(vec![
+                "today standup".to_string(),
+                "todo @work review".to_string(),
+                "todo Costs $5: work review".to_string(),
+            ]))
+        );
+        assert_eq!(
+            expand_alias(&aliases, "standup work"),
+            Err("standup needs at least 2 arguments".to_string())
+        );
+        assert_eq!(expand_alias(&aliases, "today"), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!(
+            Command::parse("schedule stats nightly export-stats csv /tmp/stats.csv"),
+            Ok(Command::Schedule {
+                name: "stats".to_string(),
+                interval_secs: 86400,
+                command: "export-stats csv /tmp/stats.csv".to_string(),
+            })
+        );
+        assert!(Command::parse("schedule loop hourly run-job loop").is_err());
+        assert!(Command::parse("schedule more daily schedule other hourly sync").is_err());
+        assert!(Command::parse("schedule stats 99999999999999999999m export-stats csv /tmp/stats.csv").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::tags;
+
+/// What is being completed in the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// The title of a note in a `[[wiki link]]`.
+    Link,
+    /// The name of a `#tag`.
+    Tag,
+}
+
+/// A partly typed link or tag right before the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trigger {
+    /// What is being completed.
+    pub kind: CompletionKind,
+    /// The byte index at which the typed text starts, after the `[[` or `#`.
+    pub start: usize,
+    /// The text typed so far.
+    pub query: String,
+}
+
+/// Finds a link or tag being typed before the cursor.
+///
+/// A link starts at an unclosed `[[` on the same line. A tag starts at a `#` that begins
+/// a word and is followed by at least one tag character, so that headings are left alone.
+///
+/// # Arguments
+///
+/// * `text` - The content of the editor.
+/// * `cursor` - The byte index of the cursor.
+///
+/// # Returns
+///
+/// The link or tag being typed, or `None` if there is none.
+pub fn trigger(text: &str, cursor: usize) -> Option<Trigger> {
+    let line_start = text[..cursor].rfind('\n').map_or(0, |index| index + 1);
+    let before = &text[line_start..cursor];
+    if let Some(open) = before.rfind("[[") {
+        let query = &before[open + 2..];
+        if !query.contains("]]") && !query.contains('|') {
+            return Some(Trigger {
+                kind: CompletionKind::Link,
+                start: line_start + open + 2,
+                query: query.to_string(),
+            });
+        }
+    }
+    let hash = before.rfind('#')?;
+    let query = &before[hash + 1..];
+    let starts_word = before[..hash].chars().next_back().map_or(true, char::is_whitespace);
+    if !starts_word || query.is_empty() || !query.chars().all(tags::is_tag_char) {
+        return None;
+    }
+    Some(Trigger {
+        kind: CompletionKind::Tag,
+        start: line_start + hash + 1,
+        query: query.to_string(),
+    })
+}
+
+/// Replaces the typed text of a trigger with the chosen completion.
+///
+/// Links are closed with `]]` unless they already are.
+///
+/// # Arguments
+///
+/// * `text` - The content of the editor.
+/// * `trigger` - The link or tag being typed.
+/// * `cursor` - The byte index of the cursor.
+/// * `choice` - The chosen note title or tag.
+///
+/// # Returns
+///
+/// The new content and the byte index to move the cursor to, after the completion.
+pub fn apply(text: &str, trigger: &Trigger, cursor: usize, choice: &str) -> (String, usize) {
+    let rest = &text[cursor..];
+    let closing = match trigger.kind {
+        CompletionKind::Link if !rest.starts_with("]]") => "]]",
+        _ => "",
+    };
+    let completed = format!("{}{}{}{}", &text[..trigger.start], choice, closing, rest);
+    let mut cursor = trigger.start + choice.len();
+    if trigger.kind == CompletionKind::Link {
+        cursor += 2;
+    }
+    (completed, cursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger() {
+        let link = trigger("See [[meet", 10).unwrap();
+        assert_eq!(link.kind, CompletionKind::Link);
+        assert_eq!((link.start, link.query.as_str()), (6, "meet"));
+        assert_eq!(trigger("See [[]]", 6).unwrap().query, "");
+        assert_eq!(trigger("[[a]] and", 9), None);
+        assert_eq!(trigger("[[a|b", 5), None);
+
+        let tag = trigger("Filed under #wo", 15).unwrap();
+        assert_eq!(tag.kind, CompletionKind::Tag);
+        assert_eq!((tag.start, tag.query.as_str()), (13, "wo"));
+        assert_eq!(trigger("# Heading", 9), None);
+        assert_eq!(trigger("#", 1), None);
+        assert_eq!(trigger("issue#12", 8), None);
+        assert_eq!(trigger("#tag\nnext", 9), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let link = trigger("See [[meet", 10).unwrap();
+        assert_eq!(apply("See [[meet", &link, 10, "meetings"), ("See [[meetings]]".to_string(), 16));
+        let closed = trigger("See [[meet]] now", 10).unwrap();
+        assert_eq!(
+            apply("See [[meet]] now", &closed, 10, "meetings"),
+            ("See [[meetings]] now".to_string(), 16)
+        );
+        let tag = trigger("#wo and", 3).unwrap();
+        assert_eq!(apply("#wo and", &tag, 3, "work"), ("#work and".to_string(), 5));
+    }
+}
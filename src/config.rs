@@ -0,0 +1,534 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::HistoryRetention;
+use crate::notes::Notes;
+use crate::storage::{self, atomic_write};
+use crate::theme::Theme;
+use crate::todos::{self, DeadlineKind};
+
+/// The name of the file in the `.notes` directory that stores the configuration.
+pub const CONFIG_FILE: &str = ".config.json";
+
+/// User settings, persisted in the `.config.json` file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds to wait after the last edit before saving the open note automatically.
+    pub autosave_secs: f64,
+    /// Whether deleting a note asks for confirmation first.
+    pub confirm_delete: bool,
+    /// How many seconds the notification shown after deleting a note offers to undo it. The
+    /// note stays in the trash afterwards.
+    pub undo_secs: u64,
+    /// Whether the open note is saved when the window loses focus.
+    pub save_on_focus_loss: bool,
+    /// Whether the open note is saved when another note is opened. Otherwise the user is asked
+    /// whether to save or discard unsaved changes.
+    pub save_on_note_switch: bool,
+    /// Hours before a hard deadline that a todo is flagged as due soon.
+    pub deadline_lead_hours: u64,
+    /// Hours before a target date that a todo is flagged as due soon.
+    pub target_lead_hours: u64,
+    /// The columns of the todo board, from left to right. The last one holds the completed todos.
+    pub board_columns: Vec<String>,
+    /// How many minutes a focus session on a todo lasts.
+    pub focus_mins: u64,
+    /// How many earlier versions of each note to keep, unless its folder says otherwise.
+    pub history: HistoryRetention,
+    /// Whether the text in attached images is read with `tesseract`, so that search finds it.
+    pub ocr_attachments: bool,
+    /// The whisper model file dictation transcribes with. Only used when built with the `dictation` feature.
+    pub dictation_model: String,
+    /// The language model behind the optional AI commands. They are off unless turned on here.
+    pub ai: AiSettings,
+    /// Where the `share` command uploads notes to.
+    pub share: ShareSettings,
+    /// Whether opening and saving notes is logged locally to compute usage insights.
+    pub activity_log: bool,
+    /// When the notes directory is backed up automatically and how many backups are kept.
+    pub backup: BackupPolicy,
+    /// Where the notes directory is synced to and how often.
+    pub sync: SyncSettings,
+    /// The shortcut that opens the quick capture window from any application, e.g.
+    /// `Ctrl+Shift+Space`, or empty for none.
+    pub quick_capture_hotkey: String,
+    /// Whether closing the window keeps the app running in the system tray.
+    pub close_to_tray: bool,
+    /// Where the notes of this directory are kept.
+    pub note_store: NoteStoreKind,
+    /// The extension new note files are saved with. Notes already saved keep theirs until they
+    /// are converted with `convert-notes`.
+    pub note_extension: NoteExtension,
+    /// Keyboard shortcuts rebound by the user, by action id, e.g. `quick_open: Ctrl+O`. Actions
+    /// not listed keep their default shortcut.
+    pub keymap: BTreeMap<String, String>,
+    /// Commands defined by the user, by name, each running a list of commands, e.g. `standup:
+    /// ["today standup", "todo @$1 $2"]`. See `commands::expand_alias` for the parameters.
+    pub aliases: BTreeMap<String, Vec<String>>,
+    /// Colour schemes and editor fonts defined by the user.
+    pub themes: Vec<Theme>,
+    /// The name of the theme in `themes` in use, or `None` for the built-in dark or light look.
+    pub theme: Option<String>,
+    /// Other notes directories the app can switch to. Only read from the configuration of `~/.notes`.
+    pub vaults: Vec<VaultEntry>,
+    /// The name of the vault the app switched to last, or `None` for `~/.notes`. Only read from
+    /// the configuration of `~/.notes`.
+    pub active_vault: Option<String>,
+}
+
+/// A notes directory registered under a name, e.g. `work`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VaultEntry {
+    /// The name the vault is listed and switched to by.
+    pub name: String,
+    /// The notes directory. A leading `~` is expanded.
+    pub path: String,
+}
+
+/// Where the notes of a directory are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteStoreKind {
+    /// One text file per note.
+    #[default]
+    Files,
+    /// A SQLite database in `.notes.db`, see `notes-cli migrate sqlite`.
+    Sqlite,
+}
+
+/// The extension of note files, which tells other apps how to open them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteExtension {
+    /// Markdown, `.md`.
+    #[default]
+    Markdown,
+    /// Plain text, `.txt`, which notes were saved as before the extension could be chosen.
+    Text,
+}
+
+impl NoteExtension {
+    /// All extensions, in the order note files are looked up and offered in the UI.
+    pub const ALL: [NoteExtension; 2] = [NoteExtension::Markdown, NoteExtension::Text];
+
+    /// The extension without the leading dot, e.g. `md`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NoteExtension::Markdown => "md",
+            NoteExtension::Text => "txt",
+        }
+    }
+
+    /// A human-readable label for the extension.
+    pub fn label(self) -> &'static str {
+        match self {
+            NoteExtension::Markdown => "Markdown (.md)",
+            NoteExtension::Text => "Plain text (.txt)",
+        }
+    }
+
+    /// Finds the extension of a note file.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - The extension of a file, without the leading dot.
+    ///
+    /// # Returns
+    ///
+    /// The matching extension, or `None` if files with this extension are not notes.
+    pub fn from_extension(extension: &OsStr) -> Option<NoteExtension> {
+        Self::ALL.into_iter().find(|candidate| extension == candidate.as_str())
+    }
+}
+
+/// When automatic backups of the notes directory are made.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackupSchedule {
+    /// No automatic backups.
+    #[default]
+    Off,
+    /// A backup each time the app starts.
+    OnStart,
+    /// A backup once a day while the app runs.
+    Daily,
+}
+
+impl BackupSchedule {
+    /// All schedules, in the order they are offered in the UI.
+    pub const ALL: [BackupSchedule; 3] = [BackupSchedule::Off, BackupSchedule::OnStart, BackupSchedule::Daily];
+
+    /// A human-readable label for the schedule.
+    pub fn label(self) -> &'static str {
+        match self {
+            BackupSchedule::Off => "Never",
+            BackupSchedule::OnStart => "When the app starts",
+            BackupSchedule::Daily => "Daily",
+        }
+    }
+}
+
+/// Automatic backups and how many of them are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct BackupPolicy {
+    /// When backups are made.
+    pub schedule: BackupSchedule,
+    /// The folder backups are written to. Empty for `~/notes-backups`.
+    pub dir: String,
+    /// How many days to keep the latest backup of.
+    pub keep_daily: usize,
+    /// How many weeks to keep the latest backup of, on top of the daily ones.
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            schedule: BackupSchedule::Off,
+            dir: String::new(),
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+/// Where the notes directory is synced to and how often.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct SyncSettings {
+    /// The kind of server the notes are synced with.
+    pub service: SyncService,
+    /// The WebDAV folder the notes are synced with, or `None` if it is not set up.
+    pub webdav: Option<WebDavSettings>,
+    /// The S3 bucket the notes are synced with, or `None` if it is not set up.
+    pub s3: Option<S3Settings>,
+    /// Whether notes are encrypted before upload with a key derived from a passphrase, which is
+    /// kept in the OS keychain.
+    pub encrypt: bool,
+    /// Minutes between automatic syncs while the app runs, or 0 to only sync on demand.
+    pub interval_mins: u64,
+}
+
+impl SyncSettings {
+    /// Whether the selected service is set up, i.e. whether the notes are synced at all.
+    pub fn is_set_up(&self) -> bool {
+        match self.service {
+            SyncService::WebDav => self.webdav.is_some(),
+            SyncService::S3 => self.s3.is_some(),
+        }
+    }
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        Self {
+            service: SyncService::WebDav,
+            webdav: None,
+            s3: None,
+            encrypt: false,
+            interval_mins: 15,
+        }
+    }
+}
+
+/// The kinds of server the notes can be synced with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncService {
+    /// A WebDAV folder, e.g. on Nextcloud.
+    #[default]
+    WebDav,
+    /// An S3-compatible bucket, e.g. on AWS, MinIO or Backblaze B2.
+    S3,
+}
+
+impl SyncService {
+    /// All services, in the order they are offered in the UI.
+    pub const ALL: [SyncService; 2] = [SyncService::WebDav, SyncService::S3];
+
+    /// A human-readable label for the service.
+    pub fn label(self) -> &'static str {
+        match self {
+            SyncService::WebDav => "WebDAV",
+            SyncService::S3 => "S3",
+        }
+    }
+}
+
+/// A WebDAV folder, e.g. on Nextcloud. The password is kept in the OS keychain.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct WebDavSettings {
+    /// The URL of the folder.
+    pub url: String,
+    /// The user to log in as.
+    pub username: String,
+}
+
+/// An S3-compatible bucket. The secret access key is kept in the OS keychain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct S3Settings {
+    /// The URL of the service, e.g. `https://s3.us-west-004.backblazeb2.com`.
+    pub endpoint: String,
+    /// The region requests are signed for.
+    pub region: String,
+    /// The bucket the notes are stored in.
+    pub bucket: String,
+    /// The folder within the bucket the notes are stored in, or empty for the whole bucket.
+    pub prefix: String,
+    /// The ID of the access key to sign requests with.
+    pub access_key_id: String,
+}
+
+impl Default for S3Settings {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            region: "us-east-1".to_string(),
+            bucket: String::new(),
+            prefix: String::new(),
+            access_key_id: String::new(),
+        }
+    }
+}
+
+/// The language model that summarizes notes, suggests titles and answers questions. Notes are only
+/// sent to it when one of the AI commands is run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct AiSettings {
+    /// Whether the AI commands are available.
+    pub enabled: bool,
+    /// The kind of server the model runs on.
+    pub provider: AiProvider,
+    /// The URL of the server, e.g. `https://api.openai.com/v1` or `http://localhost:8080`.
+    pub url: String,
+    /// The model to use, for servers hosting several. The API key is kept in the OS keychain.
+    pub model: String,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: AiProvider::OpenAiCompatible,
+            url: "https://api.openai.com/v1".to_string(),
+            model: "gpt-4o-mini".to_string(),
+        }
+    }
+}
+
+/// The kinds of server a language model can run on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AiProvider {
+    /// A server with the OpenAI chat completions API, e.g. OpenAI itself or Ollama.
+    #[default]
+    OpenAiCompatible,
+    /// The server of llama.cpp, running a model on this machine.
+    LlamaCpp,
+}
+
+impl AiProvider {
+    /// All providers, in the order they are offered in the UI.
+    pub const ALL: [AiProvider; 2] = [AiProvider::OpenAiCompatible, AiProvider::LlamaCpp];
+
+    /// A human-readable label for the provider.
+    pub fn label(self) -> &'static str {
+        match self {
+            AiProvider::OpenAiCompatible => "OpenAI-compatible API",
+            AiProvider::LlamaCpp => "llama.cpp server",
+        }
+    }
+}
+
+/// The paste service notes are shared on. The token of the service is kept in the OS keychain.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ShareSettings {
+    /// The service to upload to.
+    pub service: ShareService,
+    /// The URL notes are posted to when the service is `Custom`.
+    pub url: String,
+}
+
+/// The paste services a note can be shared on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShareService {
+    /// A secret GitHub gist, which needs a token with the `gist` scope.
+    #[default]
+    Gist,
+    /// The 0x0.st file host, which needs no account.
+    ZeroXZero,
+    /// A self-hosted service that takes the page as the body of a POST and answers with its URL.
+    Custom,
+}
+
+impl ShareService {
+    /// All services, in the order they are offered in the UI.
+    pub const ALL: [ShareService; 3] = [ShareService::Gist, ShareService::ZeroXZero, ShareService::Custom];
+
+    /// A human-readable label for the service.
+    pub fn label(self) -> &'static str {
+        match self {
+            ShareService::Gist => "Secret GitHub gist",
+            ShareService::ZeroXZero => "0x0.st",
+            ShareService::Custom => "Self-hosted",
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            autosave_secs: 2.0,
+            confirm_delete: true,
+            undo_secs: 10,
+            save_on_focus_loss: true,
+            save_on_note_switch: true,
+            deadline_lead_hours: 72,
+            target_lead_hours: 24,
+            board_columns: todos::DEFAULT_COLUMNS.map(String::from).to_vec(),
+            focus_mins: 25,
+            history: HistoryRetention::default(),
+            ocr_attachments: false,
+            dictation_model: String::new(),
+            ai: AiSettings::default(),
+            share: ShareSettings::default(),
+            activity_log: true,
+            backup: BackupPolicy::default(),
+            sync: SyncSettings::default(),
+            quick_capture_hotkey: "Ctrl+Shift+Space".to_string(),
+            close_to_tray: false,
+            note_store: NoteStoreKind::default(),
+            note_extension: NoteExtension::default(),
+            keymap: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            themes: Vec::new(),
+            theme: None,
+            vaults: Vec::new(),
+            active_vault: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Config>` containing the configuration, or the defaults if none was saved yet.
+    pub fn load() -> io::Result<Config> {
+        Self::load_in(&Notes::get_notes_dir()?)
+    }
+
+    /// Loads the configuration of a notes directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The notes directory.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Config>` containing the configuration, or the defaults if none was saved yet.
+    pub fn load_in(root: &Path) -> io::Result<Config> {
+        let path = root.join(CONFIG_FILE);
+        if !storage::backend().exists(&path) {
+            return Ok(Config::default());
+        }
+        let data: serde_json::Value = serde_json::from_str(&storage::read_to_string(&path)?)?;
+        let config: Config = serde_json::from_value(data.clone())?;
+        // A read-only window leaves the secrets for the window holding the lock to move
+        #[cfg(not(target_arch = "wasm32"))]
+        if crate::vault::check_writable(&path).is_ok()
+            && crate::secrets::migrate_plaintext(&config, &data)?
+        {
+            config.save_in(root)?;
+        }
+        Ok(config)
+    }
+
+    /// Returns how many seconds before a due date of the given kind a todo is flagged as due soon.
+    pub fn lead_secs(&self, kind: DeadlineKind) -> i64 {
+        let hours = match kind {
+            DeadlineKind::Hard => self.deadline_lead_hours,
+            DeadlineKind::Target => self.target_lead_hours,
+        };
+        hours as i64 * 60 * 60
+    }
+
+    /// Saves the configuration to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self) -> io::Result<()> {
+        self.save_in(&Notes::get_notes_dir()?)
+    }
+
+    /// Saves the configuration of a notes directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The notes directory.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save_in(&self, root: &Path) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&root.join(CONFIG_FILE), data.as_bytes(), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+    use crate::vault::{LockInfo, Vault, LOCK_FILE};
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_and_load_config() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        assert_eq!(Config::load().unwrap(), Config::default());
+
+        let config = Config {
+            autosave_secs: 10.0,
+            ..Config::default()
+        };
+        config.save().unwrap();
+        assert_eq!(Config::load().unwrap(), config);
+    }
+
+    #[test]
+    fn test_load_in_read_only_window() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let text = "{\"ai\": {\"api_key\": \"secret\"}, \"autosave_secs\": 10.0}";
+        fs::write(root.join(CONFIG_FILE), text).unwrap();
+
+        // Another running window holds the lock
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let holder = LockInfo {
+            pid: u32::MAX,
+            since: now,
+            heartbeat: now,
+        };
+        fs::write(root.join(LOCK_FILE), serde_json::to_string(&holder).unwrap()).unwrap();
+        assert!(Vault::open_at(root.clone()).unwrap().is_read_only());
+
+        assert_eq!(Config::load_in(&root).unwrap().autosave_secs, 10.0);
+        assert_eq!(fs::read_to_string(root.join(CONFIG_FILE)).unwrap(), text);
+    }
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let config: Config = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, Config::default());
+    }
+}
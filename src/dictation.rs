@@ -0,0 +1,249 @@
+//! Dictation into the open note, transcribed offline by a whisper model while the user speaks.
+//!
+//! Only built with the `dictation` feature, since whisper.cpp is compiled along with the app.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperError, WhisperState};
+
+use crate::audio::{self, Recorder};
+
+/// The sample rate whisper models expect.
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// How much speech is transcribed at a time. Shorter chunks show text sooner but cut more words in half.
+const CHUNK: Duration = Duration::from_secs(5);
+
+/// Chunks that stay below this level are silence, which whisper tends to make up words for.
+const SILENCE_RMS: f32 = 0.01;
+
+// What the transcription thread reports back
+enum Event {
+    Loaded,
+    Transcribed(String),
+    Failed(String),
+}
+
+/// A dictation: records from the microphone and transcribes what was said a chunk at a time.
+pub struct Dictation {
+    recorder: Option<Recorder>,
+    chunks: Option<Sender<Vec<f32>>>,
+    events: Receiver<Event>,
+    loaded: bool,
+    // Chunks sent to the transcription thread that have not come back yet
+    pending: usize,
+    last_chunk: Instant,
+}
+
+impl Dictation {
+    /// Starts recording and loads the model on a background thread. What is said while the model
+    /// loads is transcribed once it has.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The whisper model file, e.g. `ggml-base.en.bin`.
+    /// * `ctx` - Repainted whenever a chunk is transcribed.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Dictation>` containing the dictation, or a `NotFound` error if there is no model or microphone.
+    pub fn start(model: &Path, ctx: &egui::Context) -> io::Result<Dictation> {
+        if !model.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No speech model at {}; choose one in Preferences", model.display()),
+            ));
+        }
+        let recorder = Recorder::start()?;
+        let (chunk_sender, chunk_receiver) = channel();
+        let (event_sender, events) = channel();
+        let model = model.to_path_buf();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || transcribe_chunks(model, chunk_receiver, event_sender, ctx));
+        Ok(Dictation {
+            recorder: Some(recorder),
+            chunks: Some(chunk_sender),
+            events,
+            loaded: false,
+            pending: 0,
+            last_chunk: Instant::now(),
+        })
+    }
+
+    /// Returns whether the microphone is still recording.
+    pub fn is_listening(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Returns whether recording has stopped and everything said has been transcribed.
+    pub fn is_finished(&self) -> bool {
+        self.recorder.is_none() && self.pending == 0
+    }
+
+    /// Stops recording. What was said since the last chunk is still transcribed.
+    pub fn stop(&mut self) {
+        self.send_chunk();
+        self.recorder = None;
+        // Dropping the sender ends the transcription thread once it has caught up
+        self.chunks = None;
+    }
+
+    /// Sends what was recorded to be transcribed once a chunk is complete, and collects what has been
+    /// transcribed. Called every frame while dictating.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<String>, String>` containing the transcribed text in the order it was said, or an
+    /// error if the model could not be loaded or a chunk could not be transcribed.
+    pub fn poll(&mut self) -> Result<Vec<String>, String> {
+        if self.recorder.is_some() && self.last_chunk.elapsed() >= CHUNK {
+            self.send_chunk();
+        }
+        let mut texts = Vec::new();
+        loop {
+            match self.events.try_recv() {
+                Ok(Event::Loaded) => self.loaded = true,
+                Ok(Event::Transcribed(text)) => {
+                    self.pending = self.pending.saturating_sub(1);
+                    if !text.is_empty() {
+                        texts.push(text);
+                    }
+                }
+                Ok(Event::Failed(err)) => {
+                    self.pending = self.pending.saturating_sub(1);
+                    return Err(err);
+                }
+                Err(TryRecvError::Empty) => return Ok(texts),
+                Err(TryRecvError::Disconnected) => {
+                    if !self.is_finished() {
+                        return Err("Transcription stopped unexpectedly".to_string());
+                    }
+                    return Ok(texts);
+                }
+            }
+        }
+    }
+
+    /// Describes what the dictation is doing, for the status bar.
+    pub fn status(&self) -> String {
+        match &self.recorder {
+            Some(_) if !self.loaded => "🎙 Loading the speech model…".to_string(),
+            Some(recorder) => format!("🎙 Dictating {}", audio::format_duration(recorder.elapsed())),
+            None => "🎙 Transcribing…".to_string(),
+        }
+    }
+
+    fn send_chunk(&mut self) {
+        self.last_chunk = Instant::now();
+        let (Some(recorder), Some(chunks)) = (&self.recorder, &self.chunks) else {
+            return;
+        };
+        let samples = to_whisper_input(&recorder.take_samples(), recorder.channels(), recorder.sample_rate());
+        if is_silent(&samples) {
+            return;
+        }
+        if chunks.send(samples).is_ok() {
+            self.pending += 1;
+        }
+    }
+}
+
+// Loads the model, then transcribes chunks until the dictation is stopped
+fn transcribe_chunks(model: PathBuf, chunks: Receiver<Vec<f32>>, events: Sender<Event>, ctx: egui::Context) {
+    let state = WhisperContext::new_with_params(&model.to_string_lossy(), WhisperContextParameters::default())
+        .and_then(|context| context.create_state());
+    let mut state = match state {
+        Ok(state) => state,
+        Err(err) => {
+            let _ = events.send(Event::Failed(format!("Failed to load the speech model: {}", err)));
+            ctx.request_repaint();
+            return;
+        }
+    };
+    let _ = events.send(Event::Loaded);
+    ctx.request_repaint();
+    for samples in chunks {
+        let event = match transcribe(&mut state, &samples) {
+            Ok(text) => Event::Transcribed(text),
+            Err(err) => Event::Failed(format!("Failed to transcribe: {}", err)),
+        };
+        if events.send(event).is_err() {
+            return;
+        }
+        ctx.request_repaint();
+    }
+}
+
+fn transcribe(state: &mut WhisperState, samples: &[f32]) -> Result<String, WhisperError> {
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_special(false);
+    params.set_print_timestamps(false);
+    params.set_suppress_blank(true);
+    state.full(params, samples)?;
+    let mut text = String::new();
+    for segment in 0..state.full_n_segments()? {
+        text.push_str(&state.full_get_segment_text(segment)?);
+    }
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Converts recorded samples to what whisper expects: mono, 16 kHz and between -1 and 1.
+///
+/// # Arguments
+///
+/// * `samples` - The samples, interleaved if there are several channels.
+/// * `channels` - The number of channels, which are mixed down.
+/// * `sample_rate` - The number of samples per second and channel, which is resampled linearly.
+pub fn to_whisper_input(samples: &[i16], channels: u16, sample_rate: u32) -> Vec<f32> {
+    let mono: Vec<f32> = samples
+        .chunks(channels.max(1) as usize)
+        .map(|frame| frame.iter().map(|sample| *sample as f32 / i16::MAX as f32).sum::<f32>() / frame.len() as f32)
+        .collect();
+    if sample_rate == WHISPER_SAMPLE_RATE || mono.is_empty() {
+        return mono;
+    }
+    let step = sample_rate as f64 / WHISPER_SAMPLE_RATE as f64;
+    let len = (mono.len() as f64 / step) as usize;
+    (0..len)
+        .map(|index| {
+            let position = index as f64 * step;
+            let before = position as usize;
+            let after = (before + 1).min(mono.len() - 1);
+            let fraction = (position - before as f64) as f32;
+            mono[before] * (1.0 - fraction) + mono[after] * fraction
+        })
+        .collect()
+}
+
+// Whether a chunk is too quiet to hold any speech
+fn is_silent(samples: &[f32]) -> bool {
+    if samples.is_empty() {
+        return true;
+    }
+    let mean_square = samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32;
+    mean_square.sqrt() < SILENCE_RMS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_whisper_input() {
+        // Stereo frames are averaged
+        assert_eq!(to_whisper_input(&[i16::MAX, 0, 0, 0], 2, 16_000), vec![0.5, 0.0]);
+        // 32 kHz is halved, interpolating between samples
+        let input = to_whisper_input(&[0, i16::MAX, 0, i16::MAX], 1, 32_000);
+        assert_eq!(input, vec![0.0, 0.0]);
+        let input = to_whisper_input(&[0, i16::MAX], 1, 8_000);
+        assert_eq!(input, vec![0.0, 0.5, 1.0, 1.0]);
+        assert!(is_silent(&to_whisper_input(&[10, -10, 10], 1, 16_000)));
+        assert!(!is_silent(&to_whisper_input(&[i16::MAX / 2, i16::MIN / 2], 1, 16_000)));
+    }
+}
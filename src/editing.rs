@@ -0,0 +1,380 @@
+use std::ops::Range;
+
+/// What Tab adds in front of a list item to nest it.
+const INDENT: &str = "    ";
+
+/// A change made to the note in the editor by one of the editing helpers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// The content of the note after the change.
+    pub content: String,
+    /// Where the cursor goes, as a character index.
+    pub cursor: usize,
+}
+
+// The start of a list item, e.g. `  1. [ ] `
+struct ListMarker<'a> {
+    indent: &'a str,
+    marker: &'a str,
+    checkbox: Option<bool>,
+    // Where the text of the item starts, after the marker and the space behind it
+    text_start: usize,
+    // Where the text starts after the checkbox, if there is one
+    end: usize,
+}
+
+fn list_marker(line: &str) -> Option<ListMarker<'_>> {
+    let rest = line.trim_start_matches([' ', '\t']);
+    let indent = &line[..line.len() - rest.len()];
+    let marker_len = if rest.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 || digits > 9 || !rest[digits..].starts_with(['.', ')']) {
+            return None;
+        }
+        digits + 1
+    };
+    if !rest[marker_len..].starts_with(' ') {
+        return None;
+    }
+    let text_start = indent.len() + marker_len + 1;
+    let (checkbox, checkbox_len) = match &line[text_start..] {
+        text if text.starts_with("[ ] ") => (Some(false), 4),
+        text if text.starts_with("[x] ") || text.starts_with("[X] ") => (Some(true), 4),
+        "[ ]" => (Some(false), 3),
+        "[x]" | "[X]" => (Some(true), 3),
+        _ => (None, 0),
+    };
+    Some(ListMarker {
+        indent,
+        marker: &rest[..marker_len],
+        checkbox,
+        text_start,
+        end: text_start + checkbox_len,
+    })
+}
+
+// The marker of the item after one with `marker`, counting up numbered lists
+fn next_marker(marker: &str) -> String {
+    let (number, delimiter) = marker.split_at(marker.len() - 1);
+    match number.parse::<u64>() {
+        Ok(number) => format!("{}{}", number + 1, delimiter),
+        Err(_) => marker.to_string(),
+    }
+}
+
+/// Continues a list when Enter is pressed in one of its items, with the same indentation and
+/// the next bullet or number. Enter on an empty item ends the list instead.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `cursor` - The position of the cursor, as a character index.
+///
+/// # Returns
+///
+/// The edit, or `None` if the cursor is not behind the marker of a list item.
+pub fn continue_list(content: &str, cursor: usize) -> Option<Edit> {
+    let at = byte_index(content, cursor);
+    let line = line_range(content, at);
+    let marker = list_marker(&content[line.clone()])?;
+    if at < line.start + marker.end.min(line.len()) {
+        return None;
+    }
+    let mut edited = content.to_string();
+    if content[line.start + marker.end.min(line.len())..line.end].trim().is_empty() {
+        edited.replace_range(line.clone(), "");
+        return Some(Edit {
+            content: edited,
+            cursor: content[..line.start].chars().count(),
+        });
+    }
+    let mut prefix = format!("\n{}{} ", marker.indent, next_marker(marker.marker));
+    if marker.checkbox.is_some() {
+        prefix.push_str("[ ] ");
+    }
+    edited.insert_str(at, &prefix);
+    Some(Edit {
+        content: edited,
+        cursor: cursor + prefix.chars().count(),
+    })
+}
+
+/// Nests a list item one level deeper, or takes it one level out.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `cursor` - The position of the cursor, as a character index.
+/// * `outdent` - Whether to take the item out rather than nest it.
+///
+/// # Returns
+///
+/// The edit, or `None` if the cursor is not in a list item or it cannot be taken further out.
+pub fn indent(content: &str, cursor: usize, outdent: bool) -> Option<Edit> {
+    let at = byte_index(content, cursor);
+    let line = line_range(content, at);
+    let marker = list_marker(&content[line.clone()])?;
+    let mut edited = content.to_string();
+    if !outdent {
+        edited.insert_str(line.start, INDENT);
+        return Some(Edit {
+            content: edited,
+            cursor: cursor + INDENT.len(),
+        });
+    }
+    let removed = if marker.indent.starts_with('\t') {
+        1
+    } else {
+        marker.indent.len().min(INDENT.len())
+    };
+    if removed == 0 {
+        return None;
+    }
+    edited.replace_range(line.start..line.start + removed, "");
+    let line_start = content[..line.start].chars().count();
+    Some(Edit {
+        content: edited,
+        cursor: cursor.saturating_sub(removed).max(line_start),
+    })
+}
+
+/// Ticks or unticks the checkbox of the line with the cursor, adding a `- [ ]` checkbox first if
+/// it has none.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `cursor` - The position of the cursor, as a character index.
+pub fn toggle_checkbox(content: &str, cursor: usize) -> Edit {
+    let at = byte_index(content, cursor);
+    let line = line_range(content, at);
+    let text = &content[line.clone()];
+    let (range, replacement) = match list_marker(text) {
+        Some(ListMarker {
+            checkbox: Some(checked),
+            text_start,
+            ..
+        }) => (text_start..text_start + 3, if checked { "[ ]" } else { "[x]" }),
+        Some(marker) => (marker.text_start..marker.text_start, "[ ] "),
+        None => {
+            let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+            (indent..indent, "- [ ] ")
+        }
+    };
+    let range = line.start + range.start..line.start + range.end;
+    let mut edited = content.to_string();
+    edited.replace_range(range.clone(), replacement);
+    let shift = replacement.len() - range.len();
+    Edit {
+        content: edited,
+        cursor: if at >= range.start { cursor + shift } else { cursor },
+    }
+}
+
+/// Aligns the pipes of the Markdown table the cursor is in, and moves the cursor to the end of
+/// another cell, e.g. the next one when Tab is pressed.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `cursor` - The position of the cursor, as a character index.
+/// * `step` - How many cells to move the cursor by, skipping the row under the header.
+///
+/// # Returns
+///
+/// The edit, or `None` if the cursor is not in a table or the table has no cells.
+pub fn format_table(content: &str, cursor: usize, step: isize) -> Option<Edit> {
+    let at = byte_index(content, cursor);
+    let lines: Vec<&str> = content.split('\n').collect();
+    let mut starts = Vec::with_capacity(lines.len());
+    let mut offset = 0;
+    for line in &lines {
+        starts.push(offset);
+        offset += line.len() + 1;
+    }
+    let current = starts.iter().rposition(|&start| start <= at)?;
+    let is_row = |line: &str| line.trim_start().starts_with('|');
+    if !is_row(lines[current]) {
+        return None;
+    }
+    let first = (0..current).rev().take_while(|&index| is_row(lines[index])).last().unwrap_or(current);
+    let last = (current + 1..lines.len()).take_while(|&index| is_row(lines[index])).last().unwrap_or(current);
+
+    let rows: Vec<Vec<String>> = lines[first..=last].iter().map(|line| cells(line)).collect();
+    let is_separator = |row: &[String]| {
+        row.iter().all(|cell| {
+            let dashes = cell.trim_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+    };
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0).max(1);
+    let mut widths = vec![3; columns];
+    for row in rows.iter().filter(|row| !is_separator(row)) {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(cell.chars().count());
+        }
+    }
+
+    let mut formatted = Vec::with_capacity(rows.len());
+    // The character offset of the end of the text of each cell, by row
+    let mut cell_ends = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let separator = is_separator(row);
+        let mut line = String::from("|");
+        let mut ends = Vec::with_capacity(columns);
+        for (column, &width) in widths.iter().enumerate() {
+            let cell = row.get(column).map_or("", String::as_str);
+            let text = if separator {
+                let left = if cell.starts_with(':') { ":" } else { "-" };
+                let right = if cell.len() > 1 && cell.ends_with(':') { ":" } else { "-" };
+                format!("{}{}{}", left, "-".repeat(width - 2), right)
+            } else {
+                cell.to_string()
+            };
+            line.push(' ');
+            line.push_str(&text);
+            ends.push(line.chars().count());
+            line.push_str(&" ".repeat(width - text.chars().count()));
+            line.push_str(" |");
+        }
+        formatted.push(line);
+        cell_ends.push((!separator).then_some(ends));
+    }
+
+    // Every cell the cursor can be moved to, row by row
+    let targets: Vec<(usize, usize)> = cell_ends
+        .iter()
+        .enumerate()
+        .filter_map(|(row, ends)| ends.as_ref().map(|ends| (row, ends.len())))
+        .flat_map(|(row, count)| (0..count).map(move |column| (row, column)))
+        .collect();
+    // A table of separator rows alone has no cell to move to
+    if targets.is_empty() {
+        return None;
+    }
+    let row = current - first;
+    let line = lines[current];
+    let pipes = line[..at - starts[current]].chars().filter(|&c| c == '|').count();
+    let column = pipes.saturating_sub(1).min(columns - 1);
+    let position = targets
+        .iter()
+        .position(|&(target_row, target_column)| target_row >= row && (target_row > row || target_column >= column))
+        .unwrap_or(targets.len().saturating_sub(1));
+    let (target_row, target_column) = targets
+        .get((position as isize + step).clamp(0, targets.len() as isize - 1) as usize)
+        .copied()
+        .unwrap_or((row, 0));
+
+    let before = content[..starts[first]].chars().count();
+    let row_start: usize = before + formatted[..target_row].iter().map(|line| line.chars().count() + 1).sum::<usize>();
+    let cursor = match &cell_ends[target_row] {
+        Some(ends) => row_start + ends[target_column],
+        None => row_start,
+    };
+    let end = starts[last] + lines[last].len();
+    let mut edited = content[..starts[first]].to_string();
+    edited.push_str(&formatted.join("\n"));
+    edited.push_str(&content[end..]);
+    Some(Edit { content: edited, cursor })
+}
+
+// Splits a table row into its trimmed cells, keeping escaped pipes
+fn cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = match line.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => line,
+    };
+    let mut cells = vec![String::new()];
+    let mut escaped = false;
+    for c in line.chars() {
+        if c == '|' && !escaped {
+            cells.push(String::new());
+        } else {
+            cells.last_mut().unwrap().push(c);
+        }
+        escaped = c == '\\';
+    }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+// The byte range of the line containing byte `at`, without its line break
+fn line_range(content: &str, at: usize) -> Range<usize> {
+    let start = content[..at].rfind('\n').map_or(0, |index| index + 1);
+    let end = content[at..].find('\n').map_or(content.len(), |index| at + index);
+    start..end
+}
+
+fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices().nth(char_index).map_or(text.len(), |(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Applies a helper to text with the cursor at `|`, and marks the new cursor the same way
+    fn run(text: &str, helper: impl Fn(&str, usize) -> Option<Edit>) -> Option<String> {
+        let cursor = text.chars().position(|c| c == '¦').unwrap();
+        let edit = helper(&text.replace('¦', ""), cursor)?;
+        let at = byte_index(&edit.content, edit.cursor);
+        Some(format!("{}¦{}", &edit.content[..at], &edit.content[at..]))
+    }
+
+    #[test]
+    fn test_continue_list() {
+        assert_eq!(run("- milk¦", continue_list).unwrap(), "- milk\n- ¦");
+        assert_eq!(run("  9. ten¦\nend", continue_list).unwrap(), "  9. ten\n  10. ¦\nend");
+        assert_eq!(run("* [x] done¦", continue_list).unwrap(), "* [x] done\n* [ ] ¦");
+        assert_eq!(run("- split¦ here", continue_list).unwrap(), "- split\n- ¦ here");
+        // An empty item ends the list
+        assert_eq!(run("- a\n- ¦", continue_list).unwrap(), "- a\n¦");
+        assert_eq!(run("- a\n- [ ] ¦\nb", continue_list).unwrap(), "- a\n¦\nb");
+        assert_eq!(run("plain¦", continue_list), None);
+        assert_eq!(run("**bold**¦", continue_list), None);
+        assert_eq!(run("-¦ a", continue_list), None);
+    }
+
+    #[test]
+    fn test_indent() {
+        assert_eq!(run("- a\n- b¦", |text, cursor| indent(text, cursor, false)).unwrap(), "- a\n    - b¦");
+        assert_eq!(run("      - b¦", |text, cursor| indent(text, cursor, true)).unwrap(), "  - b¦");
+        assert_eq!(run(" ¦ - b", |text, cursor| indent(text, cursor, true)).unwrap(), "¦- b");
+        assert_eq!(run("- b¦", |text, cursor| indent(text, cursor, true)), None);
+        assert_eq!(run("text¦", |text, cursor| indent(text, cursor, false)), None);
+    }
+
+    #[test]
+    fn test_toggle_checkbox() {
+        let toggle = |text: &str, cursor| Some(toggle_checkbox(text, cursor));
+        assert_eq!(run("  buy mi¦lk", toggle).unwrap(), "  - [ ] buy mi¦lk");
+        assert_eq!(run("- buy¦", toggle).unwrap(), "- [ ] buy¦");
+        assert_eq!(run("- [ ] buy¦", toggle).unwrap(), "- [x] buy¦");
+        assert_eq!(run("a\n1. [X] b¦", toggle).unwrap(), "a\n1. [ ] b¦");
+        assert_eq!(run("¦", toggle).unwrap(), "- [ ] ¦");
+    }
+
+    #[test]
+    fn test_format_table() {
+        let table = "Intro\n|Name|Qty|\n|:-|-:|\n|apples¦|3|\n| pears \\| plums |12\nEnd";
+        assert_eq!(
+            run(table, |text, cursor| format_table(text, cursor, 0)).unwrap(),
+            "Intro\n| Name           | Qty |\n| :------------- | --: |\n| apples¦         | 3   |\n| pears \\| plums | 12  |\nEnd"
+        );
+        assert_eq!(
+            run(table, |text, cursor| format_table(text, cursor, 1)).unwrap(),
+            "Intro\n| Name           | Qty |\n| :------------- | --: |\n| apples         | 3¦   |\n| pears \\| plums | 12  |\nEnd"
+        );
+        // Moving back skips the row under the header
+        assert_eq!(
+            run(table, |text, cursor| format_table(text, cursor, -1)).unwrap(),
+            "Intro\n| Name           | Qty¦ |\n| :------------- | --: |\n| apples         | 3   |\n| pears \\| plums | 12  |\nEnd"
+        );
+        assert_eq!(run("no | table¦", |text, cursor| format_table(text, cursor, 1)), None);
+        assert_eq!(run("|---|¦", |text, cursor| format_table(text, cursor, 1)), None);
+        assert_eq!(run("|---|¦", |text, cursor| format_table(text, cursor, -1)), None);
+    }
+}
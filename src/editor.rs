@@ -0,0 +1,362 @@
+use std::io;
+
+use crate::folders::FolderSettings;
+use crate::front_matter::FrontMatter;
+use crate::merge;
+use crate::notes::Notes;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::protect::Passphrase;
+use crate::schema::SchemaError;
+
+/// The in-memory content of the note open in the editor.
+pub struct NoteBuffer {
+    /// The title of the open note.
+    pub title: String,
+    /// The content as currently edited.
+    pub content: String,
+    /// The content as last read from or written to disk.
+    saved_content: String,
+    /// Content written to disk by someone else while the buffer had unsaved changes.
+    pub conflict: Option<String>,
+    /// When the buffer was last edited, in seconds on the UI clock.
+    last_edit: Option<f64>,
+    /// The settings of the folders containing the note.
+    pub settings: FolderSettings,
+    /// The front matter fields that did not match the folder's schema when the note was last saved.
+    pub schema_errors: Vec<SchemaError>,
+    /// The key of a note protected with a passphrase once it is unlocked. The buffer then holds the
+    /// decrypted content, which is encrypted again when it is saved.
+    #[cfg(not(target_arch = "wasm32"))]
+    passphrase: Option<Passphrase>,
+}
+
+impl NoteBuffer {
+    /// Creates a buffer for a note whose content on disk is `content`.
+    pub fn new(title: &str, content: String) -> NoteBuffer {
+        NoteBuffer {
+            title: title.to_string(),
+            saved_content: content.clone(),
+            content,
+            conflict: None,
+            last_edit: None,
+            settings: FolderSettings::default(),
+            schema_errors: Vec::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            passphrase: None,
+        }
+    }
+
+    /// Opens the note with the given title from disk, along with the settings of its folders.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<NoteBuffer>` containing the buffer or an error.
+    pub fn open(title: &str) -> io::Result<NoteBuffer> {
+        let mut buffer = Self::new(title, Notes::read_note_file(title)?);
+        buffer.settings = FolderSettings::for_note(title)?;
+        buffer.validate();
+        Ok(buffer)
+    }
+
+    /// Returns whether the buffer has changes that are not on disk.
+    pub fn is_dirty(&self) -> bool {
+        self.content != self.saved_content
+    }
+
+    /// Returns whether the note is read-only, see `FrontMatter::is_locked`. The mark on disk counts,
+    /// so that it can't be edited away.
+    pub fn is_locked(&self) -> bool {
+        FrontMatter::parse(&self.saved_content).0.is_locked()
+    }
+
+    /// Returns whether the note is protected with a passphrase and not unlocked, so that the buffer
+    /// holds the encrypted note, which is shown and saved as it is.
+    pub fn is_sealed(&self) -> bool {
+        FrontMatter::parse(&self.saved_content).0.is_encrypted()
+    }
+
+    /// Returns whether the note is protected with a passphrase, unlocked or not.
+    pub fn is_protected(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.passphrase.is_some() {
+            return true;
+        }
+        self.is_sealed()
+    }
+
+    /// Unlocks a note protected with a passphrase.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success, or a `PermissionDenied` error if the passphrase is wrong.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn unlock(&mut self, passphrase: &str) -> io::Result<()> {
+        let (content, passphrase) = Passphrase::unlock(&self.saved_content, passphrase)?;
+        self.content = content.clone();
+        self.saved_content = content;
+        self.passphrase = Some(passphrase);
+        self.validate();
+        Ok(())
+    }
+
+    /// Protects the note with a passphrase, encrypting it on disk right away.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn protect(&mut self, passphrase: &str) -> io::Result<()> {
+        self.passphrase = Some(Passphrase::new(passphrase));
+        self.write()
+    }
+
+    /// Removes the passphrase of an unlocked note, storing it unencrypted again.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn unprotect(&mut self) -> io::Result<()> {
+        if self.passphrase.take().is_some() {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    /// Locks an unlocked note again after saving it, so that only the encrypted note is kept in memory.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn relock(&mut self) -> io::Result<()> {
+        if self.passphrase.is_none() {
+            return Ok(());
+        }
+        if self.is_dirty() {
+            self.save()?;
+        }
+        let sealed = Notes::read_note_file(&self.title)?;
+        self.passphrase = None;
+        self.content = sealed.clone();
+        self.saved_content = sealed;
+        Ok(())
+    }
+
+    /// Records that the buffer was edited at time `now`, postponing the next autosave.
+    pub fn mark_edited(&mut self, now: f64) {
+        self.last_edit = Some(now);
+    }
+
+    /// Returns how many seconds remain until the buffer should be saved automatically.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time in seconds on the UI clock.
+    /// * `delay` - The number of seconds to wait after the last edit.
+    ///
+    /// # Returns
+    ///
+    /// `None` if there is nothing to save, the buffer has an unresolved conflict or the note is
+    /// locked or sealed, otherwise the remaining time, which is zero once the save is due.
+    pub fn autosave_in(&self, now: f64, delay: f64) -> Option<f64> {
+        if !self.is_dirty() || self.conflict.is_some() || self.is_locked() || self.is_sealed() {
+            return None;
+        }
+        let elapsed = self.last_edit.map_or(delay, |last_edit| now - last_edit);
+        Some((delay - elapsed).max(0.0))
+    }
+
+    /// Writes the buffer to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success, or a `PermissionDenied` error if the note is locked
+    /// or sealed.
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.is_locked() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is locked", self.title),
+            ));
+        }
+        if self.is_sealed() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is protected; unlock it first", self.title),
+            ));
+        }
+        self.write()
+    }
+
+    // Writes the content, encrypted if the note is protected
+    fn write(&mut self) -> io::Result<()> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let stored = match &self.passphrase {
+            Some(passphrase) => passphrase.seal(&self.content)?,
+            None => self.content.clone(),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let stored = self.content.clone();
+        Notes::update_note_file(&self.title, &stored)?;
+        self.saved_content = self.content.clone();
+        self.validate();
+        Ok(())
+    }
+
+    /// Checks the front matter against the schema of the note's folder.
+    fn validate(&mut self) {
+        self.schema_errors = match &self.settings.schema {
+            Some(schema) => schema.validate(&self.content),
+            None => Vec::new(),
+        };
+    }
+
+    /// Re-reads the note after it changed on disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let disk_content = Notes::read_note_file(&self.title)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let disk_content = match &self.passphrase {
+            Some(passphrase) if FrontMatter::parse(&disk_content).0.is_encrypted() => passphrase.open(&disk_content)?,
+            // The passphrase was removed elsewhere
+            Some(_) => {
+                self.passphrase = None;
+                disk_content
+            }
+            None => disk_content,
+        };
+        self.apply_disk_content(disk_content);
+        Ok(())
+    }
+
+    /// Takes in new content found on disk.
+    ///
+    /// A clean buffer is replaced by the new content. The changes are merged into a dirty
+    /// buffer, which stays dirty so that the merged result gets saved. If the disk content
+    /// and the buffer changed the same lines, the disk content is recorded as a conflict
+    /// for the user to resolve instead.
+    pub fn apply_disk_content(&mut self, disk_content: String) {
+        if disk_content == self.saved_content {
+            // Our own write, or nothing changed.
+            return;
+        }
+        if self.conflict.is_some() {
+            self.conflict = Some(disk_content);
+        } else if self.is_dirty() {
+            match merge::merge(&self.saved_content, &self.content, &disk_content) {
+                Some(merged) => {
+                    self.content = merged;
+                    self.saved_content = disk_content;
+                }
+                None => self.conflict = Some(disk_content),
+            }
+        } else {
+            self.content = disk_content.clone();
+            self.saved_content = disk_content;
+        }
+    }
+
+    /// Resolves a conflict by keeping the buffer; the next save overwrites the disk content.
+    pub fn keep_mine(&mut self) {
+        if let Some(disk_content) = self.conflict.take() {
+            self.saved_content = disk_content;
+        }
+    }
+
+    /// Resolves a conflict by discarding the buffer in favour of the disk content.
+    pub fn take_theirs(&mut self) {
+        if let Some(disk_content) = self.conflict.take() {
+            self.content = disk_content.clone();
+            self.saved_content = disk_content;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_buffer_follows_disk() {
+        let mut buffer = NoteBuffer::new("note", "old".to_string());
+        buffer.apply_disk_content("new".to_string());
+        assert_eq!(buffer.content, "new");
+        assert!(!buffer.is_dirty());
+        assert!(buffer.conflict.is_none());
+    }
+
+    #[test]
+    fn test_dirty_buffer_records_conflict() {
+        let mut buffer = NoteBuffer::new("note", "old".to_string());
+        buffer.content = "mine".to_string();
+        buffer.apply_disk_content("theirs".to_string());
+        assert_eq!(buffer.content, "mine");
+        assert_eq!(buffer.conflict.as_deref(), Some("theirs"));
+
+        buffer.take_theirs();
+        assert_eq!(buffer.content, "theirs");
+        assert!(!buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_dirty_buffer_merges_separate_changes() {
+        let mut buffer = NoteBuffer::new("note", "title\nbody\n".to_string());
+        buffer.content = "title\nbody\nmine\n".to_string();
+        buffer.apply_disk_content("TITLE\nbody\n".to_string());
+        assert_eq!(buffer.content, "TITLE\nbody\nmine\n");
+        assert!(buffer.conflict.is_none());
+        assert!(buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_autosave_is_debounced() {
+        let mut buffer = NoteBuffer::new("note", "old".to_string());
+        assert_eq!(buffer.autosave_in(0.0, 2.0), None);
+
+        buffer.content = "new".to_string();
+        buffer.mark_edited(10.0);
+        assert_eq!(buffer.autosave_in(10.5, 2.0), Some(1.5));
+        buffer.mark_edited(11.0);
+        assert_eq!(buffer.autosave_in(12.0, 2.0), Some(1.0));
+        assert_eq!(buffer.autosave_in(14.0, 2.0), Some(0.0));
+
+        buffer.conflict = Some("theirs".to_string());
+        assert_eq!(buffer.autosave_in(14.0, 2.0), None);
+    }
+
+    #[test]
+    fn test_locked_buffer_is_not_saved() {
+        let mut buffer = NoteBuffer::new("note", "---\nlocked: true\n---\nold".to_string());
+        assert!(buffer.is_locked());
+        // Removing the mark in the buffer doesn't unlock it
+        buffer.content = "new".to_string();
+        assert!(buffer.is_locked());
+        assert_eq!(buffer.autosave_in(10.0, 2.0), None);
+        assert_eq!(buffer.save().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_sealed_buffer_is_not_saved() {
+        let mut buffer = NoteBuffer::new("note", "---\nencrypted: chacha20poly1305\n---\nk9Vd".to_string());
+        assert!(buffer.is_sealed());
+        assert!(buffer.is_protected());
+        buffer.content.push_str("typed");
+        assert_eq!(buffer.autosave_in(10.0, 2.0), None);
+        assert_eq!(buffer.save().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_keep_mine_stays_dirty() {
+        let mut buffer = NoteBuffer::new("note", "old".to_string());
+        buffer.content = "mine".to_string();
+        buffer.apply_disk_content("theirs".to_string());
+        buffer.keep_mine();
+        assert_eq!(buffer.content, "mine");
+        assert!(buffer.is_dirty());
+        assert!(buffer.conflict.is_none());
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::attachments;
+use crate::commands::expand_home;
+use crate::front_matter::FrontMatter;
+use crate::lang::{NoteLanguage, TextDirection};
+use crate::notes::Notes;
+use crate::permalinks::slugify;
+use crate::preview::{Block, Inline};
+use crate::reader::{self, Paragraph};
+use crate::tags;
+use crate::title;
+
+/// The front matter fields that store the export preset of a note.
+const FORMAT_FIELD: &str = "export-format";
+const DESTINATION_FIELD: &str = "export-to";
+const STYLESHEET_FIELD: &str = "export-stylesheet";
+const ATTACHMENTS_FIELD: &str = "export-attachments";
+
+/// The file format a note is exported to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteFormat {
+    /// The note as written, front matter included.
+    #[default]
+    Markdown,
+    /// A standalone web page.
+    Html,
+    /// The body of the note without front matter.
+    Text,
+}
+
+impl NoteFormat {
+    /// Every format, in the order they are offered.
+    pub const ALL: [NoteFormat; 3] = [NoteFormat::Markdown, NoteFormat::Html, NoteFormat::Text];
+
+    /// Parses a format name as written in front matter or on the command line.
+    pub fn parse(name: &str) -> Option<NoteFormat> {
+        match name.trim().to_lowercase().as_str() {
+            "md" | "markdown" => Some(NoteFormat::Markdown),
+            "html" => Some(NoteFormat::Html),
+            "txt" | "text" => Some(NoteFormat::Text),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the format, which is also the extension of exported files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            NoteFormat::Markdown => "md",
+            NoteFormat::Html => "html",
+            NoteFormat::Text => "txt",
+        }
+    }
+}
+
+/// How a note is exported, remembered in its front matter so that it can be exported again.
+///
+/// ```text
+/// ---
+/// export-format: html
+/// export-to: ~/site
+/// export-stylesheet: ~/site/style.css
+/// export-attachments: true
+/// ---
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportPreset {
+    /// The format to export to.
+    pub format: NoteFormat,
+    /// The directory the exported file is written to. A leading `~` is expanded.
+    pub destination: String,
+    /// A CSS file embedded in HTML exports.
+    pub stylesheet: Option<String>,
+    /// Whether attachments the note links to are copied along with it.
+    pub include_attachments: bool,
+}
+
+impl ExportPreset {
+    /// Reads the preset stored in the front matter of a note.
+    ///
+    /// # Returns
+    ///
+    /// The preset, or `None` if the note has no format or destination.
+    pub fn from_front_matter(front_matter: &FrontMatter) -> Option<ExportPreset> {
+        Some(ExportPreset {
+            format: NoteFormat::parse(front_matter.get(FORMAT_FIELD)?)?,
+            destination: front_matter.get(DESTINATION_FIELD).filter(|dir| !dir.is_empty())?.to_string(),
+            stylesheet: front_matter
+                .get(STYLESHEET_FIELD)
+                .filter(|path| !path.is_empty())
+                .map(str::to_string),
+            include_attachments: front_matter.get(ATTACHMENTS_FIELD) == Some("true"),
+        })
+    }
+
+    /// Stores the preset in the front matter of a note, replacing any earlier preset.
+    pub fn write_to(&self, front_matter: &mut FrontMatter) {
+        front_matter.set(FORMAT_FIELD, self.format.extension());
+        front_matter.set(DESTINATION_FIELD, &self.destination);
+        match &self.stylesheet {
+            Some(stylesheet) => front_matter.set(STYLESHEET_FIELD, stylesheet),
+            None => front_matter.remove(STYLESHEET_FIELD),
+        }
+        front_matter.set(ATTACHMENTS_FIELD, if self.include_attachments { "true" } else { "false" });
+    }
+
+    /// Loads the preset of a note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Option<ExportPreset>>` containing the preset, or `None` if the note has none.
+    pub fn load(title: &str) -> io::Result<Option<ExportPreset>> {
+        let content = Notes::read_note_file(title)?;
+        Ok(Self::from_front_matter(&FrontMatter::parse(&content).0))
+    }
+}
+
+/// Exports a note and remembers the preset in its front matter.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `preset` - How to export the note.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the path of the exported file.
+pub fn export(title: &str, preset: &ExportPreset) -> io::Result<PathBuf> {
+    let content = Notes::read_note_file(title)?;
+    let (mut front_matter, body) = FrontMatter::parse(&content);
+    if ExportPreset::from_front_matter(&front_matter).as_ref() != Some(preset) {
+        preset.write_to(&mut front_matter);
+        Notes::update_note_file(title, &front_matter.render(body))?;
+    }
+    // The preset itself is of no use to readers of the export.
+    for field in [FORMAT_FIELD, DESTINATION_FIELD, STYLESHEET_FIELD, ATTACHMENTS_FIELD] {
+        front_matter.remove(field);
+    }
+
+    let destination = expand_home(&preset.destination);
+    fs::create_dir_all(&destination)?;
+    let name = title::to_path(&Notes::get_notes_dir()?, title, preset.format.extension())?;
+    let path = destination.join(name.file_name().unwrap_or_default());
+    let output = match preset.format {
+        NoteFormat::Markdown => front_matter.render(body),
+        NoteFormat::Text => body.to_string(),
+        NoteFormat::Html => {
+            let stylesheet = match &preset.stylesheet {
+                Some(stylesheet) => fs::read_to_string(expand_home(stylesheet))?,
+                None => String::new(),
+            };
+            let body = body_html(body, "", &|_| None);
+            page(title, NoteLanguage::of(&content).as_ref(), &stylesheet, &body)
+        }
+    };
+    fs::write(&path, output)?;
+
+    if preset.include_attachments {
+        // Links keep working because the attachments folder is recreated next to the export.
+        for attachment in attachments::links(body) {
+            let target = destination.join(attachment);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(Notes::get_notes_dir()?.join(attachment), target)?;
+        }
+    }
+    Ok(path)
+}
+
+/// Exports every note as a static web site, with an index page, a page per tag and wiki links
+/// turned into links between the pages, so that the notes can be published or browsed without the app.
+///
+/// Each note is written to `<slug>.html`, where the slug is its `slug` front matter field or is
+/// derived from its title. Attachments the notes link to are copied along.
+///
+/// # Arguments
+///
+/// * `output_dir` - The directory the site is written to. It is created if needed.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of notes exported.
+pub fn site(output_dir: &Path) -> io::Result<usize> {
+    let mut notes = Vec::new();
+    for title in Notes::list_notes()? {
+        let content = Notes::read_note_file(&title)?;
+        notes.push((title, content));
+    }
+    notes.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+
+    // Pages are named after unique slugs, as different titles can share one
+    let mut pages: HashMap<&str, String> = HashMap::new();
+    let mut used = HashSet::new();
+    for (title, content) in &notes {
+        let (front_matter, _) = FrontMatter::parse(content);
+        let slug = front_matter
+            .get("slug")
+            .map(slugify)
+            .filter(|slug| !slug.is_empty())
+            .unwrap_or_else(|| slugify(title));
+        let slug = if slug.is_empty() { "note".to_string() } else { slug };
+        let mut unique = slug.clone();
+        let mut suffix = 2;
+        while !used.insert(unique.clone()) {
+            unique = format!("{}-{}", slug, suffix);
+            suffix += 1;
+        }
+        pages.insert(title, format!("{}.html", unique));
+    }
+    let mut tagged: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for (title, content) in &notes {
+        for tag in tags::extract(content) {
+            tagged.entry(tag).or_default().push(title);
+        }
+    }
+    let tag_page = |tag: &str| format!("tags/{}.html", slugify(tag));
+
+    let root = Notes::get_notes_dir()?;
+    for (title, content) in &notes {
+        let path = &pages[title.as_str()];
+        let base = relative(path, "");
+        let (_, body) = FrontMatter::parse(content);
+        let mut html = format!("<nav><a href=\"{}index.html\">Index</a></nav>\n", base);
+        html.push_str(&body_html(body, &base, &|target| {
+            pages.get(target).map(|page| relative(path, page))
+        }));
+        let note_tags = tags::extract(content);
+        if !note_tags.is_empty() {
+            let links: Vec<String> = note_tags
+                .iter()
+                .map(|tag| format!("<a href=\"{}\">#{}</a>", escape(&relative(path, &tag_page(tag))), escape(tag)))
+                .collect();
+            html.push_str(&format!("<p class=\"tags\">{}</p>\n", links.join(" ")));
+        }
+        write_page(&output_dir.join(path), &page(title, NoteLanguage::of(content).as_ref(), "", &html))?;
+
+        for attachment in attachments::links(body) {
+            let target = output_dir.join(attachment);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(root.join(attachment), target)?;
+        }
+    }
+
+    for (tag, titles) in &tagged {
+        let path = tag_page(tag);
+        let mut html = format!("<nav><a href=\"{}index.html\">Index</a></nav>\n", relative(&path, ""));
+        html.push_str(&format!("<h1>#{}</h1>\n", escape(tag)));
+        html.push_str(&note_list(titles.iter().map(|title| (*title, relative(&path, &pages[title])))));
+        write_page(&output_dir.join(&path), &page(&format!("#{}", tag), None, "", &html))?;
+    }
+
+    let mut html = "<h1>Notes</h1>\n".to_string();
+    html.push_str(&note_list(notes.iter().map(|(title, _)| (title.as_str(), pages[title.as_str()].clone()))));
+    if !tagged.is_empty() {
+        html.push_str("<h2>Tags</h2>\n");
+        html.push_str(&note_list(tagged.keys().map(|tag| (tag.as_str(), tag_page(tag)))));
+    }
+    write_page(&output_dir.join("index.html"), &page("Notes", None, "", &html))?;
+    Ok(notes.len())
+}
+
+/// Returns the link from one page of a site to another, both relative to the root of the site.
+fn relative(from: &str, to: &str) -> String {
+    format!("{}{}", "../".repeat(from.matches('/').count()), to)
+}
+
+fn note_list<'a>(entries: impl Iterator<Item = (&'a str, String)>) -> String {
+    let mut html = "<ul>\n".to_string();
+    for (label, href) in entries {
+        html.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", escape(&href), escape(label)));
+    }
+    html.push_str("</ul>\n");
+    html
+}
+
+fn write_page(path: &Path, html: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, html)
+}
+
+/// Wraps rendered HTML in a standalone web page.
+///
+/// With a language, the page is marked with it and its direction, and browsers hyphenate the text
+/// by the rules of the language.
+pub(crate) fn page(title: &str, language: Option<&NoteLanguage>, stylesheet: &str, body: &str) -> String {
+    let mut html = "<!DOCTYPE html>\n".to_string();
+    match language {
+        Some(language) => {
+            let direction = match language.direction() {
+                TextDirection::LeftToRight => "ltr",
+                TextDirection::RightToLeft => "rtl",
+            };
+            html.push_str(&format!("<html lang=\"{}\" dir=\"{}\">\n", escape(&language.code), direction));
+        }
+        None => html.push_str("<html>\n"),
+    }
+    html.push_str(&format!("<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n", escape(title)));
+    if language.is_some() {
+        html.push_str("<style>\nbody { hyphens: auto; }\n</style>\n");
+    }
+    if !stylesheet.is_empty() {
+        html.push_str(&format!("<style>\n{}\n</style>\n", stylesheet));
+    }
+    html.push_str("</head>\n<body>\n");
+    html.push_str(body);
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Renders the body of a note as HTML.
+///
+/// # Arguments
+///
+/// * `body` - The body of the note, without front matter.
+/// * `base` - Prepended to relative image paths, for pages in subdirectories.
+/// * `link` - Returns where a wiki link to the given title points, or `None` to leave it as written.
+pub(crate) fn body_html(body: &str, base: &str, link: &dyn Fn(&str) -> Option<String>) -> String {
+    let image_html = |alt: &str, target: &str| {
+        if target.contains("://") || target.starts_with('/') {
+            image(alt, target)
+        } else {
+            image(alt, &format!("{}{}", base, target))
+        }
+    };
+    let mut html = String::new();
+    for paragraph in reader::paragraphs(body) {
+        match paragraph {
+            Paragraph::Text(text) => html.push_str(&format!("<p>{}</p>\n", text_html(&text, link))),
+            Paragraph::Block(Block::Heading { level, text }) => {
+                html.push_str(&format!("<h{level}>{}</h{level}>\n", text_html(text, link), level = level));
+            }
+            Paragraph::Block(Block::Image { alt, target }) => {
+                html.push_str(&format!("<p>{}</p>\n", image_html(alt, target)));
+            }
+            Paragraph::Block(Block::Audio { name, target }) => {
+                html.push_str(&format!(
+                    "<p><audio controls src=\"{}{}\" title=\"{}\"></audio></p>\n",
+                    escape(base),
+                    escape(target),
+                    escape(name)
+                ));
+            }
+            Paragraph::Block(Block::Inline(inlines)) => {
+                html.push_str("<p>");
+                for inline in inlines {
+                    match inline {
+                        Inline::Text(text) => html.push_str(&text_html(text, link)),
+                        Inline::Image { alt, target } => html.push_str(&image_html(alt, target)),
+                    }
+                }
+                html.push_str("</p>\n");
+            }
+            Paragraph::Block(Block::Code { language, code }) if language.is_empty() => {
+                html.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&code)));
+            }
+            Paragraph::Block(Block::Code { language, code }) => html.push_str(&format!(
+                "<pre><code class=\"language-{}\">{}</code></pre>\n",
+                escape(language),
+                escape(&code)
+            )),
+            Paragraph::Block(Block::Text(text)) => html.push_str(&format!("<p>{}</p>\n", text_html(text, link))),
+            Paragraph::Block(Block::Blank) => {}
+        }
+    }
+    html
+}
+
+/// Escapes a line of text, turning the wiki links `link` knows about into HTML links.
+fn text_html(text: &str, link: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut html = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let end = start + len + 4;
+        let inner = &rest[start + 2..end - 2];
+        let (target, label) = inner.split_once('|').unwrap_or((inner, inner));
+        html.push_str(&escape(&rest[..start]));
+        match link(target.trim()) {
+            Some(href) => html.push_str(&format!("<a href=\"{}\">{}</a>", escape(&href), escape(label.trim()))),
+            None => html.push_str(&escape(&rest[start..end])),
+        }
+        rest = &rest[end..];
+    }
+    html.push_str(&escape(rest));
+    html
+}
+
+fn image(alt: &str, target: &str) -> String {
+    format!("<img src=\"{}\" alt=\"{}\">", escape(target), escape(alt))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::ATTACHMENTS_DIR;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_preset_round_trip() {
+        let preset = ExportPreset {
+            format: NoteFormat::Html,
+            destination: "~/site".to_string(),
+            stylesheet: None,
+            include_attachments: true,
+        };
+        let mut front_matter = FrontMatter::default();
+        preset.write_to(&mut front_matter);
+        assert_eq!(front_matter.get("export-format"), Some("html"));
+        assert_eq!(ExportPreset::from_front_matter(&front_matter), Some(preset));
+        assert_eq!(ExportPreset::from_front_matter(&FrontMatter::default()), None);
+    }
+
+    #[test]
+    fn test_export_remembers_preset() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        fs::create_dir_all(temp_notes_dir.join(ATTACHMENTS_DIR)).unwrap();
+        fs::write(temp_notes_dir.join(ATTACHMENTS_DIR).join("cat.png"), b"png").unwrap();
+        fs::write(temp_notes_dir.join("style.css"), "p { margin: 0 }").unwrap();
+        Notes::create_note_file("posts/hello", "# Hello <world>\nfirst\nline\n\n![cat](attachments/cat.png)").unwrap();
+
+        let destination = temp_notes_dir.parent().unwrap().join("site");
+        let preset = ExportPreset {
+            format: NoteFormat::Html,
+            destination: destination.to_string_lossy().into_owned(),
+            stylesheet: Some(temp_notes_dir.join("style.css").to_string_lossy().into_owned()),
+            include_attachments: true,
+        };
+        let path = export("posts/hello", &preset).unwrap();
+        assert_eq!(path, destination.join("hello.html"));
+        let html = fs::read_to_string(&path).unwrap();
+        assert!(html.contains("<style>\np { margin: 0 }\n</style>"));
+        assert!(html.contains("<h1>Hello &lt;world&gt;</h1>\n<p>first line</p>"));
+        assert!(html.contains("<img src=\"attachments/cat.png\" alt=\"cat\">"));
+        assert!(destination.join("attachments").join("cat.png").exists());
+
+        assert_eq!(ExportPreset::load("posts/hello").unwrap(), Some(preset));
+        let markdown = ExportPreset {
+            format: NoteFormat::Markdown,
+            ..ExportPreset::load("posts/hello").unwrap().unwrap()
+        };
+        let path = export("posts/hello", &markdown).unwrap();
+        assert!(fs::read_to_string(path).unwrap().starts_with("# Hello <world>"));
+    }
+
+    #[test]
+    fn test_page_language() {
+        let german = NoteLanguage::of("---\nlang: de\n---\n").unwrap();
+        let html = page("Plan", Some(&german), "", "<p>Silbentrennung</p>\n");
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"de\" dir=\"ltr\">\n<head>"));
+        assert!(html.contains("body { hyphens: auto; }"));
+        let hebrew = NoteLanguage::of("---\nlang: he\n---\n").unwrap();
+        assert!(page("Plan", Some(&hebrew), "", "").contains("<html lang=\"he\" dir=\"rtl\">"));
+
+        let html = page("Plan", None, "", "");
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>"));
+        assert!(!html.contains("hyphens"));
+    }
+
+    #[test]
+    fn test_export_site() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        fs::create_dir_all(temp_notes_dir.join(ATTACHMENTS_DIR)).unwrap();
+        fs::write(temp_notes_dir.join(ATTACHMENTS_DIR).join("cat.png"), b"png").unwrap();
+        Notes::create_note_file(
+            "Home",
+            "---\nslug: start\n---\nStart at [[posts/Hello World|the first post]] #blog\n\nNot [[Missing]]",
+        )
+        .unwrap();
+        Notes::create_note_file("posts/Hello World", "Back [[Home]]\n\n![cat](attachments/cat.png)").unwrap();
+
+        let output = temp_notes_dir.parent().unwrap().join("site");
+        assert_eq!(site(&output).unwrap(), 2);
+
+        let home = fs::read_to_string(output.join("start.html")).unwrap();
+        assert!(home.contains("Start at <a href=\"posts/hello-world.html\">the first post</a>"));
+        assert!(home.contains("Not [[Missing]]"));
+        assert!(home.contains("<a href=\"tags/blog.html\">#blog</a>"));
+
+        let post = fs::read_to_string(output.join("posts").join("hello-world.html")).unwrap();
+        assert!(post.contains("<a href=\"../index.html\">Index</a>"));
+        assert!(post.contains("Back <a href=\"../start.html\">Home</a>"));
+        assert!(post.contains("<img src=\"../attachments/cat.png\" alt=\"cat\">"));
+        assert!(output.join("attachments").join("cat.png").exists());
+
+        let tag = fs::read_to_string(output.join("tags").join("blog.html")).unwrap();
+        assert!(tag.contains("<a href=\"../start.html\">Home</a>"));
+        let index = fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"posts/hello-world.html\">posts/Hello World</a>"));
+        assert!(index.contains("<a href=\"tags/blog.html\">blog</a>"));
+    }
+}
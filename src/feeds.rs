@@ -0,0 +1,369 @@
+//! RSS and Atom feeds whose new items are saved as notes, for reading later.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::clip::{self, clip_title};
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+
+/// The name of the file in the `.notes` directory that stores the registered feeds.
+pub const FEEDS_FILE: &str = ".feeds.json";
+
+/// The folder the notes of each feed are saved in, e.g. `feeds/rust-blog`.
+pub const FEEDS_FOLDER: &str = "feeds";
+
+/// A feed registered by the user.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    /// The unique name of the feed, which is also the name of its folder.
+    pub name: String,
+    /// The URL of the RSS or Atom document.
+    pub url: String,
+    /// The GUIDs of the items already saved, so that they are not saved again.
+    #[serde(default)]
+    pub seen: Vec<String>,
+}
+
+impl Feed {
+    /// Returns the folder the items of the feed are saved in.
+    pub fn folder(&self) -> String {
+        format!("{}/{}", FEEDS_FOLDER, clip_title(&self.name))
+    }
+}
+
+/// An item of a feed, read from an RSS `<item>` or an Atom `<entry>`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FeedItem {
+    /// The GUID of the item, falling back to its link if the feed gives none.
+    pub guid: String,
+    pub title: String,
+    pub link: String,
+    /// When the item was published, as written in the feed.
+    pub published: String,
+    /// The HTML content or summary of the item.
+    pub content: String,
+}
+
+/// The registered feeds, persisted in the `.feeds.json` file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Feeds {
+    /// The feeds, in the order they were added.
+    pub feeds: Vec<Feed>,
+}
+
+impl Feeds {
+    /// Loads the feeds from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Feeds>` containing the feeds, which are empty if none were added yet.
+    pub fn load() -> io::Result<Feeds> {
+        let path = Self::get_feeds_file_path()?;
+        if !path.exists() {
+            return Ok(Feeds::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Saves the feeds to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&Self::get_feeds_file_path()?, data.as_bytes(), true)
+    }
+
+    /// Adds a feed, replacing the URL of an existing feed with the same name but keeping the
+    /// items it has already seen.
+    pub fn add(&mut self, name: &str, url: &str) {
+        match self.feeds.iter_mut().find(|feed| feed.name == name) {
+            Some(feed) => feed.url = url.to_string(),
+            None => self.feeds.push(Feed {
+                name: name.to_string(),
+                url: url.to_string(),
+                seen: Vec::new(),
+            }),
+        }
+    }
+
+    /// Removes the feed with the given name. The notes of its items are kept.
+    ///
+    /// # Returns
+    ///
+    /// Whether a feed was removed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.feeds.len();
+        self.feeds.retain(|feed| feed.name != name);
+        self.feeds.len() != len
+    }
+
+    fn get_feeds_file_path() -> io::Result<PathBuf> {
+        Ok(Notes::get_notes_dir()?.join(FEEDS_FILE))
+    }
+}
+
+/// Fetches feeds and saves their new items as notes in the folder of each feed.
+///
+/// The feeds are saved along with the GUIDs of the new items, so that fetching again only
+/// saves items published since.
+///
+/// # Arguments
+///
+/// * `name` - The name of the feed to fetch, or `None` to fetch all of them.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<String>>` containing the titles of the new notes, or a `NotFound` error if
+/// there is no feed with the given name.
+pub fn fetch(name: Option<&str>) -> io::Result<Vec<String>> {
+    let mut feeds = Feeds::load()?;
+    if let Some(name) = name {
+        if !feeds.feeds.iter().any(|feed| feed.name == name) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No feed named {}", name)));
+        }
+    }
+    let mut existing: HashSet<String> = Notes::list_notes()?.into_iter().collect();
+    let mut created = Vec::new();
+    for index in 0..feeds.feeds.len() {
+        let feed = &mut feeds.feeds[index];
+        if name.is_some_and(|name| name != feed.name) {
+            continue;
+        }
+        let url = clip::parse_url(&feed.url)?;
+        let response = ureq::get(url.as_str())
+            .call()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let items = parse(io::BufReader::new(response.into_reader()))?;
+        let folder = feed.folder();
+        for item in new_items(feed, &items) {
+            let title = unique_title(&mut existing, &format!("{}/{}", folder, clip_title(&item.title)));
+            Notes::create_note(&title, &render(item))?;
+            feed.seen.push(item.guid.clone());
+            created.push(title);
+        }
+        // Saved after each feed, so that a failing feed doesn't cause the items of the others to be saved twice
+        feeds.save()?;
+    }
+    Ok(created)
+}
+
+/// Returns the items of a feed that were not saved yet, oldest first.
+///
+/// Feeds list their newest items first, so saving in the opposite order keeps the notes in the
+/// order the items were published.
+pub fn new_items<'a>(feed: &Feed, items: &'a [FeedItem]) -> Vec<&'a FeedItem> {
+    let mut seen: HashSet<&str> = feed.seen.iter().map(String::as_str).collect();
+    items
+        .iter()
+        .rev()
+        .filter(|item| !item.guid.is_empty() && seen.insert(item.guid.as_str()))
+        .collect()
+}
+
+/// Renders the note of a feed item, with its link as the `source` and its content as Markdown.
+pub fn render(item: &FeedItem) -> String {
+    let mut front_matter = FrontMatter::default();
+    if !item.link.is_empty() {
+        front_matter.set("source", &item.link);
+    }
+    if !item.published.is_empty() {
+        front_matter.set("published", &item.published);
+    }
+    let heading = match item.title.trim() {
+        "" => "Untitled",
+        title => title,
+    };
+    let body = format!("# {}\n\n{}\n", heading, html2md::parse_html(&item.content).trim());
+    front_matter.render(&body)
+}
+
+/// Returns a title that is not used by an existing note, appending a counter if needed.
+fn unique_title(existing: &mut HashSet<String>, title: &str) -> String {
+    let mut candidate = title.to_string();
+    let mut counter = 1;
+    while existing.contains(&candidate) {
+        counter += 1;
+        candidate = format!("{} {}", title, counter);
+    }
+    existing.insert(candidate.clone());
+    candidate
+}
+
+/// Reads the items of an RSS 2.0 or Atom document.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<FeedItem>>` containing the items in the order they appear, or an
+/// `InvalidData` error if the document is not well-formed XML.
+pub fn parse(source: impl BufRead) -> io::Result<Vec<FeedItem>> {
+    let mut reader = Reader::from_reader(source);
+    let mut buf = Vec::new();
+    let mut items = Vec::new();
+    let mut item: Option<FeedItem> = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event_into(&mut buf).map_err(invalid_data)? {
+            Event::Start(start) => {
+                text.clear();
+                match (start.local_name().as_ref(), &mut item) {
+                    (b"item" | b"entry", _) => item = Some(FeedItem::default()),
+                    (b"link", Some(item)) => set_atom_link(item, &start),
+                    _ => {}
+                }
+            }
+            // Atom links are empty elements with the URL in an attribute
+            Event::Empty(empty) => {
+                if let (b"link", Some(item)) = (empty.local_name().as_ref(), &mut item) {
+                    set_atom_link(item, &empty);
+                }
+            }
+            Event::Text(chunk) => text.push_str(&chunk.unescape().map_err(invalid_data)?),
+            Event::CData(chunk) => text.push_str(&String::from_utf8_lossy(&chunk.into_inner())),
+            Event::End(end) => {
+                let value = std::mem::take(&mut text);
+                let value = value.trim();
+                match (end.local_name().as_ref(), &mut item) {
+                    (b"item" | b"entry", item) => {
+                        if let Some(mut item) = item.take() {
+                            if item.guid.is_empty() {
+                                item.guid = item.link.clone();
+                            }
+                            items.push(item);
+                        }
+                    }
+                    (b"title", Some(item)) => item.title = value.to_string(),
+                    (b"link", Some(item)) if !value.is_empty() => item.link = value.to_string(),
+                    (b"guid" | b"id", Some(item)) => item.guid = value.to_string(),
+                    (b"pubDate" | b"published", Some(item)) => item.published = value.to_string(),
+                    (b"updated", Some(item)) if item.published.is_empty() => item.published = value.to_string(),
+                    // The full content wins over a summary
+                    (b"encoded" | b"content", Some(item)) => item.content = value.to_string(),
+                    (b"description" | b"summary", Some(item)) if item.content.is_empty() => {
+                        item.content = value.to_string()
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(items)
+}
+
+/// Sets the link of an item from an Atom `<link href="...">`, preferring the `alternate` link.
+fn set_atom_link(item: &mut FeedItem, link: &BytesStart<'_>) {
+    let mut href = None;
+    let mut rel = None;
+    for attribute in link.attributes().flatten() {
+        let value = attribute.unescape_value().map(|value| value.to_string()).unwrap_or_default();
+        match attribute.key.as_ref() {
+            b"href" => href = Some(value),
+            b"rel" => rel = Some(value),
+            _ => {}
+        }
+    }
+    if let Some(href) = href {
+        if rel.as_deref().map_or(true, |rel| rel == "alternate") || item.link.is_empty() {
+            item.link = href;
+        }
+    }
+}
+
+fn invalid_data(err: quick_xml::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+  <channel>
+    <title>Blog</title>
+    <item>
+      <title>Second &amp; last</title>
+      <link>https://example.com/2</link>
+      <guid>post-2</guid>
+      <pubDate>Tue, 02 Apr 2024 09:00:00 GMT</pubDate>
+      <description>Short</description>
+      <content:encoded><![CDATA[<p>The <b>full</b> post</p>]]></content:encoded>
+    </item>
+    <item>
+      <title>First</title>
+      <link>https://example.com/1</link>
+      <description><![CDATA[<p>Hello</p>]]></description>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Log</title>
+  <entry>
+    <title>Entry</title>
+    <link rel="self" href="https://example.com/entry.atom"/>
+    <link href="https://example.com/entry"/>
+    <id>urn:uuid:1</id>
+    <updated>2024-04-01T10:00:00Z</updated>
+    <summary>Summary</summary>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn test_parse_rss() {
+        let items = parse(RSS.as_bytes()).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Second & last");
+        assert_eq!(items[0].guid, "post-2");
+        assert_eq!(items[0].content, "<p>The <b>full</b> post</p>");
+        assert_eq!(items[0].published, "Tue, 02 Apr 2024 09:00:00 GMT");
+        // Without a GUID the link identifies the item
+        assert_eq!(items[1].guid, "https://example.com/1");
+        assert_eq!(items[1].content, "<p>Hello</p>");
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let items = parse(ATOM.as_bytes()).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].guid, "urn:uuid:1");
+        assert_eq!(items[0].link, "https://example.com/entry");
+        assert_eq!(items[0].published, "2024-04-01T10:00:00Z");
+        assert_eq!(items[0].content, "Summary");
+    }
+
+    #[test]
+    fn test_new_items_and_render() {
+        let items = parse(RSS.as_bytes()).unwrap();
+        let mut feed = Feed {
+            name: "blog".to_string(),
+            url: "https://example.com/feed".to_string(),
+            seen: Vec::new(),
+        };
+        let new: Vec<&str> = new_items(&feed, &items).iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(new, vec!["First", "Second & last"]);
+        feed.seen.push("post-2".to_string());
+        assert_eq!(new_items(&feed, &items).len(), 1);
+        assert_eq!(feed.folder(), "feeds/blog");
+
+        let content = render(&items[0]);
+        let (front_matter, body) = FrontMatter::parse(&content);
+        assert_eq!(front_matter.get("source"), Some("https://example.com/2"));
+        assert!(body.starts_with("# Second & last\n"));
+        assert!(body.contains("**full**"));
+    }
+}
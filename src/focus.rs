@@ -0,0 +1,206 @@
+//! Focus sessions on todos, timed like a pomodoro, and the log of the time spent in them.
+//!
+//! The log is a plain file next to the `.todos` file with one session per line. The total
+//! time spent on each todo is kept with the todo itself.
+
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+use crate::todos::Todo;
+use crate::vault;
+
+/// The name of the file in the `~/.notes` directory that logs the focus sessions.
+pub const FOCUS_LOG_FILE: &str = ".focus.jsonl";
+
+/// A running focus session. It keeps going while the app is closed, as only its start is kept.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FocusTimer {
+    /// The id of the todo being worked on.
+    pub todo_id: u64,
+    /// The description of the todo, in case it is deleted during the session.
+    pub description: String,
+    /// When the session started, as a Unix timestamp.
+    pub started_at: i64,
+    /// How long the session lasts, in seconds.
+    pub length_secs: u64,
+}
+
+impl FocusTimer {
+    /// Starts a session on a todo.
+    ///
+    /// # Arguments
+    ///
+    /// * `todo` - The todo to work on.
+    /// * `now` - The current time as a Unix timestamp.
+    /// * `length_secs` - How long the session lasts, in seconds.
+    pub fn start(todo: &Todo, now: i64, length_secs: u64) -> FocusTimer {
+        FocusTimer {
+            todo_id: todo.id,
+            description: todo.description.clone(),
+            started_at: now,
+            length_secs,
+        }
+    }
+
+    /// Returns how many seconds of the session have passed, up to its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time as a Unix timestamp.
+    pub fn elapsed_secs(&self, now: i64) -> u64 {
+        u64::try_from(now - self.started_at).unwrap_or(0).min(self.length_secs)
+    }
+
+    /// Returns how many seconds of the session are left.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time as a Unix timestamp.
+    pub fn remaining_secs(&self, now: i64) -> u64 {
+        self.length_secs - self.elapsed_secs(now)
+    }
+
+    /// Whether the session is over.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time as a Unix timestamp.
+    pub fn is_finished(&self, now: i64) -> bool {
+        self.remaining_secs(now) == 0
+    }
+}
+
+/// A finished focus session, as kept in the log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FocusSession {
+    /// When the session started, as a Unix timestamp.
+    pub start: i64,
+    /// How many seconds were spent.
+    pub secs: u64,
+    /// The description of the todo.
+    pub todo: String,
+    /// The note the todo belongs to, if any.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// The project the todo belongs to, if any.
+    #[serde(default)]
+    pub project: Option<String>,
+}
+
+/// Appends a session to the log.
+///
+/// # Arguments
+///
+/// * `session` - The finished session.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn record(session: &FocusSession) -> io::Result<()> {
+    let path = log_path()?;
+    let mut data = match storage::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    data.push_str(&serde_json::to_string(session)?);
+    data.push('\n');
+    storage::backend().write(&path, data.as_bytes(), true)
+}
+
+/// Loads the log of focus sessions, oldest first.
+///
+/// Lines that cannot be read, e.g. one cut off by a crash, are skipped.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<FocusSession>>` containing the sessions, or none if there were none yet.
+pub fn load() -> io::Result<Vec<FocusSession>> {
+    match storage::read_to_string(&log_path()?) {
+        Ok(data) => Ok(data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+// Each vault logs the focus sessions on its own todos
+fn log_path() -> io::Result<PathBuf> {
+    Ok(vault::active_root()?.join(FOCUS_LOG_FILE))
+}
+
+/// Formats a duration for the todo list, e.g. `1h 05m`, or `45s` under a minute.
+///
+/// # Arguments
+///
+/// * `secs` - The duration in seconds.
+pub fn format_duration(secs: u64) -> String {
+    match (secs / 3600, secs % 3600 / 60) {
+        (0, 0) => format!("{}s", secs),
+        (0, mins) => format!("{}m", mins),
+        (hours, mins) => format!("{}h {:02}m", hours, mins),
+    }
+}
+
+/// Formats the time left of a session like a clock, e.g. `24:59`.
+///
+/// # Arguments
+///
+/// * `secs` - The time left in seconds.
+pub fn format_clock(secs: u64) -> String {
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+    use std::fs;
+
+    #[test]
+    fn test_timer() {
+        let todo = Todo {
+            description: "Write report".to_string(),
+            id: 3,
+            ..Todo::default()
+        };
+        let timer = FocusTimer::start(&todo, 1000, 1500);
+        assert_eq!(timer.todo_id, 3);
+        assert_eq!(timer.elapsed_secs(990), 0);
+        assert_eq!(timer.remaining_secs(1100), 1400);
+        assert!(!timer.is_finished(2499));
+        assert!(timer.is_finished(2500));
+        // The session ends on time even if the app was closed in the meantime
+        assert_eq!(timer.elapsed_secs(9000), 1500);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(25 * 60), "25m");
+        assert_eq!(format_duration(3900), "1h 05m");
+        assert_eq!(format_clock(1499), "24:59");
+    }
+
+    #[test]
+    fn test_log() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        assert!(load().unwrap().is_empty());
+        let session = FocusSession {
+            start: 1000,
+            secs: 1500,
+            todo: "Write report".to_string(),
+            note: None,
+            project: Some("work".to_string()),
+        };
+        record(&session).unwrap();
+        record(&session).unwrap();
+        // A line cut off by a crash is skipped
+        let path = temp_notes_dir.join(FOCUS_LOG_FILE);
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "{\"start\":").unwrap();
+        assert_eq!(load().unwrap(), vec![session.clone(), session]);
+    }
+}
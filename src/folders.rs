@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::front_matter::FrontMatter;
+use crate::history::HistoryRetention;
+use crate::notes::Notes;
+use crate::schema::Schema;
+use crate::templates;
+use crate::title;
+
+/// The name of the settings file inside a folder.
+pub const FOLDER_SETTINGS_FILE: &str = ".folder.json";
+
+/// Settings for a folder of notes, read from its `.folder.json` file.
+///
+/// ```json
+/// { "template": "meeting", "tags": ["meeting"], "autosave_secs": 10, "history": { "keep": 20 } }
+/// ```
+///
+/// Subfolders inherit the settings of their parents and can override each of them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct FolderSettings {
+    /// The name of a template in `~/.notes/.templates/` used as the content of new notes.
+    pub template: Option<String>,
+    /// Tags added to the front matter of new notes.
+    pub tags: Vec<String>,
+    /// Seconds to wait after the last edit before saving, overriding the global setting.
+    pub autosave_secs: Option<f64>,
+    /// How many earlier versions of notes to keep, overriding the global setting.
+    pub history: Option<HistoryRetention>,
+    /// The front matter fields notes in the folder must have, checked when they are saved.
+    pub schema: Option<Schema>,
+}
+
+impl FolderSettings {
+    /// Loads the settings of a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `folder` - The folder path relative to the `.notes` directory, or `""` for the root.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<FolderSettings>` containing the settings, or the defaults if the folder has no settings file.
+    pub fn load(folder: &str) -> io::Result<FolderSettings> {
+        let root = Notes::get_notes_dir()?;
+        let path = root.join(title::to_path(&root, folder, "")?).join(FOLDER_SETTINGS_FILE);
+        if !path.exists() {
+            return Ok(FolderSettings::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Resolves the settings that apply to a note.
+    ///
+    /// The settings of the root folder are applied first, followed by those of each folder
+    /// on the way down to the note's folder, so the innermost folder wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note, e.g. `meetings/standup`.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<FolderSettings>` containing the resolved settings.
+    pub fn for_note(title: &str) -> io::Result<FolderSettings> {
+        let mut settings = Self::load("")?;
+        let mut folder = String::new();
+        for component in folder_of(title).split('/').filter(|component| !component.is_empty()) {
+            if !folder.is_empty() {
+                folder.push('/');
+            }
+            folder.push_str(component);
+            settings = settings.overridden_by(Self::load(&folder)?);
+        }
+        Ok(settings)
+    }
+
+    /// Combines these settings with those of a subfolder, whose settings take precedence.
+    fn overridden_by(self, child: FolderSettings) -> FolderSettings {
+        FolderSettings {
+            template: child.template.or(self.template),
+            tags: if child.tags.is_empty() { self.tags } else { child.tags },
+            autosave_secs: child.autosave_secs.or(self.autosave_secs),
+            history: child.history.or(self.history),
+            schema: child.schema.or(self.schema),
+        }
+    }
+
+    /// Applies the folder's default template and tags to the content of a new note.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the new note, used to fill in the template.
+    /// * `content` - The content the note would otherwise be created with.
+    ///
+    /// # Returns
+    ///
+    /// The content of the new note. If the template cannot be read, the given content is used instead.
+    pub fn apply(&self, title: &str, content: &str) -> String {
+        let content = match &self.template {
+            Some(template) => match templates::read(template) {
+                Ok(text) => templates::render(&text, title, &HashMap::new()),
+                Err(err) => {
+                    log::warn!("Failed to read template {}: {}", template, err);
+                    content.to_string()
+                }
+            },
+            None => content.to_string(),
+        };
+        self.add_tags(content)
+    }
+
+    /// Adds the folder's default tags to the front matter of a new note.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the new note.
+    ///
+    /// # Returns
+    ///
+    /// The content with the tags added.
+    pub fn add_tags(&self, content: String) -> String {
+        if self.tags.is_empty() {
+            return content;
+        }
+        let (mut front_matter, body) = FrontMatter::parse(&content);
+        front_matter.add_tags(&self.tags);
+        front_matter.render(body)
+    }
+}
+
+/// Returns the folder part of a note title, or `""` for notes in the root folder.
+pub fn folder_of(title: &str) -> String {
+    Path::new(title)
+        .parent()
+        .map(|parent| parent.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_folder_of() {
+        assert_eq!(folder_of("note"), "");
+        assert_eq!(folder_of("meetings/standup"), "meetings");
+        assert_eq!(folder_of("work/meetings/standup"), "work/meetings");
+    }
+
+    #[test]
+    fn test_apply_template_and_tags() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        fs::create_dir_all(temp_notes_dir.join(".templates")).unwrap();
+        fs::write(temp_notes_dir.join(".templates").join("meeting.txt"), "## {{title}}\n").unwrap();
+        fs::create_dir_all(temp_notes_dir.join("meetings")).unwrap();
+        fs::write(
+            temp_notes_dir.join("meetings").join(FOLDER_SETTINGS_FILE),
+            r#"{ "template": "meeting", "tags": ["meeting"] }"#,
+        )
+        .unwrap();
+
+        let settings = FolderSettings::for_note("meetings/standup").unwrap();
+        assert_eq!(
+            settings.apply("meetings/standup", "ignored"),
+            "---\ntags: meeting\n---\n## meetings/standup\n"
+        );
+    }
+
+    #[test]
+    fn test_settings_are_inherited() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        fs::write(
+            temp_notes_dir.join(FOLDER_SETTINGS_FILE),
+            r#"{ "autosave_secs": 5, "history": { "keep": 10 } }"#,
+        )
+        .unwrap();
+        fs::create_dir_all(temp_notes_dir.join("journal").join("2024")).unwrap();
+        fs::write(
+            temp_notes_dir.join("journal").join(FOLDER_SETTINGS_FILE),
+            r#"{ "history": "forever", "tags": ["journal"], "schema": { "required": ["mood"] } }"#,
+        )
+        .unwrap();
+
+        let settings = FolderSettings::for_note("journal/2024/january").unwrap();
+        assert_eq!(settings.autosave_secs, Some(5.0));
+        assert_eq!(settings.history, Some(HistoryRetention::Forever));
+        assert_eq!(settings.tags, vec!["journal"]);
+        assert_eq!(settings.schema.unwrap().required, vec!["mood"]);
+        assert_eq!(FolderSettings::for_note("todo").unwrap().history, Some(HistoryRetention::Keep(10)));
+    }
+
+    #[test]
+    fn test_missing_settings_are_default() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        let settings = FolderSettings::for_note("elsewhere/note").unwrap();
+        assert_eq!(settings, FolderSettings::default());
+        assert_eq!(settings.apply("elsewhere/note", "content"), "content");
+    }
+}
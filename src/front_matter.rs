@@ -0,0 +1,184 @@
+/// Key-value metadata stored in a `---` delimited block at the top of a note.
+///
+/// ```text
+/// ---
+/// tags: meeting, work
+/// ---
+/// Note body...
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    /// The fields of the front matter, in the order they appear in the note.
+    pub fields: Vec<(String, String)>,
+}
+
+impl FrontMatter {
+    /// Splits a note into its front matter and body.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The full content of the note.
+    ///
+    /// # Returns
+    ///
+    /// The parsed front matter and the remaining body. Notes without a well-formed
+    /// front matter block yield empty front matter and the unchanged content.
+    pub fn parse(content: &str) -> (FrontMatter, &str) {
+        let Some(rest) = content
+            .strip_prefix("---\n")
+            .or_else(|| content.strip_prefix("---\r\n"))
+        else {
+            return (FrontMatter::default(), content);
+        };
+
+        let mut fields = Vec::new();
+        let mut offset = content.len() - rest.len();
+        for line in rest.split_inclusive('\n') {
+            offset += line.len();
+            let line = line.trim_end();
+            if line == "---" {
+                return (FrontMatter { fields }, &content[offset..]);
+            }
+            if line.is_empty() {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((key, value)) => fields.push((key.trim().to_string(), value.trim().to_string())),
+                None => return (FrontMatter::default(), content),
+            }
+        }
+        (FrontMatter::default(), content)
+    }
+
+    /// Renders the front matter followed by the given body.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The body of the note.
+    ///
+    /// # Returns
+    ///
+    /// The full content of the note. Empty front matter renders as the body alone.
+    pub fn render(&self, body: &str) -> String {
+        if self.fields.is_empty() {
+            return body.to_string();
+        }
+        let mut content = String::from("---\n");
+        for (key, value) in &self.fields {
+            content.push_str(&format!("{}: {}\n", key, value));
+        }
+        content.push_str("---\n");
+        content.push_str(body);
+        content
+    }
+
+    /// Returns the value of a field, if present.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the value of a field, replacing any existing value.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.fields.iter_mut().find(|(field, _)| field == key) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.fields.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Removes a field, if present.
+    pub fn remove(&mut self, key: &str) {
+        self.fields.retain(|(field, _)| field != key);
+    }
+
+    /// Returns whether the note is marked read-only with `locked: true`.
+    pub fn is_locked(&self) -> bool {
+        self.get("locked").is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    }
+
+    /// Returns whether the note is protected with a passphrase, so that the body is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.get("encrypted").is_some()
+    }
+
+    /// Marks the note read-only, or removes the mark.
+    pub fn set_locked(&mut self, locked: bool) {
+        if locked {
+            self.set("locked", "true");
+        } else {
+            self.remove("locked");
+        }
+    }
+
+    /// Returns the tags listed in the `tags` field.
+    ///
+    /// Both `tags: a, b` and `tags: [a, b]` are accepted, and a leading `#` is stripped.
+    pub fn tags(&self) -> Vec<String> {
+        let Some(value) = self.get("tags") else {
+            return Vec::new();
+        };
+        value
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches('#').to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /// Adds tags to the `tags` field, skipping any that are already present.
+    pub fn add_tags(&mut self, tags: &[String]) {
+        let mut all_tags = self.tags();
+        for tag in tags {
+            let tag = tag.trim_start_matches('#');
+            if !all_tags.iter().any(|existing| existing == tag) {
+                all_tags.push(tag.to_string());
+            }
+        }
+        if !all_tags.is_empty() {
+            self.set("tags", &all_tags.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_front_matter() {
+        let content = "---\ntags: a, b\nauthor: Me\n---\nBody text\n";
+        let (front_matter, body) = FrontMatter::parse(content);
+        assert_eq!(front_matter.get("author"), Some("Me"));
+        assert_eq!(front_matter.tags(), vec!["a", "b"]);
+        assert_eq!(body, "Body text\n");
+    }
+
+    #[test]
+    fn test_parse_without_front_matter() {
+        let content = "Just a note\n---\n";
+        let (front_matter, body) = FrontMatter::parse(content);
+        assert!(front_matter.fields.is_empty());
+        assert_eq!(body, content);
+    }
+
+    #[test]
+    fn test_locked() {
+        let (mut front_matter, body) = FrontMatter::parse("---\nlocked: True\n---\nBody");
+        assert!(front_matter.is_locked());
+        front_matter.set_locked(false);
+        assert!(!front_matter.is_locked());
+        assert_eq!(front_matter.render(body), "Body");
+        front_matter.set_locked(true);
+        assert_eq!(front_matter.render(body), "---\nlocked: true\n---\nBody");
+    }
+
+    #[test]
+    fn test_add_tags_and_render() {
+        let (mut front_matter, body) = FrontMatter::parse("---\ntags: [work]\n---\nBody");
+        front_matter.add_tags(&["#meeting".to_string(), "work".to_string()]);
+        assert_eq!(front_matter.render(body), "---\ntags: work, meeting\n---\nBody");
+    }
+}
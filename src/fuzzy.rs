@@ -0,0 +1,102 @@
+/// Scores how well a query matches a candidate, such as a note title.
+///
+/// Every character of the query must appear in the candidate in order, ignoring case.
+/// Matches score higher when characters are consecutive, start a word or start the
+/// candidate, and lower when the candidate is long.
+///
+/// # Arguments
+///
+/// * `query` - The text typed by the user.
+/// * `candidate` - The text to match against.
+///
+/// # Returns
+///
+/// The score, or `None` if the query does not match.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for query_char in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = (position..candidate.len()).find(|&index| chars_match(query_char, candidate[index]))?;
+        score += 1;
+        if found == 0 {
+            score += 8;
+        } else if is_word_start(&candidate, found) {
+            score += 6;
+        }
+        if previous_match.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if let Some(previous) = previous_match {
+            score -= (found - previous - 1).min(3) as i64;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    Some(score * 10 - candidate.len() as i64)
+}
+
+fn chars_match(query_char: char, candidate_char: char) -> bool {
+    query_char.to_lowercase().eq(candidate_char.to_lowercase())
+}
+
+fn is_word_start(candidate: &[char], index: usize) -> bool {
+    let previous = candidate[index - 1];
+    !previous.is_alphanumeric() || (previous.is_lowercase() && candidate[index].is_uppercase())
+}
+
+/// Ranks candidates by how well they match a query.
+///
+/// The best matches come first. Recently used candidates get a bonus, and with an empty
+/// query the recent candidates are listed first, most recent first, followed by the rest.
+///
+/// # Arguments
+///
+/// * `query` - The text typed by the user.
+/// * `candidates` - The texts to match against.
+/// * `recent` - Recently used candidates, most recent first.
+///
+/// # Returns
+///
+/// The matching candidates in order of rank.
+pub fn rank<'a>(query: &str, candidates: &'a [String], recent: &[String]) -> Vec<&'a str> {
+    let mut matches: Vec<(i64, &str)> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            let score = score(query, candidate)?;
+            let bonus = match recent.iter().position(|title| title == candidate) {
+                Some(position) => 100 - position.min(50) as i64,
+                None => 0,
+            };
+            Some((score + bonus, candidate.as_str()))
+        })
+        .collect();
+    matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then_with(|| a.cmp(b)));
+    matches.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("xyz", "meetings/standup"), None);
+        assert!(score("msu", "meetings/standup").is_some());
+        assert!(score("stand", "meetings/standup") > score("stand", "some tangled notes"));
+        assert!(score("Mee", "meetings") > score("mee", "summer meeting"));
+        assert_eq!(score("", "anything"), Some(-8));
+    }
+
+    #[test]
+    fn test_rank_prefers_recent() {
+        let titles = vec!["journal".to_string(), "jokes".to_string(), "recipes".to_string()];
+        assert_eq!(rank("jo", &titles, &[]), vec!["jokes", "journal"]);
+        assert_eq!(rank("jo", &titles, &["journal".to_string()]), vec!["journal", "jokes"]);
+        assert_eq!(
+            rank("", &titles, &["recipes".to_string()]),
+            vec!["recipes", "jokes", "journal"]
+        );
+    }
+}
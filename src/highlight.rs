@@ -0,0 +1,414 @@
+use std::ops::Range;
+
+use eframe::egui::{self, text::LayoutJob, Color32, FontId, Stroke, TextFormat, TextStyle};
+
+/// The languages with highlighting rules, as offered in the editor's code language dropdown.
+pub const CODE_LANGUAGES: &[&str] = &["rust", "python", "javascript", "shell", "json"];
+
+/// The kind of a span of text in a note, which determines its colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Regular note text outside code blocks.
+    Prose,
+    /// A ```` ``` ```` line opening or closing a code block.
+    Fence,
+    /// Code without a more specific kind.
+    Code,
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+/// The highlighting rules of a language.
+struct Syntax {
+    keywords: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    /// Whether `'` starts a string; in Rust it also starts lifetimes.
+    single_quote_strings: bool,
+}
+
+fn syntax_for(language: &str) -> Option<Syntax> {
+    let syntax = match language.to_lowercase().as_str() {
+        "rust" | "rs" => Syntax {
+            keywords: &[
+                "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "false",
+                "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+                "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+                "where", "while",
+            ],
+            line_comment: Some("//"),
+            single_quote_strings: false,
+        },
+        "python" | "py" => Syntax {
+            keywords: &[
+                "and", "as", "async", "await", "break", "class", "continue", "def", "elif", "else", "except",
+                "False", "finally", "for", "from", "if", "import", "in", "is", "lambda", "None", "not", "or",
+                "pass", "raise", "return", "True", "try", "while", "with", "yield",
+            ],
+            line_comment: Some("#"),
+            single_quote_strings: true,
+        },
+        "javascript" | "js" | "typescript" | "ts" => Syntax {
+            keywords: &[
+                "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "else",
+                "export", "false", "for", "from", "function", "if", "import", "let", "new", "null", "return",
+                "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "while",
+            ],
+            line_comment: Some("//"),
+            single_quote_strings: true,
+        },
+        "shell" | "sh" | "bash" | "zsh" => Syntax {
+            keywords: &[
+                "case", "do", "done", "echo", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+                "in", "local", "return", "then", "while",
+            ],
+            line_comment: Some("#"),
+            single_quote_strings: true,
+        },
+        "json" => Syntax {
+            keywords: &["false", "null", "true"],
+            line_comment: None,
+            single_quote_strings: false,
+        },
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// Splits a note into highlighted spans.
+///
+/// Fenced code blocks are highlighted according to the language after the opening fence,
+/// or `default_language` if the fence names none. Code in unknown languages is left plain.
+///
+/// # Arguments
+///
+/// * `text` - The content of the note.
+/// * `default_language` - The language of code blocks that don't name one.
+///
+/// # Returns
+///
+/// Spans covering the whole text, in order.
+pub fn tokenize(text: &str, default_language: &str) -> Vec<(Range<usize>, TokenKind)> {
+    let mut tokens = Vec::new();
+    // `Some(syntax)` inside a code block, where the syntax is `None` for unknown languages.
+    let mut code_block: Option<Option<Syntax>> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let range = offset..offset + line.len();
+        let trimmed = line.trim_start();
+        let fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        match (&code_block, fence) {
+            (None, true) => {
+                let language = trimmed[3..].split_whitespace().next().unwrap_or(default_language);
+                code_block = Some(syntax_for(language));
+                push(&mut tokens, range, TokenKind::Fence);
+            }
+            (Some(_), true) => {
+                code_block = None;
+                push(&mut tokens, range, TokenKind::Fence);
+            }
+            (Some(Some(syntax)), false) => tokenize_code(line, offset, syntax, &mut tokens),
+            (Some(None), false) => push(&mut tokens, range, TokenKind::Code),
+            (None, false) => push(&mut tokens, range, TokenKind::Prose),
+        }
+        offset += line.len();
+    }
+    tokens
+}
+
+/// Splits the code of a single block in the given language into highlighted spans.
+pub fn tokenize_block(code: &str, language: &str) -> Vec<(Range<usize>, TokenKind)> {
+    let mut tokens = Vec::new();
+    let Some(syntax) = syntax_for(language) else {
+        push(&mut tokens, 0..code.len(), TokenKind::Code);
+        return tokens;
+    };
+    let mut offset = 0;
+    for line in code.split_inclusive('\n') {
+        tokenize_code(line, offset, &syntax, &mut tokens);
+        offset += line.len();
+    }
+    tokens
+}
+
+fn tokenize_code(line: &str, offset: usize, syntax: &Syntax, tokens: &mut Vec<(Range<usize>, TokenKind)>) {
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let rest = &line[start..];
+        let (end, kind) = if syntax.line_comment.is_some_and(|comment| rest.starts_with(comment)) {
+            (line.trim_end_matches(['\n', '\r']).len().max(start + c.len_utf8()), TokenKind::Comment)
+        } else if c == '"' || (c == '\'' && syntax.single_quote_strings) {
+            let mut escaped = false;
+            let mut end = line.len();
+            for (index, next) in chars.by_ref() {
+                if next == '\n' {
+                    end = index;
+                    break;
+                }
+                if !escaped && next == c {
+                    end = index + 1;
+                    break;
+                }
+                escaped = !escaped && next == '\\';
+            }
+            (end, TokenKind::String)
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|next: char| !(next.is_ascii_alphanumeric() || next == '.' || next == '_'))
+                .map_or(line.len(), |length| start + length);
+            (end, TokenKind::Number)
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|next: char| !(next.is_alphanumeric() || next == '_'))
+                .map_or(line.len(), |length| start + length);
+            let kind = if syntax.keywords.contains(&&line[start..end]) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Code
+            };
+            (end, kind)
+        } else {
+            (start + c.len_utf8(), TokenKind::Code)
+        };
+        while chars.peek().is_some_and(|(next, _)| *next < end) {
+            chars.next();
+        }
+        push(tokens, offset + start..offset + end, kind);
+    }
+}
+
+/// Appends a span, merging it into the previous one if they are adjacent and of the same kind.
+fn push(tokens: &mut Vec<(Range<usize>, TokenKind)>, range: Range<usize>, kind: TokenKind) {
+    if range.is_empty() {
+        return;
+    }
+    match tokens.last_mut() {
+        Some((last, last_kind)) if *last_kind == kind && last.end == range.start => last.end = range.end,
+        _ => tokens.push((range, kind)),
+    }
+}
+
+/// The fonts and colours used to lay out a note, taken from the current egui style.
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct EditorTheme {
+    dark: bool,
+    text_color: Color32,
+    weak_color: Color32,
+    code_background: Color32,
+    body: FontId,
+    monospace: FontId,
+    // The bits of the line height in points, as `f32` cannot be hashed
+    line_height: Option<u32>,
+}
+
+impl EditorTheme {
+    /// Creates the theme matching an egui style, including whether it is dark or light.
+    pub fn from_style(style: &egui::Style) -> EditorTheme {
+        EditorTheme {
+            dark: style.visuals.dark_mode,
+            text_color: style
+                .visuals
+                .override_text_color
+                .unwrap_or_else(|| style.visuals.widgets.inactive.text_color()),
+            weak_color: style.visuals.weak_text_color(),
+            code_background: style.visuals.code_bg_color,
+            body: TextStyle::Body.resolve(style),
+            monospace: TextStyle::Monospace.resolve(style),
+            line_height: None,
+        }
+    }
+
+    /// Sets the font of prose and the distance between lines, e.g. from the user's theme.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The font of prose. Code keeps the monospace font.
+    /// * `line_spacing` - The distance between lines as a multiple of the font size.
+    pub fn with_font(mut self, body: FontId, line_spacing: f32) -> EditorTheme {
+        self.line_height = Some((body.size * line_spacing).to_bits());
+        self.body = body;
+        self
+    }
+
+    fn format(&self, kind: TokenKind) -> TextFormat {
+        let dark = self.dark;
+        let color = match kind {
+            TokenKind::Prose | TokenKind::Code => self.text_color,
+            TokenKind::Fence => self.weak_color,
+            TokenKind::Keyword if dark => Color32::from_rgb(255, 100, 100),
+            TokenKind::Keyword => Color32::from_rgb(235, 0, 0),
+            TokenKind::String if dark => Color32::from_rgb(109, 190, 115),
+            TokenKind::String => Color32::from_rgb(37, 130, 50),
+            TokenKind::Number if dark => Color32::from_rgb(210, 170, 90),
+            TokenKind::Number => Color32::from_rgb(160, 100, 0),
+            TokenKind::Comment if dark => Color32::from_gray(120),
+            TokenKind::Comment => Color32::from_gray(130),
+        };
+        let font = match kind {
+            TokenKind::Prose => self.body.clone(),
+            _ => self.monospace.clone(),
+        };
+        let mut format = TextFormat::simple(font, color);
+        format.line_height = self.line_height.map(f32::from_bits);
+        if kind != TokenKind::Prose {
+            format.background = self.code_background;
+        }
+        format
+    }
+}
+
+/// Lays out a note for the editor with its code blocks highlighted.
+///
+/// # Arguments
+///
+/// * `theme` - The fonts and colours to use.
+/// * `text` - The content of the note.
+/// * `default_language` - The language of code blocks that don't name one.
+pub fn layout_job(theme: &EditorTheme, text: &str, default_language: &str) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    for (range, kind) in tokenize(text, default_language) {
+        job.append(&text[range], 0.0, theme.format(kind));
+    }
+    job
+}
+
+/// Lays out the code of a single block, e.g. for the preview.
+pub fn code_layout_job(theme: &EditorTheme, code: &str, language: &str) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    for (range, kind) in tokenize_block(code, language) {
+        job.append(&code[range], 0.0, theme.format(kind));
+    }
+    job
+}
+
+/// Gives the text in the given byte ranges a coloured background, e.g. to mark search matches.
+///
+/// The ranges must be sorted and must not overlap.
+pub fn mark_ranges(job: &mut LayoutJob, ranges: &[Range<usize>], background: Color32) {
+    format_ranges(job, ranges, |format| format.background = background);
+}
+
+/// Underlines the text in the given byte ranges, e.g. to mark misspelled words.
+///
+/// The ranges must be sorted and must not overlap.
+pub fn underline_ranges(job: &mut LayoutJob, ranges: &[Range<usize>], stroke: Stroke) {
+    format_ranges(job, ranges, |format| format.underline = stroke);
+}
+
+// Splits the sections of a job at the edges of the ranges and changes the format of the pieces inside them
+fn format_ranges(job: &mut LayoutJob, ranges: &[Range<usize>], change: impl Fn(&mut TextFormat)) {
+    if ranges.is_empty() {
+        return;
+    }
+    let mut sections = Vec::with_capacity(job.sections.len() + ranges.len() * 2);
+    for section in job.sections.drain(..) {
+        let end = section.byte_range.end;
+        let mut start = section.byte_range.start;
+        let mut cuts: Vec<usize> = ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end])
+            .filter(|&cut| cut > start && cut < end)
+            .collect();
+        cuts.push(end);
+        for cut in cuts {
+            let mut piece = section.clone();
+            if start != section.byte_range.start {
+                piece.leading_space = 0.0;
+            }
+            if ranges.iter().any(|range| range.start <= start && cut <= range.end) {
+                change(&mut piece.format);
+            }
+            piece.byte_range = start..cut;
+            sections.push(piece);
+            start = cut;
+        }
+    }
+    job.sections = sections;
+}
+
+#[derive(Default)]
+struct Highlighter;
+
+impl egui::util::cache::ComputerMut<(&EditorTheme, &str, &str), LayoutJob> for Highlighter {
+    fn compute(&mut self, (theme, text, default_language): (&EditorTheme, &str, &str)) -> LayoutJob {
+        layout_job(theme, text, default_language)
+    }
+}
+
+/// Like `layout_job`, but reuses the layout of the previous frame if nothing changed.
+pub fn cached_layout_job(ctx: &egui::Context, theme: &EditorTheme, text: &str, default_language: &str) -> LayoutJob {
+    type HighlightCache = egui::util::cache::FrameCache<LayoutJob, Highlighter>;
+    ctx.memory_mut(|memory| memory.caches.cache::<HighlightCache>().get((theme, text, default_language)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_ranges() {
+        let theme = EditorTheme::from_style(&egui::Style::default());
+        let mut job = layout_job(&theme, "find the needle here", "");
+        mark_ranges(&mut job, &[9..15], Color32::YELLOW);
+        let marked: Vec<(&str, bool)> = job
+            .sections
+            .iter()
+            .map(|section| (&job.text[section.byte_range.clone()], section.format.background == Color32::YELLOW))
+            .collect();
+        assert_eq!(marked, vec![("find the ", false), ("needle", true), (" here", false)]);
+    }
+
+    #[test]
+    fn test_underline_ranges() {
+        let theme = EditorTheme::from_style(&egui::Style::default());
+        let mut job = layout_job(&theme, "a speling mistake", "");
+        let stroke = Stroke::new(1.0, Color32::RED);
+        underline_ranges(&mut job, &[2..9], stroke);
+        let underlined: Vec<(&str, bool)> = job
+            .sections
+            .iter()
+            .map(|section| (&job.text[section.byte_range.clone()], section.format.underline == stroke))
+            .collect();
+        assert_eq!(underlined, vec![("a ", false), ("speling", true), (" mistake", false)]);
+    }
+
+    fn kinds<'a>(text: &'a str, tokens: &[(Range<usize>, TokenKind)]) -> Vec<(&'a str, TokenKind)> {
+        tokens.iter().map(|(range, kind)| (&text[range.clone()], *kind)).collect()
+    }
+
+    #[test]
+    fn test_tokenize_code_block() {
+        let text = "Intro\n```rust\nlet s = \"hi\"; // note\n```\nOutro";
+        assert_eq!(
+            kinds(text, &tokenize(text, "")),
+            vec![
+                ("Intro\n", TokenKind::Prose),
+                ("```rust\n", TokenKind::Fence),
+                ("let", TokenKind::Keyword),
+                (" s = ", TokenKind::Code),
+                ("\"hi\"", TokenKind::String),
+                ("; ", TokenKind::Code),
+                ("// note", TokenKind::Comment),
+                ("\n", TokenKind::Code),
+                ("```\n", TokenKind::Fence),
+                ("Outro", TokenKind::Prose),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_and_unknown_languages() {
+        let text = "```\nx = 1 # one\n```";
+        let tokens = tokenize(text, "python");
+        assert!(kinds(text, &tokens).contains(&("# one", TokenKind::Comment)));
+        assert!(kinds(text, &tokens).contains(&("1", TokenKind::Number)));
+
+        let code = "def f(): pass";
+        assert_eq!(tokenize_block(code, "python")[0], (0..3, TokenKind::Keyword));
+        assert_eq!(tokenize_block(code, "cobol"), vec![(0..code.len(), TokenKind::Code)]);
+
+        let text = "```cobol\nMOVE 1 TO X\n```";
+        assert_eq!(kinds(text, &tokenize(text, "python"))[1], ("MOVE 1 TO X\n", TokenKind::Code));
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+use crate::title;
+
+/// The directory in `.notes` holding earlier versions of notes, one folder per note.
+pub const HISTORY_DIR: &str = ".history";
+
+/// Saves within this many seconds of the latest version replace it instead of adding a new one,
+/// so that autosaving while typing does not flood the history.
+pub const MIN_VERSION_INTERVAL_SECS: u64 = 5 * 60;
+
+/// How many earlier versions of a note to keep.
+///
+/// In `.folder.json` and `.config.json` this is written as `"forever"`, `"none"`,
+/// `{ "keep": 20 }` or `{ "days": 30 }`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryRetention {
+    /// Every version is kept.
+    Forever,
+    /// No versions are kept.
+    None,
+    /// The given number of most recent versions are kept.
+    Keep(usize),
+    /// Versions younger than the given number of days are kept.
+    Days(u64),
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        HistoryRetention::Keep(50)
+    }
+}
+
+impl HistoryRetention {
+    /// Returns whether a version saved at `saved` is kept, given its position among the
+    /// versions of the note, newest first.
+    fn keeps(&self, index: usize, saved: u64, now: u64) -> bool {
+        match *self {
+            HistoryRetention::Forever => true,
+            HistoryRetention::None => false,
+            HistoryRetention::Keep(count) => index < count,
+            HistoryRetention::Days(days) => now.saturating_sub(saved) < days * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Records a version of a note and prunes the versions the retention no longer keeps.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `content` - The content that was saved.
+/// * `retention` - How many versions of the note to keep.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn record(title: &str, content: &str, retention: HistoryRetention) -> io::Result<()> {
+    let now = unix_now();
+    let dir = history_dir(title)?;
+    if retention != HistoryRetention::None {
+        fs::create_dir_all(&dir)?;
+        if let Some(&latest) = versions(title)?.first() {
+            if now.saturating_sub(latest) < MIN_VERSION_INTERVAL_SECS {
+                fs::remove_file(dir.join(format!("{}.txt", latest)))?;
+            }
+        }
+        atomic_write(&dir.join(format!("{}.txt", now)), content.as_bytes(), false)?;
+    }
+    prune(title, retention, now)
+}
+
+/// Lists the versions of a note, newest first.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<u64>>` containing the UNIX timestamps at which the versions were saved.
+pub fn versions(title: &str) -> io::Result<Vec<u64>> {
+    let dir = history_dir(title)?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut versions = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "txt") {
+            if let Some(saved) = path.file_stem().and_then(|stem| stem.to_str()?.parse().ok()) {
+                versions.push(saved);
+            }
+        }
+    }
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(versions)
+}
+
+/// Deletes the versions of a note that the retention no longer keeps.
+fn prune(title: &str, retention: HistoryRetention, now: u64) -> io::Result<()> {
+    let dir = history_dir(title)?;
+    for (index, saved) in versions(title)?.into_iter().enumerate() {
+        if !retention.keeps(index, saved, now) {
+            fs::remove_file(dir.join(format!("{}.txt", saved)))?;
+        }
+    }
+    Ok(())
+}
+
+fn history_dir(title: &str) -> io::Result<PathBuf> {
+    let root = Notes::get_notes_dir()?;
+    let path = title::to_path(&root, title, "")?;
+    Ok(root.join(HISTORY_DIR).join(path))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_record_replaces_recent_version() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        record("journal/today", "first", HistoryRetention::Forever).unwrap();
+        record("journal/today", "second", HistoryRetention::Forever).unwrap();
+        let versions = versions("journal/today").unwrap();
+        assert_eq!(versions.len(), 1);
+        let path = history_dir("journal/today").unwrap().join(format!("{}.txt", versions[0]));
+        assert_eq!(fs::read_to_string(path).unwrap(), "second");
+    }
+
+    #[test]
+    fn test_retention_prunes_versions() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        let dir = history_dir("scratch/idea").unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        for saved in [100, 200, 300] {
+            fs::write(dir.join(format!("{}.txt", saved)), "old").unwrap();
+        }
+        prune("scratch/idea", HistoryRetention::Keep(2), 400).unwrap();
+        assert_eq!(versions("scratch/idea").unwrap(), vec![300, 200]);
+
+        record("scratch/idea", "new", HistoryRetention::None).unwrap();
+        assert!(versions("scratch/idea").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retention_by_age() {
+        let day = 24 * 60 * 60;
+        let retention = HistoryRetention::Days(7);
+        assert!(retention.keeps(100, 10 * day, 12 * day));
+        assert!(!retention.keeps(0, 2 * day, 12 * day));
+    }
+
+    #[test]
+    fn test_parse_retention() {
+        let parse = |json: &str| serde_json::from_str::<HistoryRetention>(json).unwrap();
+        assert_eq!(parse(r#""forever""#), HistoryRetention::Forever);
+        assert_eq!(parse(r#""none""#), HistoryRetention::None);
+        assert_eq!(parse(r#"{ "keep": 20 }"#), HistoryRetention::Keep(20));
+        assert_eq!(parse(r#"{ "days": 30 }"#), HistoryRetention::Days(30));
+    }
+}
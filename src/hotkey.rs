@@ -0,0 +1,99 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+/// The shortcut that opens the quick capture window unless another one is configured.
+pub const DEFAULT_HOTKEY: &str = "Ctrl+Shift+Space";
+
+/// A shortcut registered with the OS, which is noticed even while other applications have the
+/// focus. It is unregistered when dropped.
+pub struct HotkeyListener {
+    manager: GlobalHotKeyManager,
+    hotkey: HotKey,
+    pressed: Arc<AtomicBool>,
+}
+
+/// Reads a shortcut such as `Ctrl+Shift+Space` or `Alt+N`.
+///
+/// # Arguments
+///
+/// * `shortcut` - Modifiers and a key, separated by `+`.
+///
+/// # Returns
+///
+/// An `io::Result<HotKey>` containing the shortcut, or an `InvalidInput` error if it is not valid.
+pub fn parse(shortcut: &str) -> io::Result<HotKey> {
+    shortcut
+        .trim()
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid shortcut {}: {}", shortcut, err)))
+}
+
+impl HotkeyListener {
+    /// Registers a shortcut with the OS.
+    ///
+    /// # Arguments
+    ///
+    /// * `shortcut` - The shortcut, e.g. `Ctrl+Shift+Space`.
+    /// * `wake` - Called each time the shortcut is pressed, e.g. to repaint the window so it
+    ///   notices.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<HotkeyListener>` containing the listener, or an error if the shortcut is
+    /// not valid, is taken by another application, or the desktop does not support global
+    /// shortcuts (e.g. Wayland).
+    pub fn register(shortcut: &str, wake: impl Fn() + Send + Sync + 'static) -> io::Result<HotkeyListener> {
+        let hotkey = parse(shortcut)?;
+        let manager = GlobalHotKeyManager::new().map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+        manager
+            .register(hotkey)
+            .map_err(|err| io::Error::new(io::ErrorKind::AddrInUse, err))?;
+        let pressed = Arc::new(AtomicBool::new(false));
+        let flag = pressed.clone();
+        let id = hotkey.id();
+        GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+            if event.id == id && event.state == HotKeyState::Pressed {
+                flag.store(true, Ordering::SeqCst);
+                wake();
+            }
+        }));
+        Ok(HotkeyListener {
+            manager,
+            hotkey,
+            pressed,
+        })
+    }
+
+    /// Whether the shortcut was pressed since the last call.
+    pub fn take_pressed(&self) -> bool {
+        self.pressed.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Drop for HotkeyListener {
+    fn drop(&mut self) {
+        GlobalHotKeyEvent::set_event_handler(None::<fn(GlobalHotKeyEvent)>);
+        if let Err(err) = self.manager.unregister(self.hotkey) {
+            log::warn!("Failed to unregister the shortcut: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(DEFAULT_HOTKEY).unwrap(), parse("ctrl+shift+space").unwrap());
+        assert!(parse("Alt+N").is_ok());
+        assert_eq!(
+            parse("Ctrl+Nothing").err().map(|err| err.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! An iCalendar (`.ics`) feed of the todos with due dates, so that calendar apps can show them.
+
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::calendar;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+use crate::todos::{DeadlineKind, Todos};
+
+/// The longest a line of an iCalendar file may be, in bytes, before it is folded.
+const MAX_LINE_BYTES: usize = 75;
+
+/// Renders the open todos with a due date as all-day events on the day they are due.
+///
+/// # Arguments
+///
+/// * `todos` - The todos.
+/// * `titles` - The titles of the notes whose daily notes are added as all-day events too, or
+///   none to leave them out.
+/// * `now` - The current time, which is when the events are stamped as generated.
+///
+/// # Returns
+///
+/// The calendar, with the CRLF line endings iCalendar requires.
+pub fn calendar(todos: &Todos, titles: &[String], now: DateTime<Utc>) -> String {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//maccam912//notes//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Notes".to_string(),
+    ];
+    for todo in todos.items.iter().filter(|todo| !todo.done) {
+        let Some(day) = todo.due_day() else {
+            continue;
+        };
+        let mut event = vec![format!("SUMMARY:{}", escape(&todo.description))];
+        let mut details = Vec::new();
+        if todo.deadline == DeadlineKind::Target {
+            details.push(format!("{} date", DeadlineKind::Target.label()));
+        }
+        if let Some(note) = &todo.note {
+            details.push(format!("Note: {}", note));
+        }
+        if !details.is_empty() {
+            event.push(format!("DESCRIPTION:{}", escape(&details.join("\n"))));
+        }
+        if let Some(project) = &todo.project {
+            event.push(format!("CATEGORIES:{}", escape(project)));
+        }
+        push_event(&mut lines, &format!("todo-{}", todo.id), day, &stamp, event);
+    }
+    for (title, day) in titles.iter().filter_map(|title| Some((title, calendar::daily_date(title)?))) {
+        let event = vec![format!("SUMMARY:{}", escape(title))];
+        push_event(&mut lines, &format!("daily-{}", day.format("%Y%m%d")), day, &stamp, event);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+/// Exports the todos with a due date, and optionally the daily notes, to an `.ics` file.
+///
+/// # Arguments
+///
+/// * `path` - The file to write.
+/// * `daily_notes` - Whether the daily notes are added as all-day events.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn export(path: &Path, daily_notes: bool) -> io::Result<()> {
+    atomic_write(path, feed(daily_notes)?.as_bytes(), true)
+}
+
+/// Renders the calendar of the saved todos, and optionally of the daily notes.
+///
+/// # Arguments
+///
+/// * `daily_notes` - Whether the daily notes are added as all-day events.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the calendar.
+pub fn feed(daily_notes: bool) -> io::Result<String> {
+    let (todos, _) = Todos::load_or_recover()?;
+    let titles = if daily_notes { Notes::list_notes()? } else { Vec::new() };
+    Ok(calendar(&todos, &titles, Utc::now()))
+}
+
+fn push_event(lines: &mut Vec<String>, uid: &str, day: NaiveDate, stamp: &str, fields: Vec<String>) {
+    lines.push("BEGIN:VEVENT".to_string());
+    lines.push(format!("UID:{}@notes", uid));
+    lines.push(format!("DTSTAMP:{}", stamp));
+    lines.push(format!("DTSTART;VALUE=DATE:{}", day.format("%Y%m%d")));
+    lines.push(format!("DTEND;VALUE=DATE:{}", (day + Duration::days(1)).format("%Y%m%d")));
+    lines.extend(fields);
+    lines.push("END:VEVENT".to_string());
+}
+
+// Text values escape the characters that separate values
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Long lines continue on the next line after a space, without splitting a character
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        // Continuation lines start with a space, which counts towards their length
+        if length + c.len_utf8() > MAX_LINE_BYTES {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todos;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_calendar() {
+        let mut todos = Todos::new();
+        todos.add("Taxes, finally; really".to_string(), todos::parse_due_date("2024-03-20"));
+        todos.add("Someday".to_string(), None);
+        todos.add_to_note("Draft agenda".to_string(), "meetings/standup".to_string());
+        todos.items[2].due_date = todos::parse_due_date("2024-03-21");
+        todos.items[2].deadline = DeadlineKind::Target;
+        todos.add("Done".to_string(), todos::parse_due_date("2024-03-22"));
+        todos.items[3].done = true;
+        let titles = ["daily/2024-03-14", "Ideas"].map(String::from);
+        let now = Utc.with_ymd_and_hms(2024, 3, 14, 9, 30, 0).unwrap();
+
+        let ics = calendar(&todos, &titles, now);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+        assert!(ics.contains("UID:todo-1@notes\r\nDTSTAMP:20240314T093000Z\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240320\r\nDTEND;VALUE=DATE:20240321\r\n"));
+        assert!(ics.contains("SUMMARY:Taxes\\, finally\\; really\r\n"));
+        assert!(ics.contains("DESCRIPTION:Target date\\nNote: meetings/standup\r\n"));
+        assert!(ics.contains("UID:daily-20240314@notes"));
+        assert!(!ics.contains("Someday"));
+        assert!(!ics.contains("SUMMARY:Done"));
+    }
+
+    #[test]
+    fn test_fold() {
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold(&line);
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_BYTES));
+        assert_eq!(lines.concat().replacen(' ', "", 1), line);
+        assert_eq!(fold("VERSION:2.0"), "VERSION:2.0");
+    }
+}
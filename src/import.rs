@@ -0,0 +1,696 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::NaiveDateTime;
+use md5::{Digest, Md5};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use regex::{Captures, Regex};
+use zip::ZipArchive;
+
+use crate::attachments;
+use crate::clip::clip_title;
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+
+/// What an import created.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// The titles of the new notes.
+    pub notes: Vec<String>,
+    /// How many attachments were stored.
+    pub attachments: usize,
+    /// The items that were not imported, each with the reason.
+    pub skipped: Vec<String>,
+}
+
+/// A note read from an Evernote export.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct EnexNote {
+    title: String,
+    /// The ENML body of the note, an XHTML document.
+    content: String,
+    /// When the note was created, e.g. `20240315T120000Z`.
+    created: String,
+    tags: Vec<String>,
+    resources: Vec<EnexResource>,
+}
+
+/// A file attached to a note in an Evernote export.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct EnexResource {
+    /// The base64 encoded content.
+    data: String,
+    mime: String,
+    file_name: String,
+}
+
+/// Imports the notes of an Evernote export (`.enex` file).
+///
+/// Evernote exports one notebook per file, so the notes are created in a folder named after the
+/// file. The ENML of each note is converted to Markdown, attached files are stored in the
+/// attachments folder and linked where they appeared, and Evernote tags become the `tags` of
+/// the note.
+///
+/// # Arguments
+///
+/// * `path` - The `.enex` file.
+///
+/// # Returns
+///
+/// An `io::Result<ImportReport>` containing the new notes and how many attachments were stored.
+pub fn enex(path: &Path) -> io::Result<ImportReport> {
+    let notes = parse_enex(BufReader::new(File::open(path)?))?;
+    let notebook = path
+        .file_stem()
+        .map(|stem| clip_title(&stem.to_string_lossy()))
+        .unwrap_or_else(|| "Evernote".to_string());
+    let mut existing: HashSet<String> = Notes::list_notes()?.into_iter().collect();
+    let mut report = ImportReport::default();
+    for note in notes {
+        // Resources are referred to by the MD5 hash of their content
+        let mut media = HashMap::new();
+        for resource in &note.resources {
+            let cleaned: String = resource.data.split_whitespace().collect();
+            let data = match STANDARD.decode(cleaned) {
+                Ok(data) => data,
+                Err(err) => {
+                    report.skipped.push(format!("an attachment of {} ({})", note.title, err));
+                    continue;
+                }
+            };
+            let name = match resource.file_name.trim() {
+                "" => format!("attachment.{}", mime_extension(&resource.mime)),
+                name => name.to_string(),
+            };
+            let relative_path = attachments::store(&name, &data)?;
+            report.attachments += 1;
+            let hash: String = Md5::digest(&data).iter().map(|byte| format!("{:02x}", byte)).collect();
+            media.insert(hash, attachments::markdown_link(&name, &relative_path));
+        }
+
+        let mut front_matter = FrontMatter::default();
+        if !note.tags.is_empty() {
+            front_matter.set("tags", &note.tags.join(", "));
+        }
+        if let Ok(created) = NaiveDateTime::parse_from_str(note.created.trim(), "%Y%m%dT%H%M%SZ") {
+            front_matter.set("created", &created.format("%Y-%m-%d").to_string());
+        }
+        let heading = match note.title.trim() {
+            "" => "Untitled",
+            title => title,
+        };
+        let body = format!("# {}\n\n{}\n", heading, enml_to_markdown(&note.content, &media).trim());
+
+        let title = unique_title(&mut existing, &format!("{}/{}", notebook, clip_title(heading)));
+        Notes::create_note(&title, &front_matter.render(&body))?;
+        report.notes.push(title);
+    }
+    Ok(report)
+}
+
+/// An item of a Joplin export: a note, notebook, attachment, tag or the tag of a note.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct JoplinItem {
+    title: String,
+    body: String,
+    /// The metadata listed after the body, e.g. `id`, `parent_id` and `type_`.
+    fields: HashMap<String, String>,
+}
+
+impl JoplinItem {
+    fn field(&self, name: &str) -> &str {
+        self.fields.get(name).map_or("", String::as_str)
+    }
+}
+
+// The `type_` of Joplin items
+const JOPLIN_NOTE: &str = "1";
+const JOPLIN_FOLDER: &str = "2";
+const JOPLIN_RESOURCE: &str = "4";
+const JOPLIN_TAG: &str = "5";
+const JOPLIN_NOTE_TAG: &str = "6";
+
+/// Imports the notes of a Joplin export (`.jex` file).
+///
+/// Notebooks become folders, attachments are stored in the attachments folder, and links
+/// between notes (`:/id`) become wiki links.
+///
+/// # Arguments
+///
+/// * `path` - The `.jex` file, a tar archive.
+///
+/// # Returns
+///
+/// An `io::Result<ImportReport>` containing the new notes, how many attachments were stored and what was skipped.
+pub fn jex(path: &Path) -> io::Result<ImportReport> {
+    let mut items = Vec::new();
+    let mut files = HashMap::new();
+    let mut archive = tar::Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().replace('\\', "/");
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        match name.strip_prefix("resources/") {
+            Some(file) => {
+                let id = file.split('.').next().unwrap_or(file).to_string();
+                files.insert(id, data);
+            }
+            None if name.ends_with(".md") => items.push(parse_joplin_item(&String::from_utf8_lossy(&data))),
+            None => {}
+        }
+    }
+
+    let mut report = ImportReport::default();
+    let by_id: HashMap<&str, &JoplinItem> = items.iter().map(|item| (item.field("id"), item)).collect();
+    let mut tags: HashMap<&str, Vec<&str>> = HashMap::new();
+    for item in items.iter().filter(|item| item.field("type_") == JOPLIN_NOTE_TAG) {
+        if let Some(tag) = by_id.get(item.field("tag_id")) {
+            tags.entry(item.field("note_id")).or_default().push(&tag.title);
+        }
+    }
+    let mut resources = HashMap::new();
+    for item in items.iter().filter(|item| item.field("type_") == JOPLIN_RESOURCE) {
+        let id = item.field("id");
+        let Some(data) = files.get(id) else {
+            report.skipped.push(format!("attachment {} (its file is missing)", item.title));
+            continue;
+        };
+        let name = match item.title.trim() {
+            "" => format!("{}.{}", id, item.field("file_extension")),
+            title => title.to_string(),
+        };
+        let path = attachments::store(&name, data)?;
+        resources.insert(id, (name, path));
+        report.attachments += 1;
+    }
+    for item in &items {
+        match item.field("type_") {
+            JOPLIN_NOTE | JOPLIN_FOLDER | JOPLIN_RESOURCE | JOPLIN_TAG | JOPLIN_NOTE_TAG => {}
+            kind => report.skipped.push(format!("{} (items of type {} are not supported)", item.title, kind)),
+        }
+    }
+
+    // Titles are picked first, so that links to notes later in the export can be resolved
+    let mut existing: HashSet<String> = Notes::list_notes()?.into_iter().collect();
+    let notes: Vec<&JoplinItem> = items.iter().filter(|item| item.field("type_") == JOPLIN_NOTE).collect();
+    let mut titles = HashMap::new();
+    for note in &notes {
+        let mut parts = Vec::new();
+        let mut parent = by_id.get(note.field("parent_id"));
+        // Guard against notebooks that are their own ancestors
+        while let Some(folder) = parent.filter(|_| parts.len() < 32) {
+            parts.push(clip_title(&folder.title));
+            parent = by_id.get(folder.field("parent_id"));
+        }
+        parts.reverse();
+        parts.push(clip_title(if note.title.trim().is_empty() { "Untitled" } else { &note.title }));
+        titles.insert(note.field("id"), unique_title(&mut existing, &parts.join("/")));
+    }
+
+    let link = Regex::new(r"(!?)\[([^\]]*)\]\(:/([0-9a-fA-F]{32})\)").unwrap();
+    for note in notes {
+        let body = link.replace_all(&note.body, |captures: &Captures<'_>| {
+            let (bang, label, id) = (&captures[1], &captures[2], &captures[3]);
+            if let Some(title) = titles.get(id) {
+                wiki_link(title, label)
+            } else if let Some((name, path)) = resources.get(id) {
+                format!("{}[{}]({})", bang, if label.is_empty() { name.as_str() } else { label }, path)
+            } else {
+                captures[0].to_string()
+            }
+        });
+        let mut front_matter = FrontMatter::default();
+        if let Some(tags) = tags.get(note.field("id")) {
+            front_matter.set("tags", &tags.join(", "));
+        }
+        if let Some(created) = note.field("created_time").get(..10) {
+            front_matter.set("created", created);
+        }
+        let title = &titles[note.field("id")];
+        let heading = if note.title.trim().is_empty() { "Untitled" } else { note.title.trim() };
+        let content = format!("# {}\n\n{}\n", heading, body.trim());
+        Notes::create_note(title, &front_matter.render(&content))?;
+        report.notes.push(title.clone());
+    }
+    Ok(report)
+}
+
+/// Parses an item of a Joplin export: its title, a blank line, its body, a blank line and its metadata.
+fn parse_joplin_item(content: &str) -> JoplinItem {
+    let content = content.trim_end();
+    let (text, metadata) = content.rsplit_once("\n\n").unwrap_or(("", content));
+    let fields = metadata
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let (title, body) = text.split_once('\n').unwrap_or((text, ""));
+    JoplinItem {
+        title: title.trim().to_string(),
+        body: body.trim_start_matches('\n').to_string(),
+        fields,
+    }
+}
+
+/// Imports the pages of a Notion export (a zip of Markdown and CSV files).
+///
+/// Subpages become notes in a folder named after their parent page, files the pages link to
+/// are stored in the attachments folder, and links between pages become wiki links. Databases
+/// exported as CSV are skipped.
+///
+/// # Arguments
+///
+/// * `path` - The zip file.
+///
+/// # Returns
+///
+/// An `io::Result<ImportReport>` containing the new notes, how many attachments were stored and what was skipped.
+pub fn notion(path: &Path) -> io::Result<ImportReport> {
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(invalid_zip)?;
+    let mut pages = Vec::new();
+    let mut files = HashMap::new();
+    let mut report = ImportReport::default();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(invalid_zip)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let Some(name) = entry.enclosed_name() else {
+            report.skipped.push(format!("{} (outside the export)", entry.name()));
+            continue;
+        };
+        let name = name.to_string_lossy().replace('\\', "/");
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if name.ends_with(".md") {
+            pages.push((name, String::from_utf8_lossy(&data).into_owned()));
+        } else if name.ends_with(".csv") {
+            report.skipped.push(format!("{} (databases are not supported)", name));
+        } else {
+            files.insert(name, data);
+        }
+    }
+
+    let mut existing: HashSet<String> = Notes::list_notes()?.into_iter().collect();
+    let mut titles = HashMap::new();
+    for (name, _) in &pages {
+        let parts: Vec<String> = name
+            .trim_end_matches(".md")
+            .split('/')
+            .map(|part| clip_title(&strip_notion_id(part)))
+            .collect();
+        titles.insert(name.as_str(), unique_title(&mut existing, &parts.join("/")));
+    }
+
+    let link = Regex::new(r"(!?)\[([^\]]*)\]\(([^)\s]+)\)").unwrap();
+    let mut stored: HashMap<String, String> = HashMap::new();
+    for (name, content) in &pages {
+        let dir = name.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let mut result = Ok(());
+        let body = link.replace_all(content, |captures: &Captures<'_>| {
+            let (bang, label, target) = (&captures[1], &captures[2], &captures[3]);
+            if target.contains("://") {
+                return captures[0].to_string();
+            }
+            let target = join_relative(dir, &percent_decode(target));
+            if let Some(title) = titles.get(target.as_str()) {
+                return wiki_link(title, label);
+            }
+            let Some(data) = files.get(&target) else {
+                return captures[0].to_string();
+            };
+            let file_name = target.rsplit('/').next().unwrap_or(&target);
+            let path = match stored.get(&target) {
+                Some(path) => path.clone(),
+                None => match attachments::store(file_name, data) {
+                    Ok(path) => {
+                        stored.insert(target.clone(), path.clone());
+                        path
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        return captures[0].to_string();
+                    }
+                },
+            };
+            format!("{}[{}]({})", bang, label, path)
+        });
+        result?;
+        let title = &titles[name.as_str()];
+        Notes::create_note(title, &body)?;
+        report.notes.push(title.clone());
+    }
+    report.attachments = stored.len();
+    for name in files.keys().filter(|name| !stored.contains_key(*name)) {
+        report.skipped.push(format!("{} (not linked from any page)", name));
+    }
+    report.skipped.sort();
+    Ok(report)
+}
+
+/// Removes the id Notion appends to the names of exported pages, e.g. `Roadmap 1a2b…`.
+fn strip_notion_id(name: &str) -> String {
+    match name.rsplit_once(' ') {
+        Some((title, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => title.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Resolves a relative link within an archive, e.g. `../images/a.png` from `pages/notes`.
+fn join_relative(dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = dir.split('/').filter(|part| !part.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Decodes the `%XX` escapes of a link target.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = text
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the wiki link to a note, keeping the label of the original link if it differs from the title.
+fn wiki_link(title: &str, label: &str) -> String {
+    if label.is_empty() || label == title {
+        format!("[[{}]]", title)
+    } else {
+        format!("[[{}|{}]]", title, label)
+    }
+}
+
+/// Returns a title that is not used by an existing note, appending a counter if needed, and reserves it.
+fn unique_title(existing: &mut HashSet<String>, title: &str) -> String {
+    let mut candidate = title.to_string();
+    let mut counter = 1;
+    while existing.contains(&candidate) {
+        counter += 1;
+        candidate = format!("{} {}", title, counter);
+    }
+    existing.insert(candidate.clone());
+    candidate
+}
+
+/// Reads the notes of an Evernote export.
+fn parse_enex(source: impl BufRead) -> io::Result<Vec<EnexNote>> {
+    let mut reader = Reader::from_reader(source);
+    let mut buf = Vec::new();
+    let mut notes = Vec::new();
+    let mut note: Option<EnexNote> = None;
+    let mut resource: Option<EnexResource> = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event_into(&mut buf).map_err(invalid_data)? {
+            Event::Start(start) => {
+                text.clear();
+                match start.name().as_ref() {
+                    b"note" => note = Some(EnexNote::default()),
+                    b"resource" => resource = Some(EnexResource::default()),
+                    _ => {}
+                }
+            }
+            Event::Text(chunk) => text.push_str(&chunk.unescape().map_err(invalid_data)?),
+            Event::CData(chunk) => text.push_str(&String::from_utf8_lossy(&chunk.into_inner())),
+            Event::End(end) => {
+                let value = std::mem::take(&mut text);
+                match (end.name().as_ref(), &mut note, &mut resource) {
+                    (b"data", _, Some(resource)) => resource.data = value,
+                    (b"mime", _, Some(resource)) => resource.mime = value,
+                    (b"file-name", _, Some(resource)) => resource.file_name = value,
+                    (b"resource", Some(note), resource) => note.resources.extend(resource.take()),
+                    (b"title", Some(note), None) => note.title = value,
+                    (b"content", Some(note), None) => note.content = value,
+                    (b"created", Some(note), None) => note.created = value,
+                    (b"tag", Some(note), None) => note.tags.push(value.trim().to_string()),
+                    (b"note", note, _) => notes.extend(note.take()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(notes)
+}
+
+/// Converts the ENML body of a note to Markdown.
+///
+/// # Arguments
+///
+/// * `enml` - The body of the note.
+/// * `media` - The Markdown link of each attachment, by the MD5 hash of its content.
+fn enml_to_markdown(enml: &str, media: &HashMap<String, String>) -> String {
+    let declarations = Regex::new(r"(?s)<\?xml.*?\?>|<!DOCTYPE[^>]*>").unwrap();
+    let media_tag = Regex::new(r"(?s)<en-media\b([^>]*?)/?>(\s*</en-media>)?").unwrap();
+    let todo_tag = Regex::new(r"(?s)<en-todo\b([^>]*?)/?>(\s*</en-todo>)?").unwrap();
+    let hash_attribute = Regex::new(r#"hash="([0-9a-fA-F]+)""#).unwrap();
+
+    // Evernote elements become placeholders, which the HTML conversion leaves alone
+    let mut links = Vec::new();
+    let html = declarations.replace_all(enml, "");
+    let html = media_tag.replace_all(&html, |captures: &Captures<'_>| {
+        let link = hash_attribute
+            .captures(&captures[1])
+            .and_then(|hash| media.get(&hash[1].to_lowercase()))
+            .cloned()
+            .unwrap_or_default();
+        links.push(link);
+        format!("ENMEDIA{}END", links.len() - 1)
+    });
+    let html = todo_tag.replace_all(&html, |captures: &Captures<'_>| {
+        if captures[1].contains("checked=\"true\"") {
+            "ENTODODONE"
+        } else {
+            "ENTODOOPEN"
+        }
+    });
+    let html = html.replace("<en-note", "<div").replace("</en-note>", "</div>");
+
+    let mut markdown = html2md::parse_html(&html)
+        .replace("ENTODODONE", "- [x] ")
+        .replace("ENTODOOPEN", "- [ ] ");
+    for (index, link) in links.iter().enumerate() {
+        markdown = markdown.replace(&format!("ENMEDIA{}END", index), link);
+    }
+    markdown
+}
+
+/// Returns a file extension for a MIME type, e.g. `png` for `image/png`.
+fn mime_extension(mime: &str) -> &str {
+    match mime.trim() {
+        "image/jpeg" => "jpg",
+        "text/plain" => "txt",
+        mime => mime
+            .split('/')
+            .nth(1)
+            .and_then(|subtype| subtype.split('+').next())
+            .filter(|subtype| !subtype.is_empty())
+            .unwrap_or("bin"),
+    }
+}
+
+fn invalid_data(err: quick_xml::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn invalid_zip(err: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+    use std::fs;
+
+    const ENEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-export SYSTEM "http://xml.evernote.com/pub/evernote-export4.dtd">
+<en-export export-date="20240316T090000Z" application="Evernote">
+  <note>
+    <title>Groceries &amp; more</title>
+    <content><![CDATA[<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-note SYSTEM "http://xml.evernote.com/pub/enml2.dtd">
+<en-note><div><en-todo checked="true"/>Apples</div><div><en-todo/>Pears</div><div><en-media type="text/plain" hash="5d41402abc4b2a76b9719d911017c592"/></div></en-note>]]></content>
+    <created>20240315T120000Z</created>
+    <tag>shopping</tag>
+    <tag>home</tag>
+    <resource>
+      <data encoding="base64">aGVs
+bG8=</data>
+      <mime>text/plain</mime>
+      <resource-attributes><file-name>list.txt</file-name></resource-attributes>
+    </resource>
+  </note>
+  <note>
+    <title></title>
+    <content><![CDATA[<en-note><p>Untitled thoughts</p></en-note>]]></content>
+  </note>
+</en-export>"#;
+
+    #[test]
+    fn test_parse_enex() {
+        let notes = parse_enex(ENEX.as_bytes()).unwrap();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].title, "Groceries & more");
+        assert_eq!(notes[0].tags, vec!["shopping", "home"]);
+        assert_eq!(notes[0].created, "20240315T120000Z");
+        assert_eq!(notes[0].resources[0].file_name, "list.txt");
+        assert!(notes[0].content.contains("<en-todo checked=\"true\"/>Apples"));
+        assert!(notes[1].resources.is_empty());
+    }
+
+    #[test]
+    fn test_import_enex() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let home = temp_notes_dir.parent().unwrap();
+        let path = home.join("Personal.enex");
+        fs::write(&path, ENEX).unwrap();
+
+        let report = enex(&path).unwrap();
+        assert_eq!(report.notes, vec!["Personal/Groceries & more", "Personal/Untitled"]);
+        assert_eq!(report.attachments, 1);
+
+        let content = Notes::read_note_file("Personal/Groceries & more").unwrap();
+        let (front_matter, body) = FrontMatter::parse(&content);
+        assert_eq!(front_matter.tags(), vec!["shopping", "home"]);
+        assert_eq!(front_matter.get("created"), Some("2024-03-15"));
+        assert!(body.starts_with("# Groceries & more\n"));
+        assert!(body.contains("- [x] Apples"));
+        assert!(body.contains("- [ ] Pears"));
+        assert!(body.contains("[list.txt](attachments/"));
+
+        // Importing again keeps the earlier notes
+        let report = enex(&path).unwrap();
+        assert_eq!(report.notes[0], "Personal/Groceries & more 2");
+    }
+
+    fn add_to_tar(builder: &mut tar::Builder<Vec<u8>>, name: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data).unwrap();
+    }
+
+    #[test]
+    fn test_import_jex() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let home = temp_notes_dir.parent().unwrap();
+        let (work, plan, standup, logo, tag) = (
+            "11111111111111111111111111111111",
+            "22222222222222222222222222222222",
+            "33333333333333333333333333333333",
+            "44444444444444444444444444444444",
+            "55555555555555555555555555555555",
+        );
+        let mut builder = tar::Builder::new(Vec::new());
+        add_to_tar(&mut builder, &format!("{}.md", work), format!("Work\n\nid: {}\nparent_id: \ntype_: 2", work).as_bytes());
+        let plan_item = format!(
+            "Plan\n\nSee [the standup](:/{}) and ![](:/{})\n\nMore\n\nid: {}\nparent_id: {}\ncreated_time: 2024-03-15T12:00:00.000Z\ntype_: 1",
+            standup, logo, plan, work
+        );
+        add_to_tar(&mut builder, &format!("{}.md", plan), plan_item.as_bytes());
+        let standup_item = format!("Standup\n\nBack to [Plan](:/{})\n\nid: {}\nparent_id: {}\ntype_: 1", plan, standup, work);
+        add_to_tar(&mut builder, &format!("{}.md", standup), standup_item.as_bytes());
+        let logo_item = format!("logo.png\n\nid: {}\nfile_extension: png\ntype_: 4", logo);
+        add_to_tar(&mut builder, &format!("{}.md", logo), logo_item.as_bytes());
+        add_to_tar(&mut builder, &format!("resources/{}.png", logo), b"png");
+        add_to_tar(&mut builder, &format!("{}.md", tag), format!("urgent\n\nid: {}\ntype_: 5", tag).as_bytes());
+        let note_tag = format!("\n\nid: 66666666666666666666666666666666\nnote_id: {}\ntag_id: {}\ntype_: 6", plan, tag);
+        add_to_tar(&mut builder, "66666666666666666666666666666666.md", note_tag.as_bytes());
+        add_to_tar(&mut builder, "77777777777777777777777777777777.md", b"Revision\n\nid: 7\ntype_: 13");
+        let path = home.join("export.jex");
+        fs::write(&path, builder.into_inner().unwrap()).unwrap();
+
+        let report = jex(&path).unwrap();
+        assert_eq!(report.notes, vec!["Work/Plan", "Work/Standup"]);
+        assert_eq!(report.attachments, 1);
+        assert_eq!(report.skipped.len(), 1);
+
+        let content = Notes::read_note_file("Work/Plan").unwrap();
+        let (front_matter, body) = FrontMatter::parse(&content);
+        assert_eq!(front_matter.tags(), vec!["urgent"]);
+        assert_eq!(front_matter.get("created"), Some("2024-03-15"));
+        assert!(body.contains("See [[Work/Standup|the standup]] and ![logo.png](attachments/"));
+        assert!(body.ends_with("More\n"));
+        assert!(Notes::read_note_file("Work/Standup").unwrap().contains("Back to [[Work/Plan|Plan]]"));
+    }
+
+    #[test]
+    fn test_import_notion() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let home = temp_notes_dir.parent().unwrap();
+        let id = "0123456789abcdef0123456789abcdef";
+        let path = home.join("notion.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let files = [
+            (
+                format!("Projects {}.md", id),
+                format!("# Projects\n\n[Roadmap](Projects%20{}/Roadmap%20{}.md)", id, id),
+            ),
+            (
+                format!("Projects {}/Roadmap {}.md", id, id),
+                format!("# Roadmap\n\n![chart](chart.png) [up](../Projects%20{}.md) [web](https://a.org/x.md)", id),
+            ),
+            (format!("Projects {}/chart.png", id), "png".to_string()),
+            (format!("Projects {}/unused.pdf", id), "pdf".to_string()),
+            (format!("Tasks {}.csv", id), "Name,Done".to_string()),
+        ];
+        for (name, data) in &files {
+            writer.start_file(name.as_str(), options).unwrap();
+            std::io::Write::write_all(&mut writer, data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let report = notion(&path).unwrap();
+        assert_eq!(report.notes, vec!["Projects", "Projects/Roadmap"]);
+        assert_eq!(report.attachments, 1);
+        assert_eq!(report.skipped.len(), 2);
+        assert!(Notes::read_note_file("Projects").unwrap().contains("[[Projects/Roadmap|Roadmap]]"));
+        let roadmap = Notes::read_note_file("Projects/Roadmap").unwrap();
+        assert!(roadmap.contains("![chart](attachments/"));
+        assert!(roadmap.contains("[[Projects|up]]"));
+        assert!(roadmap.contains("[web](https://a.org/x.md)"));
+    }
+
+    #[test]
+    fn test_link_helpers() {
+        assert_eq!(strip_notion_id("Roadmap 0123456789abcdef0123456789abcdef"), "Roadmap");
+        assert_eq!(strip_notion_id("Roadmap 2024"), "Roadmap 2024");
+        assert_eq!(join_relative("a/b", "../c/./d.png"), "a/c/d.png");
+        assert_eq!(percent_decode("Caf%C3%A9%20menu%2"), "Café menu%2");
+    }
+}
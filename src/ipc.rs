@@ -0,0 +1,352 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vault;
+
+/// The name of the socket in `~/.notes` that the running window listens on. On platforms
+/// without Unix sockets it is a file holding the loopback port instead, followed by a token that
+/// clients send first to prove that they could read the file.
+pub const SOCKET_FILE: &str = ".notes.sock";
+
+/// The note that quick captures are appended to.
+pub const INBOX_NOTE: &str = "Inbox";
+
+/// How long a client waits for the window to carry out a request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the window waits for the next request of a client before hanging up.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A request from another program to the running window. Requests are sent as one JSON object
+/// per line, e.g. `{"command": "append", "title": "Journal", "text": "Went for a walk"}`, and
+/// each is answered with a line such as `{"ok": true}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Opens a note and brings the window to the front.
+    OpenNote { title: String },
+    /// Appends text to a note as a new paragraph, creating the note if it doesn't exist.
+    Append { title: String, text: String },
+    /// Appends text to the inbox note.
+    QuickCapture { text: String },
+    /// Shows the quick capture window, e.g. from a shortcut set up in the desktop's settings.
+    ShowQuickCapture,
+    /// Brings the window to the front.
+    Focus,
+}
+
+/// The answer to a request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Reply {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A request received by the window, to be answered once it has been carried out.
+pub struct Incoming {
+    /// What was asked for.
+    pub request: Request,
+    reply: Sender<Result<(), String>>,
+}
+
+impl Incoming {
+    /// Tells the client whether the request was carried out.
+    pub fn reply(self, result: Result<(), String>) {
+        // The client may have given up waiting
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Returns the path of the socket the running window listens on.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the path to the socket.
+pub fn socket_path() -> io::Result<PathBuf> {
+    Ok(vault::home_root()?.join(SOCKET_FILE))
+}
+
+/// Starts listening for requests from other programs on a background thread. Only one window
+/// listens at a time.
+///
+/// # Arguments
+///
+/// * `wake` - Called after each request is received, e.g. to repaint the window so it
+///   picks the request up.
+///
+/// # Returns
+///
+/// An `io::Result<Receiver<Incoming>>` containing the received requests, or an `AddrInUse`
+/// error if another window is listening already.
+pub fn listen(wake: impl Fn() + Send + Sync + 'static) -> io::Result<Receiver<Incoming>> {
+    let (listener, token) = platform::bind(&socket_path()?)?;
+    let (sender, receiver) = channel();
+    let wake = Arc::new(wake);
+    std::thread::spawn(move || {
+        for connection in listener.incoming() {
+            let sender = sender.clone();
+            let wake = Arc::clone(&wake);
+            let token = token.clone();
+            // Each client is answered on its own thread, so that one that sends nothing doesn't hold up the others
+            std::thread::spawn(move || {
+                let wake: &(dyn Fn() + Send) = wake.as_ref();
+                let result = connection.and_then(|stream| {
+                    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+                    let reader = BufReader::new(stream.try_clone()?);
+                    serve_connection(reader, stream, token.as_deref(), &sender, wake)
+                });
+                if let Err(err) = result {
+                    log::warn!("Failed to answer a request from another program: {}", err);
+                }
+            });
+        }
+    });
+    Ok(receiver)
+}
+
+/// Sends a request to the running window and waits until it has been carried out.
+///
+/// # Arguments
+///
+/// * `request` - The request to send.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success, or an error if no window is running, e.g.
+/// `ConnectionRefused` or `NotFound`, or if the window could not carry out the request.
+pub fn send(request: &Request) -> io::Result<()> {
+    let (stream, token) = platform::connect(&socket_path()?)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    if let Some(token) = token {
+        writeln!(writer, "{}", token)?;
+    }
+    writeln!(writer, "{}", serde_json::to_string(request)?)?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let reply: Reply = serde_json::from_str(&line)?;
+    match reply.error {
+        None if reply.ok => Ok(()),
+        error => Err(io::Error::new(
+            io::ErrorKind::Other,
+            error.unwrap_or_else(|| "The request failed".to_string()),
+        )),
+    }
+}
+
+// Answers the requests of one client, one line each, until it hangs up. With a token, the client
+// has to send it first, or it is hung up on before any request is read.
+fn serve_connection(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    token: Option<&str>,
+    sender: &Sender<Incoming>,
+    wake: &(dyn Fn() + Send),
+) -> io::Result<()> {
+    let mut lines = reader.lines();
+    if let Some(token) = token {
+        let first = lines.next().transpose()?;
+        if first.as_deref().map(str::trim) != Some(token) {
+            let reply = Reply {
+                ok: false,
+                error: Some("Invalid token".to_string()),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&reply)?)?;
+            return writer.flush();
+        }
+    }
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (reply, answer) = channel();
+                if sender.send(Incoming { request, reply }).is_err() {
+                    return Ok(());
+                }
+                wake();
+                answer
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| Err("The window did not respond".to_string()))
+            }
+            Err(err) => Err(format!("Invalid request: {}", err)),
+        };
+        let reply = Reply {
+            ok: result.is_ok(),
+            error: result.err(),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&reply)?)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+
+    // Only the user can reach the socket, so no token is needed
+    pub fn bind(path: &Path) -> io::Result<(UnixListener, Option<String>)> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "Another window is listening"));
+            }
+            // Left behind by a window that crashed
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        Ok((listener, None))
+    }
+
+    pub fn connect(path: &Path) -> io::Result<(UnixStream, Option<String>)> {
+        Ok((UnixStream::connect(path)?, None))
+    }
+}
+
+// The standard library has no named pipes, so other platforms listen on a loopback port that is
+// written to the socket file. Any local program or web page can reach the port, so only clients
+// that send the random token written next to it are answered.
+#[cfg(not(unix))]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::net::{TcpListener, TcpStream};
+    use std::path::Path;
+
+    use crate::secrets;
+
+    pub fn bind(path: &Path) -> io::Result<(TcpListener, Option<String>)> {
+        if connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "Another window is listening"));
+        }
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let token = secrets::generate_key();
+        fs::write(path, format!("{}\n{}\n", listener.local_addr()?.port(), token))?;
+        Ok((listener, Some(token)))
+    }
+
+    pub fn connect(path: &Path) -> io::Result<(TcpStream, Option<String>)> {
+        let data = fs::read_to_string(path)?;
+        let mut lines = data.lines();
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "The socket file is invalid");
+        let port: u16 = lines
+            .next()
+            .and_then(|port| port.trim().parse().ok())
+            .ok_or_else(invalid)?;
+        let token = lines
+            .next()
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or_else(invalid)?;
+        Ok((TcpStream::connect(("127.0.0.1", port))?, Some(token.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_request_format() {
+        let request: Request = serde_json::from_str(r#"{"command": "open-note", "title": "Ideas"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::OpenNote {
+                title: "Ideas".to_string()
+            }
+        );
+        let request = Request::QuickCapture {
+            text: "Call Bob".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"command":"quick-capture","text":"Call Bob"}"#
+        );
+    }
+
+    #[test]
+    fn test_serve_connection() {
+        let (sender, receiver) = channel();
+        let window = std::thread::spawn(move || {
+            let incoming: Incoming = receiver.recv().unwrap();
+            assert_eq!(incoming.request, Request::Focus);
+            incoming.reply(Err("No window".to_string()));
+        });
+        let input = "{\"command\": \"focus\"}\n\nnot json\n";
+        let mut output = Vec::new();
+        serve_connection(Cursor::new(input), &mut output, None, &sender, &|| {}).unwrap();
+        window.join().unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let replies: Vec<&str> = output.lines().collect();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0], r#"{"ok":false,"error":"No window"}"#);
+        assert!(replies[1].starts_with(r#"{"ok":false,"error":"Invalid request: "#));
+    }
+
+    #[test]
+    fn test_token() {
+        let (sender, receiver) = channel();
+        // A web page posting to the port sends its request line first
+        let input = "POST / HTTP/1.1\n\n{\"command\": \"append\", \"title\": \"Ideas\", \"text\": \"Hi\"}\n";
+        let mut output = Vec::new();
+        serve_connection(Cursor::new(input), &mut output, Some("secret"), &sender, &|| {}).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"ok\":false,\"error\":\"Invalid token\"}\n"
+        );
+        assert!(receiver.try_recv().is_err());
+
+        let window = std::thread::spawn(move || {
+            let incoming: Incoming = receiver.recv().unwrap();
+            assert_eq!(incoming.request, Request::Focus);
+            incoming.reply(Ok(()));
+        });
+        let mut output = Vec::new();
+        let input = "secret\n{\"command\": \"focus\"}\n";
+        serve_connection(Cursor::new(input), &mut output, Some("secret"), &sender, &|| {}).unwrap();
+        window.join().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "{\"ok\":true}\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send() {
+        let _temp_notes_dir = crate::testing::setup_temp_notes_dir();
+        assert!(send(&Request::Focus).is_err());
+
+        let receiver = listen(|| {}).unwrap();
+        assert_eq!(listen(|| {}).err().map(|err| err.kind()), Some(io::ErrorKind::AddrInUse));
+        let window = std::thread::spawn(move || {
+            let incoming = receiver.recv().unwrap();
+            assert_eq!(
+                incoming.request,
+                Request::Append {
+                    title: "Journal".to_string(),
+                    text: "Went for a walk".to_string()
+                }
+            );
+            incoming.reply(Ok(()));
+        });
+        send(&Request::Append {
+            title: "Journal".to_string(),
+            text: "Went for a walk".to_string(),
+        })
+        .unwrap();
+        window.join().unwrap();
+    }
+}
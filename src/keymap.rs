@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use eframe::egui::{Key, KeyboardShortcut, Modifiers};
+
+/// Something the app does that can be bound to a keyboard shortcut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Focuses the title of a new note.
+    NewNote,
+    /// Saves the open note.
+    Save,
+    /// Focuses the search box.
+    Search,
+    /// Shows or hides the todos panel.
+    ToggleTodos,
+    /// Opens the quick open switcher.
+    QuickOpen,
+    /// Enters or leaves zen mode.
+    Zen,
+    /// Runs the command entered last in the command bar again.
+    RepeatCommand,
+}
+
+impl Action {
+    /// Every action, in the order they are listed in the preferences.
+    pub const ALL: [Action; 7] = [
+        Action::NewNote,
+        Action::Save,
+        Action::Search,
+        Action::ToggleTodos,
+        Action::QuickOpen,
+        Action::Zen,
+        Action::RepeatCommand,
+    ];
+
+    /// The name the action is stored under in the configuration.
+    pub fn id(self) -> &'static str {
+        match self {
+            Action::NewNote => "new_note",
+            Action::Save => "save",
+            Action::Search => "search",
+            Action::ToggleTodos => "toggle_todos",
+            Action::QuickOpen => "quick_open",
+            Action::Zen => "zen",
+            Action::RepeatCommand => "repeat_command",
+        }
+    }
+
+    /// A human-readable name of the action.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::NewNote => "New note",
+            Action::Save => "Save",
+            Action::Search => "Search",
+            Action::ToggleTodos => "Show or hide todos",
+            Action::QuickOpen => "Quick open",
+            Action::Zen => "Zen mode",
+            Action::RepeatCommand => "Run the last command again",
+        }
+    }
+
+    /// The shortcut the action is bound to unless it is rebound. `Ctrl` is `Cmd` on macOS.
+    pub fn default_shortcut(self) -> &'static str {
+        match self {
+            Action::NewNote => "Ctrl+N",
+            Action::Save => "Ctrl+S",
+            Action::Search => "Ctrl+F",
+            Action::ToggleTodos => "Ctrl+T",
+            Action::QuickOpen => "Ctrl+P",
+            Action::Zen => "F11",
+            Action::RepeatCommand => "Ctrl+Shift+R",
+        }
+    }
+}
+
+/// Reads a shortcut such as `Ctrl+Shift+N`. `Ctrl` and `Cmd` both mean `Cmd` on macOS and
+/// `Ctrl` elsewhere, so the same keymap works on every platform.
+///
+/// # Arguments
+///
+/// * `shortcut` - Modifiers and a key, separated by `+`.
+///
+/// # Returns
+///
+/// An `io::Result<KeyboardShortcut>` containing the shortcut, or an `InvalidInput` error if it
+/// is not valid.
+pub fn parse(shortcut: &str) -> io::Result<KeyboardShortcut> {
+    let invalid = |reason: &str| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid shortcut {}: {}", shortcut, reason))
+    };
+    let mut parts: Vec<&str> = shortcut.split('+').map(str::trim).collect();
+    let key = parts.pop().filter(|key| !key.is_empty()).ok_or_else(|| invalid("no key"))?;
+    let key = Key::from_name(key)
+        .or_else(|| Key::from_name(&key.to_uppercase()))
+        .ok_or_else(|| invalid("unknown key"))?;
+    let mut modifiers = Modifiers::NONE;
+    for part in parts {
+        modifiers = modifiers
+            | match part.to_lowercase().as_str() {
+                "ctrl" | "cmd" | "command" => Modifiers::COMMAND,
+                "shift" => Modifiers::SHIFT,
+                "alt" | "option" => Modifiers::ALT,
+                _ => return Err(invalid("unknown modifier")),
+            };
+    }
+    Ok(KeyboardShortcut::new(modifiers, key))
+}
+
+/// Writes a shortcut the way `parse` reads it, e.g. `Ctrl+Shift+N`.
+pub fn format(shortcut: &KeyboardShortcut) -> String {
+    let mut parts = Vec::new();
+    if shortcut.modifiers.command || shortcut.modifiers.ctrl || shortcut.modifiers.mac_cmd {
+        parts.push("Ctrl");
+    }
+    if shortcut.modifiers.alt {
+        parts.push("Alt");
+    }
+    if shortcut.modifiers.shift {
+        parts.push("Shift");
+    }
+    parts.push(shortcut.logical_key.name());
+    parts.join("+")
+}
+
+/// Returns the shortcut an action is bound to.
+///
+/// # Arguments
+///
+/// * `overrides` - The shortcuts the user rebound, by action id, see `Config::keymap`.
+/// * `action` - The action to look up.
+///
+/// # Returns
+///
+/// The rebound shortcut, or the default one if the action was not rebound or its shortcut is
+/// not valid.
+pub fn shortcut(overrides: &BTreeMap<String, String>, action: Action) -> KeyboardShortcut {
+    overrides
+        .get(action.id())
+        .and_then(|shortcut| parse(shortcut).ok())
+        .unwrap_or_else(|| parse(action.default_shortcut()).expect("default shortcuts are valid"))
+}
+
+/// Checks rebound shortcuts before they are saved.
+///
+/// # Arguments
+///
+/// * `overrides` - The shortcuts the user rebound, by action id.
+///
+/// # Returns
+///
+/// An `io::Result<()>` with an `InvalidInput` error if a shortcut is not valid, belongs to an
+/// unknown action, or is bound to two actions.
+pub fn validate(overrides: &BTreeMap<String, String>) -> io::Result<()> {
+    for id in overrides.keys() {
+        if !Action::ALL.iter().any(|action| action.id() == id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown action {}", id),
+            ));
+        }
+    }
+    for shortcut in overrides.values() {
+        parse(shortcut)?;
+    }
+    let bound: Vec<(Action, KeyboardShortcut)> = Action::ALL
+        .iter()
+        .map(|&action| (action, self::shortcut(overrides, action)))
+        .collect();
+    for (index, (action, shortcut)) in bound.iter().enumerate() {
+        if let Some((other, _)) = bound[index + 1..].iter().find(|(_, other)| other == shortcut) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is bound to both {} and {}", format(shortcut), action.label(), other.label()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let shortcut = parse("ctrl + shift + n").unwrap();
+        assert_eq!(shortcut, KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::N));
+        assert_eq!(format(&shortcut), "Ctrl+Shift+N");
+        assert_eq!(parse("Cmd+P").unwrap(), parse("Ctrl+P").unwrap());
+        assert_eq!(parse("F2").unwrap(), KeyboardShortcut::new(Modifiers::NONE, Key::F2));
+        for shortcut in Action::ALL.map(Action::default_shortcut) {
+            assert_eq!(format(&parse(shortcut).unwrap()), shortcut);
+        }
+        assert_eq!(parse("Ctrl+").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(parse("Hyper+N").is_err());
+        assert!(parse("Ctrl+Nope").is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut overrides = BTreeMap::new();
+        assert_eq!(shortcut(&overrides, Action::QuickOpen), parse("Ctrl+P").unwrap());
+        overrides.insert("quick_open".to_string(), "Ctrl+O".to_string());
+        assert_eq!(shortcut(&overrides, Action::QuickOpen), parse("Ctrl+O").unwrap());
+        assert!(validate(&overrides).is_ok());
+
+        overrides.insert("save".to_string(), "Ctrl+O".to_string());
+        let err = validate(&overrides).unwrap_err();
+        assert_eq!(err.to_string(), "Ctrl+O is bound to both Save and Quick open");
+        overrides.insert("save".to_string(), "Ctrl+Shift+".to_string());
+        assert!(validate(&overrides).is_err());
+        // Invalid overrides fall back to the default
+        assert_eq!(shortcut(&overrides, Action::Save), parse("Ctrl+S").unwrap());
+
+        overrides.clear();
+        overrides.insert("print".to_string(), "Ctrl+Y".to_string());
+        assert!(validate(&overrides).is_err());
+    }
+}
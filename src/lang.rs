@@ -0,0 +1,209 @@
+use crate::front_matter::FrontMatter;
+
+/// The languages that can be picked for a note, as `(code, name)` pairs.
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("de", "German"),
+    ("fr", "French"),
+    ("es", "Spanish"),
+    ("it", "Italian"),
+    ("nl", "Dutch"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("ar", "Arabic"),
+    ("he", "Hebrew"),
+    ("fa", "Persian"),
+    ("ja", "Japanese"),
+    ("zh", "Chinese"),
+];
+
+/// How many characters at the start of a note are inspected to detect its language.
+const DETECT_SAMPLE: usize = 2000;
+
+/// Frequent short words used to guess the language of Latin-script text.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "of", "to", "in", "that", "it", "with", "for"]),
+    ("de", &["der", "die", "und", "ist", "nicht", "das", "ich", "mit", "ein", "zu"]),
+    ("fr", &["le", "la", "les", "et", "est", "un", "une", "des", "pas", "pour"]),
+    ("es", &["el", "los", "las", "y", "es", "que", "por", "una", "con", "para"]),
+    ("it", &["il", "che", "di", "e", "non", "gli", "una", "sono", "per", "della"]),
+    ("nl", &["de", "het", "een", "en", "is", "niet", "van", "dat", "ik", "met"]),
+    ("pt", &["o", "os", "que", "e", "não", "uma", "com", "para", "do", "da"]),
+];
+
+/// The direction in which a note's text runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+/// The language of a note and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteLanguage {
+    /// The ISO 639-1 code of the language, e.g. `en`.
+    pub code: String,
+    /// Whether the language was guessed from the text rather than set in the front matter.
+    pub detected: bool,
+}
+
+impl NoteLanguage {
+    /// Determines the language of a note.
+    ///
+    /// The `lang` front matter field wins; `lang: auto` or a missing field falls back to
+    /// detecting the language from the body.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The full content of the note.
+    ///
+    /// # Returns
+    ///
+    /// The language, or `None` if none is set and none could be detected.
+    pub fn of(content: &str) -> Option<NoteLanguage> {
+        let (front_matter, body) = FrontMatter::parse(content);
+        match front_matter.get("lang") {
+            Some(code) if !code.is_empty() && !code.eq_ignore_ascii_case("auto") => Some(NoteLanguage {
+                code: code.to_lowercase(),
+                detected: false,
+            }),
+            _ => {
+                let sample = body.char_indices().nth(DETECT_SAMPLE).map_or(body, |(end, _)| &body[..end]);
+                detect(sample).map(|code| NoteLanguage {
+                    code: code.to_string(),
+                    detected: true,
+                })
+            }
+        }
+    }
+
+    /// Returns the code of the language without any region suffix, e.g. `ar` for `ar-EG`.
+    pub fn primary(&self) -> &str {
+        self.code.split(['-', '_']).next().unwrap_or_default()
+    }
+
+    /// Returns the direction in which text in this language runs.
+    pub fn direction(&self) -> TextDirection {
+        match self.primary() {
+            "ar" | "he" | "fa" | "ur" | "yi" => TextDirection::RightToLeft,
+            _ => TextDirection::LeftToRight,
+        }
+    }
+
+    /// Returns the display name of the language, falling back to its code.
+    pub fn name(&self) -> &str {
+        LANGUAGES
+            .iter()
+            .find(|(code, _)| *code == self.code)
+            .map_or(self.code.as_str(), |(_, name)| *name)
+    }
+}
+
+/// Guesses the language of a text.
+///
+/// Non-Latin scripts are recognized by their characters; Latin-script languages by counting
+/// common words.
+///
+/// # Arguments
+///
+/// * `text` - The text to inspect.
+///
+/// # Returns
+///
+/// The code of the most likely language, or `None` if the text gives no clear hint.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut scripts = [0usize; 6];
+    for c in text.chars() {
+        match c {
+            '\u{0600}'..='\u{06FF}' => scripts[0] += 1,
+            '\u{0590}'..='\u{05FF}' => scripts[1] += 1,
+            '\u{0400}'..='\u{04FF}' => scripts[2] += 1,
+            '\u{3040}'..='\u{30FF}' => scripts[3] += 1,
+            '\u{4E00}'..='\u{9FFF}' => scripts[4] += 1,
+            c if c.is_alphabetic() => scripts[5] += 1,
+            _ => {}
+        }
+    }
+    let (script, &count) = scripts.iter().enumerate().max_by_key(|(_, count)| **count)?;
+    if count == 0 {
+        return None;
+    }
+    match script {
+        0 => return Some("ar"),
+        1 => return Some("he"),
+        2 => return Some("ru"),
+        3 => return Some("ja"),
+        // Kanji without any kana is most likely Chinese.
+        4 if scripts[3] > 0 => return Some("ja"),
+        4 => return Some("zh"),
+        _ => {}
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut best: Option<(&'static str, usize)> = None;
+    for (code, stopwords) in STOPWORDS {
+        let hits = words.iter().filter(|word| stopwords.contains(&word.as_str())).count();
+        if hits > best.map_or(0, |(_, best_hits)| best_hits) {
+            best = Some((*code, hits));
+        }
+    }
+    // A single common word is not enough to go on.
+    best.filter(|(_, hits)| *hits >= 2).map(|(code, _)| code)
+}
+
+/// Sets or clears the `lang` field of a note.
+///
+/// # Arguments
+///
+/// * `content` - The full content of the note.
+/// * `code` - The language code, or `None` to detect the language automatically.
+///
+/// # Returns
+///
+/// The updated content of the note.
+pub fn set_language(content: &str, code: Option<&str>) -> String {
+    let (mut front_matter, body) = FrontMatter::parse(content);
+    match code {
+        Some(code) => front_matter.set("lang", code),
+        None => front_matter.remove("lang"),
+    }
+    front_matter.render(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_matter_language_wins() {
+        let language = NoteLanguage::of("---\nlang: DE\n---\nThe cat is in the house.").unwrap();
+        assert_eq!(language.code, "de");
+        assert!(!language.detected);
+        assert_eq!(language.name(), "German");
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect("The cat is sitting in the garden"), Some("en"));
+        assert_eq!(detect("Der Hund ist nicht mit dem Ball zu Hause"), Some("de"));
+        assert_eq!(detect("Le chat est sur la table avec les enfants"), Some("fr"));
+        assert_eq!(detect("שלום עולם"), Some("he"));
+        assert_eq!(detect("Hello"), None);
+        assert_eq!(detect(""), None);
+
+        let language = NoteLanguage::of("---\nlang: auto\n---\nمرحبا بالعالم").unwrap();
+        assert!(language.detected);
+        assert_eq!(language.direction(), TextDirection::RightToLeft);
+    }
+
+    #[test]
+    fn test_set_language() {
+        let content = set_language("Body", Some("fr"));
+        assert_eq!(content, "---\nlang: fr\n---\nBody");
+        assert_eq!(set_language(&content, None), "Body");
+    }
+}
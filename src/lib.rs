@@ -1,6 +1,92 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod activity;
+#[cfg(not(target_arch = "wasm32"))]
+mod ai;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod api;
 mod app;
-mod notes;
-mod todos;
+pub mod attachments;
+#[cfg(not(target_arch = "wasm32"))]
+mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+mod backup;
+mod batch;
+mod calendar;
+#[cfg(not(target_arch = "wasm32"))]
+mod clip;
+mod command_history;
+mod commands;
+mod complete;
+mod config;
+#[cfg(all(feature = "dictation", not(target_arch = "wasm32")))]
+mod dictation;
+mod editing;
+mod editor;
+pub mod export;
+#[cfg(not(target_arch = "wasm32"))]
+mod feeds;
+mod focus;
+mod folders;
+mod front_matter;
+mod fuzzy;
+mod highlight;
+mod history;
+#[cfg(not(target_arch = "wasm32"))]
+mod hotkey;
+mod ical;
+#[cfg(not(target_arch = "wasm32"))]
+mod import;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ipc;
+mod keymap;
+mod lang;
+mod links;
+mod macros;
+mod merge;
+mod navigation;
+pub mod notes;
+#[cfg(not(target_arch = "wasm32"))]
+mod ocr;
+mod outline;
+mod permalinks;
+mod preview;
+#[cfg(not(target_arch = "wasm32"))]
+mod print;
+#[cfg(not(target_arch = "wasm32"))]
+mod protect;
+mod reader;
+mod reading;
+mod reports;
+mod safe_mode;
+pub mod scheduler;
+mod schema;
+mod search;
+#[cfg(not(target_arch = "wasm32"))]
+mod secrets;
+mod selection;
+#[cfg(not(target_arch = "wasm32"))]
+mod share;
+mod spellcheck;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sqlite;
+mod stats;
+mod storage;
+pub mod store;
+#[cfg(not(target_arch = "wasm32"))]
+mod sync;
+mod tags;
+mod tasks;
+mod templates;
+#[cfg(test)]
+mod testing;
+mod theme;
+mod title;
+pub mod todos;
+#[cfg(not(target_arch = "wasm32"))]
+mod tray;
+mod vault;
+mod verify;
+#[cfg(not(target_arch = "wasm32"))]
+mod watcher;
 pub use app::TemplateApp;
@@ -0,0 +1,95 @@
+/// Extracts the targets of wiki links (`[[Title]]` or `[[Title|label]]`) from a note.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+///
+/// # Returns
+///
+/// The titles linked to, in order of appearance.
+pub fn wiki_links(content: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        let target = inner.split('|').next().unwrap_or("").trim();
+        if !target.is_empty() && !target.contains('\n') {
+            links.push(target.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    links
+}
+
+/// Points the wiki links to one note at another, e.g. after notes were merged. Links without a
+/// label keep showing the old title, e.g. `[[idea]]` becomes `[[ideas|idea]]`.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note with the links.
+/// * `from` - The title linked to.
+/// * `to` - The title to link to instead.
+///
+/// # Returns
+///
+/// The changed content, or `None` if the note has no links to `from`.
+pub fn retarget(content: &str, from: &str, to: &str) -> Option<String> {
+    let mut retargeted = String::with_capacity(content.len());
+    let mut changed = false;
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        retargeted.push_str(&rest[..start + 2]);
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        let (target, label) = inner.split_once('|').unwrap_or((inner, inner.trim()));
+        if target.trim() == from && !inner.contains('\n') {
+            retargeted.push_str(&format!("{}|{}", to, label));
+            changed = true;
+        } else {
+            retargeted.push_str(inner);
+        }
+        retargeted.push_str("]]");
+        rest = &rest[end + 2..];
+    }
+    retargeted.push_str(rest);
+    changed.then_some(retargeted)
+}
+
+/// Counts the external links (`http://` and `https://` URLs) in a note.
+pub fn external_link_count(content: &str) -> usize {
+    content.matches("http://").count() + content.matches("https://").count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wiki_links() {
+        let content = "See [[Project Plan]] and [[meetings/standup|the standup]], not [[ ]] or [[broken";
+        assert_eq!(wiki_links(content), vec!["Project Plan", "meetings/standup"]);
+    }
+
+    #[test]
+    fn test_retarget() {
+        let content = "See [[idea]], [[ idea |the idea]] and [[ideas]]";
+        assert_eq!(
+            retarget(content, "idea", "projects/ideas").as_deref(),
+            Some("See [[projects/ideas|idea]], [[projects/ideas|the idea]] and [[ideas]]")
+        );
+        assert_eq!(retarget(content, "plan", "projects/ideas"), None);
+        assert_eq!(retarget("[[idea", "idea", "ideas"), None);
+    }
+
+    #[test]
+    fn test_external_link_count() {
+        assert_eq!(external_link_count("[a](https://a.org) http://b.org [[c]]"), 2);
+    }
+}
@@ -0,0 +1,344 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+
+/// The name of the file in the `.notes` directory that stores recorded macros.
+pub const MACROS_FILE: &str = ".macros.json";
+
+/// A single recorded action of a macro.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Typed or pasted text.
+    Insert(String),
+    /// Deletes the character before the cursor.
+    Backspace,
+    /// Deletes the character after the cursor.
+    Delete,
+    /// Moves the cursor.
+    Move(Motion),
+    /// A command entered in the command bar.
+    Command(String),
+}
+
+/// A movement of the cursor in a macro.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    DocumentStart,
+    DocumentEnd,
+}
+
+/// A named sequence of editor actions that can be replayed on any note.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Macro {
+    /// The name the macro is played by.
+    pub name: String,
+    /// The name of the key that plays the macro on the open note, e.g. `F5`.
+    pub key: Option<String>,
+    /// The recorded actions, in order.
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    /// Replays the edits of the macro on a text.
+    ///
+    /// Commands are not part of the text and are skipped; see `commands`.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to edit.
+    /// * `cursor` - The char index of the cursor when the macro starts.
+    ///
+    /// # Returns
+    ///
+    /// The edited text and the char index of the cursor afterwards.
+    pub fn replay(&self, text: &str, cursor: usize) -> (String, usize) {
+        let mut chars: Vec<char> = text.chars().collect();
+        let mut cursor = cursor.min(chars.len());
+        for step in &self.steps {
+            match step {
+                MacroStep::Insert(inserted) => {
+                    for c in inserted.chars() {
+                        chars.insert(cursor, c);
+                        cursor += 1;
+                    }
+                }
+                MacroStep::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    chars.remove(cursor);
+                }
+                MacroStep::Delete if cursor < chars.len() => {
+                    chars.remove(cursor);
+                }
+                MacroStep::Move(motion) => cursor = motion.apply(&chars, cursor),
+                _ => {}
+            }
+        }
+        (chars.into_iter().collect(), cursor)
+    }
+
+    /// Returns the commands the macro runs, in order.
+    pub fn commands(&self) -> impl Iterator<Item = &str> {
+        self.steps.iter().filter_map(|step| match step {
+            MacroStep::Command(command) => Some(command.as_str()),
+            _ => None,
+        })
+    }
+}
+
+impl Motion {
+    fn apply(self, chars: &[char], cursor: usize) -> usize {
+        let line_start = |index: usize| chars[..index].iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+        let line_end = |index: usize| {
+            chars[index..]
+                .iter()
+                .position(|c| *c == '\n')
+                .map_or(chars.len(), |i| index + i)
+        };
+        match self {
+            Motion::Left => cursor.saturating_sub(1),
+            Motion::Right => (cursor + 1).min(chars.len()),
+            Motion::Up => {
+                let start = line_start(cursor);
+                if start == 0 {
+                    return 0;
+                }
+                let previous = line_start(start - 1);
+                (previous + cursor - start).min(start - 1)
+            }
+            Motion::Down => {
+                let end = line_end(cursor);
+                if end == chars.len() {
+                    return end;
+                }
+                let column = cursor - line_start(cursor);
+                (end + 1 + column).min(line_end(end + 1))
+            }
+            Motion::LineStart => line_start(cursor),
+            Motion::LineEnd => line_end(cursor),
+            Motion::DocumentStart => 0,
+            Motion::DocumentEnd => chars.len(),
+        }
+    }
+}
+
+/// Turns an input event of the editor into a macro step, if it edits text or moves the cursor.
+pub fn step_from_event(event: &egui::Event) -> Option<MacroStep> {
+    match event {
+        egui::Event::Text(text) | egui::Event::Paste(text) => Some(MacroStep::Insert(text.clone())),
+        egui::Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+            ..
+        } => {
+            let step = match key {
+                egui::Key::Enter => MacroStep::Insert("\n".to_string()),
+                egui::Key::Backspace => MacroStep::Backspace,
+                egui::Key::Delete => MacroStep::Delete,
+                egui::Key::ArrowLeft => MacroStep::Move(Motion::Left),
+                egui::Key::ArrowRight => MacroStep::Move(Motion::Right),
+                egui::Key::ArrowUp => MacroStep::Move(Motion::Up),
+                egui::Key::ArrowDown => MacroStep::Move(Motion::Down),
+                egui::Key::Home if modifiers.command => MacroStep::Move(Motion::DocumentStart),
+                egui::Key::End if modifiers.command => MacroStep::Move(Motion::DocumentEnd),
+                egui::Key::Home => MacroStep::Move(Motion::LineStart),
+                egui::Key::End => MacroStep::Move(Motion::LineEnd),
+                _ => return None,
+            };
+            Some(step)
+        }
+        _ => None,
+    }
+}
+
+/// The recorded macros, persisted in the `.macros.json` file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Macros {
+    /// The macros, in the order they were recorded.
+    pub items: Vec<Macro>,
+}
+
+impl Macros {
+    /// Loads the macros from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Macros>` containing the macros, or none if none were recorded yet.
+    pub fn load() -> io::Result<Macros> {
+        let path = Self::get_macros_file_path()?;
+        if !path.exists() {
+            return Ok(Macros::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Saves the macros to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&Self::get_macros_file_path()?, data.as_bytes(), true)
+    }
+
+    /// Returns the macro with the given name.
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.items.iter().find(|item| item.name == name)
+    }
+
+    /// Adds a macro, replacing any macro with the same name but keeping its key.
+    pub fn add(&mut self, mut new_macro: Macro) {
+        match self.items.iter_mut().find(|item| item.name == new_macro.name) {
+            Some(existing) => {
+                new_macro.key = new_macro.key.or(existing.key.take());
+                *existing = new_macro;
+            }
+            None => self.items.push(new_macro),
+        }
+    }
+
+    /// Binds a macro to a key, unbinding any other macro from it.
+    ///
+    /// # Returns
+    ///
+    /// `false` if there is no macro with the given name.
+    pub fn bind(&mut self, name: &str, key: Option<&str>) -> bool {
+        if self.get(name).is_none() {
+            return false;
+        }
+        for item in &mut self.items {
+            if item.name == name {
+                item.key = key.map(str::to_string);
+            } else if key.is_some() && item.key.as_deref() == key {
+                item.key = None;
+            }
+        }
+        true
+    }
+
+    fn get_macros_file_path() -> io::Result<PathBuf> {
+        Ok(Notes::get_notes_dir()?.join(MACROS_FILE))
+    }
+}
+
+/// Plays the edits of a macro on notes, starting at the top of each note.
+///
+/// # Arguments
+///
+/// * `played` - The macro to play.
+/// * `titles` - The notes to play it on.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of notes that changed.
+pub fn play_on_notes(played: &Macro, titles: &[String]) -> io::Result<usize> {
+    let mut changed = 0;
+    for title in titles {
+        let content = Notes::read_note_file(title)?;
+        let (edited, _) = played.replay(&content, 0);
+        if edited != content {
+            Notes::update_note_file(title, &edited)?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    fn recorded(steps: Vec<MacroStep>) -> Macro {
+        Macro {
+            name: "test".to_string(),
+            key: None,
+            steps,
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let prefix = recorded(vec![
+            MacroStep::Insert("- ".to_string()),
+            MacroStep::Move(Motion::Down),
+            MacroStep::Move(Motion::LineStart),
+            MacroStep::Insert("- ".to_string()),
+            MacroStep::Command("autosave 5".to_string()),
+        ]);
+        assert_eq!(prefix.replay("one\ntwo", 0), ("- one\n- two".to_string(), 8));
+        assert_eq!(prefix.commands().collect::<Vec<_>>(), vec!["autosave 5"]);
+
+        let trim = recorded(vec![
+            MacroStep::Move(Motion::LineEnd),
+            MacroStep::Backspace,
+            MacroStep::Move(Motion::Up),
+            MacroStep::Move(Motion::LineStart),
+            MacroStep::Delete,
+            MacroStep::Move(Motion::DocumentEnd),
+            MacroStep::Backspace,
+        ]);
+        assert_eq!(trim.replay("abc\ndef!", 4), ("bc\nde".to_string(), 5));
+        assert_eq!(trim.replay("", 0), (String::new(), 0));
+    }
+
+    #[test]
+    fn test_step_from_event() {
+        assert_eq!(
+            step_from_event(&egui::Event::Text("x".to_string())),
+            Some(MacroStep::Insert("x".to_string()))
+        );
+        let key = |key, modifiers| egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        };
+        assert_eq!(
+            step_from_event(&key(egui::Key::Home, egui::Modifiers::COMMAND)),
+            Some(MacroStep::Move(Motion::DocumentStart))
+        );
+        assert_eq!(step_from_event(&key(egui::Key::A, egui::Modifiers::NONE)), None);
+    }
+
+    #[test]
+    fn test_macros_are_persisted() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        let mut macros = Macros::default();
+        macros.add(recorded(vec![MacroStep::Insert("# ".to_string())]));
+        assert!(macros.bind("test", Some("F5")));
+        assert!(!macros.bind("other", Some("F6")));
+        macros.add(recorded(vec![MacroStep::Insert("## ".to_string())]));
+        macros.save().unwrap();
+
+        let macros = Macros::load().unwrap();
+        assert_eq!(macros.items.len(), 1);
+        assert_eq!(macros.items[0].key.as_deref(), Some("F5"));
+
+        Notes::create_note_file("a", "title").unwrap();
+        Notes::create_note_file("b", "## title").unwrap();
+        let titles = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(play_on_notes(&macros.items[0], &titles).unwrap(), 2);
+        assert_eq!(Notes::read_note_file("a").unwrap(), "## title");
+    }
+}
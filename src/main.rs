@@ -1,11 +1,61 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+#[cfg(not(target_arch = "wasm32"))]
+use eframe_template::ipc::{self, Request};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::ErrorKind;
+
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    // Hand the request to the window that is running, if any, instead of opening a second one
+    let request = match args.as_slice() {
+        // `--serve [port]` runs the HTTP API instead of the app
+        ["--serve", rest @ ..] => {
+            let port = match rest.first() {
+                Some(port) => match port.parse() {
+                    Ok(port) => port,
+                    Err(_) => {
+                        eprintln!("Invalid port: {}", port);
+                        std::process::exit(2);
+                    }
+                },
+                None => eframe_template::api::DEFAULT_PORT,
+            };
+            if let Err(err) = eframe_template::api::serve(port) {
+                eprintln!("Failed to serve the API: {}", err);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        [] => Request::Focus,
+        ["--open", title] => Request::OpenNote { title: title.to_string() },
+        ["--append", title, text] => Request::Append {
+            title: title.to_string(),
+            text: text.to_string(),
+        },
+        ["--capture", text] => Request::QuickCapture { text: text.to_string() },
+        ["--quick-capture"] => Request::ShowQuickCapture,
+        _ => {
+            eprintln!("Usage: notes [--open <title> | --append <title> <text> | --capture <text> | --quick-capture | --serve [port]]");
+            std::process::exit(2);
+        }
+    };
+    match ipc::send(&request) {
+        Ok(()) => return Ok(()),
+        // No window is running, so this one carries out the request
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused | ErrorKind::InvalidData) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -20,7 +70,13 @@ fn main() -> eframe::Result {
     eframe::run_native(
         "eframe template",
         native_options,
-        Box::new(|cc| Ok(Box::new(eframe_template::TemplateApp::new(cc)))),
+        Box::new(move |cc| {
+            let mut app = eframe_template::TemplateApp::new(cc);
+            if let Err(err) = app.handle_request(&cc.egui_ctx, &request) {
+                log::error!("{}", err);
+            }
+            Ok(Box::new(app))
+        }),
     )
 }
 
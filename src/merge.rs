@@ -0,0 +1,176 @@
+/// A change to a range of base lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hunk<'a> {
+    /// The first base line replaced by the hunk.
+    start: usize,
+    /// The line after the last base line replaced by the hunk.
+    end: usize,
+    /// The lines replacing `start..end`.
+    lines: Vec<&'a str>,
+}
+
+impl Hunk<'_> {
+    /// Returns whether two hunks touch the same or adjacent base lines.
+    fn overlaps(&self, other: &Hunk<'_>) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+}
+
+/// Merges two independently edited versions of a text, line by line.
+///
+/// Changes that touch different parts of `base` are combined. Identical changes made on
+/// both sides are applied once.
+///
+/// # Arguments
+///
+/// * `base` - The common ancestor of both versions.
+/// * `mine` - One edited version, e.g. the editor buffer.
+/// * `theirs` - The other edited version, e.g. the file written by another program.
+///
+/// # Returns
+///
+/// The merged text, or `None` if both sides changed the same lines differently.
+pub fn merge(base: &str, mine: &str, theirs: &str) -> Option<String> {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let my_lines: Vec<&str> = mine.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let mine = diff(&base_lines, &my_lines);
+    let theirs = diff(&base_lines, &their_lines);
+
+    let mut merged = String::new();
+    let mut position = 0;
+    let (mut i, mut j) = (0, 0);
+    while i < mine.len() || j < theirs.len() {
+        let take_mine = j >= theirs.len() || (i < mine.len() && mine[i].start <= theirs[j].start);
+        let (hunk, other) = if take_mine {
+            (&mine[i], theirs.get(j))
+        } else {
+            (&theirs[j], mine.get(i))
+        };
+
+        match other {
+            Some(other) if hunk.overlaps(other) => {
+                if hunk != other {
+                    return None;
+                }
+                i += 1;
+                j += 1;
+            }
+            _ if take_mine => i += 1,
+            _ => j += 1,
+        }
+        merged.extend(base_lines[position..hunk.start].iter().copied());
+        merged.extend(hunk.lines.iter().copied());
+        position = hunk.end;
+    }
+    merged.extend(base_lines[position..].iter().copied());
+    Some(merged)
+}
+
+/// Computes the hunks turning `base` into `other`, using the longest common subsequence of lines.
+fn diff<'a>(base: &[&'a str], other: &[&'a str]) -> Vec<Hunk<'a>> {
+    // Common leading and trailing lines never change, so keep the table small by skipping them.
+    let prefix = base.iter().zip(other).take_while(|(a, b)| a == b).count();
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(other[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let base_middle = &base[prefix..base.len() - suffix];
+    let other_middle = &other[prefix..other.len() - suffix];
+    let (n, m) = (base_middle.len(), other_middle.len());
+
+    // lcs[i][j] is the length of the longest common subsequence of base_middle[i..] and other_middle[j..].
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if base_middle[i] == other_middle[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk<'a>> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && base_middle[i] == other_middle[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            let hunk = current.get_or_insert(Hunk {
+                start: prefix + i,
+                end: prefix + i,
+                lines: Vec::new(),
+            });
+            hunk.lines.push(other_middle[j]);
+            j += 1;
+        } else {
+            let hunk = current.get_or_insert(Hunk {
+                start: prefix + i,
+                end: prefix + i,
+                lines: Vec::new(),
+            });
+            hunk.end = prefix + i + 1;
+            i += 1;
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_separate_edits() {
+        let base = "one\ntwo\nthree\nfour\nfive\n";
+        let mine = "ONE\ntwo\nthree\nfour\nfive\n";
+        let theirs = "one\ntwo\nthree\nfour\nfive\nsix\n";
+        assert_eq!(
+            merge(base, mine, theirs).as_deref(),
+            Some("ONE\ntwo\nthree\nfour\nfive\nsix\n")
+        );
+    }
+
+    #[test]
+    fn test_merge_identical_edits() {
+        let base = "a\nb\nc\n";
+        let edited = "a\nB\nc\n";
+        assert_eq!(merge(base, edited, edited).as_deref(), Some(edited));
+    }
+
+    #[test]
+    fn test_merge_deletion_and_edit() {
+        let base = "a\nb\nc\nd\ne\n";
+        let mine = "a\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\n";
+        assert_eq!(merge(base, mine, theirs).as_deref(), Some("a\nc\nd\nE\n"));
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let base = "a\nb\nc\n";
+        assert_eq!(merge(base, "a\nmine\nc\n", "a\ntheirs\nc\n"), None);
+        assert_eq!(merge(base, "a\nb\nc\nmine\n", "a\nb\nc\ntheirs\n"), None);
+    }
+
+    #[test]
+    fn test_diff_hunks() {
+        let base = ["a\n", "b\n", "c\n"];
+        let other = ["a\n", "x\n", "y\n", "c\n"];
+        assert_eq!(
+            diff(&base, &other),
+            vec![Hunk {
+                start: 1,
+                end: 2,
+                lines: vec!["x\n", "y\n"],
+            }]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+/// How many notes are remembered to go back to.
+const MAX_HISTORY: usize = 100;
+
+/// Which way to move through the notes opened before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// To the note opened before.
+    Back,
+    /// To the note that was gone back from.
+    Forward,
+}
+
+/// The notes opened before and after the current one, to move between them like the back and
+/// forward buttons of a browser.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Navigation {
+    back: Vec<String>,
+    forward: Vec<String>,
+}
+
+impl Navigation {
+    /// Records that another note was opened, so that the back button returns to `from`.
+    /// Notes that could be gone forward to are forgotten.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The note that was open before.
+    pub fn visit(&mut self, from: &str) {
+        if self.back.last().map(String::as_str) != Some(from) {
+            self.back.push(from.to_string());
+        }
+        if self.back.len() > MAX_HISTORY {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+
+    /// Whether there is a note to move to in a direction.
+    pub fn can_go(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Back => !self.back.is_empty(),
+            Direction::Forward => !self.forward.is_empty(),
+        }
+    }
+
+    /// Moves back or forward.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - Which way to move.
+    /// * `current` - The open note, which can be returned to by moving the other way.
+    ///
+    /// # Returns
+    ///
+    /// The title of the note to open, or `None` if there is nothing in that direction.
+    pub fn go(&mut self, direction: Direction, current: Option<&str>) -> Option<String> {
+        let (from, to) = match direction {
+            Direction::Back => (&mut self.back, &mut self.forward),
+            Direction::Forward => (&mut self.forward, &mut self.back),
+        };
+        let title = from.pop()?;
+        if let Some(current) = current {
+            to.push(current.to_string());
+        }
+        Some(title)
+    }
+
+    /// Forgets a note, e.g. after it was deleted.
+    pub fn remove(&mut self, title: &str) {
+        self.back.retain(|note| note != title);
+        self.forward.retain(|note| note != title);
+        self.back.dedup();
+        self.forward.dedup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut navigation = Navigation::default();
+        assert!(!navigation.can_go(Direction::Back));
+        navigation.visit("a");
+        navigation.visit("b");
+        // Now at c
+        assert_eq!(navigation.go(Direction::Back, Some("c")).as_deref(), Some("b"));
+        assert_eq!(navigation.go(Direction::Back, Some("b")).as_deref(), Some("a"));
+        assert_eq!(navigation.go(Direction::Back, Some("a")), None);
+        assert_eq!(navigation.go(Direction::Forward, Some("a")).as_deref(), Some("b"));
+        assert!(navigation.can_go(Direction::Forward));
+
+        // Opening another note drops the way forward
+        navigation.visit("b");
+        assert!(!navigation.can_go(Direction::Forward));
+        assert_eq!(navigation.go(Direction::Back, Some("d")).as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut navigation = Navigation::default();
+        for title in ["a", "b", "a", "c"] {
+            navigation.visit(title);
+        }
+        navigation.remove("b");
+        assert_eq!(navigation.go(Direction::Back, None).as_deref(), Some("c"));
+        assert_eq!(navigation.go(Direction::Back, None).as_deref(), Some("a"));
+        assert_eq!(navigation.go(Direction::Back, None), None);
+    }
+}
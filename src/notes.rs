@@ -1,7 +1,79 @@
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
 use std::path::PathBuf;
-use dirs::home_dir;
+use std::time::SystemTime;
+
+use chrono::NaiveDate;
+
+use crate::folders::FolderSettings;
+use crate::front_matter::FrontMatter;
+use crate::links;
+use crate::outline;
+use crate::search::{RelatedNote, SearchIndex};
+use crate::store;
+use crate::templates;
+use crate::title;
+use crate::vault::{self, Vault};
+
+/// The folder daily notes are kept in.
+pub const DAILY_FOLDER: &str = "daily";
+
+/// Metadata about a note file on disk.
+#[derive(Debug, Clone)]
+pub struct NoteMeta {
+    /// The title of the note.
+    pub title: String,
+    /// The last modification time of the note file.
+    pub modified: SystemTime,
+    /// The creation time of the note file, if the platform reports one.
+    pub created: Option<SystemTime>,
+}
+
+/// A note that `Notes::merge` merged into another, and where its body went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedNote {
+    /// The title of the note.
+    pub title: String,
+    /// The line its body started at, after its front matter.
+    pub body_line: usize,
+    /// The line of the merged note its body starts at.
+    pub merged_line: usize,
+}
+
+/// The order in which notes are listed.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoteSort {
+    /// Case-insensitive alphabetical order by title.
+    #[default]
+    Alphabetical,
+    /// Most recently modified notes first.
+    RecentlyModified,
+    /// Most recently created notes first.
+    RecentlyCreated,
+    /// A user-defined order, rearranged by dragging.
+    Manual,
+}
+
+impl NoteSort {
+    /// All sort orders, in the order they are offered in the UI.
+    pub const ALL: [NoteSort; 4] = [
+        NoteSort::Alphabetical,
+        NoteSort::RecentlyModified,
+        NoteSort::RecentlyCreated,
+        NoteSort::Manual,
+    ];
+
+    /// A human-readable label for the sort order.
+    pub fn label(self) -> &'static str {
+        match self {
+            NoteSort::Alphabetical => "Alphabetical",
+            NoteSort::RecentlyModified => "Recently modified",
+            NoteSort::RecentlyCreated => "Recently created",
+            NoteSort::Manual => "Manual",
+        }
+    }
+}
 
 /// Struct to manage notes.
 pub struct Notes {
@@ -30,198 +102,771 @@ impl Notes {
         self.items.push(note);
     }
 
-    /// Creates a new note file with the given title and content.
+    /// Sorts the items vector according to the given sort order.
+    ///
+    /// Modification and creation times are read from the notes directory.
     ///
     /// # Arguments
     ///
-    /// * `title` - The title of the note.
-    /// * `content` - The content of the note.
+    /// * `sort` - The sort order to apply.
+    /// * `manual_order` - The user-defined order of titles, used by `NoteSort::Manual`.
+    pub fn sort(&mut self, sort: NoteSort, manual_order: &[String]) {
+        let metas = match sort {
+            NoteSort::RecentlyModified | NoteSort::RecentlyCreated => {
+                Self::list_notes_with_meta().unwrap_or_default()
+            }
+            NoteSort::Alphabetical | NoteSort::Manual => Vec::new(),
+        };
+        sort_titles(&mut self.items, &metas, sort, manual_order);
+    }
+
+    /// Creates a new note, applying the default template and tags of the folder it is created in.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note, optionally prefixed by its folder (e.g. `meetings/standup`).
+    /// * `content` - The content of the note if its folder has no default template.
     ///
     /// # Returns
     ///
     /// An `io::Result<()>` indicating success or failure.
-    pub fn create_note_file(title: &str, content: &str) -> io::Result<()> {
-        let path = Self::get_notes_dir()?.join(format!("{}.txt", title));
-        let mut file = File::create(path)?;
-        file.write_all(content.as_bytes())?;
-        Ok(())
+    pub fn create_note(title: &str, content: &str) -> io::Result<()> {
+        let settings = FolderSettings::for_note(title)?;
+        Self::create_note_file(title, &settings.apply(title, content))
     }
 
-    /// Reads the content of a note file with the given title.
+    /// Appends text to a note as a new paragraph, creating the note if it doesn't exist.
     ///
     /// # Arguments
     ///
-    /// * `title` - The title of the note to be read.
+    /// * `title` - The title of the note, optionally prefixed by its folder.
+    /// * `text` - The text to append.
     ///
     /// # Returns
     ///
-    /// An `io::Result<String>` containing the content of the note or an error.
-    pub fn read_note_file(title: &str) -> io::Result<String> {
-        let path = Self::get_notes_dir()?.join(format!("{}.txt", title));
-        let mut file = File::open(path)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        Ok(content)
+    /// An `io::Result<bool>` containing `true` if the note was created.
+    pub fn append_to_note(title: &str, text: &str) -> io::Result<bool> {
+        if !Self::note_exists(title)? {
+            Self::create_note(title, text)?;
+            return Ok(true);
+        }
+        let mut content = Self::read_note_file(title)?;
+        if !content.is_empty() && !content.ends_with("\n\n") {
+            content.push_str(if content.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        content.push_str(text);
+        Self::update_note_file(title, &content)?;
+        Ok(false)
     }
 
-    /// Updates the content of an existing note file with the given title.
+    /// Copies a note under a new title in the same folder, e.g. `ideas (copy)`, or
+    /// `ideas (copy 2)` if that one is taken.
+    ///
+    /// The front matter is copied along with the body, except for the `slug` the original is
+    /// published under.
     ///
     /// # Arguments
     ///
-    /// * `title` - The title of the note to be updated.
-    /// * `new_content` - The new content for the note.
+    /// * `title` - The title of the note to copy.
     ///
     /// # Returns
     ///
-    /// An `io::Result<()>` indicating success or failure.
-    pub fn update_note_file(title: &str, new_content: &str) -> io::Result<()> {
-        let path = Self::get_notes_dir()?.join(format!("{}.txt", title));
-        let mut file = File::create(path)?;
-        file.write_all(new_content.as_bytes())?;
-        Ok(())
+    /// An `io::Result<String>` containing the title of the copy.
+    pub fn duplicate(title: &str) -> io::Result<String> {
+        let content = Self::read_note_file(title)?;
+        let (mut front_matter, body) = FrontMatter::parse(&content);
+        front_matter.remove("slug");
+        let store = store::active_store()?;
+        let copy = copy_title(title, |candidate| store.exists(candidate));
+        store.write(&copy, &front_matter.render(body))?;
+        Ok(copy)
     }
 
-    /// Deletes a note file with the given title.
+    /// Moves a note into a folder, keeping its name, e.g. `inbox/idea` into `projects` becomes
+    /// `projects/idea`.
     ///
     /// # Arguments
     ///
-    /// * `title` - The title of the note to be deleted.
+    /// * `title` - The title of the note to move.
+    /// * `folder` - The folder to move the note into, or `""` for the top level.
     ///
     /// # Returns
     ///
-    /// An `io::Result<()>` indicating success or failure.
-    pub fn delete_note_file(title: &str) -> io::Result<()> {
-        let path = Self::get_notes_dir()?.join(format!("{}.txt", title));
-        fs::remove_file(path)?;
-        Ok(())
+    /// An `io::Result<String>` containing the new title of the note, or an `AlreadyExists` error
+    /// if the folder already has a note with the same name.
+    pub fn move_to_folder(title: &str, folder: &str) -> io::Result<String> {
+        let moved = moved_title(title, folder);
+        if moved == title {
+            return Ok(moved);
+        }
+        let store = store::active_store()?;
+        if store.exists(&moved) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A note named {} already exists", moved),
+            ));
+        }
+        store.write(&moved, &store.read(title)?)?;
+        store.delete(title)?;
+        Ok(moved)
     }
 
-    /// Lists all note files in the `.notes` directory.
+    /// Merges notes into one, each under a heading with its title, in the given order.
+    ///
+    /// The merged note keeps the front matter of `target` if it is one of the notes, or else of
+    /// the first one, plus the tags of all of them. Links to the other notes are pointed at the
+    /// merged note, and the other notes are moved to the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `titles` - The notes to merge, at least two.
+    /// * `target` - The title of the merged note: one of `titles`, or a new note.
     ///
     /// # Returns
     ///
-    /// An `io::Result<Vec<String>>` containing the list of note titles or an error.
-    pub fn list_notes() -> io::Result<Vec<String>> {
-        let path = Self::get_notes_dir()?;
+    /// An `io::Result<Vec<MergedNote>>` describing where each note ended up, or an `AlreadyExists`
+    /// error if `target` is another existing note.
+    pub fn merge(titles: &[String], target: &str) -> io::Result<Vec<MergedNote>> {
+        if titles.len() < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Select at least two notes to merge"));
+        }
+        let store = store::active_store()?;
+        let target_merged = titles.iter().any(|title| title == target);
+        if !target_merged && store.exists(target) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A note named {} already exists", target),
+            ));
+        }
         let mut notes = Vec::new();
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(name) = path.file_stem() {
-                    if let Some(name_str) = name.to_str() {
-                        notes.push(name_str.to_string());
-                    }
+        for title in titles {
+            notes.push((title.clone(), store.read(title)?));
+        }
+        let (content, merged) = merge_contents(&notes, target);
+        store.write(target, &content)?;
+
+        let others: Vec<&String> = titles.iter().filter(|title| *title != target).collect();
+        for title in store.list()?.into_iter().map(|meta| meta.title) {
+            if titles.contains(&title) {
+                continue;
+            }
+            let original = store.read(&title)?;
+            let mut relinked = original.clone();
+            for other in &others {
+                if let Some(retargeted) = links::retarget(&relinked, other, target) {
+                    relinked = retargeted;
                 }
             }
+            if relinked != original {
+                store.write(&title, &relinked)?;
+            }
         }
-        Ok(notes)
+        for title in others {
+            Self::trash(title)?;
+        }
+        Ok(merged)
     }
 
-    /// Returns the path to the `.notes` directory, creating it if it doesn't exist.
+    /// Splits a note at its top-level headings: each section becomes a note in the same folder,
+    /// named after its heading, and is replaced by a link to it in the original.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note to split.
     ///
     /// # Returns
     ///
-    /// An `io::Result<PathBuf>` containing the path to the `.notes` directory or an error.
-    fn get_notes_dir() -> io::Result<PathBuf> {
-        let home = home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
-        let notes_dir = home.join(".notes");
-        if !notes_dir.exists() {
-            fs::create_dir_all(&notes_dir)?;
+    /// An `io::Result<Vec<(String, Range<usize>)>>` containing the new notes and the lines of the
+    /// original each one took, or an `InvalidInput` error if the note has no headings.
+    pub fn split(title: &str) -> io::Result<Vec<(String, Range<usize>)>> {
+        let store = store::active_store()?;
+        let content = store.read(title)?;
+        let (intro, sections) = outline::split_sections(&content);
+        if sections.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no headings to split at", title),
+            ));
+        }
+        let folder = title.rsplit_once('/').map_or("", |(folder, _)| folder);
+        let mut split = Vec::new();
+        let mut links = Vec::new();
+        for section in sections {
+            let taken = |candidate: &str| {
+                candidate == title
+                    || split.iter().any(|(new, _): &(String, Range<usize>)| new == candidate)
+                    || store.exists(candidate)
+            };
+            let new = section_title(folder, &section.heading, taken);
+            store.write(&new, &format!("{}\n", section.body))?;
+            links.push(format!("- [[{}]]", new));
+            split.push((new, section.lines));
+        }
+
+        let (front_matter, intro) = FrontMatter::parse(intro);
+        let mut body = intro.trim_end().to_string();
+        if !body.is_empty() {
+            body.push_str("\n\n");
+        }
+        body.push_str(&links.join("\n"));
+        body.push('\n');
+        store.write(title, &front_matter.render(&body))?;
+        Ok(split)
+    }
+
+    /// Creates a new note from a template in `~/.notes/.templates/`.
+    ///
+    /// Placeholders such as `{{title}}` and `{{date}}` are filled in, and the default tags of
+    /// the note's folder are added. The folder's default template is not used.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note, optionally prefixed by its folder.
+    /// * `template` - The name of the template.
+    /// * `vars` - Values for additional placeholders in the template.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn create_from_template(title: &str, template: &str, vars: &HashMap<String, String>) -> io::Result<()> {
+        let content = templates::render(&templates::read(template)?, title, vars);
+        let settings = FolderSettings::for_note(title)?;
+        Self::create_note_file(title, &settings.add_tags(content))
+    }
+
+    /// Creates a new note with the given title and content in the active store.
+    ///
+    /// See `NoteStore::write`.
+    pub fn create_note_file(title: &str, content: &str) -> io::Result<()> {
+        store::active_store()?.write(title, content)
+    }
+
+    /// Reads the content of a note with the given title from the active store.
+    ///
+    /// See `NoteStore::read`.
+    pub fn read_note_file(title: &str) -> io::Result<String> {
+        store::active_store()?.read(title)
+    }
+
+    /// Updates the content of a note with the given title in the active store.
+    ///
+    /// See `NoteStore::write`.
+    pub fn update_note_file(title: &str, new_content: &str) -> io::Result<()> {
+        store::active_store()?.write(title, new_content)
+    }
+
+    /// Deletes a note with the given title from the active store.
+    ///
+    /// See `NoteStore::delete`.
+    pub fn delete_note_file(title: &str) -> io::Result<()> {
+        store::active_store()?.delete(title)
+    }
+
+    /// Checks whether a note with the given title exists in the active store.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<bool>` containing `true` if the note exists.
+    pub fn note_exists(title: &str) -> io::Result<bool> {
+        Ok(store::active_store()?.exists(title))
+    }
+
+    /// Returns the timestamps of a note in the active store.
+    ///
+    /// See `NoteStore::metadata`.
+    pub fn note_meta(title: &str) -> io::Result<NoteMeta> {
+        store::active_store()?.metadata(title)
+    }
+
+    /// Moves a note from the active store into `.trash/` in the active vault, from where it can be
+    /// restored.
+    ///
+    /// See `Vault::trash_note`.
+    pub fn trash(title: &str) -> io::Result<()> {
+        Vault::active()?.trash_note(store::active_store()?.as_ref(), title)
+    }
+
+    /// Moves a deleted note back out of the trash of the active vault into the active store.
+    ///
+    /// See `Vault::restore_note`.
+    pub fn restore(title: &str) -> io::Result<()> {
+        Vault::active()?.restore_note(store::active_store()?.as_ref(), title)
+    }
+
+    /// Deletes the notes in the trash of the active vault for good.
+    ///
+    /// See `Vault::empty_trash`.
+    pub fn empty_trash() -> io::Result<usize> {
+        Vault::active()?.empty_trash()
+    }
+
+    /// Lists the titles of all notes in the trash of the active vault.
+    pub fn list_trashed() -> io::Result<Vec<String>> {
+        Vault::active()?.trash()?.list_notes()
+    }
+
+    /// Reads the content of a note in the trash of the active vault.
+    pub fn read_trashed(title: &str) -> io::Result<String> {
+        Vault::active()?.trash()?.read_note_file(title)
+    }
+
+    /// Moves a note into `.archive/` in the active vault, hiding it from the note list and search.
+    ///
+    /// See `Vault::archive_note`.
+    pub fn archive(title: &str) -> io::Result<()> {
+        Vault::active()?.archive_note(store::active_store()?.as_ref(), title)
+    }
+
+    /// Marks a note read-only, or editable again, see `FrontMatter::set_locked`.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<bool>` containing whether the note changed, which it doesn't if it already was
+    /// as asked.
+    pub fn set_locked(title: &str, locked: bool) -> io::Result<bool> {
+        let content = Self::read_note_file(title)?;
+        let (mut front_matter, body) = FrontMatter::parse(&content);
+        if front_matter.is_locked() == locked {
+            return Ok(false);
+        }
+        front_matter.set_locked(locked);
+        Self::update_note_file(title, &front_matter.render(body))?;
+        Ok(true)
+    }
+
+    /// Moves an archived note back into the active vault.
+    ///
+    /// See `Vault::unarchive_note`.
+    pub fn unarchive(title: &str) -> io::Result<()> {
+        Vault::active()?.unarchive_note(store::active_store()?.as_ref(), title)
+    }
+
+    /// Finds the notes most like a note, by the tags and words they share.
+    ///
+    /// The search index is brought up to date first. See `SearchIndex::related`.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    /// * `limit` - The most notes to return.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vec<RelatedNote>>` containing the related notes, most similar first.
+    pub fn related(title: &str, limit: usize) -> io::Result<Vec<RelatedNote>> {
+        let mut index = SearchIndex::load()?;
+        index.refresh()?;
+        index.save()?;
+        Ok(index.related(title, limit))
+    }
+
+    /// Lists the archived notes in the active vault.
+    ///
+    /// See `Vault::list_notes`.
+    pub fn list_archived() -> io::Result<Vec<String>> {
+        Vault::active()?.archive()?.list_notes()
+    }
+
+    /// Reads the content of an archived note in the active vault.
+    ///
+    /// See `Vault::read_note_file`.
+    pub fn read_archived(title: &str) -> io::Result<String> {
+        Vault::active()?.archive()?.read_note_file(title)
+    }
+
+    /// Lists the titles of all notes in the active store.
+    ///
+    /// See `NoteStore::list`.
+    pub fn list_notes() -> io::Result<Vec<String>> {
+        let notes = Self::list_notes_with_meta()?;
+        Ok(notes.into_iter().map(|note| note.title).collect())
+    }
+
+    /// Lists all notes in the active store along with their timestamps.
+    ///
+    /// See `NoteStore::list`.
+    pub fn list_notes_with_meta() -> io::Result<Vec<NoteMeta>> {
+        store::active_store()?.list()
+    }
+
+    /// Returns the path of the file that stores a note in the active vault.
+    ///
+    /// See `Vault::note_path`.
+    pub fn note_path(title: &str) -> io::Result<PathBuf> {
+        Vault::active()?.note_path(title)
+    }
+
+    /// Returns the notes directory of the active vault, creating it if it doesn't exist.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<PathBuf>` containing the path to the notes directory or an error.
+    pub(crate) fn get_notes_dir() -> io::Result<PathBuf> {
+        vault::active_root()
+    }
+}
+
+/// Returns the title of the daily note for a date, e.g. `daily/2024-05-01`.
+///
+/// # Arguments
+///
+/// * `date` - The day of the note.
+pub fn daily_title(date: NaiveDate) -> String {
+    format!("{}/{}", DAILY_FOLDER, date.format("%Y-%m-%d"))
+}
+
+/// Joins the content of notes for `Notes::merge`.
+///
+/// # Arguments
+///
+/// * `notes` - The titles and content of the notes, in order.
+/// * `target` - The title of the merged note, whose front matter is kept if it is one of `notes`.
+///
+/// # Returns
+///
+/// The merged content and where the body of each note went.
+fn merge_contents(notes: &[(String, String)], target: &str) -> (String, Vec<MergedNote>) {
+    let parsed: Vec<(&String, FrontMatter, &str)> = notes
+        .iter()
+        .map(|(title, content)| {
+            let (front_matter, body) = FrontMatter::parse(content);
+            (title, front_matter, body)
+        })
+        .collect();
+    let base = parsed.iter().position(|(title, ..)| *title == target).unwrap_or(0);
+    let mut front_matter = parsed.get(base).map(|(_, front_matter, _)| front_matter.clone()).unwrap_or_default();
+    for (_, note_front_matter, _) in &parsed {
+        front_matter.add_tags(&note_front_matter.tags());
+    }
+
+    let front_matter_lines = front_matter.render("").matches('\n').count();
+    let mut body = String::new();
+    let mut merged = Vec::new();
+    for ((title, content), (_, _, note_body)) in notes.iter().zip(&parsed) {
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!("## {}\n\n", title));
+        merged.push(MergedNote {
+            title: title.clone(),
+            body_line: content[..content.len() - note_body.len()].matches('\n').count(),
+            merged_line: front_matter_lines + body.matches('\n').count(),
+        });
+        body.push_str(note_body.trim_end());
+        body.push('\n');
+    }
+    (front_matter.render(&body), merged)
+}
+
+/// Returns a free title for a section split off a note, e.g. `work/Goals` or `work/Goals (2)`.
+///
+/// # Arguments
+///
+/// * `folder` - The folder of the note, or `""` for the top level.
+/// * `heading` - The heading of the section. Slashes are replaced, since they would make folders.
+/// * `taken` - Whether a title is already used.
+fn section_title(folder: &str, heading: &str, taken: impl Fn(&str) -> bool) -> String {
+    let name = heading.replace('/', "-");
+    let name = if title::validate(&name).is_ok() { name } else { "Section".to_string() };
+    let title = moved_title(&name, folder);
+    let mut number = 1;
+    loop {
+        let candidate = if number == 1 {
+            title.clone()
+        } else {
+            format!("{} ({})", title, number)
+        };
+        if !taken(&candidate) {
+            return candidate;
+        }
+        number += 1;
+    }
+}
+
+/// Returns a free title for the copy of a note, e.g. `work/ideas (copy)`.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note being copied.
+/// * `taken` - Whether a title is already used.
+fn copy_title(title: &str, taken: impl Fn(&str) -> bool) -> String {
+    let mut number = 1;
+    loop {
+        let candidate = if number == 1 {
+            format!("{} (copy)", title)
+        } else {
+            format!("{} (copy {})", title, number)
+        };
+        if !taken(&candidate) {
+            return candidate;
+        }
+        number += 1;
+    }
+}
+
+/// Returns the title a note gets when it is moved into a folder.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `folder` - The folder, or `""` for the top level.
+pub fn moved_title(title: &str, folder: &str) -> String {
+    let name = title.rsplit('/').next().unwrap_or(title);
+    match folder.trim().trim_matches('/') {
+        "" => name.to_string(),
+        folder => format!("{}/{}", folder, name),
+    }
+}
+
+/// Sorts note titles according to the given sort order.
+///
+/// Titles without metadata are placed after those with metadata when sorting by time,
+/// and titles missing from `manual_order` are placed last when sorting manually.
+///
+/// # Arguments
+///
+/// * `titles` - The titles to sort in place.
+/// * `metas` - Metadata for the titles, used by the time-based sort orders.
+/// * `sort` - The sort order to apply.
+/// * `manual_order` - The user-defined order of titles, used by `NoteSort::Manual`.
+pub fn sort_titles(titles: &mut [String], metas: &[NoteMeta], sort: NoteSort, manual_order: &[String]) {
+    titles.sort_by_key(|title| title.to_lowercase());
+    match sort {
+        NoteSort::Alphabetical => {}
+        NoteSort::RecentlyModified => {
+            let time = |title: &String| metas.iter().find(|meta| &meta.title == title).map(|meta| meta.modified);
+            titles.sort_by(|a, b| time(b).cmp(&time(a)));
+        }
+        NoteSort::RecentlyCreated => {
+            let time = |title: &String| {
+                metas
+                    .iter()
+                    .find(|meta| &meta.title == title)
+                    .map(|meta| meta.created.unwrap_or(meta.modified))
+            };
+            titles.sort_by(|a, b| time(b).cmp(&time(a)));
+        }
+        NoteSort::Manual => {
+            let position = |title: &String| {
+                manual_order.iter().position(|ordered| ordered == title).unwrap_or(usize::MAX)
+            };
+            titles.sort_by_key(position);
         }
-        Ok(notes_dir)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
-    use tempfile::tempdir;
+    use std::fs;
+    use tempfile::{tempdir, TempDir};
 
-    fn setup_temp_notes_dir() -> PathBuf {
+    fn setup_temp_vault() -> (TempDir, Vault) {
         let temp_dir = tempdir().unwrap();
-        let temp_notes_dir = temp_dir.path().join(".notes");
-        fs::create_dir_all(&temp_notes_dir).unwrap();
-        temp_notes_dir
+        let vault = Vault::at(temp_dir.path().join(".notes")).unwrap();
+        (temp_dir, vault)
     }
 
     #[test]
     fn test_create_note_file() {
-        let temp_notes_dir = setup_temp_notes_dir();
-        env::set_var("HOME", temp_notes_dir.parent().unwrap());
+        let (_temp_dir, vault) = setup_temp_vault();
 
         let title = "test_note";
         let content = "This is a test note.";
-        Notes::create_note_file(title, content).unwrap();
+        vault.create_note_file(title, content).unwrap();
 
-        let note_path = temp_notes_dir.join(format!("{}.txt", title));
+        let note_path = vault.root().join(format!("{}.md", title));
         assert!(note_path.exists());
-
-        let mut file = File::open(note_path).unwrap();
-        let mut file_content = String::new();
-        file.read_to_string(&mut file_content).unwrap();
-        assert_eq!(file_content, content);
+        assert_eq!(fs::read_to_string(note_path).unwrap(), content);
     }
 
     #[test]
     fn test_read_note_file() {
-        let temp_notes_dir = setup_temp_notes_dir();
-        env::set_var("HOME", temp_notes_dir.parent().unwrap());
+        let (_temp_dir, vault) = setup_temp_vault();
 
         let title = "test_note";
         let content = "This is a test note.";
-        Notes::create_note_file(title, content).unwrap();
+        vault.create_note_file(title, content).unwrap();
 
-        let read_content = Notes::read_note_file(title).unwrap();
+        let read_content = vault.read_note_file(title).unwrap();
         assert_eq!(read_content, content);
     }
 
     #[test]
     fn test_update_note_file() {
-        let temp_notes_dir = setup_temp_notes_dir();
-        env::set_var("HOME", temp_notes_dir.parent().unwrap());
+        let (_temp_dir, vault) = setup_temp_vault();
 
         let title = "test_note";
         let content = "This is a test note.";
         let new_content = "This is updated content.";
-        Notes::create_note_file(title, content).unwrap();
-        Notes::update_note_file(title, new_content).unwrap();
+        vault.create_note_file(title, content).unwrap();
+        vault.update_note_file(title, new_content).unwrap();
 
-        let read_content = Notes::read_note_file(title).unwrap();
+        let read_content = vault.read_note_file(title).unwrap();
         assert_eq!(read_content, new_content);
     }
 
     #[test]
     fn test_delete_note_file() {
-        let temp_notes_dir = setup_temp_notes_dir();
-        env::set_var("HOME", temp_notes_dir.parent().unwrap());
+        let (_temp_dir, vault) = setup_temp_vault();
 
         let title = "test_note";
         let content = "This is a test note.";
-        Notes::create_note_file(title, content).unwrap();
+        vault.create_note_file(title, content).unwrap();
 
-        let note_path = temp_notes_dir.join(format!("{}.txt", title));
+        let note_path = vault.root().join(format!("{}.md", title));
         assert!(note_path.exists());
 
-        Notes::delete_note_file(title).unwrap();
+        vault.delete_note_file(title).unwrap();
         assert!(!note_path.exists());
     }
 
+    #[test]
+    fn test_invalid_titles_are_rejected() {
+        let (temp_dir, vault) = setup_temp_vault();
+
+        for title in ["../outside", "/etc/passwd", "a//b", "line\nbreak"] {
+            let err = vault.create_note_file(title, "content").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(!temp_dir.path().join("outside.md").exists());
+    }
+
+    #[test]
+    fn test_titles_are_escaped() {
+        let (_temp_dir, vault) = setup_temp_vault();
+
+        vault.create_note_file("work/CON", "device").unwrap();
+        vault.create_note_file("what? <now>", "reserved").unwrap();
+        fs::write(vault.root().join("50% done.txt"), "legacy").unwrap();
+        assert!(vault.root().join("work").join("CO%4E.md").exists());
+        assert!(vault.root().join("what%3F %3Cnow%3E.md").exists());
+
+        let mut titles = vault.list_notes().unwrap();
+        titles.sort();
+        assert_eq!(titles, vec!["50% done", "what? <now>", "work/CON"]);
+        assert_eq!(vault.read_note_file("50% done").unwrap(), "legacy");
+        assert_eq!(vault.read_note_file("what? <now>").unwrap(), "reserved");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_file_names_are_renamed() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let (_temp_dir, vault) = setup_temp_vault();
+
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        if fs::write(vault.root().join(name), "latin-1").is_err() {
+            // Some file systems only accept UTF-8 names
+            return;
+        }
+        assert_eq!(vault.list_notes().unwrap(), vec!["caf\u{fffd}"]);
+        assert_eq!(vault.read_note_file("caf\u{fffd}").unwrap(), "latin-1");
+        assert!(!vault.root().join(name).exists());
+    }
+
     #[test]
     fn test_list_notes() {
-        let temp_notes_dir = setup_temp_notes_dir();
-        env::set_var("HOME", temp_notes_dir.parent().unwrap());
+        let (_temp_dir, vault) = setup_temp_vault();
 
         let titles = vec!["note1", "note2", "note3"];
         for title in &titles {
-            Notes::create_note_file(title, "content").unwrap();
+            vault.create_note_file(title, "content").unwrap();
         }
 
-        let listed_notes = Notes::list_notes().unwrap();
+        let listed_notes = vault.list_notes().unwrap();
         assert_eq!(listed_notes.len(), titles.len());
         for title in &titles {
             assert!(listed_notes.contains(&title.to_string()));
         }
     }
+
+    fn meta(title: &str, modified: u64, created: Option<u64>) -> NoteMeta {
+        let at = |secs| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        NoteMeta {
+            title: title.to_string(),
+            modified: at(modified),
+            created: created.map(at),
+        }
+    }
+
+    #[test]
+    fn test_merge_contents() {
+        let notes = vec![
+            ("inbox".to_string(), "---\ntags: todo\n---\nCall Bob\n".to_string()),
+            ("ideas".to_string(), "---\ntags: work\nslug: ideas\n---\nA better mouse trap".to_string()),
+        ];
+        let (content, merged) = merge_contents(&notes, "ideas");
+        assert_eq!(
+            content,
+            "---\ntags: work, todo\nslug: ideas\n---\n## inbox\n\nCall Bob\n\n## ideas\n\nA better mouse trap\n"
+        );
+        assert_eq!(content.lines().nth(merged[0].merged_line), Some("Call Bob"));
+        assert_eq!(content.lines().nth(merged[1].merged_line), Some("A better mouse trap"));
+        assert_eq!(merged[1].body_line, 4);
+
+        let (content, _) = merge_contents(&notes, "all");
+        assert!(content.starts_with("---\ntags: todo, work\n---\n## inbox"));
+    }
+
+    #[test]
+    fn test_section_title() {
+        assert_eq!(section_title("work", "Goals", |_| false), "work/Goals");
+        assert_eq!(section_title("", "Q1/Q2", |_| false), "Q1-Q2");
+        assert_eq!(section_title("", "..", |_| false), "Section");
+        assert_eq!(section_title("work", "Goals", |title| title == "work/Goals"), "work/Goals (2)");
+    }
+
+    #[test]
+    fn test_copy_title() {
+        assert_eq!(copy_title("work/ideas", |_| false), "work/ideas (copy)");
+        let taken = ["ideas (copy)", "ideas (copy 2)"];
+        assert_eq!(copy_title("ideas", |title| taken.contains(&title)), "ideas (copy 3)");
+    }
+
+    #[test]
+    fn test_moved_title() {
+        assert_eq!(moved_title("inbox/idea", "projects/2024/"), "projects/2024/idea");
+        assert_eq!(moved_title("inbox/idea", ""), "idea");
+        assert_eq!(moved_title("idea", " archive "), "archive/idea");
+    }
+
+    #[test]
+    fn test_daily_title() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(daily_title(date), "daily/2024-05-01");
+    }
+
+    #[test]
+    fn test_sort_titles_alphabetical() {
+        let mut titles = vec!["banana".to_string(), "Apple".to_string(), "cherry".to_string()];
+        sort_titles(&mut titles, &[], NoteSort::Alphabetical, &[]);
+        assert_eq!(titles, vec!["Apple", "banana", "cherry"]);
+    }
+
+    #[test]
+    fn test_sort_titles_by_time() {
+        let metas = vec![meta("old", 10, Some(30)), meta("new", 20, Some(5)), meta("mid", 15, None)];
+        let mut titles = vec!["old".to_string(), "new".to_string(), "mid".to_string()];
+
+        sort_titles(&mut titles, &metas, NoteSort::RecentlyModified, &[]);
+        assert_eq!(titles, vec!["new", "mid", "old"]);
+
+        sort_titles(&mut titles, &metas, NoteSort::RecentlyCreated, &[]);
+        assert_eq!(titles, vec!["old", "mid", "new"]);
+    }
+
+    #[test]
+    fn test_sort_titles_manual() {
+        let manual_order = vec!["c".to_string(), "a".to_string()];
+        let mut titles = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        sort_titles(&mut titles, &[], NoteSort::Manual, &manual_order);
+        assert_eq!(titles, vec!["c", "a", "b"]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+//! Reads the text in attached images with the `tesseract` command, so that search finds it.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::attachments;
+use crate::storage::atomic_write;
+
+/// Reads the text in an image.
+///
+/// # Arguments
+///
+/// * `path` - The image file.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the text, or a `NotFound` error if `tesseract` is not installed.
+pub fn recognize(path: &Path) -> io::Result<String> {
+    let output = Command::new("tesseract")
+        .arg(path)
+        .arg("stdout")
+        .output()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => io::Error::new(io::ErrorKind::NotFound, "tesseract is not installed"),
+            _ => err,
+        })?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::new(io::ErrorKind::Other, message));
+    }
+    Ok(clean(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Reads the text in attached images and stores it next to them, see `attachments::text_path`.
+///
+/// Attachments are named after their content, so images that were read before are skipped.
+///
+/// # Arguments
+///
+/// * `relative_paths` - The attachments, as paths relative to the `.notes` directory. Those that
+///   are not images are skipped.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing how many images were read.
+pub fn index(relative_paths: &[String]) -> io::Result<usize> {
+    let mut read = 0;
+    for relative_path in relative_paths.iter().filter(|path| attachments::is_image(path)) {
+        let text_path = attachments::text_path(relative_path)?;
+        if text_path.exists() {
+            continue;
+        }
+        let text = recognize(&attachments::resolve(relative_path)?)?;
+        if let Some(parent) = text_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic_write(&text_path, text.as_bytes(), false)?;
+        read += 1;
+    }
+    Ok(read)
+}
+
+/// Tidies up recognized text: drops the page break `tesseract` ends with, trailing whitespace and
+/// runs of blank lines.
+pub fn clean(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(|line| line.trim_end_matches(|c: char| c.is_whitespace() || c == '\u{c}')) {
+        if line.is_empty() && lines.last().map_or(true, |last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(clean("\nTotal: 12.50  \n\n\n\nThank you\n\u{c}"), "Total: 12.50\n\nThank you");
+        assert_eq!(clean("\u{c}"), "");
+    }
+}
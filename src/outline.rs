@@ -0,0 +1,205 @@
+use std::ops::Range;
+
+use crate::front_matter::FrontMatter;
+
+/// A Markdown heading in a note, e.g. `## Goals`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// The number of `#`s, from 1 to 6.
+    pub level: usize,
+    /// The text of the heading.
+    pub text: String,
+    /// The line of the heading, counted from 0.
+    pub line: usize,
+}
+
+/// Finds the headings of a note, skipping its front matter and code blocks.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+///
+/// # Returns
+///
+/// The headings in the order they appear.
+pub fn headings(content: &str) -> Vec<Heading> {
+    let (_, body) = FrontMatter::parse(content);
+    let skipped = content[..content.len() - body.len()].lines().count();
+    let mut headings = Vec::new();
+    let mut in_code = false;
+    for (line, text) in body.lines().enumerate() {
+        let trimmed = text.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        // Four spaces of indentation make a code block rather than a heading
+        if in_code || text.len() - trimmed.len() > 3 {
+            continue;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        let rest = &trimmed[level..];
+        if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+            continue;
+        }
+        let text = rest.trim().trim_end_matches('#').trim_end();
+        if !text.is_empty() {
+            headings.push(Heading {
+                level,
+                text: text.to_string(),
+                line: skipped + line,
+            });
+        }
+    }
+    headings
+}
+
+/// Returns the index of the first heading after the section of a heading, which is the next
+/// heading of the same or a higher level, or `headings.len()` if the section runs to the end.
+///
+/// # Arguments
+///
+/// * `headings` - The headings of a note.
+/// * `index` - The index of the heading whose section to find the end of.
+pub fn section_end(headings: &[Heading], index: usize) -> usize {
+    let level = headings[index].level;
+    headings[index + 1..]
+        .iter()
+        .position(|heading| heading.level <= level)
+        .map_or(headings.len(), |offset| index + 1 + offset)
+}
+
+/// A section of a note under one of its top-level headings, see `split_sections`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The text of the heading.
+    pub heading: String,
+    /// The lines of the section after the heading and the blank lines following it.
+    pub lines: Range<usize>,
+    /// The text of those lines, without trailing blank lines.
+    pub body: String,
+}
+
+/// Splits a note at its top-level headings, which are those of the highest level it uses.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+///
+/// # Returns
+///
+/// The content before the first heading, including the front matter, and the sections in order.
+/// There are no sections if the note has no headings.
+pub fn split_sections(content: &str) -> (&str, Vec<Section>) {
+    let headings = headings(content);
+    let Some(top) = headings.iter().map(|heading| heading.level).min() else {
+        return (content, Vec::new());
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let tops: Vec<&Heading> = headings.iter().filter(|heading| heading.level == top).collect();
+    let mut sections = Vec::new();
+    for (index, heading) in tops.iter().enumerate() {
+        let end = tops.get(index + 1).map_or(lines.len(), |next| next.line);
+        let start = (heading.line + 1..end).find(|line| !lines[*line].trim().is_empty()).unwrap_or(end);
+        sections.push(Section {
+            heading: heading.text.clone(),
+            lines: start..end,
+            body: lines[start..end].join("\n").trim_end().to_string(),
+        });
+    }
+    let intro_len: usize = content.split_inclusive('\n').take(tops[0].line).map(str::len).sum();
+    (&content[..intro_len], sections)
+}
+
+/// Moves the section of a heading, including its subsections, in front of another heading.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `from` - The index of the heading to move.
+/// * `to` - The index of the heading to move it in front of, or the number of headings to
+///   move it to the end of the note.
+///
+/// # Returns
+///
+/// The rewritten note, or `None` if the section would stay where it is, e.g. when it is moved
+/// into itself.
+pub fn move_section(content: &str, from: usize, to: usize) -> Option<String> {
+    let headings = headings(content);
+    if from >= headings.len() || to > headings.len() {
+        return None;
+    }
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    let trailing_newline = content.ends_with('\n');
+    if trailing_newline {
+        lines.pop();
+    }
+    let line_of = |index: usize| headings.get(index).map_or(lines.len(), |heading| heading.line);
+    let start = line_of(from);
+    let end = line_of(section_end(&headings, from));
+    let target = line_of(to);
+    if (start..=end).contains(&target) {
+        return None;
+    }
+
+    let section: Vec<&str> = lines.drain(start..end).collect();
+    let target = if target > start { target - section.len() } else { target };
+    lines.splice(target..target, section);
+    let mut moved = lines.join("\n");
+    if trailing_newline {
+        moved.push('\n');
+    }
+    Some(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headings() {
+        let content = "---\ntags: a\n---\n# Plan\n\n```\n# not a heading\n```\n##Nope\n## Goals ##\n    # Code\n### Details";
+        let found: Vec<(usize, &str, usize)> = headings(content)
+            .iter()
+            .map(|heading| (heading.level, heading.text.as_str(), heading.line))
+            .collect();
+        assert_eq!(found, vec![(1, "Plan", 3), (2, "Goals", 9), (3, "Details", 11)]);
+    }
+
+    #[test]
+    fn test_split_sections() {
+        let content = "---\ntags: a\n---\nInbox\n## A\n\na\n### A1\na1\n\n## B\n## C\nc";
+        let (intro, sections) = split_sections(content);
+        assert_eq!(intro, "---\ntags: a\n---\nInbox\n");
+        let found: Vec<(&str, Range<usize>, &str)> = sections
+            .iter()
+            .map(|section| (section.heading.as_str(), section.lines.clone(), section.body.as_str()))
+            .collect();
+        assert_eq!(found, vec![("A", 6..10, "a\n### A1\na1"), ("B", 11..11, ""), ("C", 12..13, "c")]);
+
+        let (intro, sections) = split_sections("No headings");
+        assert_eq!(intro, "No headings");
+        assert!(sections.is_empty());
+    }
+
+    #[test]
+    fn test_move_section() {
+        let content = "Intro\n# A\na\n## A1\na1\n# B\nb\n";
+        let found = headings(content);
+        assert_eq!(section_end(&found, 0), 2);
+        assert_eq!(section_end(&found, 1), 2);
+
+        assert_eq!(
+            move_section(content, 2, 0).unwrap(),
+            "Intro\n# B\nb\n# A\na\n## A1\na1\n"
+        );
+        assert_eq!(
+            move_section(content, 0, 3).unwrap(),
+            "Intro\n# B\nb\n# A\na\n## A1\na1\n"
+        );
+        assert_eq!(move_section(content, 1, 3).unwrap(), "Intro\n# A\na\n# B\nb\n## A1\na1\n");
+        assert_eq!(move_section(content, 0, 1), None);
+        assert_eq!(move_section(content, 0, 2), None);
+        assert_eq!(move_section(content, 5, 0), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+
+/// The name of the file in the `.notes` directory that records published slugs.
+pub const PERMALINKS_FILE: &str = ".permalinks.json";
+
+/// The slugs under which notes are published, persisted in the `.permalinks.json` file.
+///
+/// The slug of a note is stored in its `slug` front matter field, so it follows the note
+/// when it is renamed. This file remembers the slugs that were handed out, so that a slug
+/// edited by hand turns into a redirect from the old URL.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Permalinks {
+    /// The current slug of every note, by note title.
+    pub slugs: BTreeMap<String, String>,
+    /// Slugs that are no longer used, mapped to the slug that replaced them.
+    pub redirects: BTreeMap<String, String>,
+}
+
+impl Permalinks {
+    /// Loads the recorded slugs from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Permalinks>` containing the slugs, which are empty if none were recorded yet.
+    pub fn load() -> io::Result<Permalinks> {
+        let path = Self::get_permalinks_file_path()?;
+        if !path.exists() {
+            return Ok(Permalinks::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Saves the recorded slugs to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&Self::get_permalinks_file_path()?, data.as_bytes(), true)
+    }
+
+    /// Gives every note a slug and records redirects for slugs that changed.
+    ///
+    /// Notes without a `slug` field get one derived from their title, made unique among
+    /// all notes, and written to their front matter.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<usize>` containing the number of notes that were given a new slug.
+    pub fn update(&mut self) -> io::Result<usize> {
+        let mut notes = Vec::new();
+        for title in Notes::list_notes()? {
+            let content = Notes::read_note_file(&title)?;
+            let (front_matter, _) = FrontMatter::parse(&content);
+            let slug = front_matter.get("slug").map(slugify).filter(|slug| !slug.is_empty());
+            notes.push((title, content, slug));
+        }
+        notes.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        let mut taken: HashSet<String> = notes.iter().filter_map(|(_, _, slug)| slug.clone()).collect();
+        let mut slugs = BTreeMap::new();
+        let mut assigned = 0;
+        for (title, content, slug) in notes {
+            let slug = match slug {
+                Some(slug) => slug,
+                None => {
+                    // Reuse the recorded slug if the field was removed, so the URL stays put.
+                    let slug = match self.slugs.get(&title) {
+                        Some(slug) if !taken.contains(slug) => slug.clone(),
+                        _ => unique_slug(&slugify(&title), &taken),
+                    };
+                    let (mut front_matter, body) = FrontMatter::parse(&content);
+                    front_matter.set("slug", &slug);
+                    Notes::update_note_file(&title, &front_matter.render(body))?;
+                    taken.insert(slug.clone());
+                    assigned += 1;
+                    slug
+                }
+            };
+            slugs.insert(title, slug);
+        }
+
+        let current: HashSet<&String> = slugs.values().collect();
+        for (title, old_slug) in &self.slugs {
+            let new_slug = match slugs.get(title) {
+                Some(new_slug) if new_slug != old_slug => new_slug,
+                _ => continue,
+            };
+            if !current.contains(old_slug) {
+                self.redirects.insert(old_slug.clone(), new_slug.clone());
+            }
+        }
+        // Point older redirects at the latest slug, and drop those whose slug is in use again.
+        self.redirects.retain(|old_slug, _| !current.contains(old_slug));
+        let renamed = self.redirects.clone();
+        for target in self.redirects.values_mut() {
+            let mut seen = HashSet::new();
+            while let Some(next) = renamed.get(target.as_str()) {
+                if !seen.insert(next.clone()) {
+                    break;
+                }
+                *target = next.clone();
+            }
+        }
+
+        self.slugs = slugs;
+        Ok(assigned)
+    }
+
+    /// Renders the redirects in the `_redirects` format understood by static hosts such as Netlify.
+    pub fn redirects_file(&self) -> String {
+        self.redirects
+            .iter()
+            .map(|(from, to)| format!("/{}/ /{}/ 301\n", from, to))
+            .collect()
+    }
+
+    /// Writes the redirects to a `_redirects` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where to write the file.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn write_redirects(&self, path: &Path) -> io::Result<()> {
+        atomic_write(path, self.redirects_file().as_bytes(), true)
+    }
+
+    fn get_permalinks_file_path() -> io::Result<PathBuf> {
+        Ok(Notes::get_notes_dir()?.join(PERMALINKS_FILE))
+    }
+}
+
+/// Turns a note title into a URL-safe slug, keeping folders as path segments.
+///
+/// Letters are lowercased and common accents are dropped; any run of other characters
+/// becomes a single `-`.
+pub fn slugify(title: &str) -> String {
+    title
+        .split('/')
+        .map(slugify_segment)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn slugify_segment(segment: &str) -> String {
+    let mut slug = String::new();
+    let mut separate = false;
+    for c in segment.chars().flat_map(char::to_lowercase) {
+        let folded = match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+            'æ' => "ae",
+            'ç' => "c",
+            'è' | 'é' | 'ê' | 'ë' => "e",
+            'ì' | 'í' | 'î' | 'ï' => "i",
+            'ñ' => "n",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o",
+            'œ' => "oe",
+            'ß' => "ss",
+            'ù' | 'ú' | 'û' | 'ü' => "u",
+            'ý' | 'ÿ' => "y",
+            c if c.is_ascii_alphanumeric() => {
+                if separate && !slug.is_empty() {
+                    slug.push('-');
+                }
+                separate = false;
+                slug.push(c);
+                continue;
+            }
+            _ => {
+                separate = true;
+                continue;
+            }
+        };
+        if separate && !slug.is_empty() {
+            slug.push('-');
+        }
+        separate = false;
+        slug.push_str(folded);
+    }
+    slug
+}
+
+/// Appends `-2`, `-3`, ... to a slug until it is not taken.
+fn unique_slug(slug: &str, taken: &HashSet<String>) -> String {
+    let slug = if slug.is_empty() { "note" } else { slug };
+    if !taken.contains(slug) {
+        return slug.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", slug, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Meetings/Stand-up: Q3 Plans!"), "meetings/stand-up-q3-plans");
+        assert_eq!(slugify("Café Crème"), "cafe-creme");
+        assert_eq!(slugify("  ???  "), "");
+    }
+
+    #[test]
+    fn test_unique_slug() {
+        let taken: HashSet<String> = ["plans".to_string(), "plans-2".to_string()].into();
+        assert_eq!(unique_slug("plans", &taken), "plans-3");
+        assert_eq!(unique_slug("", &taken), "note");
+    }
+
+    #[test]
+    fn test_update_assigns_slugs_and_redirects() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("My Plans", "Body").unwrap();
+        let mut permalinks = Permalinks::default();
+        assert_eq!(permalinks.update().unwrap(), 1);
+        assert_eq!(Notes::read_note_file("My Plans").unwrap(), "---\nslug: my-plans\n---\nBody");
+        assert_eq!(permalinks.slugs["My Plans"], "my-plans");
+
+        // Renaming the note keeps its slug.
+        fs::rename(temp_notes_dir.join("My Plans.md"), temp_notes_dir.join("Plans.md")).unwrap();
+        assert_eq!(permalinks.update().unwrap(), 0);
+        assert_eq!(permalinks.slugs["Plans"], "my-plans");
+        assert!(permalinks.redirects.is_empty());
+
+        // Changing the slug redirects the old URL.
+        Notes::update_note_file("Plans", "---\nslug: plans\n---\nBody").unwrap();
+        permalinks.update().unwrap();
+        assert_eq!(permalinks.redirects_file(), "/my-plans/ /plans/ 301\n");
+    }
+}
@@ -0,0 +1,322 @@
+use eframe::egui::{self, RichText};
+
+use crate::attachments;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::audio::{self, PlaybackState};
+use crate::commands::expand_home;
+use crate::front_matter::FrontMatter;
+use crate::highlight::{self, EditorTheme};
+
+/// A block of a note as shown in the preview.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block<'a> {
+    /// A `#` heading, with its level from 1 to 6.
+    Heading { level: usize, text: &'a str },
+    /// An image on a line of its own, `![alt](target)`.
+    Image { alt: &'a str, target: &'a str },
+    /// A link to an audio attachment on a line of its own, `[name](attachments/memo.wav)`.
+    Audio { name: &'a str, target: &'a str },
+    /// A fenced code block.
+    Code { language: &'a str, code: String },
+    /// A line of text with images in it.
+    Inline(Vec<Inline<'a>>),
+    /// Any other line of text.
+    Text(&'a str),
+    /// An empty line.
+    Blank,
+}
+
+/// A piece of a line of text that contains images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inline<'a> {
+    /// Text between images.
+    Text(&'a str),
+    /// An `![alt](target)` image.
+    Image { alt: &'a str, target: &'a str },
+}
+
+/// Splits a note into the blocks of its preview. Front matter is left out.
+pub fn blocks(content: &str) -> Vec<Block<'_>> {
+    let (_, body) = FrontMatter::parse(content);
+    let mut blocks = Vec::new();
+    let mut lines = body.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if let Some(info) = trimmed.strip_prefix("```") {
+            let mut code = String::new();
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+                code.push_str(line);
+                code.push('\n');
+            }
+            blocks.push(Block::Code {
+                language: info.split_whitespace().next().unwrap_or(""),
+                code,
+            });
+        } else if trimmed.is_empty() {
+            blocks.push(Block::Blank);
+        } else if let Some((alt, target)) = parse_image(trimmed) {
+            blocks.push(Block::Image { alt, target });
+        } else if let Some((name, target)) = parse_audio(trimmed) {
+            blocks.push(Block::Audio { name, target });
+        } else if let Some((level, text)) = parse_heading(trimmed) {
+            blocks.push(Block::Heading { level, text });
+        } else if line.contains("![") {
+            let inlines = inlines(line);
+            if inlines.iter().any(|inline| matches!(inline, Inline::Image { .. })) {
+                blocks.push(Block::Inline(inlines));
+            } else {
+                blocks.push(Block::Text(line));
+            }
+        } else {
+            blocks.push(Block::Text(line));
+        }
+    }
+    blocks
+}
+
+/// Shows a read-only rendering of a note.
+///
+/// # Arguments
+///
+/// * `ui` - The UI to show the preview in.
+/// * `content` - The content of the note.
+/// * `code_language` - The language of code blocks that don't name one.
+pub fn show(ui: &mut egui::Ui, content: &str, code_language: &str) {
+    for block in blocks(content) {
+        show_block(ui, block, code_language);
+    }
+}
+
+/// Shows a single block of a note.
+pub fn show_block(ui: &mut egui::Ui, block: Block<'_>, code_language: &str) {
+    match block {
+        Block::Heading { level, text } => {
+            let size = match level {
+                1 => 24.0,
+                2 => 20.0,
+                3 => 17.0,
+                _ => 15.0,
+            };
+            ui.label(RichText::new(text).size(size).strong());
+        }
+        Block::Image { alt, target } => show_image(ui, alt, target),
+        Block::Audio { name, target } => show_audio(ui, name, target),
+        Block::Inline(inlines) => {
+            ui.horizontal_wrapped(|ui| {
+                for inline in inlines {
+                    match inline {
+                        Inline::Text(text) => {
+                            ui.label(text);
+                        }
+                        Inline::Image { alt, target } => show_image(ui, alt, target),
+                    }
+                }
+            });
+        }
+        Block::Code { language, code } => {
+            let language = if language.is_empty() { code_language } else { language };
+            let theme = EditorTheme::from_style(ui.style());
+            egui::Frame::none()
+                .fill(ui.visuals().code_bg_color)
+                .inner_margin(4.0)
+                .show(ui, |ui| ui.label(highlight::code_layout_job(&theme, &code, language)));
+        }
+        Block::Text(text) => {
+            ui.label(text);
+        }
+        Block::Blank => ui.add_space(ui.spacing().item_spacing.y * 2.0),
+    }
+}
+
+// Images are loaded on background threads and cached by the loaders installed at startup,
+// so a spinner is shown until an image is ready and the UI never waits for it.
+fn show_image(ui: &mut egui::Ui, alt: &str, target: &str) {
+    let image = egui::Image::new(image_uri(target))
+        .max_width(ui.available_width())
+        .show_loading_spinner(true);
+    let response = ui.add(image);
+    if !alt.is_empty() {
+        response.on_hover_text(alt);
+    }
+}
+
+// Play, pause and stop buttons for an audio attachment
+#[cfg(not(target_arch = "wasm32"))]
+fn show_audio(ui: &mut egui::Ui, name: &str, target: &str) {
+    let error_id = egui::Id::new(("audio_error", target));
+    ui.horizontal(|ui| {
+        let state = audio::state(target);
+        let mut play = false;
+        match state {
+            Some(PlaybackState::Playing(_)) => {
+                if ui.button("⏸").on_hover_text("Pause").clicked() {
+                    audio::set_paused(true);
+                }
+            }
+            Some(PlaybackState::Paused(_)) => {
+                if ui.button("▶").on_hover_text("Resume").clicked() {
+                    audio::set_paused(false);
+                }
+            }
+            None => play = ui.button("▶").on_hover_text("Play").clicked(),
+        }
+        if ui.add_enabled(state.is_some(), egui::Button::new("⏹")).on_hover_text("Stop").clicked() {
+            audio::stop();
+        }
+        ui.label(format!("🔊 {}", name));
+        if let Some(PlaybackState::Playing(position) | PlaybackState::Paused(position)) = state {
+            ui.weak(audio::format_duration(position));
+        }
+        if matches!(state, Some(PlaybackState::Playing(_))) {
+            ui.ctx().request_repaint_after(std::time::Duration::from_millis(250));
+        }
+        if play {
+            let result = attachments::resolve(target).and_then(|path| audio::play(target, &path));
+            ui.data_mut(|data| match result {
+                Ok(()) => data.remove::<String>(error_id),
+                Err(err) => data.insert_temp(error_id, format!("Failed to play {}: {}", name, err)),
+            });
+        }
+    });
+    if let Some(err) = ui.data(|data| data.get_temp::<String>(error_id)) {
+        ui.colored_label(ui.visuals().error_fg_color, err);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn show_audio(ui: &mut egui::Ui, name: &str, _target: &str) {
+    ui.label(format!("🔊 {}", name));
+}
+
+/// Returns the URI an image is loaded from: web URLs as they are, paths starting with `~`
+/// in the home directory, and other targets relative to the `.notes` directory.
+pub fn image_uri(target: &str) -> String {
+    if target.contains("://") {
+        return target.to_string();
+    }
+    if target.starts_with('~') {
+        return format!("file://{}", expand_home(target).display());
+    }
+    match attachments::resolve(target) {
+        Ok(path) => format!("file://{}", path.display()),
+        Err(_) => target.to_string(),
+    }
+}
+
+/// Splits a line of text at its images.
+fn inlines(line: &str) -> Vec<Inline<'_>> {
+    let mut inlines = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("![") {
+        let image = rest[start..]
+            .find(')')
+            .and_then(|end| Some((end, parse_image(&rest[start..start + end + 1])?)));
+        let Some((end, (alt, target))) = image else {
+            break;
+        };
+        if start > 0 {
+            inlines.push(Inline::Text(&rest[..start]));
+        }
+        inlines.push(Inline::Image { alt, target });
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        inlines.push(Inline::Text(rest));
+    }
+    inlines
+}
+
+/// Parses a line that consists of a single `![alt](target)` image.
+fn parse_image(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("![")?;
+    let (alt, rest) = rest.split_once("](")?;
+    let target = rest.strip_suffix(')')?;
+    if target.is_empty() || target.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((alt, target))
+}
+
+/// Parses a line that consists of a single `[name](target)` link to an audio attachment.
+fn parse_audio(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix('[')?;
+    let (name, rest) = rest.split_once("](")?;
+    let target = rest.strip_suffix(')')?;
+    if target.contains("://") || target.contains(char::is_whitespace) || !attachments::is_audio(target) {
+        return None;
+    }
+    Some((name, target))
+}
+
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let text = line[level..].strip_prefix(' ')?;
+    Some((level, text.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        let content = "---\ntags: a\n---\n# Title\n\n![cat](attachments/ab.png)\n```rust\nfn main() {}\n```\n#tag line";
+        assert_eq!(
+            blocks(content),
+            vec![
+                Block::Heading { level: 1, text: "Title" },
+                Block::Blank,
+                Block::Image {
+                    alt: "cat",
+                    target: "attachments/ab.png"
+                },
+                Block::Code {
+                    language: "rust",
+                    code: "fn main() {}\n".to_string()
+                },
+                Block::Text("#tag line"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_image_uri() {
+        assert_eq!(image_uri("https://example.com/a.png"), "https://example.com/a.png");
+        assert!(image_uri("attachments/ab.png").starts_with("file://"));
+        assert_eq!(parse_image("![a](b) trailing"), None);
+    }
+
+    #[test]
+    fn test_inline_images() {
+        assert_eq!(
+            inlines("Before ![a](x.png) after"),
+            vec![
+                Inline::Text("Before "),
+                Inline::Image { alt: "a", target: "x.png" },
+                Inline::Text(" after"),
+            ]
+        );
+        assert_eq!(blocks("An ![unfinished link"), vec![Block::Text("An ![unfinished link")]);
+    }
+
+    #[test]
+    fn test_audio() {
+        assert_eq!(
+            blocks("[memo.wav](attachments/ab.wav)\n[spec.pdf](attachments/cd.pdf)"),
+            vec![
+                Block::Audio {
+                    name: "memo.wav",
+                    target: "attachments/ab.wav"
+                },
+                Block::Text("[spec.pdf](attachments/cd.pdf)"),
+            ]
+        );
+        assert_eq!(parse_audio("[a](https://example.com/a.wav)"), None);
+    }
+}
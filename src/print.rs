@@ -0,0 +1,509 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::export;
+use crate::lang::NoteLanguage;
+use crate::notes::Notes;
+use crate::permalinks::slugify;
+use crate::preview::{Block, Inline};
+use crate::reader::{self, Paragraph};
+
+// A4 in points, with the margins around the text
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+
+/// The text printed above and below every page. `{title}`, `{date}`, `{page}` and `{pages}` are
+/// replaced with the title of the note, today's date, the page number and the number of pages.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct PrintOptions {
+    /// The header, or empty for none.
+    pub header: String,
+    /// The footer, or empty for none.
+    pub footer: String,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            header: "{title}".to_string(),
+            footer: "{date} · Page {page} of {pages}".to_string(),
+        }
+    }
+}
+
+// A piece of a header or footer once the title and date are filled in
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Page,
+    Pages,
+}
+
+fn segments(template: &str, title: &str, date: &str) -> Vec<Segment> {
+    let template = template.replace("{title}", title).replace("{date}", date);
+    let mut segments = Vec::new();
+    let mut rest = template.as_str();
+    while let Some(start) = rest.find('{') {
+        let (segment, len) = if rest[start..].starts_with("{pages}") {
+            (Segment::Pages, "{pages}".len())
+        } else if rest[start..].starts_with("{page}") {
+            (Segment::Page, "{page}".len())
+        } else {
+            segments.push(Segment::Text(rest[..=start].to_string()));
+            rest = &rest[start + 1..];
+            continue;
+        };
+        segments.push(Segment::Text(rest[..start].to_string()));
+        segments.push(segment);
+        rest = &rest[start + len..];
+    }
+    segments.push(Segment::Text(rest.to_string()));
+    segments.retain(|segment| *segment != Segment::Text(String::new()));
+    segments
+}
+
+/// Renders a note as a web page that opens the print dialog of the browser when loaded, with the
+/// header and footer in the page margins.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `content` - The content of the note. Front matter is left out.
+/// * `options` - The header and footer.
+/// * `date` - The date to print for `{date}`.
+pub fn html(title: &str, content: &str, options: &PrintOptions, date: &str) -> String {
+    let base = Notes::get_notes_dir()
+        .map(|dir| format!("file://{}/", dir.display()))
+        .unwrap_or_default();
+    let mut margin_boxes = String::new();
+    for (position, template) in [("top-center", &options.header), ("bottom-center", &options.footer)] {
+        let segments = segments(template, title, date);
+        if segments.is_empty() {
+            continue;
+        }
+        let content: Vec<String> = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+                Segment::Page => "counter(page)".to_string(),
+                Segment::Pages => "counter(pages)".to_string(),
+            })
+            .collect();
+        margin_boxes.push_str(&format!(
+            "  @{} {{ content: {}; font: 9pt sans-serif; color: #666; }}\n",
+            position,
+            content.join(" ")
+        ));
+    }
+    let stylesheet = format!(
+        "@page {{\n  margin: 20mm;\n{}}}\nbody {{ font-family: sans-serif; line-height: 1.5; }}\npre {{ white-space: pre-wrap; }}\nimg {{ max-width: 100%; }}",
+        margin_boxes
+    );
+    let mut body = export::body_html(content, &base, &|_| None);
+    body.push_str("<script>window.addEventListener(\"load\", () => window.print());</script>\n");
+    export::page(title, NoteLanguage::of(content).as_ref(), &stylesheet, &body)
+}
+
+/// Opens a note in the browser to print it from there.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `content` - The content of the note.
+/// * `options` - The header and footer.
+///
+/// # Returns
+///
+/// An `io::Result<()>`, with an error if there is no browser to open the page with.
+pub fn print(title: &str, content: &str, options: &PrintOptions) -> io::Result<()> {
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let dir = std::env::temp_dir().join("notes-print");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.html", slugify(title)));
+    fs::write(&path, html(title, content, options, &date))?;
+    open(&path)
+}
+
+/// Saves a note as a paginated PDF, for when it cannot be printed from the browser.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `content` - The content of the note.
+/// * `options` - The header and footer.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the path of the PDF, which is saved in the documents
+/// folder.
+pub fn save_pdf(title: &str, content: &str, options: &PrintOptions) -> io::Result<PathBuf> {
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let dir = dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No documents folder to save the PDF in"))?;
+    let path = dir.join(format!("{}.pdf", slugify(title)));
+    fs::write(&path, pdf(title, content, options, &date))?;
+    Ok(path)
+}
+
+// Opens a file with the application the OS associates with it
+fn open(path: &Path) -> io::Result<()> {
+    let status = if cfg!(target_os = "macos") {
+        Command::new("open").arg(path).status()?
+    } else if cfg!(target_os = "windows") {
+        Command::new("cmd").arg("/C").arg("start").arg("").arg(path).status()?
+    } else {
+        Command::new("xdg-open").arg(path).status()?
+    };
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Failed to open {}", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Mono,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Mono => "F3",
+        }
+    }
+
+    // The standard fonts are not embedded, so widths are estimated from the average character
+    fn char_width(self) -> f32 {
+        match self {
+            Font::Regular => 0.5,
+            Font::Bold => 0.55,
+            Font::Mono => 0.6,
+        }
+    }
+}
+
+// A line of text laid out on a page
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    font: Font,
+    size: f32,
+    indent: f32,
+    // Extra space above the line, dropped at the top of a page
+    space_before: f32,
+    text: String,
+}
+
+/// Renders a note as a PDF with A4 pages.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note.
+/// * `content` - The content of the note. Front matter is left out.
+/// * `options` - The header and footer.
+/// * `date` - The date to print for `{date}`.
+///
+/// # Returns
+///
+/// The bytes of the PDF.
+pub fn pdf(title: &str, content: &str, options: &PrintOptions, date: &str) -> Vec<u8> {
+    let pages = paginate(layout(content));
+    let count = pages.len();
+    let streams: Vec<Vec<u8>> = pages
+        .iter()
+        .enumerate()
+        .map(|(index, lines)| {
+            let fill = |template: &str| -> String {
+                segments(template, title, date)
+                    .into_iter()
+                    .map(|segment| match segment {
+                        Segment::Text(text) => text,
+                        Segment::Page => (index + 1).to_string(),
+                        Segment::Pages => count.to_string(),
+                    })
+                    .collect()
+            };
+            let mut stream = Vec::new();
+            let margins = [
+                (fill(&options.header), PAGE_HEIGHT - MARGIN / 2.0 - 4.0),
+                (fill(&options.footer), MARGIN / 2.0),
+            ];
+            for (text, y) in margins.iter().filter(|(text, _)| !text.is_empty()) {
+                let x = ((PAGE_WIDTH - text_width(text, Font::Regular, 9.0)) / 2.0).max(MARGIN);
+                stream.extend_from_slice(b"0.4 g\n");
+                show_text(&mut stream, Font::Regular, 9.0, x, *y, text);
+                stream.extend_from_slice(b"0 g\n");
+            }
+            for (y, line) in lines {
+                show_text(&mut stream, line.font, line.size, MARGIN + line.indent, *y, &line.text);
+            }
+            stream
+        })
+        .collect();
+    write_pdf(&streams)
+}
+
+// Turns the paragraphs of a note into lines that fit the width of a page
+fn layout(content: &str) -> Vec<Line> {
+    let width = PAGE_WIDTH - 2.0 * MARGIN;
+    let mut lines = Vec::new();
+    let mut push = |font: Font, size: f32, indent: f32, space_before: f32, text: &str| {
+        for (index, text) in wrap(text, font, size, width - indent).into_iter().enumerate() {
+            lines.push(Line {
+                font,
+                size,
+                indent,
+                space_before: if index == 0 { space_before } else { 0.0 },
+                text,
+            });
+        }
+    };
+    for paragraph in reader::paragraphs(content) {
+        match paragraph {
+            Paragraph::Text(text) => {
+                let text = plain(&text);
+                match list_item(&text) {
+                    Some(item) => push(Font::Regular, 11.0, 12.0, 3.0, &format!("• {}", item)),
+                    None => push(Font::Regular, 11.0, 0.0, 8.0, &text),
+                }
+            }
+            Paragraph::Block(Block::Heading { level, text }) => {
+                let size = [20.0, 16.0, 14.0, 12.0, 11.0, 11.0][level.clamp(1, 6) - 1];
+                push(Font::Bold, size, 0.0, 14.0, &plain(text));
+            }
+            Paragraph::Block(Block::Image { alt, .. }) => push(Font::Regular, 11.0, 0.0, 8.0, &format!("[{}]", alt)),
+            Paragraph::Block(Block::Audio { name, .. }) => push(Font::Regular, 11.0, 0.0, 8.0, &format!("[{}]", name)),
+            Paragraph::Block(Block::Inline(inlines)) => {
+                let text: String = inlines
+                    .iter()
+                    .map(|inline| match inline {
+                        Inline::Text(text) => plain(text),
+                        Inline::Image { alt, .. } => format!("[{}]", alt),
+                    })
+                    .collect();
+                push(Font::Regular, 11.0, 0.0, 8.0, &text);
+            }
+            Paragraph::Block(Block::Code { code, .. }) => {
+                for (index, line) in code.lines().enumerate() {
+                    push(Font::Mono, 9.5, 8.0, if index == 0 { 8.0 } else { 0.0 }, line);
+                }
+            }
+            Paragraph::Block(Block::Text(text)) => push(Font::Regular, 11.0, 0.0, 8.0, &plain(text)),
+            Paragraph::Block(Block::Blank) => {}
+        }
+    }
+    lines
+}
+
+// Strips the Markdown markup that has no place on paper
+fn plain(text: &str) -> String {
+    let wiki_link = Regex::new(r"\[\[(?:[^\]|]*\|)?([^\]]*)\]\]").unwrap();
+    let link = Regex::new(r"\[([^\]]*)\]\(([^)]*)\)").unwrap();
+    let text = wiki_link.replace_all(text, "$1");
+    let text = link.replace_all(&text, "$1 ($2)");
+    text.replace("**", "").replace("__", "").replace('`', "")
+}
+
+fn list_item(text: &str) -> Option<&str> {
+    ["- ", "* ", "+ "].iter().find_map(|marker| text.strip_prefix(marker))
+}
+
+fn text_width(text: &str, font: Font, size: f32) -> f32 {
+    text.chars().count() as f32 * font.char_width() * size
+}
+
+// Breaks text into lines at spaces, and long words wherever they overflow
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let max_chars = ((width / (font.char_width() * size)) as usize).max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > max_chars {
+            let rest = word.split_off(max_chars);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    lines.push(line);
+    lines
+}
+
+// Places lines on pages from the top down, returning the baseline of each line
+fn paginate(lines: Vec<Line>) -> Vec<Vec<(f32, Line)>> {
+    let top = PAGE_HEIGHT - MARGIN;
+    let mut pages = vec![Vec::new()];
+    let mut y = top;
+    for line in lines {
+        let height = line.size * 1.35;
+        let space_before = if y < top { line.space_before } else { 0.0 };
+        let mut next = y - space_before - height;
+        if next < MARGIN && y < top {
+            pages.push(Vec::new());
+            next = top - height;
+        }
+        y = next;
+        pages.last_mut().unwrap().push((y + line.size * 0.35, line));
+    }
+    pages
+}
+
+fn show_text(stream: &mut Vec<u8>, font: Font, size: f32, x: f32, y: f32, text: &str) {
+    stream.extend_from_slice(format!("BT /{} {} Tf {:.1} {:.1} Td ", font.resource(), size, x, y).as_bytes());
+    stream.extend(pdf_string(text));
+    stream.extend_from_slice(b" Tj ET\n");
+}
+
+// A string in the WinAnsi encoding of the standard fonts. Characters it lacks become `?`.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = vec![b'('];
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => bytes.extend_from_slice(&[b'\\', c as u8]),
+            ' '..='~' | '\u{a0}'..='\u{ff}' => bytes.push(c as u32 as u8),
+            '•' => bytes.push(0x95),
+            '‘' | '’' => bytes.push(b'\''),
+            '“' | '”' => bytes.push(b'"'),
+            '–' | '—' => bytes.push(b'-'),
+            '…' => bytes.extend_from_slice(b"..."),
+            '\t' => bytes.extend_from_slice(b"    "),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes.push(b')');
+    bytes
+}
+
+// Writes the objects of a PDF: the catalog, the page tree, the fonts, then each page and its
+// content stream, followed by the table of their offsets
+fn write_pdf(streams: &[Vec<u8>]) -> Vec<u8> {
+    let first_page = 6;
+    let kids: Vec<String> = (0..streams.len())
+        .map(|index| format!("{} 0 R", first_page + 2 * index))
+        .collect();
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), streams.len()).into_bytes(),
+    ];
+    for font in ["Helvetica", "Helvetica-Bold", "Courier"] {
+        objects.push(
+            format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", font).into_bytes(),
+        );
+    }
+    for (index, stream) in streams.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                first_page + 2 * index + 1
+            )
+            .into_bytes(),
+        );
+        let mut object = format!("<< /Length {} >>\nstream\n", stream.len()).into_bytes();
+        object.extend_from_slice(stream);
+        object.extend_from_slice(b"\nendstream");
+        objects.push(object);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes(),
+    );
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(
+            segments("{title} · {page}/{pages} {x}", "Plan", "2024-05-01"),
+            vec![
+                Segment::Text("Plan · ".to_string()),
+                Segment::Page,
+                Segment::Text("/".to_string()),
+                Segment::Pages,
+                Segment::Text(" {".to_string()),
+                Segment::Text("x}".to_string()),
+            ]
+        );
+        assert!(segments("", "Plan", "2024-05-01").is_empty());
+    }
+
+    #[test]
+    fn test_html() {
+        let html = html("Plan", "# Goals\nShip it", &PrintOptions::default(), "2024-05-01");
+        assert!(html.contains("<h1>Goals</h1>"));
+        assert!(html.contains(r#"@bottom-center { content: "2024-05-01 · Page " counter(page) " of " counter(pages);"#));
+        assert!(html.contains("window.print()"));
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("aa bb cc", Font::Mono, 10.0, 36.0), vec!["aa bb", "cc"]);
+        assert_eq!(wrap("abcdefgh", Font::Mono, 10.0, 18.0), vec!["abc", "def", "gh"]);
+        assert_eq!(pdf_string("a·b (c) “d”"), b"(a\xb7b \\(c\\) \"d\")".to_vec());
+        assert_eq!(plain("See [[a|the plan]] and [docs](https://x.y) **now**"), "See the plan and docs (https://x.y) now");
+    }
+
+    #[test]
+    fn test_pdf() {
+        let content = format!("# Plan\n\n{}", "A line about (the) plan.\n\n".repeat(120));
+        let options = PrintOptions {
+            header: "{title}".to_string(),
+            footer: "{date}, page {page} of {pages}".to_string(),
+        };
+        let pdf = pdf("Plan", &content, &options, "2024-05-01");
+        let text = String::from_utf8(pdf).unwrap();
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.contains("(A line about \\(the\\) plan.) Tj"));
+        let count: usize = text.split("/Count ").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap();
+        assert!(count > 1);
+        assert!(text.contains(&format!("(2024-05-01, page {} of {}) Tj", count, count)));
+        assert_eq!(text.matches("/Type /Page ").count(), count);
+
+        // Every object is where the table of offsets says
+        let xref = text.rfind("xref\n").unwrap();
+        for (index, entry) in text[xref..].lines().skip(3).take_while(|line| line.ends_with(" n ")).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+        }
+    }
+}
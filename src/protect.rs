@@ -0,0 +1,162 @@
+//! Notes protected with a passphrase of their own, independent of vault and sync encryption.
+//!
+//! A protected note is stored as front matter holding what is needed to derive its key, followed
+//! by its whole content encrypted:
+//!
+//! ```text
+//! ---
+//! encrypted: chacha20poly1305
+//! salt: 0sF2...
+//! iterations: 600000
+//! ---
+//! k9Vd...
+//! ```
+
+use std::io;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::KeyInit;
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+
+use crate::front_matter::FrontMatter;
+use crate::secrets::{self, decrypt, encrypt, KDF_ITERATIONS, KDF_SALT_LEN, MAX_KDF_ITERATIONS};
+
+/// The cipher named in the `encrypted` field.
+const CIPHER: &str = "chacha20poly1305";
+
+/// The key of a protected note, derived from its passphrase.
+pub struct Passphrase {
+    cipher: ChaCha20Poly1305,
+    salt: Vec<u8>,
+    iterations: u32,
+}
+
+impl Passphrase {
+    /// Derives a key with a new salt, to protect a note that wasn't protected before.
+    pub fn new(passphrase: &str) -> Passphrase {
+        Passphrase::derive(passphrase, secrets::generate_salt().to_vec(), KDF_ITERATIONS)
+    }
+
+    fn derive(passphrase: &str, salt: Vec<u8>, iterations: u32) -> Passphrase {
+        let key = secrets::derive_key(passphrase, &salt, iterations);
+        Passphrase {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            salt,
+            iterations,
+        }
+    }
+
+    /// Unlocks a protected note.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The note as stored.
+    /// * `passphrase` - The passphrase it was protected with.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<(String, Passphrase)>` containing the content of the note and its key, which
+    /// `seal` keeps it protected with, or a `PermissionDenied` error if the passphrase is wrong.
+    pub fn unlock(content: &str, passphrase: &str) -> io::Result<(String, Passphrase)> {
+        let (front_matter, _) = FrontMatter::parse(content);
+        let salt = front_matter
+            .get("salt")
+            .and_then(|salt| STANDARD.decode(salt).ok())
+            .filter(|salt| salt.len() == KDF_SALT_LEN)
+            .ok_or_else(|| corrupt("salt"))?;
+        let iterations = front_matter
+            .get("iterations")
+            .and_then(|iterations| iterations.parse().ok())
+            .filter(|iterations| (KDF_ITERATIONS..=MAX_KDF_ITERATIONS).contains(iterations))
+            .ok_or_else(|| corrupt("iterations"))?;
+        let key = Passphrase::derive(passphrase, salt, iterations);
+        let content = key.open(content)?;
+        Ok((content, key))
+    }
+
+    /// Decrypts a note protected with this key, e.g. after it changed on disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<String>` containing the content of the note, or a `PermissionDenied` error if
+    /// it is protected with another passphrase.
+    pub fn open(&self, content: &str) -> io::Result<String> {
+        let (front_matter, body) = FrontMatter::parse(content);
+        if front_matter.get("encrypted") != Some(CIPHER) {
+            return Err(corrupt("encrypted"));
+        }
+        let salt = front_matter.get("salt").and_then(|salt| STANDARD.decode(salt).ok());
+        if salt.as_deref() != Some(self.salt.as_slice()) {
+            return Err(wrong_passphrase());
+        }
+        let data: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+        let data = STANDARD.decode(data).map_err(|_| corrupt("content"))?;
+        let plaintext = decrypt(&self.cipher, &data).map_err(|_| wrong_passphrase())?;
+        String::from_utf8(plaintext).map_err(|_| corrupt("content"))
+    }
+
+    /// Encrypts the content of a note to be stored.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<String>` containing the protected note.
+    pub fn seal(&self, content: &str) -> io::Result<String> {
+        let mut front_matter = FrontMatter::default();
+        front_matter.set("encrypted", CIPHER);
+        front_matter.set("salt", &STANDARD.encode(&self.salt));
+        front_matter.set("iterations", &self.iterations.to_string());
+        let data = encrypt(&self.cipher, content.as_bytes())?;
+        Ok(front_matter.render(&format!("{}\n", STANDARD.encode(data))))
+    }
+}
+
+fn wrong_passphrase() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "Wrong passphrase")
+}
+
+fn corrupt(field: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("The protected note has no valid {} field", field),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_unlock() {
+        let key = Passphrase::derive("correct horse", b"0123456789abcdef".to_vec(), KDF_ITERATIONS);
+        let sealed = key.seal("---\ntags: diary\n---\nDear diary").unwrap();
+        assert!(FrontMatter::parse(&sealed).0.is_encrypted());
+        assert!(!sealed.contains("diary"));
+        // A new nonce every time
+        assert_ne!(key.seal("Dear diary").unwrap(), key.seal("Dear diary").unwrap());
+
+        let (content, unlocked) = Passphrase::unlock(&sealed, "correct horse").unwrap();
+        assert_eq!(content, "---\ntags: diary\n---\nDear diary");
+        assert_eq!(unlocked.open(&key.seal("Changed").unwrap()).unwrap(), "Changed");
+
+        let wrong = Passphrase::unlock(&sealed, "battery staple");
+        assert_eq!(wrong.err().map(|err| err.kind()), Some(io::ErrorKind::PermissionDenied));
+        let other = Passphrase::derive("correct horse", b"fedcba9876543210".to_vec(), 1000);
+        assert_eq!(other.open(&sealed).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(key.open("Dear diary").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Notes edited to weaken or stall the key derivation are refused
+        let weak = other.seal("Dear diary").unwrap();
+        assert_eq!(
+            Passphrase::unlock(&weak, "correct horse").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let short_salt = Passphrase::derive("correct horse", b"salt".to_vec(), KDF_ITERATIONS)
+            .seal("Dear diary")
+            .unwrap();
+        assert_eq!(
+            Passphrase::unlock(&short_salt, "correct horse").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use eframe::egui::{self, text::LayoutJob, FontFamily, FontId, TextFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::preview::{self, Block};
+
+/// The name of the font family used for serif text in reading mode.
+pub const SERIF_FAMILY: &str = "serif";
+
+/// Serif fonts that ship with common desktop systems, tried in order.
+#[cfg(not(target_arch = "wasm32"))]
+const SERIF_FONT_PATHS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSerif.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSerif.ttf",
+    "/usr/share/fonts/TTF/DejaVuSerif.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSerif-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSerif-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Georgia.ttf",
+    "/Library/Fonts/Georgia.ttf",
+    "C:\\Windows\\Fonts\\georgia.ttf",
+];
+
+/// The typography of reading mode, kept separate from the editor's style.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReadingStyle {
+    /// Whether paragraphs are justified to both margins.
+    pub justify: bool,
+    /// Whether text is set in a serif font.
+    pub serif: bool,
+    /// The size of body text in points.
+    pub font_size: f32,
+    /// The distance between lines as a multiple of the font size.
+    pub line_height: f32,
+    /// The widest the column of text gets, in points.
+    pub max_width: f32,
+}
+
+impl Default for ReadingStyle {
+    fn default() -> Self {
+        Self {
+            justify: false,
+            serif: true,
+            font_size: 17.0,
+            line_height: 1.5,
+            max_width: 680.0,
+        }
+    }
+}
+
+impl ReadingStyle {
+    fn text_job(&self, ui: &egui::Ui, text: &str, size: f32, strong: bool) -> LayoutJob {
+        let family = if self.serif {
+            FontFamily::Name(SERIF_FAMILY.into())
+        } else {
+            FontFamily::Proportional
+        };
+        let color = if strong {
+            ui.visuals().strong_text_color()
+        } else {
+            ui.visuals().text_color()
+        };
+        let mut job = LayoutJob::single_section(
+            text.to_string(),
+            TextFormat {
+                font_id: FontId::new(size, family),
+                color,
+                line_height: Some(size * self.line_height),
+                ..Default::default()
+            },
+        );
+        job.wrap.max_width = ui.available_width();
+        job.justify = self.justify;
+        job
+    }
+}
+
+/// A paragraph of a note as laid out in reading mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Paragraph<'a> {
+    /// Consecutive lines of text joined into one paragraph.
+    Text(String),
+    /// A heading, image, code block or blank line, shown as in the preview.
+    Block(Block<'a>),
+}
+
+/// Joins the lines of a note into paragraphs so that they can be reflowed.
+///
+/// Lines of text are joined until a blank line or another kind of block. List items
+/// always start a new paragraph.
+pub fn paragraphs(content: &str) -> Vec<Paragraph<'_>> {
+    let mut paragraphs = Vec::new();
+    let mut text = String::new();
+    for block in preview::blocks(content) {
+        match block {
+            Block::Text(line) => {
+                if !text.is_empty() && is_list_item(line) {
+                    paragraphs.push(Paragraph::Text(std::mem::take(&mut text)));
+                }
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(line.trim());
+            }
+            block => {
+                if !text.is_empty() {
+                    paragraphs.push(Paragraph::Text(std::mem::take(&mut text)));
+                }
+                paragraphs.push(Paragraph::Block(block));
+            }
+        }
+    }
+    if !text.is_empty() {
+        paragraphs.push(Paragraph::Text(text));
+    }
+    paragraphs
+}
+
+fn is_list_item(line: &str) -> bool {
+    let line = line.trim_start();
+    if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+        return true;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && line[digits..].starts_with(". ")
+}
+
+/// Returns how far through a scrolled text the reader is, from 0 to 1.
+///
+/// # Arguments
+///
+/// * `offset` - How far the text is scrolled.
+/// * `content_height` - The height of the whole text.
+/// * `viewport_height` - The height of the visible part of the text.
+pub fn scroll_progress(offset: f32, content_height: f32, viewport_height: f32) -> f32 {
+    let scrollable = content_height - viewport_height;
+    if scrollable <= 0.0 {
+        return 1.0;
+    }
+    (offset / scrollable).clamp(0.0, 1.0)
+}
+
+/// Shows a note in reading mode.
+///
+/// # Arguments
+///
+/// * `ui` - The UI to show the note in.
+/// * `id` - Identifies the scroll position, e.g. the title of the note.
+/// * `content` - The content of the note.
+/// * `style` - The typography to use.
+/// * `code_language` - The language of code blocks that don't name one.
+///
+/// # Returns
+///
+/// How far through the note the reader has scrolled, from 0 to 1.
+pub fn show(ui: &mut egui::Ui, id: impl std::hash::Hash, content: &str, style: &ReadingStyle, code_language: &str) -> f32 {
+    let output = egui::ScrollArea::vertical()
+        .id_source(("reader", id))
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            let margin = ((ui.available_width() - style.max_width) / 2.0).max(0.0);
+            ui.horizontal(|ui| {
+                ui.add_space(margin);
+                ui.vertical(|ui| {
+                    ui.set_max_width(style.max_width);
+                    for paragraph in paragraphs(content) {
+                        match paragraph {
+                            Paragraph::Text(text) => {
+                                ui.label(style.text_job(ui, &text, style.font_size, false));
+                                ui.add_space(style.font_size * 0.5);
+                            }
+                            Paragraph::Block(Block::Heading { level, text }) => {
+                                let scale = match level {
+                                    1 => 1.6,
+                                    2 => 1.35,
+                                    3 => 1.15,
+                                    _ => 1.0,
+                                };
+                                ui.add_space(style.font_size * 0.5);
+                                ui.label(style.text_job(ui, text, style.font_size * scale, true));
+                            }
+                            Paragraph::Block(block) => preview::show_block(ui, block, code_language),
+                        }
+                    }
+                });
+            });
+        });
+    scroll_progress(
+        output.state.offset.y,
+        output.content_size.y,
+        output.inner_rect.height(),
+    )
+}
+
+/// Registers the serif font family used by reading mode.
+///
+/// A serif font installed on the system is used where one is found. Otherwise the family
+/// falls back to the regular proportional font, so that it can always be selected.
+pub fn install_fonts(ctx: &egui::Context) {
+    let mut fonts = egui::FontDefinitions::default();
+    let mut family = fonts.families.get(&FontFamily::Proportional).cloned().unwrap_or_default();
+    if let Some(data) = load_serif_font() {
+        fonts
+            .font_data
+            .insert(SERIF_FAMILY.to_string(), egui::FontData::from_owned(data));
+        family.insert(0, SERIF_FAMILY.to_string());
+    }
+    fonts.families.insert(FontFamily::Name(SERIF_FAMILY.into()), family);
+    ctx.set_fonts(fonts);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_serif_font() -> Option<Vec<u8>> {
+    SERIF_FONT_PATHS.iter().find_map(|path| std::fs::read(path).ok())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_serif_font() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paragraphs() {
+        let content = "# Title\nfirst line\nsecond line\n- item one\n- item two\n\nlast";
+        assert_eq!(
+            paragraphs(content),
+            vec![
+                Paragraph::Block(Block::Heading { level: 1, text: "Title" }),
+                Paragraph::Text("first line second line".to_string()),
+                Paragraph::Text("- item one".to_string()),
+                Paragraph::Text("- item two".to_string()),
+                Paragraph::Block(Block::Blank),
+                Paragraph::Text("last".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scroll_progress() {
+        assert_eq!(scroll_progress(0.0, 100.0, 200.0), 1.0);
+        assert_eq!(scroll_progress(0.0, 1000.0, 200.0), 0.0);
+        assert_eq!(scroll_progress(400.0, 1000.0, 200.0), 0.5);
+        assert_eq!(scroll_progress(900.0, 1000.0, 200.0), 1.0);
+    }
+}
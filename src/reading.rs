@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use chrono::NaiveDate;
+
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+
+/// The tag that puts a note on the reading list, as an alternative to `type: reading`.
+pub const READING_TAG: &str = "reading";
+
+/// How far along an item on the reading list is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadingStatus {
+    #[default]
+    ToRead,
+    Reading,
+    Finished,
+}
+
+impl ReadingStatus {
+    /// The value stored in the `status` front matter field.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "to-read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+        }
+    }
+
+    /// A human-readable label for the status.
+    pub fn label(self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "To read",
+            ReadingStatus::Reading => "Reading",
+            ReadingStatus::Finished => "Finished",
+        }
+    }
+
+    /// Parses a `status` field, defaulting to `ToRead` for unknown values.
+    pub fn parse(value: &str) -> ReadingStatus {
+        match value.trim().to_lowercase().as_str() {
+            "reading" | "in-progress" => ReadingStatus::Reading,
+            "finished" | "done" | "read" => ReadingStatus::Finished,
+            _ => ReadingStatus::ToRead,
+        }
+    }
+
+    /// The status that follows this one when cycling through them.
+    pub fn next(self) -> ReadingStatus {
+        match self {
+            ReadingStatus::ToRead => ReadingStatus::Reading,
+            ReadingStatus::Reading => ReadingStatus::Finished,
+            ReadingStatus::Finished => ReadingStatus::ToRead,
+        }
+    }
+}
+
+/// A note on the reading list, described by its front matter.
+///
+/// ```text
+/// ---
+/// type: reading
+/// url: https://example.com/article
+/// author: Jane Doe
+/// status: reading
+/// progress: 40
+/// ---
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadingItem {
+    /// The title of the note.
+    pub title: String,
+    /// Where the item can be read.
+    pub url: Option<String>,
+    /// Who wrote the item.
+    pub author: Option<String>,
+    /// How far along the item is.
+    pub status: ReadingStatus,
+    /// The percentage read, from 0 to 100.
+    pub progress: u8,
+    /// The day the item was finished.
+    pub finished: Option<NaiveDate>,
+}
+
+impl ReadingItem {
+    /// Reads the reading list details of a note.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    /// * `content` - The content of the note.
+    ///
+    /// # Returns
+    ///
+    /// The item, or `None` if the note is not on the reading list.
+    pub fn from_note(title: &str, content: &str) -> Option<ReadingItem> {
+        let (front_matter, _) = FrontMatter::parse(content);
+        let is_reading = front_matter.get("type") == Some("reading")
+            || front_matter.tags().iter().any(|tag| tag == READING_TAG);
+        if !is_reading {
+            return None;
+        }
+        let field = |key: &str| front_matter.get(key).filter(|value| !value.is_empty()).map(str::to_string);
+        Some(ReadingItem {
+            title: title.to_string(),
+            url: field("url"),
+            author: field("author"),
+            status: front_matter.get("status").map(ReadingStatus::parse).unwrap_or_default(),
+            progress: front_matter
+                .get("progress")
+                .and_then(|value| value.trim_end_matches('%').parse::<u8>().ok())
+                .unwrap_or(0)
+                .min(100),
+            finished: front_matter
+                .get("finished")
+                .and_then(|value| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()),
+        })
+    }
+}
+
+/// Lists the notes on the reading list.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<ReadingItem>>` containing the items ordered by status and then title.
+pub fn list() -> io::Result<Vec<ReadingItem>> {
+    let mut items = Vec::new();
+    for title in Notes::list_notes()? {
+        let content = Notes::read_note_file(&title)?;
+        items.extend(ReadingItem::from_note(&title, &content));
+    }
+    items.sort_by_key(|item| {
+        let order = match item.status {
+            ReadingStatus::Reading => 0,
+            ReadingStatus::ToRead => 1,
+            ReadingStatus::Finished => 2,
+        };
+        (order, item.title.to_lowercase())
+    });
+    Ok(items)
+}
+
+/// Sets the status of a reading list note.
+///
+/// Finishing an item records the day and sets its progress to 100%; moving it back out of
+/// `Finished` clears that day.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `status` - The new status.
+/// * `today` - The current day.
+///
+/// # Returns
+///
+/// The updated content of the note.
+pub fn set_status(content: &str, status: ReadingStatus, today: NaiveDate) -> String {
+    let (mut front_matter, body) = FrontMatter::parse(content);
+    front_matter.set("status", status.as_str());
+    if status == ReadingStatus::Finished {
+        front_matter.set("progress", "100");
+        front_matter.set("finished", &today.format("%Y-%m-%d").to_string());
+    } else {
+        front_matter.remove("finished");
+    }
+    front_matter.render(body)
+}
+
+/// Sets the percentage read of a reading list note.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `progress` - The percentage read, from 0 to 100.
+///
+/// # Returns
+///
+/// The updated content of the note.
+pub fn set_progress(content: &str, progress: u8) -> String {
+    let (mut front_matter, body) = FrontMatter::parse(content);
+    front_matter.set("progress", &progress.min(100).to_string());
+    front_matter.render(body)
+}
+
+/// Counts the finished items per month.
+///
+/// # Returns
+///
+/// `(YYYY-MM, count)` pairs for the months in which items were finished, oldest first.
+pub fn finished_per_month(items: &[ReadingItem]) -> Vec<(String, usize)> {
+    let mut months = BTreeMap::new();
+    for finished in items.iter().filter_map(|item| item.finished) {
+        *months.entry(finished.format("%Y-%m").to_string()).or_insert(0) += 1;
+    }
+    months.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_note() {
+        let content = "---\ntype: reading\nurl: https://example.com\nauthor: Jane\nstatus: reading\nprogress: 40%\n---\n";
+        let item = ReadingItem::from_note("article", content).unwrap();
+        assert_eq!(item.url.as_deref(), Some("https://example.com"));
+        assert_eq!(item.author.as_deref(), Some("Jane"));
+        assert_eq!(item.status, ReadingStatus::Reading);
+        assert_eq!(item.progress, 40);
+
+        let tagged = ReadingItem::from_note("book", "---\ntags: reading\n---\n").unwrap();
+        assert_eq!(tagged.status, ReadingStatus::ToRead);
+        assert!(ReadingItem::from_note("plain", "Just a note").is_none());
+    }
+
+    #[test]
+    fn test_cycle_status() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let content = "---\ntype: reading\nstatus: reading\n---\nBody";
+        let finished = set_status(content, ReadingStatus::Reading.next(), today);
+        let item = ReadingItem::from_note("article", &finished).unwrap();
+        assert_eq!(item.status, ReadingStatus::Finished);
+        assert_eq!(item.progress, 100);
+        assert_eq!(item.finished, Some(today));
+        assert!(finished.ends_with("---\nBody"));
+
+        let restarted = set_status(&finished, ReadingStatus::Finished.next(), today);
+        let item = ReadingItem::from_note("article", &restarted).unwrap();
+        assert_eq!(item.status, ReadingStatus::ToRead);
+        assert_eq!(item.finished, None);
+    }
+
+    #[test]
+    fn test_finished_per_month() {
+        let item = |finished: Option<&str>| ReadingItem {
+            title: String::new(),
+            url: None,
+            author: None,
+            status: ReadingStatus::Finished,
+            progress: 100,
+            finished: finished.map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()),
+        };
+        let items = [item(Some("2024-03-01")), item(Some("2024-02-10")), item(Some("2024-03-20")), item(None)];
+        assert_eq!(
+            finished_per_month(&items),
+            vec![("2024-02".to_string(), 1), ("2024-03".to_string(), 2)]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Weekly reports of the time spent in focus sessions, per project and per tag.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+
+use crate::focus::{self, FocusSession};
+use crate::notes::Notes;
+use crate::stats::csv_field;
+use crate::storage::atomic_write;
+use crate::tags;
+
+/// The time spent in focus sessions during a week, as shown in the Reports dialog.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeeklyReport {
+    /// The Monday the week starts on.
+    pub week: NaiveDate,
+    /// The time spent in all sessions, in seconds.
+    pub total_secs: u64,
+    /// The time spent on each project in seconds, most first. Todos without a project are
+    /// counted under `None`.
+    pub per_project: Vec<(Option<String>, u64)>,
+    /// The time spent on each tag of the notes the todos belong to in seconds, most first.
+    pub per_tag: Vec<(String, u64)>,
+}
+
+impl WeeklyReport {
+    /// Adds up the sessions started during a week.
+    ///
+    /// # Arguments
+    ///
+    /// * `sessions` - The focus sessions.
+    /// * `day` - Any day of the week.
+    /// * `tags_of` - Returns the tags of a note.
+    pub fn compute(
+        sessions: &[FocusSession],
+        day: NaiveDate,
+        mut tags_of: impl FnMut(&str) -> Vec<String>,
+    ) -> WeeklyReport {
+        let week = week_start(day);
+        let mut total_secs = 0;
+        let mut per_project: BTreeMap<Option<String>, u64> = BTreeMap::new();
+        let mut per_tag: BTreeMap<String, u64> = BTreeMap::new();
+        for session in sessions {
+            let started = DateTime::from_timestamp(session.start, 0).map(|start| start.with_timezone(&Local));
+            if started.map(|start| week_start(start.date_naive())) != Some(week) {
+                continue;
+            }
+            total_secs += session.secs;
+            *per_project.entry(session.project.clone()).or_default() += session.secs;
+            for tag in session.note.as_deref().map(&mut tags_of).unwrap_or_default() {
+                *per_tag.entry(tag).or_default() += session.secs;
+            }
+        }
+        WeeklyReport {
+            week,
+            total_secs,
+            per_project: most_first(per_project),
+            per_tag: most_first(per_tag),
+        }
+    }
+
+    /// Renders the report as CSV, with one row per project and per tag.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("week,kind,name,seconds,hours\n");
+        let week = self.week.format("%Y-%m-%d").to_string();
+        let projects = self
+            .per_project
+            .iter()
+            .map(|(project, secs)| ("project", project.as_deref().unwrap_or(""), secs));
+        let tags = self.per_tag.iter().map(|(tag, secs)| ("tag", tag.as_str(), secs));
+        for (kind, name, secs) in projects.chain(tags) {
+            let hours = format!("{:.2}", *secs as f64 / 3600.0);
+            let row = [week.clone(), kind.to_string(), csv_field(name), secs.to_string(), hours];
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Computes the report of a week from the log of focus sessions and the tags of the notes.
+///
+/// # Arguments
+///
+/// * `day` - Any day of the week.
+///
+/// # Returns
+///
+/// An `io::Result<WeeklyReport>` containing the report or an error.
+pub fn weekly_report(day: NaiveDate) -> io::Result<WeeklyReport> {
+    let sessions = focus::load()?;
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    Ok(WeeklyReport::compute(&sessions, day, |note| {
+        // Notes that were deleted since count as untagged
+        tags.entry(note.to_string())
+            .or_insert_with(|| Notes::read_note_file(note).map(|content| tags::extract(&content)).unwrap_or_default())
+            .clone()
+    }))
+}
+
+/// Exports a report to a CSV file.
+///
+/// # Arguments
+///
+/// * `path` - The file to write.
+/// * `report` - The report.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn export(path: &Path, report: &WeeklyReport) -> io::Result<()> {
+    atomic_write(path, report.to_csv().as_bytes(), true)
+}
+
+/// Returns the Monday of the week of a day.
+pub fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(i64::from(day.weekday().num_days_from_monday()))
+}
+
+fn most_first<K: Ord>(totals: BTreeMap<K, u64>) -> Vec<(K, u64)> {
+    let mut totals: Vec<(K, u64)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn session(day: u32, secs: u64, note: Option<&str>, project: Option<&str>) -> FocusSession {
+        FocusSession {
+            start: Local.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap().timestamp(),
+            secs,
+            todo: "Work".to_string(),
+            note: note.map(String::from),
+            project: project.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_weekly_report() {
+        let sessions = vec![
+            session(11, 1500, Some("report"), Some("work")),
+            session(12, 600, None, None),
+            session(14, 1500, Some("garden"), Some("home")),
+            session(17, 1800, Some("report"), Some("work")),
+            // The week before
+            session(10, 1500, Some("report"), Some("work")),
+        ];
+        let tags_of = |note: &str| match note {
+            "report" => vec!["writing".to_string(), "q1".to_string()],
+            _ => vec!["q1".to_string()],
+        };
+        let report = WeeklyReport::compute(&sessions, NaiveDate::from_ymd_opt(2024, 3, 13).unwrap(), tags_of);
+        assert_eq!(report.week, NaiveDate::from_ymd_opt(2024, 3, 11).unwrap());
+        assert_eq!(report.total_secs, 5400);
+        assert_eq!(
+            report.per_project,
+            vec![(Some("work".to_string()), 3300), (Some("home".to_string()), 1500), (None, 600)]
+        );
+        assert_eq!(report.per_tag, vec![("q1".to_string(), 4800), ("writing".to_string(), 3300)]);
+
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().nth(1), Some("2024-03-11,project,work,3300,0.92"));
+        assert_eq!(csv.lines().nth(3), Some("2024-03-11,project,,600,0.17"));
+        assert_eq!(csv.lines().count(), 6);
+    }
+}
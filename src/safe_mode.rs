@@ -0,0 +1,118 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::storage::atomic_write;
+use crate::vault;
+
+/// The name of the file in the `.notes` directory that marks a running session.
+///
+/// It holds the number of sessions in a row that ended without a clean exit and is removed on exit,
+/// so finding it at startup means the previous session crashed.
+pub const SESSION_FILE: &str = ".session";
+
+/// The command line flag that starts the app in safe mode.
+pub const SAFE_MODE_FLAG: &str = "--safe-mode";
+
+/// The environment variable that starts the app in safe mode when set to anything but `0`.
+pub const SAFE_MODE_VAR: &str = "NOTES_SAFE_MODE";
+
+/// How many sessions in a row must crash before the next one starts in safe mode.
+pub const CRASH_LOOP_SESSIONS: u32 = 3;
+
+/// Why the app started in safe mode.
+///
+/// In safe mode the configuration, macros, search index and saved window state are not loaded,
+/// and the file watcher, scheduled jobs and activity log are off, so that none of them can
+/// keep the app from starting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafeModeReason {
+    /// The user asked for it with the flag or environment variable.
+    Requested,
+    /// The given number of sessions in a row crashed.
+    CrashLoop(u32),
+}
+
+impl SafeModeReason {
+    /// Returns a short explanation to show in the UI.
+    pub fn describe(self) -> String {
+        match self {
+            SafeModeReason::Requested => "Safe mode was requested at startup.".to_string(),
+            SafeModeReason::CrashLoop(crashes) => {
+                format!("The last {} sessions ended unexpectedly.", crashes)
+            }
+        }
+    }
+}
+
+/// Returns whether safe mode was requested on the command line or in the environment.
+pub fn requested() -> bool {
+    env::args().any(|arg| arg == SAFE_MODE_FLAG) || env::var(SAFE_MODE_VAR).is_ok_and(|value| value != "0")
+}
+
+/// Marks the start of a session and decides whether it runs in safe mode.
+///
+/// # Arguments
+///
+/// * `requested` - Whether the user asked for safe mode.
+///
+/// # Returns
+///
+/// An `io::Result<Option<SafeModeReason>>` containing why safe mode is on, or `None` for a normal start.
+pub fn begin_session(requested: bool) -> io::Result<Option<SafeModeReason>> {
+    let path = get_session_file_path()?;
+    let crashes = match fs::read_to_string(&path) {
+        Ok(data) => data.trim().parse::<u32>().unwrap_or(0) + 1,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+        Err(err) => return Err(err),
+    };
+    atomic_write(&path, crashes.to_string().as_bytes(), true)?;
+    Ok(if requested {
+        Some(SafeModeReason::Requested)
+    } else if crashes >= CRASH_LOOP_SESSIONS {
+        Some(SafeModeReason::CrashLoop(crashes))
+    } else {
+        None
+    })
+}
+
+/// Marks the end of a session, so that the next one does not count it as a crash.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn end_session() -> io::Result<()> {
+    match fs::remove_file(get_session_file_path()?) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn get_session_file_path() -> io::Result<PathBuf> {
+    // Sessions belong to the app rather than to the vault it has open
+    Ok(vault::home_root()?.join(SESSION_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_crash_loop_starts_safe_mode() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        assert_eq!(begin_session(false).unwrap(), None);
+        end_session().unwrap();
+        assert!(!temp_notes_dir.join(SESSION_FILE).exists());
+
+        for _ in 0..CRASH_LOOP_SESSIONS {
+            assert_eq!(begin_session(false).unwrap(), None);
+        }
+        assert_eq!(begin_session(false).unwrap(), Some(SafeModeReason::CrashLoop(3)));
+        end_session().unwrap();
+        assert_eq!(begin_session(false).unwrap(), None);
+        assert_eq!(begin_session(true).unwrap(), Some(SafeModeReason::Requested));
+    }
+}
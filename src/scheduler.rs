@@ -0,0 +1,261 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::Command;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+
+/// The name of the file in the `.notes` directory that stores the schedule.
+pub const SCHEDULE_FILE: &str = ".schedule.json";
+
+/// The outcome of the last run of a job.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job succeeded, with the message its command reported.
+    Succeeded(String),
+    /// The job failed, with the error its command reported.
+    Failed(String),
+}
+
+/// A command that runs automatically at a fixed interval, e.g. a nightly export.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The unique name of the job.
+    pub name: String,
+    /// The command run by the job, as typed in the command bar.
+    pub command: String,
+    /// The time between runs, in seconds.
+    pub interval_secs: u64,
+    /// When the job last ran, as a UNIX timestamp.
+    pub last_run: Option<u64>,
+    /// The outcome of the last run.
+    pub last_status: Option<JobStatus>,
+}
+
+impl Job {
+    /// Returns whether the job should run at the given time. Jobs that never ran are due immediately.
+    pub fn is_due(&self, now: u64) -> bool {
+        self.last_run
+            .map_or(true, |last_run| now >= last_run.saturating_add(self.interval_secs))
+    }
+}
+
+/// The set of scheduled jobs, persisted in the `.schedule.json` file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// The scheduled jobs.
+    pub jobs: Vec<Job>,
+}
+
+impl Schedule {
+    /// Loads the schedule from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Schedule>` containing the schedule, which is empty if no schedule was saved yet.
+    pub fn load() -> io::Result<Schedule> {
+        let path = Self::get_schedule_file_path()?;
+        if !path.exists() {
+            return Ok(Schedule::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Saves the schedule to disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&Self::get_schedule_file_path()?, data.as_bytes(), true)
+    }
+
+    /// Adds a job, replacing any existing job with the same name.
+    pub fn add(&mut self, job: Job) {
+        self.jobs.retain(|existing| existing.name != job.name);
+        self.jobs.push(job);
+    }
+
+    /// Removes the job with the given name.
+    ///
+    /// # Returns
+    ///
+    /// Whether a job was removed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.jobs.len();
+        self.jobs.retain(|job| job.name != name);
+        self.jobs.len() != len
+    }
+
+    /// Returns the first job that is due at the given time.
+    pub fn next_due(&self, now: u64) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.is_due(now))
+    }
+
+    /// Returns how many seconds remain until the next job is due, or `None` if there are no jobs.
+    pub fn secs_until_next(&self, now: u64) -> Option<u64> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                job.last_run
+                    .map_or(0, |last_run| last_run.saturating_add(job.interval_secs).saturating_sub(now))
+            })
+            .min()
+    }
+
+    /// Records the outcome of a run of the named job.
+    pub fn record(&mut self, name: &str, ran_at: u64, status: JobStatus) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.name == name) {
+            job.last_run = Some(ran_at);
+            job.last_status = Some(status);
+        }
+    }
+
+    fn get_schedule_file_path() -> io::Result<PathBuf> {
+        Ok(Notes::get_notes_dir()?.join(SCHEDULE_FILE))
+    }
+}
+
+/// Runs a job's command without recording the outcome.
+///
+/// Commands that manage jobs are refused, in case one was written into the schedule file by hand,
+/// so that a job can't run itself forever.
+pub fn run_job_command(command: &str) -> JobStatus {
+    let result = Command::parse(command).and_then(|command| {
+        if command.manages_jobs() {
+            return Err("Scheduled jobs can't schedule or run jobs".to_string());
+        }
+        command.run()
+    });
+    match result {
+        Ok(message) => JobStatus::Succeeded(message),
+        Err(err) => JobStatus::Failed(err),
+    }
+}
+
+/// Runs the named job immediately and records the outcome in the schedule file.
+///
+/// # Arguments
+///
+/// * `name` - The name of the job.
+///
+/// # Returns
+///
+/// An `io::Result<JobStatus>` containing the outcome, or an error if the job does not exist
+/// or the schedule could not be read or written.
+pub fn run_job(name: &str) -> io::Result<JobStatus> {
+    let schedule = Schedule::load()?;
+    let job = schedule
+        .jobs
+        .iter()
+        .find(|job| job.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No job named {}", name)))?;
+    let ran_at = now();
+    let status = run_job_command(&job.command);
+
+    // The job may have taken a while, so pick up changes made in the meantime.
+    let mut schedule = Schedule::load()?;
+    schedule.record(name, ran_at, status.clone());
+    schedule.save()?;
+    Ok(status)
+}
+
+/// Parses an interval such as `hourly`, `daily`, `nightly`, `weekly`, `30m`, `12h` or `7d`.
+///
+/// # Returns
+///
+/// The interval in seconds, or `None` if it is not valid.
+pub fn parse_interval(interval: &str) -> Option<u64> {
+    match interval {
+        "hourly" => return Some(60 * 60),
+        "daily" | "nightly" => return Some(24 * 60 * 60),
+        "weekly" => return Some(7 * 24 * 60 * 60),
+        _ => {}
+    }
+    let unit = match interval.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = interval[..interval.len() - 1].parse().ok()?;
+    count.checked_mul(unit).filter(|secs| *secs > 0)
+}
+
+/// Describes an elapsed time in seconds, e.g. `5 min ago`.
+pub fn format_elapsed(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+/// Returns the current time as a UNIX timestamp.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str, last_run: Option<u64>) -> Job {
+        Job {
+            name: name.to_string(),
+            command: "export-stats csv /tmp/stats.csv".to_string(),
+            interval_secs: 100,
+            last_run,
+            last_status: None,
+        }
+    }
+
+    #[test]
+    fn test_next_due() {
+        let mut schedule = Schedule::default();
+        schedule.add(job("a", Some(1000)));
+        schedule.add(job("b", Some(950)));
+        assert_eq!(schedule.next_due(1000), None);
+        assert_eq!(schedule.next_due(1050).map(|job| job.name.as_str()), Some("b"));
+
+        assert_eq!(schedule.secs_until_next(1000), Some(50));
+
+        schedule.add(job("c", None));
+        schedule.record("b", 1050, JobStatus::Succeeded("ok".to_string()));
+        assert_eq!(schedule.next_due(1050).map(|job| job.name.as_str()), Some("c"));
+        assert_eq!(schedule.secs_until_next(1050), Some(0));
+        assert_eq!(Schedule::default().secs_until_next(1050), None);
+    }
+
+    #[test]
+    fn test_add_replaces_and_remove() {
+        let mut schedule = Schedule::default();
+        schedule.add(job("a", None));
+        schedule.add(job("a", Some(5)));
+        assert_eq!(schedule.jobs.len(), 1);
+        assert_eq!(schedule.jobs[0].last_run, Some(5));
+        assert!(schedule.remove("a"));
+        assert!(!schedule.remove("a"));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("nightly"), Some(86400));
+        assert_eq!(parse_interval("weekly"), Some(604800));
+        assert_eq!(parse_interval("30m"), Some(1800));
+        assert_eq!(parse_interval("12h"), Some(43200));
+        assert_eq!(parse_interval("0d"), None);
+        assert_eq!(parse_interval("18446744073709551615d"), None);
+        assert_eq!(parse_interval("soon"), None);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::front_matter::FrontMatter;
+
+/// The front matter fields that notes in a folder must have, declared in `.folder.json`.
+///
+/// This is the subset of JSON Schema that makes sense for front matter:
+///
+/// ```json
+/// {
+///   "schema": {
+///     "required": ["author", "rating"],
+///     "properties": {
+///       "rating": { "type": "integer", "minimum": 1, "maximum": 5 },
+///       "finished": { "type": "string", "format": "date" },
+///       "status": { "enum": ["reading", "done"] }
+///     }
+///   }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Schema {
+    /// The fields every note must have, with a non-empty value.
+    pub required: Vec<String>,
+    /// Constraints on the values of fields, checked where the field is present.
+    pub properties: BTreeMap<String, Property>,
+}
+
+/// Constraints on the value of a front matter field.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Property {
+    /// The type of the value.
+    #[serde(rename = "type")]
+    pub kind: Option<FieldType>,
+    /// A format for string values; only `date` (`YYYY-MM-DD`) is checked.
+    pub format: Option<String>,
+    /// The smallest allowed number.
+    pub minimum: Option<f64>,
+    /// The largest allowed number.
+    pub maximum: Option<f64>,
+    /// The allowed values, if only some are.
+    #[serde(rename = "enum")]
+    pub allowed: Vec<String>,
+}
+
+/// The type of a front matter value.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// Any text.
+    String,
+    /// A number, e.g. `4.5`.
+    Number,
+    /// A whole number, e.g. `4`.
+    Integer,
+    /// `true` or `false`.
+    Boolean,
+    /// A list, e.g. `[a, b]` or `a, b`.
+    Array,
+}
+
+/// A front matter field that does not match the schema of its folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    /// The name of the field.
+    pub field: String,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl Schema {
+    /// Checks the front matter of a note against the schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The full content of the note.
+    ///
+    /// # Returns
+    ///
+    /// The fields that are missing or invalid, in the order of the schema.
+    pub fn validate(&self, content: &str) -> Vec<SchemaError> {
+        let (front_matter, _) = FrontMatter::parse(content);
+        let mut errors = Vec::new();
+        for field in &self.required {
+            if front_matter.get(field).map_or(true, str::is_empty) {
+                errors.push(SchemaError {
+                    field: field.clone(),
+                    message: "is required".to_string(),
+                });
+            }
+        }
+        for (field, property) in &self.properties {
+            let Some(value) = front_matter.get(field).filter(|value| !value.is_empty()) else {
+                continue;
+            };
+            if let Err(message) = property.check(value) {
+                errors.push(SchemaError {
+                    field: field.clone(),
+                    message,
+                });
+            }
+        }
+        errors
+    }
+}
+
+impl Property {
+    fn check(&self, value: &str) -> Result<(), String> {
+        match self.kind {
+            Some(FieldType::Number | FieldType::Integer) => {
+                let number = if self.kind == Some(FieldType::Integer) {
+                    value.parse::<i64>().map(|number| number as f64).ok()
+                } else {
+                    value.parse::<f64>().ok()
+                };
+                let Some(number) = number else {
+                    return Err(match self.kind {
+                        Some(FieldType::Integer) => "must be a whole number".to_string(),
+                        _ => "must be a number".to_string(),
+                    });
+                };
+                if let Some(minimum) = self.minimum.filter(|minimum| number < *minimum) {
+                    return Err(format!("must be at least {}", minimum));
+                }
+                if let Some(maximum) = self.maximum.filter(|maximum| number > *maximum) {
+                    return Err(format!("must be at most {}", maximum));
+                }
+            }
+            Some(FieldType::Boolean) if value != "true" && value != "false" => {
+                return Err("must be true or false".to_string());
+            }
+            Some(FieldType::Array) if value.starts_with('[') != value.ends_with(']') => {
+                return Err("must be a list like [a, b]".to_string());
+            }
+            _ => {}
+        }
+        if self.format.as_deref() == Some("date") && NaiveDate::parse_from_str(value, "%Y-%m-%d").is_err() {
+            return Err("must be a date like 2024-01-31".to_string());
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|allowed| allowed == value) {
+            return Err(format!("must be one of {}", self.allowed.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let schema: Schema = serde_json::from_str(
+            r#"{
+                "required": ["author", "rating"],
+                "properties": {
+                    "rating": { "type": "integer", "minimum": 1, "maximum": 5 },
+                    "finished": { "type": "string", "format": "date" },
+                    "status": { "enum": ["reading", "done"] },
+                    "lent": { "type": "boolean" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let valid = "---\nauthor: Le Guin\nrating: 5\nfinished: 2024-03-01\nstatus: done\n---\nGreat.";
+        assert_eq!(schema.validate(valid), vec![]);
+
+        let invalid = "---\nauthor:\nrating: 7\nfinished: March\nstatus: lost\nlent: yes\n---\n";
+        let messages: Vec<String> = schema.validate(invalid).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "author: is required",
+                "finished: must be a date like 2024-01-31",
+                "lent: must be true or false",
+                "rating: must be at most 5",
+                "status: must be one of reading, done",
+            ]
+        );
+
+        let missing = schema.validate("No front matter");
+        assert_eq!(missing.len(), 2);
+        assert_eq!(
+            schema.validate("---\nauthor: X\nrating: 4.5\n---\n")[0].message,
+            "must be a whole number"
+        );
+    }
+}
@@ -0,0 +1,766 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::attachments;
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+use crate::tags;
+
+/// The directory in `.notes` holding search indexes.
+pub const INDEX_DIR: &str = ".index";
+
+/// The file in the index directory storing the full-text index.
+const INDEX_FILE: &str = "search.json";
+
+/// Bumped whenever the way notes are indexed changes, so that old indexes get rebuilt.
+const INDEX_VERSION: u32 = 2;
+
+/// How much more a word in the title of a note counts than one in its body.
+const TITLE_WEIGHT: u32 = 5;
+
+/// How much sharing all of its tags counts towards a related note, compared to sharing all of its words.
+const RELATED_TAG_WEIGHT: f64 = 0.5;
+
+/// The lowest score of a related note, below which notes only share a few common words.
+const MIN_RELATED_SCORE: f64 = 0.05;
+
+/// A note found by a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    /// The title of the note.
+    pub title: String,
+    /// How well the note matches; higher is better.
+    pub score: u32,
+    /// The byte ranges of the matches in the note's content, or in its title when searching
+    /// titles only. Empty for searches that only consult the index.
+    pub ranges: Vec<Range<usize>>,
+    /// Whether the note is archived.
+    pub archived: bool,
+}
+
+/// A note that resembles another, see `SearchIndex::related`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelatedNote {
+    /// The title of the note.
+    pub title: String,
+    /// How much the notes resemble each other; higher is more.
+    pub score: f64,
+    /// The tags both notes have.
+    pub shared_tags: Vec<String>,
+}
+
+/// How the text of a search is matched.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// The query is a regular expression rather than a list of words.
+    pub regex: bool,
+    /// Upper and lower case letters are told apart.
+    pub case_sensitive: bool,
+    /// Matches must be whole words.
+    pub whole_word: bool,
+    /// Only the titles of notes are searched.
+    pub in_title_only: bool,
+    /// Archived notes are searched too. They are not indexed, so every one of them is read.
+    pub include_archived: bool,
+}
+
+impl SearchOptions {
+    /// Compiles a query into the patterns a note must all match.
+    ///
+    /// A regular expression is a single pattern. Otherwise every word of the query is a
+    /// pattern of its own, matched literally.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<Regex>, regex::Error>` containing the patterns, or an error if the
+    /// query is not a valid regular expression.
+    pub fn matchers(&self, query: &str) -> Result<Vec<Regex>, regex::Error> {
+        let patterns: Vec<String> = if self.regex {
+            vec![query.to_string()]
+        } else {
+            query.split_whitespace().map(regex::escape).collect()
+        };
+        patterns
+            .into_iter()
+            .map(|pattern| {
+                let pattern = if self.whole_word {
+                    format!(r"\b(?:{})\b", pattern)
+                } else {
+                    pattern
+                };
+                RegexBuilder::new(&pattern).case_insensitive(!self.case_sensitive).build()
+            })
+            .collect()
+    }
+
+    /// Compiles a query into a single pattern, as used to find and replace.
+    ///
+    /// Unlike `matchers`, a query that is not a regular expression is matched as a whole phrase.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Regex, regex::Error>` containing the pattern, or an error if the query is
+    /// not a valid regular expression.
+    pub fn pattern(&self, query: &str) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex { query.to_string() } else { regex::escape(query) };
+        let pattern = if self.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern).case_insensitive(!self.case_sensitive).build()
+    }
+
+    /// Replaces every match of a pattern in a text.
+    ///
+    /// With regular expressions the replacement may refer to groups, e.g. `$1`; otherwise it
+    /// is inserted literally.
+    ///
+    /// # Returns
+    ///
+    /// The new text and the number of replaced matches.
+    pub fn replace_all(&self, text: &str, pattern: &Regex, replacement: &str) -> (String, usize) {
+        let count = pattern.find_iter(text).filter(|found| !found.is_empty()).count();
+        if count == 0 {
+            return (text.to_string(), 0);
+        }
+        let replaced = if self.regex {
+            pattern.replace_all(text, replacement)
+        } else {
+            pattern.replace_all(text, NoExpand(replacement))
+        };
+        (replaced.into_owned(), count)
+    }
+
+    /// Replaces the match of a pattern that spans exactly `range` in a text.
+    ///
+    /// # Returns
+    ///
+    /// The new text and the range of the inserted replacement, or `None` if `range` is not a match.
+    pub fn replace_at(
+        &self,
+        text: &str,
+        pattern: &Regex,
+        range: Range<usize>,
+        replacement: &str,
+    ) -> Option<(String, Range<usize>)> {
+        let found = pattern.find_iter(text).find(|found| found.range() == range)?;
+        let inserted = if self.regex {
+            let mut expanded = String::new();
+            pattern.captures_at(text, found.start())?.expand(replacement, &mut expanded);
+            expanded
+        } else {
+            replacement.to_string()
+        };
+        let mut replaced = String::with_capacity(text.len() + inserted.len());
+        replaced.push_str(&text[..range.start]);
+        replaced.push_str(&inserted);
+        replaced.push_str(&text[range.end..]);
+        Some((replaced, range.start..range.start + inserted.len()))
+    }
+}
+
+/// Finds the first match of a pattern that starts at or after `from`, wrapping around to the
+/// start of the text if there is none.
+pub fn find_next(text: &str, pattern: &Regex, from: usize) -> Option<Range<usize>> {
+    let mut matches = pattern.find_iter(text).filter(|found| !found.is_empty()).map(|found| found.range());
+    let first = matches.next()?;
+    if first.start >= from {
+        return Some(first);
+    }
+    Some(matches.find(|found| found.start >= from).unwrap_or(first))
+}
+
+/// Finds the matches of every pattern in a text.
+///
+/// # Returns
+///
+/// The byte ranges of the matches in order, with overlapping matches merged, or `None`
+/// if any of the patterns does not match.
+pub fn match_ranges(matchers: &[Regex], text: &str) -> Option<Vec<Range<usize>>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for matcher in matchers {
+        let before = ranges.len();
+        ranges.extend(matcher.find_iter(text).filter(|found| !found.is_empty()).map(|found| found.range()));
+        if ranges.len() == before {
+            return None;
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    (!merged.is_empty()).then_some(merged)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IndexedNote {
+    modified: SystemTime,
+    terms: HashMap<String, u32>,
+    tags: Vec<String>,
+}
+
+/// An inverted index of the words in every note, persisted in `~/.notes/.index/`.
+///
+/// Only the words of each note are stored on disk; the lookup table from words to
+/// notes is rebuilt when the index is loaded.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SearchIndex {
+    version: u32,
+    notes: HashMap<String, IndexedNote>,
+    #[serde(skip)]
+    postings: HashMap<String, HashSet<String>>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl SearchIndex {
+    /// Loads the index from disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<SearchIndex>` containing the index. An index that is missing, unreadable
+    /// or from an older version is replaced by an empty one, which `refresh` fills in.
+    pub fn load() -> io::Result<SearchIndex> {
+        let path = Self::get_index_file_path()?;
+        let mut index = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_default(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => SearchIndex::default(),
+            Err(err) => return Err(err),
+        };
+        if index.version != INDEX_VERSION {
+            index = SearchIndex {
+                version: INDEX_VERSION,
+                dirty: true,
+                ..SearchIndex::default()
+            };
+        }
+        for (title, note) in &index.notes {
+            for term in note.terms.keys() {
+                index.postings.entry(term.clone()).or_default().insert(title.clone());
+            }
+        }
+        Ok(index)
+    }
+
+    /// Saves the index to disk if it changed since it was loaded or last saved.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let path = Self::get_index_file_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic_write(&path, serde_json::to_string(self)?.as_bytes(), false)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Brings the index up to date with the notes on disk, re-reading only the notes that
+    /// changed since they were indexed.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<usize>` containing the number of notes that were indexed.
+    pub fn refresh(&mut self) -> io::Result<usize> {
+        let metas = Notes::list_notes_with_meta()?;
+        let removed: Vec<String> = self
+            .notes
+            .keys()
+            .filter(|title| !metas.iter().any(|meta| &meta.title == *title))
+            .cloned()
+            .collect();
+        for title in removed {
+            self.remove(&title);
+        }
+        let mut indexed = 0;
+        for meta in metas {
+            let up_to_date = self
+                .notes
+                .get(&meta.title)
+                .is_some_and(|note| note.modified == meta.modified);
+            if up_to_date {
+                continue;
+            }
+            let content = Notes::read_note_file(&meta.title)?;
+            self.insert(&meta.title, &content, meta.modified);
+            indexed += 1;
+        }
+        Ok(indexed)
+    }
+
+    /// Indexes a note that was just saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    /// * `content` - The content that was saved.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn update(&mut self, title: &str, content: &str) -> io::Result<()> {
+        let modified = Notes::note_meta(title)?.modified;
+        self.insert(title, content, modified);
+        Ok(())
+    }
+
+    /// Removes a note from the index.
+    pub fn remove(&mut self, title: &str) {
+        if let Some(note) = self.notes.remove(title) {
+            for term in note.terms.keys() {
+                if let Some(titles) = self.postings.get_mut(term) {
+                    titles.remove(title);
+                    if titles.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+            self.dirty = true;
+        }
+    }
+
+    fn insert(&mut self, title: &str, content: &str, modified: SystemTime) {
+        self.remove(title);
+        // Notes protected with a passphrase are only found by their title
+        let content = if FrontMatter::parse(content).0.is_encrypted() { "" } else { content };
+        let mut terms: HashMap<String, u32> = HashMap::new();
+        for term in tokenize(content).into_iter().chain(tokenize(&attachments::extracted_text(content))) {
+            *terms.entry(term).or_default() += 1;
+        }
+        for term in tokenize(title) {
+            *terms.entry(term).or_default() += TITLE_WEIGHT;
+        }
+        for term in terms.keys() {
+            self.postings.entry(term.clone()).or_default().insert(title.to_string());
+        }
+        let tags = tags::extract(content);
+        self.notes.insert(title.to_string(), IndexedNote { modified, terms, tags });
+        self.dirty = true;
+    }
+
+    /// Returns every tag used in the indexed notes, sorted and without duplicates.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.notes.values().flat_map(|note| note.tags.iter().cloned()).collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Finds the notes that contain every word of a query.
+    ///
+    /// The last word also matches longer words it is the start of, so that results can
+    /// be shown while typing.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The words to look for.
+    ///
+    /// # Returns
+    ///
+    /// The matching notes, best matches first.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let words = tokenize(query);
+        let Some((last, rest)) = words.split_last() else {
+            return Vec::new();
+        };
+
+        let mut scores: Option<HashMap<&str, u32>> = None;
+        for (word, is_prefix) in rest.iter().map(|word| (word, false)).chain([(last, true)]) {
+            let mut matches: HashMap<&str, u32> = HashMap::new();
+            let terms: Vec<&String> = if is_prefix {
+                self.postings.keys().filter(|term| term.starts_with(word.as_str())).collect()
+            } else {
+                self.postings.get_key_value(word).map(|(term, _)| term).into_iter().collect()
+            };
+            for term in terms {
+                for title in &self.postings[term] {
+                    let count = self.notes[title].terms[term];
+                    *matches.entry(title.as_str()).or_default() += count;
+                }
+            }
+            scores = Some(match scores {
+                None => matches,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(title, score)| Some((title, score + matches.get(title)?)))
+                    .collect(),
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(title, score)| SearchHit {
+                title: title.to_string(),
+                score,
+                ranges: Vec::new(),
+                archived: false,
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.title.cmp(&b.title)));
+        hits
+    }
+
+    /// Finds the notes that share the most words with a text, for questions in plain language, which
+    /// rarely contain every word of the notes that answer them.
+    ///
+    /// Each shared word counts more the fewer notes it appears in, so that common words like
+    /// "the" hardly count.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to find notes for.
+    /// * `limit` - The most notes to return.
+    ///
+    /// # Returns
+    ///
+    /// The notes sharing at least one word with the text, best matches first.
+    pub fn search_any(&self, text: &str, limit: usize) -> Vec<SearchHit> {
+        let mut words = tokenize(text);
+        words.sort();
+        words.dedup();
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for word in &words {
+            let Some(titles) = self.postings.get(word) else {
+                continue;
+            };
+            let weight = ((self.notes.len() + 1) as f64 / titles.len() as f64).ln();
+            for title in titles {
+                *scores.entry(title.as_str()).or_default() += self.notes[title].terms[word] as f64 * weight;
+            }
+        }
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|(title, score)| SearchHit {
+                title: title.to_string(),
+                score: (score * 100.0).round() as u32,
+                ranges: Vec::new(),
+                archived: false,
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.title.cmp(&b.title)));
+        hits.truncate(limit);
+        hits
+    }
+
+    /// Finds the notes most like a note, by the TF-IDF cosine similarity of their words and the
+    /// share of their tags they have in common.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    /// * `limit` - The most notes to return.
+    ///
+    /// # Returns
+    ///
+    /// The related notes, most similar first. Empty if the note is not indexed.
+    pub fn related(&self, title: &str, limit: usize) -> Vec<RelatedNote> {
+        let Some(note) = self.notes.get(title) else {
+            return Vec::new();
+        };
+        // Words count more the fewer notes they appear in
+        let weight = |term: &str, count: u32| {
+            let notes = self.postings.get(term).map_or(1, |titles| titles.len()) as f64;
+            count as f64 * ((self.notes.len() + 1) as f64 / notes).ln()
+        };
+        let norm = |other: &IndexedNote| {
+            other.terms.iter().map(|(term, count)| weight(term, *count).powi(2)).sum::<f64>().sqrt()
+        };
+        let note_norm = norm(note);
+
+        let mut related = Vec::new();
+        for (other_title, other) in &self.notes {
+            if other_title == title {
+                continue;
+            }
+            let dot: f64 = note
+                .terms
+                .iter()
+                .filter_map(|(term, count)| Some(weight(term, *count) * weight(term, *other.terms.get(term)?)))
+                .sum();
+            let other_norm = norm(other);
+            let cosine = if dot > 0.0 { dot / (note_norm * other_norm) } else { 0.0 };
+            let shared_tags: Vec<String> = note.tags.iter().filter(|tag| other.tags.contains(tag)).cloned().collect();
+            let all_tags = note.tags.len() + other.tags.len() - shared_tags.len();
+            let tag_share = if all_tags > 0 { shared_tags.len() as f64 / all_tags as f64 } else { 0.0 };
+            let score = cosine + RELATED_TAG_WEIGHT * tag_share;
+            if score >= MIN_RELATED_SCORE {
+                related.push(RelatedNote {
+                    title: other_title.clone(),
+                    score,
+                    shared_tags,
+                });
+            }
+        }
+        related.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.title.cmp(&b.title)));
+        related.truncate(limit);
+        related
+    }
+
+    /// Finds the notes matching a query with the given options.
+    ///
+    /// Word searches are narrowed down with the index before the notes are read. Regular
+    /// expressions can match anywhere, so they read every note.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The words or regular expression to look for.
+    /// * `options` - How the query is matched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<Vec<SearchHit>, String>` containing the matching notes with the ranges of
+    /// their matches, best matches first, or an error if the query is not a valid regular expression.
+    pub fn search_with(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchHit>, String> {
+        if query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let matchers = options.matchers(query).map_err(|err| err.to_string())?;
+        let candidates: Vec<(String, u32)> = if options.regex || options.in_title_only {
+            self.notes.keys().map(|title| (title.clone(), 0)).collect()
+        } else {
+            self.search(query).into_iter().map(|hit| (hit.title, hit.score)).collect()
+        };
+
+        let find = |title: &str, read: fn(&str) -> io::Result<String>| {
+            if options.in_title_only {
+                match_ranges(&matchers, title)
+            } else {
+                // Notes removed since they were indexed are skipped.
+                let content = read(title).ok()?;
+                let extracted = attachments::extracted_text(&content);
+                if extracted.is_empty() {
+                    return match_ranges(&matchers, &content);
+                }
+                // Text read from attached images matches too, but has no range in the note
+                let ranges = match_ranges(&matchers, &format!("{}\n{}", content, extracted))?;
+                Some(ranges.into_iter().filter(|range| range.end <= content.len()).collect())
+            }
+        };
+        let mut hits = Vec::new();
+        for (title, score) in candidates {
+            let Some(ranges) = find(&title, Notes::read_note_file) else {
+                continue;
+            };
+            let score = if score > 0 { score } else { ranges.len() as u32 };
+            hits.push(SearchHit {
+                title,
+                score,
+                ranges,
+                archived: false,
+            });
+        }
+        if options.include_archived {
+            for title in Notes::list_archived().map_err(|err| err.to_string())? {
+                let Some(ranges) = find(&title, Notes::read_archived) else {
+                    continue;
+                };
+                hits.push(SearchHit {
+                    score: ranges.len() as u32,
+                    title,
+                    ranges,
+                    archived: true,
+                });
+            }
+        }
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.title.cmp(&b.title)));
+        Ok(hits)
+    }
+
+    fn get_index_file_path() -> io::Result<PathBuf> {
+        Ok(Notes::get_notes_dir()?.join(INDEX_DIR).join(INDEX_FILE))
+    }
+}
+
+/// Splits text into lowercase words.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    fn titles(hits: Vec<SearchHit>) -> Vec<String> {
+        hits.into_iter().map(|hit| hit.title).collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("groceries", "Buy apples and pears").unwrap();
+        Notes::create_note_file("recipes/pie", "Apple pie needs apples, apples and butter").unwrap();
+
+        let mut index = SearchIndex::load().unwrap();
+        assert_eq!(index.refresh().unwrap(), 2);
+        assert_eq!(index.refresh().unwrap(), 0);
+
+        assert_eq!(titles(index.search("apples")), vec!["recipes/pie", "groceries"]);
+        assert_eq!(titles(index.search("apples pe")), vec!["groceries"]);
+        assert_eq!(titles(index.search("PIE")), vec!["recipes/pie"]);
+        assert!(index.search("bananas").is_empty());
+        assert!(index.search("  ").is_empty());
+
+        Notes::update_note_file("groceries", "Buy bananas").unwrap();
+        index.update("groceries", "Buy bananas").unwrap();
+        assert_eq!(titles(index.search("apples")), vec!["recipes/pie"]);
+        assert_eq!(titles(index.search("bananas")), vec!["groceries"]);
+
+        index.update("groceries", "Buy bananas #shopping #food").unwrap();
+        assert_eq!(index.tags(), vec!["food", "shopping"]);
+
+        assert_eq!(titles(index.search_any("Bananas for the pie?", 5)), vec!["recipes/pie", "groceries"]);
+        assert_eq!(titles(index.search_any("Bananas for the pie?", 1)), vec!["recipes/pie"]);
+        assert!(index.search_any("the", 5).is_empty());
+
+        index.update("groceries", "---\nencrypted: chacha20poly1305\n---\nBuy bananas").unwrap();
+        assert!(index.search("bananas").is_empty());
+        assert_eq!(titles(index.search("groceries")), vec!["groceries"]);
+    }
+
+    #[test]
+    fn test_related() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("rust/ownership", "Borrowing and lifetimes in Rust #rust").unwrap();
+        Notes::create_note_file("rust/traits", "Traits and generics in Rust #rust").unwrap();
+        Notes::create_note_file("lifetimes", "How lifetimes work").unwrap();
+        Notes::create_note_file("garden", "Tomatoes need sun").unwrap();
+        let mut index = SearchIndex::load().unwrap();
+        index.refresh().unwrap();
+
+        let related = index.related("rust/ownership", 5);
+        let related_titles: Vec<&str> = related.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(related_titles, vec!["rust/traits", "lifetimes"]);
+        assert_eq!(related[0].shared_tags, vec!["rust"]);
+        assert!(related[1].shared_tags.is_empty());
+        assert_eq!(index.related("rust/ownership", 1).len(), 1);
+        assert!(index.related("garden", 5).is_empty());
+        assert!(index.related("missing", 5).is_empty());
+    }
+
+    #[test]
+    fn test_search_options() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("cats", "The Cat sat on the mat").unwrap();
+        Notes::create_note_file("catalog", "A catalog of cat toys").unwrap();
+        let mut index = SearchIndex::load().unwrap();
+        index.refresh().unwrap();
+
+        let search = |query: &str, options: SearchOptions| titles(index.search_with(query, &options).unwrap());
+        assert_eq!(search("cat", SearchOptions::default()), vec!["catalog", "cats"]);
+        let case_sensitive = SearchOptions {
+            case_sensitive: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search("Cat", case_sensitive), vec!["cats"]);
+        let whole_word = SearchOptions {
+            whole_word: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search("catalog", whole_word.clone()), vec!["catalog"]);
+        assert!(search("cata", whole_word).is_empty());
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search(r"s\w+ on", regex.clone()), vec!["cats"]);
+        assert!(index.search_with("(", &regex).is_err());
+        let in_title_only = SearchOptions {
+            in_title_only: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search("log", in_title_only), vec!["catalog"]);
+
+        let hits = index.search_with("the", &SearchOptions::default()).unwrap();
+        assert_eq!(hits[0].ranges, vec![0..3, 15..18]);
+
+        Notes::archive("catalog").unwrap();
+        index.refresh().unwrap();
+        let hits = index.search_with("cat", &SearchOptions::default()).unwrap();
+        assert_eq!(titles(hits), vec!["cats"]);
+        let include_archived = SearchOptions {
+            include_archived: true,
+            ..SearchOptions::default()
+        };
+        let hits = index.search_with("toys", &include_archived).unwrap();
+        assert_eq!(titles(hits.clone()), vec!["catalog"]);
+        assert!(hits[0].archived);
+    }
+
+    #[test]
+    fn test_replace() {
+        let literal = SearchOptions::default();
+        let pattern = literal.pattern("a.b").unwrap();
+        assert_eq!(literal.replace_all("A.B axb a.b", &pattern, "$0"), ("$0 axb $0".to_string(), 2));
+
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let pattern = regex.pattern(r"(\w+)@(\w+)").unwrap();
+        assert_eq!(
+            regex.replace_all("me@home you@work", &pattern, "$2:$1"),
+            ("home:me work:you".to_string(), 2)
+        );
+        assert_eq!(
+            regex.replace_at("me@home you@work", &pattern, 8..16, "$2:$1"),
+            Some(("me@home work:you".to_string(), 8..16))
+        );
+        assert_eq!(regex.replace_at("me@home", &pattern, 1..7, "x"), None);
+
+        assert_eq!(find_next("ab ab ab", &literal.pattern("ab").unwrap(), 1), Some(3..5));
+        assert_eq!(find_next("ab ab ab", &literal.pattern("ab").unwrap(), 7), Some(0..2));
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        let matchers = SearchOptions::default().matchers("ab bc").unwrap();
+        assert_eq!(match_ranges(&matchers, "abc ab"), Some(vec![0..3, 4..6]));
+        assert_eq!(match_ranges(&matchers, "ab"), None);
+    }
+
+    #[test]
+    fn test_index_is_persisted() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("a", "persistent words").unwrap();
+        let mut index = SearchIndex::load().unwrap();
+        index.refresh().unwrap();
+        index.save().unwrap();
+
+        let mut index = SearchIndex::load().unwrap();
+        assert_eq!(titles(index.search("persistent")), vec!["a"]);
+        assert_eq!(index.refresh().unwrap(), 0);
+
+        Notes::delete_note_file("a").unwrap();
+        index.refresh().unwrap();
+        assert!(index.search("persistent").is_empty());
+    }
+}
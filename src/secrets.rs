@@ -0,0 +1,389 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+use crate::sync::encrypted::passphrase_secret;
+use crate::sync::{s3, webdav};
+use crate::{ai, share};
+
+/// The service name under which secrets are stored in the OS keychain.
+pub const KEYCHAIN_SERVICE: &str = "notes";
+
+/// The file in the `.notes` directory holding secrets when no OS keychain is available.
+pub const SECRETS_FILE: &str = ".secrets";
+
+/// The file holding the key that encrypts `SECRETS_FILE`. It is only readable by the user.
+pub const SECRETS_KEY_FILE: &str = ".secrets.key";
+
+// The new key while `rotate_key` re-encrypts `SECRETS_FILE`. Like other unfinished writes, it is
+// neither backed up nor synced.
+const PENDING_KEY_FILE: &str = ".secrets.key.tmp";
+
+/// PBKDF2 rounds for keys derived from a new passphrase, which makes guessing the passphrase
+/// slow. Protected notes and sync key files asking for fewer are refused, so that an edited or
+/// tampered file can't weaken the key.
+pub const KDF_ITERATIONS: u32 = 600_000;
+
+/// The most PBKDF2 rounds a protected note or sync key file may ask for, so that an edited or
+/// tampered file can't make deriving the key hang.
+pub const MAX_KDF_ITERATIONS: u32 = 100 * KDF_ITERATIONS;
+
+/// The length of the salt of the key derivation, in bytes.
+pub const KDF_SALT_LEN: usize = 16;
+
+/// Reads a secret, such as an encryption key or API token.
+///
+/// The OS keychain is tried first; secrets that could not be stored there are read from
+/// the encrypted fallback file.
+///
+/// # Arguments
+///
+/// * `name` - The name of the secret.
+///
+/// # Returns
+///
+/// An `io::Result<Option<String>>` containing the secret, or `None` if it is not set.
+pub fn get(name: &str) -> io::Result<Option<String>> {
+    match keychain_entry(name).and_then(|entry| entry.get_password()) {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(load_fallback()?.remove(name)),
+        Err(err) => {
+            log::debug!("Keychain unavailable, using {}: {}", SECRETS_FILE, err);
+            Ok(load_fallback()?.remove(name))
+        }
+    }
+}
+
+/// Stores a secret in the OS keychain, or in the encrypted fallback file if there is no keychain.
+///
+/// # Arguments
+///
+/// * `name` - The name of the secret.
+/// * `secret` - The value to store.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn set(name: &str, secret: &str) -> io::Result<()> {
+    match keychain_entry(name).and_then(|entry| entry.set_password(secret)) {
+        Ok(()) => {
+            // Don't leave a stale copy behind from a time the keychain was unavailable.
+            let mut secrets = load_fallback()?;
+            if secrets.remove(name).is_some() {
+                save_fallback(&secrets)?;
+            }
+            Ok(())
+        }
+        Err(err) => {
+            log::warn!("Keychain unavailable, storing {} in {}: {}", name, SECRETS_FILE, err);
+            let mut secrets = load_fallback()?;
+            secrets.insert(name.to_string(), secret.to_string());
+            save_fallback(&secrets)
+        }
+    }
+}
+
+/// Deletes a secret from both the OS keychain and the fallback file.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure. Deleting a missing secret succeeds.
+pub fn delete(name: &str) -> io::Result<()> {
+    match keychain_entry(name).and_then(|entry| entry.delete_password()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(err) => log::debug!("Keychain unavailable: {}", err),
+    }
+    let mut secrets = load_fallback()?;
+    if secrets.remove(name).is_some() {
+        save_fallback(&secrets)?;
+    }
+    Ok(())
+}
+
+/// Replaces the key of the fallback file with a newly generated one, and re-encrypts the secrets
+/// in the file with it.
+///
+/// This is the only key kept by the app itself. Secrets in the OS keychain are protected by the
+/// keychain, and the sync passphrase is changed in the preferences, as the notes on the server
+/// have to be encrypted again.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of secrets that were re-encrypted.
+pub fn rotate_key() -> io::Result<usize> {
+    let root = Notes::get_notes_dir()?;
+    let secrets = load_fallback()?;
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    let pending_path = root.join(PENDING_KEY_FILE);
+    let _ = fs::remove_file(&pending_path);
+    write_key(&pending_path, &key)?;
+    // If this is interrupted, `load_fallback` finishes the rotation with the pending key
+    let data = encrypt(&ChaCha20Poly1305::new(&key), &serde_json::to_vec(&secrets)?)?;
+    atomic_write(&root.join(SECRETS_FILE), &data, true)?;
+    fs::rename(&pending_path, root.join(SECRETS_KEY_FILE))?;
+    Ok(secrets.len())
+}
+
+/// Generates a random 256-bit key, hex encoded.
+pub fn generate_key() -> String {
+    to_hex(&ChaCha20Poly1305::generate_key(&mut OsRng))
+}
+
+/// Generates a random salt for deriving a key from a passphrase.
+pub fn generate_salt() -> [u8; KDF_SALT_LEN] {
+    let mut salt = [0u8; KDF_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// Derives a 256-bit key from a passphrase with PBKDF2-HMAC-SHA256.
+///
+/// # Arguments
+///
+/// * `passphrase` - The passphrase the user entered.
+/// * `salt` - The salt of the key, see `generate_salt`.
+/// * `iterations` - The PBKDF2 rounds, `KDF_ITERATIONS` for new keys.
+///
+/// # Returns
+///
+/// The derived key.
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// Moves secrets kept in the clear in the configuration file into the keychain.
+///
+/// Hand-edited and older configuration files may hold the sync password, secret key or
+/// passphrase, or an API token, next to the other settings. Secrets already in the keychain are
+/// kept, since they were set later.
+///
+/// # Arguments
+///
+/// * `config` - The configuration read from the file, which names the secrets.
+/// * `data` - The file as JSON.
+///
+/// # Returns
+///
+/// An `io::Result<bool>` indicating whether the file held any secret, in which case it should be
+/// saved again without them.
+pub fn migrate_plaintext(config: &Config, data: &serde_json::Value) -> io::Result<bool> {
+    let fields = plaintext_fields(config, data);
+    for (name, secret) in &fields {
+        if !secret.is_empty() && get(name)?.is_none() {
+            set(name, secret)?;
+        }
+        log::info!("Moved {} out of the configuration file", name);
+    }
+    Ok(!fields.is_empty())
+}
+
+// The secrets in a configuration file, by the name they are stored under
+fn plaintext_fields(config: &Config, data: &serde_json::Value) -> Vec<(String, String)> {
+    let mut fields = vec![
+        ("/ai/api_key", Some(ai::API_KEY_SECRET.to_string())),
+        ("/share/token", Some(share::TOKEN_SECRET.to_string())),
+        ("/sync/passphrase", passphrase_secret(&config.sync)),
+    ];
+    if let Some(settings) = &config.sync.webdav {
+        fields.push(("/sync/webdav/password", Some(webdav::password_secret(&settings.url))));
+    }
+    if let Some(settings) = &config.sync.s3 {
+        fields.push(("/sync/s3/secret_access_key", Some(s3::secret_key_name(settings))));
+    }
+    fields
+        .into_iter()
+        .filter_map(|(pointer, name)| {
+            let secret = data.pointer(pointer)?.as_str()?;
+            Some((name?, secret.to_string()))
+        })
+        .collect()
+}
+
+fn keychain_entry(name: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, name)
+}
+
+fn load_fallback() -> io::Result<BTreeMap<String, String>> {
+    let root = Notes::get_notes_dir()?;
+    let path = root.join(SECRETS_FILE);
+    let pending_path = root.join(PENDING_KEY_FILE);
+    if !path.exists() {
+        let _ = fs::remove_file(&pending_path);
+        return Ok(BTreeMap::new());
+    }
+    let data = fs::read(path)?;
+    let plaintext = match decrypt(&fallback_cipher()?, &data) {
+        Err(err) if err.kind() == io::ErrorKind::InvalidData && pending_path.exists() => {
+            // The secrets were re-encrypted but the new key wasn't moved into place
+            let plaintext = decrypt(&read_key(&pending_path)?, &data)?;
+            fs::rename(&pending_path, root.join(SECRETS_KEY_FILE))?;
+            plaintext
+        }
+        result => {
+            let _ = fs::remove_file(&pending_path);
+            result?
+        }
+    };
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn save_fallback(secrets: &BTreeMap<String, String>) -> io::Result<()> {
+    let plaintext = serde_json::to_vec(secrets)?;
+    let data = encrypt(&fallback_cipher()?, &plaintext)?;
+    atomic_write(&Notes::get_notes_dir()?.join(SECRETS_FILE), &data, true)
+}
+
+/// Loads the key of the fallback file, creating it on first use.
+fn fallback_cipher() -> io::Result<ChaCha20Poly1305> {
+    let path: PathBuf = Notes::get_notes_dir()?.join(SECRETS_KEY_FILE);
+    if !path.exists() {
+        write_key(&path, &ChaCha20Poly1305::generate_key(&mut OsRng))?;
+    }
+    read_key(&path)
+}
+
+// Writes a new key file that only the user can read
+fn write_key(path: &Path, key: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(key)?;
+    file.sync_all()
+}
+
+fn read_key(path: &Path) -> io::Result<ChaCha20Poly1305> {
+    let mut key = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut key)?;
+    if key.len() != 32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is corrupt", SECRETS_KEY_FILE),
+        ));
+    }
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// Encrypts data, prefixing it with the random nonce used.
+pub fn encrypt(cipher: &ChaCha20Poly1305, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Encryption failed"))?;
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+/// Decrypts data produced by `encrypt`.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<u8>>` containing the plaintext, or an `InvalidData` error if the data was
+/// encrypted with another key or tampered with.
+pub fn decrypt(cipher: &ChaCha20Poly1305, data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 12 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Encrypted data is truncated"));
+    }
+    let (nonce, ciphertext) = data.split_at(12);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Decryption failed"))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let cipher = ChaCha20Poly1305::new(&ChaCha20Poly1305::generate_key(&mut OsRng));
+        let data = encrypt(&cipher, b"token").unwrap();
+        assert_ne!(&data[12..], b"token");
+        assert_eq!(decrypt(&cipher, &data).unwrap(), b"token");
+        assert!(decrypt(&cipher, &data[..5]).is_err());
+    }
+
+    #[test]
+    fn test_fallback_file() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        let mut secrets = BTreeMap::new();
+        secrets.insert("api-token".to_string(), "s3cret".to_string());
+        save_fallback(&secrets).unwrap();
+        assert!(!fs::read(temp_notes_dir.join(SECRETS_FILE))
+            .unwrap()
+            .windows(6)
+            .any(|window| window == b"s3cret"));
+        assert_eq!(load_fallback().unwrap(), secrets);
+
+        let old_key = fs::read(temp_notes_dir.join(SECRETS_KEY_FILE)).unwrap();
+        assert_eq!(rotate_key().unwrap(), 1);
+        assert_ne!(fs::read(temp_notes_dir.join(SECRETS_KEY_FILE)).unwrap(), old_key);
+        assert!(!temp_notes_dir.join(PENDING_KEY_FILE).exists());
+        assert_eq!(load_fallback().unwrap(), secrets);
+
+        // A rotation interrupted after re-encrypting is finished on the next read
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        write_key(&temp_notes_dir.join(PENDING_KEY_FILE), &key).unwrap();
+        let data = encrypt(&ChaCha20Poly1305::new(&key), &serde_json::to_vec(&secrets).unwrap()).unwrap();
+        fs::write(temp_notes_dir.join(SECRETS_FILE), data).unwrap();
+        assert_eq!(load_fallback().unwrap(), secrets);
+        assert_eq!(fs::read(temp_notes_dir.join(SECRETS_KEY_FILE)).unwrap(), key.to_vec());
+        assert!(!temp_notes_dir.join(PENDING_KEY_FILE).exists());
+    }
+
+    #[test]
+    fn test_plaintext_fields() {
+        let data = serde_json::json!({
+            "ai": { "api_key": "sk-123" },
+            "sync": {
+                "service": "WebDav",
+                "webdav": { "url": "https://dav.example.com/notes", "username": "me", "password": "hunter2" },
+                "passphrase": "correct horse",
+            },
+        });
+        let config: Config = serde_json::from_value(data.clone()).unwrap();
+        let fields = plaintext_fields(&config, &data);
+        assert_eq!(
+            fields,
+            vec![
+                (ai::API_KEY_SECRET.to_string(), "sk-123".to_string()),
+                (
+                    "sync-passphrase:https://dav.example.com/notes".to_string(),
+                    "correct horse".to_string()
+                ),
+                ("webdav:https://dav.example.com/notes".to_string(), "hunter2".to_string()),
+            ]
+        );
+        assert!(!serde_json::to_string(&config).unwrap().contains("hunter2"));
+        assert!(plaintext_fields(&Config::default(), &serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let key = generate_key();
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, generate_key());
+    }
+}
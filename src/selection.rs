@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+
+/// The notes selected in the note list to act on all at once, picked like files in a file
+/// manager: Ctrl (Cmd on macOS) adds or removes a note, Shift selects the notes up to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    selected: BTreeSet<String>,
+    anchor: Option<String>,
+}
+
+impl Selection {
+    /// Updates the selection after a note in the list was clicked.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The note that was clicked.
+    /// * `toggle` - Whether Ctrl or Cmd was held, which adds or removes the note.
+    /// * `range` - Whether Shift was held, which selects the notes from the one clicked last.
+    /// * `listed` - The notes in the order they are listed.
+    ///
+    /// # Returns
+    ///
+    /// Whether the click only changed the selection. A plain click clears the selection and
+    /// should open the note instead.
+    pub fn click(&mut self, title: &str, toggle: bool, range: bool, listed: &[String]) -> bool {
+        let position = |title: &str| listed.iter().position(|note| note == title);
+        let anchor = self.anchor.as_deref().and_then(position);
+        match (range, anchor, position(title)) {
+            (true, Some(anchor), Some(clicked)) => {
+                if !toggle {
+                    self.selected.clear();
+                }
+                let (first, last) = (anchor.min(clicked), anchor.max(clicked));
+                self.selected.extend(listed[first..=last].iter().cloned());
+                true
+            }
+            _ if toggle || range => {
+                if !self.selected.remove(title) {
+                    self.selected.insert(title.to_string());
+                }
+                self.anchor = Some(title.to_string());
+                true
+            }
+            _ => {
+                self.selected.clear();
+                self.anchor = Some(title.to_string());
+                false
+            }
+        }
+    }
+
+    /// Whether a note is selected.
+    pub fn contains(&self, title: &str) -> bool {
+        self.selected.contains(title)
+    }
+
+    /// Whether no note is selected.
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    /// How many notes are selected.
+    pub fn len(&self) -> usize {
+        self.selected.len()
+    }
+
+    /// Returns the selected notes in alphabetical order.
+    pub fn titles(&self) -> Vec<String> {
+        self.selected.iter().cloned().collect()
+    }
+
+    /// Forgets notes that are gone, e.g. after they were deleted or moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `listed` - The notes that still exist.
+    pub fn retain(&mut self, listed: &[String]) {
+        self.selected.retain(|title| listed.contains(title));
+        if self.anchor.as_ref().is_some_and(|anchor| !listed.contains(anchor)) {
+            self.anchor = None;
+        }
+    }
+
+    /// Selects no note.
+    pub fn clear(&mut self) {
+        self.selected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed() -> Vec<String> {
+        ["a", "b", "c", "d", "e"].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn test_click() {
+        let listed = listed();
+        let mut selection = Selection::default();
+        assert!(!selection.click("b", false, false, &listed));
+        assert!(selection.is_empty());
+
+        assert!(selection.click("d", false, true, &listed));
+        assert_eq!(selection.titles(), vec!["b", "c", "d"]);
+        assert!(selection.click("c", true, false, &listed));
+        assert_eq!(selection.titles(), vec!["b", "d"]);
+        // The last note toggled is where the next range starts
+        assert!(selection.click("e", false, true, &listed));
+        assert_eq!(selection.titles(), vec!["c", "d", "e"]);
+        assert!(selection.click("a", true, false, &listed));
+        assert!(selection.click("b", true, true, &listed));
+        assert_eq!(selection.titles(), vec!["a", "b", "c", "d", "e"]);
+
+        assert!(!selection.click("c", false, false, &listed));
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut selection = Selection::default();
+        selection.click("a", true, false, &listed());
+        selection.click("c", true, false, &listed());
+        selection.retain(&["a".to_string(), "b".to_string()]);
+        assert_eq!(selection.titles(), vec!["a"]);
+        // The anchor was removed, so Shift starts a new range
+        assert!(selection.click("b", false, true, &listed()));
+        assert_eq!(selection.titles(), vec!["a", "b"]);
+        assert_eq!(selection.len(), 2);
+    }
+}
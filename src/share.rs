@@ -0,0 +1,174 @@
+//! Sharing a note by uploading it to a paste service and handing out the link.
+
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::{ShareService, ShareSettings};
+use crate::export;
+use crate::front_matter::FrontMatter;
+use crate::lang::NoteLanguage;
+use crate::secrets;
+
+/// The name of the secret holding the token of the paste service.
+pub const TOKEN_SECRET: &str = "share-token";
+
+const GIST_API: &str = "https://api.github.com/gists";
+const ZERO_X_ZERO: &str = "https://0x0.st";
+
+#[derive(Deserialize)]
+struct Gist {
+    html_url: String,
+}
+
+/// Uploads a note to the service chosen in the preferences. Gists get the Markdown, which GitHub
+/// renders; the other services get the note rendered as a web page.
+///
+/// # Arguments
+///
+/// * `settings` - Where to upload to.
+/// * `title` - The title of the note.
+/// * `content` - The content of the note. Its front matter is left out.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the URL of the shared note, or an `InvalidInput` error if the
+/// note is protected with a passphrase.
+pub fn share(settings: &ShareSettings, title: &str, content: &str) -> io::Result<String> {
+    let (front_matter, body) = FrontMatter::parse(content);
+    if front_matter.is_encrypted() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Unlock the note to share it; only what it says is shared, not the passphrase",
+        ));
+    }
+    let language = NoteLanguage::of(content);
+    let token = secrets::get(TOKEN_SECRET)?.filter(|token| !token.is_empty());
+    match settings.service {
+        ShareService::Gist => {
+            let token = token.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "Sharing as a gist needs a GitHub token; add one in Preferences",
+                )
+            })?;
+            let response = ureq::post(GIST_API)
+                .set("Accept", "application/vnd.github+json")
+                .set("Authorization", &format!("Bearer {}", token))
+                .set("Content-Type", "application/json")
+                .send_string(&gist_body(title, body).to_string())
+                .map_err(upload_error)?;
+            let gist: Gist = serde_json::from_str(&response.into_string()?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Ok(gist.html_url)
+        }
+        ShareService::ZeroXZero => {
+            let boundary = boundary();
+            let data = multipart(&boundary, &file_name(title, "html"), &page(title, language.as_ref(), body));
+            let response = ureq::post(ZERO_X_ZERO)
+                .set("Content-Type", &format!("multipart/form-data; boundary={}", boundary))
+                .send_bytes(&data)
+                .map_err(upload_error)?;
+            answered_url(response)
+        }
+        ShareService::Custom => {
+            if settings.url.trim().is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Enter the URL of the paste service in Preferences",
+                ));
+            }
+            let mut request = ureq::post(settings.url.trim()).set("Content-Type", "text/html; charset=utf-8");
+            if let Some(token) = &token {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            answered_url(request.send_string(&page(title, language.as_ref(), body)).map_err(upload_error)?)
+        }
+    }
+}
+
+/// The request that creates a secret gist holding a note.
+pub fn gist_body(title: &str, body: &str) -> serde_json::Value {
+    let mut files = serde_json::Map::new();
+    files.insert(file_name(title, "md"), json!({ "content": body }));
+    json!({
+        "description": title,
+        "public": false,
+        "files": files,
+    })
+}
+
+// The note as a standalone web page
+fn page(title: &str, language: Option<&NoteLanguage>, body: &str) -> String {
+    export::page(title, language, "", &export::body_html(body, "", &|_| None))
+}
+
+// The name of the uploaded file, e.g. `ideas.md` for `work/ideas`
+fn file_name(title: &str, extension: &str) -> String {
+    let name = title.rsplit('/').next().unwrap_or(title).replace('"', "'");
+    format!("{}.{}", name, extension)
+}
+
+/// Encodes a file as a `multipart/form-data` upload in the `file` field, asking for a URL that
+/// can't be guessed with an empty `secret` field.
+pub fn multipart(boundary: &str, file_name: &str, data: &str) -> Vec<u8> {
+    format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: text/html; charset=utf-8\r\n\r\n{data}\r\n--{boundary}\r\nContent-Disposition: form-data; name=\"secret\"\r\n\r\n\r\n--{boundary}--\r\n"
+    )
+    .into_bytes()
+}
+
+fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    format!("notes-share-{:x}", nanos)
+}
+
+// Services that take plain uploads answer with the URL alone
+fn answered_url(response: ureq::Response) -> io::Result<String> {
+    let url = response.into_string()?.trim().to_string();
+    if !url.starts_with("http") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The service answered something other than a link: {}", url),
+        ));
+    }
+    Ok(url)
+}
+
+fn upload_error(err: ureq::Error) -> io::Error {
+    match err {
+        ureq::Error::Status(code, response) => {
+            let message = response.into_string().unwrap_or_default();
+            io::Error::new(io::ErrorKind::Other, format!("The service answered {}: {}", code, message.trim()))
+        }
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uploads() {
+        let body = gist_body("work/ideas", "# Ideas\n");
+        assert_eq!(body["public"], false);
+        assert_eq!(body["files"]["ideas.md"]["content"], "# Ideas\n");
+
+        let data = String::from_utf8(multipart("b0undary", "ideas.html", "<p>Hi</p>")).unwrap();
+        assert!(data.starts_with("--b0undary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"ideas.html\"\r\n"));
+        assert!(data.contains("\r\n\r\n<p>Hi</p>\r\n--b0undary\r\n"));
+        assert!(data.ends_with("name=\"secret\"\r\n\r\n\r\n--b0undary--\r\n"));
+    }
+
+    #[test]
+    fn test_protected_notes_are_not_shared() {
+        let err = share(&ShareSettings::default(), "diary", "---\nencrypted: chacha20poly1305\n---\nk9Vd").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::ops::Range;
+
+use crate::front_matter::FrontMatter;
+use crate::highlight::{self, TokenKind};
+use crate::lang::NoteLanguage;
+use crate::storage;
+use crate::vault;
+
+/// The folder in the `.notes` directory that holds a dictionary per language, e.g. `de.dic`.
+///
+/// Hunspell `.dic` files can be used as they are, as can plain lists with one word per line.
+pub const DICTIONARIES_DIR: &str = ".dictionaries";
+
+/// The words of a language that are spelled correctly.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Reads a dictionary from the text of a `.dic` file or a word list.
+    ///
+    /// The word count on the first line of Hunspell dictionaries and the affix flags after a `/`
+    /// are skipped.
+    pub fn parse(text: &str) -> Dictionary {
+        let words = text
+            .lines()
+            .map(|line| line.split('/').next().unwrap_or_default().trim())
+            .filter(|word| {
+                !word.is_empty() && !word.starts_with('#') && !word.chars().all(|c| c.is_ascii_digit())
+            })
+            .map(str::to_string)
+            .collect();
+        Dictionary { words }
+    }
+
+    /// Loads the dictionary of a language from the dictionaries folder.
+    ///
+    /// A dictionary for the full code, e.g. `pt-br.dic`, is preferred over one for the language
+    /// alone, e.g. `pt.dic`.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - The language of the note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Option<Dictionary>>` containing the dictionary, or `None` if there is none
+    /// for the language.
+    pub fn load(language: &NoteLanguage) -> io::Result<Option<Dictionary>> {
+        let dir = vault::home_root()?.join(DICTIONARIES_DIR);
+        for code in [language.code.as_str(), language.primary()] {
+            match storage::read_to_string(&dir.join(format!("{}.dic", code))) {
+                Ok(text) => return Ok(Some(Dictionary::parse(&text))),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether a word is spelled correctly. Words capitalized at the start of a sentence are
+    /// looked up in lower case too.
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word) || self.words.contains(&word.to_lowercase())
+    }
+
+    /// Finds the misspelled words in the prose of a note.
+    ///
+    /// The front matter, code, links, tags and words with digits or in capitals, e.g. acronyms,
+    /// are not checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The full content of the note.
+    ///
+    /// # Returns
+    ///
+    /// The byte ranges of the misspelled words, in order.
+    pub fn misspelled(&self, text: &str) -> Vec<Range<usize>> {
+        let (_, body) = FrontMatter::parse(text);
+        let body_start = text.len() - body.len();
+        let mut misspelled = Vec::new();
+        for (range, kind) in highlight::tokenize(text, "") {
+            if kind != TokenKind::Prose || range.end <= body_start {
+                continue;
+            }
+            let start = range.start.max(body_start);
+            for (offset, chunk) in chunks(&text[start..range.end]) {
+                let skipped = ["://", "[[", "`", "@"].iter().any(|marker| chunk.contains(marker));
+                if skipped || chunk.starts_with('#') {
+                    continue;
+                }
+                for (word_offset, word) in words(chunk) {
+                    let checked = word.chars().count() > 1
+                        && !word.chars().any(|c| c.is_numeric())
+                        && word.chars().any(|c| c.is_lowercase())
+                        && !self.contains(word);
+                    if checked {
+                        let word_start = start + offset + word_offset;
+                        misspelled.push(word_start..word_start + word.len());
+                    }
+                }
+            }
+        }
+        misspelled
+    }
+}
+
+/// The dictionaries loaded so far, by language code, so that switching between notes in
+/// different languages switches the dictionary without reading it again.
+#[derive(Debug, Default)]
+pub struct Dictionaries {
+    loaded: HashMap<String, Option<Dictionary>>,
+}
+
+impl Dictionaries {
+    /// Loads the dictionary of a language unless it was loaded before.
+    pub fn load(&mut self, language: &NoteLanguage) {
+        if self.loaded.contains_key(&language.code) {
+            return;
+        }
+        let dictionary = Dictionary::load(language).unwrap_or_else(|err| {
+            log::warn!("Failed to load the {} dictionary: {}", language.name(), err);
+            None
+        });
+        self.loaded.insert(language.code.clone(), dictionary);
+    }
+
+    /// Returns the dictionary of a language loaded with `load`.
+    ///
+    /// # Returns
+    ///
+    /// The dictionary, or `None` if there is none for the language, it can't be read or it
+    /// wasn't loaded.
+    pub fn get(&self, language: &NoteLanguage) -> Option<&Dictionary> {
+        self.loaded.get(&language.code)?.as_ref()
+    }
+}
+
+// Apostrophes inside a word belong to it, e.g. in `don't`
+const APOSTROPHES: [char; 2] = ['\'', '’'];
+
+// The runs of text between whitespace, along with their byte offsets
+fn chunks(text: &str) -> Vec<(usize, &str)> {
+    runs(text, |c| !c.is_whitespace())
+}
+
+// The words of a chunk without the quotes around them, along with their byte offsets
+fn words(chunk: &str) -> Vec<(usize, &str)> {
+    runs(chunk, |c| c.is_alphanumeric() || APOSTROPHES.contains(&c))
+        .into_iter()
+        .filter_map(|(offset, run)| {
+            let trimmed = run.trim_start_matches(APOSTROPHES);
+            let word = trimmed.trim_end_matches(APOSTROPHES);
+            (!word.is_empty()).then_some((offset + run.len() - trimmed.len(), word))
+        })
+        .collect()
+}
+
+// The runs of characters that belong together, along with their byte offsets
+fn runs(text: &str, belongs: impl Fn(char) -> bool) -> Vec<(usize, &str)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        let inside = index < text.len() && belongs(c);
+        match start {
+            None if inside => start = Some(index),
+            Some(run_start) if !inside => {
+                runs.push((run_start, &text[run_start..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+    use std::fs;
+
+    fn misspelled<'a>(dictionary: &Dictionary, text: &'a str) -> Vec<&'a str> {
+        dictionary.misspelled(text).into_iter().map(|range| &text[range]).collect()
+    }
+
+    #[test]
+    fn test_misspelled() {
+        let dictionary = Dictionary::parse("5\nthe/S\ncat\nsat\non\nmat's\n");
+        assert!(dictionary.contains("The"));
+        assert!(!dictionary.contains("5"));
+        assert_eq!(misspelled(&dictionary, "The catt sat on the matt."), vec!["catt", "matt"]);
+        assert_eq!(misspelled(&dictionary, "'The' mat's NASA 2nd a"), Vec::<&str>::new());
+
+        let text = "---\nlang: en\n---\nThe dogg #tagg [[Linkk]] https://examplle.com\n```\nlett x\n```\ncatt";
+        assert_eq!(misspelled(&dictionary, text), vec!["dogg", "catt"]);
+    }
+
+    #[test]
+    fn test_load_dictionary_of_language() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let dir = temp_notes_dir.join(DICTIONARIES_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("de.dic"), "2\nHund/S\nKatze\n").unwrap();
+        fs::write(dir.join("pt-br.dic"), "ônibus\n").unwrap();
+        fs::write(dir.join("pt.dic"), "autocarro\n").unwrap();
+
+        let german = NoteLanguage::of("---\nlang: de-AT\n---\nDer Hund").unwrap();
+        let mut dictionaries = Dictionaries::default();
+        assert!(dictionaries.get(&german).is_none());
+        dictionaries.load(&german);
+        let dictionary = dictionaries.get(&german).unwrap();
+        assert!(dictionary.contains("Hund"));
+        assert!(!dictionary.contains("dog"));
+
+        let brazilian = NoteLanguage::of("---\nlang: pt-BR\n---\n").unwrap();
+        dictionaries.load(&brazilian);
+        assert!(dictionaries.get(&brazilian).unwrap().contains("ônibus"));
+        assert!(!dictionaries.get(&brazilian).unwrap().contains("autocarro"));
+        let french = NoteLanguage::of("---\nlang: fr\n---\n").unwrap();
+        dictionaries.load(&french);
+        assert!(dictionaries.get(&french).is_none());
+    }
+}
@@ -0,0 +1,366 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::config::NoteStoreKind;
+use crate::notes::NoteMeta;
+use crate::store::NoteStore;
+use crate::title;
+use crate::vault::{self, Vault};
+
+/// The name of the database in the notes directory when notes are kept in SQLite.
+pub const DATABASE_FILE: &str = ".notes.db";
+
+// Notes and their full-text index, which is kept up to date in the same transaction
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS notes (
+        title TEXT PRIMARY KEY NOT NULL,
+        content TEXT NOT NULL,
+        modified INTEGER NOT NULL,
+        created INTEGER
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(title, content);
+";
+
+/// Keeps notes in a SQLite database, with a full-text index of their titles and contents.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    // The database file, or `None` in memory
+    path: Option<PathBuf>,
+}
+
+impl SqliteStore {
+    /// Opens a database, creating it if it doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The database file.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<SqliteStore>` containing the store.
+    pub fn open(path: &Path) -> io::Result<SqliteStore> {
+        let connection = Connection::open(path).map_err(db_error)?;
+        connection.execute_batch(SCHEMA).map_err(db_error)?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Opens a database in memory, e.g. for tests.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<SqliteStore>` containing the store.
+    pub fn open_in_memory() -> io::Result<SqliteStore> {
+        let connection = Connection::open_in_memory().map_err(db_error)?;
+        connection.execute_batch(SCHEMA).map_err(db_error)?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            path: None,
+        })
+    }
+
+    /// Copies all notes of a vault into the database in one transaction, keeping their
+    /// timestamps. Notes already in the database are replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `vault` - The vault to import.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<usize>` containing the number of notes imported.
+    pub(crate) fn import(&self, vault: &Vault) -> io::Result<usize> {
+        self.check_writable()?;
+        let notes = vault.list_notes_with_meta()?;
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(db_error)?;
+        for note in &notes {
+            let content = vault.read_note_file(&note.title)?;
+            put(&transaction, &note.title, &content, note.modified, note.created)?;
+        }
+        transaction.commit().map_err(db_error)?;
+        Ok(notes.len())
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // The database is changed only by the window holding the lock of the vault, like the note files
+    fn check_writable(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => vault::check_writable(path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl NoteStore for SqliteStore {
+    fn list(&self) -> io::Result<Vec<NoteMeta>> {
+        let connection = self.connection();
+        let mut statement = connection
+            .prepare("SELECT title, modified, created FROM notes")
+            .map_err(db_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(NoteMeta {
+                    title: row.get(0)?,
+                    modified: from_millis(row.get(1)?),
+                    created: row.get::<_, Option<i64>>(2)?.map(from_millis),
+                })
+            })
+            .map_err(db_error)?;
+        rows.collect::<Result<_, _>>().map_err(db_error)
+    }
+
+    fn read(&self, title: &str) -> io::Result<String> {
+        self.connection()
+            .query_row("SELECT content FROM notes WHERE title = ?1", [title], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| not_found(title))
+    }
+
+    fn write(&self, title: &str, content: &str) -> io::Result<()> {
+        title::validate(title)?;
+        self.check_writable()?;
+        let now = SystemTime::now();
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(db_error)?;
+        let created: Option<Option<i64>> = transaction
+            .query_row("SELECT created FROM notes WHERE title = ?1", [title], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        let created = match created {
+            Some(created) => created.map(from_millis),
+            None => Some(now),
+        };
+        put(&transaction, title, content, now, created)?;
+        transaction.commit().map_err(db_error)
+    }
+
+    fn delete(&self, title: &str) -> io::Result<()> {
+        self.check_writable()?;
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(db_error)?;
+        let deleted = transaction
+            .execute("DELETE FROM notes WHERE title = ?1", [title])
+            .map_err(db_error)?;
+        if deleted == 0 {
+            return Err(not_found(title));
+        }
+        transaction
+            .execute("DELETE FROM notes_fts WHERE title = ?1", [title])
+            .map_err(db_error)?;
+        transaction.commit().map_err(db_error)
+    }
+
+    fn metadata(&self, title: &str) -> io::Result<NoteMeta> {
+        self.connection()
+            .query_row(
+                "SELECT modified, created FROM notes WHERE title = ?1",
+                [title],
+                |row| {
+                    Ok(NoteMeta {
+                        title: title.to_string(),
+                        modified: from_millis(row.get(0)?),
+                        created: row.get::<_, Option<i64>>(1)?.map(from_millis),
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| not_found(title))
+    }
+
+    fn search(&self, query: &str) -> io::Result<Option<Vec<(String, f64)>>> {
+        // Each word is quoted so that FTS5 operators in the query are matched literally
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect();
+        if words.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+        let connection = self.connection();
+        let mut statement = connection
+            .prepare("SELECT title, bm25(notes_fts) FROM notes_fts WHERE notes_fts MATCH ?1 ORDER BY bm25(notes_fts)")
+            .map_err(db_error)?;
+        // bm25 is lower for better matches
+        let rows = statement
+            .query_map([words.join(" ")], |row| Ok((row.get(0)?, -row.get::<_, f64>(1)?)))
+            .map_err(db_error)?;
+        Ok(Some(rows.collect::<Result<_, _>>().map_err(db_error)?))
+    }
+}
+
+/// Imports the notes of the active vault into its database and switches the vault over to it.
+/// The note files are left in place.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of notes imported.
+pub fn migrate() -> io::Result<usize> {
+    let vault = Vault::active()?;
+    let store = SqliteStore::open(&vault.root().join(DATABASE_FILE))?;
+    let imported = store.import(&vault)?;
+    let mut config = vault.config().clone();
+    config.note_store = NoteStoreKind::Sqlite;
+    config.save_in(vault.root())?;
+    Ok(imported)
+}
+
+/// Checks that the notes of the active vault are kept as files, for what works on the files
+/// directly, such as sync and backups.
+///
+/// # Arguments
+///
+/// * `feature` - What needs the note files, e.g. `Sync`, for the error message.
+///
+/// # Returns
+///
+/// An `io::Result<()>` with an `Unsupported` error if the notes are kept in the database.
+pub fn require_files(feature: &str) -> io::Result<()> {
+    if Vault::active()?.config().note_store == NoteStoreKind::Sqlite {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} works with note files, but the notes of this vault are kept in {}",
+                feature, DATABASE_FILE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Replaces a note and its entry in the full-text index
+fn put(
+    transaction: &rusqlite::Transaction<'_>,
+    title: &str,
+    content: &str,
+    modified: SystemTime,
+    created: Option<SystemTime>,
+) -> io::Result<()> {
+    transaction
+        .execute(
+            "INSERT INTO notes (title, content, modified, created) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(title) DO UPDATE SET content = excluded.content, modified = excluded.modified",
+            params![title, content, to_millis(modified), created.map(to_millis)],
+        )
+        .map_err(db_error)?;
+    transaction
+        .execute("DELETE FROM notes_fts WHERE title = ?1", [title])
+        .map_err(db_error)?;
+    transaction
+        .execute("INSERT INTO notes_fts (title, content) VALUES (?1, ?2)", [title, content])
+        .map_err(db_error)?;
+    Ok(())
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis() as i64)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn not_found(title: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No note named {}", title))
+}
+
+fn db_error(err: rusqlite::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::Notes;
+    use crate::testing::setup_temp_notes_dir;
+    use crate::vault::{LockInfo, LOCK_FILE};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sqlite_store() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store.write("meetings/standup", "Talked about the release").unwrap();
+        let created = store.metadata("meetings/standup").unwrap().created;
+        store.write("meetings/standup", "Talked about the \"beta\" release").unwrap();
+        store.write("Ideas", "A garden on the roof").unwrap();
+        assert_eq!(store.metadata("meetings/standup").unwrap().created, created);
+        assert_eq!(store.read("meetings/standup").unwrap(), "Talked about the \"beta\" release");
+        assert_eq!(store.list().unwrap().len(), 2);
+
+        let hits = store.search("release").unwrap().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0, "meetings/standup");
+        assert!(store.search("garden OR \"").unwrap().unwrap().is_empty());
+
+        store.delete("meetings/standup").unwrap();
+        assert!(store.search("release").unwrap().unwrap().is_empty());
+        assert_eq!(store.read("meetings/standup").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(store.delete("meetings/standup").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_import() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().to_path_buf()).unwrap();
+        vault.create_note_file("Ideas", "A garden on the roof").unwrap();
+        vault.create_note_file("meetings/standup", "Release").unwrap();
+        let store = SqliteStore::open(&temp_dir.path().join(DATABASE_FILE)).unwrap();
+        assert_eq!(store.import(&vault).unwrap(), 2);
+        assert_eq!(store.read("meetings/standup").unwrap(), "Release");
+        let modified = vault.list_notes_with_meta().unwrap().into_iter().find(|note| note.title == "Ideas").unwrap().modified;
+        assert_eq!(to_millis(store.metadata("Ideas").unwrap().modified), to_millis(modified));
+        // The database is hidden from the vault
+        assert_eq!(vault.list_notes().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_migrated_vault() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+        Notes::create_note_file("Ideas", "A garden on the roof").unwrap();
+        require_files("Sync").unwrap();
+        assert_eq!(migrate().unwrap(), 1);
+        assert_eq!(require_files("Sync").unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        // Archiving moves the note out of the database, not its stale file
+        Notes::update_note_file("Ideas", "A garden on the balcony").unwrap();
+        Notes::archive("Ideas").unwrap();
+        assert!(Notes::list_notes().unwrap().is_empty());
+        assert_eq!(Notes::read_archived("Ideas").unwrap(), "A garden on the balcony");
+        Notes::unarchive("Ideas").unwrap();
+        assert_eq!(Notes::read_note_file("Ideas").unwrap(), "A garden on the balcony");
+    }
+
+    #[test]
+    fn test_read_only_window() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let store = SqliteStore::open(&root.join(DATABASE_FILE)).unwrap();
+        store.write("Ideas", "A garden on the roof").unwrap();
+
+        // Another running window holds the lock
+        let now = to_millis(SystemTime::now()) as u64 / 1000;
+        let holder = LockInfo {
+            pid: u32::MAX,
+            since: now,
+            heartbeat: now,
+        };
+        fs::write(root.join(LOCK_FILE), serde_json::to_string(&holder).unwrap()).unwrap();
+        let vault = Vault::open_at(root).unwrap();
+        assert!(vault.is_read_only());
+        assert_eq!(store.write("Ideas", "Changed").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(store.delete("Ideas").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(store.read("Ideas").unwrap(), "A garden on the roof");
+    }
+}
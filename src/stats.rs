@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::front_matter::FrontMatter;
+use crate::links;
+use crate::notes::Notes;
+use crate::storage::atomic_write;
+use crate::tags;
+
+/// Metrics about a single note, as exported for analysis in external tools.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct NoteMetrics {
+    /// The title of the note.
+    pub title: String,
+    /// The size of the note file in bytes.
+    pub size_bytes: usize,
+    /// The number of words in the body of the note, excluding front matter.
+    pub word_count: usize,
+    /// The number of characters in the body of the note, excluding front matter.
+    pub char_count: usize,
+    /// The estimated time to read the note, in minutes.
+    pub reading_minutes: usize,
+    /// The creation time as a UNIX timestamp, if the platform reports one.
+    pub created: Option<u64>,
+    /// The last modification time as a UNIX timestamp.
+    pub modified: u64,
+    /// The tags of the note.
+    pub tags: Vec<String>,
+    /// The number of wiki links to other notes.
+    pub outgoing_links: usize,
+    /// The number of wiki links from other notes to this note.
+    pub incoming_links: usize,
+    /// The number of links to web pages.
+    pub external_links: usize,
+}
+
+/// Statistics about the whole vault, as shown in the Stats dialog.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VaultStats {
+    /// The number of notes.
+    pub total_notes: usize,
+    /// The number of words across all notes.
+    pub total_words: usize,
+    /// The number of notes with each tag, most used first.
+    pub notes_per_tag: Vec<(String, usize)>,
+    /// The number of notes created in each ISO week (`YYYY-Www`), oldest first.
+    pub created_per_week: Vec<(String, usize)>,
+}
+
+impl VaultStats {
+    /// Summarizes the metrics of every note.
+    pub fn from_metrics(metrics: &[NoteMetrics]) -> VaultStats {
+        let mut tags: HashMap<&str, usize> = HashMap::new();
+        let mut weeks: BTreeMap<String, usize> = BTreeMap::new();
+        for note in metrics {
+            for tag in &note.tags {
+                *tags.entry(tag).or_default() += 1;
+            }
+            let week = note
+                .created
+                .and_then(|created| DateTime::from_timestamp(created as i64, 0))
+                .map(|created| created.with_timezone(&Local).format("%G-W%V").to_string());
+            if let Some(week) = week {
+                *weeks.entry(week).or_default() += 1;
+            }
+        }
+        let mut notes_per_tag: Vec<(String, usize)> =
+            tags.into_iter().map(|(tag, count)| (tag.to_string(), count)).collect();
+        notes_per_tag.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        VaultStats {
+            total_notes: metrics.len(),
+            total_words: metrics.iter().map(|note| note.word_count).sum(),
+            notes_per_tag,
+            created_per_week: weeks.into_iter().collect(),
+        }
+    }
+}
+
+/// The reading speed used to estimate reading times.
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// Counts the words and characters in the body of a note, excluding front matter.
+pub fn body_counts(content: &str) -> (usize, usize) {
+    let (_, body) = FrontMatter::parse(content);
+    (body.split_whitespace().count(), body.chars().count())
+}
+
+/// Estimates the time to read a number of words, in whole minutes. Any text takes at least a minute.
+pub fn reading_minutes(words: usize) -> usize {
+    words.div_ceil(WORDS_PER_MINUTE)
+}
+
+/// The file format of an exported statistics file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// A JSON array of objects.
+    Json,
+}
+
+/// Computes the metrics of every note in the vault.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<NoteMetrics>>` containing the metrics or an error.
+pub fn collect() -> io::Result<Vec<NoteMetrics>> {
+    let mut notes = Vec::new();
+    for meta in Notes::list_notes_with_meta()? {
+        let content = Notes::read_note_file(&meta.title)?;
+        notes.push((meta, content));
+    }
+
+    let mut incoming: HashMap<String, usize> = HashMap::new();
+    for (_, content) in &notes {
+        for target in links::wiki_links(content) {
+            *incoming.entry(target).or_default() += 1;
+        }
+    }
+
+    Ok(notes
+        .into_iter()
+        .map(|(meta, content)| {
+            let (word_count, char_count) = body_counts(&content);
+            NoteMetrics {
+                size_bytes: content.len(),
+                word_count,
+                char_count,
+                reading_minutes: reading_minutes(word_count),
+                created: meta.created.map(unix_seconds),
+                modified: unix_seconds(meta.modified),
+                tags: tags::extract(&content),
+                outgoing_links: links::wiki_links(&content).len(),
+                incoming_links: incoming.get(&meta.title).copied().unwrap_or(0),
+                external_links: links::external_link_count(&content),
+                title: meta.title,
+            }
+        })
+        .collect())
+}
+
+/// Exports the metrics of every note in the vault to a file.
+///
+/// # Arguments
+///
+/// * `path` - The file to write.
+/// * `format` - The format of the file.
+///
+/// # Returns
+///
+/// An `io::Result<usize>` containing the number of notes exported or an error.
+pub fn export(path: &Path, format: ExportFormat) -> io::Result<usize> {
+    let metrics = collect()?;
+    let data = match format {
+        ExportFormat::Csv => to_csv(&metrics),
+        ExportFormat::Json => serde_json::to_string_pretty(&metrics)?,
+    };
+    atomic_write(path, data.as_bytes(), true)?;
+    Ok(metrics.len())
+}
+
+/// Renders metrics as CSV. Tags are separated by `;` within their column.
+pub fn to_csv(metrics: &[NoteMetrics]) -> String {
+    let mut csv = String::from(
+        "title,size_bytes,word_count,char_count,reading_minutes,created,modified,tags,outgoing_links,incoming_links,external_links\n",
+    );
+    for note in metrics {
+        let row = [
+            csv_field(&note.title),
+            note.size_bytes.to_string(),
+            note.word_count.to_string(),
+            note.char_count.to_string(),
+            note.reading_minutes.to_string(),
+            note.created.map(|created| created.to_string()).unwrap_or_default(),
+            note.modified.to_string(),
+            csv_field(&note.tags.join(";")),
+            note.outgoing_links.to_string(),
+            note.incoming_links.to_string(),
+            note.external_links.to_string(),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes a CSV field if it contains separators, quotes or line breaks.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_collect_metrics() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        Notes::create_note_file("a", "---\ntags: work\n---\nLinks to [[b]] and https://example.com").unwrap();
+        Notes::create_note_file("b", "Just #words here").unwrap();
+
+        let metrics = collect().unwrap();
+        let a = metrics.iter().find(|note| note.title == "a").unwrap();
+        let b = metrics.iter().find(|note| note.title == "b").unwrap();
+        assert_eq!(a.word_count, 5);
+        assert_eq!(a.reading_minutes, 1);
+        assert_eq!(b.char_count, 16);
+        assert_eq!(a.tags, vec!["work"]);
+        assert_eq!(a.outgoing_links, 1);
+        assert_eq!(a.external_links, 1);
+        assert_eq!(b.incoming_links, 1);
+        assert_eq!(b.tags, vec!["words"]);
+    }
+
+    #[test]
+    fn test_reading_minutes() {
+        assert_eq!(reading_minutes(0), 0);
+        assert_eq!(reading_minutes(1), 1);
+        assert_eq!(reading_minutes(200), 1);
+        assert_eq!(reading_minutes(201), 2);
+    }
+
+    #[test]
+    fn test_vault_stats() {
+        let note = |tags: &[&str], word_count: usize| NoteMetrics {
+            title: String::new(),
+            size_bytes: 0,
+            word_count,
+            char_count: 0,
+            reading_minutes: 0,
+            created: Some(1_700_000_000),
+            modified: 0,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            outgoing_links: 0,
+            incoming_links: 0,
+            external_links: 0,
+        };
+        let stats = VaultStats::from_metrics(&[note(&["work", "ideas"], 10), note(&["work"], 5), note(&[], 1)]);
+        assert_eq!(stats.total_notes, 3);
+        assert_eq!(stats.total_words, 16);
+        assert_eq!(
+            stats.notes_per_tag,
+            vec![("work".to_string(), 2), ("ideas".to_string(), 1)]
+        );
+        assert_eq!(stats.created_per_week.len(), 1);
+        assert_eq!(stats.created_per_week[0].1, 3);
+    }
+
+    #[test]
+    fn test_to_csv_quotes_fields() {
+        let metrics = vec![NoteMetrics {
+            title: "Hello, \"world\"".to_string(),
+            size_bytes: 10,
+            word_count: 2,
+            char_count: 11,
+            reading_minutes: 1,
+            created: None,
+            modified: 5,
+            tags: vec!["a".to_string(), "b".to_string()],
+            outgoing_links: 0,
+            incoming_links: 1,
+            external_links: 0,
+        }];
+        let csv = to_csv(&metrics);
+        assert_eq!(csv.lines().nth(1), Some("\"Hello, \"\"world\"\"\",10,2,11,1,,5,a;b,0,1,0"));
+    }
+}
@@ -0,0 +1,452 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use crate::vault;
+
+/// A file or folder found by `Storage::list`.
+#[derive(Debug, Clone)]
+pub struct StorageEntry {
+    /// The full path of the entry.
+    pub path: PathBuf,
+    /// Whether the entry is a folder.
+    pub is_dir: bool,
+    /// When the file was last written.
+    pub modified: SystemTime,
+    /// When the file was created, if known.
+    pub created: Option<SystemTime>,
+}
+
+/// Where the notes, todos and settings are kept: the file system on the desktop and the
+/// browser's local storage on the web. Paths look the same on both, e.g. `~/.notes/ideas.txt`,
+/// so the rest of the app does not need to know which one is used.
+pub trait Storage: Sync {
+    /// Reads a whole file, with a `NotFound` error if it doesn't exist.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Replaces the contents of a file, creating it if needed, so that a crash leaves either the
+    /// old or the new contents. With `fsync`, the data is on disk before this returns.
+    fn write(&self, path: &Path, data: &[u8], fsync: bool) -> io::Result<()>;
+
+    /// Deletes a file.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Moves a file, replacing any file at the target.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Whether a file or folder exists.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Whether a file exists.
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Returns the times of a file, with a `NotFound` error if it doesn't exist.
+    fn metadata(&self, path: &Path) -> io::Result<StorageEntry>;
+
+    /// Creates a folder and its parents. Creating a folder that exists succeeds.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Lists the files and folders directly inside a folder.
+    fn list(&self, dir: &Path) -> io::Result<Vec<StorageEntry>>;
+}
+
+/// Returns the storage of this platform.
+pub fn backend() -> &'static dyn Storage {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        &FileStorage
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        &BrowserStorage
+    }
+}
+
+/// Reads a whole file as text.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the text, or an `InvalidData` error if it is not UTF-8.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(backend().read(path)?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Atomically replaces the contents of a file.
+///
+/// On the file system, the data is written to a temporary file in the same directory which is
+/// then renamed over the target, so a crash mid-write leaves either the old or the new contents
+/// on disk. Files inside a vault that another window holds the lock of are never written.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to write.
+/// * `data` - The bytes to write.
+/// * `fsync` - Whether to flush the file and its directory entry to disk before returning.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn atomic_write(path: &Path, data: &[u8], fsync: bool) -> io::Result<()> {
+    vault::check_writable(path)?;
+    backend().write(path, data, fsync)
+}
+
+// Numbers the temporary files of this process, so that threads writing the same file at the same
+// time don't share one
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The files and folders of the local file system.
+pub struct FileStorage;
+
+impl Storage for FileStorage {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8], fsync: bool) -> io::Result<()> {
+        let dir = path
+            .parent()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no parent directory"))?;
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+        let temp_path = dir.join(format!(
+            ".{}.{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = write_and_rename(&temp_path, path, data, fsync);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result?;
+
+        if fsync {
+            sync_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<StorageEntry> {
+        let metadata = fs::metadata(path)?;
+        Ok(StorageEntry {
+            path: path.to_path_buf(),
+            is_dir: metadata.is_dir(),
+            modified: metadata.modified()?,
+            created: metadata.created().ok(),
+        })
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<StorageEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            entries.push(StorageEntry {
+                path: entry.path(),
+                is_dir: metadata.is_dir(),
+                modified: metadata.modified()?,
+                created: metadata.created().ok(),
+            });
+        }
+        Ok(entries)
+    }
+}
+
+fn write_and_rename(temp_path: &Path, path: &Path, data: &[u8], fsync: bool) -> io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    file.write_all(data)?;
+    if fsync {
+        file.sync_all()?;
+    }
+    drop(file);
+    fs::rename(temp_path, path)
+}
+
+/// Flushes a directory so that a rename inside it survives a crash.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened for syncing on this platform.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The browser's local storage, which keeps each file under a key made of its path. Folders
+/// exist implicitly while they contain files. Only text can be stored.
+#[cfg(target_arch = "wasm32")]
+pub struct BrowserStorage;
+
+#[cfg(target_arch = "wasm32")]
+mod browser {
+    use std::io;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // Keys of file contents and of their times, followed by the path
+    pub const DATA_PREFIX: &str = "notes-file:";
+    pub const TIMES_PREFIX: &str = "notes-times:";
+
+    pub fn local_storage() -> io::Result<web_sys::Storage> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "The browser does not allow local storage"))
+    }
+
+    pub fn js_error(err: wasm_bindgen::JsValue) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, format!("{:?}", err))
+    }
+
+    pub fn key(path: &Path) -> String {
+        path.to_string_lossy().replace('\\', "/")
+    }
+
+    // `SystemTime::now` is not available in the browser
+    pub fn now_millis() -> u64 {
+        js_sys::Date::now() as u64
+    }
+
+    pub fn time(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    // Times are kept as `modified,created` in milliseconds
+    pub fn times(storage: &web_sys::Storage, key: &str) -> io::Result<(SystemTime, Option<SystemTime>)> {
+        let times = storage
+            .get_item(&format!("{}{}", TIMES_PREFIX, key))
+            .map_err(js_error)?
+            .unwrap_or_default();
+        let mut times = times.split(',').map(|time| time.parse().ok());
+        let modified = times.next().flatten().unwrap_or(0);
+        let created = times.next().flatten();
+        Ok((time(modified), created.map(time)))
+    }
+
+    pub fn keys(storage: &web_sys::Storage) -> io::Result<Vec<String>> {
+        let length = storage.length().map_err(js_error)?;
+        let mut keys = Vec::new();
+        for index in 0..length {
+            if let Some(key) = storage.key(index).map_err(js_error)? {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for BrowserStorage {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let storage = browser::local_storage()?;
+        match storage
+            .get_item(&format!("{}{}", browser::DATA_PREFIX, browser::key(path)))
+            .map_err(browser::js_error)?
+        {
+            Some(data) => Ok(data.into_bytes()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))),
+        }
+    }
+
+    fn write(&self, path: &Path, data: &[u8], _fsync: bool) -> io::Result<()> {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Only text can be stored in the browser"))?;
+        let storage = browser::local_storage()?;
+        let key = browser::key(path);
+        let times_key = format!("{}{}", browser::TIMES_PREFIX, key);
+        let now = browser::now_millis();
+        let created = storage
+            .get_item(&times_key)
+            .map_err(browser::js_error)?
+            .and_then(|times| times.split_once(',').and_then(|(_, created)| created.parse().ok()))
+            .unwrap_or(now);
+        // A single item is replaced as a whole, which keeps the write atomic
+        storage
+            .set_item(&format!("{}{}", browser::DATA_PREFIX, key), text)
+            .map_err(browser::js_error)?;
+        storage
+            .set_item(&times_key, &format!("{},{}", now, created))
+            .map_err(browser::js_error)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if !self.is_file(path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())));
+        }
+        let storage = browser::local_storage()?;
+        let key = browser::key(path);
+        storage
+            .remove_item(&format!("{}{}", browser::DATA_PREFIX, key))
+            .map_err(browser::js_error)?;
+        storage
+            .remove_item(&format!("{}{}", browser::TIMES_PREFIX, key))
+            .map_err(browser::js_error)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let data = self.read(from)?;
+        self.write(to, &data, false)?;
+        self.remove(from)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let folder = format!("{}{}/", browser::DATA_PREFIX, browser::key(path).trim_end_matches('/'));
+        self.is_file(path)
+            || browser::local_storage()
+                .and_then(|storage| browser::keys(&storage))
+                .is_ok_and(|keys| keys.iter().any(|key| key.starts_with(&folder)))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        browser::local_storage()
+            .and_then(|storage| {
+                let key = format!("{}{}", browser::DATA_PREFIX, browser::key(path));
+                storage.get_item(&key).map_err(browser::js_error)
+            })
+            .is_ok_and(|data| data.is_some())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<StorageEntry> {
+        if !self.is_file(path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())));
+        }
+        let (modified, created) = browser::times(&browser::local_storage()?, &browser::key(path))?;
+        Ok(StorageEntry {
+            path: path.to_path_buf(),
+            is_dir: false,
+            modified,
+            created,
+        })
+    }
+
+    fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<StorageEntry>> {
+        let storage = browser::local_storage()?;
+        let folder = format!("{}/", browser::key(dir).trim_end_matches('/'));
+        let mut entries: Vec<StorageEntry> = Vec::new();
+        for key in browser::keys(&storage)? {
+            let Some(rest) = key
+                .strip_prefix(browser::DATA_PREFIX)
+                .and_then(|path| path.strip_prefix(&folder))
+            else {
+                continue;
+            };
+            let (name, is_dir) = match rest.split_once('/') {
+                Some((name, _)) => (name, true),
+                None => (rest, false),
+            };
+            let path = dir.join(name);
+            if entries.iter().any(|entry| entry.path == path) {
+                continue;
+            }
+            let (modified, created) = browser::times(&storage, &format!("{}{}", folder, name))?;
+            entries.push(StorageEntry {
+                path,
+                is_dir,
+                modified,
+                created,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_atomic_write_creates_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("note.txt");
+
+        atomic_write(&path, b"hello", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_atomic_write_replaces_file_and_cleans_up() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("note.txt");
+        fs::write(&path, "a much longer original content").unwrap();
+
+        atomic_write(&path, b"short", false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "short");
+
+        let entries: Vec<_> = fs::read_dir(temp_dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_atomic_write_from_threads() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("note.txt");
+
+        std::thread::scope(|scope| {
+            for number in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        atomic_write(path, format!("writer {}", number).as_bytes(), false).unwrap();
+                    }
+                });
+            }
+        });
+        assert!(fs::read_to_string(&path).unwrap().starts_with("writer "));
+        let entries: Vec<_> = fs::read_dir(temp_dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_file_storage_list() {
+        let temp_dir = tempdir().unwrap();
+        let storage = FileStorage;
+        storage.create_dir_all(&temp_dir.path().join("meetings")).unwrap();
+        storage.write(&temp_dir.path().join("ideas.txt"), b"ideas", false).unwrap();
+
+        let mut entries = storage.list(temp_dir.path()).unwrap();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let listed: Vec<(PathBuf, bool)> = entries.into_iter().map(|entry| (entry.path, entry.is_dir)).collect();
+        assert_eq!(
+            listed,
+            vec![(temp_dir.path().join("ideas.txt"), false), (temp_dir.path().join("meetings"), true)]
+        );
+        assert!(storage.is_file(&temp_dir.path().join("ideas.txt")));
+        assert!(!storage.is_file(&temp_dir.path().join("meetings")));
+        assert_eq!(read_to_string(&temp_dir.path().join("ideas.txt")).unwrap(), "ideas");
+    }
+}
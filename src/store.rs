@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::config::NoteStoreKind;
+use crate::notes::NoteMeta;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sqlite::{SqliteStore, DATABASE_FILE};
+use crate::storage;
+use crate::title;
+use crate::vault::Vault;
+
+/// Where notes are kept. The flat files of the active vault are used unless another store is
+/// set, so that other backends, such as a database or a server, can be added without changing
+/// the code that reads and writes notes.
+pub trait NoteStore {
+    /// Lists all notes along with their timestamps.
+    fn list(&self) -> io::Result<Vec<NoteMeta>>;
+
+    /// Reads the content of a note, with a `NotFound` error if it doesn't exist.
+    fn read(&self, title: &str) -> io::Result<String>;
+
+    /// Writes the content of a note, creating it if it doesn't exist.
+    fn write(&self, title: &str, content: &str) -> io::Result<()>;
+
+    /// Deletes a note, with a `NotFound` error if it doesn't exist.
+    fn delete(&self, title: &str) -> io::Result<()>;
+
+    /// Returns the timestamps of a note, with a `NotFound` error if it doesn't exist.
+    fn metadata(&self, title: &str) -> io::Result<NoteMeta>;
+
+    /// Whether a note exists.
+    fn exists(&self, title: &str) -> bool {
+        self.metadata(title).is_ok()
+    }
+
+    /// Finds the notes matching the words of a query with the store's own full-text index,
+    /// best matches first, along with their scores.
+    ///
+    /// Returns `None` for stores without an index, which are searched with `SearchIndex`.
+    fn search(&self, _query: &str) -> io::Result<Option<Vec<(String, f64)>>> {
+        Ok(None)
+    }
+}
+
+// Replaces the flat files of the active vault, e.g. with a `MemoryStore`
+static STORE: Mutex<Option<Arc<dyn NoteStore + Send + Sync>>> = Mutex::new(None);
+
+/// Keeps notes in another store instead of the flat files of the active vault.
+///
+/// # Arguments
+///
+/// * `store` - The store to use, or `None` to go back to the active vault.
+pub fn set_store(store: Option<Arc<dyn NoteStore + Send + Sync>>) {
+    *STORE.lock().unwrap_or_else(|err| err.into_inner()) = store;
+}
+
+// The database of the vault that was active last, so that its connection is opened and its
+// schema is checked once rather than on every read and write
+#[cfg(not(target_arch = "wasm32"))]
+static DATABASE: Mutex<Option<(PathBuf, Arc<SqliteStore>)>> = Mutex::new(None);
+
+/// Returns the store notes are kept in.
+///
+/// # Returns
+///
+/// An `io::Result<Arc<dyn NoteStore>>` containing the store set with `set_store`, or else the
+/// store configured for the active vault.
+pub fn active_store() -> io::Result<Arc<dyn NoteStore>> {
+    if let Some(store) = STORE.lock().unwrap_or_else(|err| err.into_inner()).clone() {
+        return Ok(store);
+    }
+    let vault = Vault::active()?;
+    #[cfg(not(target_arch = "wasm32"))]
+    if vault.config().note_store == NoteStoreKind::Sqlite {
+        return Ok(open_database(&vault.root().join(DATABASE_FILE))?);
+    }
+    Ok(Arc::new(vault))
+}
+
+// Reuses the open database if it is the same file, e.g. until another vault is switched to
+#[cfg(not(target_arch = "wasm32"))]
+fn open_database(path: &Path) -> io::Result<Arc<SqliteStore>> {
+    let mut database = DATABASE.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some((open, store)) = database.as_ref() {
+        if open == path {
+            return Ok(Arc::clone(store));
+        }
+    }
+    let store = Arc::new(SqliteStore::open(path)?);
+    *database = Some((path.to_path_buf(), Arc::clone(&store)));
+    Ok(store)
+}
+
+impl NoteStore for Vault {
+    fn list(&self) -> io::Result<Vec<NoteMeta>> {
+        self.list_notes_with_meta()
+    }
+
+    fn read(&self, title: &str) -> io::Result<String> {
+        self.read_note_file(title)
+    }
+
+    // New notes are synced to disk, updates are not, as they happen frequently while editing
+    fn write(&self, title: &str, content: &str) -> io::Result<()> {
+        if storage::backend().is_file(&self.note_path(title)?) {
+            self.update_note_file(title, content)
+        } else {
+            self.create_note_file(title, content)
+        }
+    }
+
+    fn delete(&self, title: &str) -> io::Result<()> {
+        self.delete_note_file(title)
+    }
+
+    fn metadata(&self, title: &str) -> io::Result<NoteMeta> {
+        let entry = storage::backend().metadata(&self.note_path(title)?)?;
+        Ok(NoteMeta {
+            title: title.to_string(),
+            modified: entry.modified,
+            created: entry.created,
+        })
+    }
+}
+
+/// Keeps notes in memory, e.g. for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    notes: Mutex<BTreeMap<String, (String, NoteMeta)>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    fn notes(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, (String, NoteMeta)>> {
+        self.notes.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl NoteStore for MemoryStore {
+    fn list(&self) -> io::Result<Vec<NoteMeta>> {
+        Ok(self.notes().values().map(|(_, meta)| meta.clone()).collect())
+    }
+
+    fn read(&self, title: &str) -> io::Result<String> {
+        match self.notes().get(title) {
+            Some((content, _)) => Ok(content.clone()),
+            None => Err(not_found(title)),
+        }
+    }
+
+    fn write(&self, title: &str, content: &str) -> io::Result<()> {
+        title::validate(title)?;
+        let now = SystemTime::now();
+        let mut notes = self.notes();
+        let created = notes.get(title).map_or(Some(now), |(_, meta)| meta.created);
+        let meta = NoteMeta {
+            title: title.to_string(),
+            modified: now,
+            created,
+        };
+        notes.insert(title.to_string(), (content.to_string(), meta));
+        Ok(())
+    }
+
+    fn delete(&self, title: &str) -> io::Result<()> {
+        match self.notes().remove(title) {
+            Some(_) => Ok(()),
+            None => Err(not_found(title)),
+        }
+    }
+
+    fn metadata(&self, title: &str) -> io::Result<NoteMeta> {
+        match self.notes().get(title) {
+            Some((_, meta)) => Ok(meta.clone()),
+            None => Err(not_found(title)),
+        }
+    }
+}
+
+fn not_found(title: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No note named {}", title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // The same checks for every store
+    fn check_store(store: &dyn NoteStore) {
+        assert!(store.list().unwrap().is_empty());
+        assert!(!store.exists("meetings/standup"));
+        assert_eq!(store.read("meetings/standup").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        store.write("meetings/standup", "Monday").unwrap();
+        store.write("meetings/standup", "Tuesday").unwrap();
+        store.write("Ideas", "").unwrap();
+        assert!(store.exists("meetings/standup"));
+        assert_eq!(store.read("meetings/standup").unwrap(), "Tuesday");
+        assert_eq!(store.metadata("Ideas").unwrap().title, "Ideas");
+        let mut titles: Vec<String> = store.list().unwrap().into_iter().map(|meta| meta.title).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["Ideas", "meetings/standup"]);
+
+        store.delete("Ideas").unwrap();
+        assert!(!store.exists("Ideas"));
+        assert_eq!(store.delete("Ideas").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(store.metadata("Ideas").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_vault_store() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().to_path_buf()).unwrap();
+        check_store(&vault);
+        assert!(vault.note_path("meetings/standup").unwrap().is_file());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_database_opened_once() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join(DATABASE_FILE);
+        let store = open_database(&path).unwrap();
+        store.write("Ideas", "Cache the store").unwrap();
+        assert!(Arc::ptr_eq(&store, &open_database(&path).unwrap()));
+
+        let other = open_database(&temp_dir.path().join("other.db")).unwrap();
+        assert!(!Arc::ptr_eq(&store, &other));
+        assert!(!other.exists("Ideas"));
+        assert_eq!(open_database(&path).unwrap().read("Ideas").unwrap(), "Cache the store");
+    }
+}
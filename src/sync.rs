@@ -0,0 +1,931 @@
+pub mod encrypted;
+pub mod s3;
+pub mod webdav;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::activity::ACTIVITY_FILE;
+use crate::backup;
+use crate::command_history::COMMAND_HISTORY_FILE;
+use crate::config::{Config, SyncService};
+use crate::history::HISTORY_DIR;
+use crate::merge;
+use crate::notes::Notes;
+use crate::scheduler;
+use crate::search::INDEX_DIR;
+use crate::secrets::{self, SECRETS_FILE, SECRETS_KEY_FILE};
+use crate::sqlite::{self, DATABASE_FILE};
+use crate::storage::atomic_write;
+use crate::vault::{self, TRASH_DIR};
+
+/// The name of the file in the `.notes` directory that records the state of the last sync.
+pub const SYNC_STATE_FILE: &str = ".sync.json";
+
+/// The name of the folder in the `.notes` directory that keeps each text file as it was last
+/// synced, the common ancestor for merging edits made on both sides.
+pub const SYNC_BASE_DIR: &str = ".sync-base";
+
+/// The name of the file on the server that lists deleted files.
+pub const TOMBSTONES_FILE: &str = ".sync-tombstones.json";
+
+/// How long deletions are remembered on the server.
+const TOMBSTONE_RETENTION_SECS: u64 = 90 * 24 * 60 * 60;
+
+/// Files and folders that belong to this device and are never synced.
+const LOCAL_ONLY: &[&str] = &[
+    SYNC_STATE_FILE,
+    SYNC_BASE_DIR,
+    TOMBSTONES_FILE,
+    SECRETS_FILE,
+    SECRETS_KEY_FILE,
+    ACTIVITY_FILE,
+    COMMAND_HISTORY_FILE,
+    INDEX_DIR,
+    HISTORY_DIR,
+    TRASH_DIR,
+    DATABASE_FILE,
+];
+
+/// A server the notes directory can be synced with. Paths are relative to the synced folder,
+/// with `/` separators. Versions identify the content of a file on the server, e.g. its ETag.
+pub trait SyncBackend {
+    /// Identifies the server and folder, so that switching servers starts from a clean state.
+    fn remote(&self) -> String;
+
+    /// Lists every file in the synced folder.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<BTreeMap<String, Option<String>>>` containing the version of each file, if
+    /// the server reports one.
+    fn list(&self) -> io::Result<BTreeMap<String, Option<String>>>;
+
+    /// Downloads a file.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<(Vec<u8>, Option<String>)>` containing the content and the version of the file.
+    fn get(&self, path: &str) -> io::Result<(Vec<u8>, Option<String>)>;
+
+    /// Uploads a file, unless it changed on the server since it was last seen.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    /// * `data` - The content of the file.
+    /// * `expected` - The version the file had when it was last seen, or `None` if it should not exist yet.
+    fn put(&self, path: &str, data: &[u8], expected: Option<&str>) -> io::Result<Written>;
+
+    /// Deletes a file, unless it changed on the server since it was last seen. Deleting a file
+    /// that does not exist succeeds.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    /// * `expected` - The version the file had when it was last seen.
+    fn delete(&self, path: &str, expected: Option<&str>) -> io::Result<Written>;
+}
+
+/// The outcome of a conditional write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Written {
+    /// The file was written; the server may report its new version.
+    Stored(Option<String>),
+    /// The file changed on the server since it was last seen, so it was not written.
+    Conflict,
+}
+
+/// A file as it was when it was last synced.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SyncedFile {
+    /// The hash of the content, as returned by `content_hash`.
+    pub hash: String,
+    /// The version of the file on the server, e.g. its ETag, if the server reported one.
+    pub version: Option<String>,
+}
+
+/// The files as they were after the last sync, persisted in the `.sync.json` file. A file that
+/// changed since then on one side only is copied to the other side.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct SyncState {
+    /// The server the files were synced with, e.g. the URL of a WebDAV folder.
+    pub remote: String,
+    /// The synced files, by path relative to the notes directory with `/` separators.
+    pub files: BTreeMap<String, SyncedFile>,
+    /// Files changed on both sides that could not be merged, with their version on the server
+    /// when the conflict was found, or `None` if it was deleted there.
+    pub conflicts: BTreeMap<String, Option<String>>,
+}
+
+impl SyncState {
+    /// Reads the sync state of a notes directory, whichever server it was synced with.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<SyncState>` containing the state, which is empty if the directory was never synced.
+    pub fn read(root: &Path) -> io::Result<SyncState> {
+        match fs::read_to_string(root.join(SYNC_STATE_FILE)) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Loads the sync state of a notes directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The notes directory.
+    /// * `remote` - The server that is about to be synced with.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<SyncState>` containing the state, which is empty if the directory was never
+    /// synced with that server. Otherwise files missing on a new server would count as deleted.
+    pub fn load(root: &Path, remote: &str) -> io::Result<SyncState> {
+        let state = SyncState::read(root)?;
+        if state.remote != remote {
+            return Ok(SyncState {
+                remote: remote.to_string(),
+                ..SyncState::default()
+            });
+        }
+        Ok(state)
+    }
+
+    /// Saves the sync state of a notes directory.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        atomic_write(&root.join(SYNC_STATE_FILE), data.as_bytes(), true)
+    }
+}
+
+/// A file deleted on some device, as listed in the tombstones file on the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    /// The hash of the content the file had when it was deleted.
+    pub hash: String,
+    /// When the file was deleted, in seconds since the Unix epoch.
+    pub deleted_at: u64,
+}
+
+/// Files deleted on any device. A device that never synced a file cannot tell whether it is
+/// new or was deleted elsewhere; the tombstone keeps it from uploading the file again.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Tombstones {
+    /// The deleted files, by path.
+    pub files: BTreeMap<String, Tombstone>,
+}
+
+/// How to settle a file that was changed on both sides and could not be merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Replace the file on the server with the local one.
+    KeepMine,
+    /// Replace the local file with the one on the server.
+    KeepTheirs,
+    /// Rename the local file to `<name> (conflict)` and download the one on the server.
+    KeepBoth,
+}
+
+/// What a sync changed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Files copied to the server.
+    pub uploaded: Vec<String>,
+    /// Files copied from the server.
+    pub downloaded: Vec<String>,
+    /// Files edited on both sides whose edits were combined.
+    pub merged: Vec<String>,
+    /// Files deleted on either side because they were deleted on the other.
+    pub deleted: Vec<String>,
+    /// Files changed on both sides that could not be merged, which were left alone.
+    pub conflicts: Vec<String>,
+    /// The fingerprint of the encryption key, when this device synced with it for the first
+    /// time. The user compares it with the one their other devices showed.
+    pub new_key: Option<String>,
+}
+
+impl SyncReport {
+    /// Whether the sync changed files in the notes directory.
+    pub fn changed_local(&self) -> bool {
+        !self.downloaded.is_empty() || !self.merged.is_empty() || !self.deleted.is_empty()
+    }
+
+    /// Describes the outcome in one line, e.g. `2 uploaded, 1 downloaded`.
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.uploaded.len(), "uploaded"),
+            (self.downloaded.len(), "downloaded"),
+            (self.merged.len(), "merged"),
+            (self.deleted.len(), "deleted"),
+            (self.conflicts.len(), "in conflict"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{} {}", count, label))
+            .collect();
+        if parts.is_empty() {
+            "Everything is up to date".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Syncs the active vault with the server set up in its configuration.
+///
+/// # Returns
+///
+/// An `io::Result<SyncReport>` containing what changed, or an error if sync is not set up or
+/// the server could not be reached.
+pub fn run() -> io::Result<SyncReport> {
+    sqlite::require_files("Sync")?;
+    let config = Config::load()?;
+    let root = Notes::get_notes_dir()?;
+    let passphrase_secret = encrypted::passphrase_secret(&config.sync);
+    let not_set_up = || io::Error::new(io::ErrorKind::NotFound, "Sync is not set up");
+    let mut backend: Box<dyn SyncBackend> = match config.sync.service {
+        SyncService::WebDav => {
+            let settings = config.sync.webdav.ok_or_else(not_set_up)?;
+            let password = secrets::get(&webdav::password_secret(&settings.url))?.unwrap_or_default();
+            Box::new(webdav::WebDav::new(&settings.url, &settings.username, &password)?)
+        }
+        SyncService::S3 => {
+            let settings = config.sync.s3.ok_or_else(not_set_up)?;
+            let secret_key = secrets::get(&s3::secret_key_name(&settings))?.unwrap_or_default();
+            Box::new(s3::S3::new(&settings, &secret_key)?)
+        }
+    };
+    let mut new_key = None;
+    if config.sync.encrypt {
+        let passphrase = match passphrase_secret {
+            Some(name) => secrets::get(&name)?.unwrap_or_default(),
+            None => String::new(),
+        };
+        let unlocked = encrypted::Encrypted::unlock(backend, &passphrase)?;
+        if SyncState::read(&root)?.remote != unlocked.remote() {
+            new_key = Some(unlocked.fingerprint().to_string());
+        }
+        backend = Box::new(unlocked);
+    }
+    let mut report = sync(backend.as_ref(), &root)?;
+    report.new_key = new_key;
+    Ok(report)
+}
+
+/// Syncs a notes directory with a server.
+///
+/// Files changed on one side only since the last sync are copied to the other side, including
+/// deletions. Text files edited on both sides are merged line by line; files that cannot be
+/// merged are reported as conflicts and left alone until they are resolved with `resolve`.
+/// Conditional writes make sure that a file changed by another device during the sync is never
+/// overwritten, on servers that support them.
+///
+/// # Arguments
+///
+/// * `backend` - The server.
+/// * `root` - The notes directory.
+///
+/// # Returns
+///
+/// An `io::Result<SyncReport>` containing what changed.
+pub fn sync(backend: &dyn SyncBackend, root: &Path) -> io::Result<SyncReport> {
+    let state = SyncState::load(root, &backend.remote())?;
+    let local = local_files(root)?;
+    let mut remote = backend.list()?;
+    let tombstones_version = remote.remove(TOMBSTONES_FILE);
+    // Whatever the server lists, nothing is written outside the notes directory or over the
+    // files that belong to this device
+    remote.retain(|path, _| {
+        let syncable = is_syncable_remote_path(path);
+        if !syncable {
+            log::warn!("Ignoring {} on the server", path);
+        }
+        syncable
+    });
+    let tombstones = match &tombstones_version {
+        Some(_) => serde_json::from_slice(&backend.get(TOMBSTONES_FILE)?.0)?,
+        None => Tombstones::default(),
+    };
+    let paths: BTreeSet<String> = local
+        .keys()
+        .chain(remote.keys())
+        .chain(state.files.keys())
+        .cloned()
+        .collect();
+
+    let mut engine = Engine {
+        backend,
+        root,
+        state,
+        deleted: Tombstones::default(),
+        revived: BTreeSet::new(),
+        report: SyncReport::default(),
+    };
+    let result = paths
+        .iter()
+        .try_for_each(|path| engine.sync_file(path, local.get(path), remote.get(path), &tombstones));
+    // Keep track of what was synced before the error, so it is not mistaken for a conflict next time
+    engine.state.save(root)?;
+    result?;
+    if !engine.deleted.files.is_empty() || engine.revived.iter().any(|path| tombstones.files.contains_key(path)) {
+        engine.save_tombstones(tombstones, tombstones_version.flatten())?;
+    }
+    Ok(engine.report)
+}
+
+/// Settles a conflict found by the last sync. The files are transferred by the next sync.
+///
+/// # Arguments
+///
+/// * `root` - The notes directory.
+/// * `path` - The path of the file in conflict.
+/// * `resolution` - Which version to keep.
+///
+/// # Returns
+///
+/// An `io::Result<Option<String>>` containing the path of the renamed local copy when both
+/// versions are kept, or an error if the file is not in conflict.
+pub fn resolve(root: &Path, path: &str, resolution: Resolution) -> io::Result<Option<String>> {
+    let mut state = SyncState::read(root)?;
+    let Some(version) = state.conflicts.remove(path) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not in conflict", path),
+        ));
+    };
+    let local = root.join(path);
+    let mut copy = None;
+    match resolution {
+        // The local file counts as edited and the server version as seen, so it is uploaded
+        Resolution::KeepMine => {
+            state.files.insert(path.to_string(), SyncedFile { hash: String::new(), version });
+        }
+        // The other way around, so the server version is downloaded
+        Resolution::KeepTheirs => {
+            let hash = match fs::read(&local) {
+                Ok(data) => content_hash(&data),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(err) => return Err(err),
+            };
+            state.files.insert(
+                path.to_string(),
+                SyncedFile {
+                    hash,
+                    version: Some(String::new()),
+                },
+            );
+        }
+        Resolution::KeepBoth => {
+            let renamed = conflict_copy_path(path, |candidate| root.join(candidate).exists());
+            vault::check_writable(&local)?;
+            fs::rename(&local, root.join(&renamed))?;
+            state.files.remove(path);
+            copy = Some(renamed);
+        }
+    }
+    state.save(root)?;
+    Ok(copy)
+}
+
+// Applies the decisions of one sync and records them in the state
+struct Engine<'a> {
+    backend: &'a dyn SyncBackend,
+    root: &'a Path,
+    state: SyncState,
+    // Files deleted on the server by this sync
+    deleted: Tombstones,
+    // Files written to the server by this sync, whose tombstones are obsolete
+    revived: BTreeSet<String>,
+    report: SyncReport,
+}
+
+impl Engine<'_> {
+    fn sync_file(
+        &mut self,
+        path: &str,
+        local_hash: Option<&String>,
+        remote_version: Option<&Option<String>>,
+        tombstones: &Tombstones,
+    ) -> io::Result<()> {
+        let base = self.state.files.get(path).cloned();
+        let local_changed = local_hash != base.as_ref().map(|base| &base.hash);
+        let remote_changed = remote_version != base.as_ref().map(|base| &base.version);
+        let known_version = base.as_ref().and_then(|base| base.version.clone());
+
+        match (local_hash, remote_version) {
+            // Never synced here and deleted elsewhere: finish the deletion instead of uploading it again
+            (Some(hash), None) if base.is_none() && tombstones.files.get(path).is_some_and(|tomb| tomb.hash == *hash) => {
+                self.delete_local(path)
+            }
+            (Some(_), _) if local_changed && !remote_changed => self.upload(path, known_version.as_deref()),
+            (None, Some(_)) if local_changed && !remote_changed => {
+                match self.backend.delete(path, known_version.as_deref())? {
+                    Written::Stored(_) => {
+                        let hash = base.map(|base| base.hash).unwrap_or_default();
+                        self.deleted.files.insert(
+                            path.to_string(),
+                            Tombstone {
+                                hash,
+                                deleted_at: scheduler::now(),
+                            },
+                        );
+                        self.forget(path)?;
+                        self.report.deleted.push(path.to_string());
+                    }
+                    // Changed on the server during the sync; the next sync picks that up
+                    Written::Conflict => {}
+                }
+                Ok(())
+            }
+            // Deleted here and edited on the server: the edit wins
+            (_, Some(_)) if remote_changed && (!local_changed || local_hash.is_none()) => self.download(path),
+            (Some(_), None) if remote_changed && !local_changed => self.delete_local(path),
+            // Edited here and deleted on the server: the edit wins
+            (Some(_), None) if remote_changed => self.upload(path, None),
+            (Some(hash), Some(version)) if local_changed && remote_changed => self.merge(path, hash, version),
+            (None, None) => self.forget(path),
+            _ => Ok(()),
+        }
+    }
+
+    fn upload(&mut self, path: &str, expected: Option<&str>) -> io::Result<()> {
+        let data = fs::read(self.root.join(path))?;
+        match self.backend.put(path, &data, expected)? {
+            Written::Stored(version) => {
+                self.record(path, &data, version)?;
+                self.report.uploaded.push(path.to_string());
+            }
+            Written::Conflict => {}
+        }
+        Ok(())
+    }
+
+    fn download(&mut self, path: &str) -> io::Result<()> {
+        let (data, version) = self.backend.get(path)?;
+        self.write_local(path, &data)?;
+        self.record(path, &data, version)?;
+        self.report.downloaded.push(path.to_string());
+        Ok(())
+    }
+
+    // Both sides changed: combine the edits if the file is text and they touch different lines
+    fn merge(&mut self, path: &str, local_hash: &str, remote_version: &Option<String>) -> io::Result<()> {
+        let (theirs, version) = self.backend.get(path)?;
+        let version = version.or_else(|| remote_version.clone());
+        if content_hash(&theirs) == local_hash {
+            return self.record(path, &theirs, version);
+        }
+        let mine = fs::read(self.root.join(path))?;
+        let base = fs::read(self.root.join(SYNC_BASE_DIR).join(path)).ok();
+        let merged = match (base.as_deref().map(std::str::from_utf8), std::str::from_utf8(&mine), std::str::from_utf8(&theirs)) {
+            (Some(Ok(base)), Ok(mine), Ok(theirs)) => merge::merge(base, mine, theirs),
+            _ => None,
+        };
+        let Some(merged) = merged else {
+            self.conflict(path, version);
+            return Ok(());
+        };
+        match self.backend.put(path, merged.as_bytes(), version.as_deref())? {
+            Written::Stored(new_version) => {
+                self.write_local(path, merged.as_bytes())?;
+                self.record(path, merged.as_bytes(), new_version)?;
+                self.report.merged.push(path.to_string());
+            }
+            // Changed again in the meantime; the next sync merges that too
+            Written::Conflict => {}
+        }
+        Ok(())
+    }
+
+    fn delete_local(&mut self, path: &str) -> io::Result<()> {
+        let target = self.root.join(path);
+        vault::check_writable(&target)?;
+        fs::remove_file(target)?;
+        self.forget(path)?;
+        self.report.deleted.push(path.to_string());
+        Ok(())
+    }
+
+    fn write_local(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let target = self.root.join(path);
+        vault::check_writable(&target)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic_write(&target, data, true)
+    }
+
+    // Both sides now hold `data`
+    fn record(&mut self, path: &str, data: &[u8], version: Option<String>) -> io::Result<()> {
+        let base = self.root.join(SYNC_BASE_DIR).join(path);
+        if std::str::from_utf8(data).is_ok() {
+            if let Some(parent) = base.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            atomic_write(&base, data, false)?;
+        } else if base.exists() {
+            fs::remove_file(base)?;
+        }
+        self.state.files.insert(
+            path.to_string(),
+            SyncedFile {
+                hash: content_hash(data),
+                version,
+            },
+        );
+        self.state.conflicts.remove(path);
+        self.revived.insert(path.to_string());
+        Ok(())
+    }
+
+    // Neither side holds the file anymore
+    fn forget(&mut self, path: &str) -> io::Result<()> {
+        let base = self.root.join(SYNC_BASE_DIR).join(path);
+        if base.exists() {
+            fs::remove_file(base)?;
+        }
+        self.state.files.remove(path);
+        self.state.conflicts.remove(path);
+        Ok(())
+    }
+
+    fn conflict(&mut self, path: &str, remote_version: Option<String>) {
+        self.state.conflicts.insert(path.to_string(), remote_version);
+        self.report.conflicts.push(path.to_string());
+    }
+
+    // Adds the deletions of this sync to the tombstones on the server, retrying if another
+    // device changed them in the meantime
+    fn save_tombstones(&self, mut tombstones: Tombstones, mut version: Option<String>) -> io::Result<()> {
+        for _ in 0..3 {
+            let now = scheduler::now();
+            tombstones
+                .files
+                .retain(|path, tomb| !self.revived.contains(path) && now < tomb.deleted_at + TOMBSTONE_RETENTION_SECS);
+            tombstones.files.extend(self.deleted.files.clone());
+            let data = serde_json::to_vec_pretty(&tombstones)?;
+            if let Written::Stored(_) = self.backend.put(TOMBSTONES_FILE, &data, version.as_deref())? {
+                return Ok(());
+            }
+            let (data, current) = self.backend.get(TOMBSTONES_FILE)?;
+            tombstones = serde_json::from_slice(&data)?;
+            version = current;
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "The list of deleted files kept changing on the server",
+        ))
+    }
+}
+
+/// Lists the files of a notes directory that are synced.
+///
+/// # Returns
+///
+/// An `io::Result<BTreeMap<String, String>>` containing the hash of each file, by path relative
+/// to the notes directory with `/` separators.
+pub fn local_files(root: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut files = Vec::new();
+    backup::collect_files(root, root, &mut files)?;
+    let mut hashes = BTreeMap::new();
+    for relative in files {
+        let parts: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let path = parts.join("/");
+        if !is_local_only(&path) {
+            hashes.insert(path, content_hash(&fs::read(root.join(&relative))?));
+        }
+    }
+    Ok(hashes)
+}
+
+/// Returns the hash that identifies the content of a file.
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether a file belongs to this device only, such as search indexes and secrets.
+fn is_local_only(path: &str) -> bool {
+    LOCAL_ONLY
+        .iter()
+        .any(|name| path == *name || path.strip_prefix(name).is_some_and(|rest| rest.starts_with('/')))
+}
+
+/// Whether a path listed by the server may be synced: relative, without `.`, `..` or empty
+/// segments or backslashes, and not one of the files that are never synced.
+fn is_syncable_remote_path(path: &str) -> bool {
+    !path.contains('\\')
+        && path.split('/').all(|segment| !matches!(segment, "" | "." | ".."))
+        && Path::new(path).components().all(|component| matches!(component, Component::Normal(_)))
+        && !is_local_only(path)
+}
+
+/// Returns a free path for the local copy of a file in conflict, e.g. `ideas (conflict).txt`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file in conflict.
+/// * `taken` - Whether a path is already used.
+fn conflict_copy_path(path: &str, taken: impl Fn(&str) -> bool) -> String {
+    let (folder, name) = path.rsplit_once('/').map_or(("", path), |(folder, name)| (folder, name));
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    let prefix = if folder.is_empty() { String::new() } else { format!("{}/", folder) };
+    let mut number = 1;
+    loop {
+        let suffix = if number == 1 {
+            " (conflict)".to_string()
+        } else {
+            format!(" (conflict {})", number)
+        };
+        let candidate = format!("{}{}{}{}", prefix, stem, suffix, extension);
+        if !taken(&candidate) {
+            return candidate;
+        }
+        number += 1;
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn http_error(err: ureq::Error) -> io::Error {
+    match err {
+        ureq::Error::Status(code, response) => io::Error::new(
+            if code == 404 { io::ErrorKind::NotFound } else { io::ErrorKind::Other },
+            format!("The server answered {} {}", code, response.status_text()),
+        ),
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+fn invalid_data(err: quick_xml::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::LOCK_FILE;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use tempfile::tempdir;
+
+    /// A server kept in memory. Clones share the same files.
+    #[derive(Default, Clone)]
+    pub(super) struct MemoryBackend {
+        pub(super) files: Rc<RefCell<BTreeMap<String, (Vec<u8>, u64)>>>,
+        next_version: Rc<Cell<u64>>,
+    }
+
+    impl MemoryBackend {
+        pub(super) fn contents(&self, path: &str) -> Option<String> {
+            let files = self.files.borrow();
+            files.get(path).map(|(data, _)| String::from_utf8_lossy(data).into_owned())
+        }
+
+        fn matches(&self, path: &str, expected: Option<&str>) -> bool {
+            let files = self.files.borrow();
+            files.get(path).map(|(_, version)| version.to_string()).as_deref() == expected
+        }
+    }
+
+    impl SyncBackend for MemoryBackend {
+        fn remote(&self) -> String {
+            "memory".to_string()
+        }
+
+        fn list(&self) -> io::Result<BTreeMap<String, Option<String>>> {
+            let files = self.files.borrow();
+            Ok(files
+                .iter()
+                .map(|(path, (_, version))| (path.clone(), Some(version.to_string())))
+                .collect())
+        }
+
+        fn get(&self, path: &str) -> io::Result<(Vec<u8>, Option<String>)> {
+            let files = self.files.borrow();
+            let (data, version) = files.get(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            Ok((data.clone(), Some(version.to_string())))
+        }
+
+        fn put(&self, path: &str, data: &[u8], expected: Option<&str>) -> io::Result<Written> {
+            if !self.matches(path, expected) {
+                return Ok(Written::Conflict);
+            }
+            let version = self.next_version.get() + 1;
+            self.next_version.set(version);
+            self.files.borrow_mut().insert(path.to_string(), (data.to_vec(), version));
+            Ok(Written::Stored(Some(version.to_string())))
+        }
+
+        fn delete(&self, path: &str, expected: Option<&str>) -> io::Result<Written> {
+            if expected.is_some() && !self.matches(path, expected) {
+                return Ok(Written::Conflict);
+            }
+            self.files.borrow_mut().remove(path);
+            Ok(Written::Stored(None))
+        }
+    }
+
+    #[test]
+    fn test_local_files() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("meetings")).unwrap();
+        fs::create_dir_all(root.join(INDEX_DIR)).unwrap();
+        fs::write(root.join("meetings").join("standup.txt"), "Notes").unwrap();
+        fs::write(root.join(INDEX_DIR).join("segment"), "index").unwrap();
+        fs::write(root.join(SECRETS_KEY_FILE), "key").unwrap();
+        fs::write(root.join(DATABASE_FILE), "database").unwrap();
+        fs::write(root.join(LOCK_FILE), "{}").unwrap();
+        fs::write(root.join(".indexes.txt"), "not the index").unwrap();
+
+        let files = local_files(root).unwrap();
+        let paths: Vec<&str> = files.keys().map(String::as_str).collect();
+        assert_eq!(paths, vec![".indexes.txt", "meetings/standup.txt"]);
+        assert_eq!(files["meetings/standup.txt"], content_hash(b"Notes"));
+    }
+
+    #[test]
+    fn test_report_summary() {
+        let report = SyncReport {
+            uploaded: vec!["a.txt".to_string(), "b.txt".to_string()],
+            conflicts: vec!["c.txt".to_string()],
+            ..SyncReport::default()
+        };
+        assert_eq!(report.summary(), "2 uploaded, 1 in conflict");
+        assert!(!report.changed_local());
+        assert_eq!(SyncReport::default().summary(), "Everything is up to date");
+    }
+
+    #[test]
+    fn test_state_of_other_remote() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut state = SyncState::load(root, "https://a.example.com/notes").unwrap();
+        state.files.insert(
+            "a.txt".to_string(),
+            SyncedFile {
+                hash: content_hash(b"a"),
+                version: None,
+            },
+        );
+        state.save(root).unwrap();
+
+        assert_eq!(SyncState::load(root, "https://a.example.com/notes").unwrap(), state);
+        let other = SyncState::load(root, "s3:https://b.example.com/notes/").unwrap();
+        assert_eq!(other.remote, "s3:https://b.example.com/notes/");
+        assert!(other.files.is_empty());
+    }
+
+    #[test]
+    fn test_sync_copies_changes() {
+        let server = MemoryBackend::default();
+        let (laptop, phone) = (tempdir().unwrap(), tempdir().unwrap());
+        fs::create_dir_all(laptop.path().join("work")).unwrap();
+        fs::write(laptop.path().join("work").join("plan.txt"), "Ship it").unwrap();
+        fs::write(laptop.path().join("old.txt"), "Stale").unwrap();
+
+        let report = sync(&server, laptop.path()).unwrap();
+        assert_eq!(report.uploaded, vec!["old.txt", "work/plan.txt"]);
+        let report = sync(&server, phone.path()).unwrap();
+        assert_eq!(report.downloaded, vec!["old.txt", "work/plan.txt"]);
+        assert_eq!(fs::read_to_string(phone.path().join("work").join("plan.txt")).unwrap(), "Ship it");
+
+        fs::remove_file(phone.path().join("old.txt")).unwrap();
+        fs::write(phone.path().join("work").join("plan.txt"), "Ship it today").unwrap();
+        let report = sync(&server, phone.path()).unwrap();
+        assert_eq!(report.uploaded, vec!["work/plan.txt"]);
+        assert_eq!(report.deleted, vec!["old.txt"]);
+
+        let report = sync(&server, laptop.path()).unwrap();
+        assert_eq!(report.downloaded, vec!["work/plan.txt"]);
+        assert_eq!(report.deleted, vec!["old.txt"]);
+        assert!(!laptop.path().join("old.txt").exists());
+        assert_eq!(sync(&server, laptop.path()).unwrap(), SyncReport::default());
+    }
+
+    #[test]
+    fn test_sync_ignores_unsafe_remote_paths() {
+        let server = MemoryBackend::default();
+        for path in ["../evil", ".secrets.key", "/etc/evil", "a//b", "a\\b", "./a", "notes/ok.txt"] {
+            server.put(path, b"remote", None).unwrap();
+        }
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().join("vault");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(SECRETS_KEY_FILE), "mine").unwrap();
+
+        let report = sync(&server, &root).unwrap();
+        assert_eq!(report.downloaded, vec!["notes/ok.txt"]);
+        assert!(report.deleted.is_empty());
+        assert!(!temp_dir.path().join("evil").exists());
+        assert_eq!(fs::read_to_string(root.join(SECRETS_KEY_FILE)).unwrap(), "mine");
+        assert_eq!(server.contents("../evil").unwrap(), "remote");
+    }
+
+    #[test]
+    fn test_sync_merges_edits() {
+        let server = MemoryBackend::default();
+        let (laptop, phone) = (tempdir().unwrap(), tempdir().unwrap());
+        fs::write(laptop.path().join("list.txt"), "eggs\nmilk\nbread\n").unwrap();
+        sync(&server, laptop.path()).unwrap();
+        sync(&server, phone.path()).unwrap();
+
+        fs::write(laptop.path().join("list.txt"), "eggs\nmilk\nbread\nbutter\n").unwrap();
+        fs::write(phone.path().join("list.txt"), "ham\nmilk\nbread\n").unwrap();
+        sync(&server, laptop.path()).unwrap();
+        let report = sync(&server, phone.path()).unwrap();
+        assert_eq!(report.merged, vec!["list.txt"]);
+        assert_eq!(server.contents("list.txt").unwrap(), "ham\nmilk\nbread\nbutter\n");
+        assert_eq!(fs::read_to_string(phone.path().join("list.txt")).unwrap(), "ham\nmilk\nbread\nbutter\n");
+    }
+
+    #[test]
+    fn test_conflict_resolution() {
+        let server = MemoryBackend::default();
+        let (laptop, phone) = (tempdir().unwrap(), tempdir().unwrap());
+        fs::write(laptop.path().join("idea.txt"), "A\n").unwrap();
+        fs::write(laptop.path().join("plan.txt"), "A\n").unwrap();
+        sync(&server, laptop.path()).unwrap();
+        sync(&server, phone.path()).unwrap();
+
+        for name in ["idea.txt", "plan.txt"] {
+            fs::write(laptop.path().join(name), "Laptop\n").unwrap();
+            fs::write(phone.path().join(name), "Phone\n").unwrap();
+        }
+        sync(&server, laptop.path()).unwrap();
+        let report = sync(&server, phone.path()).unwrap();
+        assert_eq!(report.conflicts, vec!["idea.txt", "plan.txt"]);
+        assert_eq!(sync(&server, phone.path()).unwrap().conflicts, vec!["idea.txt", "plan.txt"]);
+
+        let copy = resolve(phone.path(), "idea.txt", Resolution::KeepBoth).unwrap();
+        assert_eq!(copy.as_deref(), Some("idea (conflict).txt"));
+        resolve(phone.path(), "plan.txt", Resolution::KeepMine).unwrap();
+        assert!(resolve(phone.path(), "plan.txt", Resolution::KeepTheirs).is_err());
+
+        let report = sync(&server, phone.path()).unwrap();
+        assert_eq!(report.uploaded, vec!["idea (conflict).txt", "plan.txt"]);
+        assert_eq!(report.downloaded, vec!["idea.txt"]);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(fs::read_to_string(phone.path().join("idea.txt")).unwrap(), "Laptop\n");
+        assert_eq!(server.contents("idea (conflict).txt").unwrap(), "Phone\n");
+        assert_eq!(server.contents("plan.txt").unwrap(), "Phone\n");
+    }
+
+    #[test]
+    fn test_tombstones() {
+        let server = MemoryBackend::default();
+        let (laptop, phone) = (tempdir().unwrap(), tempdir().unwrap());
+        fs::write(laptop.path().join("old.txt"), "Stale").unwrap();
+        fs::write(phone.path().join("old.txt"), "Stale").unwrap();
+        sync(&server, laptop.path()).unwrap();
+        fs::remove_file(laptop.path().join("old.txt")).unwrap();
+        sync(&server, laptop.path()).unwrap();
+        assert!(server.contents(TOMBSTONES_FILE).unwrap().contains("old.txt"));
+
+        // The phone never synced the file, but knows from the tombstone that it was deleted
+        let report = sync(&server, phone.path()).unwrap();
+        assert_eq!(report.deleted, vec!["old.txt"]);
+        assert!(!phone.path().join("old.txt").exists());
+
+        // Creating it again clears the tombstone
+        fs::write(phone.path().join("old.txt"), "Fresh").unwrap();
+        sync(&server, phone.path()).unwrap();
+        assert!(!server.contents(TOMBSTONES_FILE).unwrap().contains("old.txt"));
+    }
+
+    #[test]
+    fn test_conflict_copy_path() {
+        assert_eq!(conflict_copy_path("work/idea.txt", |_| false), "work/idea (conflict).txt");
+        assert_eq!(
+            conflict_copy_path("todo", |path| path == "todo (conflict)"),
+            "todo (conflict 2)"
+        );
+        assert_eq!(conflict_copy_path(".hidden", |_| false), ".hidden (conflict)");
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use super::{hmac_sha256, SyncBackend, Written};
+use crate::config::{SyncService, SyncSettings};
+use crate::secrets::{self, decrypt, encrypt, KDF_ITERATIONS, KDF_SALT_LEN, MAX_KDF_ITERATIONS};
+
+/// The name of the file on the server that holds what is needed to derive the key from the
+/// passphrase. It is the only file stored in the clear.
+pub const KEY_FILE: &str = ".sync-key.json";
+
+// Encrypted in the key file, so that a wrong passphrase is detected before anything is synced
+const KEY_CHECK: &[u8] = b"notes sync key check";
+
+/// What the server keeps to let each device derive the same key from the passphrase.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct KeyFile {
+    /// The base64 salt of the key derivation.
+    salt: String,
+    /// The PBKDF2 rounds of the key derivation.
+    iterations: u32,
+    /// `KEY_CHECK` encrypted with the content key, in base64.
+    check: String,
+}
+
+/// A server whose files are encrypted on this device before upload. File contents are
+/// encrypted with random nonces; paths are encrypted deterministically, so that every device
+/// maps a note to the same name on the server without revealing its title.
+pub struct Encrypted {
+    inner: Box<dyn SyncBackend>,
+    contents: ChaCha20Poly1305,
+    names: ChaCha20Poly1305,
+    name_nonces: Vec<u8>,
+    fingerprint: String,
+}
+
+/// Returns the name of the secret that holds the encryption passphrase for a sync server, or
+/// `None` if the selected service is not set up.
+pub fn passphrase_secret(settings: &SyncSettings) -> Option<String> {
+    let location = match settings.service {
+        SyncService::WebDav => settings.webdav.as_ref().map(|webdav| webdav.url.trim().to_string()),
+        SyncService::S3 => settings.s3.as_ref().map(|s3| {
+            let endpoint = s3.endpoint.trim().trim_end_matches('/');
+            format!("{}/{}/{}", endpoint, s3.bucket.trim(), s3.prefix.trim())
+        }),
+    };
+    location.map(|location| format!("sync-passphrase:{}", location))
+}
+
+impl Encrypted {
+    /// Derives the key from the passphrase and checks it against the key file on the server.
+    /// The first device to sync creates the key file.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The server the encrypted files are stored on.
+    /// * `passphrase` - The passphrase shared by all devices.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Encrypted>` containing the encrypting server, or a `PermissionDenied`
+    /// error if the passphrase differs from the one the key file was made with.
+    pub fn unlock(inner: Box<dyn SyncBackend>, passphrase: &str) -> io::Result<Encrypted> {
+        if passphrase.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Enter the encryption passphrase in Preferences",
+            ));
+        }
+        let key_file = match inner.get(KEY_FILE) {
+            Ok((data, _)) => serde_json::from_slice::<KeyFile>(&data)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let salt = secrets::generate_salt();
+                let encrypted = Encrypted::derive(inner, passphrase, &salt, KDF_ITERATIONS);
+                let key_file = KeyFile {
+                    salt: STANDARD.encode(salt),
+                    iterations: KDF_ITERATIONS,
+                    check: STANDARD.encode(encrypt(&encrypted.contents, KEY_CHECK)?),
+                };
+                let data = serde_json::to_vec_pretty(&key_file)?;
+                return match encrypted.inner.put(KEY_FILE, &data, None)? {
+                    Written::Stored(_) => Ok(encrypted),
+                    // Another device got there first; use its key file
+                    Written::Conflict => Encrypted::unlock(encrypted.inner, passphrase),
+                };
+            }
+            Err(err) => return Err(err),
+        };
+        let salt = STANDARD.decode(&key_file.salt).map_err(invalid_key_file)?;
+        let check = STANDARD.decode(&key_file.check).map_err(invalid_key_file)?;
+        let iterations = KDF_ITERATIONS..=MAX_KDF_ITERATIONS;
+        if salt.len() != KDF_SALT_LEN || !iterations.contains(&key_file.iterations) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has an unsafe salt or number of iterations", KEY_FILE),
+            ));
+        }
+        let encrypted = Encrypted::derive(inner, passphrase, &salt, key_file.iterations);
+        match decrypt(&encrypted.contents, &check) {
+            Ok(plaintext) if plaintext == KEY_CHECK => Ok(encrypted),
+            _ => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The passphrase differs from the one your other devices sync with",
+            )),
+        }
+    }
+
+    /// A short summary of the key, e.g. `3f2a-91c0-5be7-d448`. Devices that sync with the same
+    /// passphrase show the same fingerprint.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    fn derive(inner: Box<dyn SyncBackend>, passphrase: &str, salt: &[u8], iterations: u32) -> Encrypted {
+        let master = secrets::derive_key(passphrase, salt, iterations);
+        // Separate keys for separate purposes, all from the one derived from the passphrase
+        let subkey = |purpose: &str| hmac_sha256(&master, purpose.as_bytes());
+        let fingerprint = subkey("fingerprint")[..8]
+            .chunks(2)
+            .map(|pair| pair.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("-");
+        Encrypted {
+            inner,
+            contents: ChaCha20Poly1305::new(Key::from_slice(&subkey("contents"))),
+            names: ChaCha20Poly1305::new(Key::from_slice(&subkey("names"))),
+            name_nonces: subkey("name nonces"),
+            fingerprint,
+        }
+    }
+
+    // The nonce is derived from the path, so the same path always gets the same name
+    fn encrypt_path(&self, path: &str) -> io::Result<String> {
+        let nonce = hmac_sha256(&self.name_nonces, path.as_bytes());
+        let nonce = Nonce::from_slice(&nonce[..12]);
+        let ciphertext = self
+            .names
+            .encrypt(nonce, path.as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Encryption failed"))?;
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        Ok(URL_SAFE_NO_PAD.encode(data))
+    }
+
+    // Names that are not ours, such as files uploaded before encryption was turned on, give `None`
+    fn decrypt_path(&self, name: &str) -> Option<String> {
+        let data = URL_SAFE_NO_PAD.decode(name).ok()?;
+        String::from_utf8(decrypt(&self.names, &data).ok()?).ok()
+    }
+}
+
+impl SyncBackend for Encrypted {
+    fn remote(&self) -> String {
+        format!("encrypted:{}:{}", self.fingerprint, self.inner.remote())
+    }
+
+    fn list(&self) -> io::Result<BTreeMap<String, Option<String>>> {
+        let files = self.inner.list()?;
+        Ok(files
+            .into_iter()
+            .filter_map(|(name, version)| Some((self.decrypt_path(&name)?, version)))
+            .collect())
+    }
+
+    fn get(&self, path: &str) -> io::Result<(Vec<u8>, Option<String>)> {
+        let (data, version) = self.inner.get(&self.encrypt_path(path)?)?;
+        Ok((decrypt(&self.contents, &data)?, version))
+    }
+
+    fn put(&self, path: &str, data: &[u8], expected: Option<&str>) -> io::Result<Written> {
+        let data = encrypt(&self.contents, data)?;
+        self.inner.put(&self.encrypt_path(path)?, &data, expected)
+    }
+
+    fn delete(&self, path: &str, expected: Option<&str>) -> io::Result<Written> {
+        self.inner.delete(&self.encrypt_path(path)?, expected)
+    }
+}
+
+fn invalid_key_file(err: base64::DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} is corrupt: {}", KEY_FILE, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::sync;
+    use crate::sync::tests::MemoryBackend;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_encrypted_sync() {
+        let server = MemoryBackend::default();
+        let (laptop, phone) = (tempdir().unwrap(), tempdir().unwrap());
+        fs::write(laptop.path().join("Secret plans.txt"), "Take over the world").unwrap();
+        let encrypted = Encrypted::unlock(Box::new(server.clone()), "correct horse").unwrap();
+        sync(&encrypted, laptop.path()).unwrap();
+
+        // Only the key file is readable on the server
+        let files = server.files.borrow().clone();
+        assert!(files.contains_key(KEY_FILE));
+        for (name, (data, _)) in &files {
+            assert!(!name.contains("Secret"));
+            assert!(!String::from_utf8_lossy(data).contains("Take over"));
+        }
+
+        let wrong = Encrypted::unlock(Box::new(server.clone()), "battery staple");
+        assert_eq!(wrong.err().map(|err| err.kind()), Some(io::ErrorKind::PermissionDenied));
+        let unlocked = Encrypted::unlock(Box::new(server.clone()), "correct horse").unwrap();
+        assert_eq!(unlocked.fingerprint(), encrypted.fingerprint());
+        assert_eq!(unlocked.encrypt_path("a.txt").unwrap(), encrypted.encrypt_path("a.txt").unwrap());
+
+        let report = sync(&unlocked, phone.path()).unwrap();
+        assert_eq!(report.downloaded, vec!["Secret plans.txt"]);
+        assert_eq!(
+            fs::read_to_string(phone.path().join("Secret plans.txt")).unwrap(),
+            "Take over the world"
+        );
+    }
+
+    #[test]
+    fn test_unsafe_key_file() {
+        let salt = STANDARD.encode([0u8; KDF_SALT_LEN]);
+        for (salt, iterations) in [
+            (salt.clone(), 1),
+            (salt, u32::MAX),
+            (STANDARD.encode([0u8; 4]), KDF_ITERATIONS),
+        ] {
+            let server = MemoryBackend::default();
+            let key_file = KeyFile {
+                salt,
+                iterations,
+                check: String::new(),
+            };
+            let data = serde_json::to_vec(&key_file).unwrap();
+            server.put(KEY_FILE, &data, None).unwrap();
+            let unlocked = Encrypted::unlock(Box::new(server), "correct horse");
+            assert_eq!(unlocked.err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Read};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use url::Url;
+
+use super::{http_error, invalid_data, SyncBackend, Written};
+
+// Asks for the ETag and type of every entry of a collection
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+
+/// A folder on a WebDAV server, such as a Nextcloud folder, that the notes are synced with.
+pub struct WebDav {
+    base: Url,
+    authorization: String,
+    // Subfolders known to exist, so uploads only create missing ones
+    folders: RefCell<HashSet<String>>,
+}
+
+/// An entry of a WebDAV folder listing.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Entry {
+    /// The path relative to the synced folder, with `/` separators.
+    path: String,
+    etag: Option<String>,
+    collection: bool,
+}
+
+/// Returns the name of the secret that holds the password for a WebDAV folder.
+pub fn password_secret(url: &str) -> String {
+    format!("webdav:{}", url.trim())
+}
+
+impl WebDav {
+    /// Connects to a WebDAV folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the folder, e.g. `https://cloud.example.com/remote.php/dav/files/me/notes`.
+    /// * `username` - The user to log in as.
+    /// * `password` - The password or app token of the user.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<WebDav>` containing the client, or an error if the URL is not valid.
+    pub fn new(url: &str, username: &str, password: &str) -> io::Result<WebDav> {
+        let base = Url::parse(url.trim()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        if base.cannot_be_a_base() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a folder URL", url)));
+        }
+        let credentials = STANDARD.encode(format!("{}:{}", username, password));
+        Ok(WebDav {
+            base,
+            authorization: format!("Basic {}", credentials),
+            folders: RefCell::new(HashSet::new()),
+        })
+    }
+
+    /// Creates a subfolder. Creating a folder that exists succeeds.
+    pub fn make_folder(&self, path: &str) -> io::Result<()> {
+        match self.request("MKCOL", path, true)?.call() {
+            Ok(_) | Err(ureq::Error::Status(405, _)) => Ok(()),
+            Err(err) => Err(http_error(err)),
+        }
+    }
+
+    // Creates the folders a file goes into, as WebDAV does not create them on upload
+    fn make_parents(&self, path: &str) -> io::Result<()> {
+        let mut parent = String::new();
+        for segment in path.split('/').rev().skip(1).collect::<Vec<_>>().into_iter().rev() {
+            if !parent.is_empty() {
+                parent.push('/');
+            }
+            parent.push_str(segment);
+            if !self.folders.borrow().contains(&parent) {
+                self.make_folder(&parent)?;
+                self.folders.borrow_mut().insert(parent.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn propfind(&self, folder: &str) -> io::Result<Vec<Entry>> {
+        let response = self
+            .request("PROPFIND", folder, true)?
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND_BODY)
+            .map_err(http_error)?;
+        let body = response.into_string()?;
+        parse_multistatus(&body, &decoded_path(self.base.path()))
+    }
+
+    fn request(&self, method: &str, path: &str, folder: bool) -> io::Result<ureq::Request> {
+        let mut url = self.base.clone();
+        {
+            let mut segments = url
+                .path_segments_mut()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Not a folder URL"))?;
+            segments.pop_if_empty();
+            segments.extend(path.split('/').filter(|segment| !segment.is_empty()));
+            if folder {
+                segments.push("");
+            }
+        }
+        Ok(ureq::request_url(method, &url).set("Authorization", &self.authorization))
+    }
+}
+
+impl SyncBackend for WebDav {
+    fn remote(&self) -> String {
+        self.base.to_string()
+    }
+
+    // Walks the folder and its subfolders, one level per request
+    fn list(&self) -> io::Result<BTreeMap<String, Option<String>>> {
+        let mut files = BTreeMap::new();
+        let mut folders = HashSet::new();
+        let mut pending = vec![String::new()];
+        while let Some(folder) = pending.pop() {
+            for entry in self.propfind(&folder)? {
+                if entry.path == folder {
+                    continue;
+                }
+                if entry.collection {
+                    if folders.insert(entry.path.clone()) {
+                        pending.push(entry.path);
+                    }
+                } else {
+                    files.insert(entry.path, entry.etag);
+                }
+            }
+        }
+        self.folders.replace(folders);
+        Ok(files)
+    }
+
+    fn get(&self, path: &str) -> io::Result<(Vec<u8>, Option<String>)> {
+        let response = self.request("GET", path, false)?.call().map_err(http_error)?;
+        let etag = response.header("ETag").map(str::to_string);
+        let mut data = Vec::new();
+        response.into_reader().read_to_end(&mut data)?;
+        Ok((data, etag))
+    }
+
+    fn put(&self, path: &str, data: &[u8], expected: Option<&str>) -> io::Result<Written> {
+        self.make_parents(path)?;
+        let request = self.request("PUT", path, false)?;
+        let request = match expected {
+            Some(etag) => request.set("If-Match", etag),
+            None => request.set("If-None-Match", "*"),
+        };
+        match request.send_bytes(data) {
+            Ok(response) => Ok(Written::Stored(response.header("ETag").map(str::to_string))),
+            Err(ureq::Error::Status(412, _)) => Ok(Written::Conflict),
+            Err(err) => Err(http_error(err)),
+        }
+    }
+
+    fn delete(&self, path: &str, expected: Option<&str>) -> io::Result<Written> {
+        let mut request = self.request("DELETE", path, false)?;
+        if let Some(etag) = expected {
+            request = request.set("If-Match", etag);
+        }
+        match request.call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(Written::Stored(None)),
+            Err(ureq::Error::Status(412, _)) => Ok(Written::Conflict),
+            Err(err) => Err(http_error(err)),
+        }
+    }
+}
+
+/// Reads the entries of a `207 Multi-Status` response to a `PROPFIND` request.
+///
+/// # Arguments
+///
+/// * `body` - The XML response.
+/// * `base_path` - The decoded path of the synced folder, which is removed from the entry paths.
+fn parse_multistatus(body: &str, base_path: &str) -> io::Result<Vec<Entry>> {
+    let mut reader = Reader::from_str(body);
+    let mut entries = Vec::new();
+    let mut entry: Option<Entry> = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event().map_err(invalid_data)? {
+            Event::Start(start) => {
+                text.clear();
+                match start.local_name().as_ref() {
+                    b"response" => entry = Some(Entry::default()),
+                    b"collection" => entry.iter_mut().for_each(|entry| entry.collection = true),
+                    _ => {}
+                }
+            }
+            Event::Empty(empty) if empty.local_name().as_ref() == b"collection" => {
+                entry.iter_mut().for_each(|entry| entry.collection = true);
+            }
+            Event::Text(chunk) => text.push_str(&chunk.unescape().map_err(invalid_data)?),
+            Event::End(end) => {
+                let value = std::mem::take(&mut text);
+                match (end.local_name().as_ref(), &mut entry) {
+                    (b"href", Some(entry)) => {
+                        // Servers send either a path or a full URL
+                        let href = Url::parse(value.trim())
+                            .map(|url| url.path().to_string())
+                            .unwrap_or_else(|_| value.trim().to_string());
+                        let path = decoded_path(&href);
+                        let relative = path.strip_prefix(base_path.trim_end_matches('/')).unwrap_or(&path);
+                        entry.path = relative.trim_matches('/').to_string();
+                    }
+                    (b"getetag", Some(entry)) => entry.etag = Some(value.trim().to_string()).filter(|etag| !etag.is_empty()),
+                    (b"response", entry) => entries.extend(entry.take()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn decoded_path(path: &str) -> String {
+    percent_decode_str(path).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multistatus() {
+        let body = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/remote.php/dav/files/me/notes/</d:href>
+    <d:propstat><d:prop><d:getetag>"root"</d:getetag><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://cloud.example.com/remote.php/dav/files/me/notes/My%20Notes/</d:href>
+    <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/me/notes/groceries.txt</d:href>
+    <d:propstat><d:prop><d:getetag>"abc&amp;1"</d:getetag><d:resourcetype/></d:prop></d:propstat>
+  </d:response>
+</d:multistatus>"#;
+        let entries = parse_multistatus(body, "/remote.php/dav/files/me/notes").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    path: String::new(),
+                    etag: Some("\"root\"".to_string()),
+                    collection: true,
+                },
+                Entry {
+                    path: "My Notes".to_string(),
+                    etag: None,
+                    collection: true,
+                },
+                Entry {
+                    path: "groceries.txt".to_string(),
+                    etag: Some("\"abc&1\"".to_string()),
+                    collection: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_request_urls() {
+        let client = WebDav::new("https://cloud.example.com/dav/notes/", "me", "secret").unwrap();
+        let request = client.request("PUT", "My Notes/a#1.txt", false).unwrap();
+        assert_eq!(request.url(), "https://cloud.example.com/dav/notes/My%20Notes/a%231.txt");
+        let request = client.request("PROPFIND", "", true).unwrap();
+        assert_eq!(request.url(), "https://cloud.example.com/dav/notes/");
+        assert!(WebDav::new("mailto:me@example.com", "me", "secret").is_err());
+    }
+}
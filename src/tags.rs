@@ -0,0 +1,142 @@
+use crate::front_matter::FrontMatter;
+
+/// Extracts the tags of a note.
+///
+/// Tags come from the `tags` front matter field and from inline `#tag` words in the body.
+/// Headings (`# Title`) and fenced code blocks are ignored.
+///
+/// # Arguments
+///
+/// * `content` - The full content of the note.
+///
+/// # Returns
+///
+/// The tags without their leading `#`, in order of first appearance and without duplicates.
+pub fn extract(content: &str) -> Vec<String> {
+    let (front_matter, body) = FrontMatter::parse(content);
+    let mut tags = front_matter.tags();
+    let mut in_code_block = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        for tag in inline_tags(line) {
+            if !tags.iter().any(|existing| existing == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+    tags
+}
+
+/// Renames a tag in the front matter and the inline `#tag` words of a note.
+///
+/// # Arguments
+///
+/// * `content` - The full content of the note.
+/// * `from` - The tag to rename, without its leading `#`.
+/// * `to` - The new name of the tag.
+///
+/// # Returns
+///
+/// The updated content, or `None` if the note does not have the tag.
+pub fn rename(content: &str, from: &str, to: &str) -> Option<String> {
+    let (mut front_matter, body) = FrontMatter::parse(content);
+    let mut changed = false;
+    let tags = front_matter.tags();
+    if tags.iter().any(|tag| tag == from) {
+        let mut renamed: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = if tag == from { to.to_string() } else { tag };
+            if !renamed.contains(&tag) {
+                renamed.push(tag);
+            }
+        }
+        front_matter.set("tags", &renamed.join(", "));
+        changed = true;
+    }
+
+    let mut new_body = String::with_capacity(body.len());
+    let mut in_code_block = false;
+    for line in body.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block || !inline_tags(line).contains(&from) {
+            new_body.push_str(line);
+            continue;
+        }
+        let mut previous = ' ';
+        let mut rest_start = 0;
+        for (index, c) in line.char_indices() {
+            if c == '#' && previous.is_whitespace() {
+                let rest = &line[index + 1..];
+                let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+                if rest[..end] == *from {
+                    new_body.push_str(&line[rest_start..index + 1]);
+                    new_body.push_str(to);
+                    rest_start = index + 1 + end;
+                }
+            }
+            previous = c;
+        }
+        new_body.push_str(&line[rest_start..]);
+        changed = true;
+    }
+
+    changed.then(|| front_matter.render(&new_body))
+}
+
+/// Returns whether a character may appear in a tag name.
+pub fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || c == '/'
+}
+
+/// Finds the inline `#tag` words of a single line.
+fn inline_tags(line: &str) -> Vec<&str> {
+    let mut tags = Vec::new();
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            let rest = &line[index + 1..];
+            let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+            let tag = &rest[..end];
+            // Tags need at least one letter so that `#1` or `#` headings are not tags.
+            if tag.chars().any(char::is_alphabetic) {
+                tags.push(tag);
+            }
+        }
+        previous = c;
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_tags() {
+        let content = "---\ntags: work\n---\n# Heading\nSome #idea and #work, issue #42.\n```\n#not-a-tag\n```\n#done";
+        assert_eq!(extract(content), vec!["work", "idea", "done"]);
+    }
+
+    #[test]
+    fn test_rename_tag() {
+        let content = "---\ntags: work, home\n---\nSome #work and #workshop.\n```\n#work\n```\n";
+        assert_eq!(
+            rename(content, "work", "job").unwrap(),
+            "---\ntags: job, home\n---\nSome #job and #workshop.\n```\n#work\n```\n"
+        );
+        assert_eq!(rename(content, "play", "fun"), None);
+    }
+
+    #[test]
+    fn test_tag_must_follow_whitespace() {
+        assert!(extract("see https://example.com/#anchor and a#b").is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::SystemTime;
+
+use crate::front_matter::FrontMatter;
+use crate::notes::Notes;
+
+/// A Markdown task checkbox (`- [ ] ...`) found in the body of a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineTask {
+    /// The title of the note containing the task.
+    pub note: String,
+    /// The line of the task within the note, counting from zero.
+    pub line: usize,
+    /// The text after the checkbox.
+    pub text: String,
+    /// Whether the checkbox is ticked.
+    pub done: bool,
+    /// Whether the note is locked read-only, so that the checkbox can't be ticked.
+    pub locked: bool,
+}
+
+/// An index of the inline tasks of every note, re-reading only notes that changed.
+#[derive(Default)]
+pub struct TaskIndex {
+    entries: HashMap<String, (SystemTime, Vec<InlineTask>)>,
+}
+
+impl TaskIndex {
+    /// Brings the index up to date with the notes on disk.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let metas = Notes::list_notes_with_meta()?;
+        self.entries
+            .retain(|title, _| metas.iter().any(|meta| &meta.title == title));
+        for meta in metas {
+            let up_to_date = self
+                .entries
+                .get(&meta.title)
+                .is_some_and(|(modified, _)| *modified == meta.modified);
+            if up_to_date {
+                continue;
+            }
+            let content = Notes::read_note_file(&meta.title)?;
+            let tasks = extract(&meta.title, &content);
+            self.entries.insert(meta.title, (meta.modified, tasks));
+        }
+        Ok(())
+    }
+
+    /// Returns the notes that contain tasks along with their tasks, ordered by note title.
+    pub fn by_note(&self) -> Vec<(&str, &[InlineTask])> {
+        let mut notes: Vec<(&str, &[InlineTask])> = self
+            .entries
+            .iter()
+            .filter(|(_, (_, tasks))| !tasks.is_empty())
+            .map(|(title, (_, tasks))| (title.as_str(), tasks.as_slice()))
+            .collect();
+        notes.sort_by_key(|(title, _)| title.to_lowercase());
+        notes
+    }
+}
+
+/// Extracts the task checkboxes from a note.
+///
+/// Both `-` and `*` list markers are recognized, at any indentation, with `[ ]` for open
+/// tasks and `[x]` or `[X]` for completed ones.
+///
+/// # Arguments
+///
+/// * `note` - The title of the note.
+/// * `content` - The content of the note.
+///
+/// # Returns
+///
+/// The tasks in the order they appear in the note.
+pub fn extract(note: &str, content: &str) -> Vec<InlineTask> {
+    let locked = FrontMatter::parse(content).0.is_locked();
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let (done, text) = parse_checkbox(text)?;
+            Some(InlineTask {
+                note: note.to_string(),
+                line,
+                text: text.trim().to_string(),
+                done,
+                locked,
+            })
+        })
+        .collect()
+}
+
+/// Flips the checkbox on a line of a note's content.
+///
+/// # Arguments
+///
+/// * `content` - The content of the note.
+/// * `line` - The line of the task, counting from zero.
+///
+/// # Returns
+///
+/// The updated content, or `None` if the line is not a task.
+pub fn toggle_in_content(content: &str, line: usize) -> Option<String> {
+    let mut lines: Vec<String> = content.split('\n').map(str::to_string).collect();
+    let target = lines.get_mut(line)?;
+    let (done, _) = parse_checkbox(target)?;
+    let marker = target.find('[')?;
+    let replacement = if done { " " } else { "x" };
+    target.replace_range(marker + 1..marker + 2, replacement);
+    Some(lines.join("\n"))
+}
+
+/// Finds the line a task is on now, as the note may have been edited since the task was indexed.
+///
+/// # Arguments
+///
+/// * `content` - The current content of the note.
+/// * `task` - The task as it was indexed.
+///
+/// # Returns
+///
+/// The line of the task with the same text and state nearest to where it was, or `None` if it is
+/// no longer in the note.
+pub fn locate(content: &str, task: &InlineTask) -> Option<usize> {
+    extract(&task.note, content)
+        .into_iter()
+        .filter(|found| found.text == task.text && found.done == task.done)
+        .min_by_key(|found| found.line.abs_diff(task.line))
+        .map(|found| found.line)
+}
+
+/// Flips the checkbox of a task in its note file.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure. It fails if the line is no longer a task,
+/// or with a `PermissionDenied` error if the note is locked.
+pub fn toggle(task: &InlineTask) -> io::Result<()> {
+    let content = Notes::read_note_file(&task.note)?;
+    if FrontMatter::parse(&content).0.is_locked() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is locked", task.note),
+        ));
+    }
+    let updated = locate(&content, task)
+        .and_then(|line| toggle_in_content(&content, line))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "The task is no longer in the note"))?;
+    Notes::update_note_file(&task.note, &updated)
+}
+
+/// Parses a task line, returning whether it is done and the text after the checkbox.
+fn parse_checkbox(line: &str) -> Option<(bool, &str)> {
+    let rest = line.trim_start();
+    let rest = rest.strip_prefix("- ").or_else(|| rest.strip_prefix("* "))?;
+    let rest = rest.trim_start();
+    let done = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    let text = &rest[3..];
+    if !text.is_empty() && !text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((done, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_tasks() {
+        let content = "# Plan\n- [ ] write draft\n  * [x] gather notes\n- [link](url)\n-[ ] not a task\n- [X]";
+        let tasks = extract("plan", content);
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].line, 1);
+        assert_eq!(tasks[0].text, "write draft");
+        assert!(!tasks[0].done);
+        assert_eq!(tasks[1].line, 2);
+        assert!(tasks[1].done);
+        assert_eq!(tasks[2].text, "");
+        assert!(!tasks[0].locked);
+        assert!(extract("plan", "---\nlocked: true\n---\n- [ ] sign off")[0].locked);
+    }
+
+    #[test]
+    fn test_toggle_in_content() {
+        let content = "- [ ] one\n  - [x] two\n";
+        assert_eq!(toggle_in_content(content, 0).as_deref(), Some("- [x] one\n  - [x] two\n"));
+        assert_eq!(toggle_in_content(content, 1).as_deref(), Some("- [ ] one\n  - [ ] two\n"));
+        assert_eq!(toggle_in_content(content, 2), None);
+    }
+
+    #[test]
+    fn test_locate() {
+        let task = extract("plan", "- [ ] one\n- [ ] two\n- [ ] one")[2].clone();
+        assert_eq!(locate("- [ ] one\n- [ ] two\n- [ ] one", &task), Some(2));
+        assert_eq!(locate("# Plan\n\n- [ ] one\n- [ ] two\n- [ ] one", &task), Some(4));
+        assert_eq!(locate("- [ ] one\n- [ ] two", &task), Some(0));
+        assert_eq!(locate("- [x] one\n- [ ] two", &task), None);
+    }
+}
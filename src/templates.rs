@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::notes::Notes;
+
+/// The name of the directory in the `.notes` directory that holds the templates.
+pub const TEMPLATES_DIR: &str = ".templates";
+
+/// Lists the names of the available templates.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<String>>` containing the template names in alphabetical order.
+pub fn list() -> io::Result<Vec<String>> {
+    let dir = templates_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("txt") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            names.push(name.to_string());
+        }
+    }
+    names.sort_by_key(|name| name.to_lowercase());
+    Ok(names)
+}
+
+/// Reads a template.
+///
+/// # Arguments
+///
+/// * `name` - The name of the template, without the `.txt` extension.
+///
+/// # Returns
+///
+/// An `io::Result<String>` containing the template text or an error.
+pub fn read(name: &str) -> io::Result<String> {
+    fs::read_to_string(templates_dir()?.join(format!("{}.txt", name)))
+}
+
+/// Fills in the `{{placeholders}}` of a template.
+///
+/// `{{title}}`, `{{date}}` (`YYYY-MM-DD`) and `{{time}}` (`HH:MM`) are always available;
+/// `vars` can add to or override them. Unknown placeholders are left as they are.
+///
+/// # Arguments
+///
+/// * `template` - The template text.
+/// * `title` - The title of the note being created.
+/// * `vars` - Additional values for placeholders, keyed by placeholder name.
+///
+/// # Returns
+///
+/// The rendered text.
+pub fn render(template: &str, title: &str, vars: &HashMap<String, String>) -> String {
+    let now = chrono::Local::now();
+    let mut values = HashMap::from([
+        ("title".to_string(), title.to_string()),
+        ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+        ("time".to_string(), now.format("%H:%M").to_string()),
+    ]);
+    values.extend(vars.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + end];
+        rendered.push_str(&rest[..start]);
+        match values.get(name.trim()) {
+            Some(value) => rendered.push_str(value),
+            None => rendered.push_str(&rest[start..start + 4 + end]),
+        }
+        rest = &rest[start + 4 + end..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn templates_dir() -> io::Result<PathBuf> {
+    Ok(Notes::get_notes_dir()?.join(TEMPLATES_DIR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup_temp_notes_dir;
+
+    #[test]
+    fn test_render_placeholders() {
+        let vars = HashMap::from([("project".to_string(), "Apollo".to_string())]);
+        let rendered = render("# {{ title }} for {{project}}\n{{unknown}} {{date", "Kickoff", &vars);
+        assert_eq!(rendered, "# Kickoff for Apollo\n{{unknown}} {{date");
+
+        let date = render("{{date}}", "", &HashMap::new());
+        assert_eq!(date, chrono::Local::now().format("%Y-%m-%d").to_string());
+    }
+
+    #[test]
+    fn test_list_templates() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        assert!(list().unwrap().is_empty());
+        let dir = temp_notes_dir.join(TEMPLATES_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("meeting.txt"), "## Attendees").unwrap();
+        fs::write(dir.join("Daily.txt"), "{{date}}").unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+        assert_eq!(list().unwrap(), vec!["Daily", "meeting"]);
+        assert_eq!(read("meeting").unwrap(), "## Attendees");
+    }
+}
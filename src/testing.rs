@@ -0,0 +1,53 @@
+//! Helpers for the tests of modules that read and write the notes directory.
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use tempfile::{tempdir, TempDir};
+
+use crate::vault;
+
+// `HOME` is shared by the whole process while tests run in parallel, so the tests that point it
+// at a temporary directory take turns
+static HOME_LOCK: Mutex<()> = Mutex::new(());
+
+/// A temporary `.notes` directory that is used as `~/.notes` until it is dropped.
+///
+/// Only one exists at a time. Creating another waits until the first is dropped.
+pub struct TempNotesDir {
+    path: PathBuf,
+    _temp_dir: TempDir,
+    // Dropped last, once the directory is gone
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Deref for TempNotesDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Creates an empty `.notes` directory in a temporary home directory, and points `HOME` at it.
+///
+/// # Returns
+///
+/// A `TempNotesDir` that derefs to the path of the `.notes` directory. It must be kept alive for
+/// as long as the test uses the notes directory.
+pub fn setup_temp_notes_dir() -> TempNotesDir {
+    let guard = HOME_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join(".notes");
+    fs::create_dir_all(&path).unwrap();
+    env::set_var("HOME", temp_dir.path());
+    vault::set_active_root(None);
+    TempNotesDir {
+        path,
+        _temp_dir: temp_dir,
+        _guard: guard,
+    }
+}
@@ -0,0 +1,235 @@
+use std::io;
+
+use eframe::egui::{self, Color32, FontFamily, FontId, Stroke, Visuals};
+use serde::{Deserialize, Serialize};
+
+use crate::reader::SERIF_FAMILY;
+
+/// The font notes are edited in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EditorFont {
+    /// The font of the rest of the app.
+    #[default]
+    Proportional,
+    /// A fixed-width font, as for code.
+    Monospace,
+    /// The serif font of reading mode, where one is installed.
+    Serif,
+}
+
+impl EditorFont {
+    /// Every editor font, in the order they are offered.
+    pub const ALL: [EditorFont; 3] = [EditorFont::Proportional, EditorFont::Monospace, EditorFont::Serif];
+
+    /// A human-readable name of the font.
+    pub fn label(self) -> &'static str {
+        match self {
+            EditorFont::Proportional => "Sans-serif",
+            EditorFont::Monospace => "Monospace",
+            EditorFont::Serif => "Serif",
+        }
+    }
+
+    fn family(self) -> FontFamily {
+        match self {
+            EditorFont::Proportional => FontFamily::Proportional,
+            EditorFont::Monospace => FontFamily::Monospace,
+            EditorFont::Serif => FontFamily::Name(SERIF_FAMILY.into()),
+        }
+    }
+}
+
+/// A colour scheme and editor font defined by the user, persisted in the configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Theme {
+    /// The name the theme is picked by.
+    pub name: String,
+    /// Whether the theme starts from the dark look rather than the light one.
+    pub dark: bool,
+    /// The colour of selections and links, e.g. `#3b82f6`.
+    pub accent: String,
+    /// The colour of panels and windows, or `None` to keep the one of the dark or light look.
+    pub background: Option<String>,
+    /// The colour of text, or `None` to keep the one of the dark or light look.
+    pub text: Option<String>,
+    /// The font notes are edited in.
+    pub editor_font: EditorFont,
+    /// The size of the editor font in points.
+    pub editor_font_size: f32,
+    /// The distance between lines in the editor as a multiple of the font size.
+    pub line_spacing: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "Custom".to_string(),
+            dark: true,
+            accent: "#3b82f6".to_string(),
+            background: None,
+            text: None,
+            editor_font: EditorFont::default(),
+            editor_font_size: 14.0,
+            line_spacing: 1.3,
+        }
+    }
+}
+
+impl Theme {
+    /// Builds the egui look of the theme.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Visuals>` containing the look, or an `InvalidInput` error if one of the
+    /// colours is not valid.
+    pub fn visuals(&self) -> io::Result<Visuals> {
+        let mut visuals = if self.dark { Visuals::dark() } else { Visuals::light() };
+        let accent = parse_color(&self.accent)?;
+        visuals.selection.bg_fill = accent.gamma_multiply(0.6);
+        visuals.selection.stroke = Stroke::new(1.0, accent);
+        visuals.hyperlink_color = accent;
+        visuals.widgets.hovered.bg_stroke = Stroke::new(1.0, accent);
+        if let Some(background) = &self.background {
+            let background = parse_color(background)?;
+            visuals.panel_fill = background;
+            visuals.window_fill = background;
+            visuals.faint_bg_color = background;
+        }
+        if let Some(text) = &self.text {
+            visuals.override_text_color = Some(parse_color(text)?);
+        }
+        Ok(visuals)
+    }
+
+    /// The font notes are edited in.
+    pub fn editor_font_id(&self) -> FontId {
+        FontId::new(self.editor_font_size, self.editor_font.family())
+    }
+}
+
+/// Reads a colour written as `#rrggbb` or `#rrggbbaa`.
+///
+/// # Arguments
+///
+/// * `color` - The colour, with or without the leading `#`.
+///
+/// # Returns
+///
+/// An `io::Result<Color32>` containing the colour, or an `InvalidInput` error if it is not valid.
+pub fn parse_color(color: &str) -> io::Result<Color32> {
+    let hex = color.trim().trim_start_matches('#');
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid colour {}", color));
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid());
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Color32::from_rgba_unmultiplied(channel(0)?, channel(2)?, channel(4)?, alpha))
+}
+
+/// Writes a colour the way `parse_color` reads it, leaving out the alpha if it is opaque.
+pub fn format_color(color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    if a == 255 {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+/// Checks themes before they are saved.
+///
+/// # Arguments
+///
+/// * `themes` - The themes defined by the user.
+///
+/// # Returns
+///
+/// An `io::Result<()>` with an `InvalidInput` error if a theme has no name, shares its name with
+/// another theme, or has a colour that is not valid.
+pub fn validate(themes: &[Theme]) -> io::Result<()> {
+    for (index, theme) in themes.iter().enumerate() {
+        let name = theme.name.trim();
+        if name.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Every theme needs a name"));
+        }
+        if themes[..index].iter().any(|other| other.name.trim() == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("There are two themes named {}", name),
+            ));
+        }
+        theme.visuals()?;
+    }
+    Ok(())
+}
+
+/// Finds a theme by name.
+///
+/// # Arguments
+///
+/// * `themes` - The themes defined by the user.
+/// * `name` - The name of the theme in use, or `None` for the built-in look.
+pub fn find<'a>(themes: &'a [Theme], name: Option<&str>) -> Option<&'a Theme> {
+    let name = name?;
+    themes.iter().find(|theme| theme.name == name)
+}
+
+/// Switches the app to a theme, or back to the built-in dark or light look.
+///
+/// # Arguments
+///
+/// * `ctx` - The egui context.
+/// * `theme` - The theme to use, or `None` for the built-in look.
+pub fn apply(ctx: &egui::Context, theme: Option<&Theme>) {
+    let visuals = match theme.map(Theme::visuals) {
+        Some(Ok(visuals)) => visuals,
+        Some(Err(err)) => {
+            log::warn!("Failed to apply the theme: {}", err);
+            return;
+        }
+        None if ctx.style().visuals.dark_mode => Visuals::dark(),
+        None => Visuals::light(),
+    };
+    ctx.set_visuals(visuals);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(parse_color("#3b82f6").unwrap(), Color32::from_rgb(0x3b, 0x82, 0xf6));
+        assert_eq!(parse_color("ff000080").unwrap(), Color32::from_rgba_unmultiplied(255, 0, 0, 128));
+        assert_eq!(format_color(parse_color("#3B82F6").unwrap()), "#3b82f6");
+        assert_eq!(parse_color("#12345").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(parse_color("#12345g").is_err());
+        assert!(parse_color("#ééé").is_err());
+    }
+
+    #[test]
+    fn test_themes() {
+        let paper = Theme {
+            name: "Paper".to_string(),
+            dark: false,
+            background: Some("#fdf6e3".to_string()),
+            ..Theme::default()
+        };
+        let visuals = paper.visuals().unwrap();
+        assert!(!visuals.dark_mode);
+        assert_eq!(visuals.panel_fill, Color32::from_rgb(0xfd, 0xf6, 0xe3));
+        assert_eq!(visuals.hyperlink_color, parse_color("#3b82f6").unwrap());
+
+        let mut themes = vec![paper.clone(), Theme::default()];
+        assert!(validate(&themes).is_ok());
+        assert_eq!(find(&themes, Some("Paper")), Some(&paper));
+        assert_eq!(find(&themes, None), None);
+        themes[1].name = " Paper ".to_string();
+        assert!(validate(&themes).is_err());
+        themes[1].name = "Night".to_string();
+        themes[1].text = Some("white".to_string());
+        assert!(validate(&themes).is_err());
+    }
+}
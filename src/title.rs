@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::storage::{self, atomic_write};
+
+/// The name of the file in the `.notes` directory that maps shortened file names back to the
+/// parts of titles they were made from.
+pub const TITLES_FILE: &str = ".titles.json";
+
+/// Characters that cannot appear in file names on at least one supported platform.
+const RESERVED_CHARS: &[char] = &['\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// File names that Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The longest a folder or file name may be on disk, in bytes, before it is shortened.
+///
+/// This stays well below the 255 byte limit of common file systems so that notes in a few
+/// levels of folders still fit in the 260 character paths of older Windows APIs.
+const MAX_FILE_NAME_LEN: usize = 120;
+
+/// The length of the `~` and hash suffix of shortened file names.
+const HASH_SUFFIX_LEN: usize = 17;
+
+/// Why a title cannot be used as the path of a note.
+///
+/// Titles may contain `/` to place a note in a folder, but every part between the slashes
+/// must name something inside `~/.notes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TitleError {
+    /// The title is empty or only whitespace.
+    Empty,
+    /// The title starts or ends with `/`, or contains `//`.
+    EmptySegment,
+    /// A part of the title is `.` or `..`, which would leave the folder.
+    RelativeSegment,
+    /// The title contains a line break, tab or other control character.
+    ControlChar,
+}
+
+impl fmt::Display for TitleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TitleError::Empty => write!(f, "the title is empty"),
+            TitleError::EmptySegment => write!(f, "folder names cannot be empty"),
+            TitleError::RelativeSegment => write!(f, "`.` and `..` cannot be used as names"),
+            TitleError::ControlChar => write!(f, "the title contains a control character"),
+        }
+    }
+}
+
+impl Error for TitleError {}
+
+impl From<TitleError> for io::Error {
+    fn from(err: TitleError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// Checks that a title maps to a file inside the `.notes` directory.
+///
+/// Characters that file systems do not allow are fine, as they are escaped by `to_path`.
+///
+/// # Arguments
+///
+/// * `title` - The title of the note, e.g. `meetings/standup`.
+///
+/// # Returns
+///
+/// A `Result<(), TitleError>` describing the first problem found, if any.
+pub fn validate(title: &str) -> Result<(), TitleError> {
+    if title.trim().is_empty() {
+        return Err(TitleError::Empty);
+    }
+    if title.chars().any(char::is_control) {
+        return Err(TitleError::ControlChar);
+    }
+    for segment in title.split('/') {
+        if segment.is_empty() {
+            return Err(TitleError::EmptySegment);
+        }
+        if segment == "." || segment == ".." {
+            return Err(TitleError::RelativeSegment);
+        }
+    }
+    Ok(())
+}
+
+/// Turns any text into a valid title, keeping as much of it as possible.
+///
+/// Control characters become spaces, folder names are trimmed, and empty and relative folder
+/// names are dropped. The result always passes `validate`.
+///
+/// # Arguments
+///
+/// * `title` - The title as typed.
+///
+/// # Returns
+///
+/// The suggested title, or `Untitled` if nothing usable is left.
+pub fn sanitize(title: &str) -> String {
+    let replaced: String = title.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    let segments: Vec<&str> = replaced
+        .split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+        .collect();
+    match segments.join("/") {
+        title if title.is_empty() => "Untitled".to_string(),
+        title => title,
+    }
+}
+
+/// Returns the path of a note or folder relative to the `.notes` directory.
+///
+/// Each part of the title is escaped so that the same file name works on every platform:
+/// `%`, reserved characters, control characters, leading and trailing dots and spaces, and
+/// the last letter of device names such as `CON` are written as `%XX` bytes. Parts that are
+/// still too long are shortened and remembered in the `.titles.json` file.
+///
+/// # Arguments
+///
+/// * `root` - The notes directory, which holds the `.titles.json` file.
+/// * `title` - A valid title, or the folder part of one.
+/// * `extension` - Added to the last part, e.g. `txt`, or `""` for folders.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the relative path, or an error if the shortened name could not be remembered.
+pub fn to_path(root: &Path, title: &str, extension: &str) -> io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    let mut shortened = Vec::new();
+    for segment in title.split('/').filter(|segment| !segment.is_empty()) {
+        let mut name = encode(segment);
+        if name.len() > MAX_FILE_NAME_LEN {
+            name = shorten(&name, segment);
+            shortened.push((name.clone(), segment.to_string()));
+        }
+        path.push(name);
+    }
+    if !shortened.is_empty() {
+        let mut titles = load_titles(root)?;
+        let before = titles.len();
+        titles.extend(shortened);
+        if titles.len() != before {
+            let data = serde_json::to_string_pretty(&titles)?;
+            atomic_write(&root.join(TITLES_FILE), data.as_bytes(), true)?;
+        }
+    }
+    if !extension.is_empty() {
+        let name = format!("{}.{}", path.file_name().unwrap_or_default().to_string_lossy(), extension);
+        path.set_file_name(name);
+    }
+    Ok(path)
+}
+
+/// Maps a path relative to the notes directory `root`, without its extension, back to a title.
+///
+/// # Returns
+///
+/// The title, or `None` if the path leaves the directory, is hidden, or is not valid UTF-8.
+pub fn from_path(root: &Path, relative: &Path) -> Option<String> {
+    let mut titles = None;
+    let mut parts = Vec::new();
+    for component in relative.components() {
+        let Component::Normal(name) = component else {
+            return None;
+        };
+        let name = name.to_str()?;
+        if name.starts_with('.') {
+            return None;
+        }
+        if is_shortened(name) {
+            let titles = titles.get_or_insert_with(|| load_titles(root).unwrap_or_default());
+            if let Some(segment) = titles.get(name) {
+                parts.push(segment.clone());
+                continue;
+            }
+        }
+        parts.push(decode(name));
+    }
+    Some(parts.join("/"))
+}
+
+/// Escapes a part of a title for use as a file name.
+fn encode(segment: &str) -> String {
+    let stem_len = segment.find('.').unwrap_or(segment.len());
+    let device = RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(segment[..stem_len].trim_end()));
+    let last = segment.chars().count().saturating_sub(1);
+    let mut encoded = String::new();
+    for (index, c) in segment.chars().enumerate() {
+        let edge = (index == 0 || index == last) && (c == '.' || c == ' ');
+        // Device names are ASCII, so the char index is also the byte index
+        let device_end = device && index + 1 == stem_len;
+        if c == '%' || RESERVED_CHARS.contains(&c) || c.is_control() || edge || device_end {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Reverses `encode`, leaving `%` signs that are not followed by two hex digits as they are.
+fn decode(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|hex| bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Cuts an escaped name down to size, keeping it unique with a hash of the part it was made from.
+fn shorten(name: &str, segment: &str) -> String {
+    let mut end = MAX_FILE_NAME_LEN - HASH_SUFFIX_LEN;
+    while !name.is_char_boundary(end) || name.as_bytes()[end - 2..end].contains(&b'%') {
+        end -= 1;
+    }
+    format!("{}~{:016x}", &name[..end], fnv1a(segment.as_bytes()))
+}
+
+fn is_shortened(name: &str) -> bool {
+    name.len() > HASH_SUFFIX_LEN
+        && name.is_char_boundary(name.len() - HASH_SUFFIX_LEN)
+        && name[name.len() - HASH_SUFFIX_LEN..]
+            .strip_prefix('~')
+            .is_some_and(|hash| hash.bytes().all(|byte| byte.is_ascii_hexdigit()))
+}
+
+/// A hash that stays the same across platforms and Rust versions, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn load_titles(root: &Path) -> io::Result<BTreeMap<String, String>> {
+    let path = root.join(TITLES_FILE);
+    if !storage::backend().exists(&path) {
+        return Ok(BTreeMap::new());
+    }
+    let data = storage::read_to_string(&path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate("meetings/standup"), Ok(()));
+        assert_eq!(validate("what? <now>"), Ok(()));
+        assert_eq!(validate("  "), Err(TitleError::Empty));
+        assert_eq!(validate("../outside"), Err(TitleError::RelativeSegment));
+        assert_eq!(validate("a/../../b"), Err(TitleError::RelativeSegment));
+        assert_eq!(validate("/etc/passwd"), Err(TitleError::EmptySegment));
+        assert_eq!(validate("a//b"), Err(TitleError::EmptySegment));
+        assert_eq!(validate("tab\there"), Err(TitleError::ControlChar));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let cases = [
+            ("meetings/standup", "meetings/standup"),
+            ("../outside", "outside"),
+            ("/etc//passwd/", "etc/passwd"),
+            ("line\nbreak", "line break"),
+            (" spaced / out ", "spaced/out"),
+            ("..", "Untitled"),
+        ];
+        for (title, expected) in cases {
+            assert_eq!(sanitize(title), expected);
+            assert_eq!(validate(&sanitize(title)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_escaped_paths_round_trip() {
+        let root = Path::new("/nonexistent/.notes");
+        let cases = [
+            ("meetings/standup", "meetings/standup.txt"),
+            ("v1.2 release notes", "v1.2 release notes.txt"),
+            ("what? <now>", "what%3F %3Cnow%3E.txt"),
+            ("100%", "100%25.txt"),
+            ("C:\\Windows", "C%3A%5CWindows.txt"),
+            (".hidden/trailing.", "%2Ehidden/trailing%2E.txt"),
+            ("work/con", "work/co%6E.txt"),
+            ("Nul.notes", "Nu%6C.notes.txt"),
+            ("console", "console.txt"),
+            ("café/crème", "café/crème.txt"),
+        ];
+        for (title, expected) in cases {
+            let path = to_path(root, title, "txt").unwrap();
+            assert_eq!(path, Path::new(expected));
+            assert_eq!(from_path(root, &Path::new(expected).with_extension("")), Some(title.to_string()));
+        }
+        assert_eq!(to_path(root, "journal/2024", "").unwrap(), Path::new("journal/2024"));
+        assert_eq!(from_path(root, Path::new("50% off")), Some("50% off".to_string()));
+        assert_eq!(from_path(root, Path::new(".templates/meeting")), None);
+        assert_eq!(from_path(root, Path::new("../outside")), None);
+    }
+
+    #[test]
+    fn test_long_titles_are_shortened() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+
+        let long = format!("{}/{}", "folder ".repeat(30).trim_end(), "é?".repeat(100));
+        let path = to_path(root, &long, "").unwrap();
+        for name in path.iter() {
+            assert!(name.len() <= MAX_FILE_NAME_LEN);
+        }
+        assert!(root.join(TITLES_FILE).exists());
+        assert_eq!(from_path(root, &path), Some(long.clone()));
+        assert_ne!(to_path(root, &format!("{}!", long), "").unwrap(), path);
+    }
+}
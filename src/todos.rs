@@ -1,9 +1,13 @@
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use std::collections::BTreeSet;
+use std::io;
+use std::ops::Range;
 use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
 use serde_json;
-use dirs::home_dir;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+
+use crate::storage::{self, atomic_write};
+use crate::vault;
 
 /// Struct to represent a single todo item.
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -12,6 +16,222 @@ pub struct Todo {
     pub description: String,
     /// The optional due date timestamp of the todo item.
     pub due_date: Option<i64>,
+    /// The title of the note the todo was created from, if any.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// The line of the note the todo was created from, counting from zero.
+    #[serde(default)]
+    pub line: Option<usize>,
+    /// Whether the due date is a hard deadline or a soft target date.
+    #[serde(default)]
+    pub deadline: DeadlineKind,
+    /// Whether the todo has been completed.
+    #[serde(default)]
+    pub done: bool,
+    /// When the todo was completed as a Unix timestamp, if it is and this was recorded.
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+    /// The project the todo belongs to, e.g. `work`.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// The position of the todo in the order the user arranged the todos in.
+    #[serde(default)]
+    pub order: usize,
+    /// The column of the board the todo was put in, e.g. `In Progress`. Completed todos are
+    /// always in the last column.
+    #[serde(default)]
+    pub status: Option<String>,
+    /// A number that identifies the todo while it is moved around, starting from 1.
+    #[serde(default)]
+    pub id: u64,
+    /// The total time spent on the todo in focus sessions, in seconds.
+    #[serde(default)]
+    pub time_spent_secs: u64,
+}
+
+/// The columns of the board unless the user sets up others.
+pub const DEFAULT_COLUMNS: [&str; 3] = ["To Do", "In Progress", "Done"];
+
+/// Changes to apply to a todo. Fields left as `None` are kept as they are.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TodoPatch {
+    /// The new description.
+    pub description: Option<String>,
+    /// The new due date, or `Some(None)` to clear it.
+    pub due_date: Option<Option<i64>>,
+    /// The new kind of due date.
+    pub deadline: Option<DeadlineKind>,
+    /// The new project, or `Some(None)` to take the todo out of its project.
+    pub project: Option<Option<String>>,
+    /// Whether the todo is completed.
+    pub done: Option<bool>,
+}
+
+/// The order todos are listed in.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TodoSort {
+    /// The order the user arranged the todos in.
+    #[default]
+    Manual,
+    /// Soonest due date first, followed by the todos without one.
+    DueDate,
+    /// Case-insensitive alphabetical order by description.
+    Description,
+}
+
+impl TodoSort {
+    /// All sort orders, in the order they are offered in the UI.
+    pub const ALL: [TodoSort; 3] = [TodoSort::Manual, TodoSort::DueDate, TodoSort::Description];
+
+    /// A human-readable label for the sort order.
+    pub fn label(self) -> &'static str {
+        match self {
+            TodoSort::Manual => "Manual",
+            TodoSort::DueDate => "Due date",
+            TodoSort::Description => "Description",
+        }
+    }
+}
+
+/// The kind of date a todo is due on.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineKind {
+    /// A date that must not be missed.
+    #[default]
+    Hard,
+    /// A date the todo is aimed to be done by, which may slip.
+    Target,
+}
+
+impl DeadlineKind {
+    /// A human-readable label for the kind of date.
+    pub fn label(self) -> &'static str {
+        match self {
+            DeadlineKind::Hard => "Deadline",
+            DeadlineKind::Target => "Target",
+        }
+    }
+}
+
+/// How close a todo is to its due date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DueStatus {
+    /// The due date has passed.
+    Overdue,
+    /// The due date is within the alert lead time.
+    DueSoon,
+    /// The due date is further away than the alert lead time.
+    Upcoming,
+}
+
+impl Todo {
+    /// Returns how close the todo is to its due date.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time as a Unix timestamp.
+    /// * `lead_secs` - How many seconds before the due date the todo counts as due soon.
+    ///
+    /// # Returns
+    ///
+    /// The status, or `None` if the todo has no due date.
+    pub fn due_status(&self, now: i64, lead_secs: i64) -> Option<DueStatus> {
+        let due_date = self.due_date?;
+        Some(if due_date < now {
+            DueStatus::Overdue
+        } else if due_date - now <= lead_secs {
+            DueStatus::DueSoon
+        } else {
+            DueStatus::Upcoming
+        })
+    }
+
+    /// Returns the day the todo is due in local time, if it has a due date.
+    pub fn due_day(&self) -> Option<NaiveDate> {
+        Some(DateTime::from_timestamp(self.due_date?, 0)?.with_timezone(&Local).date_naive())
+    }
+
+    /// Returns the column of the board the todo is in.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The names of the columns. The last one holds the completed todos.
+    ///
+    /// # Returns
+    ///
+    /// The index of the column. Open todos whose column was removed are in the first one.
+    pub fn column(&self, columns: &[String]) -> usize {
+        let last = columns.len().saturating_sub(1);
+        if self.done {
+            return last;
+        }
+        self.status
+            .as_ref()
+            .and_then(|status| columns[..last].iter().position(|column| column == status))
+            .unwrap_or(0)
+    }
+
+    // Completing a todo records when, so that the completions can be counted later on
+    fn set_done(&mut self, done: bool) {
+        if done != self.done {
+            self.done = done;
+            self.completed_at = done.then(|| chrono::Utc::now().timestamp());
+        }
+    }
+}
+
+/// How many todos get done, counting the completed todos in the archive too.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TodoStats {
+    /// The number of todos completed since Monday.
+    pub completed_this_week: usize,
+    /// The number of days in a row with at least one completed todo, up to today. A streak
+    /// that reached yesterday still counts, as there is time left to complete a todo today.
+    pub streak: usize,
+    /// The number of open todos whose due date has passed.
+    pub overdue: usize,
+}
+
+impl TodoStats {
+    /// Computes the statistics of the todos.
+    ///
+    /// # Arguments
+    ///
+    /// * `todos` - The todos.
+    /// * `archive` - The archived todos.
+    /// * `now` - The current time.
+    pub fn compute(todos: &Todos, archive: &Todos, now: DateTime<Local>) -> TodoStats {
+        let completed: Vec<NaiveDate> = todos
+            .items
+            .iter()
+            .chain(&archive.items)
+            .filter(|todo| todo.done)
+            .filter_map(|todo| DateTime::from_timestamp(todo.completed_at?, 0))
+            .map(|completed_at| completed_at.with_timezone(&Local).date_naive())
+            .collect();
+        let today = now.date_naive();
+        let monday = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+        let completed_this_week = completed.iter().filter(|&&day| day >= monday).count();
+
+        let days: BTreeSet<NaiveDate> = completed.into_iter().collect();
+        let mut day = if days.contains(&today) { today } else { today - Duration::days(1) };
+        let mut streak = 0;
+        while days.contains(&day) {
+            streak += 1;
+            day -= Duration::days(1);
+        }
+
+        let overdue = todos
+            .items
+            .iter()
+            .filter(|todo| !todo.done && todo.due_status(now.timestamp(), 0) == Some(DueStatus::Overdue))
+            .count();
+        TodoStats {
+            completed_this_week,
+            streak,
+            overdue,
+        }
+    }
 }
 
 /// Struct to manage todos.
@@ -40,20 +260,471 @@ impl Todos {
     /// * `description` - A string representing the description of the todo.
     /// * `due_date` - An optional timestamp representing the due date of the todo.
     pub fn add(&mut self, description: String, due_date: Option<i64>) {
-        self.items.push(Todo { description, due_date });
+        self.push(Todo {
+            description,
+            due_date,
+            ..Default::default()
+        });
+    }
+
+    /// Adds a new todo that refers back to a line of a note.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A string representing the description of the todo.
+    /// * `note` - The title of the note the todo was created from.
+    /// * `line` - The line of the note the todo was created from, counting from zero.
+    pub fn add_linked(&mut self, description: String, note: String, line: usize) {
+        self.push(Todo {
+            description,
+            note: Some(note),
+            line: Some(line),
+            ..Default::default()
+        });
+    }
+
+    /// Adds a new todo owned by a note, without pointing at a particular line.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A string representing the description of the todo.
+    /// * `note` - The title of the note that owns the todo.
+    pub fn add_to_note(&mut self, description: String, note: String) {
+        self.push(Todo {
+            description,
+            note: Some(note),
+            ..Default::default()
+        });
+    }
+
+    /// Adds a new todo to a project.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A string representing the description of the todo.
+    /// * `project` - The name of the project, e.g. `work`.
+    pub fn add_to_project(&mut self, description: String, project: String) {
+        self.push(Todo {
+            description,
+            project: Some(project),
+            ..Default::default()
+        });
+    }
+
+    /// Moves a todo to another position in the manual order.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The index of the todo to move.
+    /// * `to` - The index to move it to.
+    ///
+    /// # Returns
+    ///
+    /// Whether both indices are valid.
+    pub fn move_item(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.items.len() || to >= self.items.len() {
+            return false;
+        }
+        let todo = self.items.remove(from);
+        self.items.insert(to, todo);
+        for (order, todo) in self.items.iter_mut().enumerate() {
+            todo.order = order;
+        }
+        true
+    }
+
+    /// Changes a todo.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the todo.
+    /// * `patch` - The changes to apply.
+    ///
+    /// # Returns
+    ///
+    /// Whether there is a todo at the index.
+    pub fn update(&mut self, index: usize, patch: TodoPatch) -> bool {
+        let Some(todo) = self.items.get_mut(index) else {
+            return false;
+        };
+        if let Some(description) = patch.description {
+            todo.description = description;
+        }
+        if let Some(due_date) = patch.due_date {
+            todo.due_date = due_date;
+        }
+        if let Some(deadline) = patch.deadline {
+            todo.deadline = deadline;
+        }
+        if let Some(project) = patch.project {
+            todo.project = project;
+        }
+        if let Some(done) = patch.done {
+            todo.set_done(done);
+        }
+        true
+    }
+
+    /// Returns the todos owned by a note along with their indices.
+    ///
+    /// # Arguments
+    ///
+    /// * `note` - The title of the note.
+    pub fn for_note(&self, note: &str) -> Vec<(usize, &Todo)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, todo)| todo.note.as_deref() == Some(note))
+            .collect()
+    }
+
+    /// Groups the todos by the note that owns them.
+    ///
+    /// # Returns
+    ///
+    /// The groups with their todos and indices, starting with the todos that belong to no
+    /// note and followed by the notes in alphabetical order.
+    pub fn grouped_by_note(&self) -> Vec<(Option<&str>, Vec<(usize, &Todo)>)> {
+        self.grouped_by(|todo| todo.note.as_deref())
+    }
+
+    /// Groups the todos by their project.
+    ///
+    /// # Returns
+    ///
+    /// The groups with their todos and indices, starting with the todos that belong to no
+    /// project and followed by the projects in alphabetical order.
+    pub fn grouped_by_project(&self) -> Vec<(Option<&str>, Vec<(usize, &Todo)>)> {
+        self.grouped_by(|todo| todo.project.as_deref())
+    }
+
+    /// Returns the names of all projects that have todos, in alphabetical order.
+    pub fn projects(&self) -> Vec<&str> {
+        self.grouped_by_project().into_iter().filter_map(|(project, _)| project).collect()
+    }
+
+    fn grouped_by<'a>(
+        &'a self,
+        key: impl Fn(&'a Todo) -> Option<&'a str>,
+    ) -> Vec<(Option<&'a str>, Vec<(usize, &'a Todo)>)> {
+        let mut groups: Vec<(Option<&str>, Vec<(usize, &Todo)>)> = Vec::new();
+        for (index, todo) in self.items.iter().enumerate() {
+            let group_key = key(todo);
+            match groups.iter_mut().find(|(group, _)| *group == group_key) {
+                Some((_, todos)) => todos.push((index, todo)),
+                None => groups.push((group_key, vec![(index, todo)])),
+            }
+        }
+        groups.sort_by_key(|(group, _)| group.map(str::to_lowercase));
+        groups
+    }
+
+    /// Releases the todos owned by a note, e.g. when the note is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `note` - The title of the note.
+    ///
+    /// # Returns
+    ///
+    /// Whether any todo was owned by the note.
+    pub fn detach_note(&mut self, note: &str) -> bool {
+        let mut detached = false;
+        for todo in self.items.iter_mut().filter(|todo| todo.note.as_deref() == Some(note)) {
+            todo.note = None;
+            todo.line = None;
+            detached = true;
+        }
+        detached
+    }
+
+    /// Points the todos owned by a note to its new title, e.g. after it was moved into a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The old title of the note.
+    /// * `to` - The new title of the note.
+    ///
+    /// # Returns
+    ///
+    /// Whether any todo was owned by the note.
+    pub fn rename_note(&mut self, from: &str, to: &str) -> bool {
+        let mut renamed = false;
+        for todo in self.items.iter_mut().filter(|todo| todo.note.as_deref() == Some(from)) {
+            todo.note = Some(to.to_string());
+            renamed = true;
+        }
+        renamed
+    }
+
+    /// Points the todos owned by a note into the note it was merged into, moving their lines along.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The title of the merged note.
+    /// * `to` - The title of the note it was merged into.
+    /// * `from_line` - The first line of `from` that was kept, after its front matter.
+    /// * `to_line` - The line of `to` that line ended up at.
+    ///
+    /// # Returns
+    ///
+    /// Whether any todo was owned by the note.
+    pub fn merge_note(&mut self, from: &str, to: &str, from_line: usize, to_line: usize) -> bool {
+        let mut merged = false;
+        for todo in self.items.iter_mut().filter(|todo| todo.note.as_deref() == Some(from)) {
+            todo.note = Some(to.to_string());
+            todo.line = todo.line.and_then(|line| line.checked_sub(from_line)).map(|line| line + to_line);
+            merged = true;
+        }
+        merged
+    }
+
+    /// Points the todos on some lines of a note to the note those lines were split off into.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The title of the note that was split.
+    /// * `to` - The title of the note split off it.
+    /// * `lines` - The lines of `from` that were moved.
+    ///
+    /// # Returns
+    ///
+    /// Whether any todo was on those lines.
+    pub fn split_note(&mut self, from: &str, to: &str, lines: Range<usize>) -> bool {
+        let mut split = false;
+        for todo in self.items.iter_mut().filter(|todo| todo.note.as_deref() == Some(from)) {
+            if let Some(line) = todo.line.filter(|line| lines.contains(line)) {
+                todo.note = Some(to.to_string());
+                todo.line = Some(line - lines.start);
+                split = true;
+            }
+        }
+        split
+    }
+
+    /// Returns the todos due on a date of the given kind along with their indices, soonest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of due date to select.
+    pub fn agenda(&self, kind: DeadlineKind) -> Vec<(usize, &Todo)> {
+        let mut agenda: Vec<(usize, &Todo)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, todo)| todo.due_date.is_some() && todo.deadline == kind)
+            .collect();
+        agenda.sort_by_key(|(_, todo)| todo.due_date);
+        agenda
+    }
+
+    /// Returns the todos in each column of the board along with their indices.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The names of the columns. The last one holds the completed todos.
+    pub fn board(&self, columns: &[String]) -> Vec<Vec<(usize, &Todo)>> {
+        let mut board = vec![Vec::new(); columns.len()];
+        if columns.is_empty() {
+            return board;
+        }
+        for (index, todo) in self.items.iter().enumerate() {
+            board[todo.column(columns)].push((index, todo));
+        }
+        board
+    }
+
+    /// Puts a todo in a column of the board. Moving it into the last column completes it, and
+    /// moving it out of there reopens it.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the todo.
+    /// * `column` - The index of the column.
+    /// * `columns` - The names of the columns.
+    ///
+    /// # Returns
+    ///
+    /// Whether both indices are valid.
+    pub fn move_to_column(&mut self, index: usize, column: usize, columns: &[String]) -> bool {
+        let (Some(todo), Some(status)) = (self.items.get_mut(index), columns.get(column)) else {
+            return false;
+        };
+        todo.set_done(column + 1 == columns.len());
+        todo.status = Some(status.clone());
+        true
+    }
+
+    /// Marks todos as completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The indices of the todos. Indices without a todo are ignored.
+    ///
+    /// # Returns
+    ///
+    /// The number of todos that were not completed yet.
+    pub fn complete(&mut self, indices: &[usize]) -> usize {
+        let mut completed = 0;
+        for &index in indices {
+            if let Some(todo) = self.items.get_mut(index).filter(|todo| !todo.done) {
+                todo.set_done(true);
+                completed += 1;
+            }
+        }
+        completed
+    }
+
+    /// Removes todos.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The indices of the todos, in any order. Indices without a todo are ignored.
+    ///
+    /// # Returns
+    ///
+    /// The removed todos, in the order they were listed in.
+    pub fn remove(&mut self, indices: &[usize]) -> Vec<Todo> {
+        let (removed, kept) = std::mem::take(&mut self.items)
+            .into_iter()
+            .enumerate()
+            .partition::<Vec<_>, _>(|(index, _)| indices.contains(index));
+        self.items = kept.into_iter().map(|(_, todo)| todo).collect();
+        removed.into_iter().map(|(_, todo)| todo).collect()
+    }
+
+    /// Moves the completed todos to the archive, which is kept in its own file so that they can
+    /// still be looked up, and saves both files.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<usize>` containing the number of todos that were archived.
+    pub fn archive_completed(&mut self) -> io::Result<usize> {
+        let done: Vec<usize> = (0..self.items.len()).filter(|&index| self.items[index].done).collect();
+        if done.is_empty() {
+            return Ok(0);
+        }
+        let mut archive = Self::load_archive()?;
+        for todo in self.remove(&done) {
+            archive.push(todo);
+        }
+        // The archive is saved first, so that a failure can't lose the todos
+        let data = serde_json::to_string(&archive)?;
+        atomic_write(&Self::get_archive_file_path()?, data.as_bytes(), true)?;
+        self.save_to_file()?;
+        Ok(done.len())
+    }
+
+    /// Loads the archive of completed todos.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Todos>` containing the archived todos, oldest first, which are none if
+    /// nothing has been archived yet.
+    pub fn load_archive() -> io::Result<Todos> {
+        match storage::read_to_string(&Self::get_archive_file_path()?) {
+            Ok(data) => Ok(Todos::loaded(serde_json::from_str(&data)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Todos::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the index of the todo with the given id.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the todo.
+    pub fn position(&self, id: u64) -> Option<usize> {
+        self.items.iter().position(|todo| todo.id == id)
+    }
+
+    /// Adds the time spent in a focus session to a todo.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the todo.
+    /// * `secs` - The time spent in seconds.
+    ///
+    /// # Returns
+    ///
+    /// The todo, or `None` if there is no todo with the id, e.g. because it was deleted.
+    pub fn add_time_spent(&mut self, id: u64, secs: u64) -> Option<&Todo> {
+        let index = self.position(id)?;
+        let todo = &mut self.items[index];
+        todo.time_spent_secs += secs;
+        Some(todo)
+    }
+
+    fn push(&mut self, mut todo: Todo) {
+        todo.order = self.items.iter().map(|existing| existing.order + 1).max().unwrap_or(0);
+        todo.id = self.next_id();
+        self.items.push(todo);
+    }
+
+    fn next_id(&self) -> u64 {
+        self.items.iter().map(|todo| todo.id + 1).max().unwrap_or(1).max(1)
+    }
+
+    // Files saved before todos had an order keep the order they were saved in, as the sort is
+    // stable, and todos saved before they had an id get one
+    fn loaded(mut self) -> Todos {
+        self.items.sort_by_key(|todo| todo.order);
+        for index in 0..self.items.len() {
+            if self.items[index].id == 0 {
+                self.items[index].id = self.next_id();
+            }
+        }
+        self
     }
 
     /// Saves the todos to a file.
     ///
+    /// The file is replaced atomically and synced to disk.
+    ///
     /// # Returns
     ///
     /// An `io::Result<()>` indicating success or failure.
     pub fn save_to_file(&self) -> io::Result<()> {
         let path = Self::get_todos_file_path()?;
-        let mut file = File::create(path)?;
         let data = serde_json::to_string(&self)?;
-        file.write_all(data.as_bytes())?;
-        Ok(())
+        atomic_write(&path, data.as_bytes(), true)
+    }
+
+    /// Loads the todos, repairing the file if it is corrupted.
+    ///
+    /// A file that can't be parsed, e.g. because a write was cut short, is kept as
+    /// `.todos.bak` and replaced by the todos that could still be read from its start.
+    /// A missing file means there are no todos yet.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<(Todos, Option<Recovery>)>` containing the todos and, if the file
+    /// had to be repaired, a report of the repair.
+    pub fn load_or_recover() -> io::Result<(Todos, Option<Recovery>)> {
+        let path = Self::get_todos_file_path()?;
+        let data = match storage::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Todos::new(), None)),
+            Err(err) => return Err(err),
+        };
+        let error = match serde_json::from_str(&data) {
+            Ok(todos) => return Ok((Todos::loaded(todos), None)),
+            Err(err) => err.to_string(),
+        };
+
+        let backup = path.with_file_name(".todos.bak");
+        storage::backend().write(&backup, data.as_bytes(), true)?;
+        let todos = Todos {
+            items: recover_items(&data),
+        }
+        .loaded();
+        todos.save_to_file()?;
+        let recovery = Recovery {
+            recovered: todos.items.len(),
+            backup,
+            error,
+        };
+        Ok((todos, Some(recovery)))
     }
 
     /// Loads the todos from a file.
@@ -63,40 +734,125 @@ impl Todos {
     /// An `io::Result<Todos>` containing the loaded todos or an error.
     pub fn load_from_file() -> io::Result<Todos> {
         let path = Self::get_todos_file_path()?;
-        let mut file = File::open(path)?;
-        let mut data = String::new();
-        file.read_to_string(&mut data)?;
+        let data = storage::read_to_string(&path)?;
         let todos: Todos = serde_json::from_str(&data)?;
-        Ok(todos)
+        Ok(todos.loaded())
     }
 
-    /// Returns the path to the `.todos` file in the `.notes` directory, creating the directory if it doesn't exist.
+    /// Returns the path to the `.todos` file in the notes directory of the active vault, so that every
+    /// vault has its own todos.
     ///
     /// # Returns
     ///
     /// An `io::Result<PathBuf>` containing the path to the `.todos` file or an error.
     fn get_todos_file_path() -> io::Result<PathBuf> {
-        let home = home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
-        let notes_dir = home.join(".notes");
-        if !notes_dir.exists() {
-            fs::create_dir_all(&notes_dir)?;
+        Ok(vault::active_root()?.join(".todos"))
+    }
+
+    // Completed todos are archived next to the `.todos` file
+    fn get_archive_file_path() -> io::Result<PathBuf> {
+        Ok(vault::active_root()?.join(".todos-archive"))
+    }
+}
+
+/// A report of a corrupted todos file that was repaired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// The number of todos that could be read.
+    pub recovered: usize,
+    /// Where the corrupted file was kept.
+    pub backup: PathBuf,
+    /// Why the file could not be read.
+    pub error: String,
+}
+
+/// Reads the todos from the start of a corrupted todos file, up to the first one that is damaged.
+fn recover_items(data: &str) -> Vec<Todo> {
+    let mut items = Vec::new();
+    let Some(start) = data.find("\"items\"").and_then(|items| Some(items + data[items..].find('[')? + 1)) else {
+        return items;
+    };
+    let mut rest = &data[start..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<Todo>();
+        match stream.next() {
+            Some(Ok(todo)) => {
+                items.push(todo);
+                rest = &rest[stream.byte_offset()..];
+            }
+            _ => return items,
         }
-        Ok(notes_dir.join(".todos"))
     }
 }
 
+/// Sorts todos along with their indices.
+///
+/// # Arguments
+///
+/// * `entries` - The todos and their indices, in manual order.
+/// * `sort` - The order to sort them in.
+pub fn sort_entries(entries: &mut [(usize, &Todo)], sort: TodoSort) {
+    match sort {
+        TodoSort::Manual => {}
+        TodoSort::DueDate => entries.sort_by_key(|(_, todo)| (todo.due_date.is_none(), todo.due_date)),
+        TodoSort::Description => entries.sort_by_key(|(_, todo)| todo.description.to_lowercase()),
+    }
+}
+
+/// Checks the columns of a board.
+///
+/// # Arguments
+///
+/// * `columns` - The names of the columns.
+///
+/// # Returns
+///
+/// An `io::Result<()>` with an `InvalidInput` error if there are fewer than two columns, or a
+/// column is unnamed or named twice.
+pub fn validate_columns(columns: &[String]) -> io::Result<()> {
+    if columns.len() < 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The board needs at least two columns"));
+    }
+    let mut names = BTreeSet::new();
+    for column in columns {
+        if column.trim().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Board columns need a name"));
+        }
+        if !names.insert(column.trim()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("There are two board columns named {}", column.trim()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `YYYY-MM-DD` date into a due date at the end of that day in local time.
+///
+/// # Returns
+///
+/// The due date as a Unix timestamp, or `None` if the date is invalid.
+pub fn parse_due_date(date: &str) -> Option<i64> {
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()?;
+    let end_of_day = date.and_hms_opt(23, 59, 59)?;
+    Some(end_of_day.and_local_timezone(Local).earliest()?.timestamp())
+}
+
+/// Formats a due date as a `YYYY-MM-DD` date in local time.
+pub fn format_due_date(due_date: i64) -> String {
+    DateTime::from_timestamp(due_date, 0)
+        .map(|date| date.with_timezone(&Local).format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
-    use tempfile::tempdir;
-
-    fn setup_temp_notes_dir() -> PathBuf {
-        let temp_dir = tempdir().unwrap();
-        let temp_notes_dir = temp_dir.path().join(".notes");
-        fs::create_dir_all(&temp_notes_dir).unwrap();
-        temp_notes_dir
-    }
+    use crate::testing::setup_temp_notes_dir;
+    use chrono::TimeZone;
+    use std::fs;
 
     #[test]
     fn test_add_todo() {
@@ -108,8 +864,7 @@ mod tests {
 
     #[test]
     fn test_save_and_load_todos() {
-        let temp_notes_dir = setup_temp_notes_dir();
-        env::set_var("HOME", temp_notes_dir.parent().unwrap());
+        let _temp_notes_dir = setup_temp_notes_dir();
 
         let mut todos = Todos::new();
         todos.add("Test todo".to_string(), Some(1627849200));
@@ -120,4 +875,309 @@ mod tests {
         assert_eq!(loaded_todos.items[0].description, "Test todo");
         assert_eq!(loaded_todos.items[0].due_date, Some(1627849200));
     }
+
+    #[test]
+    fn test_todos_per_vault() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let other = temp_notes_dir.parent().unwrap().join("other");
+        fs::create_dir_all(&other).unwrap();
+
+        let mut todos = Todos::new();
+        todos.add("Home todo".to_string(), None);
+        todos.save_to_file().unwrap();
+
+        vault::set_active_root(Some(other.clone()));
+        assert!(Todos::load_from_file().is_err());
+        let mut todos = Todos::new();
+        todos.add("Other todo".to_string(), None);
+        todos.save_to_file().unwrap();
+        assert!(other.join(".todos").exists());
+
+        vault::set_active_root(None);
+        let loaded_todos = Todos::load_from_file().unwrap();
+        assert_eq!(loaded_todos.items.len(), 1);
+        assert_eq!(loaded_todos.items[0].description, "Home todo");
+    }
+
+    #[test]
+    fn test_recover_truncated_file() {
+        let temp_notes_dir = setup_temp_notes_dir();
+
+        let mut todos = Todos::new();
+        todos.add("First".to_string(), None);
+        todos.add("Second".to_string(), Some(1627849200));
+        todos.add("Third".to_string(), None);
+        let data = serde_json::to_string(&todos).unwrap();
+        let truncated = &data[..data.find("Third").unwrap()];
+        fs::write(temp_notes_dir.join(".todos"), truncated).unwrap();
+
+        let (todos, recovery) = Todos::load_or_recover().unwrap();
+        let recovery = recovery.unwrap();
+        assert_eq!(recovery.recovered, 2);
+        assert_eq!(todos.items[1].description, "Second");
+        assert_eq!(fs::read_to_string(&recovery.backup).unwrap(), truncated);
+
+        let (todos, recovery) = Todos::load_or_recover().unwrap();
+        assert_eq!(todos.items.len(), 2);
+        assert!(recovery.is_none());
+    }
+
+    #[test]
+    fn test_complete_and_archive() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        let mut todos = Todos::new();
+        todos.add("First".to_string(), None);
+        todos.add("Second".to_string(), None);
+        todos.add("Third".to_string(), None);
+        todos.add("Fourth".to_string(), None);
+        assert_eq!(todos.complete(&[0, 2, 9]), 2);
+        assert_eq!(todos.complete(&[0]), 0);
+        assert_eq!(todos.archive_completed().unwrap(), 2);
+        assert_eq!(todos.archive_completed().unwrap(), 0);
+
+        let removed = todos.remove(&[1, 0]);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[0].description, "Second");
+        todos.complete(&[0]);
+        todos.archive_completed().unwrap();
+
+        assert!(Todos::load_from_file().unwrap().items.is_empty());
+        let archive = Todos::load_archive().unwrap();
+        let descriptions: Vec<&str> = archive.items.iter().map(|todo| todo.description.as_str()).collect();
+        assert_eq!(descriptions, vec!["First", "Third", "Fourth"]);
+        assert!(archive.items.iter().all(|todo| todo.done));
+    }
+
+    #[test]
+    fn test_todo_stats() {
+        let now = Local.with_ymd_and_hms(2024, 3, 14, 12, 0, 0).unwrap();
+        let days_ago = |days: i64| Some((now - Duration::days(days)).timestamp());
+        let mut todos = Todos::new();
+        for completed_at in [days_ago(1), days_ago(2), days_ago(2), days_ago(4)] {
+            todos.add("Done".to_string(), None);
+            let todo = todos.items.last_mut().unwrap();
+            todo.done = true;
+            todo.completed_at = completed_at;
+        }
+        todos.add("Late".to_string(), days_ago(1));
+        todos.add("Soon".to_string(), days_ago(-1));
+        let mut archive = Todos::new();
+        archive.add("Old".to_string(), days_ago(30));
+        archive.items[0].done = true;
+        archive.items[0].completed_at = days_ago(3);
+
+        // Thursday, so Monday was three days ago
+        let stats = TodoStats::compute(&todos, &archive, now);
+        assert_eq!(stats.completed_this_week, 4);
+        assert_eq!(stats.streak, 4);
+        assert_eq!(stats.overdue, 1);
+
+        let stats = TodoStats::compute(&todos, &Todos::new(), now + Duration::days(2));
+        assert_eq!(stats.streak, 0);
+        assert_eq!(stats.completed_this_week, 3);
+
+        todos.complete(&[4]);
+        assert!(todos.items[4].completed_at.is_some());
+        todos.update(
+            4,
+            TodoPatch {
+                done: Some(false),
+                ..TodoPatch::default()
+            },
+        );
+        assert_eq!(todos.items[4].completed_at, None);
+    }
+
+    #[test]
+    fn test_board() {
+        let columns: Vec<String> = DEFAULT_COLUMNS.map(String::from).to_vec();
+        let mut todos = Todos::new();
+        todos.add("Plan".to_string(), None);
+        todos.add("Build".to_string(), None);
+        todos.add("Ship".to_string(), None);
+        todos.items[2].done = true;
+        assert!(todos.move_to_column(1, 1, &columns));
+        assert!(!todos.move_to_column(1, 3, &columns));
+
+        let indices = |todos: &Todos, columns: &[String]| -> Vec<Vec<usize>> {
+            let board = todos.board(columns);
+            board.iter().map(|column| column.iter().map(|(index, _)| *index).collect()).collect()
+        };
+        assert_eq!(indices(&todos, &columns), vec![vec![0], vec![1], vec![2]]);
+
+        assert!(todos.move_to_column(0, 2, &columns));
+        assert!(todos.items[0].done);
+        assert!(todos.move_to_column(2, 0, &columns));
+        assert!(!todos.items[2].done);
+        assert_eq!(indices(&todos, &columns), vec![vec![2], vec![1], vec![0]]);
+
+        // Todos in a column that was removed go back to the first one
+        let columns: Vec<String> = ["Backlog", "Done"].map(String::from).to_vec();
+        assert_eq!(indices(&todos, &columns), vec![vec![1, 2], vec![0]]);
+
+        assert!(validate_columns(&columns).is_ok());
+        assert!(validate_columns(&columns[..1]).is_err());
+        assert!(validate_columns(&["Done".to_string(), " Done ".to_string()]).is_err());
+        assert!(validate_columns(&["Done".to_string(), "".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_update_todo() {
+        let mut todos = Todos::new();
+        todos.add("Tset todo".to_string(), Some(1627849200));
+        assert!(todos.update(
+            0,
+            TodoPatch {
+                description: Some("Test todo".to_string()),
+                ..TodoPatch::default()
+            }
+        ));
+        assert_eq!(todos.items[0].description, "Test todo");
+        assert_eq!(todos.items[0].due_date, Some(1627849200));
+
+        assert!(todos.update(
+            0,
+            TodoPatch {
+                due_date: Some(None),
+                ..TodoPatch::default()
+            }
+        ));
+        assert_eq!(todos.items[0].due_date, None);
+        assert_eq!(todos.items[0].description, "Test todo");
+        assert!(!todos.update(1, TodoPatch::default()));
+    }
+
+    #[test]
+    fn test_move_and_sort_todos() {
+        let _temp_notes_dir = setup_temp_notes_dir();
+
+        let mut todos = Todos::new();
+        todos.add("first".to_string(), None);
+        todos.add("Second".to_string(), Some(200));
+        todos.add("third".to_string(), Some(100));
+        assert!(todos.move_item(2, 0));
+        assert!(!todos.move_item(3, 0));
+        todos.save_to_file().unwrap();
+
+        let todos = Todos::load_from_file().unwrap();
+        let descriptions: Vec<&str> = todos.items.iter().map(|todo| todo.description.as_str()).collect();
+        assert_eq!(descriptions, vec!["third", "first", "Second"]);
+
+        let sorted = |sort: TodoSort| {
+            let mut entries: Vec<(usize, &Todo)> = todos.items.iter().enumerate().collect();
+            sort_entries(&mut entries, sort);
+            entries.iter().map(|(index, _)| *index).collect::<Vec<usize>>()
+        };
+        assert_eq!(sorted(TodoSort::Manual), vec![0, 1, 2]);
+        assert_eq!(sorted(TodoSort::DueDate), vec![0, 2, 1]);
+        assert_eq!(sorted(TodoSort::Description), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_add_linked_todo() {
+        let mut todos = Todos::new();
+        todos.add_linked("Call Bob".to_string(), "meetings/standup".to_string(), 3);
+        assert_eq!(todos.items[0].note.as_deref(), Some("meetings/standup"));
+        assert_eq!(todos.items[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_todos_grouped_by_note() {
+        let mut todos = Todos::new();
+        todos.add_to_note("Draft agenda".to_string(), "project".to_string());
+        todos.add("Buy milk".to_string(), None);
+        todos.add_linked("Call Bob".to_string(), "Archive".to_string(), 0);
+        todos.add_to_note("Book room".to_string(), "project".to_string());
+
+        let groups = todos.grouped_by_note();
+        let notes: Vec<Option<&str>> = groups.iter().map(|(note, _)| *note).collect();
+        assert_eq!(notes, vec![None, Some("Archive"), Some("project")]);
+        let indices: Vec<usize> = todos.for_note("project").iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![0, 3]);
+
+        assert!(todos.rename_note("project", "work/project"));
+        assert_eq!(todos.for_note("work/project").len(), 2);
+        assert!(todos.detach_note("work/project"));
+        assert!(todos.for_note("work/project").is_empty());
+        assert!(!todos.detach_note("work/project"));
+
+        assert!(todos.merge_note("Archive", "project", 0, 4));
+        assert_eq!(todos.items[2].note.as_deref(), Some("project"));
+        assert_eq!(todos.items[2].line, Some(4));
+        assert!(todos.split_note("project", "project/later", 3..5));
+        assert_eq!(todos.items[2].note.as_deref(), Some("project/later"));
+        assert_eq!(todos.items[2].line, Some(1));
+        assert!(!todos.split_note("project", "project/later", 0..1));
+    }
+
+    #[test]
+    fn test_todos_grouped_by_project() {
+        let mut todos = Todos::new();
+        todos.add_to_project("Finish report".to_string(), "work".to_string());
+        todos.add("Buy milk".to_string(), None);
+        todos.add_to_project("Paint fence".to_string(), "home".to_string());
+        todos.add_to_project("Review budget".to_string(), "work".to_string());
+
+        let groups = todos.grouped_by_project();
+        let projects: Vec<Option<&str>> = groups.iter().map(|(project, _)| *project).collect();
+        assert_eq!(projects, vec![None, Some("home"), Some("work")]);
+        let indices: Vec<usize> = groups[2].1.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![0, 3]);
+        assert_eq!(todos.projects(), vec!["home", "work"]);
+    }
+
+    #[test]
+    fn test_deadlines_and_targets() {
+        let mut todos = Todos::new();
+        todos.add("Ship release".to_string(), Some(300));
+        todos.add("Tidy docs".to_string(), Some(100));
+        todos.items[1].deadline = DeadlineKind::Target;
+        todos.add("Someday".to_string(), None);
+        todos.add("File taxes".to_string(), Some(200));
+
+        let deadlines: Vec<usize> = todos.agenda(DeadlineKind::Hard).iter().map(|(index, _)| *index).collect();
+        assert_eq!(deadlines, vec![3, 0]);
+        assert_eq!(todos.agenda(DeadlineKind::Target).len(), 1);
+
+        assert_eq!(todos.items[0].due_status(100, 50), Some(DueStatus::Upcoming));
+        assert_eq!(todos.items[0].due_status(260, 50), Some(DueStatus::DueSoon));
+        assert_eq!(todos.items[0].due_status(301, 50), Some(DueStatus::Overdue));
+        assert_eq!(todos.items[2].due_status(0, 50), None);
+    }
+
+    #[test]
+    fn test_due_date_round_trip() {
+        let due_date = parse_due_date("2024-03-15").unwrap();
+        assert_eq!(format_due_date(due_date), "2024-03-15");
+        assert_eq!(parse_due_date("15/03/2024"), None);
+    }
+
+    #[test]
+    fn test_todo_ids() {
+        let mut todos = Todos::new();
+        todos.add("First".to_string(), None);
+        todos.add("Second".to_string(), None);
+        assert_eq!(todos.items[1].id, 2);
+        todos.move_item(1, 0);
+        assert_eq!(todos.position(2), Some(0));
+        assert_eq!(todos.position(3), None);
+        todos.add_time_spent(2, 60);
+        assert_eq!(todos.add_time_spent(2, 30).unwrap().time_spent_secs, 90);
+        assert!(todos.add_time_spent(3, 30).is_none());
+
+        let data = r#"{"items":[{"description":"Old","due_date":null},{"description":"New","due_date":null,"id":4}]}"#;
+        let todos = serde_json::from_str::<Todos>(data).unwrap().loaded();
+        assert_eq!(todos.items[0].id, 5);
+        assert_eq!(todos.items[1].id, 4);
+    }
+
+    #[test]
+    fn test_load_todos_without_links() {
+        let todos: Todos = serde_json::from_str(r#"{"items":[{"description":"Old","due_date":null}]}"#).unwrap();
+        assert_eq!(todos.items[0].note, None);
+        assert_eq!(todos.items[0].line, None);
+        assert_eq!(todos.items[0].deadline, DeadlineKind::Hard);
+        assert_eq!(todos.items[0].project, None);
+    }
 }
\ No newline at end of file
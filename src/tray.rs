@@ -0,0 +1,209 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+/// What was picked from the tray menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    /// Show the window.
+    Show,
+    /// Open the quick capture window.
+    QuickCapture,
+    /// Open today's daily note.
+    TodaysNote,
+    /// Quit the app, even if closing only hides the window.
+    Quit,
+}
+
+impl TrayAction {
+    const ALL: [TrayAction; 4] = [
+        TrayAction::Show,
+        TrayAction::QuickCapture,
+        TrayAction::TodaysNote,
+        TrayAction::Quit,
+    ];
+
+    fn id(self) -> &'static str {
+        match self {
+            TrayAction::Show => "show",
+            TrayAction::QuickCapture => "quick-capture",
+            TrayAction::TodaysNote => "todays-note",
+            TrayAction::Quit => "quit",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            TrayAction::Show => "Show Notes",
+            TrayAction::QuickCapture => "Quick Capture…",
+            TrayAction::TodaysNote => "Today's Note",
+            TrayAction::Quit => "Quit",
+        }
+    }
+}
+
+/// The icon of the app in the system tray. It is removed when dropped.
+pub struct Tray {
+    actions: Receiver<TrayAction>,
+    pending: Arc<AtomicUsize>,
+    // On Linux the icon lives on its own GTK thread, which picks up `pending` by itself
+    #[cfg(not(target_os = "linux"))]
+    shown: Shown,
+}
+
+// The parts of the icon that change while the app runs
+struct Shown {
+    icon: TrayIcon,
+    pending_item: MenuItem,
+    pending: usize,
+}
+
+impl Tray {
+    /// Adds the icon to the system tray.
+    ///
+    /// # Arguments
+    ///
+    /// * `wake` - Called each time a menu item is picked, e.g. to repaint the window so it
+    ///   notices.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Tray>` containing the tray icon, or an error if it could not be added.
+    pub fn start(wake: impl Fn() + Send + Sync + 'static) -> io::Result<Tray> {
+        let (sender, actions) = channel();
+        let sender = Mutex::new(sender);
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            let action = TrayAction::ALL.into_iter().find(|action| event.id.0 == action.id());
+            if let Some(action) = action {
+                let _ = sender.lock().unwrap_or_else(|err| err.into_inner()).send(action);
+                wake();
+            }
+        }));
+        let data = eframe::icon_data::from_png_bytes(include_bytes!("../assets/icon-256.png"))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        let icon = Icon::from_rgba(data.rgba, data.width, data.height).map_err(tray_error)?;
+        let pending = Arc::new(AtomicUsize::new(0));
+        #[cfg(target_os = "linux")]
+        spawn_gtk(icon, pending.clone());
+        #[cfg(not(target_os = "linux"))]
+        let shown = show(icon)?;
+        Ok(Tray {
+            actions,
+            pending,
+            #[cfg(not(target_os = "linux"))]
+            shown,
+        })
+    }
+
+    /// Returns the menu items picked since the last call.
+    pub fn actions(&self) -> Vec<TrayAction> {
+        self.actions.try_iter().collect()
+    }
+
+    /// Shows how many todos are not done yet.
+    pub fn set_pending(&mut self, count: usize) {
+        self.pending.store(count, Ordering::SeqCst);
+        #[cfg(not(target_os = "linux"))]
+        self.shown.update(count);
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        MenuEvent::set_event_handler(None::<fn(MenuEvent)>);
+    }
+}
+
+impl Shown {
+    fn update(&mut self, pending: usize) {
+        if pending == self.pending {
+            return;
+        }
+        self.pending = pending;
+        let text = pending_text(pending);
+        self.pending_item.set_text(&text);
+        if let Err(err) = self.icon.set_tooltip(Some(format!("Notes — {}", text))) {
+            log::warn!("Failed to update the tray icon: {}", err);
+        }
+        // Shown next to the icon in the macOS menu bar
+        self.icon.set_title(Some(if pending == 0 { String::new() } else { pending.to_string() }));
+    }
+}
+
+fn show(icon: Icon) -> io::Result<Shown> {
+    let pending_item = MenuItem::new(pending_text(0), false, None);
+    let menu = Menu::new();
+    let item = |action: TrayAction| MenuItem::with_id(action.id(), action.label(), true, None);
+    menu.append_items(&[
+        &pending_item,
+        &PredefinedMenuItem::separator(),
+        &item(TrayAction::Show),
+        &item(TrayAction::QuickCapture),
+        &item(TrayAction::TodaysNote),
+        &PredefinedMenuItem::separator(),
+        &item(TrayAction::Quit),
+    ])
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let icon = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip(format!("Notes — {}", pending_text(0)))
+        .with_icon(icon)
+        .build()
+        .map_err(tray_error)?;
+    Ok(Shown {
+        icon,
+        pending_item,
+        pending: 0,
+    })
+}
+
+// GTK needs its own main loop for the icon, which winit does not run
+#[cfg(target_os = "linux")]
+fn spawn_gtk(icon: Icon, pending: Arc<AtomicUsize>) {
+    std::thread::spawn(move || {
+        if let Err(err) = gtk::init() {
+            log::warn!("Failed to show the tray icon: {}", err);
+            return;
+        }
+        let mut shown = match show(icon) {
+            Ok(shown) => shown,
+            Err(err) => {
+                log::warn!("Failed to show the tray icon: {}", err);
+                return;
+            }
+        };
+        gtk::glib::timeout_add_local(std::time::Duration::from_secs(1), move || {
+            shown.update(pending.load(Ordering::SeqCst));
+            gtk::glib::ControlFlow::Continue
+        });
+        gtk::main();
+    });
+}
+
+fn pending_text(count: usize) -> String {
+    match count {
+        0 => "No pending todos".to_string(),
+        1 => "1 pending todo".to_string(),
+        count => format!("{} pending todos", count),
+    }
+}
+
+fn tray_error(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_text() {
+        assert_eq!(pending_text(0), "No pending todos");
+        assert_eq!(pending_text(1), "1 pending todo");
+        assert_eq!(pending_text(12), "12 pending todos");
+    }
+}
@@ -0,0 +1,1031 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(not(target_arch = "wasm32"))]
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::expand_home;
+use crate::config::{Config, NoteExtension, VaultEntry};
+use crate::notes::NoteMeta;
+use crate::storage::{self, atomic_write};
+use crate::store::NoteStore;
+use crate::title;
+use crate::verify::{self, Issue, VerifyReport};
+
+/// The name of the file in the `.notes` directory that marks it as open in a window.
+pub const LOCK_FILE: &str = ".lock";
+
+/// The folder in the notes directory that archived notes are moved to.
+pub const ARCHIVE_DIR: &str = ".archive";
+
+/// The folder in the notes directory that deleted notes are moved to, so that they can be restored.
+pub const TRASH_DIR: &str = ".trash";
+
+/// How often the window holding the lock confirms that it is still running.
+pub const HEARTBEAT_SECS: u64 = 30;
+
+/// How long after the last heartbeat a lock is considered left behind by a crashed window.
+pub const STALE_AFTER_SECS: u64 = 4 * HEARTBEAT_SECS;
+
+/// The vaults this process opened read-only. Writes to files inside them are refused.
+static READ_ONLY_ROOTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The notes directory the app switched to, or `None` for `~/.notes`.
+static ACTIVE_ROOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Who holds the lock of a vault, as stored in its `.lock` file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockInfo {
+    /// The process id of the window holding the lock.
+    pub pid: u32,
+    /// When the lock was taken, in seconds since the Unix epoch.
+    pub since: u64,
+    /// When the holder last confirmed it is running, in seconds since the Unix epoch.
+    pub heartbeat: u64,
+}
+
+impl LockInfo {
+    fn is_stale(&self, now: u64) -> bool {
+        // A lock with our own process id can only be left over from a process that crashed
+        self.pid == std::process::id() || now.saturating_sub(self.heartbeat) > STALE_AFTER_SECS
+    }
+}
+
+/// A directory of notes, along with its configuration.
+///
+/// `Vault::at` gives access to the notes in any directory, e.g. in tests. A window opens its
+/// vault with `Vault::open` instead, which also takes the vault's lock: only one window at a
+/// time may write to a vault, and the lock is released when the `Vault` is dropped. If another
+/// window holds the lock, the vault is opened read-only and every write to it fails until the
+/// lock is taken.
+#[derive(Debug)]
+pub struct Vault {
+    root: PathBuf,
+    config: OnceCell<Config>,
+    lock: Option<LockInfo>,
+    held_by: Option<LockInfo>,
+    locked_out: bool,
+}
+
+impl Vault {
+    /// Gives access to the notes in a directory without locking it, creating the directory if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The notes directory.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vault>` containing the vault.
+    pub fn at(root: PathBuf) -> io::Result<Vault> {
+        if !storage::backend().exists(&root) {
+            storage::backend().create_dir_all(&root)?;
+        }
+        Ok(Vault {
+            root,
+            config: OnceCell::new(),
+            lock: None,
+            held_by: None,
+            locked_out: false,
+        })
+    }
+
+    /// Gives access to the active vault without locking it.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vault>` containing the vault, or an error if there is no home directory.
+    pub fn active() -> io::Result<Vault> {
+        Self::at(active_root()?)
+    }
+
+    /// Opens the active vault, taking its lock if no other window holds it.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vault>` containing the vault, read-only if the lock is held elsewhere.
+    pub fn open() -> io::Result<Vault> {
+        Self::open_at(active_root()?)
+    }
+
+    /// Opens a notes directory, taking its lock if no other window holds it.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The notes directory.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vault>` containing the vault, read-only if the lock is held elsewhere.
+    pub fn open_at(root: PathBuf) -> io::Result<Vault> {
+        let mut vault = Self::at(root)?;
+        vault.try_lock(false)?;
+        Ok(vault)
+    }
+
+    /// Returns the notes directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the configuration of the vault, read from its `.config.json` file when first needed.
+    ///
+    /// A configuration that cannot be read is logged and replaced by the defaults, so that the
+    /// notes stay accessible.
+    pub fn config(&self) -> &Config {
+        self.config.get_or_init(|| {
+            Config::load_in(&self.root).unwrap_or_else(|err| {
+                log::error!("Failed to load the configuration of {}: {}", self.root.display(), err);
+                Config::default()
+            })
+        })
+    }
+
+    /// Returns whether another window holds the lock, so that nothing can be saved.
+    pub fn is_read_only(&self) -> bool {
+        self.locked_out
+    }
+
+    /// Returns who holds the lock if this vault is read-only.
+    pub fn held_by(&self) -> Option<&LockInfo> {
+        self.held_by.as_ref()
+    }
+
+    /// Tries again to take the lock of a read-only vault.
+    ///
+    /// # Arguments
+    ///
+    /// * `force` - Whether to take the lock even if its holder seems to be running, e.g. when
+    ///   the user knows the other window is gone.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<bool>` telling whether the vault is now writable.
+    pub fn try_lock(&mut self, force: bool) -> io::Result<bool> {
+        if self.lock.is_some() {
+            return Ok(true);
+        }
+        let path = self.root.join(LOCK_FILE);
+        let now = unix_now();
+        let info = LockInfo {
+            pid: std::process::id(),
+            since: now,
+            heartbeat: now,
+        };
+        // Taking the lock is retried once after clearing a stale one
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(serde_json::to_string(&info)?.as_bytes())?;
+                    file.sync_all()?;
+                    self.lock = Some(info);
+                    self.held_by = None;
+                    self.locked_out = false;
+                    set_read_only(&self.root, false);
+                    return Ok(true);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    // An unreadable lock file was most likely cut short by a crash
+                    let holder = fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|data| serde_json::from_str::<LockInfo>(&data).ok());
+                    match holder {
+                        Some(holder) if !force && !holder.is_stale(now) => {
+                            self.held_by = Some(holder);
+                            break;
+                        }
+                        _ => match fs::remove_file(&path) {
+                            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                            _ => {}
+                        },
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        self.locked_out = true;
+        set_read_only(&self.root, true);
+        Ok(false)
+    }
+
+    /// Confirms that this window still holds the lock, at most every `HEARTBEAT_SECS` seconds.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        let Some(lock) = &mut self.lock else {
+            return Ok(());
+        };
+        let now = unix_now();
+        if now.saturating_sub(lock.heartbeat) < HEARTBEAT_SECS {
+            return Ok(());
+        }
+        lock.heartbeat = now;
+        atomic_write(&self.root.join(LOCK_FILE), serde_json::to_string(lock)?.as_bytes(), false)
+    }
+
+    /// Returns the path of the file that stores a note.
+    ///
+    /// Every file operation on notes goes through here, so titles that would point outside
+    /// the vault are rejected, and the rest are escaped into file names that work on every
+    /// platform. Notes saved before titles were escaped keep their original file name, and
+    /// notes saved with another extension than the configured one keep theirs.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<PathBuf>` containing the path of the note file, or an `InvalidInput` error if the title is not valid.
+    pub fn note_path(&self, title: &str) -> io::Result<PathBuf> {
+        title::validate(title)?;
+        let extension = self.config().note_extension;
+        let path = self.root.join(title::to_path(&self.root, title, extension.as_str())?);
+        if storage::backend().exists(&path) {
+            return Ok(path);
+        }
+        for other in NoteExtension::ALL.into_iter().filter(|&other| other != extension) {
+            let existing = path.with_extension(other.as_str());
+            if storage::backend().is_file(&existing) {
+                return Ok(existing);
+            }
+        }
+        if !title.contains('\\') {
+            let legacy = self.root.join(format!("{}.txt", title));
+            if storage::backend().is_file(&legacy) {
+                return Ok(legacy);
+            }
+        }
+        Ok(path)
+    }
+
+    /// Creates a new note file with the given title and content.
+    ///
+    /// Folders in the title are created as needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note.
+    /// * `content` - The content of the note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn create_note_file(&self, title: &str, content: &str) -> io::Result<()> {
+        let path = self.note_path(title)?;
+        if let Some(parent) = path.parent() {
+            storage::backend().create_dir_all(parent)?;
+        }
+        atomic_write(&path, content.as_bytes(), true)
+    }
+
+    /// Reads the content of a note file with the given title.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note to be read.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<String>` containing the content of the note or an error.
+    pub fn read_note_file(&self, title: &str) -> io::Result<String> {
+        storage::read_to_string(&self.note_path(title)?)
+    }
+
+    /// Updates the content of an existing note file with the given title.
+    ///
+    /// The file is replaced atomically but not synced to disk, as updates happen frequently while editing.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note to be updated.
+    /// * `new_content` - The new content for the note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn update_note_file(&self, title: &str, new_content: &str) -> io::Result<()> {
+        atomic_write(&self.note_path(title)?, new_content.as_bytes(), false)
+    }
+
+    /// Deletes a note file with the given title.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the note to be deleted.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn delete_note_file(&self, title: &str) -> io::Result<()> {
+        let path = self.note_path(title)?;
+        check_writable(&path)?;
+        storage::backend().remove(&path)
+    }
+
+    /// Checks every note for problems: content that changed without a new modification time,
+    /// which points to disk corruption, text that is not UTF-8, malformed front matter, links
+    /// to missing attachments and untidy titles. The checksums are remembered for the next
+    /// check in `.checksums.json`.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<VerifyReport>` containing the problems found.
+    pub fn verify(&self) -> io::Result<VerifyReport> {
+        verify::verify(self)
+    }
+
+    /// Fixes a problem found by `verify`.
+    ///
+    /// # Arguments
+    ///
+    /// * `issue` - The problem to fix.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success, with an `Unsupported` error if the problem has
+    /// to be fixed by hand, see `Issue::fix_label`.
+    pub fn fix_issue(&self, issue: &Issue) -> io::Result<()> {
+        verify::fix(self, issue)
+    }
+
+    /// Gives access to the notes archived in the vault.
+    ///
+    /// The archive is a hidden folder laid out like the vault itself, so archived notes keep
+    /// their titles but are left out of the note list and the search index.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vault>` containing the archive.
+    pub fn archive(&self) -> io::Result<Vault> {
+        let archive = Self::at(self.root.join(ARCHIVE_DIR))?;
+        // Archived notes are saved with the extension of the vault
+        let _ = archive.config.set(self.config().clone());
+        Ok(archive)
+    }
+
+    /// Moves a note into the archive.
+    ///
+    /// Like the trash, the archive is always kept as files in the vault, so notes kept in another
+    /// store are copied into it and then deleted from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the note is kept in, e.g. the vault itself.
+    /// * `title` - The title of the note to be archived.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure, with an `AlreadyExists` error if an archived note has the same title.
+    pub fn archive_note(&self, store: &dyn NoteStore, title: &str) -> io::Result<()> {
+        let archive = self.archive()?;
+        let content = store.read(title)?;
+        if NoteStore::exists(&archive, title) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A note named {} is already archived", title),
+            ));
+        }
+        NoteStore::write(&archive, title, &content)?;
+        store.delete(title)
+    }
+
+    /// Moves an archived note back into the vault.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the note is moved back into, e.g. the vault itself.
+    /// * `title` - The title of the archived note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure, with an `AlreadyExists` error if a note has the same title.
+    pub fn unarchive_note(&self, store: &dyn NoteStore, title: &str) -> io::Result<()> {
+        let archive = self.archive()?;
+        let content = archive.read_note_file(title)?;
+        if store.exists(title) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A note named {} already exists", title),
+            ));
+        }
+        store.write(title, &content)?;
+        archive.delete_note_file(title)
+    }
+
+    /// Gives access to the notes deleted from the vault, laid out like the archive.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vault>` containing the trash.
+    pub fn trash(&self) -> io::Result<Vault> {
+        let trash = Self::at(self.root.join(TRASH_DIR))?;
+        let _ = trash.config.set(self.config().clone());
+        Ok(trash)
+    }
+
+    /// Moves a note into the trash, replacing a note with the same title deleted earlier.
+    ///
+    /// The trash is always kept as files in the vault, so notes kept in another store, such as
+    /// the SQLite database, are copied into it and then deleted from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the note is kept in, e.g. the vault itself.
+    /// * `title` - The title of the note to be deleted.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure.
+    pub fn trash_note(&self, store: &dyn NoteStore, title: &str) -> io::Result<()> {
+        let content = store.read(title)?;
+        NoteStore::write(&self.trash()?, title, &content)?;
+        store.delete(title)
+    }
+
+    /// Moves a deleted note back out of the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the note is restored into, e.g. the vault itself.
+    /// * `title` - The title of the deleted note.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<()>` indicating success or failure, with an `AlreadyExists` error if a note has the same title.
+    pub fn restore_note(&self, store: &dyn NoteStore, title: &str) -> io::Result<()> {
+        let trash = self.trash()?;
+        let content = trash.read_note_file(title)?;
+        if store.exists(title) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("A note named {} already exists", title),
+            ));
+        }
+        store.write(title, &content)?;
+        trash.delete_note_file(title)
+    }
+
+    /// Deletes the notes in the trash for good.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<usize>` containing the number of notes deleted.
+    pub fn empty_trash(&self) -> io::Result<usize> {
+        let trash = self.trash()?;
+        let titles = trash.list_notes()?;
+        for title in &titles {
+            trash.delete_note_file(title)?;
+        }
+        Ok(titles.len())
+    }
+
+    /// Lists the titles of all notes in the vault.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vec<String>>` containing the list of note titles or an error.
+    pub fn list_notes(&self) -> io::Result<Vec<String>> {
+        let notes = self.list_notes_with_meta()?;
+        Ok(notes.into_iter().map(|note| note.title).collect())
+    }
+
+    /// Lists all notes in the vault and its folders along with their timestamps.
+    ///
+    /// Hidden files and folders, such as the todos file and in-progress writes, are skipped,
+    /// as are files that are not notes, such as attachments.
+    /// Notes in folders are titled by their relative path, e.g. `meetings/standup`.
+    /// Both `.md` and `.txt` files are notes. When a title is saved with both, the file that isn't
+    /// opened by that title is renamed to a conflict copy, e.g. `plan (conflict).txt`, so that it
+    /// isn't hidden.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vec<NoteMeta>>` containing the metadata of every note or an error.
+    pub fn list_notes_with_meta(&self) -> io::Result<Vec<NoteMeta>> {
+        let mut files = Vec::new();
+        self.collect_notes(&self.root, &mut files)?;
+        let mut notes: Vec<NoteMeta> = Vec::with_capacity(files.len());
+        let mut seen: HashMap<String, (usize, PathBuf)> = HashMap::new();
+        for (path, note) in files {
+            let Some((index, first)) = seen.get(&note.title).cloned() else {
+                seen.insert(note.title.clone(), (notes.len(), path));
+                notes.push(note);
+                continue;
+            };
+            let (duplicate, mut meta) = if self.note_path(&note.title)? == first {
+                (path, note)
+            } else {
+                seen.insert(note.title.clone(), (index, path));
+                (first, std::mem::replace(&mut notes[index], note))
+            };
+            match self.rename_to_conflict_copy(&meta.title, &duplicate) {
+                Ok(title) => {
+                    meta.title = title;
+                    notes.push(meta);
+                }
+                Err(err) => log::warn!(
+                    "Cannot rename {} to a conflict copy: {}",
+                    duplicate.display(),
+                    err
+                ),
+            }
+        }
+        Ok(notes)
+    }
+
+    /// Saves new notes with an extension from now on and renames every note file to it, e.g.
+    /// from `.txt` to `.md`. The content of the notes is left as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `extension` - The extension to convert the notes to.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Vec<String>>` containing the titles of the converted notes. Notes that
+    /// also exist with the new extension are left alone.
+    pub fn convert_notes(&mut self, extension: NoteExtension) -> io::Result<Vec<String>> {
+        let mut config = self.config().clone();
+        if config.note_extension != extension {
+            config.note_extension = extension;
+            config.save_in(&self.root)?;
+            self.config = OnceCell::from(config);
+        }
+        let extension = extension.as_str();
+        let mut converted = Vec::new();
+        for title in self.list_notes()? {
+            let path = self.note_path(&title)?;
+            if path.extension().is_some_and(|existing| existing == extension) {
+                continue;
+            }
+            let target = self.root.join(title::to_path(&self.root, &title, extension)?);
+            if storage::backend().exists(&target) {
+                log::warn!("Cannot convert {}, {} already exists", path.display(), target.display());
+                continue;
+            }
+            check_writable(&path)?;
+            storage::backend().rename(&path, &target)?;
+            converted.push(title);
+        }
+        Ok(converted)
+    }
+
+    /// Recursively collects the notes in `dir` along with their files, titling them by their path
+    /// relative to the root.
+    fn collect_notes(&self, dir: &Path, notes: &mut Vec<(PathBuf, NoteMeta)>) -> io::Result<()> {
+        for entry in storage::backend().list(dir)? {
+            let path = entry.path;
+            if path.file_name().map_or(true, |name| name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+            if entry.is_dir {
+                self.collect_notes(&path, notes)?;
+            } else if path.extension().and_then(NoteExtension::from_extension).is_some() {
+                let relative = path.strip_prefix(&self.root).unwrap_or(&path).with_extension("");
+                let title = match title::from_path(&self.root, &relative) {
+                    Some(title) => title,
+                    None => match self.repair_file_name(&path)? {
+                        Some(title) => title,
+                        None => continue,
+                    },
+                };
+                let meta = NoteMeta {
+                    title,
+                    modified: entry.modified,
+                    created: entry.created,
+                };
+                notes.push((path, meta));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renames a note file whose name is not valid UTF-8, e.g. one copied from another system,
+    /// to the escaped form of its readable name so that it can be opened by title.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<Option<String>>` containing the title of the note, or `None` if the readable name is already taken.
+    fn repair_file_name(&self, path: &Path) -> io::Result<Option<String>> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path).with_extension("");
+        let Some(title) = title::from_path(&self.root, Path::new(&*relative.to_string_lossy())) else {
+            return Ok(None);
+        };
+        let target = self.note_path(&title)?;
+        if storage::backend().exists(&target) {
+            log::warn!("Cannot rename {}, {} already exists", path.display(), target.display());
+            return Ok(None);
+        }
+        if let Some(parent) = target.parent() {
+            storage::backend().create_dir_all(parent)?;
+        }
+        storage::backend().rename(path, &target)?;
+        log::info!("Renamed {} to {}", path.display(), target.display());
+        Ok(Some(title))
+    }
+
+    /// Renames the file of a note to the first free conflict copy of its title, e.g.
+    /// `plan (conflict)` or `plan (conflict 2)`, keeping its extension.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result<String>` containing the title of the conflict copy.
+    fn rename_to_conflict_copy(&self, title: &str, path: &Path) -> io::Result<String> {
+        check_writable(path)?;
+        let extension = path
+            .extension()
+            .map_or(String::new(), |extension| extension.to_string_lossy().into_owned());
+        let mut number = 1;
+        loop {
+            let candidate = if number == 1 {
+                format!("{} (conflict)", title)
+            } else {
+                format!("{} (conflict {})", title, number)
+            };
+            let target = self.root.join(title::to_path(&self.root, &candidate, &extension)?);
+            let taken = NoteExtension::ALL
+                .into_iter()
+                .any(|other| storage::backend().exists(&target.with_extension(other.as_str())));
+            if !taken {
+                storage::backend().rename(path, &target)?;
+                log::warn!("{} was also saved as {}, renamed it to {}", title, path.display(), target.display());
+                return Ok(candidate);
+            }
+            number += 1;
+        }
+    }
+}
+
+impl Drop for Vault {
+    fn drop(&mut self) {
+        if let Some(lock) = &self.lock {
+            let path = self.root.join(LOCK_FILE);
+            // Leave the lock alone if another window took it over in the meantime
+            let still_ours = fs::read_to_string(&path)
+                .ok()
+                .and_then(|data| serde_json::from_str::<LockInfo>(&data).ok())
+                .is_some_and(|holder| holder.pid == lock.pid && holder.since == lock.since);
+            if still_ours {
+                if let Err(err) = fs::remove_file(&path) {
+                    log::error!("Failed to release the lock of {}: {}", self.root.display(), err);
+                }
+            }
+        } else if self.locked_out {
+            set_read_only(&self.root, false);
+        }
+    }
+}
+
+/// Checks that a file may be written, i.e. that it is not inside a vault opened read-only.
+///
+/// # Arguments
+///
+/// * `path` - The file about to be written or removed.
+///
+/// # Returns
+///
+/// An `io::Result<()>` with a `PermissionDenied` error if the file is in a read-only vault.
+pub fn check_writable(path: &Path) -> io::Result<()> {
+    let roots = READ_ONLY_ROOTS.lock().unwrap_or_else(|err| err.into_inner());
+    if roots.iter().any(|root| path.starts_with(root)) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The notes are open in another window and cannot be changed here",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the `~/.notes` directory, creating it if it doesn't exist.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the path to the `.notes` directory or an error.
+pub fn home_root() -> io::Result<PathBuf> {
+    // The browser has no home directory, so the notes are kept under the same path in its storage
+    #[cfg(target_arch = "wasm32")]
+    let home = Some(PathBuf::from("/"));
+    #[cfg(not(target_arch = "wasm32"))]
+    let home = home_dir();
+    let home = home.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
+    let notes_dir = home.join(".notes");
+    if !storage::backend().exists(&notes_dir) {
+        storage::backend().create_dir_all(&notes_dir)?;
+    }
+    Ok(notes_dir)
+}
+
+/// Returns the notes directory of the active vault, `~/.notes` unless the app switched to another one.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the path to the notes directory or an error.
+pub fn active_root() -> io::Result<PathBuf> {
+    let active = ACTIVE_ROOT.lock().unwrap_or_else(|err| err.into_inner()).clone();
+    match active {
+        Some(root) => Ok(root),
+        None => home_root(),
+    }
+}
+
+/// Makes a vault the active one, so that notes and their settings are read from and written to it.
+///
+/// # Arguments
+///
+/// * `root` - The notes directory, or `None` for `~/.notes`.
+pub fn set_active_root(root: Option<PathBuf>) {
+    *ACTIVE_ROOT.lock().unwrap_or_else(|err| err.into_inner()) = root;
+}
+
+/// Returns the vaults registered in the configuration of `~/.notes`.
+///
+/// # Returns
+///
+/// An `io::Result<Vec<VaultEntry>>` containing the vaults in the order they were registered.
+pub fn registered() -> io::Result<Vec<VaultEntry>> {
+    Ok(Config::load_in(&home_root()?)?.vaults)
+}
+
+/// Registers a vault under a name, replacing any vault registered under the same name.
+///
+/// # Arguments
+///
+/// * `name` - The name to list and switch to the vault by.
+/// * `path` - The notes directory. A leading `~` is expanded when the vault is opened.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn register(name: &str, path: &str) -> io::Result<()> {
+    let home = home_root()?;
+    let mut config = Config::load_in(&home)?;
+    let entry = VaultEntry {
+        name: name.to_string(),
+        path: path.to_string(),
+    };
+    match config.vaults.iter_mut().find(|vault| vault.name == name) {
+        Some(existing) => *existing = entry,
+        None => config.vaults.push(entry),
+    }
+    config.save_in(&home)
+}
+
+/// Finds the notes directory of a vault.
+///
+/// # Arguments
+///
+/// * `name` - The name of a registered vault, or `None` for `~/.notes`.
+///
+/// # Returns
+///
+/// An `io::Result<PathBuf>` containing the notes directory, or a `NotFound` error if no vault has the name.
+pub fn resolve(name: Option<&str>) -> io::Result<PathBuf> {
+    let Some(name) = name else {
+        return home_root();
+    };
+    registered()?
+        .into_iter()
+        .find(|vault| vault.name == name)
+        .map(|vault| expand_home(&vault.path))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No vault named {}", name)))
+}
+
+/// Returns the name of the vault the app switched to last, or `None` for `~/.notes`.
+///
+/// # Returns
+///
+/// An `io::Result<Option<String>>` containing the name.
+pub fn last_active() -> io::Result<Option<String>> {
+    Ok(Config::load_in(&home_root()?)?.active_vault)
+}
+
+/// Remembers the vault the app switched to, so that it opens again on the next start.
+///
+/// # Arguments
+///
+/// * `name` - The name of a registered vault, or `None` for `~/.notes`.
+///
+/// # Returns
+///
+/// An `io::Result<()>` indicating success or failure.
+pub fn set_last_active(name: Option<&str>) -> io::Result<()> {
+    let home = home_root()?;
+    let mut config = Config::load_in(&home)?;
+    config.active_vault = name.map(str::to_string);
+    config.save_in(&home)
+}
+
+fn set_read_only(root: &Path, read_only: bool) {
+    let mut roots = READ_ONLY_ROOTS.lock().unwrap_or_else(|err| err.into_inner());
+    roots.retain(|existing| existing != root);
+    if read_only {
+        roots.push(root.to_path_buf());
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use crate::testing::setup_temp_notes_dir;
+    use tempfile::tempdir;
+
+    fn holder(pid: u32, heartbeat: u64) -> String {
+        serde_json::to_string(&LockInfo {
+            pid,
+            since: heartbeat,
+            heartbeat,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().to_path_buf();
+
+        let vault = Vault::open_at(root.clone()).unwrap();
+        assert!(!vault.is_read_only());
+        assert!(root.join(LOCK_FILE).exists());
+        drop(vault);
+        assert!(!root.join(LOCK_FILE).exists());
+
+        // Another running window holds the lock
+        fs::write(root.join(LOCK_FILE), holder(u32::MAX, unix_now())).unwrap();
+        let mut vault = Vault::open_at(root.clone()).unwrap();
+        assert!(vault.is_read_only());
+        assert_eq!(vault.held_by().unwrap().pid, u32::MAX);
+        let err = atomic_write(&root.join("note.txt"), b"text", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        assert!(vault.try_lock(true).unwrap());
+        atomic_write(&root.join("note.txt"), b"text", false).unwrap();
+        drop(vault);
+        assert!(!root.join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().to_path_buf();
+
+        fs::write(root.join(LOCK_FILE), holder(u32::MAX, unix_now() - STALE_AFTER_SECS - 1)).unwrap();
+        assert!(!Vault::open_at(root.clone()).unwrap().is_read_only());
+
+        fs::write(root.join(LOCK_FILE), "{ cut short").unwrap();
+        assert!(!Vault::open_at(root.clone()).unwrap().is_read_only());
+    }
+
+    #[test]
+    fn test_config_is_per_vault() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().join("work")).unwrap();
+        fs::write(vault.root().join(crate::config::CONFIG_FILE), r#"{ "autosave_secs": 5 }"#).unwrap();
+        assert_eq!(vault.config().autosave_secs, 5.0);
+
+        let other = Vault::at(temp_dir.path().join("personal")).unwrap();
+        assert_eq!(other.config(), &crate::config::Config::default());
+    }
+
+    #[test]
+    fn test_archive() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().to_path_buf()).unwrap();
+        vault.create_note_file("projects/old", "Done").unwrap();
+        vault.create_note_file("current", "Ongoing").unwrap();
+
+        vault.archive_note(&vault, "projects/old").unwrap();
+        assert_eq!(vault.list_notes().unwrap(), vec!["current"]);
+        let archive = vault.archive().unwrap();
+        assert_eq!(archive.list_notes().unwrap(), vec!["projects/old"]);
+        assert_eq!(archive.read_note_file("projects/old").unwrap(), "Done");
+        assert_eq!(vault.archive_note(&vault, "projects/old").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        vault.create_note_file("projects/old", "Restarted").unwrap();
+        assert_eq!(
+            vault.unarchive_note(&vault, "projects/old").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        vault.delete_note_file("projects/old").unwrap();
+        vault.unarchive_note(&vault, "projects/old").unwrap();
+        assert_eq!(vault.read_note_file("projects/old").unwrap(), "Done");
+        assert!(archive.list_notes().unwrap().is_empty());
+
+        // Notes kept in another store are archived as files too
+        let store = MemoryStore::new();
+        store.write("ideas", "Kept in a database").unwrap();
+        vault.archive_note(&store, "ideas").unwrap();
+        assert!(!store.exists("ideas"));
+        assert_eq!(archive.read_note_file("ideas").unwrap(), "Kept in a database");
+        vault.unarchive_note(&store, "ideas").unwrap();
+        assert_eq!(store.read("ideas").unwrap(), "Kept in a database");
+        assert!(archive.list_notes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_trash() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().to_path_buf()).unwrap();
+        vault.create_note_file("ideas", "First").unwrap();
+        vault.trash_note(&vault, "ideas").unwrap();
+        assert!(vault.list_notes().unwrap().is_empty());
+
+        vault.create_note_file("ideas", "Second").unwrap();
+        vault.trash_note(&vault, "ideas").unwrap();
+        assert_eq!(vault.trash().unwrap().read_note_file("ideas").unwrap(), "Second");
+        vault.create_note_file("ideas", "Third").unwrap();
+        assert_eq!(
+            vault.restore_note(&vault, "ideas").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        vault.delete_note_file("ideas").unwrap();
+        vault.restore_note(&vault, "ideas").unwrap();
+        assert_eq!(vault.read_note_file("ideas").unwrap(), "Second");
+        assert!(vault.trash().unwrap().list_notes().unwrap().is_empty());
+
+        let store = MemoryStore::new();
+        store.write("plans", "Fourth").unwrap();
+        vault.trash_note(&store, "plans").unwrap();
+        assert!(!store.exists("plans"));
+        vault.create_note_file("old", "Fifth").unwrap();
+        vault.trash_note(&vault, "old").unwrap();
+        vault.restore_note(&store, "plans").unwrap();
+        assert_eq!(store.read("plans").unwrap(), "Fourth");
+        assert_eq!(vault.empty_trash().unwrap(), 1);
+        assert!(vault.trash().unwrap().list_notes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mixed_extensions() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        fs::write(root.join("old.txt"), "Saved before").unwrap();
+        fs::create_dir(root.join("work")).unwrap();
+        fs::write(root.join("work").join("plan.txt"), "Ship it").unwrap();
+        fs::write(root.join("work").join("plan.md"), "Ship it today").unwrap();
+        let mut vault = Vault::at(root.clone()).unwrap();
+
+        vault.create_note_file("new", "Saved after").unwrap();
+        assert!(root.join("new.md").exists());
+        assert_eq!(vault.read_note_file("old").unwrap(), "Saved before");
+        assert_eq!(vault.read_note_file("work/plan").unwrap(), "Ship it today");
+        let mut titles = vault.list_notes().unwrap();
+        titles.sort();
+        // The file of a title saved with both extensions that isn't opened becomes a conflict copy
+        assert_eq!(titles, vec!["new", "old", "work/plan", "work/plan (conflict)"]);
+        assert_eq!(vault.read_note_file("work/plan (conflict)").unwrap(), "Ship it");
+        assert!(!root.join("work").join("plan.txt").exists());
+
+        vault.update_note_file("old", "Changed").unwrap();
+        assert_eq!(fs::read_to_string(root.join("old.txt")).unwrap(), "Changed");
+        let mut converted = vault.convert_notes(NoteExtension::Markdown).unwrap();
+        converted.sort();
+        assert_eq!(converted, vec!["old", "work/plan (conflict)"]);
+        assert!(!root.join("old.txt").exists());
+        assert_eq!(vault.read_note_file("old").unwrap(), "Changed");
+
+        let mut converted = vault.convert_notes(NoteExtension::Text).unwrap();
+        converted.sort();
+        assert_eq!(converted, vec!["new", "old", "work/plan", "work/plan (conflict)"]);
+        assert_eq!(Vault::at(root.clone()).unwrap().config().note_extension, NoteExtension::Text);
+        assert_eq!(fs::read_to_string(root.join("old.txt")).unwrap(), "Changed");
+        let work = root.join("work");
+        assert_eq!(fs::read_to_string(work.join("plan.txt")).unwrap(), "Ship it today");
+        assert_eq!(fs::read_to_string(work.join("plan (conflict).txt")).unwrap(), "Ship it");
+    }
+
+    #[test]
+    fn test_registered_vaults() {
+        let temp_notes_dir = setup_temp_notes_dir();
+        let home = temp_notes_dir.parent().unwrap();
+        let work = home.join("work");
+
+        assert_eq!(resolve(None).unwrap(), home.join(".notes"));
+        assert_eq!(resolve(Some("work")).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        register("work", "/elsewhere").unwrap();
+        register("work", work.to_str().unwrap()).unwrap();
+        assert_eq!(registered().unwrap().len(), 1);
+        assert_eq!(resolve(Some("work")).unwrap(), work);
+
+        assert_eq!(last_active().unwrap(), None);
+        set_last_active(Some("work")).unwrap();
+        assert_eq!(last_active().unwrap(), Some("work".to_string()));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::UNIX_EPOCH;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::attachments;
+use crate::front_matter::FrontMatter;
+use crate::storage::{self, atomic_write};
+use crate::title;
+use crate::vault::Vault;
+
+/// The name of the file in the notes directory that remembers the checksum of each note as of
+/// the last check.
+pub const CHECKSUMS_FILE: &str = ".checksums.json";
+
+/// The checksum of a note as of the last check.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Checksum {
+    /// The SHA-256 hash of the file, in hex.
+    sha256: String,
+    /// The modification time of the file in milliseconds since the epoch.
+    modified: u128,
+}
+
+/// Something wrong with a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The note is not valid UTF-8, e.g. because another program saved it as Latin-1.
+    InvalidEncoding,
+    /// The note starts with a byte order mark, which ends up in front of the first line.
+    ByteOrderMark,
+    /// The front matter is not closed with `---` or has lines that are not `key: value`.
+    MalformedFrontMatter,
+    /// The note links to an attachment that doesn't exist.
+    MissingAttachment(String),
+    /// The title has spaces around its folder names or other leftovers, and could be renamed
+    /// to the suggested title.
+    UntidyTitle(String),
+    /// The content changed since the last check although its modification time did not, which
+    /// points to a damaged disk.
+    ChecksumMismatch,
+}
+
+/// A problem found in a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The title of the note.
+    pub title: String,
+    /// What is wrong.
+    pub problem: Problem,
+}
+
+impl Issue {
+    /// A human-readable description of the problem.
+    pub fn description(&self) -> String {
+        match &self.problem {
+            Problem::InvalidEncoding => "Not valid UTF-8 text".to_string(),
+            Problem::ByteOrderMark => "Starts with a byte order mark".to_string(),
+            Problem::MalformedFrontMatter => "The front matter is not closed or not `key: value` lines".to_string(),
+            Problem::MissingAttachment(link) => format!("Links to the missing attachment {}", link),
+            Problem::UntidyTitle(suggested) => format!("The title could be {}", suggested),
+            Problem::ChecksumMismatch => {
+                "Changed without a new modification time; restore it from the history or a backup".to_string()
+            }
+        }
+    }
+
+    /// The label of the button that fixes the problem, or `None` if it has to be fixed by hand.
+    pub fn fix_label(&self) -> Option<&'static str> {
+        match self.problem {
+            Problem::InvalidEncoding => Some("Convert from Latin-1"),
+            Problem::ByteOrderMark => Some("Remove"),
+            Problem::MissingAttachment(_) => Some("Remove Link"),
+            Problem::UntidyTitle(_) => Some("Rename"),
+            Problem::MalformedFrontMatter | Problem::ChecksumMismatch => None,
+        }
+    }
+}
+
+/// The result of checking every note in a vault.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// How many notes were checked.
+    pub checked: usize,
+    /// The problems found, grouped by note.
+    pub issues: Vec<Issue>,
+}
+
+/// Checks every note of a vault, see `Vault::verify`.
+pub(crate) fn verify(vault: &Vault) -> io::Result<VerifyReport> {
+    let checksums_path = vault.root().join(CHECKSUMS_FILE);
+    let previous: HashMap<String, Checksum> = if storage::backend().is_file(&checksums_path) {
+        serde_json::from_str(&storage::read_to_string(&checksums_path)?).unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+    let mut checksums = HashMap::new();
+    let mut report = VerifyReport::default();
+    let mut notes = vault.list_notes_with_meta()?;
+    notes.sort_by_key(|note| note.title.to_lowercase());
+    for note in notes {
+        let data = storage::backend().read(&vault.note_path(&note.title)?)?;
+        let checksum = Checksum {
+            sha256: Sha256::digest(&data).iter().map(|byte| format!("{:02x}", byte)).collect(),
+            modified: note.modified.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis()),
+        };
+        let mut problems = Vec::new();
+        match previous.get(&note.title) {
+            // Keep reporting the damage until the note is saved again
+            Some(old) if old.modified == checksum.modified && old.sha256 != checksum.sha256 => {
+                problems.push(Problem::ChecksumMismatch);
+                checksums.insert(note.title.clone(), old.clone());
+            }
+            _ => {
+                checksums.insert(note.title.clone(), checksum);
+            }
+        }
+        match String::from_utf8(data) {
+            Ok(content) => problems.extend(check_content(vault, &content)),
+            Err(_) => problems.push(Problem::InvalidEncoding),
+        }
+        let tidy = title::sanitize(&note.title);
+        if tidy != note.title {
+            problems.push(Problem::UntidyTitle(tidy));
+        }
+        report.checked += 1;
+        report.issues.extend(problems.into_iter().map(|problem| Issue {
+            title: note.title.clone(),
+            problem,
+        }));
+    }
+    // The problems are worth showing even if the checksums cannot be remembered, e.g. in a
+    // vault open in another window
+    if let Err(err) = atomic_write(&checksums_path, serde_json::to_string(&checksums)?.as_bytes(), false) {
+        log::warn!("Failed to remember the checksums of the notes: {}", err);
+    }
+    Ok(report)
+}
+
+fn check_content(vault: &Vault, content: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let content = match content.strip_prefix('\u{feff}') {
+        Some(rest) => {
+            problems.push(Problem::ByteOrderMark);
+            rest
+        }
+        None => content,
+    };
+    let (_, body) = FrontMatter::parse(content);
+    let opens = content.starts_with("---\n") || content.starts_with("---\r\n");
+    if opens && body.len() == content.len() {
+        problems.push(Problem::MalformedFrontMatter);
+    }
+    for link in attachments::links(body) {
+        if !storage::backend().is_file(&vault.root().join(link)) {
+            problems.push(Problem::MissingAttachment(link.to_string()));
+        }
+    }
+    problems
+}
+
+/// Fixes a problem found by `verify`.
+pub(crate) fn fix(vault: &Vault, issue: &Issue) -> io::Result<()> {
+    let title = &issue.title;
+    match &issue.problem {
+        Problem::InvalidEncoding => {
+            let data = storage::backend().read(&vault.note_path(title)?)?;
+            // Every byte is a character in Latin-1, so nothing is lost
+            let content: String = data.iter().map(|&byte| byte as char).collect();
+            vault.update_note_file(title, &content)
+        }
+        Problem::ByteOrderMark => {
+            let content = vault.read_note_file(title)?;
+            vault.update_note_file(title, content.trim_start_matches('\u{feff}'))
+        }
+        Problem::MissingAttachment(link) => {
+            let content = vault.read_note_file(title)?;
+            vault.update_note_file(title, &remove_link(&content, link))
+        }
+        Problem::UntidyTitle(suggested) => {
+            if storage::backend().exists(&vault.note_path(suggested)?) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("A note named {} already exists", suggested),
+                ));
+            }
+            vault.create_note_file(suggested, &vault.read_note_file(title)?)?;
+            vault.delete_note_file(title)
+        }
+        Problem::MalformedFrontMatter | Problem::ChecksumMismatch => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "This problem has to be fixed by hand",
+        )),
+    }
+}
+
+// Replaces links and images pointing at `target` with their text
+fn remove_link(content: &str, target: &str) -> String {
+    let link = Regex::new(&format!(r"!?\[([^\]]*)\]\({}\)", regex::escape(target))).unwrap();
+    link.replace_all(content, "$1").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_verify_and_fix() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().to_path_buf()).unwrap();
+        vault.create_note_file("Good", "---\ntags: a\n---\nFine").unwrap();
+        vault.create_note_file("Links", "See ![cat](attachments/cat.png) and [spec](attachments/spec.pdf)").unwrap();
+        vault.create_note_file("Broken", "---\ntags: a\nNo end").unwrap();
+        vault.create_note_file("Marked", "\u{feff}Text").unwrap();
+        vault.create_note_file("work /plans", "Plans").unwrap();
+        fs::write(vault.note_path("Latin").unwrap(), b"caf\xe9").unwrap();
+        fs::create_dir_all(temp_dir.path().join("attachments")).unwrap();
+        fs::write(temp_dir.path().join("attachments/spec.pdf"), b"pdf").unwrap();
+
+        let report = vault.verify().unwrap();
+        assert_eq!(report.checked, 6);
+        let problems: Vec<(&str, &Problem)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.title.as_str(), &issue.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                ("Broken", &Problem::MalformedFrontMatter),
+                ("Latin", &Problem::InvalidEncoding),
+                ("Links", &Problem::MissingAttachment("attachments/cat.png".to_string())),
+                ("Marked", &Problem::ByteOrderMark),
+                ("work /plans", &Problem::UntidyTitle("work/plans".to_string())),
+            ]
+        );
+
+        for issue in report.issues.iter().filter(|issue| issue.fix_label().is_some()) {
+            vault.fix_issue(issue).unwrap();
+        }
+        assert_eq!(vault.read_note_file("Latin").unwrap(), "café");
+        assert_eq!(vault.read_note_file("Marked").unwrap(), "Text");
+        assert_eq!(
+            vault.read_note_file("Links").unwrap(),
+            "See cat and [spec](attachments/spec.pdf)"
+        );
+        assert_eq!(vault.read_note_file("work/plans").unwrap(), "Plans");
+        assert_eq!(vault.verify().unwrap().issues.len(), 1);
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let temp_dir = tempdir().unwrap();
+        let vault = Vault::at(temp_dir.path().to_path_buf()).unwrap();
+        vault.create_note_file("Note", "Original").unwrap();
+        assert!(vault.verify().unwrap().issues.is_empty());
+
+        // Flip the content but keep the modification time, like a failing disk would
+        let path = vault.note_path("Note").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, "Originel").unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        let report = vault.verify().unwrap();
+        assert_eq!(report.issues[0].problem, Problem::ChecksumMismatch);
+        assert_eq!(vault.verify().unwrap().issues.len(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::NoteExtension;
+use crate::notes::Notes;
+use crate::title;
+
+/// A change to a note file, usually made outside the app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoteEvent {
+    /// The note was created or its content changed.
+    Changed(String),
+    /// The note was deleted or moved away.
+    Removed(String),
+}
+
+/// Watches the `.notes` directory for changes made by external editors or sync tools.
+pub struct NoteWatcher {
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<Event>>,
+    root: PathBuf,
+}
+
+impl NoteWatcher {
+    /// Starts watching the `.notes` directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_event` - Called from the watcher thread whenever an event arrives, e.g. to wake up the UI.
+    ///
+    /// # Returns
+    ///
+    /// A `notify::Result<NoteWatcher>` containing the watcher or an error.
+    pub fn new(on_event: impl Fn() + Send + 'static) -> notify::Result<NoteWatcher> {
+        let root = Notes::get_notes_dir()?;
+        let (sender, receiver) = channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+            on_event();
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        Ok(NoteWatcher {
+            _watcher: watcher,
+            receiver,
+            root,
+        })
+    }
+
+    /// Returns the note events received since the last call, without blocking.
+    pub fn poll(&self) -> Vec<NoteEvent> {
+        let mut events = Vec::new();
+        for event in self.receiver.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("File watcher error: {}", err);
+                    continue;
+                }
+            };
+            for path in &event.paths {
+                let Some(title) = title_for_path(&self.root, path) else {
+                    continue;
+                };
+                let note_event = match event.kind {
+                    EventKind::Remove(_) => NoteEvent::Removed(title),
+                    EventKind::Create(_) | EventKind::Modify(_) if path.is_file() => NoteEvent::Changed(title),
+                    EventKind::Modify(_) => NoteEvent::Removed(title),
+                    _ => continue,
+                };
+                if !events.contains(&note_event) {
+                    events.push(note_event);
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Maps a path inside the notes directory to a note title.
+///
+/// Returns `None` for paths outside the notes directory, hidden files and folders,
+/// and files that are not notes.
+pub fn title_for_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    NoteExtension::from_extension(relative.extension()?)?;
+    title::from_path(root, &relative.with_extension(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_for_path() {
+        let root = Path::new("/home/me/.notes");
+        assert_eq!(title_for_path(root, &root.join("note.txt")), Some("note".to_string()));
+        assert_eq!(title_for_path(root, &root.join("note.md")), Some("note".to_string()));
+        assert_eq!(title_for_path(root, &root.join("photo.png")), None);
+        assert_eq!(
+            title_for_path(root, &root.join("meetings").join("standup.txt")),
+            Some("meetings/standup".to_string())
+        );
+        assert_eq!(title_for_path(root, &root.join(".todos")), None);
+        assert_eq!(title_for_path(root, &root.join(".note.txt.42.tmp")), None);
+        assert_eq!(title_for_path(root, &root.join(".templates").join("meeting.txt")), None);
+        assert_eq!(title_for_path(root, Path::new("/elsewhere/note.txt")), None);
+        assert_eq!(title_for_path(root, &root.join("why%3F.txt")), Some("why?".to_string()));
+    }
+}